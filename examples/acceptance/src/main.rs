@@ -0,0 +1,351 @@
+//! Go/no-go gate before cutting a release of the routing code. Drives simulated nodes and a
+//! simulated gateway through mesh formation, steady traffic, a gateway restart and node churn,
+//! and fails if the mesh takes too long to converge or delivers too little.
+//!
+//! The nodes stand in a grid `COLUMNS` wide, each hearing its neighbours, and the first column
+//! hears the gateway. The mesh has converged once every node the gateway can reach knows its
+//! shortest distance to it.
+//!
+//! `cargo run --release -- [nodes]`, with `DEFAULT_NODES` nodes unless given. Exits with 1 if a
+//! threshold is missed.
+use core::cell::RefCell;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    process::ExitCode,
+    rc::Rc,
+};
+
+use embassy_time::Duration;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        PacketType,
+        entropy::Xorshift,
+        mesh_router::MeshRouter,
+        network_manager::NetworkManager,
+        policy::{GatewayPolicy, NodePolicy},
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const FIRST_NODE: u8 = 2;
+const DEFAULT_NODES: u8 = 16;
+/// Leaves room for the ids of the nodes joining during churn
+const MAX_NODES: u8 = 160;
+/// Nodes per row of the grid
+const COLUMNS: u8 = 4;
+/// Every this many nodes leaves during churn, and is replaced by a new one
+const CHURN_EVERY: u8 = 5;
+
+/// Steps between two bootups of the gateway
+const BOOTUP_EVERY: u32 = 4;
+/// Rounds of traffic per traffic phase, each node sending one uplink per round
+const TRAFFIC_ROUNDS: u8 = 3;
+/// Steps without new uplinks at the end of a traffic phase, for the last ones to arrive
+const SETTLE_STEPS: u32 = 20;
+
+/// Most steps the mesh may take to converge after a change
+const MAX_CONVERGENCE_STEPS: u32 = 2 * BOOTUP_EVERY;
+/// Least share of uplinks the gateway has to receive
+const MIN_DELIVERY_RATIO: f32 = 0.95;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Router = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+type Gateway = MeshRouter<SimRadio<SIZE>, SIZE, LEN, GatewayPolicy>;
+
+/// The phases, in the order they run. Their number goes in the uplinks' payloads.
+const FORMATION: u8 = 0;
+const STEADY: u8 = 1;
+const RESTARTED: u8 = 2;
+const CHURNED: u8 = 3;
+
+struct Mesh {
+    env: Env,
+    gw: Gateway,
+    /// By grid position
+    nodes: BTreeMap<u8, (u8, Router)>,
+    next_id: u8,
+    restarts: u32,
+    step: u32,
+    /// Uplinks the gateway received, as source, phase and round
+    delivered: BTreeSet<(u8, u8, u8)>,
+    /// Sends and receives which failed, reported but not a failure by themselves
+    errors: u32,
+}
+
+impl Mesh {
+    fn new(nodes: u8) -> Self {
+        let env = Rc::new(RefCell::new(SimulationEnv::new()));
+        let mut mesh = Self {
+            gw: gateway(&env, 0),
+            env,
+            nodes: BTreeMap::new(),
+            next_id: FIRST_NODE,
+            restarts: 0,
+            step: 0,
+            delivered: BTreeSet::new(),
+            errors: 0,
+        };
+        for position in 0..nodes {
+            mesh.join(position);
+        }
+        mesh
+    }
+
+    /// A new node at `position`, hearing the neighbours there and the gateway in the first column
+    fn join(&mut self, position: u8) {
+        let node_id = self.next_id;
+        self.next_id += 1;
+        let mut neighbours: std::vec::Vec<u8> = [
+            position.checked_sub(1).filter(|_| position % COLUMNS > 0),
+            Some(position + 1).filter(|_| position % COLUMNS + 1 < COLUMNS),
+            position.checked_sub(COLUMNS),
+            position.checked_add(COLUMNS),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|at| self.nodes.get(&at).map(|(id, _)| *id))
+        .collect();
+        if position % COLUMNS == 0 {
+            neighbours.push(GW);
+        }
+        let mut env = self.env.borrow_mut();
+        env.inboxes.entry(node_id).or_default();
+        for neighbour in neighbours {
+            env.add_bidi_link(node_id, neighbour);
+        }
+        drop(env);
+
+        let mut manager = NetworkManager::<SIZE, LEN>::new(node_id, 5, 3);
+        // Every new bootup replaces the path, so hops can grow when a relay leaves
+        manager.set_gateway_ttl(Some(Duration::from_secs(60)));
+        let radio = SimRadio {
+            node_id,
+            env: self.env.clone(),
+        };
+        self.nodes.insert(
+            position,
+            (node_id, MeshRouter::new(radio, manager, NodePolicy)),
+        );
+    }
+
+    fn leave(&mut self, position: u8) {
+        if let Some((node_id, _)) = self.nodes.remove(&position) {
+            self.env.borrow_mut().isolate(node_id);
+        }
+    }
+
+    /// A new gateway in place of the old one, which lost what was on the air while it was down
+    fn restart_gateway(&mut self) {
+        self.restarts += 1;
+        if let Some(inbox) = self.env.borrow_mut().inboxes.get_mut(&GW) {
+            inbox.clear();
+        }
+        self.gw = gateway(&self.env, self.restarts);
+    }
+
+    /// The gateway and every node receive once, after the gateway's bootup if one is due
+    async fn step(&mut self) {
+        if self.step % BOOTUP_EVERY == 0 && self.gw.bootup().await.is_err() {
+            self.errors += 1;
+        }
+        match self.gw.receive((), &()).await {
+            Ok(pkts) => {
+                for delivered in pkts {
+                    let pkt = delivered.packet;
+                    if let (PacketType::Data, [phase, round]) = (pkt.packet_type, &pkt.payload[..])
+                    {
+                        self.delivered.insert((pkt.source_id, *phase, *round));
+                    }
+                }
+            }
+            Err(_) => self.errors += 1,
+        }
+        for (_, router) in self.nodes.values_mut() {
+            if router.receive((), &()).await.is_err() {
+                self.errors += 1;
+            }
+        }
+        self.step += 1;
+    }
+
+    /// The hops from each node the gateway can reach to it
+    fn expected_hops(&self) -> BTreeMap<u8, u8> {
+        let env = self.env.borrow();
+        let mut hops = BTreeMap::from([(GW, 0)]);
+        let mut queue = VecDeque::from([GW]);
+        while let Some(node_id) = queue.pop_front() {
+            let next = hops[&node_id] + 1;
+            for neighbour in env.topology.get(&node_id).into_iter().flatten() {
+                if !hops.contains_key(neighbour) {
+                    hops.insert(*neighbour, next);
+                    queue.push_back(*neighbour);
+                }
+            }
+        }
+        hops
+    }
+
+    fn converged(&self) -> bool {
+        let expected = self.expected_hops();
+        self.nodes.values().all(|(node_id, router)| {
+            expected
+                .get(node_id)
+                .is_none_or(|hops| router.gw_hops() == *hops)
+        })
+    }
+
+    /// Steps until the mesh converged, how many it took if within `MAX_CONVERGENCE_STEPS`
+    async fn converge(&mut self) -> Option<u32> {
+        for steps in 0..=MAX_CONVERGENCE_STEPS {
+            if self.converged() {
+                return Some(steps);
+            }
+            self.step().await;
+        }
+        None
+    }
+
+    /// Every node the gateway can reach sends `TRAFFIC_ROUNDS` uplinks, one node per step.
+    /// Returns how many were sent and how many the gateway received.
+    async fn traffic(&mut self, phase: u8) -> (u32, u32) {
+        let reachable = self.expected_hops();
+        let positions: std::vec::Vec<u8> = self
+            .nodes
+            .iter()
+            .filter(|(_, (node_id, _))| reachable.contains_key(node_id))
+            .map(|(position, _)| *position)
+            .collect();
+        let mut sent = 0;
+        for round in 0..TRAFFIC_ROUNDS {
+            for position in &positions {
+                let (_, router) = self.nodes.get_mut(position).expect("senders stay");
+                let payload = Vec::from_slice(&[phase, round]).expect("fits in SIZE");
+                sent += 1;
+                if router.send_payload(payload, GW).await.is_err() {
+                    self.errors += 1;
+                }
+                self.step().await;
+            }
+        }
+        for _ in 0..SETTLE_STEPS {
+            self.step().await;
+        }
+        let delivered = self
+            .delivered
+            .iter()
+            .filter(|(_, of, _)| *of == phase)
+            .count() as u32;
+        (sent, delivered)
+    }
+}
+
+/// A gateway hearing every node, seeded by how often it restarted, so its packet ids differ
+fn gateway(env: &Env, restarts: u32) -> Gateway {
+    let mut manager = NetworkManager::<SIZE, LEN>::new(GW, 5, 3);
+    manager.seed(Xorshift::new(0x5eed + restarts));
+    sim::gateway(env, manager, &[])
+}
+
+/// Outcomes of the checks, printed and counted as they come in
+#[derive(Default)]
+struct Report {
+    failed: u32,
+}
+
+impl Report {
+    fn converged(&mut self, phase: &str, steps: Option<u32>) {
+        match steps {
+            Some(steps) => println!("PASS {}: converged in {} steps", phase, steps),
+            None => {
+                println!(
+                    "FAIL {}: not converged within {} steps",
+                    phase, MAX_CONVERGENCE_STEPS
+                );
+                self.failed += 1;
+            }
+        }
+    }
+
+    fn delivered(&mut self, phase: &str, (sent, delivered): (u32, u32)) {
+        let ratio = match sent {
+            0 => 1.0,
+            _ => delivered as f32 / sent as f32,
+        };
+        let passed = ratio >= MIN_DELIVERY_RATIO;
+        println!(
+            "{} {}: {}/{} uplinks delivered ({:.1}%, at least {:.1}%)",
+            if passed { "PASS" } else { "FAIL" },
+            phase,
+            delivered,
+            sent,
+            ratio * 100.0,
+            MIN_DELIVERY_RATIO * 100.0
+        );
+        if !passed {
+            self.failed += 1;
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let nodes = match std::env::args().nth(1).map(|n| n.parse::<u8>()) {
+        None => DEFAULT_NODES,
+        Some(Ok(n)) if (1..=MAX_NODES).contains(&n) => n,
+        _ => {
+            eprintln!("usage: must-acceptance [nodes, 1 to {}]", MAX_NODES);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("{} nodes, {} per row", nodes, COLUMNS);
+    let mut mesh = Mesh::new(nodes);
+    let mut report = Report::default();
+
+    let steps = mesh.converge().await;
+    report.converged("formation", steps);
+    let traffic = mesh.traffic(FORMATION).await;
+    report.delivered("first traffic", traffic);
+
+    let traffic = mesh.traffic(STEADY).await;
+    report.delivered("steady traffic", traffic);
+
+    mesh.restart_gateway();
+    let steps = mesh.converge().await;
+    report.converged("gateway restart", steps);
+    let traffic = mesh.traffic(RESTARTED).await;
+    report.delivered("traffic after the restart", traffic);
+
+    let churned: std::vec::Vec<u8> = (0..nodes).filter(|at| at % CHURN_EVERY == 1).collect();
+    for position in &churned {
+        mesh.leave(*position);
+    }
+    let steps = mesh.converge().await;
+    report.converged("nodes leaving", steps);
+    for position in churned {
+        mesh.join(position);
+    }
+    let steps = mesh.converge().await;
+    report.converged("nodes joining", steps);
+    let traffic = mesh.traffic(CHURNED).await;
+    report.delivered("traffic after churn", traffic);
+
+    println!(
+        "{} steps, {} failed sends and receives",
+        mesh.step, mesh.errors
+    );
+    match report.failed {
+        0 => {
+            println!("GO");
+            ExitCode::SUCCESS
+        }
+        failed => {
+            println!("NO GO: {} checks failed", failed);
+            ExitCode::FAILURE
+        }
+    }
+}
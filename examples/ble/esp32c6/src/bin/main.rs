@@ -11,6 +11,8 @@
 mod ble_bas_peripheral_run;
 #[path = "../led_runner.rs"]
 mod led_runner;
+#[path = "../musthop_service.rs"]
+mod musthop_service;
 // use c6_tester::led_runner::slide_rbg_colors;
 // use c6_tester::bas_peripheral::ble_bas_peripheral_run;
 
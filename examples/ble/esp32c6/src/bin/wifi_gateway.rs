@@ -0,0 +1,289 @@
+//! A mini-gateway for small sites without a Raspberry Pi: an ESP32-C6 with an SX1262 runs the
+//! mesh's gateway with `must_hop_lora::tasks::gateway_task`, and posts every packet it receives
+//! over Wi-Fi to an HTTP collector, as the same JSON fields must-gw's TCP sink sends. There is no
+//! wall clock here, so the collector stamps when it got the packet.
+//!
+//! The SX1262 is wired like in `main.rs`. Set the network when building:
+//! `SSID=... PASSWORD=... COLLECTOR=192.168.1.10 cargo run --release --bin wifi_gateway`
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+#![deny(clippy::large_stack_frames)]
+
+use core::{fmt::Write as _, net::Ipv4Addr};
+
+use defmt::{error, info, warn};
+use embassy_executor::Spawner;
+use embassy_net::{Runner, Stack, StackResources, tcp::TcpSocket};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::{Channel, Receiver},
+};
+use embassy_time::{Delay, Duration, Timer};
+use embedded_hal_bus::spi::ExclusiveDevice;
+use embedded_io_async::Write as _;
+use esp_hal::{
+    Async, Config,
+    gpio::{Input, InputConfig, Level, Output, OutputConfig},
+    rng::Rng,
+    spi::{
+        Mode,
+        master::{Config as SpiConfig, Spi},
+    },
+    time::Rate,
+    timer::timg::TimerGroup,
+};
+use esp_radio::wifi::{ClientConfig, ModeConfig, WifiController, WifiDevice, WifiEvent};
+use heapless::String;
+use lora_phy::{
+    LoRa,
+    iv::GenericSx126xInterfaceVariant,
+    mod_params::{Bandwidth, CodingRate, SpreadingFactor},
+    sx126x::{self, Sx126x, Sx1262, TcxoCtrlVoltage},
+};
+use must_hop::node::{
+    LinkDirection, MHPacket, config::ChannelPlan, entropy, network_manager::NetworkManager,
+};
+use must_hop_lora::{TransmitParameters, tasks::gateway_task};
+use panic_rtt_target as _;
+use rtt_target::rtt_init_defmt;
+use static_cell::StaticCell;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+const SSID: &str = match option_env!("SSID") {
+    Some(ssid) => ssid,
+    None => "must-hop",
+};
+const PASSWORD: &str = match option_env!("PASSWORD") {
+    Some(password) => password,
+    None => "",
+};
+/// IPv4 address of the HTTP collector
+const COLLECTOR: &str = match option_env!("COLLECTOR") {
+    Some(collector) => collector,
+    None => "192.168.1.10",
+};
+const COLLECTOR_PORT: u16 = 8080;
+/// Sent as `gateway` with every packet, to tell gateways apart at the collector
+const GATEWAY_NAME: &str = "esp32c6";
+
+const GW_ID: u8 = 1;
+const SIZE: usize = 40;
+const LEN: usize = 5;
+/// Packets received but not posted yet, more are dropped while the collector is slow
+const UPLINK_QUEUE: usize = 8;
+const BOOTUP_INTERVAL: Duration = Duration::from_secs(60);
+/// Advertised to the mesh in the bootups
+const PLAN: ChannelPlan = ChannelPlan {
+    frequency_hz: 868_100_000, // warning: set this appropriately for the region
+    spreading_factor: 7,
+    bandwidth_khz: 125,
+};
+
+static UPLINKS: Channel<CriticalSectionRawMutex, MHPacket<SIZE>, UPLINK_QUEUE> = Channel::new();
+
+type GatewayLoRa = LoRa<
+    Sx126x<
+        ExclusiveDevice<Spi<'static, Async>, Output<'static>, Delay>,
+        GenericSx126xInterfaceVariant<Output<'static>, Input<'static>>,
+        Sx1262,
+    >,
+    Delay,
+>;
+
+#[allow(
+    clippy::large_stack_frames,
+    reason = "it's not unusual to allocate larger buffers etc. in main"
+)]
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    let p = esp_hal::init(Config::default());
+    rtt_init_defmt!();
+    let timg0 = TimerGroup::new(p.TIMG0);
+    let sw_interrupt = esp_hal::interrupt::software::SoftwareInterruptControl::new(p.SW_INTERRUPT);
+    esp_rtos::start(timg0.timer0, sw_interrupt.software_interrupt0);
+    esp_alloc::heap_allocator!(size: 72 * 1024);
+
+    info!("Setting up Wi-Fi");
+    static RADIO: StaticCell<esp_radio::Controller<'static>> = StaticCell::new();
+    let radio = RADIO.init(esp_radio::init().expect("Radio init failed"));
+    let (controller, interfaces) =
+        esp_radio::wifi::new(radio, p.WIFI, Default::default()).expect("Wi-Fi init failed");
+    let rng = Rng::new();
+    let seed = u64::from(rng.random()) << 32 | u64::from(rng.random());
+    static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
+    let (stack, runner) = embassy_net::new(
+        interfaces.sta,
+        embassy_net::Config::dhcpv4(Default::default()),
+        RESOURCES.init(StackResources::new()),
+        seed,
+    );
+    spawner
+        .spawn(wifi_task(controller))
+        .expect("TASK wifi failed");
+    spawner.spawn(net_task(runner)).expect("TASK net failed");
+
+    info!("Setting up the SX1262");
+    let spi = Spi::new(
+        p.SPI2,
+        SpiConfig::default()
+            .with_frequency(Rate::from_mhz(8))
+            .with_mode(Mode::_0),
+    )
+    .expect("SPI init failed")
+    .with_sck(p.GPIO9)
+    .with_mosi(p.GPIO10)
+    .with_miso(p.GPIO11)
+    .into_async();
+    let nss = Output::new(p.GPIO7, Level::High, OutputConfig::default());
+    let spi = ExclusiveDevice::new(spi, nss, Delay).expect("SPI device init failed");
+    let iv = GenericSx126xInterfaceVariant::new(
+        Output::new(p.GPIO12, Level::High, OutputConfig::default()),
+        Input::new(p.GPIO14, InputConfig::default()),
+        Input::new(p.GPIO13, InputConfig::default()),
+        None,
+        None,
+    )
+    .expect("SX1262 pins failed");
+    let config = sx126x::Config {
+        chip: Sx1262,
+        tcxo_ctrl: Some(TcxoCtrlVoltage::Ctrl1V7),
+        use_dcdc: true,
+        rx_boost: false,
+    };
+    let lora = LoRa::new(Sx126x::new(spi, iv, config), false, Delay)
+        .await
+        .expect("SX1262 init failed");
+    spawner.spawn(radio_task(lora)).expect("TASK radio failed");
+    spawner
+        .spawn(uplink_task(stack, UPLINKS.receiver()))
+        .expect("TASK uplink failed");
+
+    loop {
+        Timer::after_secs(60).await;
+    }
+}
+
+#[embassy_executor::task]
+async fn radio_task(mut lora: GatewayLoRa) {
+    let tp = TransmitParameters {
+        sf: SpreadingFactor::_7,
+        bw: Bandwidth::_125KHz,
+        cr: CodingRate::_4_5,
+        lora_hz: PLAN.frequency_hz,
+        pre_amp: 8,
+        imp_hed: false,
+        max_pack_len: SIZE,
+        crc: true,
+        direction: LinkDirection::Mesh,
+    };
+    let mut manager = NetworkManager::<SIZE, LEN>::new(GW_ID, 5, 3);
+    let rng = Rng::new();
+    manager.seed(entropy::from_fn(|| rng.random()));
+    manager.set_channel_plan(PLAN);
+    gateway_task(&mut lora, UPLINKS.sender(), tp, manager, BOOTUP_INTERVAL).await;
+}
+
+/// Keeps the station connected, reconnecting whenever the access point drops it
+#[embassy_executor::task]
+async fn wifi_task(mut controller: WifiController<'static>) {
+    let config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    if let Err(e) = controller.set_config(&config) {
+        error!("[wifi] bad config: {:?}", e);
+        return;
+    }
+    loop {
+        if !matches!(controller.is_started(), Ok(true)) {
+            if let Err(e) = controller.start_async().await {
+                error!("[wifi] start failed: {:?}", e);
+            }
+        }
+        match controller.connect_async().await {
+            Ok(()) => {
+                info!("[wifi] connected to {}", SSID);
+                controller.wait_for_event(WifiEvent::StaDisconnected).await;
+                warn!("[wifi] disconnected");
+            }
+            Err(e) => warn!("[wifi] connecting failed: {:?}", e),
+        }
+        Timer::after_secs(5).await;
+    }
+}
+
+#[embassy_executor::task]
+async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
+    runner.run().await
+}
+
+/// Posts received packets to the collector, one request each. A packet the collector can't be
+/// reached for is dropped, there is no spool like in must-gw.
+#[embassy_executor::task]
+async fn uplink_task(
+    stack: Stack<'static>,
+    uplinks: Receiver<'static, CriticalSectionRawMutex, MHPacket<SIZE>, UPLINK_QUEUE>,
+) {
+    let Ok(collector) = COLLECTOR.parse::<Ipv4Addr>() else {
+        error!("[uplink] COLLECTOR is not an IPv4 address");
+        return;
+    };
+    stack.wait_config_up().await;
+    info!("[uplink] posting to {}:{}", COLLECTOR, COLLECTOR_PORT);
+    let mut rx_buffer = [0u8; 512];
+    let mut tx_buffer = [0u8; 512];
+    loop {
+        let packet = uplinks.receive().await;
+        let Some(body) = to_json(&packet) else {
+            warn!("[uplink] packet too large to post");
+            continue;
+        };
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(5)));
+        if let Err(e) = socket.connect((collector, COLLECTOR_PORT)).await {
+            warn!("[uplink] collector unreachable: {:?}", e);
+            continue;
+        }
+        let mut head: String<128> = String::new();
+        let _ = write!(
+            head,
+            "POST /uplinks HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n",
+            COLLECTOR,
+            body.len()
+        );
+        let sent = async {
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(body.as_bytes()).await?;
+            socket.flush().await
+        };
+        if let Err(e) = sent.await {
+            warn!("[uplink] posting failed: {:?}", e);
+        }
+        // The response doesn't matter, the collector closes the connection after it
+        socket.close();
+    }
+}
+
+/// The packet as must-gw's TCP sink sends it, without `received_at`
+fn to_json(packet: &MHPacket<SIZE>) -> Option<String<256>> {
+    let mut json = String::new();
+    write!(
+        json,
+        "{{\"gateway\":\"{}\",\"source_id\":{},\"packet_id\":{},\"payload\":\"",
+        GATEWAY_NAME, packet.source_id, packet.packet_id
+    )
+    .ok()?;
+    for byte in &packet.payload {
+        write!(json, "{:02x}", byte).ok()?;
+    }
+    json.push_str("\"}").ok()?;
+    Some(json)
+}
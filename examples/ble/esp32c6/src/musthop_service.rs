@@ -0,0 +1,177 @@
+//! The MustHop GATT service, for a phone app to inspect a node in the field and poke it with a
+//! payload. The UUIDs are those in `must_hop::node::status`; the gatt macros need them as
+//! literals. The router runs in the radio task, and this talks to it through a `MeshHandle`.
+//! At install time the app writes the node's `Provisioning`, which is saved before the node
+//! reboots into the mesh with it.
+use defmt::{info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::Timer;
+use heapless::Vec;
+use must_hop::node::{
+    BROADCAST_ID,
+    handle::{MeshEvent, MeshHandle},
+    provision::{self, ProvisionStore, Provisioning},
+    status::{MeshStatus, parse_inject},
+};
+use trouble_host::prelude::*;
+
+/// Largest payload the app can inject, what fits in a write with the default MTU besides the
+/// destination
+pub const INJECT_LEN: usize = 19;
+/// How often the status is refreshed and notified while an app is connected, and nothing else
+/// happens on the connection
+const STATUS_INTERVAL_SECS: u64 = 2;
+
+#[gatt_server]
+pub struct MustHopServer {
+    pub musthop: MustHopService,
+}
+
+/// Mirrors `MeshStatus`, one characteristic per field
+#[gatt_service(uuid = "6d750001-7374-4f68-6f70-6d6573680000")]
+pub struct MustHopService {
+    #[characteristic(uuid = "6d750002-7374-4f68-6f70-6d6573680000", read)]
+    pub node_id: u8,
+    #[characteristic(
+        uuid = "6d750003-7374-4f68-6f70-6d6573680000",
+        read,
+        notify,
+        value = 255
+    )]
+    pub gw_hops: u8,
+    #[characteristic(uuid = "6d750004-7374-4f68-6f70-6d6573680000", read, notify)]
+    pub pending: u8,
+    #[characteristic(uuid = "6d750005-7374-4f68-6f70-6d6573680000", read, notify, value = i16::MIN)]
+    pub last_rssi: i16,
+    #[characteristic(uuid = "6d750006-7374-4f68-6f70-6d6573680000", read, notify)]
+    pub queue_depth: u8,
+    /// The destination id, then the payload
+    #[characteristic(uuid = "6d750007-7374-4f68-6f70-6d6573680000", write)]
+    pub inject: [u8; INJECT_LEN + 1],
+    /// An encoded `Provisioning`
+    #[characteristic(uuid = "6d750008-7374-4f68-6f70-6d6573680000", write)]
+    pub provision: [u8; provision::MAX_ENCODED_LEN],
+}
+
+/// Serves the MustHop service on `conn` until the app disconnects: keeps the status
+/// characteristics up to date from the router, and sends what the app writes to the inject
+/// characteristic through `handle`. A valid provisioning is saved in `store`, and the node
+/// reboots into the mesh with it once the write is acknowledged.
+#[allow(unused)]
+pub async fn musthop_gatt_task<
+    M: RawMutex,
+    S: ProvisionStore,
+    const SIZE: usize,
+    const CAP: usize,
+    const SUBS: usize,
+>(
+    server: &MustHopServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    handle: &MeshHandle<'_, M, SIZE, CAP, SUBS>,
+    store: &mut S,
+) {
+    let Ok(mut events) = handle.subscribe_events() else {
+        warn!("[musthop] no subscriber left for the status");
+        return;
+    };
+    let service = &server.musthop;
+    loop {
+        let event = select(conn.next(), async {
+            Timer::after_secs(STATUS_INTERVAL_SECS).await;
+            handle.request_status().await;
+            loop {
+                if let MeshEvent::Status(status) = events.next_message_pure().await {
+                    break status;
+                }
+            }
+        });
+        match event.await {
+            Either::First(GattConnectionEvent::Disconnected { reason }) => {
+                info!("[musthop] disconnected: {:?}", reason);
+                return;
+            }
+            Either::First(GattConnectionEvent::Gatt { event }) => {
+                let mut provisioned = false;
+                if let GattEvent::Write(write) = &event {
+                    if write.handle() == service.inject.handle {
+                        inject(write.data(), handle).await;
+                    } else if write.handle() == service.provision.handle {
+                        provisioned = provision(write.data(), store).await;
+                    }
+                }
+                if let Ok(reply) = event.accept() {
+                    reply.send().await;
+                }
+                if provisioned {
+                    info!("[musthop] provisioned, rebooting into the mesh");
+                    esp_hal::system::software_reset();
+                }
+            }
+            Either::First(_) => {}
+            Either::Second(status) => notify_status(server, conn, &status).await,
+        }
+    }
+}
+
+/// Sends a payload written by the app, to its destination or to everyone
+async fn inject<M: RawMutex, const SIZE: usize, const CAP: usize, const SUBS: usize>(
+    value: &[u8],
+    handle: &MeshHandle<'_, M, SIZE, CAP, SUBS>,
+) {
+    let Some((destination, payload)) = parse_inject(value) else {
+        warn!("[musthop] empty inject");
+        return;
+    };
+    let Ok(payload) = Vec::from_slice(payload) else {
+        warn!("[musthop] injected payload is larger than SIZE");
+        return;
+    };
+    info!(
+        "[musthop] injecting {} bytes to {}",
+        payload.len(),
+        destination
+    );
+    if destination == BROADCAST_ID {
+        handle.broadcast(payload).await;
+    } else {
+        handle.send(payload, destination).await;
+    }
+}
+
+/// Saves a provisioning written by the app, returning whether the node should reboot with it
+async fn provision<S: ProvisionStore>(value: &[u8], store: &mut S) -> bool {
+    let provisioning = match Provisioning::decode(value) {
+        Ok(provisioning) => provisioning,
+        Err(e) => {
+            warn!("[musthop] refused provisioning: {:?}", e);
+            return false;
+        }
+    };
+    if store.save_provisioning(&provisioning).await.is_err() {
+        warn!("[musthop] saving the provisioning failed");
+        return false;
+    }
+    info!(
+        "[musthop] provisioned as node {} on network {}",
+        provisioning.node_id, provisioning.network_id
+    );
+    true
+}
+
+async fn notify_status(
+    server: &MustHopServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    status: &MeshStatus,
+) {
+    let service = &server.musthop;
+    let _ = server.set(&service.node_id, &status.node_id);
+    // A failed notify means the app went away, which the connection reports next
+    let _ = service.gw_hops.notify(conn, &status.gw_hops).await;
+    let _ = service.pending.notify(conn, &status.pending).await;
+    let _ = service
+        .last_rssi
+        .notify(conn, &status.last_rssi_or_min())
+        .await;
+    let _ = service.queue_depth.notify(conn, &status.queue_depth).await;
+}
@@ -0,0 +1,59 @@
+//! Receives with a Corecell USB concentrator, e.g. a RAK7271 or a Semtech SX1302CFDUSB, with the
+//! same configuration as the RAK2287 on SPI but for the board.
+//!
+//! Run it with `cargo run --bin usb -- /dev/ttyACM0`, the path being the default.
+use loragw::{ComType, Concentrator, Error, ResetToken, Running, RxPacket, cfg::Config};
+use std::ffi::CString;
+
+fn create_concentrator(path: &str) -> Result<Concentrator<Running>, Error> {
+    let mut conf = Config::from_str_or_default(None)?;
+    conf.board.com_type = ComType::USB;
+    conf.board.spidev_path = CString::new(path).map_err(|_| Error::Data)?;
+
+    // The STM32 on the USB stick resets the SX1302 when the HAL connects, no GPIO needed
+    Concentrator::open(&ResetToken::usb())?
+        .set_config(&conf)?
+        .connect()?
+        .start()
+}
+
+fn main() {
+    env_logger::init();
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/dev/ttyACM0".to_string());
+
+    let conc = match create_concentrator(&path) {
+        Ok(conc) => conc,
+        Err(e) => {
+            eprintln!("Error starting the concentrator on {path}: {e}");
+            eprintln!("Is it plugged in, and are you in the dialout group?");
+            return;
+        }
+    };
+    match conc.eui() {
+        Ok(eui) => println!("Concentrator {eui:016X} on {path}"),
+        Err(e) => eprintln!("Error reading the EUI: {e}"),
+    }
+    loop {
+        let pkts: Vec<RxPacket> = match conc.receive() {
+            Ok(Some(pkts)) => pkts,
+            Ok(None) => {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Error receiving packets: {e}");
+                continue;
+            }
+        };
+        for pkt in pkts {
+            println!(
+                "{} Hz, RSSI {:?}: {:02X?}",
+                pkt.freq(),
+                pkt.rssi(),
+                pkt.payload()
+            );
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use libloragw_sys::{lgw_get_eui, lgw_version_info};
+use libloragw_sys::lgw_version_info;
 use loragw::{
     cfg::Config, BoardConf, ChannelConf, Concentrator, Error, Running, RxPacket, RxRFConf, TxGain,
 };
-use must_hop::{lora::SensorData, node::MHPacket};
+use must_hop::{node::MHPacket, telemetry::SensorReport};
 use rppal::gpio::Gpio;
 use std::ffi::CStr;
 use std::thread;
@@ -91,8 +91,6 @@ fn main() {
     unsafe {
         // lgw_version_info returns a *const c_char
         let version_ptr = lgw_version_info();
-        // let mut test: u64 = 10;
-        // let eui_ptr = lgw_get_eui(&mut test);
 
         if !version_ptr.is_null() {
             // Convert the C string pointer to a safe Rust String
@@ -101,8 +99,6 @@ fn main() {
         } else {
             println!("Failed to get version info pointer.");
         }
-        // Convert the C string pointer to a safe Rust String
-        // println!("Success! libloragw EUI: {}", eui_ptr);
     }
     println!("Now try and use loragw:");
     let conc = match create_concentrator() {
@@ -112,6 +108,10 @@ fn main() {
             return;
         }
     };
+    match conc.eui() {
+        Ok(eui) => println!("Concentrator EUI: {:016X}", eui),
+        Err(e) => eprintln!("Error reading the EUI: {:?}", e),
+    }
     println!("check receive status");
     match conc.receive_status() {
         Ok(status) => println!("Receive status: {:?}", status),
@@ -134,7 +134,7 @@ fn main() {
                 _ => continue,
             };
             let raw_bytes = pkt.payload;
-            let mh_pack = match postcard::from_bytes::<heapless::Vec<MHPacket<40>, 5>>(&raw_bytes) {
+            let mh_pack = match MHPacket::<40>::decode_frame::<5>(&raw_bytes) {
                 Ok(packet) => packet,
                 Err(e) => {
                     eprintln!("Error deserializing MHPacket: {:?}", e);
@@ -144,10 +144,10 @@ fn main() {
             println!("SUCCESS !!!! Received packet: {:?}", mh_pack.len());
             for pack in mh_pack {
                 let raw_bytes = pack.payload;
-                let sensor_data = match postcard::from_bytes::<SensorData>(&raw_bytes) {
-                    Ok(packet) => packet,
+                let sensor_data = match SensorReport::<16>::decode(&raw_bytes) {
+                    Ok(report) => report,
                     Err(e) => {
-                        eprintln!("Error deserializing SensorData: {:?}", e);
+                        eprintln!("Error decoding SensorReport: {:?}", e);
                         continue;
                     }
                 };
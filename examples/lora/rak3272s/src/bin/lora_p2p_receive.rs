@@ -5,8 +5,6 @@
 
 #[path = "../iv.rs"]
 mod iv;
-#[path = "../lora_tasks.rs"]
-mod lora_tasks;
 
 use defmt::{error, info, warn};
 use embassy_executor::Spawner;
@@ -17,14 +15,14 @@ use embassy_stm32::{
     spi::Spi,
 };
 use embassy_time::Delay;
+use heapless::Vec;
 use lora_phy::sx126x::{Stm32wl, Sx126x};
 use lora_phy::{LoRa, RxMode};
 use lora_phy::{mod_params::*, sx126x};
-use postcard::from_bytes;
+use must_hop::telemetry::{Acceleration, Reading, SensorReport};
 use {defmt_rtt as _, panic_probe as _};
 
 use self::iv::{InterruptHandler, Stm32wlInterfaceVariant, SubghzSpiDevice};
-use self::lora_tasks::SensorData;
 
 const LORA_FREQUENCY_IN_HZ: u32 = 868_000_000; // warning: set this appropriately for the region
 
@@ -64,11 +62,17 @@ async fn main(_spawner: Spawner) {
         .unwrap();
     info!("lora setup done ...");
 
-    let expected_packet = SensorData {
+    let expected_packet = SensorReport::<3> {
         device_id: 42,
-        temperate: 23.5,
-        voltage: 3.3,
-        acceleration_x: 1.2,
+        readings: Vec::from_array([
+            Reading::Temperature(23.5),
+            Reading::Battery(3300),
+            Reading::Acceleration(Acceleration {
+                x: 1.2,
+                y: 0.0,
+                z: 0.0,
+            }),
+        ]),
     };
 
     loop {
@@ -120,7 +124,7 @@ async fn main(_spawner: Spawner) {
             Ok((len, rx_pkt_status)) => {
                 info!("rx successful, pkt status: {:?}", rx_pkt_status);
                 let valid_data = &receiving_buffer[..len as usize];
-                match from_bytes::<SensorData>(valid_data) {
+                match SensorReport::<3>::decode(valid_data) {
                     Ok(packet) => {
                         info!("Got packet!");
                         if packet == expected_packet {
@@ -5,8 +5,6 @@
 
 #[path = "../iv.rs"]
 mod iv;
-#[path = "../lora_tasks.rs"]
-mod lora_tasks;
 
 use defmt::{error, info};
 use embassy_executor::Spawner;
@@ -14,14 +12,14 @@ use embassy_stm32::bind_interrupts;
 use embassy_stm32::gpio::{Level, Output, Speed};
 use embassy_stm32::spi::Spi;
 use embassy_time::{Delay, Timer};
+use heapless::Vec;
 use lora_phy::LoRa;
 use lora_phy::sx126x::{Stm32wl, Sx126x};
 use lora_phy::{mod_params::*, sx126x};
-use postcard::to_slice;
+use must_hop::telemetry::{Acceleration, Reading, SensorReport};
 use {defmt_rtt as _, panic_probe as _};
 
 use self::iv::{InterruptHandler, Stm32wlInterfaceVariant, SubghzSpiDevice};
-use self::lora_tasks::SensorData;
 
 const LORA_FREQUENCY_IN_HZ: u32 = 868_000_000; // warning: set this appropriately for the region
 
@@ -89,15 +87,20 @@ async fn main(_spawner: Spawner) {
                 }
             }
         };
-        let mut buffer = [0u8; 32];
-        let packet = SensorData {
+        let report = SensorReport::<3> {
             device_id: 42,
-            temperate: 23.5,
-            voltage: 3.3,
-            acceleration_x: 1.2,
+            readings: Vec::from_array([
+                Reading::Temperature(23.5),
+                Reading::Battery(3300),
+                Reading::Acceleration(Acceleration {
+                    x: 1.2,
+                    y: 0.0,
+                    z: 0.0,
+                }),
+            ]),
         };
-        let used_slice = match to_slice(&packet, &mut buffer) {
-            Ok(slice) => slice,
+        let payload = match report.encode::<32>() {
+            Ok(payload) => payload,
             Err(e) => {
                 error!("Serialization failed: {:?}", e);
                 continue;
@@ -105,7 +108,7 @@ async fn main(_spawner: Spawner) {
         };
 
         if let Err(err) = lora
-            .prepare_for_tx(&mdltn_params, &mut tx_pkt_params, 20, used_slice)
+            .prepare_for_tx(&mdltn_params, &mut tx_pkt_params, 20, &payload)
             .await
         {
             error!("Radio error = {}", err);
@@ -32,15 +32,16 @@ use self::iv::{InterruptHandler, Stm32wlInterfaceVariant, SubghzSpiDevice};
 use embassy_stm32::spi::mode::Master;
 
 use embassy_stm32::mode::Async;
-use must_hop::{lora::TransmitParameters, tasks::lora};
-use postcard::to_slice;
+use must_hop::{
+    node::{LinkDirection, entropy::Xorshift},
+    telemetry::{Acceleration, Reading, SensorReport},
+};
+use must_hop_lora::{TransmitParameters, tasks as lora};
 use {defmt_rtt as _, panic_probe as _};
 
-use serde::{Deserialize, Serialize};
-
 const LORA_FREQUENCY_IN_HZ: u32 = 868_100_000; // warning: set this appropriately for the region
 
-static CHANNEL: Channel<ThreadModeRawMutex, SensorData, 3> = Channel::new();
+static CHANNEL: Channel<ThreadModeRawMutex, Vec<u8, MAX_PACK_LEN>, 3> = Channel::new();
 
 bind_interrupts!(struct Irqs{
     SUBGHZ_RADIO => InterruptHandler;
@@ -79,11 +80,13 @@ async fn main(spawner: Spawner) {
         .await
         .unwrap();
     info!("lora setup done ...");
-    if let Err(e) = spawner.spawn(lora_task(lora, CHANNEL.receiver())) {
+    let mut rng = Rng::new(p.RNG, Irqs);
+    // The task can't be generic over the RNG, so the mesh gets a seed from it
+    let seed = rng.next_u32();
+    if let Err(e) = spawner.spawn(lora_task(lora, CHANNEL.receiver(), seed)) {
         error!("error in spawning lora task: {:?}", e);
     }
     // TODO: Add sensor data creation task
-    let rng = Rng::new(p.RNG, Irqs);
     if let Err(e) = spawner.spawn(sensor_task(CHANNEL.sender(), rng)) {
         error!("Error in spawning lora task: {:?}, ", e);
     }
@@ -96,18 +99,27 @@ async fn main(spawner: Spawner) {
 
 #[embassy_executor::task]
 async fn sensor_task(
-    channel: channel::Sender<'static, ThreadModeRawMutex, SensorData, 3>,
+    channel: channel::Sender<'static, ThreadModeRawMutex, Vec<u8, MAX_PACK_LEN>, 3>,
     mut rng: Rng<'static, peripherals::RNG>, // Ensure 'mut' is here
 ) {
     Timer::after_secs(10).await;
     loop {
-        let expected_packet = SensorData {
+        let report = SensorReport::<3> {
             device_id: 42,
-            temperate: 23.5,
-            voltage: 3.3,
-            acceleration_x: 1.2,
+            readings: Vec::from_array([
+                Reading::Temperature(23.5),
+                Reading::Battery(3300),
+                Reading::Acceleration(Acceleration {
+                    x: 1.2,
+                    y: 0.0,
+                    z: 0.0,
+                }),
+            ]),
         };
-        channel.send(expected_packet).await;
+        match report.encode() {
+            Ok(payload) => channel.send(payload).await,
+            Err(e) => error!("Report doesn't fit a packet: {:?}", e),
+        }
 
         info!("Send a packet!");
         let random = rng.next_u64();
@@ -127,7 +139,8 @@ const LEN: usize = 5; // floor(256/MAX_PACK_LEN)
 #[embassy_executor::task]
 pub async fn lora_task(
     mut lora: Stm32wlLoRa<'static, Master>,
-    channel: channel::Receiver<'static, ThreadModeRawMutex, SensorData, 3>,
+    channel: channel::Receiver<'static, ThreadModeRawMutex, Vec<u8, MAX_PACK_LEN>, 3>,
+    seed: u32,
 ) {
     let sf = SpreadingFactor::_7;
     let bw = Bandwidth::_125KHz;
@@ -141,10 +154,19 @@ pub async fn lora_task(
         imp_hed: false,
         max_pack_len: MAX_PACK_LEN,
         crc: true,
-        iq: false,
+        direction: LinkDirection::Mesh,
     };
     let source_id = 1;
-    lora::lora_task::<_, _, _, _, MAX_PACK_LEN, LEN>(&mut lora, channel, tp, source_id, 3, 3).await;
+    lora::lora_task::<_, _, _, _, MAX_PACK_LEN, LEN>(
+        &mut lora,
+        channel,
+        tp,
+        source_id,
+        3,
+        3,
+        Xorshift::new(seed),
+    )
+    .await;
 }
 
 // This creates the task which checks for sensor data
@@ -157,20 +179,3 @@ type Stm32wlLoRa<'d, CM> = LoRa<
     >,
     Delay,
 >;
-
-#[derive(Serialize, Deserialize, Debug, PartialEq, defmt::Format, Copy, Clone)]
-pub struct SensorData {
-    pub device_id: u8,
-    pub temperate: f32,
-    pub voltage: f32,
-    pub acceleration_x: f32,
-}
-
-// TODO: Shuold not use this, only for prototyping
-impl From<SensorData> for Vec<u8, MAX_PACK_LEN> {
-    fn from(data: SensorData) -> Self {
-        let mut buffer = [0u8; MAX_PACK_LEN];
-        let slice = to_slice(&data, &mut buffer).expect("Could not serialize sensor data");
-        Vec::from_slice(slice).expect("buffer too small")
-    }
-}
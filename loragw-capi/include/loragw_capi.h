@@ -0,0 +1,117 @@
+/*
+ * C API for the loragw concentrator wrapper.
+ *
+ * A handle goes through open -> configure -> start -> (receive | transmit)* -> stop -> close.
+ * Calls made out of order return LGWC_ERR_STATE. Handles are not thread safe, and only one
+ * handle can be open at a time.
+ */
+#ifndef LORAGW_CAPI_H
+#define LORAGW_CAPI_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define LGWC_OK 0
+#define LGWC_ERR_NULL -1
+#define LGWC_ERR_STATE -2
+#define LGWC_ERR_HAL -3
+#define LGWC_ERR_HAL_INVALID -4
+#define LGWC_ERR_BUSY -5
+#define LGWC_ERR_SIZE -6
+#define LGWC_ERR_DATA -7
+#define LGWC_ERR_CONFIG -8
+#define LGWC_ERR_RESET -9
+#define LGWC_ERR_PANIC -10
+#define LGWC_ERR_TIMEOUT -11
+#define LGWC_ERR_TOO_LATE -12
+#define LGWC_ERR_TX_ABORTED -13
+
+/* Most packets a single lgwc_receive call returns */
+#define LGWC_RX_FIFO_SIZE 16
+#define LGWC_MAX_PAYLOAD 256
+
+#define LGWC_MOD_LORA 0
+#define LGWC_MOD_FSK 1
+
+#define LGWC_CRC_NONE 0
+#define LGWC_CRC_FAIL 1
+#define LGWC_CRC_PASS 2
+
+#define LGWC_TX_IMMEDIATE 0
+#define LGWC_TX_TIMESTAMPED 1
+#define LGWC_TX_ON_GPS 2
+
+typedef struct lgwc_handle lgwc_handle;
+
+typedef struct {
+    uint32_t freq_hz;
+    uint8_t if_chain;
+    uint8_t radio;
+    /* LGWC_MOD_* */
+    uint8_t modulation;
+    /* LGWC_CRC_* */
+    uint8_t crc_status;
+    /* LoRa only, same values as the HAL */
+    uint8_t bandwidth;
+    uint8_t spreading;
+    uint8_t coderate;
+    /* FSK only, in bits per second */
+    uint32_t datarate;
+    /* Concentrator counter at reception */
+    uint32_t count_us;
+    /* Wall clock time of reception, in milliseconds since the Unix epoch */
+    uint64_t received_at_ms;
+    float rssi;
+    /* LoRa only */
+    float snr;
+    uint16_t size;
+    uint8_t payload[LGWC_MAX_PAYLOAD];
+} lgwc_rx_packet;
+
+typedef struct {
+    uint32_t freq_hz;
+    /* LGWC_TX_* */
+    uint8_t tx_mode;
+    /* Counter value for LGWC_TX_TIMESTAMPED, ignored for LGWC_TX_ON_GPS, which sends on the next PPS */
+    uint32_t count_us;
+    uint8_t radio;
+    int8_t power;
+    uint8_t bandwidth;
+    uint8_t spreading;
+    uint8_t coderate;
+    bool invert_pol;
+    /* 0 for the default preamble */
+    uint16_t preamble;
+    bool no_crc;
+    bool no_header;
+    uint16_t size;
+    uint8_t payload[LGWC_MAX_PAYLOAD];
+} lgwc_tx_packet;
+
+/* Opens the concentrator, resetting it through the Raspberry Pi GPIO first if reset is true. */
+int lgwc_open(bool reset, lgwc_handle **out);
+/* Applies a TOML configuration, or the built in SX1302 default when config is NULL. */
+int lgwc_configure(lgwc_handle *handle, const char *config);
+/* Connects to and starts the configured concentrator. */
+int lgwc_start(lgwc_handle *handle);
+/* Reads up to LGWC_RX_FIFO_SIZE packets, max must be at least LGWC_RX_FIFO_SIZE. */
+int lgwc_receive(lgwc_handle *handle, lgwc_rx_packet *pkts, size_t max, size_t *count);
+/* Schedules a LoRa packet for transmission. */
+int lgwc_transmit(lgwc_handle *handle, const lgwc_tx_packet *pkt);
+/* Stops the concentrator, the handle must still be closed. */
+int lgwc_stop(lgwc_handle *handle);
+/* Frees the handle, stopping the concentrator if it is running. NULL is ignored. */
+void lgwc_close(lgwc_handle *handle);
+/* Static description of an error code. */
+const char *lgwc_strerror(int code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,377 @@
+//! C API over `loragw`, for embedding the concentrator in existing C daemons. The header is
+//! `include/loragw_capi.h`, keep the two in sync.
+//!
+//! The type-state `Concentrator` lives behind an opaque handle, and every call returns one of
+//! the `LGWC_*` codes instead of panicking across the FFI boundary.
+#![allow(non_camel_case_types)]
+
+use loragw::{
+    Bandwidth, Builder, CRCCheck, Coderate, Concentrator, Dbm, Error, FrontRadio, RX_FIFO_SIZE,
+    ResetToken, Running, RxPacket, Spreading, TxMode, TxPacket, TxPacketLoRa, cfg::Config,
+    raspberrypi,
+};
+use std::{
+    ffi::{CStr, c_char, c_int},
+    panic::{AssertUnwindSafe, catch_unwind},
+    time::UNIX_EPOCH,
+};
+
+pub const LGWC_OK: c_int = 0;
+pub const LGWC_ERR_NULL: c_int = -1;
+pub const LGWC_ERR_STATE: c_int = -2;
+pub const LGWC_ERR_HAL: c_int = -3;
+pub const LGWC_ERR_HAL_INVALID: c_int = -4;
+pub const LGWC_ERR_BUSY: c_int = -5;
+pub const LGWC_ERR_SIZE: c_int = -6;
+pub const LGWC_ERR_DATA: c_int = -7;
+pub const LGWC_ERR_CONFIG: c_int = -8;
+pub const LGWC_ERR_RESET: c_int = -9;
+pub const LGWC_ERR_PANIC: c_int = -10;
+pub const LGWC_ERR_TIMEOUT: c_int = -11;
+pub const LGWC_ERR_TOO_LATE: c_int = -12;
+pub const LGWC_ERR_TX_ABORTED: c_int = -13;
+
+pub const LGWC_MAX_PAYLOAD: usize = 256;
+
+pub const LGWC_MOD_LORA: u8 = 0;
+pub const LGWC_MOD_FSK: u8 = 1;
+
+pub const LGWC_CRC_NONE: u8 = 0;
+pub const LGWC_CRC_FAIL: u8 = 1;
+pub const LGWC_CRC_PASS: u8 = 2;
+
+pub const LGWC_TX_IMMEDIATE: u8 = 0;
+pub const LGWC_TX_TIMESTAMPED: u8 = 1;
+pub const LGWC_TX_ON_GPS: u8 = 2;
+
+fn code(err: &Error) -> c_int {
+    match err {
+        Error::Busy => LGWC_ERR_BUSY,
+        Error::HAL => LGWC_ERR_HAL,
+        Error::HalInvalid(_) => LGWC_ERR_HAL_INVALID,
+        Error::Size => LGWC_ERR_SIZE,
+        Error::Data => LGWC_ERR_DATA,
+        Error::ResetFailed => LGWC_ERR_RESET,
+        Error::Timeout => LGWC_ERR_TIMEOUT,
+        Error::TooLate => LGWC_ERR_TOO_LATE,
+        Error::TxAborted => LGWC_ERR_TX_ABORTED,
+        Error::BuilderError(_) | Error::Toml(_) => LGWC_ERR_CONFIG,
+    }
+}
+
+enum State {
+    Builder(Concentrator<Builder>),
+    Running(Concentrator<Running>),
+    Closed,
+}
+
+/// Opaque handle to a concentrator.
+pub struct lgwc_handle {
+    state: State,
+}
+
+#[repr(C)]
+pub struct lgwc_rx_packet {
+    pub freq_hz: u32,
+    pub if_chain: u8,
+    pub radio: u8,
+    pub modulation: u8,
+    pub crc_status: u8,
+    pub bandwidth: u8,
+    pub spreading: u8,
+    pub coderate: u8,
+    pub datarate: u32,
+    pub count_us: u32,
+    pub received_at_ms: u64,
+    pub rssi: f32,
+    pub snr: f32,
+    pub size: u16,
+    pub payload: [u8; LGWC_MAX_PAYLOAD],
+}
+
+#[repr(C)]
+pub struct lgwc_tx_packet {
+    pub freq_hz: u32,
+    pub tx_mode: u8,
+    pub count_us: u32,
+    pub radio: u8,
+    pub power: i8,
+    pub bandwidth: u8,
+    pub spreading: u8,
+    pub coderate: u8,
+    pub invert_pol: bool,
+    pub preamble: u16,
+    pub no_crc: bool,
+    pub no_header: bool,
+    pub size: u16,
+    pub payload: [u8; LGWC_MAX_PAYLOAD],
+}
+
+impl From<&RxPacket> for lgwc_rx_packet {
+    fn from(pkt: &RxPacket) -> Self {
+        // SAFE: Plain old data, all zeroes is a valid value
+        let mut out: lgwc_rx_packet = unsafe { std::mem::zeroed() };
+        let (crc_check, payload) = match pkt {
+            RxPacket::LoRa(pkt) => {
+                out.freq_hz = pkt.freq;
+                out.if_chain = pkt.if_chain;
+                out.radio = pkt.radio as u8;
+                out.modulation = LGWC_MOD_LORA;
+                out.bandwidth = pkt.bandwidth as u8;
+                out.spreading = pkt.spreading as u8;
+                out.coderate = pkt.coderate as u8;
+                out.rssi = pkt.rssi.0;
+                out.snr = pkt.snr.0;
+                (&pkt.crc_check, &pkt.payload)
+            }
+            RxPacket::FSK(pkt) => {
+                out.freq_hz = pkt.freq;
+                out.if_chain = pkt.if_chain;
+                out.radio = pkt.radio as u8;
+                out.modulation = LGWC_MOD_FSK;
+                out.datarate = pkt.datarate;
+                out.rssi = pkt.rssi.0;
+                (&pkt.crc_check, &pkt.payload)
+            }
+        };
+        out.crc_status = match crc_check {
+            CRCCheck::NoCRC => LGWC_CRC_NONE,
+            CRCCheck::Fail => LGWC_CRC_FAIL,
+            CRCCheck::Pass => LGWC_CRC_PASS,
+        };
+        out.count_us = pkt.timestamp().as_micros() as u32;
+        out.received_at_ms = pkt
+            .received_at()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let size = payload.len().min(LGWC_MAX_PAYLOAD);
+        out.payload[..size].copy_from_slice(&payload[..size]);
+        out.size = size as u16;
+        out
+    }
+}
+
+impl TryFrom<&lgwc_tx_packet> for TxPacket {
+    type Error = Error;
+
+    fn try_from(pkt: &lgwc_tx_packet) -> Result<Self, Error> {
+        let size = pkt.size as usize;
+        if size > LGWC_MAX_PAYLOAD {
+            return Err(Error::Size);
+        }
+        let mode = match pkt.tx_mode {
+            LGWC_TX_IMMEDIATE => TxMode::Immediate,
+            LGWC_TX_TIMESTAMPED => TxMode::Timestamped {
+                count_us: pkt.count_us,
+            },
+            LGWC_TX_ON_GPS => TxMode::OnGps,
+            _ => return Err(Error::Data),
+        };
+        Ok(TxPacket::LoRa(TxPacketLoRa {
+            freq: pkt.freq_hz,
+            mode,
+            radio: FrontRadio::try_from(u32::from(pkt.radio))?,
+            power: Dbm(pkt.power),
+            bandwidth: Bandwidth::try_from(u32::from(pkt.bandwidth))?,
+            spreading: Spreading::try_from(u32::from(pkt.spreading))?,
+            coderate: Coderate::try_from(u32::from(pkt.coderate))?,
+            invert_polarity: pkt.invert_pol,
+            preamble: (pkt.preamble != 0).then_some(pkt.preamble),
+            omit_crc: pkt.no_crc,
+            implicit_header: pkt.no_header,
+            payload: pkt.payload[..size].to_vec(),
+        }))
+    }
+}
+
+/// Runs `f`, turning its error or a panic into an error code.
+fn guard(f: impl FnOnce() -> Result<(), c_int>) -> c_int {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => LGWC_OK,
+        Ok(Err(code)) => code,
+        Err(_) => LGWC_ERR_PANIC,
+    }
+}
+
+/// Runs `f` on the handle's state, which it takes ownership of and must give back.
+///
+/// # Safety
+/// `handle` must be NULL or a pointer returned by `lgwc_open`.
+unsafe fn with_state(
+    handle: *mut lgwc_handle,
+    f: impl FnOnce(State) -> (State, Result<(), c_int>),
+) -> c_int {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return LGWC_ERR_NULL;
+    };
+    guard(|| {
+        let state = std::mem::replace(&mut handle.state, State::Closed);
+        let (state, res) = f(state);
+        handle.state = state;
+        res
+    })
+}
+
+/// # Safety
+/// `out` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lgwc_open(reset: bool, out: *mut *mut lgwc_handle) -> c_int {
+    if out.is_null() {
+        return LGWC_ERR_NULL;
+    }
+    guard(|| {
+        let token = if reset {
+            ResetToken::generate(raspberrypi::reset_lgw).map_err(|_| LGWC_ERR_RESET)?
+        } else {
+            // SAFE: The caller promises the concentrator was reset
+            unsafe { ResetToken::bypass() }
+        };
+        let conc = Concentrator::open(&token).map_err(|e| code(&e))?;
+        let handle = Box::new(lgwc_handle {
+            state: State::Builder(conc),
+        });
+        unsafe { *out = Box::into_raw(handle) };
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `handle` must come from `lgwc_open`, and `config` must be NULL or a NUL terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lgwc_configure(handle: *mut lgwc_handle, config: *const c_char) -> c_int {
+    let config = if config.is_null() {
+        None
+    } else {
+        match unsafe { CStr::from_ptr(config) }.to_str() {
+            Ok(config) => Some(config),
+            Err(_) => return LGWC_ERR_CONFIG,
+        }
+    };
+    unsafe {
+        with_state(handle, |state| match state {
+            State::Builder(conc) => {
+                let conf = match Config::from_str_or_default(config) {
+                    Ok(conf) => conf,
+                    Err(e) => return (State::Builder(conc), Err(code(&e))),
+                };
+                // The builder is consumed on error, so the handle can only be closed after
+                match conc.set_config(&conf) {
+                    Ok(conc) => (State::Builder(conc), Ok(())),
+                    Err(e) => (State::Closed, Err(code(&e))),
+                }
+            }
+            state => (state, Err(LGWC_ERR_STATE)),
+        })
+    }
+}
+
+/// # Safety
+/// `handle` must come from `lgwc_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lgwc_start(handle: *mut lgwc_handle) -> c_int {
+    unsafe {
+        with_state(handle, |state| match state {
+            State::Builder(conc) => match conc.connect().and_then(|conc| conc.start()) {
+                Ok(conc) => (State::Running(conc), Ok(())),
+                Err(e) => (State::Closed, Err(code(&e))),
+            },
+            state => (state, Err(LGWC_ERR_STATE)),
+        })
+    }
+}
+
+/// # Safety
+/// `handle` must come from `lgwc_open`, `pkts` must be valid for `max` writes and `count` for
+/// one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lgwc_receive(
+    handle: *mut lgwc_handle,
+    pkts: *mut lgwc_rx_packet,
+    max: usize,
+    count: *mut usize,
+) -> c_int {
+    if pkts.is_null() || count.is_null() {
+        return LGWC_ERR_NULL;
+    }
+    if max < RX_FIFO_SIZE {
+        return LGWC_ERR_SIZE;
+    }
+    unsafe {
+        with_state(handle, |state| {
+            let State::Running(conc) = &state else {
+                return (state, Err(LGWC_ERR_STATE));
+            };
+            let mut received = Vec::with_capacity(RX_FIFO_SIZE);
+            let res = conc.receive_into(&mut received).map_err(|e| code(&e));
+            for (i, pkt) in received.iter().enumerate() {
+                pkts.add(i).write(pkt.into());
+            }
+            *count = received.len();
+            (state, res.map(|_| ()))
+        })
+    }
+}
+
+/// # Safety
+/// `handle` must come from `lgwc_open`, and `pkt` must point to a valid packet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lgwc_transmit(
+    handle: *mut lgwc_handle,
+    pkt: *const lgwc_tx_packet,
+) -> c_int {
+    let Some(pkt) = (unsafe { pkt.as_ref() }) else {
+        return LGWC_ERR_NULL;
+    };
+    unsafe {
+        with_state(handle, |state| {
+            let State::Running(conc) = &state else {
+                return (state, Err(LGWC_ERR_STATE));
+            };
+            let res = TxPacket::try_from(pkt)
+                .and_then(|pkt| conc.transmit(pkt))
+                .map_err(|e| code(&e));
+            (state, res)
+        })
+    }
+}
+
+/// # Safety
+/// `handle` must come from `lgwc_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lgwc_stop(handle: *mut lgwc_handle) -> c_int {
+    unsafe {
+        with_state(handle, |state| match state {
+            State::Running(conc) => (State::Closed, conc.stop().map(|_| ()).map_err(|e| code(&e))),
+            state => (state, Err(LGWC_ERR_STATE)),
+        })
+    }
+}
+
+/// # Safety
+/// `handle` must be NULL or come from `lgwc_open`, and is invalid afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lgwc_close(handle: *mut lgwc_handle) {
+    if !handle.is_null() {
+        // Dropping the concentrator stops it and releases it for the next open
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(handle) })));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn lgwc_strerror(code: c_int) -> *const c_char {
+    let msg: &'static CStr = match code {
+        LGWC_OK => c"success",
+        LGWC_ERR_NULL => c"null pointer argument",
+        LGWC_ERR_STATE => c"call not valid in the handle's current state",
+        LGWC_ERR_HAL => c"concentrator HAL returned a generic error",
+        LGWC_ERR_HAL_INVALID => c"concentrator HAL returned an invalid value",
+        LGWC_ERR_BUSY => c"concentrator device is already in use",
+        LGWC_ERR_SIZE => c"buffer or payload has the wrong size",
+        LGWC_ERR_DATA => c"invalid value in packet",
+        LGWC_ERR_CONFIG => c"invalid configuration",
+        LGWC_ERR_RESET => c"failed to reset concentrator",
+        LGWC_ERR_PANIC => c"internal error",
+        _ => c"unknown error code",
+    };
+    msg.as_ptr()
+}
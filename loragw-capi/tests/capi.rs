@@ -0,0 +1,68 @@
+//! Drives the C API through its whole lifecycle against the mock HAL.
+use loragw::mock;
+use loragw_capi::*;
+use std::ptr;
+
+fn tx_packet(payload: &[u8]) -> lgwc_tx_packet {
+    let mut pkt: lgwc_tx_packet = unsafe { std::mem::zeroed() };
+    pkt.freq_hz = 868_100_000;
+    pkt.tx_mode = LGWC_TX_IMMEDIATE;
+    pkt.power = 14;
+    pkt.bandwidth = 4;
+    pkt.spreading = 7;
+    pkt.coderate = 1;
+    pkt.size = payload.len() as u16;
+    pkt.payload[..payload.len()].copy_from_slice(payload);
+    pkt
+}
+
+#[test]
+fn lifecycle() {
+    mock::reset();
+    let mut handle = ptr::null_mut();
+    unsafe {
+        assert_eq!(lgwc_open(false, &mut handle), LGWC_OK);
+        // Can't transmit before starting
+        assert_eq!(lgwc_transmit(handle, &tx_packet(&[0x01])), LGWC_ERR_STATE);
+        assert_eq!(lgwc_configure(handle, ptr::null()), LGWC_OK);
+        assert_eq!(lgwc_start(handle), LGWC_OK);
+
+        mock::push_rx(mock::lora_rx_packet(&[0xAA, 0xBB]));
+        let mut pkts: Vec<lgwc_rx_packet> = (0..16).map(|_| std::mem::zeroed()).collect();
+        let mut count = 0;
+        assert_eq!(
+            lgwc_receive(handle, pkts.as_mut_ptr(), pkts.len(), &mut count),
+            LGWC_OK
+        );
+        assert_eq!(count, 1);
+        assert_eq!(pkts[0].modulation, LGWC_MOD_LORA);
+        assert_eq!(&pkts[0].payload[..pkts[0].size as usize], &[0xAA, 0xBB]);
+        assert_eq!(
+            lgwc_receive(handle, pkts.as_mut_ptr(), 1, &mut count),
+            LGWC_ERR_SIZE
+        );
+
+        assert_eq!(lgwc_transmit(handle, &tx_packet(&[0x01])), LGWC_OK);
+        let sent = mock::take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].payload[0], 0x01);
+
+        let mut invalid = tx_packet(&[0x01]);
+        invalid.spreading = 42;
+        assert_eq!(lgwc_transmit(handle, &invalid), LGWC_ERR_DATA);
+
+        assert_eq!(lgwc_stop(handle), LGWC_OK);
+        assert_eq!(lgwc_stop(handle), LGWC_ERR_STATE);
+        lgwc_close(handle);
+    }
+}
+
+#[test]
+fn null_arguments_are_rejected() {
+    unsafe {
+        assert_eq!(lgwc_open(false, ptr::null_mut()), LGWC_ERR_NULL);
+        assert_eq!(lgwc_start(ptr::null_mut()), LGWC_ERR_NULL);
+        lgwc_close(ptr::null_mut());
+    }
+    assert!(!lgwc_strerror(LGWC_ERR_HAL).is_null());
+}
@@ -0,0 +1,164 @@
+//! LoRaWAN Class B beacon formatting, following the beacon frame layout of the LoRaWAN
+//! regional parameters.
+use crate::{
+    error::{Error, Result},
+    types::{Bandwidth, Coderate, FrontRadio, Spreading, TxMode, TxPacketLoRa},
+};
+use must_units::Dbm;
+
+/// Beacons are sent every 128 seconds, aligned to GPS time.
+pub const BEACON_PERIOD_SECS: u32 = 128;
+
+/// Preamble length of a beacon, in symbols.
+const BEACON_PREAMBLE: u16 = 10;
+
+/// Parameters of a LoRaWAN beacon.
+#[derive(Debug, Clone)]
+pub struct BeaconParams {
+    /// Center frequency to transmit on.
+    pub freq: u32,
+    /// Which radio to transmit on.
+    pub radio: FrontRadio,
+    /// TX power.
+    pub power: Dbm<i8>,
+    /// Spreading factor of the region's beacon, one of SF8, SF9, SF10 or SF12.
+    pub spreading: Spreading,
+    /// Modulation bandwidth of the region's beacon.
+    pub bandwidth: Bandwidth,
+    /// GPS time of the beacon, in seconds. Must be a multiple of `BEACON_PERIOD_SECS`.
+    pub gps_time: u32,
+    /// Describes what the coordinates are the position of, 0 is the gateway's first antenna.
+    pub info_desc: u8,
+    /// Latitude of the gateway, in degrees.
+    pub latitude: f64,
+    /// Longitude of the gateway, in degrees.
+    pub longitude: f64,
+}
+
+impl BeaconParams {
+    /// Sizes of the two RFU fields, which depend on the beacon's datarate.
+    fn rfu_sizes(&self) -> Result<(usize, usize)> {
+        match self.spreading {
+            Spreading::SF8 => Ok((1, 3)),
+            Spreading::SF9 => Ok((2, 0)),
+            Spreading::SF10 => Ok((3, 1)),
+            Spreading::SF12 => Ok((5, 3)),
+            _ => Err(Error::Data),
+        }
+    }
+
+    /// Formats the beacon frame.
+    pub fn payload(&self) -> Result<Vec<u8>> {
+        if !self.gps_time.is_multiple_of(BEACON_PERIOD_SECS) {
+            return Err(Error::Data);
+        }
+        let (rfu1, rfu2) = self.rfu_sizes()?;
+
+        // Network common part
+        let mut payload = vec![0u8; rfu1];
+        payload.extend_from_slice(&self.gps_time.to_le_bytes());
+        let crc = crc16(&payload);
+        payload.extend_from_slice(&crc.to_le_bytes());
+
+        // Gateway specific part
+        let gw_start = payload.len();
+        payload.push(self.info_desc);
+        payload.extend_from_slice(&coordinate(self.latitude, 90.0));
+        payload.extend_from_slice(&coordinate(self.longitude, 180.0));
+        payload.resize(payload.len() + rfu2, 0);
+        let crc = crc16(&payload[gw_start..]);
+        payload.extend_from_slice(&crc.to_le_bytes());
+        Ok(payload)
+    }
+
+    /// Builds the packet to transmit on the PPS edge starting the beacon's GPS second.
+    pub fn tx_packet(&self) -> Result<TxPacketLoRa> {
+        Ok(TxPacketLoRa {
+            freq: self.freq,
+            mode: TxMode::OnGps,
+            radio: self.radio,
+            power: self.power,
+            bandwidth: self.bandwidth,
+            spreading: self.spreading,
+            coderate: Coderate::Cr4_5,
+            invert_polarity: false,
+            preamble: Some(BEACON_PREAMBLE),
+            omit_crc: true,
+            implicit_header: true,
+            payload: self.payload()?,
+        })
+    }
+}
+
+/// Encodes a coordinate as a 24 bit little endian fraction of `range`.
+fn coordinate(degrees: f64, range: f64) -> [u8; 3] {
+    let value = ((degrees / range) * f64::from(1 << 23)) as i32;
+    let value = value.clamp(-(1 << 23), (1 << 23) - 1);
+    let bytes = value.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+/// CRC-16/CCITT with polynomial 0x1021 and no initial value, as used by the beacon.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eu868() -> BeaconParams {
+        BeaconParams {
+            freq: 869_525_000,
+            radio: FrontRadio::R0,
+            power: Dbm(14),
+            spreading: Spreading::SF9,
+            bandwidth: Bandwidth::BW125kHz,
+            gps_time: 1_280_000_000,
+            info_desc: 0,
+            latitude: 55.6761,
+            longitude: 12.5683,
+        }
+    }
+
+    #[test]
+    fn crc_matches_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn eu868_beacon_layout() {
+        let payload = eu868().payload().unwrap();
+        assert_eq!(payload.len(), 17);
+        assert_eq!(&payload[..2], &[0, 0]);
+        assert_eq!(&payload[2..6], &1_280_000_000u32.to_le_bytes());
+        assert_eq!(&payload[6..8], &crc16(&payload[..6]).to_le_bytes());
+        assert_eq!(&payload[15..], &crc16(&payload[8..15]).to_le_bytes());
+    }
+
+    #[test]
+    fn coordinates_are_clamped() {
+        assert_eq!(coordinate(90.0, 90.0), [0xFF, 0xFF, 0x7F]);
+        assert_eq!(coordinate(-90.0, 90.0), [0x00, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn unaligned_time_is_rejected() {
+        let params = BeaconParams {
+            gps_time: 1_280_000_001,
+            ..eu868()
+        };
+        assert!(params.payload().is_err());
+    }
+}
@@ -1,7 +1,11 @@
 use super::{
     error::{AppError, Error},
-    types::{BoardConf, ChannelConf, ComType, FrontRadio, RadioType, RxRFConf, TxGain},
+    types::{
+        Bandwidth, BoardConf, ChannelConf, ComType, FSK_DATARATES, FrontRadio, RadioType, RxRFConf,
+        Spreading, TxGain,
+    },
 };
+use must_units::{Db, Dbm};
 use serde::{Deserialize, Serialize};
 use std::{convert::TryFrom, ffi::CString};
 use toml;
@@ -14,7 +18,13 @@ pub struct Config {
     pub board: Board,
     pub radios: Option<Vec<Radio>>,
     pub multirate_channels: Option<Vec<MultirateLoraChannel>>,
+    /// The LoRa service channel, e.g. 250 kHz SF7 in EU868
+    pub lora_std_channel: Option<LoraStdChannel>,
+    /// The FSK channel, e.g. 50 kbps in EU868
+    pub fsk_channel: Option<FskChannel>,
     pub tx_gains: Option<Vec<ConfTxGain>>,
+    /// Merge what both radios receive, see `Diversity`
+    pub antenna_diversity: Option<bool>,
 }
 
 impl Config {
@@ -31,6 +41,8 @@ impl Config {
 pub struct Board {
     pub lorawan_public: bool,
     pub clksrc: u32,
+    /// Also read as `com_path`, the name the HAL's own configuration files use
+    #[serde(alias = "com_path")]
     pub spidev_path: CString,
     pub com_type: ComType,
 }
@@ -52,7 +64,7 @@ pub struct Radio {
     // TODO: Make id an enum? { Radio1, Radio2 } to avoid trys
     pub id: u32,
     pub freq: u32,
-    pub rssi_offset: f32,
+    pub rssi_offset: Db<f32>,
     #[serde(rename(serialize = "type", deserialize = "type"))]
     pub type_: String,
     pub tx_enable: bool,
@@ -90,10 +102,91 @@ impl TryFrom<&MultirateLoraChannel> for ChannelConf {
     }
 }
 
+/// A channel of a single bandwidth and spreading factor
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LoraStdChannel {
+    radio: u32,
+    #[serde(rename(serialize = "if", deserialize = "if"))]
+    if_: i32,
+    /// In Hz
+    bandwidth: u32,
+    spread_factor: u32,
+}
+
+impl TryFrom<&LoraStdChannel> for ChannelConf {
+    type Error = AppError;
+    fn try_from(other: &LoraStdChannel) -> Result<ChannelConf, Self::Error> {
+        let spreading = match Spreading::try_from(other.spread_factor)? {
+            Spreading::Undefined | Spreading::Multi => {
+                return Err(AppError::Generic(format!(
+                    "invalid spread factor {}",
+                    other.spread_factor
+                )));
+            }
+            spreading => spreading,
+        };
+        Ok(ChannelConf::Fixed {
+            radio: FrontRadio::try_from(other.radio)?,
+            freq: other.if_,
+            bandwidth: bandwidth(other.bandwidth)?,
+            spreading,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FskChannel {
+    radio: u32,
+    #[serde(rename(serialize = "if", deserialize = "if"))]
+    if_: i32,
+    /// In Hz
+    bandwidth: u32,
+    /// In bits/second
+    datarate: u32,
+    /// Bytes of `sync_word`, 0 for the HAL's default
+    #[serde(default)]
+    sync_word_size: u8,
+    #[serde(default)]
+    sync_word: u64,
+}
+
+impl TryFrom<&FskChannel> for ChannelConf {
+    type Error = AppError;
+    fn try_from(other: &FskChannel) -> Result<ChannelConf, Self::Error> {
+        if !FSK_DATARATES.contains(&other.datarate) {
+            return Err(AppError::Generic(format!(
+                "invalid FSK datarate {}",
+                other.datarate
+            )));
+        }
+        Ok(ChannelConf::FSK {
+            radio: FrontRadio::try_from(other.radio)?,
+            freq: other.if_,
+            bandwidth: bandwidth(other.bandwidth)?,
+            datarate: other.datarate,
+            sync_word_size: other.sync_word_size,
+            sync_word: other.sync_word,
+        })
+    }
+}
+
+/// The bandwidth of `hz`
+fn bandwidth(hz: u32) -> Result<Bandwidth, AppError> {
+    match hz {
+        125_000 => Ok(Bandwidth::BW125kHz),
+        250_000 => Ok(Bandwidth::BW250kHz),
+        500_000 => Ok(Bandwidth::BW500kHz),
+        invalid => Err(AppError::Generic(format!(
+            "invalid bandwidth {} Hz",
+            invalid
+        ))),
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ConfTxGain {
     #[serde(rename(serialize = "dbm", deserialize = "dbm"))]
-    pub rf_power: i8,
+    pub rf_power: Dbm<i8>,
     #[serde(rename(serialize = "dig", deserialize = "dig"))]
     pub dig_gain: u8,
     #[serde(rename(serialize = "pa", deserialize = "pa"))]
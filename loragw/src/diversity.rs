@@ -0,0 +1,123 @@
+//! Antenna diversity: both RF chains tuned to the same frequency, each on its own antenna, with
+//! the same channels on both.
+//!
+//! A packet is then demodulated once per chain, and `Concentrator::receive` keeps the copy with
+//! the best RSSI. Its `radio` tells which antenna won, and `other_rssi` how the other one heard
+//! it.
+use crate::{
+    error::{BuilderError, Error, Result},
+    types::{ChannelConf, FrontRadio, RxPacket, RxRFConf},
+};
+use std::time::Duration;
+
+/// Multirate IF chains of the SX1302, split between the two antennas
+const MULTIRATE_CHAINS: usize = 8;
+
+/// How to merge the copies the two chains demodulated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diversity {
+    /// Most the timestamps of two copies of a packet may differ. Both chains demodulate the
+    /// same frame, so their copies are only microseconds apart.
+    pub window: Duration,
+}
+
+impl Default for Diversity {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_micros(50),
+        }
+    }
+}
+
+impl Diversity {
+    /// Multirate channels at `offsets` from the shared center frequency on both radios, IF
+    /// chains `0..n` on R0 and `n..2n` on R1. At most 4 channels, as each takes two chains.
+    pub fn channels(offsets: &[i32]) -> Result<Vec<(u8, ChannelConf)>> {
+        if offsets.len() * 2 > MULTIRATE_CHAINS {
+            log::error!(
+                "{} channels on both antennas need more than {} IF chains",
+                offsets.len(),
+                MULTIRATE_CHAINS
+            );
+            return Err(Error::BuilderError(BuilderError::InvalidChannelConf));
+        }
+        let on = |radio| {
+            offsets
+                .iter()
+                .map(move |&freq| ChannelConf::Multirate { radio, freq })
+        };
+        Ok(on(FrontRadio::R0)
+            .chain(on(FrontRadio::R1))
+            .enumerate()
+            .map(|(chain, conf)| (chain as u8, conf))
+            .collect())
+    }
+
+    /// Both radios have to be enabled and tuned to the same frequency.
+    pub(crate) fn check(rx_rfs: &[RxRFConf]) -> Result {
+        match rx_rfs {
+            [a, b] if a.enable && b.enable && a.freq == b.freq => Ok(()),
+            _ => {
+                log::error!("antenna diversity needs both radios enabled on the same frequency");
+                Err(Error::BuilderError(BuilderError::InvalidRxRFConf))
+            }
+        }
+    }
+
+    /// Drops every packet in `packets[from..]` the other chain received as well, i.e. on the
+    /// same channel with the same payload within `window`, keeping the copy with the best RSSI.
+    pub(crate) fn merge(&self, packets: &mut Vec<RxPacket>, from: usize) {
+        let window = self.window.as_micros() as u32;
+        let mut i = from;
+        while i < packets.len() {
+            let copy = (from..i).find(|&j| {
+                let (a, b) = (&packets[j], &packets[i]);
+                a.radio() as u8 != b.radio() as u8
+                    && a.freq() == b.freq()
+                    && a.payload() == b.payload()
+                    && apart_us(a, b) <= window
+            });
+            let Some(j) = copy else {
+                i += 1;
+                continue;
+            };
+            let copy = packets.remove(i);
+            let kept = &mut packets[j];
+            if copy.rssi() > kept.rssi() {
+                let beaten = std::mem::replace(kept, copy);
+                kept.set_other_rssi(beaten.rssi());
+            } else {
+                kept.set_other_rssi(copy.rssi());
+            }
+        }
+    }
+}
+
+/// Between the counter values of `a` and `b`, either way around the wrap
+fn apart_us(a: &RxPacket, b: &RxPacket) -> u32 {
+    let a = a.timestamp().as_micros() as u32;
+    let b = b.timestamp().as_micros() as u32;
+    a.wrapping_sub(b).min(b.wrapping_sub(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels_on_both_radios() {
+        let channels = Diversity::channels(&[-200_000, 0, 200_000]).unwrap();
+        assert_eq!(channels.len(), 6);
+        assert!(matches!(
+            channels[4],
+            (
+                4,
+                ChannelConf::Multirate {
+                    radio: FrontRadio::R1,
+                    freq: 0
+                }
+            )
+        ));
+        assert!(Diversity::channels(&[0; 5]).is_err());
+    }
+}
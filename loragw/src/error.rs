@@ -8,10 +8,14 @@ pub enum BuilderError {
     MissingBoard,
     NotConnected,
     InvalidBoard,
+    /// The device path doesn't fit the `ComType`, or is too long.
+    InvalidComPath,
     InvalidRxRFConf,
     InvalidTxGain,
     InvalidChain,
     InvalidChannelConf,
+    /// A spectral scan needs `set_config_sx1261`.
+    MissingSx1261,
 }
 
 /// A common error type for this crate.
@@ -21,11 +25,22 @@ pub enum Error {
     Busy,
     /// Catch-all error returned by the low-level `libloragw` c code.
     HAL,
+    /// The HAL returned a value outside of its documented range, e.g. after an SPI glitch.
+    HalInvalid(i32),
     /// A buffer, primarily transmit payloads, is too large for the LoRa packet format.
     Size,
     /// Represents an error when attempting to convert between this crate's high-level types
     /// and those defined in `libloragw`.
     Data,
+    /// The reset routine of a `RecoveryPolicy` failed.
+    ResetFailed,
+    /// The concentrator didn't get done in time, e.g. emitting a packet.
+    Timeout,
+    /// The counter is already past the `TxMode::Timestamped` count of a packet, or too close to
+    /// it to load the packet.
+    TooLate,
+    /// The TX modem was stopped before the packet was emitted.
+    TxAborted,
     BuilderError(BuilderError),
     Toml(toml::de::Error),
 }
@@ -42,16 +57,23 @@ impl fmt::Display for Error {
         match self {
             Error::Busy => write!(f, "concentrator device is already in use"),
             Error::HAL => write!(f, "concentrator HAL returned a generic error"),
+            Error::HalInvalid(val) => write!(f, "concentrator HAL returned invalid value {val}"),
             Error::Size => write!(f, "provided buffer is too large"),
             Error::Data => write!(f, "failure to convert hardware val to symbolic val"),
+            Error::ResetFailed => write!(f, "failed to reset concentrator during recovery"),
+            Error::Timeout => write!(f, "timed out waiting for the concentrator"),
+            Error::TooLate => write!(f, "too late to transmit at the requested count"),
+            Error::TxAborted => write!(f, "TX modem stopped before the packet was emitted"),
             Error::BuilderError(err) => match err {
                 BuilderError::InvalidBoard => write!(f, "builder error: invalid board"),
+                BuilderError::InvalidComPath => write!(f, "builder error: invalid device path"),
                 BuilderError::InvalidChain => write!(f, "builder error: invalid frequency"),
                 BuilderError::InvalidChannelConf => write!(f, "builder error: invalid channel"),
                 BuilderError::InvalidRxRFConf => write!(f, "builder error: invalid data rate"),
                 BuilderError::InvalidTxGain => write!(f, "builder error: invalid tx gain"),
                 BuilderError::MissingBoard => write!(f, "builder error: missing board"),
                 BuilderError::NotConnected => write!(f, "builder error: not connected"),
+                BuilderError::MissingSx1261 => write!(f, "builder error: missing SX1261"),
             },
             Error::Toml(_err) => write!(f, "Error from toml"),
         }
@@ -87,6 +109,9 @@ impl From<AppError> for Error {
 /// Wraps a `libloragw-sys` function call and:
 /// - wraps the return code in a `Result`
 /// - logs name of FFI function on error
+///
+/// Return values the HAL should never produce are reported as `Error::HalInvalid` instead of
+/// panicking, so a long-running gateway can recover from them.
 #[macro_export] // Optional: exposes the macro if you need it outside this module
 macro_rules! hal_call {
     ( $fn:ident ( $($arg:expr),* ) ) => {
@@ -96,7 +121,10 @@ macro_rules! hal_call {
                 Err($crate::error::Error::HAL)
             }
             val if val >= 0 => Ok(val as usize),
-            invalid => panic!("HAL call {} returned invalid value {}", stringify!($fn), invalid),
+            invalid => {
+                eprintln!("HAL call {} returned invalid value {}", stringify!($fn), invalid);
+                Err($crate::error::Error::HalInvalid(invalid))
+            }
         }
     }
 }
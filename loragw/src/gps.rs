@@ -0,0 +1,272 @@
+//! A uBlox GPS receiver on a serial port, read through the HAL's `loragw_gps`.
+//!
+//! The HAL configures the receiver to send UBX NAV-TIMEGPS messages next to its NMEA sentences.
+//! `Gps::read` parses what the port has into `GpsEvent`s: the time of the last PPS edge from
+//! NAV-TIMEGPS, and the position from RMC and GGA sentences. Given to `Gps::sync` with the
+//! concentrator latching its counter on the same edge, the time ties the counter to UTC and GPS
+//! time.
+use crate::{Concentrator, Error, Result, Running, llg};
+use std::{
+    cell::Cell,
+    ffi::CString,
+    fs::File,
+    io::Read,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    os::{fd::FromRawFd, raw::c_int},
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// UBX messages start with these, NMEA sentences with `$`
+const UBX_SYNC: u8 = 0xB5;
+const NMEA_START: u8 = b'$';
+/// Bytes kept of a message not complete yet. The longest UBX message the HAL parses is far
+/// shorter, so more is a stream out of sync.
+const MAX_PENDING: usize = 1024;
+
+// The HAL keeps the receiver's state in globals, so only one may be enabled at a time
+static GPS_IS_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Time of the last PPS edge, as the receiver tells it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpsTime {
+    pub utc: SystemTime,
+    /// Since the GPS epoch, 1980-01-06, without leap seconds
+    pub gps: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    /// In degrees, north positive
+    pub latitude: f64,
+    /// In degrees, east positive
+    pub longitude: f64,
+    /// Above mean sea level, in meters
+    pub altitude: i16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GpsEvent {
+    /// A NAV-TIMEGPS message, to `sync` the concentrator with
+    Time(GpsTime),
+    /// A fix, with its accuracy in the same units
+    Position { position: Position, error: Position },
+}
+
+/// The concentrator's counter at a PPS edge, and the time of that edge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeReference {
+    /// Concentrator counter latched on the edge, in µs
+    pub count_us: u32,
+    pub utc: SystemTime,
+    /// Since the GPS epoch
+    pub gps: Duration,
+    /// How fast the concentrator's crystal runs against GPS time, 1.0 when exact
+    pub xtal_err: f64,
+}
+
+/// A GPS receiver enabled with `Gps::enable`, disabled again on drop
+pub struct Gps {
+    /// The HAL's GPS state is not thread-safe, like the concentrator's
+    _prevent_sync: PhantomData<Cell<()>>,
+    fd: c_int,
+    /// Bytes read but not parsed yet, a message may span reads
+    pending: Vec<u8>,
+    /// Set by a successful `sync`
+    reference: Option<llg::tref>,
+}
+
+impl Gps {
+    /// Opens the receiver on `tty_path`, e.g. `/dev/ttyAMA0` on a RAK2287 Pi HAT, and sets it
+    /// up to send NAV-TIMEGPS.
+    pub fn enable(tty_path: &str) -> Result<Self> {
+        let path = CString::new(tty_path).map_err(|_| Error::Data)?;
+        if GPS_IS_OPEN
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            log::error!("GPS busy");
+            return Err(Error::Busy);
+        }
+        let mut fd: c_int = -1;
+        // The HAL takes the family and baud rate, but only supports uBlox at its default rate
+        let res = unsafe {
+            hal_call!(lgw_gps_enable(
+                path.as_ptr() as *mut _,
+                ptr::null_mut(),
+                0,
+                &mut fd
+            ))
+        };
+        if let Err(err) = res {
+            GPS_IS_OPEN.store(false, Ordering::SeqCst);
+            return Err(err);
+        }
+        log::info!("GPS enabled on {tty_path}");
+        Ok(Gps {
+            _prevent_sync: PhantomData,
+            fd,
+            pending: Vec::new(),
+            reference: None,
+        })
+    }
+
+    /// Reads what the receiver sent, blocking until it sent something, and parses every whole
+    /// message in it. The receiver sends a burst every second.
+    pub fn read(&mut self) -> Result<Vec<GpsEvent>> {
+        // Borrowed from the HAL, which closes it in `lgw_gps_disable`
+        let mut port = ManuallyDrop::new(unsafe { File::from_raw_fd(self.fd) });
+        let mut buf = [0u8; 256];
+        let len = port.read(&mut buf).map_err(|err| {
+            log::error!("reading GPS failed: {err}");
+            Error::HAL
+        })?;
+        Ok(self.parse(&buf[..len]))
+    }
+
+    /// Parses `bytes` from the receiver, e.g. read off the port elsewhere. Incomplete messages
+    /// are kept until the next call completes them.
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<GpsEvent> {
+        self.pending.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        let mut start = 0;
+        while start < self.pending.len() {
+            let rest = &self.pending[start..];
+            let (msg, size) = match rest[0] {
+                UBX_SYNC => {
+                    let mut size = 0usize;
+                    let msg = unsafe {
+                        llg::lgw_parse_ubx(rest.as_ptr() as *const _, rest.len(), &mut size)
+                    };
+                    (msg, size)
+                }
+                NMEA_START => match rest.iter().position(|b| *b == b'\n') {
+                    Some(end) => {
+                        // The HAL reads sentences as C strings
+                        let mut sentence = rest[..end].to_vec();
+                        sentence.push(0);
+                        let msg = unsafe {
+                            llg::lgw_parse_nmea(sentence.as_ptr() as *const _, end as c_int)
+                        };
+                        (msg, end + 1)
+                    }
+                    None => (llg::gps_msg_INCOMPLETE, 0),
+                },
+                _ => (llg::gps_msg_IGNORED, 1),
+            };
+            if msg == llg::gps_msg_INCOMPLETE {
+                break;
+            }
+            // Past a broken message by at least a byte, to find the next start
+            start += size.max(1);
+            if let Some(event) = event_after(msg) {
+                events.push(event);
+            }
+        }
+        self.pending.drain(..start);
+        if self.pending.len() > MAX_PENDING {
+            log::warn!("dropping {} unparsed GPS bytes", self.pending.len());
+            self.pending.clear();
+        }
+        events
+    }
+
+    /// Ties the counter the concentrator latched on the last PPS edge to `time`, which has to
+    /// be the time of that edge, i.e. from the NAV-TIMEGPS read since. Fails while the last few
+    /// syncs disagree on the crystal's error, e.g. the first ones after enabling, so keep
+    /// syncing on every `GpsEvent::Time`.
+    pub fn sync(
+        &mut self,
+        concentrator: &Concentrator<Running>,
+        time: GpsTime,
+    ) -> Result<TimeReference> {
+        let count_us = concentrator.trigcnt()?;
+        let mut reference = self.reference.unwrap_or(unsafe { std::mem::zeroed() });
+        unsafe {
+            hal_call!(lgw_gps_sync(
+                &mut reference,
+                count_us,
+                timespec_of(time.utc.duration_since(UNIX_EPOCH).unwrap_or_default()),
+                timespec_of(time.gps)
+            ))
+        }?;
+        self.reference = Some(reference);
+        Ok(TimeReference {
+            count_us: reference.count_us,
+            utc: UNIX_EPOCH + duration_of(reference.utc),
+            gps: duration_of(reference.gps),
+            xtal_err: reference.xtal_err,
+        })
+    }
+
+    /// UTC of the concentrator counter value `count_us`, e.g. a packet's timestamp, once synced
+    pub fn utc_of(&self, count_us: u32) -> Option<SystemTime> {
+        let reference = self.reference?;
+        let mut utc: llg::timespec = unsafe { std::mem::zeroed() };
+        unsafe { hal_call!(lgw_cnt2utc(reference, count_us, &mut utc)) }.ok()?;
+        Some(UNIX_EPOCH + duration_of(utc))
+    }
+}
+
+impl Drop for Gps {
+    fn drop(&mut self) {
+        log::info!("Disabling GPS");
+        unsafe {
+            let _ = hal_call!(lgw_gps_disable(self.fd));
+        }
+        GPS_IS_OPEN.store(false, Ordering::SeqCst);
+    }
+}
+
+/// What the HAL learned from the message just parsed
+fn event_after(msg: llg::gps_msg) -> Option<GpsEvent> {
+    if msg == llg::gps_msg_UBX_NAV_TIMEGPS {
+        let mut utc: llg::timespec = unsafe { std::mem::zeroed() };
+        let mut gps: llg::timespec = unsafe { std::mem::zeroed() };
+        let res = unsafe { llg::lgw_gps_get(&mut utc, &mut gps, ptr::null_mut(), ptr::null_mut()) };
+        if res != 0 {
+            return None;
+        }
+        return Some(GpsEvent::Time(GpsTime {
+            utc: UNIX_EPOCH + duration_of(utc),
+            gps: duration_of(gps),
+        }));
+    }
+    if msg == llg::gps_msg_NMEA_RMC || msg == llg::gps_msg_NMEA_GGA {
+        let mut loc: llg::coord_s = unsafe { std::mem::zeroed() };
+        let mut err: llg::coord_s = unsafe { std::mem::zeroed() };
+        // Fails until the receiver has a fix, called directly so that isn't logged every second
+        let res = unsafe { llg::lgw_gps_get(ptr::null_mut(), ptr::null_mut(), &mut loc, &mut err) };
+        if res != 0 {
+            return None;
+        }
+        return Some(GpsEvent::Position {
+            position: loc.into(),
+            error: err.into(),
+        });
+    }
+    None
+}
+
+impl From<llg::coord_s> for Position {
+    fn from(coord: llg::coord_s) -> Self {
+        Position {
+            latitude: coord.lat,
+            longitude: coord.lon,
+            altitude: coord.alt,
+        }
+    }
+}
+
+fn duration_of(time: llg::timespec) -> Duration {
+    Duration::new(time.tv_sec.max(0) as u64, time.tv_nsec.max(0) as u32)
+}
+
+fn timespec_of(duration: Duration) -> llg::timespec {
+    llg::timespec {
+        tv_sec: duration.as_secs() as _,
+        tv_nsec: duration.subsec_nanos() as _,
+    }
+}
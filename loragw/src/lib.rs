@@ -5,9 +5,24 @@
 
 #[macro_use]
 mod error;
+mod beacon;
+mod diversity;
+mod lut;
+mod poll;
+mod recovery;
+mod scan;
+mod tx_report;
 mod types;
+pub use crate::beacon::*;
+pub use crate::diversity::*;
 pub use crate::error::*;
+pub use crate::lut::*;
+pub use crate::poll::*;
+pub use crate::recovery::*;
+pub use crate::scan::*;
+pub use crate::tx_report::*;
 pub use crate::types::*;
+pub use must_units::{Db, Dbm};
 use std::{
     cell::Cell,
     convert::{TryFrom, TryInto},
@@ -16,9 +31,27 @@ use std::{
 };
 
 pub mod cfg;
+pub mod gps;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod raspberrypi;
-// pub(crate) use libloragw_sys as llg;
-pub(crate) use libloragw_sys as llg;
+pub mod record;
+#[cfg(not(feature = "mock"))]
+pub(crate) use libloragw_sys as hal;
+#[cfg(feature = "mock")]
+pub(crate) use mock as hal;
+// Every HAL call goes through the recorder, which passes it on to `hal`
+pub(crate) use record::shim as llg;
+
+/// Most packets a single `receive` call reads from the concentrator's FIFO.
+pub const RX_FIFO_SIZE: usize = 16;
+
+/// Least time a `TxMode::Timestamped` count has to be ahead of the counter. The HAL starts the
+/// radio 1.5 ms before the count, and loading the packet takes about as long again.
+pub const TX_MIN_LEAD: std::time::Duration = std::time::Duration::from_micros(3_000);
+
+/// How often `transmit_blocking` and `transmit_async` check the TX status.
+pub const TX_POLL: std::time::Duration = std::time::Duration::from_millis(5);
 
 // Ensures we only have 0 or 1 gateway instances opened at a time.
 // This is not a great solution, since another process has its
@@ -38,14 +71,24 @@ impl Drop for GatewayGuard {
 
 pub struct Closed {}
 #[derive(Default)]
-pub struct Builder<'a> {
+pub struct Builder {
     connected: bool,
     board: Option<BoardConf>,
     rx_rf_conf: Vec<RxRFConf>,
-    gains: &'a [TxGain],
+    gains: Vec<TxGain>,
     channel_conf: Vec<(u8, ChannelConf)>,
+    recovery: Option<RecoveryPolicy>,
+    fine_timestamp: Option<FineTimestampMode>,
+    diversity: Option<Diversity>,
+    sx1261: Option<Sx1261Conf>,
+}
+pub struct Running {
+    /// The configuration the concentrator was started with, kept to restart it on recovery.
+    config: Builder,
+    consecutive_errors: Cell<u32>,
+    /// Of `receive_async`
+    poll: Cell<PollInterval>,
 }
-pub struct Running {}
 
 /// A LoRa concentrator.
 pub struct Concentrator<State> {
@@ -72,6 +115,12 @@ impl ResetToken {
         Ok(ResetToken { _priv: () })
     }
 
+    /// For a USB concentrator, whose STM32 resets the SX1302 when the HAL connects to it, so
+    /// there's no reset routine to run.
+    pub fn usb() -> Self {
+        ResetToken { _priv: () }
+    }
+
     /// Unsafe bypass if you are sure the concentrator is reset before use
     pub unsafe fn bypass() -> Self {
         ResetToken { _priv: () }
@@ -79,8 +128,8 @@ impl ResetToken {
 }
 
 impl Concentrator<Closed> {
-    // Open the spidev-connected concentrator.
-    pub fn open<'a>(_token: &ResetToken) -> Result<Concentrator<Builder<'a>>> {
+    // Open the concentrator, connected through SPI or USB as the board configuration says.
+    pub fn open(_token: &ResetToken) -> Result<Concentrator<Builder>> {
         // We expect `false`, and want to swap to `true`.
         // If it fails (is_err), the lock is already held.
         if GW_IS_OPEN
@@ -102,25 +151,143 @@ impl Concentrator<Closed> {
     }
 }
 
-impl<'a> Concentrator<Builder<'a>> {
-    /// Attempt to connect to concentrator.
-    ///
-    /// This function is intended to check if we the concentrator chip
-    /// exists and is the correct version.
-    pub fn connect(mut self) -> Result<Self> {
-        log::info!("self state: {:?}", self.state.board);
+impl Builder {
+    /// Hands the board, RF chain, IF chain and Tx gain configuration to the HAL.
+    fn configure(&self) -> Result {
+        let board = match &self.board {
+            Some(board) => board.clone(),
+            None => return Err(Error::BuilderError(BuilderError::MissingBoard)),
+        };
+        unsafe { hal_call!(lgw_board_setconf(&mut board.into())) }?;
+
+        // rx_rf chain
+        self.rx_rf_conf.iter().try_for_each(|c| unsafe {
+            hal_call!(lgw_rxrf_setconf(c.radio as u8, &mut c.into())).map(|_| ())
+        })?;
+
+        // configure IF chain + modem
+        if let Some((chain, conf)) = self
+            .channel_conf
+            .iter()
+            .find(|(chain, conf)| !conf.fits_chain(*chain))
+        {
+            log::error!("IF chain {} can't receive {:?}", chain, conf);
+            return Err(Error::BuilderError(BuilderError::InvalidChain));
+        }
+        self.channel_conf
+            .iter()
+            .try_for_each(|(chain, chan_conf)| unsafe {
+                hal_call!(lgw_rxif_setconf(*chain, &mut chan_conf.into())).map(|_| ())
+            })?;
+
+        if let Some(mode) = self.fine_timestamp {
+            unsafe { hal_call!(lgw_ftime_setconf(&mut mode.into())) }?;
+        }
+
+        if let Some(sx1261) = &self.sx1261 {
+            unsafe { hal_call!(lgw_sx1261_setconf(&mut sx1261.into())) }?;
+        }
+
+        // conf Tx gain LUT
+        let gains = &self.gains;
+        if gains.is_empty() || gains.len() > 16 {
+            log::error!(
+                "gain table must contain 1 to 16 entries, {} provided",
+                gains.len()
+            );
+            return Err(Error::Size);
+        }
+        let mut lut = TxGainLUT::default();
+        lut.lut[..gains.len()].clone_from_slice(gains);
+        lut.size = gains.len() as u8;
+        unsafe {
+            // TODO: de-hardcode this 0u8 (? from helium)
+            hal_call!(lgw_txgain_setconf(
+                0u8,
+                &mut lut as *mut TxGainLUT as *mut llg::lgw_tx_gain_lut_s
+            ))
+        }?;
+        Ok(())
+    }
+
+    fn connect(&self) -> Result {
         let board_conf = self
-            .state
             .board
             .as_ref()
             .ok_or(Error::BuilderError(BuilderError::MissingBoard))?;
         let com_type = board_conf.com_type.clone();
         let spidev_path = board_conf.spidev_path.clone();
+        com_type.check_path(&spidev_path)?;
         unsafe { hal_call!(lgw_connect(com_type as u32, spidev_path.as_ptr())) }?;
+        Ok(())
+    }
+}
+
+impl Concentrator<Builder> {
+    /// Attempt to connect to concentrator.
+    ///
+    /// This function is intended to check if we the concentrator chip
+    /// exists and is the correct version.
+    pub fn connect(mut self) -> Result<Self> {
+        log::info!("self state: {:?}", self.state.board);
+        self.state.connect()?;
         self.state.connected = true;
         Ok(self)
     }
 
+    /// The concentrator chip's EUI, like `Concentrator<Running>::eui`, readable once connected,
+    /// e.g. to pick the configuration for this gateway before starting.
+    pub fn eui(&self) -> Result<u64> {
+        if !self.state.connected {
+            return Err(Error::BuilderError(BuilderError::NotConnected));
+        }
+        let mut eui = 0u64;
+        unsafe { hal_call!(lgw_get_eui(&mut eui)) }?;
+        Ok(eui)
+    }
+
+    /// Apply a whole configuration document: board, RF chains, channels and Tx gains.
+    pub fn set_config(self, conf: &cfg::Config) -> Result<Self> {
+        let board = BoardConf::try_from(conf.board.clone()).map_err(Error::from)?;
+        let radios = conf
+            .radios
+            .iter()
+            .flatten()
+            .map(|r| RxRFConf::try_from(r.clone()).map_err(Error::from))
+            .collect::<Result<Vec<_>>>()?;
+        let mut channels = conf
+            .multirate_channels
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, c)| Ok((i as u8, ChannelConf::try_from(c).map_err(Error::from)?)))
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(c) = &conf.lora_std_channel {
+            channels.push((
+                LORA_STD_CHAIN,
+                ChannelConf::try_from(c).map_err(Error::from)?,
+            ));
+        }
+        if let Some(c) = &conf.fsk_channel {
+            channels.push((FSK_CHAIN, ChannelConf::try_from(c).map_err(Error::from)?));
+        }
+        let gains: Vec<TxGain> = conf
+            .tx_gains
+            .iter()
+            .flatten()
+            .map(|g| TxGain::from(g.clone()))
+            .collect();
+        let conc = match conf.antenna_diversity {
+            Some(true) => self.set_antenna_diversity(Diversity::default()),
+            _ => self,
+        };
+        Ok(conc
+            .set_config_board(board)
+            .set_rx_rfs(radios)
+            .set_config_channels(channels)
+            .set_config_tx_gains(&gains))
+    }
+
     /// Configure the gateway board.
     pub fn set_config_board(mut self, conf: BoardConf) -> Self {
         log::info!("conf: {:?}", conf);
@@ -152,10 +319,65 @@ impl<'a> Concentrator<Builder<'a>> {
         self
     }
 
+    /// Configure the LoRa service channel on `LORA_STD_CHAIN`, a `ChannelConf::Fixed`,
+    /// replacing an earlier one.
+    pub fn set_config_lora_std(self, conf: ChannelConf) -> Self {
+        self.replace_config_channel(LORA_STD_CHAIN, conf)
+    }
+
+    /// Configure the FSK channel on `FSK_CHAIN`, a `ChannelConf::FSK`, replacing an earlier one.
+    pub fn set_config_fsk(self, conf: ChannelConf) -> Self {
+        self.replace_config_channel(FSK_CHAIN, conf)
+    }
+
+    fn replace_config_channel(mut self, chain: u8, conf: ChannelConf) -> Self {
+        self.state.channel_conf.retain(|(c, _)| *c != chain);
+        self.add_config_channel(chain, conf)
+    }
+
     /// Configure the Tx gain LUT.
-    pub fn set_config_tx_gains(mut self, gains: &'a [TxGain]) -> Self {
+    pub fn set_config_tx_gains(mut self, gains: &[TxGain]) -> Self {
         log::info!("gains: {:?}", gains);
-        self.state.gains = gains;
+        self.state.gains = gains.to_vec();
+        self
+    }
+
+    /// Configure the Tx gain LUT from the wanted output powers, see `TxGainLUT::for_powers`.
+    pub fn set_config_tx_powers(self, powers: &[Dbm<i8>], profile: BoardProfile) -> Result<Self> {
+        let lut = TxGainLUT::for_powers(powers, profile)?;
+        Ok(self.set_config_tx_gains(lut.gains()))
+    }
+
+    /// Configure the SX1261 radio, needed for `spectral_scan`.
+    pub fn set_config_sx1261(mut self, conf: Sx1261Conf) -> Self {
+        log::info!("conf: {:?}", conf);
+        self.state.sx1261 = Some(conf);
+        self
+    }
+
+    /// Restart the concentrator by itself after repeated HAL errors, see `RecoveryPolicy`.
+    pub fn set_recovery_policy(mut self, policy: RecoveryPolicy) -> Self {
+        log::info!(
+            "recovering after {} consecutive errors",
+            policy.max_consecutive_errors
+        );
+        self.state.recovery = Some(policy);
+        self
+    }
+
+    /// Timestamp received LoRa packets in nanoseconds since the last PPS edge, e.g. for TDoA
+    /// geolocation, see `RxPacket::fine_timestamp`. Needs a GPS PPS signal to be of use.
+    pub fn set_fine_timestamp(mut self, mode: FineTimestampMode) -> Self {
+        log::info!("fine timestamps: {:?}", mode);
+        self.state.fine_timestamp = Some(mode);
+        self
+    }
+
+    /// Merge the receptions of both RF chains, which need to be tuned to the same frequency with
+    /// the same channels, e.g. from `Diversity::channels`. See `Diversity`.
+    pub fn set_antenna_diversity(mut self, diversity: Diversity) -> Self {
+        log::info!("antenna diversity: {:?}", diversity);
+        self.state.diversity = Some(diversity);
         self
     }
 
@@ -164,53 +386,22 @@ impl<'a> Concentrator<Builder<'a>> {
         if !self.state.connected {
             return Err(Error::BuilderError(BuilderError::NotConnected));
         }
-        log::info!("starting concentrator");
-        // board config
-        let board = match self.state.board {
-            Some(board) => board,
-            None => return Err(Error::BuilderError(BuilderError::MissingBoard)),
-        };
-        unsafe { hal_call!(lgw_board_setconf(&mut board.into())) }?;
-
-        // rx_rf chain
-        self.state.rx_rf_conf.iter().try_for_each(|c| unsafe {
-            hal_call!(lgw_rxrf_setconf(c.radio as u8, &mut c.into())).map(|_| ())
-        })?;
-
-        // configure IF chain + modem
-        self.state
-            .channel_conf
-            .iter()
-            .try_for_each(|(chain, chan_conf)| unsafe {
-                hal_call!(lgw_rxif_setconf(*chain, &mut chan_conf.into())).map(|_| ())
-            })?;
-
-        // conf Tx gain LUT
-        let gains = self.state.gains;
-        if gains.is_empty() || gains.len() > 16 {
-            log::error!(
-                "gain table must contain 1 to 16 entries, {} provided",
-                gains.len()
-            );
-            return Err(Error::Size);
+        if self.state.diversity.is_some() {
+            Diversity::check(&self.state.rx_rf_conf)?;
         }
-        let mut lut = TxGainLUT::default();
-        lut.lut[..gains.len()].clone_from_slice(gains);
-        lut.size = gains.len() as u8;
-        unsafe {
-            // TODO: de-hardcode this 0u8 (? from helium)
-            hal_call!(lgw_txgain_setconf(
-                0u8,
-                &mut lut as *mut TxGainLUT as *mut llg::lgw_tx_gain_lut_s
-            ))
-        }?;
+        log::info!("starting concentrator");
+        self.state.configure()?;
 
         // Now we ready to start
         unsafe { hal_call!(lgw_start()) }?;
         Ok(Concentrator {
             _prevent_sync: PhantomData,
             _guard: self._guard,
-            state: Running {},
+            state: Running {
+                config: self.state,
+                consecutive_errors: Cell::new(0),
+                poll: Cell::new(PollInterval::default()),
+            },
         })
     }
 }
@@ -218,6 +409,11 @@ impl<'a> Concentrator<Builder<'a>> {
 impl Concentrator<Running> {
     /// Returns the concentrators current receive status.
     pub fn receive_status(&self) -> Result<RxStatus> {
+        let res = self.read_receive_status();
+        self.track(res)
+    }
+
+    fn read_receive_status(&self) -> Result<RxStatus> {
         const RX_STATUS: u8 = 2;
         let mut rx_status = 0xFE;
         unsafe {
@@ -234,11 +430,61 @@ impl Concentrator<Running> {
         rx_status.try_into()
     }
 
-    /// Perform a non-blocking read of up to 16 packets from
+    /// Perform a non-blocking read of up to `RX_FIFO_SIZE` packets from
     /// concentrator's FIFO.
     pub fn receive(&self) -> Result<Option<Vec<RxPacket>>> {
+        let mut out = Vec::new();
+        let res = self.read_fifo(&mut out);
+        self.track(res)?;
+        Ok(if out.is_empty() { None } else { Some(out) })
+    }
+
+    /// Like `receive`, but appends the packets to `out` so its allocation can be reused between
+    /// polls. Returns the number of packets read, if this is `RX_FIFO_SIZE` there may be more
+    /// waiting in the FIFO. With antenna diversity fewer are appended, one per pair of copies.
+    pub fn receive_into(&self, out: &mut Vec<RxPacket>) -> Result<usize> {
+        let res = self.read_fifo(out);
+        self.track(res)
+    }
+
+    /// Polls the FIFO until packets arrive, sleeping by the poll interval in between, and
+    /// appends all of them to `out`, draining the FIFO. Returns how many were read.
+    ///
+    /// Cancel safe, packets are only read right before returning, so it can be `select!`ed
+    /// with other work, e.g. downlinks. Takes `&mut self` as the concentrator isn't `Sync`, so
+    /// the future is `Send` and can run in a spawned task.
+    #[cfg(feature = "tokio")]
+    pub async fn receive_async(&mut self, out: &mut Vec<RxPacket>) -> Result<usize> {
+        loop {
+            let mut read = 0;
+            loop {
+                let len = self.receive_into(out)?;
+                read += len;
+                // A full read means more may be waiting
+                if len < RX_FIFO_SIZE {
+                    break;
+                }
+            }
+            let mut poll = self.state.poll.get();
+            if read > 0 {
+                poll.on_traffic();
+                self.state.poll.set(poll);
+                return Ok(read);
+            }
+            let sleep = poll.on_idle();
+            self.state.poll.set(poll);
+            tokio::time::sleep(sleep).await;
+        }
+    }
+
+    /// Use `poll` instead of the default adaptive interval in `receive_async`
+    pub fn set_poll_interval(&self, poll: PollInterval) {
+        self.state.poll.set(poll);
+    }
+
+    fn read_fifo(&self, out: &mut Vec<RxPacket>) -> Result<usize> {
         log::info!("Setting up receive!");
-        let mut tmp_buf: [std::mem::MaybeUninit<llg::lgw_pkt_rx_s>; 16] =
+        let mut tmp_buf: [std::mem::MaybeUninit<llg::lgw_pkt_rx_s>; RX_FIFO_SIZE] =
             unsafe { std::mem::MaybeUninit::uninit().assume_init() };
 
         log::info!("Now calling");
@@ -249,24 +495,212 @@ impl Concentrator<Running> {
             ))
         }?;
         log::info!("Received {} packets", len);
+        if len > tmp_buf.len() {
+            log::error!(
+                "HAL reported {} packets for a buffer of {}",
+                len,
+                tmp_buf.len()
+            );
+            return Err(Error::HalInvalid(len as i32));
+        }
         if len > 0 {
-            let mut out = Vec::with_capacity(len as usize);
-            for i in 0..(len as usize) {
+            // Reference point for converting packet counter values to wall clock time
+            let now = std::time::SystemTime::now();
+            let reference_cnt = self.read_instcnt();
+            let from = out.len();
+            out.reserve(len);
+            for pkt in &tmp_buf[..len] {
                 // SAFE: We know C initialized up to `len` elements
-                let pkt = unsafe { tmp_buf[i].assume_init() };
-                out.push(RxPacket::try_from(&pkt)?);
+                let pkt = unsafe { pkt.assume_init() };
+                let mut pkt = RxPacket::try_from(&pkt)?;
+                if let Ok(reference_cnt) = reference_cnt {
+                    pkt.set_reference_time(reference_cnt, now);
+                }
+                out.push(pkt);
+            }
+            if let Some(diversity) = &self.state.config.diversity {
+                diversity.merge(out, from);
             }
-            Ok(Some(out))
-        } else {
-            Ok(None)
         }
+        Ok(len)
+    }
+
+    /// The concentrator chip's 64 bit EUI, unique to it, e.g. to identify the gateway.
+    pub fn eui(&self) -> Result<u64> {
+        let mut eui = 0u64;
+        let res = unsafe { hal_call!(lgw_get_eui(&mut eui)) }.map(|_| eui);
+        self.track(res)
     }
 
-    // TODO: How to do this
-    // /// Transmit `packet` over the air.
+    /// Temperature of the concentrator board in degrees Celsius, from its sensor.
+    pub fn temperature(&self) -> Result<f32> {
+        let mut temperature = 0f32;
+        let res = unsafe { hal_call!(lgw_get_temperature(&mut temperature)) }.map(|_| temperature);
+        self.track(res)
+    }
+
+    /// The concentrator's internal 1uS counter now, which packets are timestamped with. It wraps
+    /// about every 71 minutes. E.g. to tell how long ago a packet came in, or whether a
+    /// `TxMode::Timestamp` relative to a received packet's `timestamp` is still ahead.
+    pub fn instcnt(&self) -> Result<u32> {
+        let res = self.read_instcnt();
+        self.track(res)
+    }
+
+    /// Like `instcnt`, without counting an error towards the recovery policy.
+    fn read_instcnt(&self) -> Result<u32> {
+        let mut inst_cnt_us = 0u32;
+        unsafe { hal_call!(lgw_get_instcnt(&mut inst_cnt_us)) }?;
+        Ok(inst_cnt_us)
+    }
+
+    /// The 1uS counter as the concentrator latched it on the last GPS PPS edge, to tie the
+    /// counter to GPS time, see `gps::Gps::sync`.
+    pub fn trigcnt(&self) -> Result<u32> {
+        let mut trig_cnt_us = 0u32;
+        let res = unsafe { hal_call!(lgw_get_trigcnt(&mut trig_cnt_us)) }.map(|_| trig_cnt_us);
+        self.track(res)
+    }
+
+    /// Transmit `packet` over the air, when its `TxMode` says. Fails with `Error::TooLate`
+    /// without sending for a `TxMode::Timestamped` count less than `TX_MIN_LEAD` ahead of the
+    /// counter, or already past.
     pub fn transmit(&self, packet: TxPacket) -> Result {
-        unsafe { hal_call!(lgw_send(&mut packet.try_into()?)) }?;
-        Ok(())
+        let mode = match &packet {
+            TxPacket::LoRa(pkt) => pkt.mode,
+            TxPacket::FSK(pkt) => pkt.mode,
+        };
+        if let TxMode::Timestamped { count_us } = mode {
+            let ahead = count_us.wrapping_sub(self.instcnt()?) as i32;
+            if i64::from(ahead) < TX_MIN_LEAD.as_micros() as i64 {
+                log::warn!("not transmitting at {count_us}, {ahead} us ahead of the counter");
+                return Err(Error::TooLate);
+            }
+        }
+        let res = unsafe { hal_call!(lgw_send(&mut packet.try_into()?)) }.map(|_| ());
+        self.track(res)
+    }
+
+    /// Like `transmit`, but returns once the packet left the antenna, checking the TX status
+    /// every `poll`. Blocks the thread meanwhile. Gives up with `Error::Timeout` if the
+    /// concentrator isn't free again within `timeout`, e.g. for a packet scheduled too far ahead.
+    pub fn transmit_confirmed(
+        &self,
+        packet: TxPacket,
+        poll: std::time::Duration,
+        timeout: std::time::Duration,
+    ) -> Result<TxReport> {
+        self.transmit(packet)?;
+        let mut tracker = TxTracker::new(std::time::Instant::now());
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let status = self.transmit_status()?;
+            let now = std::time::Instant::now();
+            if let Some(report) = tracker.update(status, now)? {
+                return Ok(report);
+            }
+            if now >= deadline {
+                return Err(Error::Timeout);
+            }
+            std::thread::sleep(poll);
+        }
+    }
+
+    /// Waits for the concentrator to finish the packet it has, transmits `packet` and returns
+    /// once it left the antenna, checking the TX status every `TX_POLL`. Blocks the thread
+    /// meanwhile. Fails with `Error::Timeout` if all of it takes longer than `timeout`, e.g. for a
+    /// packet scheduled further ahead, or a `TxMode::OnGps` one without PPS, which then stays
+    /// scheduled.
+    pub fn transmit_blocking(
+        &self,
+        packet: TxPacket,
+        timeout: std::time::Duration,
+    ) -> Result<TxReport> {
+        let deadline = std::time::Instant::now() + timeout;
+        while self.transmit_status()?.is_busy() {
+            if std::time::Instant::now() >= deadline {
+                log::warn!("concentrator still busy with the last packet, not transmitting");
+                return Err(Error::Timeout);
+            }
+            std::thread::sleep(TX_POLL);
+        }
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        self.transmit_confirmed(packet, TX_POLL, left)
+    }
+
+    /// Like `transmit_blocking`, sleeping on a tokio timer instead of blocking the thread.
+    ///
+    /// Cancelling it after the packet was handed to the HAL doesn't stop the packet. Takes
+    /// `&mut self` like `receive_async`, so the future is `Send`.
+    #[cfg(feature = "tokio")]
+    pub async fn transmit_async(
+        &mut self,
+        packet: TxPacket,
+        timeout: std::time::Duration,
+    ) -> Result<TxReport> {
+        let conc = &mut *self;
+        let transmitted = async move {
+            while conc.transmit_status()?.is_busy() {
+                tokio::time::sleep(TX_POLL).await;
+            }
+            conc.transmit(packet)?;
+            let mut tracker = TxTracker::new(std::time::Instant::now());
+            loop {
+                let status = conc.transmit_status()?;
+                if let Some(report) = tracker.update(status, std::time::Instant::now())? {
+                    return Ok(report);
+                }
+                tokio::time::sleep(TX_POLL).await;
+            }
+        };
+        tokio::time::timeout(timeout, transmitted)
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    /// Samples the RSSI at `freq` `nb_scan` times with the SX1261, and returns how the samples
+    /// spread over the RSSI levels. Blocks the thread until the scan is done. A transmit during
+    /// the scan aborts it, failing with `Error::HAL`.
+    pub fn spectral_scan(&self, freq: u32, nb_scan: u16) -> Result<SpectralScan> {
+        if self.state.config.sx1261.is_none() {
+            return Err(Error::BuilderError(BuilderError::MissingSx1261));
+        }
+        let res = self.run_spectral_scan(freq, nb_scan);
+        self.track(res)
+    }
+
+    fn run_spectral_scan(&self, freq: u32, nb_scan: u16) -> Result<SpectralScan> {
+        const POLL: std::time::Duration = std::time::Duration::from_millis(10);
+        unsafe { hal_call!(lgw_spectral_scan_start(freq, nb_scan)) }?;
+        loop {
+            let mut status: llg::lgw_spectral_scan_status_t = SCAN_ON_GOING;
+            unsafe { hal_call!(lgw_spectral_scan_get_status(&mut status)) }?;
+            match status {
+                SCAN_ON_GOING => std::thread::sleep(POLL),
+                SCAN_COMPLETED => break,
+                status => {
+                    log::error!("spectral scan at {freq} Hz ended with status {status}");
+                    return Err(Error::HAL);
+                }
+            }
+        }
+        let mut levels = [0i16; SPECTRAL_SCAN_LEVELS];
+        let mut counts = [0u16; SPECTRAL_SCAN_LEVELS];
+        unsafe {
+            hal_call!(lgw_spectral_scan_get_results(
+                levels.as_mut_ptr(),
+                counts.as_mut_ptr()
+            ))
+        }?;
+        Ok(SpectralScan::new(freq, &levels, &counts))
+    }
+
+    /// Schedule a LoRaWAN Class B beacon.
+    ///
+    /// The beacon is sent on the next GPS pulse-per-second, so this must be called during the
+    /// second before `params.gps_time`.
+    pub fn send_beacon(&self, params: BeaconParams) -> Result {
+        self.transmit(TxPacket::LoRa(params.tx_packet()?))
     }
 
     /// Stop the LoRa concentrator and disconnect it.
@@ -286,6 +720,11 @@ impl Concentrator<Running> {
     /// it may lead to confusion about who's responsibility it is to
     /// check TX status.
     pub fn transmit_status(&self) -> Result<TxStatus> {
+        let res = self.read_transmit_status();
+        self.track(res)
+    }
+
+    fn read_transmit_status(&self) -> Result<TxStatus> {
         const TX_STATUS: u8 = 1;
         let mut tx_status = 0xFE;
         unsafe {
@@ -300,6 +739,43 @@ impl Concentrator<Running> {
         }?;
         tx_status.try_into()
     }
+
+    /// Counts consecutive HAL errors, and runs the `RecoveryPolicy` once there are too many.
+    fn track<T>(&self, res: Result<T>) -> Result<T> {
+        match &res {
+            Ok(_) => self.state.consecutive_errors.set(0),
+            Err(err @ (Error::HAL | Error::HalInvalid(_))) => {
+                let errors = self.state.consecutive_errors.get() + 1;
+                self.state.consecutive_errors.set(errors);
+                if let Some(policy) = &self.state.config.recovery
+                    && errors >= policy.max_consecutive_errors
+                {
+                    self.state.consecutive_errors.set(0);
+                    let result = self.recover(policy);
+                    policy.emit(&RecoveryEvent {
+                        errors,
+                        last_error: err.clone(),
+                        result,
+                    });
+                }
+            }
+            Err(_) => {}
+        }
+        res
+    }
+
+    /// Stops, resets and restarts the concentrator with the configuration it was started with.
+    fn recover(&self, policy: &RecoveryPolicy) -> Result {
+        log::warn!("too many consecutive HAL errors, restarting concentrator");
+        // The concentrator is probably in a bad state already, so a failing stop is expected
+        let _ = unsafe { hal_call!(lgw_stop()) };
+        policy.reset()?;
+        self.state.config.connect()?;
+        self.state.config.configure()?;
+        unsafe { hal_call!(lgw_start()) }?;
+        log::info!("concentrator recovered");
+        Ok(())
+    }
 }
 
 // #[cfg(test)]
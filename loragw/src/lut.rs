@@ -0,0 +1,247 @@
+//! Generation of Tx gain look-up-tables from a list of wanted output powers, so users do not
+//! have to copy gain settings from Semtech's reference configurations.
+use crate::{
+    error::{Error, Result},
+    types::{TxGain, TxGainLUT},
+};
+use must_units::Dbm;
+
+/// Highest power index accepted by the SX1250.
+const SX1250_MAX_PWR_IDX: i32 = 22;
+
+/// Boards we have calibration tables for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardProfile {
+    /// RAK2287 and other SX1302 + SX1250 boards (`global_conf.json.sx1250.EU868`).
+    Sx1250,
+    /// SX1302 + SX1257 boards, as in `default_config_sx1302.toml`.
+    Sx1257,
+}
+
+/// One measured point of a calibration table.
+struct Calibration {
+    rf_power: i8,
+    pa_gain: u8,
+    pwr_idx: u8,
+    mix_gain: u8,
+    dig_gain: u8,
+}
+
+const fn sx1250(rf_power: i8, pa_gain: u8, pwr_idx: u8) -> Calibration {
+    Calibration {
+        rf_power,
+        pa_gain,
+        pwr_idx,
+        mix_gain: 5,
+        dig_gain: 0,
+    }
+}
+
+const fn sx1257(rf_power: i8, pa_gain: u8, mix_gain: u8, dig_gain: u8) -> Calibration {
+    Calibration {
+        rf_power,
+        pa_gain,
+        pwr_idx: 0,
+        mix_gain,
+        dig_gain,
+    }
+}
+
+const SX1250_TABLE: &[Calibration] = &[
+    sx1250(12, 0, 15),
+    sx1250(13, 0, 16),
+    sx1250(14, 0, 17),
+    sx1250(15, 0, 19),
+    sx1250(16, 0, 20),
+    sx1250(18, 1, 1),
+    sx1250(19, 1, 2),
+    sx1250(20, 1, 3),
+    sx1250(21, 1, 4),
+    sx1250(22, 1, 5),
+    sx1250(23, 1, 6),
+    sx1250(24, 1, 7),
+    sx1250(25, 1, 9),
+    sx1250(26, 1, 11),
+    sx1250(27, 1, 14),
+];
+
+const SX1257_TABLE: &[Calibration] = &[
+    sx1257(-11, 0, 8, 3),
+    sx1257(-7, 0, 10, 3),
+    sx1257(-4, 0, 10, 1),
+    sx1257(-1, 0, 14, 2),
+    sx1257(3, 1, 10, 3),
+    sx1257(9, 1, 12, 2),
+    sx1257(10, 1, 12, 1),
+    sx1257(11, 1, 12, 0),
+    sx1257(12, 1, 14, 2),
+    sx1257(15, 2, 11, 1),
+    sx1257(18, 2, 13, 1),
+    sx1257(19, 2, 15, 2),
+    sx1257(22, 3, 10, 2),
+    sx1257(23, 3, 10, 1),
+    sx1257(28, 3, 14, 1),
+];
+
+impl BoardProfile {
+    fn table(self) -> &'static [Calibration] {
+        match self {
+            BoardProfile::Sx1250 => SX1250_TABLE,
+            BoardProfile::Sx1257 => SX1257_TABLE,
+        }
+    }
+
+    /// Gain settings giving `power` at the connector.
+    ///
+    /// The SX1250 power index is linear within a PA gain setting, so it is interpolated from the
+    /// two nearest calibration points sharing the PA gain of the nearest point. The SX1257 mixer
+    /// and digital gains are not linear, so the strongest calibrated entry not above `power` is
+    /// used, and the entry reports that power instead.
+    pub fn gain_for(self, power: Dbm<i8>) -> Result<TxGain> {
+        let rf_power = power.0;
+        let table = self.table();
+        let (min, max) = (table[0].rf_power, table[table.len() - 1].rf_power);
+        if rf_power < min || rf_power > max {
+            log::error!(
+                "{} dBm is outside the calibrated {}..={} dBm of {:?}",
+                rf_power,
+                min,
+                max,
+                self
+            );
+            return Err(Error::Size);
+        }
+        match self {
+            BoardProfile::Sx1250 => {
+                let nearest = table
+                    .iter()
+                    .min_by_key(|c| (c.rf_power - rf_power).abs())
+                    .expect("calibration table is not empty");
+                let group: Vec<&Calibration> = table
+                    .iter()
+                    .filter(|c| c.pa_gain == nearest.pa_gain)
+                    .collect();
+                let pwr_idx = interpolate_pwr_idx(&group, rf_power);
+                Ok(TxGain {
+                    rf_power: power,
+                    pa_gain: nearest.pa_gain,
+                    pwr_id: pwr_idx,
+                    mix_gain: nearest.mix_gain,
+                    dig_gain: nearest.dig_gain,
+                    dac_gain: 3,
+                    ..Default::default()
+                })
+            }
+            BoardProfile::Sx1257 => {
+                let below = table
+                    .iter()
+                    .rev()
+                    .find(|c| c.rf_power <= rf_power)
+                    .expect("rf_power is within the calibrated range");
+                Ok(TxGain {
+                    rf_power: Dbm(below.rf_power),
+                    pa_gain: below.pa_gain,
+                    mix_gain: below.mix_gain,
+                    dig_gain: below.dig_gain,
+                    dac_gain: 3,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+}
+
+/// Linear inter-/extrapolation of the power index between the two calibration points closest to
+/// `rf_power`, clamped to what the SX1250 accepts.
+fn interpolate_pwr_idx(group: &[&Calibration], rf_power: i8) -> u8 {
+    if group.len() == 1 {
+        return group[0].pwr_idx;
+    }
+    let i = group
+        .windows(2)
+        .position(|w| rf_power <= w[1].rf_power)
+        .unwrap_or(group.len() - 2);
+    let (lo, hi) = (group[i], group[i + 1]);
+    let (p0, p1) = (i32::from(lo.rf_power), i32::from(hi.rf_power));
+    let (i0, i1) = (i32::from(lo.pwr_idx), i32::from(hi.pwr_idx));
+    let offset = i32::from(rf_power) - p0;
+    // Round to nearest, the spans are small positive numbers
+    let idx = i0 + (offset * (i1 - i0) * 2 + (p1 - p0)).div_euclid(2 * (p1 - p0));
+    idx.clamp(0, SX1250_MAX_PWR_IDX) as u8
+}
+
+impl TxGainLUT {
+    /// Generates a LUT with one entry per wanted output power, sorted and deduplicated as the HAL
+    /// expects.
+    pub fn for_powers(powers: &[Dbm<i8>], profile: BoardProfile) -> Result<Self> {
+        let mut gains = powers
+            .iter()
+            .map(|p| profile.gain_for(*p))
+            .collect::<Result<Vec<_>>>()?;
+        gains.sort_by_key(|g| g.rf_power);
+        gains.dedup_by_key(|g| g.rf_power);
+        if gains.is_empty() || gains.len() > 16 {
+            log::error!(
+                "gain table must contain 1 to 16 entries, {} provided",
+                gains.len()
+            );
+            return Err(Error::Size);
+        }
+        let mut lut = TxGainLUT::default();
+        lut.lut[..gains.len()].clone_from_slice(&gains);
+        lut.size = gains.len() as u8;
+        Ok(lut)
+    }
+
+    /// The used entries of the LUT.
+    pub fn gains(&self) -> &[TxGain] {
+        &self.lut[..self.size as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibrated_power_is_used_as_is() {
+        let gain = BoardProfile::Sx1250.gain_for(Dbm(20)).unwrap();
+        assert_eq!((gain.pa_gain, gain.pwr_id), (1, 3));
+    }
+
+    #[test]
+    fn power_index_is_interpolated() {
+        // 17 dBm sits between the PA settings, the nearest point is the PA off 16 dBm entry, and
+        // the index is extrapolated from 15 dBm (idx 19) and 16 dBm (idx 20)
+        let gain = BoardProfile::Sx1250.gain_for(Dbm(17)).unwrap();
+        assert_eq!((gain.rf_power, gain.pa_gain, gain.pwr_id), (Dbm(17), 0, 21));
+    }
+
+    #[test]
+    fn sx1257_rounds_down_to_calibrated_entry() {
+        let gain = BoardProfile::Sx1257.gain_for(Dbm(14)).unwrap();
+        assert_eq!(
+            (gain.rf_power, gain.pa_gain, gain.mix_gain),
+            (Dbm(12), 1, 14)
+        );
+    }
+
+    #[test]
+    fn lut_is_sorted_and_deduplicated() {
+        let powers = [Dbm(27), Dbm(14), Dbm(20), Dbm(14)];
+        let lut = TxGainLUT::for_powers(&powers, BoardProfile::Sx1250).unwrap();
+        let powers: Vec<i8> = lut.gains().iter().map(|g| g.rf_power.0).collect();
+        assert_eq!(powers, [14, 20, 27]);
+
+        let full: Vec<Dbm<i8>> = (12..=27).map(Dbm).collect();
+        let lut = TxGainLUT::for_powers(&full, BoardProfile::Sx1250).unwrap();
+        assert_eq!(lut.gains().len(), 16);
+    }
+
+    #[test]
+    fn invalid_requests_fail() {
+        assert!(TxGainLUT::for_powers(&[Dbm(30)], BoardProfile::Sx1250).is_err());
+        assert!(TxGainLUT::for_powers(&[Dbm(-20)], BoardProfile::Sx1257).is_err());
+        assert!(TxGainLUT::for_powers(&[], BoardProfile::Sx1250).is_err());
+    }
+}
@@ -0,0 +1,569 @@
+//! An in-process stand-in for `libloragw`, enabled with the `mock` feature.
+//!
+//! The HAL functions used by this crate are replaced with Rust versions operating on a global
+//! mock state, so the `Concentrator` type-state machine can be exercised without a RAK2287. Tests
+//! can queue received packets, inspect transmitted ones, and inject return values to simulate HAL
+//! faults.
+
+use std::{
+    collections::VecDeque,
+    ffi::{CStr, CString},
+    fs::File,
+    os::{
+        fd::{FromRawFd, IntoRawFd},
+        raw::{c_char, c_int},
+    },
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use crate::{
+    gps::{GpsTime, Position},
+    types::{ComType, FineTimestampMode, TxStatus},
+};
+
+// The types are taken from the real bindings, only the functions are mocked. Local definitions
+// shadow the glob import.
+pub(crate) use libloragw_sys::*;
+pub use libloragw_sys::{lgw_conf_rxif_s, lgw_pkt_rx_s, lgw_pkt_tx_s};
+
+const MOD_LORA: u8 = 0x10;
+const STAT_CRC_OK: u8 = 0x10;
+const TX_STATUS: u8 = 1;
+const TX_FREE: u8 = 2;
+const TX_EMITTING: u8 = 4;
+const RX_ON: u8 = 2;
+/// EUI of the mock concentrator, until `set_eui` changes it.
+pub const MOCK_EUI: u64 = 0x0016_C001_FF10_0001;
+/// Board temperature of the mock concentrator, until `set_temperature` changes it.
+pub const MOCK_TEMPERATURE: f32 = 25.0;
+
+struct MockState {
+    /// One-shot return values, consumed by the next call of the named HAL function.
+    faults: VecDeque<(&'static str, i32)>,
+    /// Return values for the nth call from now of the named HAL function.
+    nth_faults: Vec<(&'static str, u32, i32)>,
+    rx_fifo: VecDeque<lgw_pkt_rx_s>,
+    /// Handed to the last `lgw_connect`.
+    connected: Option<(ComType, CString)>,
+    sent: Vec<lgw_pkt_tx_s>,
+    inst_cnt: u32,
+    eui: u64,
+    temperature: f32,
+    /// TX statuses the next status polls return, before the modem reports free again.
+    tx_statuses: VecDeque<u8>,
+    trig_cnt: u32,
+    /// What `lgw_gps_get` reports, None before the receiver has a fix.
+    gps_fix: Option<(GpsTime, Position)>,
+    /// Handed to `lgw_ftime_setconf`, None if it wasn't called.
+    ftime_conf: Option<lgw_conf_ftime_s>,
+    /// Handed to `lgw_rxif_setconf`, with their IF chain.
+    if_confs: Vec<(u8, lgw_conf_rxif_s)>,
+    /// Frequency and sample count of the last spectral scan started.
+    scan_started: Option<(u32, u16)>,
+    /// Status polls left before the running scan completes.
+    scan_polls: u32,
+    scan_levels: Vec<i16>,
+    scan_counts: Vec<u16>,
+    /// Packets still to be lost on their way out of the RX FIFO.
+    rx_drops: u32,
+    /// Packets still to be corrupted, with the payload byte and the mask it is XORed with.
+    rx_corruption: Option<(u32, usize, u8)>,
+    /// How long a packet handed to `lgw_send` is emitted for.
+    tx_delay: Duration,
+    /// Until when the TX status reports emitting.
+    tx_busy_until: Option<Instant>,
+}
+
+impl MockState {
+    const fn new() -> Self {
+        Self {
+            faults: VecDeque::new(),
+            nth_faults: Vec::new(),
+            rx_fifo: VecDeque::new(),
+            connected: None,
+            sent: Vec::new(),
+            inst_cnt: 0,
+            eui: MOCK_EUI,
+            temperature: MOCK_TEMPERATURE,
+            tx_statuses: VecDeque::new(),
+            trig_cnt: 0,
+            gps_fix: None,
+            ftime_conf: None,
+            if_confs: Vec::new(),
+            scan_started: None,
+            scan_polls: 0,
+            scan_levels: Vec::new(),
+            scan_counts: Vec::new(),
+            rx_drops: 0,
+            rx_corruption: None,
+            tx_delay: Duration::ZERO,
+            tx_busy_until: None,
+        }
+    }
+}
+
+static STATE: Mutex<MockState> = Mutex::new(MockState::new());
+
+fn state() -> MutexGuard<'static, MockState> {
+    // A panicking test must not poison the mock for the rest of the test binary
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns an injected return value for `call`, if one is queued or due.
+fn take_fault(call: &'static str) -> Option<i32> {
+    let mut state = state();
+    let mut due = None;
+    state.nth_faults.retain_mut(|(name, nth, ret)| {
+        if *name != call {
+            return true;
+        }
+        if *nth <= 1 && due.is_none() {
+            due = Some(*ret);
+            return false;
+        }
+        // Two due at once: the other one fails the call after
+        *nth = nth.saturating_sub(1).max(1);
+        true
+    });
+    if due.is_some() {
+        return due;
+    }
+    let pos = state.faults.iter().position(|(name, _)| *name == call)?;
+    state.faults.remove(pos).map(|(_, ret)| ret)
+}
+
+/// Clears all queued packets, faults, transmit history and replayed calls.
+pub fn reset() {
+    *state() = MockState::new();
+    crate::record::replay([]);
+}
+
+/// Makes the next call of the HAL function `call` (e.g. `"lgw_receive"`) return `ret`.
+pub fn inject_return(call: &'static str, ret: i32) {
+    state().faults.push_back((call, ret));
+}
+
+/// Makes the `nth` call from now of the HAL function `call` return `ret`, 1 being the next one,
+/// e.g. to fail a transmit in the middle of a burst.
+pub fn inject_return_on(call: &'static str, nth: u32, ret: i32) {
+    state().nth_faults.push((call, nth, ret));
+}
+
+/// Loses the next `packets` packets leaving the RX FIFO, as if they were never demodulated.
+pub fn drop_rx(packets: u32) {
+    state().rx_drops = packets;
+}
+
+/// XORs payload byte `index` of the next `packets` packets leaving the RX FIFO with `mask`. Their
+/// CRC status stays as it was, like a corruption the CRC didn't catch.
+pub fn corrupt_rx(packets: u32, index: usize, mask: u8) {
+    state().rx_corruption = (packets > 0).then_some((packets, index, mask));
+}
+
+/// Makes the TX status report emitting for `delay` after every `lgw_send`, before it is free
+/// again.
+pub fn set_tx_delay(delay: Duration) {
+    state().tx_delay = delay;
+}
+
+/// Queues a packet in the mock concentrator's RX FIFO.
+pub fn push_rx(pkt: lgw_pkt_rx_s) {
+    state().rx_fifo.push_back(pkt);
+}
+
+/// Takes all packets handed to `lgw_send` so far.
+pub fn take_sent() -> Vec<lgw_pkt_tx_s> {
+    std::mem::take(&mut state().sent)
+}
+
+/// Sets the value of the concentrator's internal 1uS counter.
+pub fn set_instcnt(inst_cnt: u32) {
+    state().inst_cnt = inst_cnt;
+}
+
+/// Makes the next TX status polls return `statuses` in order, then free as usual.
+pub fn queue_tx_statuses(statuses: &[TxStatus]) {
+    state()
+        .tx_statuses
+        .extend(statuses.iter().map(|status| *status as u8));
+}
+
+/// Sets the EUI the mock concentrator reports.
+pub fn set_eui(eui: u64) {
+    state().eui = eui;
+}
+
+/// Sets the board temperature the mock concentrator reports.
+pub fn set_temperature(temperature: f32) {
+    state().temperature = temperature;
+}
+
+/// Sets the value the 1uS counter latched on the last PPS edge.
+pub fn set_trigcnt(trig_cnt: u32) {
+    state().trig_cnt = trig_cnt;
+}
+
+/// Sets the time and position the mock GPS reports after parsing a message. The GPS port is
+/// whatever file `Gps::enable` is given, where the mock takes UBX messages of class 0x01 id 0x20
+/// for NAV-TIMEGPS and RMC or GGA sentences for the position.
+pub fn set_gps_fix(time: GpsTime, position: Position) {
+    state().gps_fix = Some((time, position));
+}
+
+/// How the concentrator was last connected to, with its device path.
+pub fn connected_to() -> Option<(ComType, CString)> {
+    state().connected.clone()
+}
+
+/// The fine timestamp mode the concentrator was configured with, None if it wasn't enabled.
+pub fn fine_timestamp_mode() -> Option<FineTimestampMode> {
+    let conf = state().ftime_conf.filter(|conf| conf.enable)?;
+    Some(match conf.mode {
+        0 => FineTimestampMode::HighCapacity,
+        _ => FineTimestampMode::AllSpreading,
+    })
+}
+
+/// The IF chains the concentrator was configured with, and their channels.
+pub fn if_confs() -> Vec<(u8, lgw_conf_rxif_s)> {
+    state().if_confs.clone()
+}
+
+/// Sets the histogram the next spectral scans return, with a level and count per bin. The
+/// scan reports itself ongoing for `polls` status polls first.
+pub fn set_spectral_scan(levels: &[i16], counts: &[u16], polls: u32) {
+    let mut state = state();
+    state.scan_levels = levels.to_vec();
+    state.scan_counts = counts.to_vec();
+    state.scan_polls = polls;
+}
+
+/// Frequency and sample count of the last spectral scan started.
+pub fn spectral_scan_started() -> Option<(u32, u16)> {
+    state().scan_started
+}
+
+/// Creates a received LoRa packet with a passing CRC, on SF7 BW125.
+pub fn lora_rx_packet(payload: &[u8]) -> lgw_pkt_rx_s {
+    let mut pkt: lgw_pkt_rx_s = unsafe { std::mem::zeroed() };
+    pkt.freq_hz = 868_100_000;
+    pkt.modulation = MOD_LORA;
+    pkt.status = STAT_CRC_OK;
+    pkt.bandwidth = 4;
+    pkt.datarate = 7;
+    pkt.coderate = 1;
+    pkt.rssis = -60.0;
+    pkt.snr = 9.5;
+    pkt.size = payload.len() as u16;
+    pkt.payload[..payload.len()].copy_from_slice(payload);
+    pkt
+}
+
+pub(crate) unsafe fn lgw_connect(com_type: u32, com_path: *const c_char) -> i32 {
+    if let Some(ret) = take_fault("lgw_connect") {
+        return ret;
+    }
+    let com_type = match com_type {
+        0 => ComType::SPI,
+        _ => ComType::USB,
+    };
+    let com_path = unsafe { CStr::from_ptr(com_path) }.to_owned();
+    state().connected = Some((com_type, com_path));
+    0
+}
+
+pub(crate) unsafe fn lgw_board_setconf(_conf: *mut lgw_conf_board_s) -> i32 {
+    take_fault("lgw_board_setconf").unwrap_or(0)
+}
+
+pub(crate) unsafe fn lgw_rxrf_setconf(_rf_chain: u8, _conf: *mut lgw_conf_rxrf_s) -> i32 {
+    take_fault("lgw_rxrf_setconf").unwrap_or(0)
+}
+
+pub(crate) unsafe fn lgw_rxif_setconf(if_chain: u8, conf: *mut lgw_conf_rxif_s) -> i32 {
+    if let Some(ret) = take_fault("lgw_rxif_setconf") {
+        return ret;
+    }
+    state().if_confs.push((if_chain, unsafe { *conf }));
+    0
+}
+
+pub(crate) unsafe fn lgw_ftime_setconf(conf: *mut lgw_conf_ftime_s) -> i32 {
+    if let Some(ret) = take_fault("lgw_ftime_setconf") {
+        return ret;
+    }
+    state().ftime_conf = Some(unsafe { *conf });
+    0
+}
+
+pub(crate) unsafe fn lgw_sx1261_setconf(_conf: *mut lgw_conf_sx1261_s) -> i32 {
+    take_fault("lgw_sx1261_setconf").unwrap_or(0)
+}
+
+pub(crate) unsafe fn lgw_txgain_setconf(_rf_chain: u8, _conf: *mut lgw_tx_gain_lut_s) -> i32 {
+    take_fault("lgw_txgain_setconf").unwrap_or(0)
+}
+
+pub(crate) unsafe fn lgw_start() -> i32 {
+    take_fault("lgw_start").unwrap_or(0)
+}
+
+pub(crate) unsafe fn lgw_stop() -> i32 {
+    take_fault("lgw_stop").unwrap_or(0)
+}
+
+pub(crate) unsafe fn lgw_status(_rf_chain: u8, select: u8, code: *mut u8) -> i32 {
+    if let Some(ret) = take_fault("lgw_status") {
+        return ret;
+    }
+    let mut state = state();
+    let emitting = state
+        .tx_busy_until
+        .is_some_and(|until| Instant::now() < until);
+    let status = match select {
+        TX_STATUS => match state.tx_statuses.pop_front() {
+            Some(status) => status,
+            None if emitting => TX_EMITTING,
+            None => TX_FREE,
+        },
+        _ => RX_ON,
+    };
+    unsafe { *code = status };
+    0
+}
+
+pub(crate) unsafe fn lgw_receive(max_pkt: u8, pkt_data: *mut lgw_pkt_rx_s) -> i32 {
+    if let Some(ret) = take_fault("lgw_receive") {
+        return ret;
+    }
+    let mut state = state();
+    let mut nb_pkt = 0;
+    while nb_pkt < max_pkt as usize {
+        let Some(mut pkt) = state.rx_fifo.pop_front() else {
+            break;
+        };
+        if state.rx_drops > 0 {
+            state.rx_drops -= 1;
+            continue;
+        }
+        if let Some((packets, index, mask)) = &mut state.rx_corruption {
+            if *index < pkt.size as usize {
+                pkt.payload[*index] ^= *mask;
+            }
+            *packets -= 1;
+            if *packets == 0 {
+                state.rx_corruption = None;
+            }
+        }
+        unsafe { pkt_data.add(nb_pkt).write(pkt) };
+        nb_pkt += 1;
+    }
+    nb_pkt as i32
+}
+
+pub(crate) unsafe fn lgw_send(pkt_data: *mut lgw_pkt_tx_s) -> i32 {
+    if let Some(ret) = take_fault("lgw_send") {
+        return ret;
+    }
+    let mut state = state();
+    state.sent.push(unsafe { *pkt_data });
+    if !state.tx_delay.is_zero() {
+        state.tx_busy_until = Some(Instant::now() + state.tx_delay);
+    }
+    0
+}
+
+pub(crate) unsafe fn lgw_spectral_scan_start(freq_hz: u32, nb_scan: u16) -> i32 {
+    if let Some(ret) = take_fault("lgw_spectral_scan_start") {
+        return ret;
+    }
+    state().scan_started = Some((freq_hz, nb_scan));
+    0
+}
+
+pub(crate) unsafe fn lgw_spectral_scan_get_status(status: *mut lgw_spectral_scan_status_t) -> i32 {
+    if let Some(ret) = take_fault("lgw_spectral_scan_get_status") {
+        return ret;
+    }
+    let mut state = state();
+    let scan_status = if state.scan_polls > 0 {
+        state.scan_polls -= 1;
+        crate::scan::SCAN_ON_GOING
+    } else {
+        crate::scan::SCAN_COMPLETED
+    };
+    unsafe { *status = scan_status };
+    0
+}
+
+pub(crate) unsafe fn lgw_spectral_scan_get_results(levels_dbm: *mut i16, results: *mut u16) -> i32 {
+    if let Some(ret) = take_fault("lgw_spectral_scan_get_results") {
+        return ret;
+    }
+    let state = state();
+    let size = LGW_SPECTRAL_SCAN_RESULT_SIZE as usize;
+    let levels = unsafe { std::slice::from_raw_parts_mut(levels_dbm, size) };
+    let counts = unsafe { std::slice::from_raw_parts_mut(results, size) };
+    for (i, (level, count)) in levels.iter_mut().zip(counts.iter_mut()).enumerate() {
+        *level = state.scan_levels.get(i).copied().unwrap_or_default();
+        *count = state.scan_counts.get(i).copied().unwrap_or_default();
+    }
+    0
+}
+
+pub(crate) unsafe fn lgw_get_eui(eui: *mut u64) -> i32 {
+    if let Some(ret) = take_fault("lgw_get_eui") {
+        return ret;
+    }
+    unsafe { *eui = state().eui };
+    0
+}
+
+pub(crate) unsafe fn lgw_get_temperature(temperature: *mut f32) -> i32 {
+    if let Some(ret) = take_fault("lgw_get_temperature") {
+        return ret;
+    }
+    unsafe { *temperature = state().temperature };
+    0
+}
+
+pub(crate) unsafe fn lgw_get_instcnt(inst_cnt_us: *mut u32) -> i32 {
+    if let Some(ret) = take_fault("lgw_get_instcnt") {
+        return ret;
+    }
+    unsafe { *inst_cnt_us = state().inst_cnt };
+    0
+}
+
+pub(crate) unsafe fn lgw_get_trigcnt(trig_cnt_us: *mut u32) -> i32 {
+    if let Some(ret) = take_fault("lgw_get_trigcnt") {
+        return ret;
+    }
+    unsafe { *trig_cnt_us = state().trig_cnt };
+    0
+}
+
+pub(crate) unsafe fn lgw_gps_enable(
+    tty_path: *mut c_char,
+    _gps_family: *mut c_char,
+    _target_brate: speed_t,
+    fd_ptr: *mut c_int,
+) -> i32 {
+    if let Some(ret) = take_fault("lgw_gps_enable") {
+        return ret;
+    }
+    let path = unsafe { CStr::from_ptr(tty_path) };
+    let Ok(file) = File::open(path.to_string_lossy().as_ref()) else {
+        return -1;
+    };
+    unsafe { *fd_ptr = file.into_raw_fd() };
+    0
+}
+
+pub(crate) unsafe fn lgw_gps_disable(fd: c_int) -> i32 {
+    if let Some(ret) = take_fault("lgw_gps_disable") {
+        return ret;
+    }
+    drop(unsafe { File::from_raw_fd(fd) });
+    0
+}
+
+pub(crate) unsafe fn lgw_parse_ubx(
+    serial_buff: *const c_char,
+    buff_size: usize,
+    msg_size: *mut usize,
+) -> gps_msg {
+    let buff = unsafe { std::slice::from_raw_parts(serial_buff as *const u8, buff_size) };
+    if buff.len() < 8 {
+        return gps_msg_INCOMPLETE;
+    }
+    let size = 8 + usize::from(u16::from_le_bytes([buff[4], buff[5]]));
+    if buff.len() < size {
+        return gps_msg_INCOMPLETE;
+    }
+    unsafe { *msg_size = size };
+    match (buff[1], buff[2], buff[3]) {
+        (0x62, 0x01, 0x20) => gps_msg_UBX_NAV_TIMEGPS,
+        _ => gps_msg_IGNORED,
+    }
+}
+
+pub(crate) unsafe fn lgw_parse_nmea(serial_buff: *const c_char, buff_size: c_int) -> gps_msg {
+    let buff =
+        unsafe { std::slice::from_raw_parts(serial_buff as *const u8, buff_size.max(0) as usize) };
+    match buff.get(3..6) {
+        Some(b"RMC") => gps_msg_NMEA_RMC,
+        Some(b"GGA") => gps_msg_NMEA_GGA,
+        _ => gps_msg_IGNORED,
+    }
+}
+
+pub(crate) unsafe fn lgw_gps_get(
+    utc: *mut timespec,
+    gps_time: *mut timespec,
+    loc: *mut coord_s,
+    err: *mut coord_s,
+) -> i32 {
+    if let Some(ret) = take_fault("lgw_gps_get") {
+        return ret;
+    }
+    let Some((time, position)) = state().gps_fix else {
+        return -1;
+    };
+    unsafe {
+        if !utc.is_null() {
+            *utc = mock_timespec(time.utc.duration_since(UNIX_EPOCH).unwrap_or_default());
+        }
+        if !gps_time.is_null() {
+            *gps_time = mock_timespec(time.gps);
+        }
+        if !loc.is_null() {
+            *loc = coord_s {
+                lat: position.latitude,
+                lon: position.longitude,
+                alt: position.altitude,
+            };
+        }
+        if !err.is_null() {
+            *err = std::mem::zeroed();
+        }
+    }
+    0
+}
+
+pub(crate) unsafe fn lgw_gps_sync(
+    ref_: *mut tref,
+    count_us: u32,
+    utc: timespec,
+    gps_time: timespec,
+) -> i32 {
+    if let Some(ret) = take_fault("lgw_gps_sync") {
+        return ret;
+    }
+    unsafe {
+        (*ref_).systime = 0;
+        (*ref_).count_us = count_us;
+        (*ref_).utc = utc;
+        (*ref_).gps = gps_time;
+        (*ref_).xtal_err = 1.0;
+    }
+    0
+}
+
+pub(crate) unsafe fn lgw_cnt2utc(ref_: tref, count_us: u32, utc: *mut timespec) -> i32 {
+    if let Some(ret) = take_fault("lgw_cnt2utc") {
+        return ret;
+    }
+    let since = Duration::from_micros(u64::from(count_us.wrapping_sub(ref_.count_us)));
+    let at = Duration::new(ref_.utc.tv_sec as u64, ref_.utc.tv_nsec as u32) + since;
+    unsafe { *utc = mock_timespec(at) };
+    0
+}
+
+fn mock_timespec(duration: Duration) -> timespec {
+    timespec {
+        tv_sec: duration.as_secs() as _,
+        tv_nsec: duration.subsec_nanos() as _,
+    }
+}
@@ -0,0 +1,48 @@
+//! How often the concentrator's FIFO is polled by `Concentrator::receive_async`.
+use std::time::Duration;
+
+/// Sleep between polls of the concentrator's FIFO.
+///
+/// The interval drops to `min` as soon as packets arrive, and doubles towards `max` while the
+/// FIFO stays empty. This keeps latency low during bursts, while an idle gateway barely uses the
+/// CPU.
+#[derive(Debug, Clone, Copy)]
+pub struct PollInterval {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl PollInterval {
+    /// Interval that adapts between `min` and `max` based on recent traffic.
+    pub fn adaptive(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            current: min,
+        }
+    }
+
+    /// Interval that never changes, as the gateway polled before.
+    pub fn fixed(interval: Duration) -> Self {
+        Self::adaptive(interval, interval)
+    }
+
+    /// Packets were received, so poll quickly again.
+    pub fn on_traffic(&mut self) {
+        self.current = self.min;
+    }
+
+    /// Nothing was received. Returns how long to sleep before the next poll.
+    pub fn on_idle(&mut self) -> Duration {
+        let sleep = self.current;
+        self.current = (self.current * 2).min(self.max);
+        sleep
+    }
+}
+
+impl Default for PollInterval {
+    fn default() -> Self {
+        Self::adaptive(Duration::from_millis(1), Duration::from_millis(50))
+    }
+}
@@ -0,0 +1,405 @@
+//! Recording the calls this crate makes into `libloragw`, and replaying them.
+//!
+//! `record_to` writes every HAL call from then on as a line: its arguments, its return value and
+//! what the HAL wrote through its pointers in hex, e.g. `lgw_get_instcnt(*) = 0 40420f00`.
+//! Pointer arguments are recorded as `*`. With the `mock` feature, `replay` feeds such a trace
+//! back: the calls return what was recorded instead of reaching the mock, until the trace runs
+//! out, so a failure in the field becomes a reproducible test. A call other than the next one
+//! recorded panics. The GPS and spectral scan results calls are passed through unrecorded.
+use std::{
+    fmt,
+    io::{LineWriter, Write},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::error::Error;
+
+/// A recorded HAL call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HalCall {
+    /// Name of the HAL function, e.g. `lgw_receive`.
+    pub call: String,
+    /// Its arguments, `*` for pointers.
+    pub args: Vec<String>,
+    /// What it returned.
+    pub ret: i32,
+    /// What it wrote through its pointers, e.g. the received packets.
+    pub out: Vec<u8>,
+}
+
+impl fmt::Display for HalCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({}) = {}", self.call, self.args.join(", "), self.ret)?;
+        if !self.out.is_empty() {
+            write!(f, " ")?;
+            for byte in &self.out {
+                write!(f, "{:02x}", byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HalCall {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self, Error> {
+        let invalid = || {
+            eprintln!("unable to parse {:?} as a HAL call", line);
+            Error::Data
+        };
+        let (head, result) = line.trim().split_once(") = ").ok_or_else(invalid)?;
+        let (call, args) = head.split_once('(').ok_or_else(invalid)?;
+        let (ret, out) = result.split_once(' ').unwrap_or((result, ""));
+        if out.len() % 2 != 0 {
+            return Err(invalid());
+        }
+        Ok(HalCall {
+            call: call.to_string(),
+            args: args
+                .split(", ")
+                .filter(|arg| !arg.is_empty())
+                .map(str::to_string)
+                .collect(),
+            ret: ret.parse().map_err(|_| invalid())?,
+            // By bytes, a corrupt line may have other than ASCII where the hex should be
+            out: out
+                .as_bytes()
+                .chunks(2)
+                .map(|pair| {
+                    let digit = |byte: u8| char::from(byte).to_digit(16);
+                    match (digit(pair[0]), digit(pair[1])) {
+                        (Some(high), Some(low)) => Ok((high << 4 | low) as u8),
+                        _ => Err(invalid()),
+                    }
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Parses a trace written by `record_to`, skipping empty lines.
+pub fn parse_trace(trace: &str) -> Result<Vec<HalCall>, Error> {
+    trace
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static RECORDER: Mutex<Option<LineWriter<Box<dyn Write + Send>>>> = Mutex::new(None);
+
+/// Writes every HAL call from now on to `out`, a line each, replacing an earlier recording.
+pub fn record_to(out: impl Write + Send + 'static) {
+    let out: Box<dyn Write + Send> = Box::new(out);
+    *RECORDER.lock().unwrap_or_else(|e| e.into_inner()) = Some(LineWriter::new(out));
+    RECORDING.store(true, Ordering::Relaxed);
+}
+
+/// Stops recording and closes what it wrote to.
+pub fn stop_recording() {
+    RECORDING.store(false, Ordering::Relaxed);
+    RECORDER.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+#[cfg(feature = "mock")]
+static REPLAY: Mutex<std::collections::VecDeque<HalCall>> =
+    Mutex::new(std::collections::VecDeque::new());
+
+/// Makes the next HAL calls return `calls` instead of reaching the mock, in order.
+#[cfg(feature = "mock")]
+pub fn replay(calls: impl IntoIterator<Item = HalCall>) {
+    *REPLAY.lock().unwrap_or_else(|e| e.into_inner()) = calls.into_iter().collect();
+}
+
+/// How many of the replayed calls are still to be made.
+#[cfg(feature = "mock")]
+pub fn replay_left() -> usize {
+    REPLAY.lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+/// The recorded call to return for `call(args)`, panicking if the next one is another.
+#[cfg(feature = "mock")]
+fn next_replayed(call: &str, args: &[String]) -> Option<HalCall> {
+    let mut replay = REPLAY.lock().unwrap_or_else(|e| e.into_inner());
+    let next = replay.pop_front()?;
+    drop(replay);
+    if next.call != call || next.args != args {
+        panic!(
+            "replay diverged: {}({}) was called, {} was recorded",
+            call,
+            args.join(", "),
+            next
+        );
+    }
+    Some(next)
+}
+
+/// Makes a HAL call through `hal`, recording it or returning the replayed one. `out` is where
+/// the HAL writes, as many bytes as `out_len` gives for its return value.
+unsafe fn traced(
+    call: &'static str,
+    args: &[&dyn fmt::Display],
+    out: *mut u8,
+    out_len: impl Fn(i32) -> usize,
+    hal: impl FnOnce() -> i32,
+) -> i32 {
+    #[cfg(feature = "mock")]
+    let replaying = replay_left() > 0;
+    #[cfg(not(feature = "mock"))]
+    let replaying = false;
+    if !replaying && !RECORDING.load(Ordering::Relaxed) {
+        return hal();
+    }
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+
+    #[cfg(feature = "mock")]
+    if let Some(recorded) = next_replayed(call, &args) {
+        let len = recorded.out.len().min(out_len(recorded.ret));
+        if !out.is_null() {
+            unsafe { std::ptr::copy_nonoverlapping(recorded.out.as_ptr(), out, len) };
+        }
+        return recorded.ret;
+    }
+
+    // Structs have padding the HAL doesn't write, it's recorded as zeroes. `out_len` of the
+    // largest return is the whole buffer.
+    if !out.is_null() {
+        unsafe { std::ptr::write_bytes(out, 0, out_len(i32::MAX)) };
+    }
+    let ret = hal();
+    let out = if ret >= 0 && !out.is_null() {
+        unsafe { std::slice::from_raw_parts(out, out_len(ret)) }.to_vec()
+    } else {
+        Vec::new()
+    };
+    let line = HalCall {
+        call: call.to_string(),
+        args,
+        ret,
+        out,
+    };
+    if let Some(recorder) = RECORDER.lock().unwrap_or_else(|e| e.into_inner()).as_mut()
+        && let Err(e) = writeln!(recorder, "{}", line)
+    {
+        eprintln!("unable to record HAL call: {}", e);
+    }
+    ret
+}
+
+/// The HAL functions this crate calls, each going through `traced` to `crate::hal`. Those not
+/// defined here are passed through from the glob import.
+pub(crate) mod shim {
+    use std::{mem::size_of, os::raw::c_char, ptr};
+
+    pub(crate) use crate::hal::*;
+
+    use super::traced;
+
+    const PTR: &str = "*";
+
+    /// `out_len` of a call writing one `T`
+    fn one<T>(_: i32) -> usize {
+        size_of::<T>()
+    }
+
+    fn none(_: i32) -> usize {
+        0
+    }
+
+    pub(crate) unsafe fn lgw_connect(com_type: u32, com_path: *const c_char) -> i32 {
+        unsafe {
+            traced(
+                "lgw_connect",
+                &[&com_type, &PTR],
+                ptr::null_mut(),
+                none,
+                || crate::hal::lgw_connect(com_type, com_path),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_board_setconf(conf: *mut lgw_conf_board_s) -> i32 {
+        unsafe {
+            traced("lgw_board_setconf", &[&PTR], ptr::null_mut(), none, || {
+                crate::hal::lgw_board_setconf(conf)
+            })
+        }
+    }
+
+    pub(crate) unsafe fn lgw_rxrf_setconf(rf_chain: u8, conf: *mut lgw_conf_rxrf_s) -> i32 {
+        unsafe {
+            traced(
+                "lgw_rxrf_setconf",
+                &[&rf_chain, &PTR],
+                ptr::null_mut(),
+                none,
+                || crate::hal::lgw_rxrf_setconf(rf_chain, conf),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_rxif_setconf(if_chain: u8, conf: *mut lgw_conf_rxif_s) -> i32 {
+        unsafe {
+            traced(
+                "lgw_rxif_setconf",
+                &[&if_chain, &PTR],
+                ptr::null_mut(),
+                none,
+                || crate::hal::lgw_rxif_setconf(if_chain, conf),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_ftime_setconf(conf: *mut lgw_conf_ftime_s) -> i32 {
+        unsafe {
+            traced("lgw_ftime_setconf", &[&PTR], ptr::null_mut(), none, || {
+                crate::hal::lgw_ftime_setconf(conf)
+            })
+        }
+    }
+
+    pub(crate) unsafe fn lgw_sx1261_setconf(conf: *mut lgw_conf_sx1261_s) -> i32 {
+        unsafe {
+            traced("lgw_sx1261_setconf", &[&PTR], ptr::null_mut(), none, || {
+                crate::hal::lgw_sx1261_setconf(conf)
+            })
+        }
+    }
+
+    pub(crate) unsafe fn lgw_txgain_setconf(rf_chain: u8, conf: *mut lgw_tx_gain_lut_s) -> i32 {
+        unsafe {
+            traced(
+                "lgw_txgain_setconf",
+                &[&rf_chain, &PTR],
+                ptr::null_mut(),
+                none,
+                || crate::hal::lgw_txgain_setconf(rf_chain, conf),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_start() -> i32 {
+        unsafe {
+            traced("lgw_start", &[], ptr::null_mut(), none, || {
+                crate::hal::lgw_start()
+            })
+        }
+    }
+
+    pub(crate) unsafe fn lgw_stop() -> i32 {
+        unsafe {
+            traced("lgw_stop", &[], ptr::null_mut(), none, || {
+                crate::hal::lgw_stop()
+            })
+        }
+    }
+
+    pub(crate) unsafe fn lgw_status(rf_chain: u8, select: u8, code: *mut u8) -> i32 {
+        unsafe {
+            traced(
+                "lgw_status",
+                &[&rf_chain, &select, &PTR],
+                code,
+                one::<u8>,
+                || crate::hal::lgw_status(rf_chain, select, code),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_receive(max_pkt: u8, pkt_data: *mut lgw_pkt_rx_s) -> i32 {
+        let packets = move |ret: i32| ret.clamp(0, i32::from(max_pkt)) as usize;
+        unsafe {
+            traced(
+                "lgw_receive",
+                &[&max_pkt, &PTR],
+                pkt_data as *mut u8,
+                move |ret| packets(ret) * size_of::<lgw_pkt_rx_s>(),
+                || crate::hal::lgw_receive(max_pkt, pkt_data),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_send(pkt_data: *mut lgw_pkt_tx_s) -> i32 {
+        unsafe {
+            traced("lgw_send", &[&PTR], ptr::null_mut(), none, || {
+                crate::hal::lgw_send(pkt_data)
+            })
+        }
+    }
+
+    pub(crate) unsafe fn lgw_spectral_scan_start(freq_hz: u32, nb_scan: u16) -> i32 {
+        unsafe {
+            traced(
+                "lgw_spectral_scan_start",
+                &[&freq_hz, &nb_scan],
+                ptr::null_mut(),
+                none,
+                || crate::hal::lgw_spectral_scan_start(freq_hz, nb_scan),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_spectral_scan_get_status(
+        status: *mut lgw_spectral_scan_status_t,
+    ) -> i32 {
+        unsafe {
+            traced(
+                "lgw_spectral_scan_get_status",
+                &[&PTR],
+                status as *mut u8,
+                one::<lgw_spectral_scan_status_t>,
+                || crate::hal::lgw_spectral_scan_get_status(status),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_get_eui(eui: *mut u64) -> i32 {
+        unsafe {
+            traced("lgw_get_eui", &[&PTR], eui as *mut u8, one::<u64>, || {
+                crate::hal::lgw_get_eui(eui)
+            })
+        }
+    }
+
+    pub(crate) unsafe fn lgw_get_temperature(temperature: *mut f32) -> i32 {
+        unsafe {
+            traced(
+                "lgw_get_temperature",
+                &[&PTR],
+                temperature as *mut u8,
+                one::<f32>,
+                || crate::hal::lgw_get_temperature(temperature),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_get_instcnt(inst_cnt_us: *mut u32) -> i32 {
+        unsafe {
+            traced(
+                "lgw_get_instcnt",
+                &[&PTR],
+                inst_cnt_us as *mut u8,
+                one::<u32>,
+                || crate::hal::lgw_get_instcnt(inst_cnt_us),
+            )
+        }
+    }
+
+    pub(crate) unsafe fn lgw_get_trigcnt(trig_cnt_us: *mut u32) -> i32 {
+        unsafe {
+            traced(
+                "lgw_get_trigcnt",
+                &[&PTR],
+                trig_cnt_us as *mut u8,
+                one::<u32>,
+                || crate::hal::lgw_get_trigcnt(trig_cnt_us),
+            )
+        }
+    }
+}
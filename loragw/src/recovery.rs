@@ -0,0 +1,64 @@
+use crate::error::{Error, Result};
+
+type ResetRoutine = dyn Fn() -> std::result::Result<(), Box<dyn std::error::Error>> + Send;
+type RecoveryHook = dyn Fn(&RecoveryEvent) + Send;
+
+/// Lets a running concentrator recover by itself from transient SPI errors.
+///
+/// After `max_consecutive_errors` HAL errors in a row, the concentrator is stopped, reset with
+/// the given routine, and started again with the configuration it was built with. This happens
+/// inside the same `Concentrator<Running>`, so callers only see the error that triggered it.
+pub struct RecoveryPolicy {
+    /// Amount of HAL errors in a row before a recovery is attempted.
+    pub max_consecutive_errors: u32,
+    reset: Box<ResetRoutine>,
+    on_recovery: Option<Box<RecoveryHook>>,
+}
+
+impl RecoveryPolicy {
+    /// `reset` is the same routine used to generate the `ResetToken`, e.g.
+    /// `raspberrypi::reset_lgw`.
+    pub fn new<F>(max_consecutive_errors: u32, reset: F) -> Self
+    where
+        F: Fn() -> std::result::Result<(), Box<dyn std::error::Error>> + Send + 'static,
+    {
+        Self {
+            max_consecutive_errors,
+            reset: Box::new(reset),
+            on_recovery: None,
+        }
+    }
+
+    /// Called after every recovery attempt, successful or not.
+    pub fn on_recovery<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&RecoveryEvent) + Send + 'static,
+    {
+        self.on_recovery = Some(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn reset(&self) -> Result {
+        (self.reset)().map_err(|e| {
+            log::error!("reset routine failed: {}", e);
+            Error::ResetFailed
+        })
+    }
+
+    pub(crate) fn emit(&self, event: &RecoveryEvent) {
+        if let Some(hook) = &self.on_recovery {
+            hook(event);
+        }
+    }
+}
+
+/// Emitted when a `RecoveryPolicy` has restarted the concentrator.
+#[derive(Debug, Clone)]
+pub struct RecoveryEvent {
+    /// Amount of consecutive errors which triggered the recovery.
+    pub errors: u32,
+    /// The error which triggered the recovery.
+    pub last_error: Error,
+    /// Outcome of the stop, reset and start sequence.
+    pub result: Result,
+}
@@ -0,0 +1,108 @@
+//! Spectral scans with the SX1261 next to the SX1302, to survey how busy a channel is before
+//! picking a channel plan.
+//!
+//! The SX1261 samples the RSSI at one frequency `nb_scan` times, and counts how many samples fell
+//! into each of 33 levels. `Concentrator::spectral_scan` runs a scan and returns that histogram.
+use crate::llg;
+use must_units::{Db, Dbm};
+use std::ffi::CString;
+
+/// Levels of the RSSI histogram of a scan, 4 dB apart.
+pub const SPECTRAL_SCAN_LEVELS: usize = llg::LGW_SPECTRAL_SCAN_RESULT_SIZE as usize;
+
+// Values of `lgw_spectral_scan_status_t`
+pub(crate) const SCAN_ON_GOING: llg::lgw_spectral_scan_status_t = 1;
+pub(crate) const SCAN_COMPLETED: llg::lgw_spectral_scan_status_t = 3;
+
+/// The SX1261, which a RAK2287 or a Semtech reference design has for LBT and spectral scans.
+#[derive(Debug, Clone)]
+pub struct Sx1261Conf {
+    /// Path to its SPI device, e.g. `/dev/spidev0.1`.
+    pub spidev_path: CString,
+    /// Board-specific RSSI correction.
+    pub rssi_offset: Db<i8>,
+}
+
+impl From<&Sx1261Conf> for llg::lgw_conf_sx1261_s {
+    fn from(other: &Sx1261Conf) -> Self {
+        let mut spi_path = [0; 64];
+        for (dst, &src) in spi_path
+            .iter_mut()
+            .zip(other.spidev_path.as_bytes_with_nul())
+        {
+            *dst = src as _;
+        }
+        llg::lgw_conf_sx1261_s {
+            enable: true,
+            spi_path,
+            rssi_offset: other.rssi_offset.0,
+            // LBT stays disabled
+            ..unsafe { std::mem::zeroed() }
+        }
+    }
+}
+
+/// How many samples of a scan fell into one RSSI level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RssiBin {
+    pub level: Dbm<i16>,
+    pub count: u16,
+}
+
+/// The RSSI histogram of one frequency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpectralScan {
+    /// Center frequency scanned.
+    pub freq: u32,
+    /// From the lowest level up.
+    pub bins: Vec<RssiBin>,
+}
+
+impl SpectralScan {
+    pub(crate) fn new(freq: u32, levels: &[i16], counts: &[u16]) -> Self {
+        let bins = levels
+            .iter()
+            .zip(counts)
+            .map(|(&level, &count)| RssiBin {
+                level: Dbm(level),
+                count,
+            })
+            .collect();
+        Self { freq, bins }
+    }
+
+    /// Samples taken.
+    pub fn samples(&self) -> u32 {
+        self.bins.iter().map(|bin| u32::from(bin.count)).sum()
+    }
+
+    /// Share of the samples at `threshold` or above, i.e. how much of the time the channel was
+    /// busy, from 0.0 to 1.0.
+    pub fn occupancy(&self, threshold: Dbm<i16>) -> f32 {
+        let samples = self.samples();
+        if samples == 0 {
+            return 0.0;
+        }
+        let busy: u32 = self
+            .bins
+            .iter()
+            .filter(|bin| bin.level >= threshold)
+            .map(|bin| u32::from(bin.count))
+            .sum();
+        busy as f32 / samples as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_occupancy() {
+        let scan = SpectralScan::new(868_100_000, &[-120, -116, -80, -76], &[60, 20, 15, 5]);
+        assert_eq!(scan.samples(), 100);
+        assert_eq!(scan.occupancy(Dbm(-80)), 0.2);
+        assert_eq!(scan.occupancy(Dbm(-130)), 1.0);
+        assert_eq!(SpectralScan::new(0, &[], &[]).occupancy(Dbm(-80)), 0.0);
+    }
+}
@@ -0,0 +1,55 @@
+//! Confirmation that a packet left the antenna, instead of only being accepted by the HAL.
+use crate::{error::Error, types::TxStatus};
+use std::time::{Duration, Instant};
+
+/// When a transmitted packet was handed to the HAL, and when it was on the air.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxReport {
+    /// When the HAL accepted the packet.
+    pub scheduled_at: Instant,
+    /// When the concentrator was done emitting it, to within one poll.
+    pub emitted_at: Instant,
+    /// How long it was seen emitting, to within one poll. Zero for a packet which was done
+    /// before it was seen emitting.
+    pub duration: Duration,
+}
+
+/// Follows the TX status after a `transmit`, until the concentrator is free again.
+///
+/// `Concentrator::transmit_confirmed` polls with a blocking sleep, async code can poll
+/// `transmit_status` itself and feed it to `update`.
+#[derive(Debug, Clone, Copy)]
+pub struct TxTracker {
+    scheduled_at: Instant,
+    emitting_since: Option<Instant>,
+}
+
+impl TxTracker {
+    /// Starts tracking a packet the HAL accepted at `scheduled_at`.
+    pub fn new(scheduled_at: Instant) -> Self {
+        Self {
+            scheduled_at,
+            emitting_since: None,
+        }
+    }
+
+    /// Takes the TX status polled at `now`, and gives the report once the packet is out. Fails
+    /// with `Error::TxAborted` if the modem was stopped, as the packet then didn't go out.
+    pub fn update(&mut self, status: TxStatus, now: Instant) -> Result<Option<TxReport>, Error> {
+        match status {
+            TxStatus::Transmitting => {
+                self.emitting_since.get_or_insert(now);
+                Ok(None)
+            }
+            TxStatus::Scheduled => Ok(None),
+            TxStatus::Free => Ok(Some(TxReport {
+                scheduled_at: self.scheduled_at,
+                emitted_at: now,
+                duration: self
+                    .emitting_since
+                    .map_or(Duration::ZERO, |since| now.duration_since(since)),
+            })),
+            TxStatus::Off => Err(Error::TxAborted),
+        }
+    }
+}
@@ -1,7 +1,22 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{error, llg};
-use std::{convert::TryFrom, fmt, os::raw::c_char, time};
+use must_units::{Db, Dbm};
+use std::{convert::TryFrom, fmt, ops::RangeInclusive, os::raw::c_char, time};
+
+/// Maps the concentrator counter value `count_us` to wall clock time, given the counter value
+/// `reference_cnt` read at `reference`.
+///
+/// The counter wraps roughly every 71 minutes, which `wrapping_sub` handles as long as the packet
+/// is younger than that.
+pub(crate) fn counter_to_system_time(
+    count_us: u32,
+    reference_cnt: u32,
+    reference: time::SystemTime,
+) -> time::SystemTime {
+    let age = time::Duration::from_micros(u64::from(reference_cnt.wrapping_sub(count_us)));
+    reference.checked_sub(age).unwrap_or(reference)
+}
 
 const MOD_LORA: u8 = 0x10;
 const MOD_FSK: u8 = 0x20;
@@ -176,10 +191,36 @@ impl TryFrom<u32> for FrontRadio {
 /// Communication type used by the concentrator.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum ComType {
+    /// Through spidev, e.g. `/dev/spidev0.0` for a RAK2287 on a Pi HAT.
     SPI = 0,
+    /// Through the STM32 of a Corecell USB concentrator, at its serial device, e.g. `/dev/ttyACM0`.
     USB = 1,
 }
 
+impl ComType {
+    /// Whether `path` fits in the HAL's board configuration and looks like a device of this type,
+    /// to catch a spidev path left behind when switching to USB or the other way around.
+    pub(crate) fn check_path(&self, path: &std::ffi::CStr) -> Result<(), error::Error> {
+        let bytes = path.to_bytes();
+        let name = bytes.rsplit(|&b| b == b'/').next().unwrap_or_default();
+        let valid = match self {
+            ComType::SPI => name.starts_with(b"spidev"),
+            // Also e.g. a `/dev/serial/by-id/` link, which keeps its name across replugging
+            ComType::USB => !name.is_empty() && !name.starts_with(b"spidev"),
+        };
+        // The HAL keeps at most 63 bytes and the nul
+        if !valid || bytes.len() >= COM_PATH_SIZE {
+            log::error!("{path:?} is not a valid {self:?} device path");
+            return Err(error::Error::BuilderError(
+                error::BuilderError::InvalidComPath,
+            ));
+        }
+        Ok(())
+    }
+}
+
+const COM_PATH_SIZE: usize = 64;
+
 /// Board-specific configuration.
 #[derive(Debug, Clone)]
 pub struct BoardConf {
@@ -187,7 +228,7 @@ pub struct BoardConf {
     pub lorawan_public: bool,
     /// Index of RF chain which provides clock to concentrator.
     pub clksrc: FrontRadio,
-    /// Path to SPI device.
+    /// Path to the SPI device, or to the serial device of a USB concentrator.
     pub spidev_path: ::std::ffi::CString,
     /// Communication type.
     pub com_type: ComType,
@@ -195,7 +236,7 @@ pub struct BoardConf {
 
 impl From<BoardConf> for llg::lgw_conf_board_s {
     fn from(other: BoardConf) -> Self {
-        let mut com_path = [0u8; 64];
+        let mut com_path = [0u8; COM_PATH_SIZE];
 
         let bytes = other.spidev_path.as_bytes_with_nul();
         for (dst, &src) in com_path.iter_mut().zip(bytes.iter()) {
@@ -211,6 +252,25 @@ impl From<BoardConf> for llg::lgw_conf_board_s {
     }
 }
 
+/// Which packets the SX1302 gives a fine timestamp.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FineTimestampMode {
+    /// Only packets on SF5 to SF10, which keeps all 16 demodulators for receiving.
+    #[default]
+    HighCapacity = 0,
+    /// Packets on all spreading factors, at the cost of fewer demodulators.
+    AllSpreading = 1,
+}
+
+impl From<FineTimestampMode> for llg::lgw_conf_ftime_s {
+    fn from(mode: FineTimestampMode) -> Self {
+        llg::lgw_conf_ftime_s {
+            enable: true,
+            mode: mode as u32,
+        }
+    }
+}
+
 /// Configuration structure for LBT channels.
 #[derive(Debug, Clone)]
 pub struct LBTChanConf {
@@ -226,13 +286,13 @@ pub struct LBTConf {
     /// Enable LBT.
     pub enable: bool,
     /// RSSI threshold for deciding if channel is currently busy.
-    pub rssi_target: i8,
+    pub rssi_target: Dbm<i8>,
     /// Number of LBT chanels.
     pub nb_channel: u8,
     /// Channel map.
     pub channels: [LBTChanConf; 8],
     /// RSSI offset to be applied to SX127x RSSI values.
-    pub rssi_offset: i8,
+    pub rssi_offset: Db<i8>,
 }
 
 /// RF chain configuration.
@@ -245,7 +305,7 @@ pub struct RxRFConf {
     /// Tune this chain to this frequency.
     pub freq: u32,
     /// Board-specific RSSI correction factor.
-    pub rssi_offset: f32,
+    pub rssi_offset: Db<f32>,
     /// FrontRadio model of this chain.
     pub type_: RadioType,
     /// Enable transmission on this chain.
@@ -260,7 +320,7 @@ impl From<&RxRFConf> for llg::lgw_conf_rxrf_s {
         llg::lgw_conf_rxrf_s {
             enable: other.enable,
             freq_hz: other.freq,
-            rssi_offset: other.rssi_offset,
+            rssi_offset: other.rssi_offset.0,
             type_: other.type_ as u32,
             tx_enable: other.tx_enable,
             ..unsafe { std::mem::zeroed() } // ..Default::default()
@@ -312,6 +372,26 @@ pub enum ChannelConf {
     },
 }
 
+/// IF chains of the multirate LoRa channels.
+pub const MULTIRATE_CHAINS: std::ops::Range<u8> = 0..8;
+/// IF chain of the LoRa service channel, a `ChannelConf::Fixed`.
+pub const LORA_STD_CHAIN: u8 = 8;
+/// IF chain of the FSK channel.
+pub const FSK_CHAIN: u8 = 9;
+
+impl ChannelConf {
+    /// Whether IF chain `chain` can receive this channel: multirate ones on `MULTIRATE_CHAINS`,
+    /// the LoRa service channel on `LORA_STD_CHAIN` and FSK on `FSK_CHAIN`.
+    pub fn fits_chain(&self, chain: u8) -> bool {
+        match self {
+            ChannelConf::Disable => chain <= FSK_CHAIN,
+            ChannelConf::Multirate { .. } => MULTIRATE_CHAINS.contains(&chain),
+            ChannelConf::Fixed { .. } => chain == LORA_STD_CHAIN,
+            ChannelConf::FSK { .. } => chain == FSK_CHAIN,
+        }
+    }
+}
+
 #[allow(clippy::needless_update)]
 impl From<&ChannelConf> for llg::lgw_conf_rxif_s {
     fn from(other: &ChannelConf) -> Self {
@@ -409,6 +489,11 @@ pub struct RxPacketLoRa {
     pub crc_check: CRCCheck,
     /// 1uS-resolution timestamp derived from concentrator's internal counter.
     pub timestamp: time::Duration,
+    /// Wall clock time of reception, derived from `timestamp` and the system clock.
+    pub received_at: time::SystemTime,
+    /// Nanosecond-resolution time of reception since the last GPS PPS edge, when fine
+    /// timestamps are enabled and the concentrator timestamped this packet.
+    pub fine_timestamp: Option<time::Duration>,
     /// RF chain this packet was received on.
     pub radio: FrontRadio,
     /// Modulation bandwidth.
@@ -417,14 +502,17 @@ pub struct RxPacketLoRa {
     pub spreading: Spreading,
     /// Error Correcting Code rate of this packet.
     pub coderate: Coderate,
-    /// Average packet RSSI in dB.
-    pub rssi: f32,
-    /// Average packet SNR, in dB.
-    pub snr: f32,
-    /// Minimum packet SNR, in dB.
-    pub snr_min: f32,
-    /// Maximum packet SNR, in dB.
-    pub snr_max: f32,
+    /// Average packet RSSI.
+    pub rssi: Dbm,
+    /// RSSI of this packet on the other antenna, if antenna diversity merged two receptions of
+    /// it. `radio` is the antenna that heard it best.
+    pub other_rssi: Option<Dbm>,
+    /// Average packet SNR.
+    pub snr: Db,
+    /// Minimum packet SNR.
+    pub snr_min: Db,
+    /// Maximum packet SNR.
+    pub snr_max: Db,
     /// CRC that was declared in this packet's payload.
     ///
     /// A CRC-check mismatch indicates that this packet was corrupted in flight.
@@ -444,12 +532,14 @@ pub struct RxPacketFSK {
     pub crc_check: CRCCheck,
     /// 1uS-resolution timestamp derived from concentrator's internal counter.
     pub timestamp: time::Duration,
+    /// Wall clock time of reception, derived from `timestamp` and the system clock.
+    pub received_at: time::SystemTime,
     /// RF chain this packet was received on.
     pub radio: FrontRadio,
     /// Datarate of this packet.
     pub datarate: u32,
-    /// Average packet RSSI, in dB.
-    pub rssi: f32,
+    /// Average packet RSSI.
+    pub rssi: Dbm,
     /// CRC that was declared in this packet's payload.
     ///
     /// A CRC-check mismatch indicates that this packet was corrupted in flight.
@@ -467,6 +557,106 @@ pub enum RxPacket {
     LoRa(RxPacketLoRa),
 }
 
+impl RxPacket {
+    /// Concentrator counter value at reception.
+    pub fn timestamp(&self) -> time::Duration {
+        match self {
+            RxPacket::FSK(pkt) => pkt.timestamp,
+            RxPacket::LoRa(pkt) => pkt.timestamp,
+        }
+    }
+
+    /// Concentrator counter value at reception, as the counter reads it, e.g. to compare with
+    /// `Concentrator::instcnt`.
+    pub fn count_us(&self) -> u32 {
+        self.timestamp().as_micros() as u32
+    }
+
+    /// IF chain the packet was demodulated on, one per channel.
+    pub fn if_chain(&self) -> u8 {
+        match self {
+            RxPacket::FSK(pkt) => pkt.if_chain,
+            RxPacket::LoRa(pkt) => pkt.if_chain,
+        }
+    }
+
+    /// Center frequency of the channel.
+    pub fn freq(&self) -> u32 {
+        match self {
+            RxPacket::FSK(pkt) => pkt.freq,
+            RxPacket::LoRa(pkt) => pkt.freq,
+        }
+    }
+
+    /// Status of the CRC check.
+    pub fn crc_check(&self) -> CRCCheck {
+        match self {
+            RxPacket::FSK(pkt) => pkt.crc_check,
+            RxPacket::LoRa(pkt) => pkt.crc_check,
+        }
+    }
+
+    /// RF chain the packet was received on, the antenna that won with antenna diversity.
+    pub fn radio(&self) -> FrontRadio {
+        match self {
+            RxPacket::FSK(pkt) => pkt.radio,
+            RxPacket::LoRa(pkt) => pkt.radio,
+        }
+    }
+
+    /// Average RSSI of the packet.
+    pub fn rssi(&self) -> Dbm {
+        match self {
+            RxPacket::FSK(pkt) => pkt.rssi,
+            RxPacket::LoRa(pkt) => pkt.rssi,
+        }
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            RxPacket::FSK(pkt) => &pkt.payload,
+            RxPacket::LoRa(pkt) => &pkt.payload,
+        }
+    }
+
+    /// Time of reception since the last GPS PPS edge, in nanosecond resolution. Only LoRa
+    /// packets have one, and only with fine timestamps enabled, see
+    /// `Concentrator::set_fine_timestamp`.
+    pub fn fine_timestamp(&self) -> Option<time::Duration> {
+        match self {
+            RxPacket::FSK(_) => None,
+            RxPacket::LoRa(pkt) => pkt.fine_timestamp,
+        }
+    }
+
+    /// Wall clock time of reception.
+    ///
+    /// This is best-effort: without GPS it is based on the system clock, but it does not include
+    /// the latency of polling the concentrator's FIFO.
+    pub fn received_at(&self) -> time::SystemTime {
+        match self {
+            RxPacket::FSK(pkt) => pkt.received_at,
+            RxPacket::LoRa(pkt) => pkt.received_at,
+        }
+    }
+
+    /// Only LoRa channels are received on both antennas.
+    pub(crate) fn set_other_rssi(&mut self, rssi: Dbm) {
+        if let RxPacket::LoRa(pkt) = self {
+            pkt.other_rssi = Some(rssi);
+        }
+    }
+
+    /// Derives `received_at` from the counter value `reference_cnt` read at `reference`.
+    pub(crate) fn set_reference_time(&mut self, reference_cnt: u32, reference: time::SystemTime) {
+        let received_at = counter_to_system_time(self.count_us(), reference_cnt, reference);
+        match self {
+            RxPacket::FSK(pkt) => pkt.received_at = received_at,
+            RxPacket::LoRa(pkt) => pkt.received_at = received_at,
+        }
+    }
+}
+
 impl TryFrom<&llg::lgw_pkt_rx_s> for RxPacket {
     type Error = error::Error;
     fn try_from(other: &llg::lgw_pkt_rx_s) -> Result<Self, Self::Error> {
@@ -476,15 +666,20 @@ impl TryFrom<&llg::lgw_pkt_rx_s> for RxPacket {
                 if_chain: other.if_chain,
                 crc_check: CRCCheck::try_from(u32::from(other.status))?,
                 timestamp: time::Duration::from_micros(u64::from(other.count_us)),
+                received_at: time::SystemTime::now(),
+                fine_timestamp: other
+                    .ftime_received
+                    .then(|| time::Duration::from_nanos(u64::from(other.ftime))),
                 radio: FrontRadio::try_from(u32::from(other.rf_chain))?,
                 bandwidth: Bandwidth::try_from(u32::from(other.bandwidth))?,
                 spreading: Spreading::try_from(other.datarate)?,
                 coderate: Coderate::try_from(u32::from(other.coderate))?,
 
-                rssi: other.rssis,
-                snr: other.snr,
-                snr_min: other.snr_min,
-                snr_max: other.snr_max,
+                rssi: Dbm(other.rssis),
+                other_rssi: None,
+                snr: Db(other.snr),
+                snr_min: Db(other.snr_min),
+                snr_max: Db(other.snr_max),
                 crc: other.crc,
                 payload: other.payload[..other.size as usize].to_vec(),
             }),
@@ -493,10 +688,11 @@ impl TryFrom<&llg::lgw_pkt_rx_s> for RxPacket {
                 if_chain: other.if_chain,
                 crc_check: CRCCheck::try_from(u32::from(other.status))?,
                 timestamp: time::Duration::from_micros(u64::from(other.count_us)),
+                received_at: time::SystemTime::now(),
                 radio: FrontRadio::try_from(u32::from(other.rf_chain))?,
                 datarate: other.datarate,
 
-                rssi: other.rssis,
+                rssi: Dbm(other.rssis),
                 crc: other.crc,
                 payload: other.payload[..other.size as usize].to_vec(),
             }),
@@ -509,17 +705,30 @@ impl TryFrom<&llg::lgw_pkt_rx_s> for RxPacket {
 }
 
 /// Specifies when to send a `TxPacket`
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TxMode {
     /// Do not delay.
     ///
     /// There are still delays incurred from SPI communication and
     /// analog circuitry settling.
     Immediate,
-    /// Send when concentrator's internal counter equals the time specified.
-    Timestamp(time::Duration),
-    /// Send at specified duration after the next GPS pulse-per-second transition.
-    PPS(time::Duration),
+    /// Send when the concentrator's 1 µs counter reaches `count_us`, e.g. an RX window after a
+    /// received packet, see `TxMode::after`. The counter wraps, so a count is taken to be ahead
+    /// if it is within half the counter's range. `Concentrator::transmit` refuses one which is
+    /// not at least `TX_MIN_LEAD` ahead.
+    Timestamped { count_us: u32 },
+    /// Send on the next GPS pulse-per-second edge, e.g. a Class B beacon. Needs the PPS of a GPS
+    /// receiver wired to the concentrator, without it the packet stays scheduled.
+    OnGps,
+}
+
+impl TxMode {
+    /// `delay` after the packet received at `timestamp`, its `RxPacket::timestamp`
+    pub fn after(timestamp: time::Duration, delay: time::Duration) -> Self {
+        TxMode::Timestamped {
+            count_us: (timestamp.as_micros() as u32).wrapping_add(delay.as_micros() as u32),
+        }
+    }
 }
 
 impl From<TxMode> for (u8, u32) {
@@ -527,8 +736,8 @@ impl From<TxMode> for (u8, u32) {
         use crate::TxMode::*;
         match other {
             Immediate => (0, 0),
-            Timestamp(delay) => (1, delay.as_micros() as u32),
-            PPS(delay) => (2, delay.as_micros() as u32),
+            Timestamped { count_us } => (1, count_us),
+            OnGps => (2, 0),
         }
     }
 }
@@ -543,24 +752,12 @@ pub enum TxPacket {
 }
 
 impl TryFrom<TxPacket> for llg::lgw_pkt_tx_s {
-    // Make sure this matches the Error type expected by your `transmit` function
-    type Error = crate::error::Error;
+    type Error = error::Error;
 
     fn try_from(packet: TxPacket) -> Result<Self, Self::Error> {
         match packet {
-            TxPacket::LoRa(lora_packet) => {
-                // Call the TryFrom<TxPacketLoRa> implementation we made earlier!
-                // If your TryFrom<TxPacketLoRa> returns a different error type (like &'static str),
-                // you will need to map it to your crate's main Error type here.
-                llg::lgw_pkt_tx_s::try_from(lora_packet)
-                    // Example of mapping the error, adjust to fit your actual Error variants
-                    .map_err(|_e| crate::error::Error::from(error::Error::Data))
-            }
-            TxPacket::FSK(_) => {
-                // Immediately reject FSK packets without wasting time on them
-                // Adjust this to match whatever generic error variant you have available
-                Err(crate::error::Error::from(error::Error::Data))
-            }
+            TxPacket::LoRa(pkt) => llg::lgw_pkt_tx_s::try_from(pkt),
+            TxPacket::FSK(pkt) => llg::lgw_pkt_tx_s::try_from(pkt),
         }
     }
 }
@@ -574,8 +771,8 @@ pub struct TxPacketLoRa {
     pub mode: TxMode,
     /// Which radio to transmit on.
     pub radio: FrontRadio,
-    /// TX power (in dBm).
-    pub power: i8,
+    /// TX power.
+    pub power: Dbm<i8>,
     /// Modulation bandwidth.
     pub bandwidth: Bandwidth,
     /// Spreading factor to use with this packet.
@@ -606,13 +803,11 @@ impl TryFrom<TxPacketLoRa> for llg::lgw_pkt_tx_s {
         let mut payload_buf = [0u8; 256];
         payload_buf[..packet.payload.len()].copy_from_slice(&packet.payload);
 
-        let (tx_mode, count_us) = match packet.mode {
-            _ => (0, 0), // TODO: Need more here?
-        };
-        let rf_chain = 0; // TODO: CHeck config?
-        let bandwidth = 0x04; // Check config?
-        let datarate = 7; // TODO: Check config?
-        let coderate = 1; // Check config?
+        let (tx_mode, count_us) = packet.mode.into();
+        let rf_chain = packet.radio as u8;
+        let bandwidth = packet.bandwidth as u8;
+        let datarate = packet.spreading as u32;
+        let coderate = packet.coderate as u8;
 
         Ok(llg::lgw_pkt_tx_s {
             freq_hz: packet.freq,
@@ -620,7 +815,7 @@ impl TryFrom<TxPacketLoRa> for llg::lgw_pkt_tx_s {
             tx_mode,
             count_us,
             rf_chain,
-            rf_power: packet.power,
+            rf_power: packet.power.0,
 
             // Modulation is hardcoded to LoRa (usually 0x10 in Semtech libs)
             // modulation: llg::MOD_LORA as u8,
@@ -646,7 +841,7 @@ impl TryFrom<TxPacketLoRa> for llg::lgw_pkt_tx_s {
     }
 }
 
-/// A transmittable LoRa packet.
+/// A transmittable FSK packet.
 #[derive(Debug, Clone)]
 pub struct TxPacketFSK {
     /// Center frequency to transmit on.
@@ -655,8 +850,8 @@ pub struct TxPacketFSK {
     pub mode: TxMode,
     /// Which radio to transmit on.
     pub radio: FrontRadio,
-    /// TX power (in dBm).
-    pub power: i8,
+    /// TX power.
+    pub power: Dbm<i8>,
     /// Datarate in bits/second.
     pub datarate: u32,
     /// Frequency deviation, in kHz.
@@ -672,12 +867,55 @@ pub struct TxPacketFSK {
     /// Arbitrary user-defined payload to transmit.
     pub payload: Vec<u8>,
 }
+
+/// Lowest and highest FSK datarate the concentrator sends, in bits/second.
+pub const FSK_DATARATES: RangeInclusive<u32> = 500..=250_000;
+
+impl TryFrom<TxPacketFSK> for llg::lgw_pkt_tx_s {
+    type Error = error::Error;
+
+    fn try_from(packet: TxPacketFSK) -> Result<Self, Self::Error> {
+        if packet.payload.len() > 255 {
+            return Err(error::Error::Size);
+        }
+        if !FSK_DATARATES.contains(&packet.datarate) || packet.deviation == 0 {
+            return Err(error::Error::Data);
+        }
+        let mut payload_buf = [0u8; 256];
+        payload_buf[..packet.payload.len()].copy_from_slice(&packet.payload);
+
+        let (tx_mode, count_us) = packet.mode.into();
+        Ok(llg::lgw_pkt_tx_s {
+            freq_hz: packet.freq,
+            freq_offset: 0,
+            tx_mode,
+            count_us,
+            rf_chain: packet.radio as u8,
+            rf_power: packet.power.0,
+            modulation: MOD_FSK,
+            // Bandwidth and coderate are LoRa only, FSK is shaped by the datarate and deviation
+            bandwidth: Bandwidth::Undefined as u8,
+            datarate: packet.datarate,
+            coderate: Coderate::Undefined as u8,
+            invert_pol: false,
+            f_dev: packet.deviation,
+            // In bytes, the HAL's standard FSK preamble is 5
+            preamble: packet.preamble.unwrap_or(5),
+            no_crc: packet.omit_crc,
+            // The HAL sends FSK packets without their length byte when told there is no header
+            no_header: packet.fixed_len,
+            size: packet.payload.len() as u16,
+            payload: payload_buf,
+        })
+    }
+}
+
 /// Structure containing all gains of Tx chain.
 #[repr(C)]
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct TxGain {
-    /// Measured TX power at the board connector (in dBm).
-    pub rf_power: i8,
+    /// Measured TX power at the board connector.
+    pub rf_power: Dbm<i8>,
     /// Control of the digital gain of SX1301 (2 bits).
     pub dig_gain: u8,
     /// Control of the external PA (SX1301 I/O) (2 bits).
@@ -719,6 +957,14 @@ pub enum TxStatus {
     Transmitting = 4,
 }
 
+impl TxStatus {
+    /// Whether the concentrator has a packet scheduled or is emitting one, so it can't take
+    /// another yet.
+    pub fn is_busy(self) -> bool {
+        matches!(self, TxStatus::Scheduled | TxStatus::Transmitting)
+    }
+}
+
 impl TryFrom<u8> for TxStatus {
     type Error = error::Error;
     fn try_from(other: u8) -> Result<Self, error::Error> {
@@ -0,0 +1,56 @@
+//! Fixtures shared by the tests against the mock HAL, each test uses what it needs.
+#![allow(dead_code)]
+use loragw::{
+    BoardConf, Builder, ComType, Concentrator, Dbm, FrontRadio, ResetToken, Running, TxGain,
+    TxMode, TxPacket, TxPacketLoRa, mock,
+};
+use std::{
+    ffi::CString,
+    sync::{Mutex, MutexGuard},
+};
+
+// Only one concentrator can be open at a time, and the mock state is global
+pub static TEST_MUTEX: Mutex<()> = Mutex::new(());
+
+/// Has the mock to the calling test until dropped, also after another test panicked with it
+pub fn lock() -> MutexGuard<'static, ()> {
+    TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A concentrator on SPI with `gains` to configure further, the mock left as the test set it
+pub fn builder(gains: &[TxGain]) -> Concentrator<Builder> {
+    let token = unsafe { ResetToken::bypass() };
+    Concentrator::open(&token)
+        .unwrap()
+        .set_config_board(BoardConf {
+            lorawan_public: false,
+            clksrc: FrontRadio::R0,
+            spidev_path: CString::new("/dev/spidev0.0").unwrap(),
+            com_type: ComType::SPI,
+        })
+        .set_config_tx_gains(gains)
+}
+
+/// A running concentrator on SPI with `gains`, on a reset mock
+pub fn start_concentrator(gains: &[TxGain]) -> Concentrator<Running> {
+    mock::reset();
+    builder(gains).connect().unwrap().start().unwrap()
+}
+
+/// Three bytes on SF7 BW125, sent at once
+pub fn tx_packet() -> TxPacket {
+    TxPacket::LoRa(TxPacketLoRa {
+        freq: 868_100_000,
+        mode: TxMode::Immediate,
+        radio: FrontRadio::R0,
+        power: Dbm(14),
+        bandwidth: loragw::Bandwidth::BW125kHz,
+        spreading: loragw::Spreading::SF7,
+        coderate: loragw::Coderate::Cr4_5,
+        invert_polarity: false,
+        preamble: None,
+        omit_crc: false,
+        implicit_header: false,
+        payload: vec![1, 2, 3],
+    })
+}
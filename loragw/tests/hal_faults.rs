@@ -0,0 +1,222 @@
+//! Fault injection against the mock HAL, checking that invalid HAL return values surface as
+//! errors instead of panics, and that the concentrator keeps working afterwards. Also lost,
+//! corrupted and late packets.
+use loragw::{BuilderError, Error, RecoveryPolicy, RxPacket, TxGain, mock};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+mod common;
+
+#[test]
+fn invalid_receive_return_is_an_error() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+
+    mock::inject_return("lgw_receive", -7);
+    assert!(matches!(conc.receive(), Err(Error::HalInvalid(-7))));
+
+    // The next call goes through to the FIFO again
+    mock::push_rx(mock::lora_rx_packet(&[0xAA]));
+    let pkts = conc.receive().unwrap().expect("packet should be received");
+    assert_eq!(pkts.len(), 1);
+}
+
+#[test]
+fn receive_loop_survives_faults() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+
+    for i in 0..5u8 {
+        mock::push_rx(mock::lora_rx_packet(&[i]));
+    }
+    mock::inject_return("lgw_receive", -1);
+    mock::inject_return("lgw_receive", 1234);
+    mock::inject_return("lgw_receive", -2);
+
+    // Poll like the gateway does, logging errors and carrying on
+    let mut received: Vec<u8> = Vec::new();
+    let mut errors = 0;
+    for _ in 0..10 {
+        match conc.receive() {
+            Ok(Some(pkts)) => received.extend(pkts.iter().filter_map(|pkt| match pkt {
+                RxPacket::LoRa(pkt) => pkt.payload.first().copied(),
+                RxPacket::FSK(_) => None,
+            })),
+            Ok(None) => {}
+            Err(_) => errors += 1,
+        }
+    }
+    assert_eq!(errors, 3);
+    assert_eq!(received, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn transmit_recovers_after_invalid_return() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+
+    mock::inject_return("lgw_send", -3);
+    assert_eq!(
+        conc.transmit(common::tx_packet()),
+        Err(Error::HalInvalid(-3))
+    );
+    assert!(mock::take_sent().is_empty());
+
+    conc.transmit(common::tx_packet()).unwrap();
+    assert_eq!(mock::take_sent().len(), 1);
+}
+
+#[test]
+fn recovery_policy_restarts_after_consecutive_errors() {
+    let _lock = common::lock();
+    let resets = Arc::new(AtomicU32::new(0));
+    let recoveries = Arc::new(AtomicU32::new(0));
+    let policy = {
+        let resets = resets.clone();
+        let recoveries = recoveries.clone();
+        RecoveryPolicy::new(3, move || {
+            resets.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .on_recovery(move |event| {
+            assert_eq!(event.errors, 3);
+            assert!(event.result.is_ok());
+            recoveries.fetch_add(1, Ordering::SeqCst);
+        })
+    };
+    mock::reset();
+    let conc = common::builder(&[TxGain::default()])
+        .set_recovery_policy(policy)
+        .connect()
+        .unwrap()
+        .start()
+        .unwrap();
+
+    // Two errors, then a success resets the count
+    mock::inject_return("lgw_receive", -1);
+    mock::inject_return("lgw_receive", -1);
+    assert!(conc.receive().is_err());
+    assert!(conc.receive().is_err());
+    assert!(conc.receive().is_ok());
+    assert_eq!(resets.load(Ordering::SeqCst), 0);
+
+    // Three in a row triggers a restart, transparently to the caller
+    for _ in 0..3 {
+        mock::inject_return("lgw_receive", -1);
+    }
+    for _ in 0..3 {
+        assert_eq!(conc.receive().unwrap_err(), Error::HAL);
+    }
+    assert_eq!(resets.load(Ordering::SeqCst), 1);
+    assert_eq!(recoveries.load(Ordering::SeqCst), 1);
+
+    mock::push_rx(mock::lora_rx_packet(&[0x01]));
+    assert_eq!(conc.receive().unwrap().map(|pkts| pkts.len()), Some(1));
+}
+
+#[test]
+fn eui_is_read_from_the_chip() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+    assert_eq!(conc.eui(), Ok(mock::MOCK_EUI));
+
+    mock::set_eui(0x0016_C001_FF10_ABCD);
+    assert_eq!(conc.eui(), Ok(0x0016_C001_FF10_ABCD));
+
+    mock::inject_return("lgw_get_eui", -1);
+    assert_eq!(conc.eui(), Err(Error::HAL));
+    assert_eq!(conc.eui(), Ok(0x0016_C001_FF10_ABCD));
+}
+
+#[test]
+fn eui_is_read_before_start() {
+    let _lock = common::lock();
+    mock::reset();
+    let conc = common::builder(&[TxGain::default()]);
+    assert_eq!(
+        conc.eui(),
+        Err(Error::BuilderError(BuilderError::NotConnected))
+    );
+
+    let conc = conc.connect().unwrap();
+    assert_eq!(conc.eui(), Ok(mock::MOCK_EUI));
+    conc.start().unwrap();
+}
+
+#[test]
+fn temperature_is_read_from_the_board() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+    assert_eq!(conc.temperature(), Ok(mock::MOCK_TEMPERATURE));
+
+    mock::set_temperature(61.5);
+    assert_eq!(conc.temperature(), Ok(61.5));
+
+    mock::inject_return("lgw_get_temperature", -1);
+    assert_eq!(conc.temperature(), Err(Error::HAL));
+}
+
+#[test]
+fn nth_call_fails() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+
+    // The third packet of a burst fails
+    mock::inject_return_on("lgw_send", 3, -1);
+    let results: Vec<_> = (0..4).map(|_| conc.transmit(common::tx_packet())).collect();
+    assert_eq!(results, [Ok(()), Ok(()), Err(Error::HAL), Ok(())]);
+    assert_eq!(mock::take_sent().len(), 3);
+}
+
+#[test]
+fn dropped_and_corrupted_packets() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+
+    for i in 0..5u8 {
+        mock::push_rx(mock::lora_rx_packet(&[i, 0x10]));
+    }
+    mock::drop_rx(2);
+    mock::corrupt_rx(2, 1, 0xFF);
+    let payloads: Vec<Vec<u8>> = conc
+        .receive()
+        .unwrap()
+        .unwrap()
+        .iter()
+        .map(|pkt| pkt.payload().to_vec())
+        .collect();
+    assert_eq!(payloads, [[2, 0xEF], [3, 0xEF], [4, 0x10]]);
+}
+
+#[test]
+fn delayed_transmit_is_confirmed_late() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+    let poll = Duration::from_millis(1);
+
+    mock::set_tx_delay(Duration::from_millis(30));
+    let report = conc
+        .transmit_confirmed(common::tx_packet(), poll, Duration::from_secs(1))
+        .unwrap();
+    assert!(report.duration >= Duration::from_millis(20));
+
+    // The watchdog gives up on a packet which doesn't leave in time
+    mock::set_tx_delay(Duration::from_secs(10));
+    assert_eq!(
+        conc.transmit_confirmed(common::tx_packet(), poll, Duration::from_millis(20)),
+        Err(Error::Timeout)
+    );
+}
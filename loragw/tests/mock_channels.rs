@@ -0,0 +1,153 @@
+//! Configuring the LoRa service and FSK channels next to the multirate ones.
+use loragw::{
+    Bandwidth, BuilderError, ChannelConf, Concentrator, Error, FSK_CHAIN, FrontRadio,
+    LORA_STD_CHAIN, ResetToken, Spreading, cfg::Config, mock,
+};
+
+mod common;
+
+/// The 8 multirate, the LoRa service and the FSK channel of EU868, and a TX gain to start with
+const EU868: &str = r#"
+[board]
+lorawan_public = true
+clksrc = 0
+com_type = "SPI"
+spidev_path = "/dev/spidev0.0"
+
+[[radios]]
+id = 0
+type = 'SX1250'
+freq = 867_500_000
+rssi_offset = -215.4
+tx_enable = true
+
+[[radios]]
+id = 1
+type = 'SX1250'
+freq = 868_500_000
+rssi_offset = -215.4
+tx_enable = false
+
+[[multirate_channels]]
+radio = 1
+if = -400_000
+
+[[multirate_channels]]
+radio = 1
+if = -200_000
+
+[[multirate_channels]]
+radio = 1
+if = 0
+
+[[multirate_channels]]
+radio = 0
+if = -400_000
+
+[[multirate_channels]]
+radio = 0
+if = -200_000
+
+[[multirate_channels]]
+radio = 0
+if = 0
+
+[[multirate_channels]]
+radio = 0
+if = 200_000
+
+[[multirate_channels]]
+radio = 0
+if = 400_000
+
+[lora_std_channel]
+radio = 1
+if = -200_000
+bandwidth = 250_000
+spread_factor = 7
+
+[fsk_channel]
+radio = 1
+if = 300_000
+bandwidth = 125_000
+datarate = 50_000
+
+[[tx_gains]]
+dbm = 12
+pa = 1
+mix = 14
+dig = 2
+"#;
+
+#[test]
+fn eu868_plan_from_config() {
+    let _lock = common::lock();
+    mock::reset();
+    let conf = Config::from_str(EU868).unwrap();
+    let token = unsafe { ResetToken::bypass() };
+    let _conc = Concentrator::open(&token)
+        .unwrap()
+        .set_config(&conf)
+        .unwrap()
+        .connect()
+        .unwrap()
+        .start()
+        .unwrap();
+
+    let confs = mock::if_confs();
+    let chains: Vec<u8> = confs.iter().map(|(chain, _)| *chain).collect();
+    assert_eq!(chains, (0..=9).collect::<Vec<u8>>());
+    let (_, std) = &confs[usize::from(LORA_STD_CHAIN)];
+    assert_eq!((std.freq_hz, std.datarate), (-200_000, 7));
+    let (_, fsk) = &confs[usize::from(FSK_CHAIN)];
+    assert_eq!((fsk.freq_hz, fsk.datarate), (300_000, 50_000));
+}
+
+#[test]
+fn invalid_channels_are_refused() {
+    // Only 125, 250 and 500 kHz, refused when applied
+    let conf = Config::from_str(&EU868.replace("bandwidth = 250_000", "bandwidth = 200_000"));
+    let token = unsafe { ResetToken::bypass() };
+    {
+        let _lock = common::lock();
+        let res = Concentrator::open(&token)
+            .unwrap()
+            .set_config(&conf.unwrap());
+        assert_eq!(res.err(), Some(Error::Data));
+    }
+
+    // The service channel only has its own IF chain
+    let _lock = common::lock();
+    mock::reset();
+    let std = ChannelConf::Fixed {
+        radio: FrontRadio::R1,
+        freq: -200_000,
+        bandwidth: Bandwidth::BW250kHz,
+        spreading: Spreading::SF7,
+    };
+    let res = Concentrator::open(&token)
+        .unwrap()
+        .set_config(&Config::from_str(EU868).unwrap())
+        .unwrap()
+        .add_config_channel(3, std.clone())
+        .connect()
+        .unwrap()
+        .start();
+    assert_eq!(
+        res.err(),
+        Some(Error::BuilderError(BuilderError::InvalidChain))
+    );
+
+    // And replacing it keeps a single one
+    mock::reset();
+    let _conc = Concentrator::open(&token)
+        .unwrap()
+        .set_config(&Config::from_str(EU868).unwrap())
+        .unwrap()
+        .set_config_lora_std(std)
+        .connect()
+        .unwrap()
+        .start()
+        .unwrap();
+    assert_eq!(mock::if_confs().len(), 10);
+}
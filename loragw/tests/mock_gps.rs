@@ -0,0 +1,113 @@
+//! Reading a GPS receiver through the mock HAL, from a file standing in for its serial port.
+use loragw::{
+    TxGain,
+    gps::{Gps, GpsEvent, GpsTime, Position},
+    mock,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod common;
+
+fn time() -> GpsTime {
+    GpsTime {
+        utc: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        gps: Duration::from_secs(1_384_035_218),
+    }
+}
+const POSITION: Position = Position {
+    latitude: 55.68,
+    longitude: 12.57,
+    altitude: 14,
+};
+
+/// A NAV-TIMEGPS message, whose payload the mock doesn't look at
+fn nav_timegps() -> Vec<u8> {
+    let mut msg = vec![0xB5, 0x62, 0x01, 0x20, 16, 0];
+    msg.extend_from_slice(&[0; 16]);
+    msg.extend_from_slice(&[0, 0]);
+    msg
+}
+
+fn port_with(name: &str, bytes: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("loragw-{name}-{}", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn reads_time_and_position() {
+    let _lock = common::lock();
+    mock::reset();
+    mock::set_gps_fix(time(), POSITION);
+    let mut bytes = b"noise$GPRMC,123519,A,5540.800,N,01234.200,E,,,161026,,*6A\r\n".to_vec();
+    bytes.extend(nav_timegps());
+    let mut gps = Gps::enable(&port_with("read", &bytes)).unwrap();
+
+    let events = gps.read().unwrap();
+    assert_eq!(
+        events,
+        [
+            GpsEvent::Position {
+                position: POSITION,
+                error: Position {
+                    latitude: 0.0,
+                    longitude: 0.0,
+                    altitude: 0,
+                },
+            },
+            GpsEvent::Time(time()),
+        ]
+    );
+}
+
+#[test]
+fn messages_split_across_reads_are_kept() {
+    let _lock = common::lock();
+    mock::reset();
+    mock::set_gps_fix(time(), POSITION);
+    let mut gps = Gps::enable(&port_with("split", &[])).unwrap();
+
+    let msg = nav_timegps();
+    assert!(gps.parse(&msg[..10]).is_empty());
+    assert_eq!(gps.parse(&msg[10..]), [GpsEvent::Time(time())]);
+    // Without a fix, sentences give nothing
+    mock::reset();
+    assert!(
+        gps.parse(b"$GPGGA,123519,,,,,0,00,,,M,,M,,*66\r\n")
+            .is_empty()
+    );
+}
+
+#[test]
+fn only_one_gps_at_a_time() {
+    let _lock = common::lock();
+    mock::reset();
+    let path = port_with("busy", &[]);
+    let gps = Gps::enable(&path).unwrap();
+    assert!(matches!(Gps::enable(&path), Err(loragw::Error::Busy)));
+    drop(gps);
+    assert!(Gps::enable(&path).is_ok());
+}
+
+#[test]
+fn sync_ties_the_counter_to_utc() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+    let mut gps = Gps::enable(&port_with("sync", &[])).unwrap();
+    assert_eq!(gps.utc_of(0), None);
+
+    mock::set_trigcnt(5_000_000);
+    let reference = gps.sync(&conc, time()).unwrap();
+    assert_eq!(reference.count_us, 5_000_000);
+    assert_eq!(reference.utc, time().utc);
+    assert_eq!(reference.gps, time().gps);
+
+    // A packet 1.5 s after the edge
+    let utc: SystemTime = gps.utc_of(6_500_000).unwrap();
+    assert_eq!(utc, time().utc + Duration::from_millis(1500));
+
+    mock::inject_return("lgw_gps_sync", -1);
+    assert!(gps.sync(&conc, time()).is_err());
+    // The last good reference stays
+    assert_eq!(gps.utc_of(5_000_000), Some(time().utc));
+}
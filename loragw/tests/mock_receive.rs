@@ -0,0 +1,137 @@
+//! Receiving through the mock HAL.
+use loragw::{
+    BuilderError, Concentrator, Dbm, Diversity, Error, FineTimestampMode, FrontRadio, Running,
+    RxPacket, RxRFConf, TxGain, mock,
+};
+use std::time::{Duration, SystemTime};
+
+mod common;
+
+#[test]
+fn received_at_accounts_for_counter_age() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+
+    // The packet was received 2 seconds before we polled the FIFO
+    let mut pkt = mock::lora_rx_packet(&[0x01]);
+    pkt.count_us = 1_000_000;
+    mock::push_rx(pkt);
+    mock::set_instcnt(3_000_000);
+
+    let before = SystemTime::now();
+    let pkts = conc.receive().unwrap().unwrap();
+    let age = before.duration_since(pkts[0].received_at()).unwrap();
+    assert!(age >= Duration::from_millis(1900) && age <= Duration::from_millis(2100));
+}
+
+#[test]
+fn received_at_handles_counter_wrap() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+
+    let mut pkt = mock::lora_rx_packet(&[0x01]);
+    pkt.count_us = u32::MAX - 499_999;
+    mock::push_rx(pkt);
+    mock::set_instcnt(500_000);
+
+    let before = SystemTime::now();
+    let pkts = conc.receive().unwrap().unwrap();
+    let age = before.duration_since(pkts[0].received_at()).unwrap();
+    assert!(age >= Duration::from_millis(900) && age <= Duration::from_millis(1100));
+}
+
+#[test]
+fn fine_timestamps_when_enabled() {
+    let _lock = common::lock();
+    mock::reset();
+    let conc = common::builder(&[TxGain::default()])
+        .set_fine_timestamp(FineTimestampMode::AllSpreading)
+        .connect()
+        .unwrap()
+        .start()
+        .unwrap();
+    assert_eq!(
+        mock::fine_timestamp_mode(),
+        Some(FineTimestampMode::AllSpreading)
+    );
+
+    let mut pkt = mock::lora_rx_packet(&[0x01]);
+    pkt.ftime_received = true;
+    pkt.ftime = 123_456_789;
+    mock::push_rx(pkt);
+    // One the concentrator couldn't timestamp
+    mock::push_rx(mock::lora_rx_packet(&[0x02]));
+
+    let pkts = conc.receive().unwrap().unwrap();
+    assert_eq!(
+        pkts[0].fine_timestamp(),
+        Some(Duration::from_nanos(123_456_789))
+    );
+    assert_eq!(pkts[1].fine_timestamp(), None);
+}
+
+#[test]
+fn no_fine_timestamps_by_default() {
+    let _lock = common::lock();
+    let _conc = common::start_concentrator(&[TxGain::default()]);
+    assert_eq!(mock::fine_timestamp_mode(), None);
+}
+
+fn diversity_concentrator(radio_1_freq: u32) -> loragw::Result<Concentrator<Running>> {
+    mock::reset();
+    let radio = |radio, freq| RxRFConf {
+        radio,
+        enable: true,
+        freq,
+        ..Default::default()
+    };
+    common::builder(&[TxGain::default()])
+        .set_rx_rfs(vec![
+            radio(FrontRadio::R0, 868_100_000),
+            radio(FrontRadio::R1, radio_1_freq),
+        ])
+        .set_config_channels(Diversity::channels(&[0])?)
+        .set_antenna_diversity(Diversity::default())
+        .connect()?
+        .start()
+}
+
+#[test]
+fn diversity_keeps_the_best_copy() {
+    let _lock = common::lock();
+    let conc = diversity_concentrator(868_100_000).unwrap();
+
+    // Heard better on the second antenna
+    let mut weak = mock::lora_rx_packet(&[0x01]);
+    weak.count_us = 1_000;
+    weak.rssis = -90.0;
+    let mut strong = weak;
+    strong.rf_chain = 1;
+    strong.count_us = 1_002;
+    strong.rssis = -70.0;
+    mock::push_rx(weak);
+    mock::push_rx(strong);
+    // Another packet, only heard on the first
+    let mut other = mock::lora_rx_packet(&[0x02]);
+    other.count_us = 1_001;
+    mock::push_rx(other);
+
+    let pkts = conc.receive().unwrap().unwrap();
+    assert_eq!(pkts.len(), 2);
+    let RxPacket::LoRa(best) = &pkts[0] else {
+        panic!("not LoRa");
+    };
+    assert!(matches!(best.radio, FrontRadio::R1));
+    assert_eq!((best.rssi, best.other_rssi), (Dbm(-70.0), Some(Dbm(-90.0))));
+    assert_eq!(pkts[1].payload(), [0x02]);
+    assert!(matches!(&pkts[1], RxPacket::LoRa(pkt) if pkt.other_rssi.is_none()));
+}
+
+#[test]
+fn diversity_needs_radios_on_one_frequency() {
+    let _lock = common::lock();
+    assert_eq!(
+        diversity_concentrator(868_500_000).err(),
+        Some(Error::BuilderError(BuilderError::InvalidRxRFConf))
+    );
+}
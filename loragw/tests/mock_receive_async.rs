@@ -0,0 +1,65 @@
+//! Receiving through the mock HAL with `receive_async`.
+use loragw::{Concentrator, PollInterval, RX_FIFO_SIZE, Running, TxGain, mock};
+use std::{thread, time::Duration};
+
+mod common;
+
+/// Runs `test` with the concentrator to itself, the lock isn't held across awaits
+fn run<F: Future<Output = ()>>(test: impl FnOnce(Concentrator<Running>) -> F) {
+    let _lock = common::lock();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    runtime.block_on(test(common::start_concentrator(&[TxGain::default()])));
+}
+
+#[test]
+fn drains_the_whole_fifo() {
+    run(|mut conc| async move {
+        for _ in 0..RX_FIFO_SIZE + 3 {
+            mock::push_rx(mock::lora_rx_packet(&[0x01]));
+        }
+
+        let mut out = Vec::new();
+        assert_eq!(
+            conc.receive_async(&mut out).await.unwrap(),
+            RX_FIFO_SIZE + 3
+        );
+        assert_eq!(out.len(), RX_FIFO_SIZE + 3);
+    });
+}
+
+#[test]
+fn waits_for_packets_and_can_be_cancelled() {
+    run(|mut conc| async move {
+        conc.set_poll_interval(PollInterval::fixed(Duration::from_millis(1)));
+
+        // Nothing arrives, so it is still waiting when the timeout fires
+        let mut out = Vec::new();
+        let waited = tokio::time::timeout(Duration::from_millis(20), conc.receive_async(&mut out));
+        assert!(waited.await.is_err());
+        assert!(out.is_empty());
+
+        let pusher = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(10));
+            mock::push_rx(mock::lora_rx_packet(&[0x02]));
+        });
+        assert_eq!(conc.receive_async(&mut out).await.unwrap(), 1);
+        assert_eq!(out[0].payload(), [0x02]);
+        pusher.join().unwrap();
+    });
+}
+
+#[test]
+fn runs_in_a_spawned_task() {
+    // tokio::spawn needs a Send future, which the concentrator not being Sync mustn't prevent
+    run(|mut conc| async move {
+        mock::push_rx(mock::lora_rx_packet(&[0x03]));
+        let received = tokio::spawn(async move {
+            let mut out = Vec::new();
+            conc.receive_async(&mut out).await.map(|_| out)
+        });
+        assert_eq!(received.await.unwrap().unwrap()[0].payload(), [0x03]);
+    });
+}
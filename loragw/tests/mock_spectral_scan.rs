@@ -0,0 +1,52 @@
+//! Spectral scans through the mock HAL.
+use loragw::{
+    BuilderError, Concentrator, Db, Dbm, Error, RssiBin, Running, Sx1261Conf, TxGain, mock,
+};
+use std::ffi::CString;
+
+mod common;
+
+fn start_concentrator(sx1261: Option<Sx1261Conf>) -> Concentrator<Running> {
+    mock::reset();
+    let conc = common::builder(&[TxGain::default()]);
+    let conc = match sx1261 {
+        Some(sx1261) => conc.set_config_sx1261(sx1261),
+        None => conc,
+    };
+    conc.connect().unwrap().start().unwrap()
+}
+
+#[test]
+fn spectral_scan_returns_the_histogram() {
+    let _lock = common::lock();
+    let conc = start_concentrator(Some(Sx1261Conf {
+        spidev_path: CString::new("/dev/spidev0.1").unwrap(),
+        rssi_offset: Db(0),
+    }));
+    mock::set_spectral_scan(&[-120, -116, -112], &[150, 40, 10], 3);
+
+    let scan = conc.spectral_scan(868_100_000, 200).unwrap();
+    assert_eq!(mock::spectral_scan_started(), Some((868_100_000, 200)));
+    assert_eq!(scan.freq, 868_100_000);
+    assert_eq!(scan.bins.len(), loragw::SPECTRAL_SCAN_LEVELS);
+    assert_eq!(
+        scan.bins[1],
+        RssiBin {
+            level: Dbm(-116),
+            count: 40
+        }
+    );
+    assert_eq!(scan.samples(), 200);
+    assert_eq!(scan.occupancy(Dbm(-116)), 0.25);
+}
+
+#[test]
+fn spectral_scan_needs_the_sx1261() {
+    let _lock = common::lock();
+    let conc = start_concentrator(None);
+    assert_eq!(
+        conc.spectral_scan(868_100_000, 200),
+        Err(Error::BuilderError(BuilderError::MissingSx1261))
+    );
+    assert_eq!(mock::spectral_scan_started(), None);
+}
@@ -0,0 +1,188 @@
+//! Transmitting through the mock HAL, until the packet has left the antenna.
+use loragw::{
+    Dbm, Error, FrontRadio, TX_POLL, TxGain, TxMode, TxPacket, TxPacketFSK, TxStatus, mock,
+};
+use std::time::Duration;
+
+mod common;
+
+const POLL: Duration = Duration::from_millis(2);
+
+#[test]
+fn transmit_confirmed_waits_for_emission() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+
+    mock::queue_tx_statuses(&[
+        TxStatus::Scheduled,
+        TxStatus::Transmitting,
+        TxStatus::Transmitting,
+        TxStatus::Transmitting,
+    ]);
+    let report = conc
+        .transmit_confirmed(common::tx_packet(), POLL, Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(mock::take_sent().len(), 1);
+    // Seen emitting on three polls, so for at least the two sleeps between them
+    assert!(report.duration >= 2 * POLL, "{:?}", report.duration);
+    assert!(report.emitted_at - report.scheduled_at >= 4 * POLL);
+}
+
+#[test]
+fn transmit_confirmed_times_out() {
+    let _lock = common::lock();
+    let gains = [TxGain::default()];
+    let conc = common::start_concentrator(&gains);
+
+    // Scheduled for far later
+    mock::queue_tx_statuses(&[TxStatus::Scheduled; 100]);
+    assert_eq!(
+        conc.transmit_confirmed(common::tx_packet(), POLL, 5 * POLL),
+        Err(Error::Timeout)
+    );
+}
+
+#[test]
+fn transmit_confirmed_fails_when_the_modem_stops() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+
+    // Stopped while the packet waited, so it never went out
+    mock::queue_tx_statuses(&[TxStatus::Scheduled, TxStatus::Off]);
+    assert_eq!(
+        conc.transmit_confirmed(common::tx_packet(), POLL, Duration::from_secs(1)),
+        Err(Error::TxAborted)
+    );
+}
+
+#[test]
+fn transmit_blocking_waits_for_the_last_packet() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+    mock::set_tx_delay(Duration::from_millis(30));
+
+    let started = std::time::Instant::now();
+    conc.transmit(common::tx_packet()).unwrap();
+    let report = conc
+        .transmit_blocking(common::tx_packet(), Duration::from_secs(1))
+        .unwrap();
+    // Sent once the first was out, and returned once it was out too
+    assert!(report.scheduled_at - started >= Duration::from_millis(30));
+    assert!(report.emitted_at - started >= Duration::from_millis(60));
+    assert_eq!(mock::take_sent().len(), 2);
+}
+
+#[test]
+fn transmit_blocking_times_out() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+
+    // The last packet is scheduled for far later, so this one isn't sent at all
+    mock::queue_tx_statuses(&[TxStatus::Scheduled; 100]);
+    assert_eq!(
+        conc.transmit_blocking(common::tx_packet(), 5 * TX_POLL),
+        Err(Error::Timeout)
+    );
+    assert!(mock::take_sent().is_empty());
+}
+
+#[test]
+fn downlink_is_scheduled_from_the_counter() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+    mock::set_instcnt(u32::MAX - 500_000);
+    mock::set_trigcnt(1_234);
+    assert_eq!(conc.instcnt(), Ok(u32::MAX - 500_000));
+    assert_eq!(conc.trigcnt(), Ok(1_234));
+
+    // One second from now, across the wrap of the counter
+    let at = conc.instcnt().unwrap().wrapping_add(1_000_000);
+    let TxPacket::LoRa(mut pkt) = common::tx_packet() else {
+        unreachable!()
+    };
+    pkt.mode = TxMode::Timestamped { count_us: at };
+    conc.transmit(TxPacket::LoRa(pkt)).unwrap();
+    assert_eq!(mock::take_sent()[0].count_us, 499_999);
+
+    mock::inject_return("lgw_get_instcnt", -1);
+    assert_eq!(conc.instcnt(), Err(Error::HAL));
+}
+
+#[test]
+fn reply_is_timed_from_the_received_packet() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+    let mut rx = mock::lora_rx_packet(&[0x01]);
+    rx.count_us = u32::MAX - 200_000;
+    mock::push_rx(rx);
+    let received = conc.receive().unwrap().unwrap();
+    assert_eq!(received[0].count_us(), u32::MAX - 200_000);
+    mock::set_instcnt(u32::MAX - 100_000);
+
+    // One second after the packet, across the wrap of the counter
+    let TxPacket::LoRa(mut pkt) = common::tx_packet() else {
+        unreachable!()
+    };
+    pkt.mode = TxMode::after(received[0].timestamp(), Duration::from_secs(1));
+    assert_eq!(pkt.mode, TxMode::Timestamped { count_us: 799_999 });
+    conc.transmit(TxPacket::LoRa(pkt.clone())).unwrap();
+    let sent = mock::take_sent();
+    assert_eq!((sent[0].tx_mode, sent[0].count_us), (1, 799_999));
+
+    // Passed, or too close to load the packet in time
+    for now in [900_000, 799_999 - 1_000] {
+        mock::set_instcnt(now);
+        assert_eq!(
+            conc.transmit(TxPacket::LoRa(pkt.clone())),
+            Err(Error::TooLate)
+        );
+    }
+    assert!(mock::take_sent().is_empty());
+}
+
+#[test]
+fn beacon_goes_out_on_gps_pps() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+    let TxPacket::LoRa(mut pkt) = common::tx_packet() else {
+        unreachable!()
+    };
+    pkt.mode = TxMode::OnGps;
+    conc.transmit(TxPacket::LoRa(pkt)).unwrap();
+    assert_eq!(mock::take_sent()[0].tx_mode, 2);
+}
+
+#[test]
+fn fsk_reply_on_the_50_kbps_channel() {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+    let pkt = TxPacketFSK {
+        freq: 868_800_000,
+        mode: TxMode::Immediate,
+        radio: FrontRadio::R0,
+        power: Dbm(14),
+        datarate: 50_000,
+        deviation: 25,
+        preamble: None,
+        omit_crc: false,
+        fixed_len: false,
+        payload: vec![4, 5, 6],
+    };
+    conc.transmit(TxPacket::FSK(pkt.clone())).unwrap();
+    let sent = mock::take_sent();
+    assert_eq!(sent[0].modulation, 0x20);
+    assert_eq!(sent[0].datarate, 50_000);
+    assert_eq!(sent[0].f_dev, 25);
+    assert_eq!(sent[0].preamble, 5);
+    assert_eq!(&sent[0].payload[..usize::from(sent[0].size)], &[4, 5, 6]);
+
+    // Outside what the concentrator can send
+    assert_eq!(
+        conc.transmit(TxPacket::FSK(TxPacketFSK {
+            datarate: 300_000,
+            ..pkt
+        })),
+        Err(Error::Data)
+    );
+}
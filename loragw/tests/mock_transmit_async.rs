@@ -0,0 +1,56 @@
+//! Transmitting through the mock HAL with `transmit_async`.
+use loragw::{Concentrator, Error, Running, TX_POLL, TxGain, TxStatus, mock};
+use std::time::Duration;
+
+mod common;
+
+/// Runs `test` with the concentrator to itself, the lock isn't held across awaits
+fn run<F: Future<Output = ()>>(test: impl FnOnce(Concentrator<Running>) -> F) {
+    let _lock = common::lock();
+    let conc = common::start_concentrator(&[TxGain::default()]);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    runtime.block_on(test(conc));
+}
+
+#[test]
+fn waits_until_emitted() {
+    run(|mut conc| async move {
+        mock::set_tx_delay(Duration::from_millis(30));
+        conc.transmit(common::tx_packet()).unwrap();
+        let report = conc
+            .transmit_async(common::tx_packet(), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(report.duration >= Duration::from_millis(30) - TX_POLL);
+        assert_eq!(mock::take_sent().len(), 2);
+    });
+}
+
+#[test]
+fn times_out_while_scheduled() {
+    run(|mut conc| async move {
+        mock::queue_tx_statuses(&[TxStatus::Free]);
+        mock::queue_tx_statuses(&[TxStatus::Scheduled; 100]);
+        assert_eq!(
+            conc.transmit_async(common::tx_packet(), 5 * TX_POLL).await,
+            Err(Error::Timeout)
+        );
+        // Handed to the HAL, it stayed scheduled
+        assert_eq!(mock::take_sent().len(), 1);
+    });
+}
+
+#[test]
+fn runs_in_a_spawned_task() {
+    run(|mut conc| async move {
+        let sent = tokio::spawn(async move {
+            conc.transmit_async(common::tx_packet(), Duration::from_secs(1))
+                .await
+        });
+        assert!(sent.await.unwrap().is_ok());
+        assert_eq!(mock::take_sent().len(), 1);
+    });
+}
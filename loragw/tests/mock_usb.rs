@@ -0,0 +1,75 @@
+//! Connecting to a Corecell USB concentrator through the mock HAL, with the same builder as one
+//! on SPI.
+use loragw::{
+    BoardConf, BuilderError, ComType, Concentrator, Error, FrontRadio, ResetToken, TxGain,
+    cfg::Config, mock,
+};
+use std::ffi::CString;
+
+mod common;
+
+fn board(com_type: ComType, path: &str) -> BoardConf {
+    BoardConf {
+        lorawan_public: false,
+        clksrc: FrontRadio::R0,
+        spidev_path: CString::new(path).unwrap(),
+        com_type,
+    }
+}
+
+fn connect(board: BoardConf) -> Result<(), Error> {
+    mock::reset();
+    Concentrator::open(&ResetToken::usb())?
+        .set_config_board(board)
+        .set_config_tx_gains(&[TxGain::default()])
+        .connect()?
+        .start()
+        .map(|_| ())
+}
+
+#[test]
+fn usb_concentrator_starts() {
+    let _lock = common::lock();
+    connect(board(ComType::USB, "/dev/ttyACM0")).unwrap();
+    assert_eq!(
+        mock::connected_to(),
+        Some((ComType::USB, CString::new("/dev/ttyACM0").unwrap()))
+    );
+
+    let by_id = "/dev/serial/by-id/usb-STMicroelectronics_Virtual_COM_Port-if00";
+    connect(board(ComType::USB, by_id)).unwrap();
+    assert_eq!(mock::connected_to().unwrap().1.to_str(), Ok(by_id));
+}
+
+#[test]
+fn path_must_fit_the_com_type() {
+    let _lock = common::lock();
+    let invalid = Err(Error::BuilderError(BuilderError::InvalidComPath));
+    // Left behind from the SPI configuration
+    assert_eq!(connect(board(ComType::USB, "/dev/spidev0.0")), invalid);
+    assert_eq!(connect(board(ComType::SPI, "/dev/ttyACM0")), invalid);
+    assert_eq!(connect(board(ComType::USB, "")), invalid);
+    // Would be cut short in the HAL's board configuration
+    let long = format!("/dev/serial/by-id/{}", "x".repeat(50));
+    assert_eq!(connect(board(ComType::USB, &long)), invalid);
+    assert_eq!(mock::connected_to(), None);
+
+    connect(board(ComType::SPI, "/dev/spidev0.0")).unwrap();
+    assert_eq!(mock::connected_to().unwrap().0, ComType::SPI);
+}
+
+#[test]
+fn usb_board_from_config() {
+    let conf = Config::from_str(
+        r#"
+        [board]
+        lorawan_public = false
+        clksrc = 0
+        com_type = "USB"
+        com_path = "/dev/ttyACM0"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(conf.board.com_type, ComType::USB);
+    assert_eq!(conf.board.spidev_path.to_str(), Ok("/dev/ttyACM0"));
+}
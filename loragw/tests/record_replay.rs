@@ -0,0 +1,84 @@
+//! Recording the HAL calls of a session and replaying them against the mock.
+use loragw::{Concentrator, Error, Running, TxGain, TxPacket, TxPacketLoRa, mock, record};
+use std::fs;
+
+mod common;
+
+/// Without resetting the mock, which would clear what it replays
+fn start_concentrator() -> Concentrator<Running> {
+    common::builder(&[TxGain::default()])
+        .connect()
+        .unwrap()
+        .start()
+        .unwrap()
+}
+
+/// Receives, reads the counter and answers, returning the payload and count
+fn session() -> (Vec<u8>, u32) {
+    let conc = start_concentrator();
+    let pkts = conc.receive().unwrap().unwrap();
+    let count = conc.instcnt().unwrap();
+    let TxPacket::LoRa(pkt) = common::tx_packet() else {
+        unreachable!()
+    };
+    conc.transmit(TxPacket::LoRa(TxPacketLoRa {
+        payload: vec![9],
+        ..pkt
+    }))
+    .unwrap();
+    (pkts[0].payload().to_vec(), count)
+}
+
+#[test]
+fn recorded_session_replays_without_the_mock_state() {
+    let _lock = common::lock();
+    mock::reset();
+    mock::push_rx(mock::lora_rx_packet(&[1, 2, 3]));
+    mock::set_instcnt(1_000_000);
+    let path = std::env::temp_dir().join("loragw_record_replay.trace");
+    record::record_to(fs::File::create(&path).unwrap());
+    let recorded = session();
+    record::stop_recording();
+    assert_eq!(mock::take_sent().len(), 1);
+
+    let trace = record::parse_trace(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert!(
+        trace
+            .iter()
+            .any(|call| call.call == "lgw_receive" && call.ret == 1)
+    );
+    // Lines read back as they were written
+    assert_eq!(
+        trace[0].to_string().parse::<record::HalCall>(),
+        Ok(trace[0].clone())
+    );
+
+    // Nothing queued in the mock, everything comes from the trace
+    mock::reset();
+    record::replay(trace);
+    assert_eq!(session(), recorded);
+    assert_eq!(record::replay_left(), 0);
+    assert!(mock::take_sent().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "replay diverged")]
+fn replay_panics_on_another_call() {
+    let _lock = common::lock();
+    mock::reset();
+    record::replay(record::parse_trace("lgw_start() = 0").unwrap());
+    let _ = start_concentrator();
+}
+
+#[test]
+fn malformed_lines_are_data_errors() {
+    for line in [
+        "lgw_get_instcnt(*) = 0 0é0",
+        "lgw_get_instcnt(*) = 0 40420f0",
+        "lgw_get_instcnt(*) = 0 +f",
+        "lgw_get_instcnt(*) = zero",
+        "lgw_get_instcnt",
+    ] {
+        assert_eq!(line.parse::<record::HalCall>(), Err(Error::Data), "{line}");
+    }
+}
@@ -0,0 +1,222 @@
+//! Python bindings over the must-gw packet stream.
+use std::collections::VecDeque;
+
+use loragw::{Error, RX_FIFO_SIZE, RxPacket};
+use must_gw::{LEN, SIZE, create_concentrator, node::GWNode};
+use must_hop::node::{
+    MHPacket,
+    mesh_router::{MeshRouter, MeshRouterError},
+    network_manager::NetworkManager,
+    policy::GatewayPolicy,
+};
+use pyo3::{create_exception, exceptions::PyException, prelude::*, types::PyBytes};
+use tokio::runtime::Runtime;
+
+create_exception!(must_gw_py, GatewayError, PyException);
+
+fn gateway_error(err: impl std::fmt::Display) -> PyErr {
+    GatewayError::new_err(err.to_string())
+}
+
+/// A packet received by the gateway.
+#[pyclass(frozen)]
+struct Packet {
+    #[pyo3(get)]
+    source: u8,
+    #[pyo3(get)]
+    destination: u8,
+    #[pyo3(get)]
+    packet_id: u16,
+    #[pyo3(get)]
+    hop_count: u8,
+    payload: Vec<u8>,
+}
+
+impl From<MHPacket<SIZE>> for Packet {
+    fn from(pkt: MHPacket<SIZE>) -> Self {
+        Self {
+            source: pkt.source_id,
+            destination: pkt.destination_id,
+            packet_id: pkt.packet_id,
+            hop_count: pkt.hop_count,
+            payload: pkt.payload.to_vec(),
+        }
+    }
+}
+
+#[pymethods]
+impl Packet {
+    #[getter]
+    fn payload<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.payload)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Packet(source={}, destination={}, packet_id={}, hop_count={}, payload={:?})",
+            self.source, self.destination, self.packet_id, self.hop_count, self.payload
+        )
+    }
+}
+
+/// The gateway, running the concentrator with the mesh's gateway policy.
+///
+/// The concentrator is not thread safe, so a Gateway can only be used from the thread that
+/// created it.
+#[pyclass(unsendable)]
+struct Gateway {
+    runtime: Runtime,
+    router: MeshRouter<GWNode, SIZE, LEN, GatewayPolicy>,
+    rec_buf: Vec<RxPacket>,
+    received: VecDeque<Packet>,
+}
+
+impl Gateway {
+    /// Blocks until a packet is received, checking for Python signals between listens. The GIL
+    /// is released while listening, so other Python threads run meanwhile.
+    fn next_packet(&mut self, py: Python<'_>) -> PyResult<Packet> {
+        loop {
+            if let Some(pkt) = self.received.pop_front() {
+                return Ok(pkt);
+            }
+            py.check_signals()?;
+            let heard = py.allow_threads(|| {
+                self.runtime
+                    .block_on(self.router.listen_with_timeout(&mut self.rec_buf))
+            });
+            match heard {
+                Ok(()) => {}
+                // Listening timed out, nothing was received
+                Err(MeshRouterError::Node(Error::Busy)) => continue,
+                Err(e) => return Err(gateway_error(e)),
+            }
+            let pkts = py
+                .allow_threads(|| {
+                    self.runtime
+                        .block_on(self.router.receive((), &self.rec_buf))
+                })
+                .map_err(gateway_error)?;
+            self.received.extend(
+                pkts.into_iter()
+                    .map(|delivered| Packet::from(delivered.packet)),
+            );
+        }
+    }
+}
+
+#[pymethods]
+impl Gateway {
+    /// Resets and starts the concentrator, using `source_id` as the gateway's id in the mesh.
+    #[new]
+    #[pyo3(signature = (source_id = 0))]
+    fn new(source_id: u8) -> PyResult<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .map_err(gateway_error)?;
+        let conc = create_concentrator().map_err(gateway_error)?;
+        let router = MeshRouter::new(
+            GWNode::new(conc),
+            NetworkManager::new(source_id, 10, 3),
+            GatewayPolicy,
+        );
+        Ok(Self {
+            runtime,
+            router,
+            rec_buf: Vec::with_capacity(RX_FIFO_SIZE),
+            received: VecDeque::new(),
+        })
+    }
+
+    /// Iterator over received packets, which blocks until the next packet arrives.
+    fn receive(slf: Py<Self>) -> PacketIter {
+        PacketIter { gateway: slf }
+    }
+
+    /// Sends `data` to the node with id `dest`, releasing the GIL until it is sent.
+    fn send(&mut self, py: Python<'_>, dest: u8, data: &[u8]) -> PyResult<()> {
+        let payload = heapless::Vec::from_slice(data)
+            .map_err(|_| gateway_error(format!("payload is larger than {} bytes", SIZE)))?;
+        py.allow_threads(|| {
+            self.runtime
+                .block_on(self.router.send_payload(payload, dest))
+        })
+        .map_err(gateway_error)
+    }
+
+    /// Announces the gateway, so nodes learn their distance to it, releasing the GIL until it is
+    /// sent.
+    fn bootup(&mut self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.runtime.block_on(self.router.bootup()))
+            .map_err(gateway_error)
+    }
+}
+
+/// Endless iterator of packets received by a Gateway.
+#[pyclass(unsendable)]
+struct PacketIter {
+    gateway: Py<Gateway>,
+}
+
+#[pymethods]
+impl PacketIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Packet> {
+        self.gateway.borrow_mut(py).next_packet(py)
+    }
+}
+
+#[pymodule]
+fn must_gw_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Gateway>()?;
+    m.add_class::<Packet>()?;
+    m.add_class::<PacketIter>()?;
+    m.add("GatewayError", m.py().get_type::<GatewayError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use must_hop::node::PacketType;
+
+    #[test]
+    fn test_packet_has_the_mesh_fields() {
+        let pkt = Packet::from(MHPacket::<SIZE> {
+            network_id: 0,
+            destination_id: 0,
+            packet_type: PacketType::Data,
+            flags: 0,
+            packet_id: 7,
+            source_id: 4,
+            max_payload: SIZE as u8,
+            payload: heapless::Vec::from_slice(&[1, 2, 3]).unwrap(),
+            hop_count: 2,
+            hop_to_gw: u8::MAX,
+            route: heapless::Vec::new(),
+        });
+        assert_eq!(
+            (pkt.source, pkt.destination, pkt.packet_id, pkt.hop_count),
+            (4, 0, 7, 2)
+        );
+        Python::with_gil(|py| assert_eq!(pkt.payload(py).as_bytes(), [1, 2, 3]));
+        assert_eq!(
+            pkt.__repr__(),
+            "Packet(source=4, destination=0, packet_id=7, hop_count=2, payload=[1, 2, 3])"
+        );
+    }
+
+    #[test]
+    fn test_errors_are_gateway_errors() {
+        let err = MeshRouterError::<Error>::Node(Error::Size);
+        let message = err.to_string();
+        let err = gateway_error(err);
+        Python::with_gil(|py| {
+            assert!(err.is_instance_of::<GatewayError>(py));
+            assert_eq!(err.value(py).to_string(), message);
+        });
+    }
+}
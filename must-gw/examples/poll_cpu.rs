@@ -0,0 +1,104 @@
+//! Compares CPU usage of the gateway's receive loop with a fixed and an adaptive poll interval,
+//! against the mock HAL so it runs without a concentrator attached.
+//!
+//! Run it on the target machine, e.g. a Pi Zero, with
+//! `cargo run --release -p must-gw --example poll_cpu --features mock`
+use std::{
+    ffi::CString,
+    thread,
+    time::{Duration, Instant},
+};
+
+use loragw::{
+    BoardConf, ComType, Concentrator, FrontRadio, RX_FIFO_SIZE, ResetToken, TxGain, mock,
+};
+use must_gw::{node::GWNode, poll::PollInterval};
+use must_hop::node::MHNode;
+
+const RUN_TIME: Duration = Duration::from_secs(10);
+
+/// Total user and system CPU time of this process, from `/proc/self/stat`
+fn cpu_time() -> Duration {
+    let stat = std::fs::read_to_string("/proc/self/stat").expect("needs /proc, run on Linux");
+    // Skip past the command name, which may contain spaces
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+    let utime: u64 = fields[11].parse().unwrap();
+    let stime: u64 = fields[12].parse().unwrap();
+    // USER_HZ is 100 on every Linux platform we run on
+    Duration::from_millis((utime + stime) * 10)
+}
+
+/// Pushes bursts of `burst` packets every `period` into the mock FIFO until `RUN_TIME` is over
+fn traffic(burst: usize, period: Duration) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let start = Instant::now();
+        while start.elapsed() < RUN_TIME {
+            for _ in 0..burst {
+                mock::push_rx(mock::lora_rx_packet(&[0x00]));
+            }
+            thread::sleep(period);
+        }
+    })
+}
+
+async fn run(name: &str, poll: PollInterval, burst: usize, period: Duration) {
+    mock::reset();
+    let token = unsafe { ResetToken::bypass() };
+    let conc = Concentrator::open(&token)
+        .unwrap()
+        .set_config_board(BoardConf {
+            lorawan_public: false,
+            clksrc: FrontRadio::R0,
+            spidev_path: CString::new("/dev/spidev0.0").unwrap(),
+            com_type: ComType::SPI,
+        })
+        // Starting needs a gain table, even for receiving only
+        .set_config_tx_gains(&[TxGain::default()])
+        .connect()
+        .unwrap()
+        .start()
+        .unwrap();
+    let mut node = GWNode::new(conc).set_poll_interval(poll);
+
+    let generator = traffic(burst, period);
+    let cpu_before = cpu_time();
+    let start = Instant::now();
+    let mut rec_buf = Vec::with_capacity(RX_FIFO_SIZE);
+    let mut received = 0;
+    while start.elapsed() < RUN_TIME {
+        if node.listen(&mut rec_buf, true).await.is_ok() {
+            received += rec_buf.len();
+        }
+    }
+    let cpu = cpu_time() - cpu_before;
+    generator.join().unwrap();
+    println!(
+        "{name:<28} cpu {:>6.2}%  received {received}",
+        100.0 * cpu.as_secs_f64() / start.elapsed().as_secs_f64()
+    );
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let fixed = || PollInterval::fixed(Duration::from_millis(10));
+    for (traffic_name, burst, period) in [
+        ("idle", 0, Duration::from_millis(100)),
+        ("sparse", 1, Duration::from_secs(1)),
+        ("busy", 8, Duration::from_millis(20)),
+    ] {
+        run(
+            &format!("fixed 10ms, {traffic_name}"),
+            fixed(),
+            burst,
+            period,
+        )
+        .await;
+        run(
+            &format!("adaptive, {traffic_name}"),
+            PollInterval::default(),
+            burst,
+            period,
+        )
+        .await;
+    }
+}
@@ -0,0 +1,361 @@
+//! Adaptive data rate: recommends a spreading factor and TX power per node from the SNR the
+//! gateway hears it with, and has the node switch with a `Control::LinkAdr`.
+//!
+//! Like LoRaWAN's ADR, the margin is the best SNR of the last `history` frames above what the
+//! spreading factor needs to be demodulated, less an installation margin. Every 3 dB of it steps
+//! the spreading factor down, then the power down. Unlike LoRaWAN, where a node backs off by
+//! itself when its frames aren't answered, a missing margin steps the power and then the
+//! spreading factor up again from here. Only the last hop to the gateway is heard, so only frames
+//! a node sent to us itself count, which needs relays to record routes (`set_record_route`).
+//! Relays are left alone, the nodes behind them listen on the channel plan.
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use must_hop::prelude::{Control, Db, Dbm, Delivered};
+
+/// Margin each step of the spreading factor or power uses up
+const STEP: Db = Db(3.0);
+
+/// SNR the SX126x and SX130x need to demodulate `spreading_factor`
+pub fn required_snr(spreading_factor: u8) -> Db {
+    Db(-7.5 - 2.5 * (f32::from(spreading_factor) - 7.0))
+}
+
+/// A spreading factor and TX power for a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub spreading_factor: u8,
+    pub tx_power: Dbm<i8>,
+}
+
+impl From<Settings> for Control {
+    fn from(settings: Settings) -> Self {
+        Control::LinkAdr {
+            spreading_factor: settings.spreading_factor,
+            tx_power: settings.tx_power,
+        }
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SF{} at {}", self.spreading_factor, self.tx_power)
+    }
+}
+
+/// The bounds and margins of the ADR
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdrConfig {
+    pub min_spreading_factor: u8,
+    pub max_spreading_factor: u8,
+    pub min_tx_power: Dbm<i8>,
+    pub max_tx_power: Dbm<i8>,
+    /// Steps of the TX power, 2 dB like LoRaWAN's in EU868
+    pub tx_power_step: Db<i8>,
+    /// Kept above the SNR a spreading factor needs, for fading and what changes around the node
+    pub margin: Db,
+    /// Extra margin before a step down, so a node at the edge isn't switched back and forth
+    pub hysteresis: Db,
+    /// Frames the best SNR is taken of, none is recommended before as many were heard
+    pub history: usize,
+    /// Least time between two recommendations to a node, and how long it gets to reply
+    pub holdoff: Duration,
+}
+
+impl Default for AdrConfig {
+    fn default() -> Self {
+        Self {
+            min_spreading_factor: 7,
+            max_spreading_factor: 12,
+            min_tx_power: Dbm(2),
+            max_tx_power: Dbm(14),
+            tx_power_step: Db(2),
+            margin: Db(10.0),
+            hysteresis: Db(3.0),
+            history: 20,
+            holdoff: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl AdrConfig {
+    /// Parses settings like `sf=7-10,power=2-14,margin=10`, the rest keep their defaults. Also
+    /// takes `step`, `hysteresis`, `history` and `holdoff` in seconds.
+    pub fn from_str_or_default(s: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected <setting>=<value>, got {}", pair))?;
+            let value = value.trim();
+            match key.trim() {
+                "sf" => (config.min_spreading_factor, config.max_spreading_factor) = range(value)?,
+                "power" => {
+                    let (min, max) = range(value)?;
+                    (config.min_tx_power, config.max_tx_power) = (Dbm(min), Dbm(max));
+                }
+                "step" => config.tx_power_step = Db(number(value)?),
+                "margin" => config.margin = Db(number(value)?),
+                "hysteresis" => config.hysteresis = Db(number(value)?),
+                "history" => config.history = number(value)?,
+                "holdoff" => config.holdoff = Duration::from_secs(number(value)?),
+                other => return Err(format!("unknown ADR setting {}", other)),
+            }
+        }
+        if config.min_spreading_factor > config.max_spreading_factor
+            || config.min_tx_power > config.max_tx_power
+        {
+            return Err("ADR ranges must go from low to high".to_string());
+        }
+        if config.tx_power_step <= Db(0) {
+            return Err("the ADR power step must be positive".to_string());
+        }
+        config.history = config.history.max(1);
+        Ok(config)
+    }
+
+    /// `settings` within the bounds
+    fn clamp(&self, settings: Settings) -> Settings {
+        Settings {
+            spreading_factor: settings
+                .spreading_factor
+                .clamp(self.min_spreading_factor, self.max_spreading_factor),
+            tx_power: settings
+                .tx_power
+                .clamp(self.min_tx_power, self.max_tx_power),
+        }
+    }
+
+    /// What a node on `current`, heard at best with `snr`, should switch to
+    pub fn recommend(&self, current: Settings, snr: Db) -> Settings {
+        let margin = snr - required_snr(current.spreading_factor) - self.margin;
+        let mut next = self.clamp(current);
+        if margin - self.hysteresis >= STEP {
+            let mut steps = ((margin - self.hysteresis).0 / STEP.0) as u32;
+            while steps > 0 && next.spreading_factor > self.min_spreading_factor {
+                next.spreading_factor -= 1;
+                steps -= 1;
+            }
+            while steps > 0 && next.tx_power - self.tx_power_step >= self.min_tx_power {
+                next.tx_power -= self.tx_power_step;
+                steps -= 1;
+            }
+        } else if margin < Db(0.0) {
+            let mut steps = (-margin.0 / STEP.0).ceil() as u32;
+            while steps > 0 && next.tx_power + self.tx_power_step <= self.max_tx_power {
+                next.tx_power += self.tx_power_step;
+                steps -= 1;
+            }
+            while steps > 0 && next.spreading_factor < self.max_spreading_factor {
+                next.spreading_factor += 1;
+                steps -= 1;
+            }
+        }
+        next
+    }
+}
+
+/// A range like `7-10`, or `-4-14` with a negative low end
+fn range<T: std::str::FromStr>(value: &str) -> Result<(T, T), String>
+where
+    T::Err: fmt::Display,
+{
+    // The separator is the first dash after the low end's sign
+    let dash = value
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '-')
+        .map(|(i, _)| i)
+        .ok_or_else(|| format!("expected <low>-<high>, got {}", value))?;
+    Ok((number(&value[..dash])?, number(&value[dash + 1..])?))
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid number {}: {}", value, e))
+}
+
+#[derive(Debug, Clone)]
+struct NodeAdr {
+    /// What the node is on, as far as we know
+    settings: Settings,
+    snr: VecDeque<Db>,
+    /// Sent and not replied to yet, and when the last recommendation went out
+    pending: Option<Settings>,
+    last_sent: Option<Instant>,
+}
+
+/// SNR history and settings per node, for the nodes the gateway hears directly
+#[derive(Debug, Clone)]
+pub struct AdrEngine {
+    config: AdrConfig,
+    /// What nodes are on before they are told otherwise
+    initial: Settings,
+    nodes: BTreeMap<u8, NodeAdr>,
+    relays: BTreeSet<u8>,
+}
+
+impl AdrEngine {
+    /// Nodes are taken to be on `spreading_factor`, the channel plan's, at the highest power
+    /// allowed until they are told otherwise
+    pub fn new(config: AdrConfig, spreading_factor: u8) -> Self {
+        let initial = Settings {
+            spreading_factor,
+            tx_power: config.max_tx_power,
+        };
+        Self {
+            config,
+            initial,
+            nodes: BTreeMap::new(),
+            relays: BTreeSet::new(),
+        }
+    }
+
+    /// `delivered`'s last hop was heard with `snr`. Frames relayed to us count for the relay,
+    /// which is then left alone, and frames whose last hop can't be told don't count.
+    pub fn heard<const SIZE: usize>(&mut self, delivered: &Delivered<SIZE>, snr: Db) {
+        let sender = match (delivered.relayed_by, delivered.hops) {
+            (Some(relay), _) => {
+                self.relays.insert(relay);
+                self.nodes.remove(&relay);
+                return;
+            }
+            (None, Some(1)) => delivered.packet.source_id,
+            _ => return,
+        };
+        if self.relays.contains(&sender) {
+            return;
+        }
+        let history = self.config.history;
+        let initial = self.initial;
+        let node = self.nodes.entry(sender).or_insert_with(|| NodeAdr {
+            settings: initial,
+            snr: VecDeque::with_capacity(history),
+            pending: None,
+            last_sent: None,
+        });
+        if node.snr.len() == history {
+            node.snr.pop_front();
+        }
+        node.snr.push_back(snr);
+    }
+
+    /// `node` replied to the last recommendation. Once accepted, its SNR history was heard on
+    /// the old settings and starts over.
+    pub fn replied(&mut self, node: u8, accepted: bool) {
+        let Some(entry) = self.nodes.get_mut(&node) else {
+            return;
+        };
+        let Some(settings) = entry.pending.take() else {
+            return;
+        };
+        if accepted {
+            entry.settings = settings;
+            entry.snr.clear();
+        }
+    }
+
+    /// The nodes to send a recommendation now, with what to switch to. Each is noted as sent, and
+    /// a node which doesn't reply within the holdoff may be sent another.
+    pub fn recommendations(&mut self, now: Instant) -> Vec<(u8, Settings)> {
+        let mut recommended = Vec::new();
+        for (&id, node) in self.nodes.iter_mut() {
+            if node.snr.len() < self.config.history {
+                continue;
+            }
+            let holdoff = self.config.holdoff;
+            if node
+                .last_sent
+                .is_some_and(|sent| now.saturating_duration_since(sent) < holdoff)
+            {
+                continue;
+            }
+            let best = node.snr.iter().map(|snr| snr.0).fold(f32::MIN, f32::max);
+            let next = self.config.recommend(node.settings, Db(best));
+            if next == node.settings {
+                continue;
+            }
+            node.pending = Some(next);
+            node.last_sent = Some(now);
+            recommended.push((id, next));
+        }
+        recommended
+    }
+
+    /// What `node` is on, as far as we know
+    pub fn settings(&self, node: u8) -> Option<Settings> {
+        self.nodes.get(&node).map(|node| node.settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(spreading_factor: u8, tx_power: i8) -> Settings {
+        Settings {
+            spreading_factor,
+            tx_power: Dbm(tx_power),
+        }
+    }
+
+    #[test]
+    fn test_recommend_holds_within_the_hysteresis() {
+        let config = AdrConfig::default();
+        // SF10 needs -15 dB, so -5 dB is just the 10 dB installation margin
+        assert_eq!(config.recommend(on(10, 14), Db(-5.0)), on(10, 14));
+        // A step of margin, which the hysteresis takes up
+        assert_eq!(config.recommend(on(10, 14), Db(-2.0)), on(10, 14));
+        assert_eq!(config.recommend(on(10, 14), Db(1.0)), on(9, 14));
+    }
+
+    #[test]
+    fn test_recommend_steps_down_the_spreading_factor_first() {
+        let config = AdrConfig::default();
+        // Four steps: three to SF7, one of the power
+        assert_eq!(config.recommend(on(10, 14), Db(10.0)), on(7, 12));
+        // No lower than the bounds
+        assert_eq!(config.recommend(on(10, 14), Db(40.0)), on(7, 2));
+    }
+
+    #[test]
+    fn test_recommend_steps_up_the_power_first() {
+        let config = AdrConfig::default();
+        // 4 dB short, two steps
+        assert_eq!(config.recommend(on(10, 10), Db(-9.0)), on(10, 14));
+        assert_eq!(config.recommend(on(10, 14), Db(-9.0)), on(12, 14));
+        // No higher than the bounds
+        assert_eq!(config.recommend(on(12, 14), Db(-40.0)), on(12, 14));
+        // Settings out of the bounds are brought back in
+        assert_eq!(config.recommend(on(5, 20), Db(7.5)), on(7, 14));
+    }
+
+    #[test]
+    fn test_range_takes_negative_bounds() {
+        assert_eq!(range::<u8>("7-10"), Ok((7, 10)));
+        assert_eq!(range::<i8>("-4-14"), Ok((-4, 14)));
+        assert_eq!(range::<i8>("-8--2"), Ok((-8, -2)));
+        assert!(range::<u8>("7").is_err());
+        assert!(range::<u8>("-7").is_err());
+    }
+
+    #[test]
+    fn test_config_from_str() {
+        let config = AdrConfig::from_str_or_default("sf=7-10, power=-4-14, margin=5").unwrap();
+        assert_eq!(config.max_spreading_factor, 10);
+        assert_eq!(config.min_tx_power, Dbm(-4));
+        assert_eq!(config.margin, Db(5.0));
+        assert_eq!(config.history, AdrConfig::default().history);
+        assert!(AdrConfig::from_str_or_default("sf=10-7").is_err());
+        assert!(AdrConfig::from_str_or_default("step=0").is_err());
+        assert!(AdrConfig::from_str_or_default("speed=7").is_err());
+    }
+}
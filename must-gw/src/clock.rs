@@ -0,0 +1,115 @@
+//! Disciplines the concentrator's counter to GPS time, so receptions carry a time comparable
+//! between gateways instead of the host's clock, which NTP keeps no closer than a few ms.
+//!
+//! Each PPS edge latches the counter, and together with the time of that second from the GPS it
+//! makes a `PpsSync`. From two syncs follows how fast the counter runs against GPS time, and from
+//! the last one the time of any counter value near it. Without syncs, or once the last one is
+//! older than `HOLDOVER`, there is no disciplined time and records fall back to the system clock.
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// How far from the last sync the counter is still trusted. At a residual drift of 1 ppm this
+/// is off by 60 µs at worst.
+pub const HOLDOVER: Duration = Duration::from_secs(60);
+/// Syncs further apart are not used for the drift, the counter may have wrapped in between
+const MAX_DRIFT_SPAN: Duration = Duration::from_secs(30 * 60);
+/// Drifts beyond this are taken as a bad fix rather than the crystal
+const MAX_DRIFT_PPM: f64 = 100.0;
+
+/// The counter at a PPS edge, and the time of the second it started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PpsSync {
+    /// Concentrator counter latched on the edge, at 1 µs resolution
+    pub count_us: Duration,
+    /// Time of the second, from the GPS
+    pub time: SystemTime,
+}
+
+/// How the clocks relate at the last sync
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockStats {
+    /// System clock minus GPS time, in µs
+    pub offset_us: i64,
+    /// How much faster the counter runs than GPS time, in ppm. None until the second sync.
+    pub drift_ppm: Option<f64>,
+}
+
+impl fmt::Display for ClockStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {} us, drift ", self.offset_us)?;
+        match self.drift_ppm {
+            Some(drift) => write!(f, "{:+.3} ppm", drift),
+            None => write!(f, "n/a"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClockDiscipline {
+    last: Option<PpsSync>,
+    stats: Option<ClockStats>,
+}
+
+impl ClockDiscipline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the sync of a PPS edge, with `system_time` what the system clock read on it
+    pub fn sync(&mut self, pps: PpsSync, system_time: SystemTime) {
+        let drift_ppm = self
+            .last
+            .and_then(|last| drift_ppm(last, pps))
+            .or(self.stats.and_then(|s| s.drift_ppm));
+        self.stats = Some(ClockStats {
+            offset_us: signed_micros(system_time, pps.time),
+            drift_ppm,
+        });
+        self.last = Some(pps);
+    }
+
+    pub fn stats(&self) -> Option<ClockStats> {
+        self.stats
+    }
+
+    /// GPS time of the counter value `count_us`, if the last sync is within `HOLDOVER` of it
+    pub fn time_of(&self, count_us: Duration) -> Option<SystemTime> {
+        let last = self.last?;
+        // Signed, packets polled after a sync may have been received before it
+        let ticks = (count_us.as_micros() as u32).wrapping_sub(last.count_us.as_micros() as u32);
+        let ticks = ticks as i32;
+        if u128::from(ticks.unsigned_abs()) > HOLDOVER.as_micros() {
+            return None;
+        }
+        let drift = self.stats.and_then(|s| s.drift_ppm).unwrap_or(0.0);
+        let elapsed =
+            Duration::from_secs_f64(f64::from(ticks.unsigned_abs()) / 1e6 / (1.0 + drift / 1e6));
+        if ticks >= 0 {
+            last.time.checked_add(elapsed)
+        } else {
+            last.time.checked_sub(elapsed)
+        }
+    }
+}
+
+/// From the counter and GPS time elapsed between two syncs
+fn drift_ppm(earlier: PpsSync, later: PpsSync) -> Option<f64> {
+    let gps = later.time.duration_since(earlier.time).ok()?;
+    if gps.is_zero() || gps > MAX_DRIFT_SPAN {
+        return None;
+    }
+    let counter =
+        (later.count_us.as_micros() as u32).wrapping_sub(earlier.count_us.as_micros() as u32);
+    let drift = (f64::from(counter) / gps.as_secs_f64() / 1e6 - 1.0) * 1e6;
+    (drift.abs() <= MAX_DRIFT_PPM).then_some(drift)
+}
+
+/// `a - b` in µs
+fn signed_micros(a: SystemTime, b: SystemTime) -> i64 {
+    match a.duration_since(b) {
+        Ok(ahead) => ahead.as_micros() as i64,
+        Err(behind) => -(behind.duration().as_micros() as i64),
+    }
+}
@@ -0,0 +1,115 @@
+//! Manages nodes over the mesh from the command line: fetches their logs one packet at a time for
+//! `must-gw logs`, has them reboot, reset or go into safe mode, and turns their logs up or down.
+use std::time::Duration;
+
+use loragw::{Error, RxPacket};
+use must_hop::{
+    prelude::{Control, Delivered, GatewayPolicy, MeshRouter, MeshRouterError, PacketType},
+    verbosity::{Level, Subsystem},
+};
+
+use crate::{LEN, SIZE, node::GWNode};
+
+type Router = MeshRouter<GWNode, SIZE, LEN, GatewayPolicy>;
+
+/// The request a `must-gw` command sends, e.g. `reboot`
+pub fn request_for(command: &str) -> Option<Control> {
+    match command {
+        "reboot" => Some(Control::Reboot),
+        "factory-reset" => Some(Control::FactoryReset),
+        "safe-mode" => Some(Control::EnterSafeMode),
+        _ => None,
+    }
+}
+
+/// The request of `must-gw loglevel <node> <subsystem> <level>`, the subsystem being nm,
+/// router, mac or radio and the level off, error, warn, info or trace
+pub fn log_level_request(subsystem: &str, level: &str) -> Option<Control> {
+    let subsystem = match subsystem {
+        "nm" => Subsystem::Manager,
+        "router" => Subsystem::Router,
+        "mac" => Subsystem::Mac,
+        "radio" => Subsystem::Radio,
+        _ => return None,
+    };
+    let level = match level {
+        "off" => Level::Off,
+        "error" => Level::Error,
+        "warn" => Level::Warn,
+        "info" => Level::Info,
+        "trace" => Level::Trace,
+        _ => return None,
+    };
+    Some(Control::SetLogLevel { subsystem, level })
+}
+
+/// Sends `node` a Reboot, FactoryReset, EnterSafeMode or SetLogLevel, and returns whether it accepted, or
+/// None if it didn't reply within `timeout`
+pub async fn send_request(
+    router: &mut Router,
+    rec_buf: &mut Vec<RxPacket>,
+    node: u8,
+    request: Control,
+    timeout: Duration,
+) -> Result<Option<bool>, MeshRouterError<Error>> {
+    router.send_control(node, request).await?;
+    let reply = reply(router, rec_buf, node, |msg, _| match msg {
+        Control::Reply { accepted } => Some(accepted),
+        _ => None,
+    });
+    match tokio::time::timeout(timeout, reply).await {
+        Ok(accepted) => accepted.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Asks `node` for its logs until it sent all it has, and returns them oldest first. Stops
+/// early with what came so far if a part isn't answered within `timeout`.
+pub async fn fetch_logs(
+    router: &mut Router,
+    rec_buf: &mut Vec<RxPacket>,
+    node: u8,
+    timeout: Duration,
+) -> Result<Vec<u8>, MeshRouterError<Error>> {
+    let mut logs = Vec::new();
+    loop {
+        let offset = logs.len() as u32;
+        router.request_logs(node, offset).await?;
+        // A late reply to an earlier request is skipped
+        let part = reply(router, rec_buf, node, |msg, data| match msg {
+            Control::Logs { offset: at, total } if at == offset => Some((total, data.to_vec())),
+            _ => None,
+        });
+        let Ok(part) = tokio::time::timeout(timeout, part).await else {
+            eprintln!("Node {} didn't send its logs from byte {}", node, offset);
+            return Ok(logs);
+        };
+        let (total, data) = part?;
+        logs.extend_from_slice(&data);
+        if data.is_empty() || logs.len() >= total as usize {
+            return Ok(logs);
+        }
+    }
+}
+
+/// Receives until `node` sends a Control message which `wanted` picks, given the data after it
+async fn reply<T>(
+    router: &mut Router,
+    rec_buf: &mut Vec<RxPacket>,
+    node: u8,
+    wanted: impl Fn(Control, &[u8]) -> Option<T>,
+) -> Result<T, MeshRouterError<Error>> {
+    loop {
+        router.listen(rec_buf).await?;
+        for Delivered { packet: pkt, .. } in router.receive((), rec_buf).await? {
+            if pkt.packet_type != PacketType::Control || pkt.source_id != node {
+                continue;
+            }
+            if let Some(found) =
+                Control::decode(&pkt.payload).and_then(|(msg, data)| wanted(msg, data))
+            {
+                return Ok(found);
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Drops packets the gateway heard moments ago.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Packets heard within the last `window`, by network, source and packet id.
+///
+/// A moving gateway hears the same packet relayed by every node it passes, within a short time.
+/// A retransmission only comes after the node's ACK timeout, so a window shorter than that drops
+/// the copies while a lost ACK is still answered.
+#[derive(Debug, Clone)]
+pub struct RecentPackets {
+    window: Duration,
+    seen: VecDeque<((u8, u8, u16), Instant)>,
+}
+
+impl RecentPackets {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: VecDeque::new(),
+        }
+    }
+
+    /// Whether `(network_id, source_id, packet_id)` was heard within the window. Remembers it if
+    /// not.
+    pub fn is_duplicate(&mut self, pid: (u8, u8, u16), now: Instant) -> bool {
+        while let Some(&(_, heard)) = self.seen.front() {
+            if now.duration_since(heard) <= self.window {
+                break;
+            }
+            self.seen.pop_front();
+        }
+        if self.seen.iter().any(|(seen, _)| *seen == pid) {
+            return true;
+        }
+        self.seen.push_back((pid, now));
+        false
+    }
+}
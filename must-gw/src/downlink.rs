@@ -0,0 +1,246 @@
+//! Shares the gateway's airtime fairly among the nodes it sends to.
+//!
+//! While the concentrator is emitting, further frames wait here instead of in the order they
+//! came. The most urgent class goes first, ACKs before commands before bulk data, so a long
+//! transfer to one node can't hold up the ACKs everyone else is waiting for. Within a class the
+//! destinations take turns by deficit round robin: each turn a destination gets `quantum` more
+//! bytes of credit, and sends frames as long as its credit covers them. A node getting many long
+//! frames gets no more bytes on the air than one getting a few, it just waits longer.
+use std::collections::{HashMap, VecDeque};
+
+use must_hop::node::{MHPacket, PacketType, control::Control};
+
+/// Credit a destination gets per turn, unless `DownlinkScheduler::new` says. One full frame's
+/// worth, so every turn sends at least one frame.
+pub const DEFAULT_QUANTUM: usize = 256;
+
+/// How urgent a frame is, most urgent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DownlinkClass {
+    /// ACKs, bootups and link tests, which the other end times out on, and the `Control::Quiet`
+    /// starting a quiet period
+    Ack,
+    /// Control requests and replies
+    Command,
+    /// Data, e.g. the chunks of a firmware update
+    Bulk,
+}
+
+impl DownlinkClass {
+    pub fn of<const SIZE: usize>(pkt: &MHPacket<SIZE>) -> Self {
+        match pkt.packet_type {
+            PacketType::Ack | PacketType::BootUp | PacketType::LinkTest => DownlinkClass::Ack,
+            PacketType::Control => match Control::decode(&pkt.payload) {
+                Some((Control::Quiet { .. }, _)) => DownlinkClass::Ack,
+                _ => DownlinkClass::Command,
+            },
+            PacketType::Data => DownlinkClass::Bulk,
+        }
+    }
+
+    /// That of the most urgent packet in a frame, Bulk for an empty one
+    pub fn of_frame<const SIZE: usize>(packets: &[MHPacket<SIZE>]) -> Self {
+        packets
+            .iter()
+            .map(Self::of)
+            .min()
+            .unwrap_or(DownlinkClass::Bulk)
+    }
+}
+
+/// Who a frame is for: a node, or None for frames to several or to everyone
+pub type Destination = Option<u8>;
+
+/// Who a frame of `packets` is for
+pub fn destination<const SIZE: usize>(packets: &[MHPacket<SIZE>]) -> Destination {
+    let node = packets.first()?.destination_id;
+    packets
+        .iter()
+        .all(|pkt| pkt.destination_id == node)
+        .then_some(node)
+}
+
+struct Flow<F> {
+    deficit: usize,
+    /// With their cost in bytes
+    frames: VecDeque<(F, usize)>,
+}
+
+/// Destinations with frames waiting in one class, taking turns
+struct Class<F> {
+    turns: VecDeque<Destination>,
+    flows: HashMap<Destination, Flow<F>>,
+    /// Whether the destination at the front got its quantum for this turn
+    credited: bool,
+}
+
+impl<F> Class<F> {
+    fn new() -> Self {
+        Self {
+            turns: VecDeque::new(),
+            flows: HashMap::new(),
+            credited: false,
+        }
+    }
+
+    fn pop(&mut self, quantum: usize) -> Option<F> {
+        loop {
+            let destination = *self.turns.front()?;
+            let flow = self.flows.get_mut(&destination)?;
+            if !self.credited {
+                flow.deficit += quantum;
+                self.credited = true;
+            }
+            let (_, cost) = flow.frames.front()?;
+            if *cost > flow.deficit {
+                // Its credit carries over to its next turn
+                self.turns.rotate_left(1);
+                self.credited = false;
+                continue;
+            }
+            flow.deficit -= *cost;
+            let (frame, _) = flow.frames.pop_front()?;
+            if flow.frames.is_empty() {
+                // Credit isn't saved up while there is nothing to send
+                self.flows.remove(&destination);
+                self.turns.pop_front();
+                self.credited = false;
+            }
+            return Some(frame);
+        }
+    }
+}
+
+/// Frames waiting for the concentrator, in the order they should go out
+pub struct DownlinkScheduler<F> {
+    quantum: usize,
+    classes: [Class<F>; 3],
+    len: usize,
+}
+
+impl<F> DownlinkScheduler<F> {
+    /// Destinations get `quantum` bytes of credit per turn, e.g. `DEFAULT_QUANTUM`
+    pub fn new(quantum: usize) -> Self {
+        Self {
+            quantum: quantum.max(1),
+            classes: [Class::new(), Class::new(), Class::new()],
+            len: 0,
+        }
+    }
+
+    /// Queues `frame` of `cost` bytes for `destination`
+    pub fn push(&mut self, destination: Destination, class: DownlinkClass, cost: usize, frame: F) {
+        let class = &mut self.classes[class as usize];
+        let flow = class.flows.entry(destination).or_insert_with(|| Flow {
+            deficit: 0,
+            frames: VecDeque::new(),
+        });
+        if flow.frames.is_empty() {
+            class.turns.push_back(destination);
+        }
+        flow.frames.push_back((frame, cost));
+        self.len += 1;
+    }
+
+    /// The frame to send next
+    pub fn pop(&mut self) -> Option<F> {
+        self.pop_up_to(DownlinkClass::Bulk)
+    }
+
+    /// The frame to send next of those in `class` or a more urgent one, e.g. only ACKs during a
+    /// quiet period
+    pub fn pop_up_to(&mut self, class: DownlinkClass) -> Option<F> {
+        let quantum = self.quantum;
+        let frame = self.classes[..=class as usize]
+            .iter_mut()
+            .find_map(|class| class.pop(quantum))?;
+        self.len -= 1;
+        Some(frame)
+    }
+
+    /// Frames waiting
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Frames waiting in `class`
+    pub fn waiting(&self, class: DownlinkClass) -> usize {
+        self.classes[class as usize]
+            .flows
+            .values()
+            .map(|flow| flow.frames.len())
+            .sum()
+    }
+}
+
+impl<F> Default for DownlinkScheduler<F> {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUANTUM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(scheduler: &mut DownlinkScheduler<&'static str>) -> Vec<&'static str> {
+        std::iter::from_fn(|| scheduler.pop()).collect()
+    }
+
+    #[test]
+    fn test_more_urgent_classes_go_first() {
+        let mut scheduler = DownlinkScheduler::default();
+        scheduler.push(Some(1), DownlinkClass::Bulk, 200, "chunk");
+        scheduler.push(Some(2), DownlinkClass::Command, 20, "request");
+        scheduler.push(Some(3), DownlinkClass::Ack, 10, "ack");
+        assert_eq!(scheduler.len(), 3);
+        assert_eq!(scheduler.waiting(DownlinkClass::Bulk), 1);
+
+        assert_eq!(scheduler.pop_up_to(DownlinkClass::Ack), Some("ack"));
+        // Only ACKs during a quiet period
+        assert_eq!(scheduler.pop_up_to(DownlinkClass::Ack), None);
+        assert_eq!(drain(&mut scheduler), ["request", "chunk"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_destinations_share_bytes() {
+        let mut scheduler = DownlinkScheduler::new(100);
+        for frame in ["a1", "a2", "a3"] {
+            scheduler.push(Some(1), DownlinkClass::Bulk, 100, frame);
+        }
+        for frame in ["b1", "b2", "b3", "b4"] {
+            scheduler.push(Some(2), DownlinkClass::Bulk, 50, frame);
+        }
+        assert_eq!(
+            drain(&mut scheduler),
+            ["a1", "b1", "b2", "a2", "b3", "b4", "a3"]
+        );
+    }
+
+    #[test]
+    fn test_credit_carries_over_to_the_next_turn() {
+        let mut scheduler = DownlinkScheduler::new(100);
+        scheduler.push(Some(1), DownlinkClass::Bulk, 150, "a1");
+        scheduler.push(Some(1), DownlinkClass::Bulk, 150, "a2");
+        scheduler.push(Some(2), DownlinkClass::Bulk, 100, "b1");
+        scheduler.push(Some(2), DownlinkClass::Bulk, 100, "b2");
+        // a1 waits a turn for credit to cover it
+        assert_eq!(drain(&mut scheduler), ["b1", "a1", "b2", "a2"]);
+    }
+
+    #[test]
+    fn test_credit_isnt_saved_while_idle() {
+        let mut scheduler = DownlinkScheduler::new(100);
+        scheduler.push(Some(1), DownlinkClass::Bulk, 10, "short");
+        assert_eq!(scheduler.pop(), Some("short"));
+        // The 90 left went with the flow
+        scheduler.push(Some(1), DownlinkClass::Bulk, 150, "long");
+        scheduler.push(None, DownlinkClass::Bulk, 100, "broadcast");
+        assert_eq!(drain(&mut scheduler), ["broadcast", "long"]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Rules deciding where received packets go upstream, so test traffic can be diverted and junk
+//! suppressed without a rebuild.
+//!
+//! `MUST_GW_FILTERS` lists the rules, separated by `;`, each conditions and an action:
+//! `source=200-254 => route 2; type=linktest => drop; rssi<-125 => drop; network=3 => accept`.
+//! A rule matches when all its conditions do, and the first rule matching a packet decides. A
+//! packet no rule matches is accepted, and goes to its own network's uplink like without rules.
+//! The conditions are `source` (an id or a range of ids), `type` (the packet type), `network` and
+//! `rssi<` (weaker than, in dBm, never matching a packet with no RSSI). `route <network>` sends
+//! the packet to the uplink of another network the gateway serves instead.
+use std::{fmt, ops::RangeInclusive};
+
+use must_hop::prelude::{Dbm, PacketType};
+
+use crate::uplink::Uplink;
+
+/// What happens to a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// To its network's uplink
+    Accept,
+    /// Nowhere
+    Drop,
+    /// To the uplink of this network instead
+    Route(u8),
+}
+
+/// Conditions of a rule, those left `None` match every packet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conditions {
+    pub sources: Option<RangeInclusive<u8>>,
+    pub packet_type: Option<PacketType>,
+    pub network: Option<u8>,
+    /// Heard weaker than this
+    pub rssi_below: Option<Dbm<i16>>,
+}
+
+impl Conditions {
+    fn matches(&self, uplink: &Uplink) -> bool {
+        let packet = &uplink.packet;
+        self.sources
+            .as_ref()
+            .is_none_or(|sources| sources.contains(&packet.source_id))
+            && self
+                .packet_type
+                .is_none_or(|packet_type| packet.packet_type == packet_type)
+            && self
+                .network
+                .is_none_or(|network| packet.network_id == network)
+            && self
+                .rssi_below
+                .is_none_or(|threshold| uplink.link.is_some_and(|link| link.rssi < threshold))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub conditions: Conditions,
+    pub action: Action,
+}
+
+/// The rules, in the order they are tried
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filters {
+    rules: Vec<Rule>,
+}
+
+impl Filters {
+    /// Reads `MUST_GW_FILTERS`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let rules = s
+            .split(';')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(rule)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The networks packets may be routed to, which need an uplink
+    pub fn routes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.rules.iter().filter_map(|rule| match rule.action {
+            Action::Route(network) => Some(network),
+            _ => None,
+        })
+    }
+
+    /// What happens to `uplink`, by the first rule matching it
+    pub fn decide(&self, uplink: &Uplink) -> Action {
+        self.rules
+            .iter()
+            .find(|rule| rule.conditions.matches(uplink))
+            .map_or(Action::Accept, |rule| rule.action)
+    }
+}
+
+/// A rule like `source=200-254,type=data => route 2`
+fn rule(s: &str) -> Result<Rule, String> {
+    let (conditions, action) = s
+        .split_once("=>")
+        .ok_or_else(|| format!("expected <conditions> => <action>, got {}", s))?;
+    Ok(Rule {
+        conditions: parse_conditions(conditions)?,
+        action: parse_action(action.trim())?,
+    })
+}
+
+fn parse_conditions(s: &str) -> Result<Conditions, String> {
+    let mut conditions = Conditions::default();
+    for condition in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        if let Some(rssi) = condition.strip_prefix("rssi<") {
+            conditions.rssi_below = Some(Dbm(number(rssi)?));
+            continue;
+        }
+        let (key, value) = condition
+            .split_once('=')
+            .ok_or_else(|| format!("expected <condition>=<value>, got {}", condition))?;
+        let value = value.trim();
+        match key.trim() {
+            "source" => {
+                conditions.sources = Some(match value.split_once('-') {
+                    Some((low, high)) => number(low)?..=number(high)?,
+                    None => number(value)?..=number(value)?,
+                })
+            }
+            "type" => conditions.packet_type = Some(packet_type(value)?),
+            "network" => conditions.network = Some(number(value)?),
+            other => return Err(format!("unknown filter condition {}", other)),
+        }
+    }
+    Ok(conditions)
+}
+
+fn parse_action(s: &str) -> Result<Action, String> {
+    match s.split_whitespace().collect::<Vec<_>>()[..] {
+        ["accept"] => Ok(Action::Accept),
+        ["drop"] => Ok(Action::Drop),
+        ["route", network] => Ok(Action::Route(number(network)?)),
+        _ => Err(format!(
+            "expected accept, drop or route <network>, got {}",
+            s
+        )),
+    }
+}
+
+fn packet_type(s: &str) -> Result<PacketType, String> {
+    Ok(match s.to_ascii_lowercase().as_str() {
+        "data" => PacketType::Data,
+        "ack" => PacketType::Ack,
+        "bootup" => PacketType::BootUp,
+        "linktest" => PacketType::LinkTest,
+        "control" => PacketType::Control,
+        _ => return Err(format!("unknown packet type {}", s)),
+    })
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid number {}: {}", value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use must_hop::{
+        node::{LinkQuality, MHPacket},
+        prelude::Db,
+    };
+
+    use super::*;
+
+    fn uplink(source_id: u8, packet_type: PacketType, rssi: Option<i16>) -> Uplink {
+        Uplink {
+            packet: MHPacket {
+                network_id: 1,
+                destination_id: 0,
+                packet_type,
+                flags: 0,
+                packet_id: 7,
+                source_id,
+                max_payload: 40,
+                payload: heapless::Vec::new(),
+                hop_count: 0,
+                hop_to_gw: 1,
+                route: heapless::Vec::new(),
+            },
+            received_at: 1_700_000_000,
+            relayed_by: None,
+            hops: Some(1),
+            link: rssi.map(|rssi| LinkQuality {
+                rssi: Dbm(rssi),
+                snr: Db(5),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let filters =
+            Filters::parse("source=200-254 => route 2; type=linktest => drop; rssi<-125 => drop; ")
+                .unwrap();
+        assert_eq!(
+            filters.rules()[0],
+            Rule {
+                conditions: Conditions {
+                    sources: Some(200..=254),
+                    ..Default::default()
+                },
+                action: Action::Route(2),
+            }
+        );
+        assert_eq!(filters.rules()[2].conditions.rssi_below, Some(Dbm(-125)));
+        assert_eq!(filters.routes().collect::<Vec<_>>(), [2]);
+        assert_eq!(Filters::parse("").unwrap(), Filters::default());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Filters::parse("source=5").is_err());
+        assert!(Filters::parse("source=5 => forward").is_err());
+        assert!(Filters::parse("source=5 => route").is_err());
+        assert!(Filters::parse("colour=red => drop").is_err());
+        assert!(Filters::parse("type=beacon => drop").is_err());
+        assert!(Filters::parse("network=300 => drop").is_err());
+    }
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let filters =
+            Filters::parse("source=200-254, type=data => route 2; source=200-254 => drop").unwrap();
+        assert_eq!(
+            filters.decide(&uplink(210, PacketType::Data, None)),
+            Action::Route(2)
+        );
+        assert_eq!(
+            filters.decide(&uplink(210, PacketType::Ack, None)),
+            Action::Drop
+        );
+        // No rule matching, accepted
+        assert_eq!(
+            filters.decide(&uplink(5, PacketType::Data, None)),
+            Action::Accept
+        );
+    }
+
+    #[test]
+    fn test_rssi_never_matches_without_a_link() {
+        let filters = Filters::parse("rssi<-125 => drop").unwrap();
+        assert_eq!(
+            filters.decide(&uplink(5, PacketType::Data, Some(-130))),
+            Action::Drop
+        );
+        assert_eq!(
+            filters.decide(&uplink(5, PacketType::Data, Some(-125))),
+            Action::Accept
+        );
+        assert_eq!(
+            filters.decide(&uplink(5, PacketType::Data, None)),
+            Action::Accept
+        );
+    }
+}
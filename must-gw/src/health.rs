@@ -0,0 +1,173 @@
+//! Health of the gateway device as a whole: the host's load, memory and SoC temperature next to
+//! the concentrator's temperature. The host stats come from /proc and /sys, so they are only
+//! there on Linux.
+use std::{fmt, fs, thread};
+
+use crate::clock::ClockStats;
+
+const LOADAVG: &str = "/proc/loadavg";
+const MEMINFO: &str = "/proc/meminfo";
+/// The SoC's sensor on a Raspberry Pi, and the CPU's on most other boards
+const SOC_THERMAL: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+/// Memory of the host, in kB as /proc/meminfo counts it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Memory {
+    pub total_kb: u64,
+    /// What can be allocated without swapping, including caches the kernel would drop
+    pub available_kb: u64,
+}
+
+impl Memory {
+    pub fn used_percent(&self) -> f32 {
+        if self.total_kb == 0 {
+            return 0.0;
+        }
+        100.0 * (self.total_kb - self.available_kb.min(self.total_kb)) as f32 / self.total_kb as f32
+    }
+}
+
+/// A snapshot of the gateway's health, with None for what couldn't be read
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SystemHealth {
+    /// Load average over 1, 5 and 15 minutes
+    pub load: Option<[f32; 3]>,
+    /// CPUs the load is spread over
+    pub cpus: Option<usize>,
+    pub memory: Option<Memory>,
+    pub soc_temp_c: Option<f32>,
+    pub concentrator_temp_c: Option<f32>,
+    /// Of the GPS discipline, if the gateway has one
+    pub clock: Option<ClockStats>,
+}
+
+impl SystemHealth {
+    /// Reads the host's stats now, along with the concentrator's temperature if the caller has it
+    pub fn read(concentrator_temp_c: Option<f32>) -> Self {
+        Self {
+            load: read_with(LOADAVG, parse_loadavg),
+            cpus: thread::available_parallelism().ok().map(|n| n.get()),
+            memory: read_with(MEMINFO, parse_meminfo),
+            soc_temp_c: read_with(SOC_THERMAL, parse_millidegrees),
+            concentrator_temp_c,
+            clock: None,
+        }
+    }
+}
+
+fn read_with<T>(path: &str, parse: fn(&str) -> Option<T>) -> Option<T> {
+    fs::read_to_string(path).ok().and_then(|s| parse(&s))
+}
+
+/// `0.12 0.08 0.05 1/123 4567`, of which the first three fields are the load
+fn parse_loadavg(s: &str) -> Option<[f32; 3]> {
+    let mut fields = s.split_whitespace().map(|f| f.parse().ok());
+    Some([fields.next()??, fields.next()??, fields.next()??])
+}
+
+/// Lines of `MemTotal:        3884076 kB`
+fn parse_meminfo(s: &str) -> Option<Memory> {
+    let field = |name: &str| {
+        s.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.split_whitespace().next()?.parse().ok())
+    };
+    Some(Memory {
+        total_kb: field("MemTotal")?,
+        available_kb: field("MemAvailable")?,
+    })
+}
+
+/// The kernel reports thermal zones in thousandths of a degree
+fn parse_millidegrees(s: &str) -> Option<f32> {
+    s.trim().parse::<i32>().ok().map(|m| m as f32 / 1000.0)
+}
+
+/// Writes `value` with `write`, or n/a if it is missing
+fn or_na<T>(
+    f: &mut fmt::Formatter<'_>,
+    value: Option<T>,
+    write: impl FnOnce(&mut fmt::Formatter<'_>, T) -> fmt::Result,
+) -> fmt::Result {
+    match value {
+        Some(value) => write(f, value),
+        None => write!(f, "n/a"),
+    }
+}
+
+impl fmt::Display for SystemHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "load ")?;
+        or_na(f, self.load, |f, [l1, l5, l15]| {
+            write!(f, "{:.2}/{:.2}/{:.2}", l1, l5, l15)
+        })?;
+        if let Some(cpus) = self.cpus {
+            write!(f, " on {} CPUs", cpus)?;
+        }
+        write!(f, ", memory ")?;
+        or_na(f, self.memory, |f, m| {
+            write!(f, "{:.0}% of {} MiB", m.used_percent(), m.total_kb / 1024)
+        })?;
+        write!(f, ", SoC ")?;
+        or_na(f, self.soc_temp_c, |f, t| write!(f, "{:.1} C", t))?;
+        write!(f, ", concentrator ")?;
+        or_na(f, self.concentrator_temp_c, |f, t| write!(f, "{:.1} C", t))?;
+        write!(f, ", clock ")?;
+        or_na(f, self.clock, |f, c| write!(f, "{}", c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_loadavg() {
+        assert_eq!(
+            parse_loadavg("0.12 0.08 0.05 1/123 4567\n"),
+            Some([0.12, 0.08, 0.05])
+        );
+        assert_eq!(parse_loadavg("0.12 0.08"), None);
+        assert_eq!(parse_loadavg("0.12 high 0.05"), None);
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:        3884076 kB\n\
+                       MemFree:          123456 kB\n\
+                       MemAvailable:    2942057 kB\n";
+        let memory = parse_meminfo(meminfo).unwrap();
+        assert_eq!(
+            memory,
+            Memory {
+                total_kb: 3884076,
+                available_kb: 2942057,
+            }
+        );
+        assert!((memory.used_percent() - 24.25).abs() < 0.01);
+        // Kernels before 3.14 don't have MemAvailable
+        assert_eq!(
+            parse_meminfo("MemTotal: 3884076 kB\nMemFree: 123456 kB\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_millidegrees() {
+        assert_eq!(parse_millidegrees("48312\n"), Some(48.312));
+        assert_eq!(parse_millidegrees("-5000"), Some(-5.0));
+        assert_eq!(parse_millidegrees(""), None);
+    }
+
+    #[test]
+    fn test_missing_stats_show_as_na() {
+        let health = SystemHealth {
+            load: Some([0.5, 0.25, 0.1]),
+            ..SystemHealth::default()
+        };
+        assert_eq!(
+            health.to_string(),
+            "load 0.50/0.25/0.10, memory n/a, SoC n/a, concentrator n/a, clock n/a"
+        );
+    }
+}
@@ -0,0 +1,29 @@
+//! The gateway's identity, so gateways sharing a deployment can be told apart without assigning
+//! ids by hand.
+use std::{fmt, num::ParseIntError, str::FromStr};
+
+/// A gateway's id, the EUI of its concentrator unless configured otherwise. Shown as 16 hex
+/// digits, like a LoRaWAN gateway EUI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct GatewayId(pub u64);
+
+impl fmt::Display for GatewayId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016X}", self.0)
+    }
+}
+
+impl FromStr for GatewayId {
+    type Err = ParseIntError;
+
+    /// Hex digits, optionally with a `0x` prefix and `:` or `-` between bytes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits: String = s
+            .trim()
+            .trim_start_matches("0x")
+            .chars()
+            .filter(|c| *c != ':' && *c != '-')
+            .collect();
+        u64::from_str_radix(&digits, 16).map(GatewayId)
+    }
+}
@@ -1,59 +1,64 @@
 use loragw::{
-    BoardConf, ChannelConf, Concentrator, Error, Running, RxRFConf, TxGain, cfg::Config,
-    raspberrypi,
+    ComType, Concentrator, Error, RecoveryPolicy, ResetToken, Running, cfg::Config, raspberrypi,
 };
 
 pub const SIZE: usize = 128;
+/// Packets per frame, lets keep it the same as the nodes, make it simple
+pub const LEN: usize = 5;
 
+pub mod adr;
+pub mod clock;
+pub mod control;
+pub mod dedup;
+pub mod downlink;
+pub mod filter;
+pub mod health;
+pub mod identity;
+pub mod linktest;
+pub mod liveness;
+pub mod locate;
+pub mod network;
 pub mod node;
+pub mod poll;
+pub mod quiet;
+pub mod schema;
+pub mod shutdown;
+pub mod store;
+pub mod tls;
+pub mod trace;
+pub mod uplink;
+pub mod utilization;
 
-/// Default constructor when using the SX1302 on top of a Raspberry pi 4B
+/// Default constructor when using the SX1302 on top of a Raspberry pi 4B, or a Corecell USB
+/// concentrator if the configuration's board says `com_type = "USB"`
 pub fn create_concentrator() -> Result<Concentrator<Running>, Error> {
     let conf = Config::from_str_or_default(None)?;
+    let usb = conf.board.com_type == ComType::USB;
 
-    let board_conf = BoardConf::try_from(conf.board.clone()).map_err(Error::from)?;
-
-    let radios: Vec<RxRFConf> = match &conf.radios {
-        Some(r_vec) => r_vec
-            .iter()
-            .map(|r| RxRFConf::try_from(r.clone()).map_err(Error::from))
-            .collect::<Result<Vec<_>, _>>()?,
-        None => Vec::new(),
+    let token = if usb {
+        // Its STM32 resets the SX1302 itself, there's no GPIO to pull
+        ResetToken::usb()
+    } else {
+        println!("Resetting board first ...");
+        ResetToken::generate(raspberrypi::reset_lgw).expect("Failed to generate reset token")
     };
-
-    let channels: Vec<(u8, ChannelConf)> = match &conf.multirate_channels {
-        Some(ch_vec) => ch_vec
-            .iter()
-            .enumerate()
-            .map(|(i, c)| {
-                let conf = ChannelConf::try_from(c).map_err(Error::from)?;
-                Ok((i as u8, conf))
-            })
-            .collect::<Result<Vec<_>, Error>>()?,
-        None => Vec::new(),
+    let reset = move || {
+        if usb {
+            Ok(())
+        } else {
+            raspberrypi::reset_lgw()
+        }
     };
 
-    // Returns a slice &[TxGain] derived from the owned 'conf'
-    let tx_gains: Vec<TxGain> = conf
-        .tx_gains
-        .as_ref()
-        .map(|gains| {
-            gains
-                .iter()
-                .map(|g| TxGain::from(g.clone())) // Convert ConfTxGain -> TxGain
-                .collect()
-        })
-        .unwrap_or_default();
-    println!("Resetting board first ...");
-    let token = loragw::ResetToken::generate(|| raspberrypi::reset_lgw())
-        .expect("Failed to generate reset token");
-
     println!("Starting concentrator...");
     Concentrator::open(&token)?
-        .set_config_board(board_conf)
-        .set_rx_rfs(radios)
-        .set_config_channels(channels)
-        .set_config_tx_gains(&tx_gains)
+        .set_config(&conf)?
+        .set_recovery_policy(RecoveryPolicy::new(3, reset).on_recovery(|event| {
+            eprintln!(
+                "Concentrator restarted after {} errors ({}): {:?}",
+                event.errors, event.last_error, event.result
+            )
+        }))
         .connect()?
         .start()
 }
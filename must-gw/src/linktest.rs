@@ -0,0 +1,75 @@
+//! Report of a `must-gw linktest`, one row per spreading factor.
+use std::fmt;
+
+use embassy_time::Duration;
+use must_hop::node::{LinkQuality, linktest::SfResult};
+
+/// Spreading factors a link test goes through, fastest first
+pub const SPREADING_FACTORS: std::ops::RangeInclusive<u8> = 7..=12;
+
+/// Time between probes on `spreading_factor`, enough for a probe and its echo on the air.
+/// Airtime doubles with every step up in spreading factor.
+pub fn probe_interval(spreading_factor: u8) -> Duration {
+    Duration::from_millis(200 << spreading_factor.saturating_sub(7).min(5))
+}
+
+/// Results of testing the link to one node
+#[derive(Debug, Clone)]
+pub struct LinkTestReport {
+    node: u8,
+    results: Vec<SfResult>,
+}
+
+impl LinkTestReport {
+    pub fn new(node: u8) -> Self {
+        Self {
+            node,
+            results: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, result: SfResult) {
+        self.results.push(result);
+    }
+
+    pub fn results(&self) -> &[SfResult] {
+        &self.results
+    }
+}
+
+/// `-87 dBm 7 dB`, or `-` if nothing was heard
+struct Quality(Option<LinkQuality>);
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self.0 {
+            Some(q) => format!("{} {}", q.rssi, q.snr),
+            None => "-".to_string(),
+        };
+        f.pad(&text)
+    }
+}
+
+impl fmt::Display for LinkTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Link test to node {}", self.node)?;
+        writeln!(
+            f,
+            "{:>4} {:>6} {:>6} {:>6}  {:<16} {:<16}",
+            "SF", "sent", "echoed", "ratio", "downlink", "uplink"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "{:>4} {:>6} {:>6} {:>5.0}%  {:<16} {:<16}",
+                result.spreading_factor,
+                result.sent,
+                result.echoed,
+                result.delivery_ratio() * 100.0,
+                Quality(result.downlink.mean()),
+                Quality(result.uplink.mean()),
+            )?;
+        }
+        Ok(())
+    }
+}
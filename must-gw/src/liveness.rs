@@ -0,0 +1,244 @@
+//! Watches that every node keeps reporting, and raises an alert when one falls silent.
+//!
+//! Each node reports at an interval, either configured with `expect` or learned from the gaps
+//! between its packets. A node which missed `missed_intervals` of them in a row is alerted on
+//! once, with how it was last heard, until it reports again.
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use must_hop::prelude::{Db, Dbm};
+
+/// Weight of the newest gap when learning a node's interval
+const LEARNING_RATE: f64 = 0.25;
+
+/// How a node was last heard, by the last hop to the gateway
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LastHeard {
+    pub at: Instant,
+    pub rssi: Dbm,
+    pub snr: Db,
+}
+
+#[derive(Debug, Clone)]
+struct Expectation {
+    /// Configured by the operator, or None while it is learned
+    configured: Option<Duration>,
+    learned: Option<Duration>,
+    last: Option<LastHeard>,
+    alerted: bool,
+}
+
+impl Expectation {
+    fn interval(&self) -> Option<Duration> {
+        self.configured.or(self.learned)
+    }
+}
+
+/// A node which missed its reports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alert {
+    pub node: u8,
+    /// Intervals missed in a row
+    pub missed: u32,
+    pub interval: Duration,
+    /// None if the node was never heard since the gateway started
+    pub last: Option<LastHeard>,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "node {} missed {} reports, expected every {:?}",
+            self.node, self.missed, self.interval
+        )?;
+        match self.last {
+            Some(last) => write!(
+                f,
+                ", last heard {:?} ago at {:.0}, SNR {:.1}",
+                last.at.elapsed(),
+                last.rssi,
+                last.snr
+            ),
+            None => write!(f, ", never heard"),
+        }
+    }
+}
+
+/// Report intervals per node, and when each was last heard
+#[derive(Debug, Clone)]
+pub struct LivenessMonitor {
+    missed_intervals: u32,
+    /// When monitoring started, so a configured node which is never heard is alerted on too
+    started: Instant,
+    nodes: BTreeMap<u8, Expectation>,
+}
+
+impl LivenessMonitor {
+    pub fn new(missed_intervals: u32, now: Instant) -> Self {
+        Self {
+            missed_intervals: missed_intervals.max(1),
+            started: now,
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    /// Expect `node` to report every `interval`, instead of learning it
+    pub fn expect(&mut self, node: u8, interval: Duration) {
+        self.entry(node).configured = Some(interval);
+    }
+
+    /// Parses expectations like `2=300,3=600`, node ids and report intervals in seconds
+    pub fn expect_from_str(&mut self, s: &str) -> Result<(), String> {
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (node, secs) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected <node>=<seconds>, got {}", pair))?;
+            let node = node
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid node id {}: {}", node, e))?;
+            let secs = secs
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid interval {}: {}", secs, e))?;
+            self.expect(node, Duration::from_secs(secs));
+        }
+        Ok(())
+    }
+
+    /// `node` was heard, at `rssi` and `snr`
+    pub fn heard(&mut self, node: u8, rssi: Dbm, snr: Db, now: Instant) {
+        let entry = self.entry(node);
+        if let Some(last) = entry.last {
+            let gap = now.saturating_duration_since(last.at);
+            // Several packets of one report arrive together, they aren't a gap
+            if gap >= Duration::from_secs(1) {
+                entry.learned = Some(match entry.learned {
+                    Some(learned) => {
+                        learned.mul_f64(1.0 - LEARNING_RATE) + gap.mul_f64(LEARNING_RATE)
+                    }
+                    None => gap,
+                });
+            }
+        }
+        entry.last = Some(LastHeard { at: now, rssi, snr });
+        entry.alerted = false;
+    }
+
+    /// Nodes which just missed `missed_intervals` reports. Each is returned once, until it is
+    /// heard again.
+    pub fn check(&mut self, now: Instant) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (&node, entry) in self.nodes.iter_mut() {
+            let Some(interval) = entry.interval() else {
+                continue;
+            };
+            if entry.alerted || interval.is_zero() {
+                continue;
+            }
+            let since = entry.last.map_or(self.started, |last| last.at);
+            let missed = (now.saturating_duration_since(since).as_secs_f64()
+                / interval.as_secs_f64()) as u32;
+            if missed >= self.missed_intervals {
+                entry.alerted = true;
+                alerts.push(Alert {
+                    node,
+                    missed,
+                    interval,
+                    last: entry.last,
+                });
+            }
+        }
+        alerts
+    }
+
+    /// The interval `node` is expected to report at, configured or learned
+    pub fn interval(&self, node: u8) -> Option<Duration> {
+        self.nodes.get(&node).and_then(Expectation::interval)
+    }
+
+    fn entry(&mut self, node: u8) -> &mut Expectation {
+        self.nodes.entry(node).or_insert(Expectation {
+            configured: None,
+            learned: None,
+            last: None,
+            alerted: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn heard(monitor: &mut LivenessMonitor, node: u8, at: Instant) {
+        monitor.heard(node, Dbm(-90.0), Db(5.0), at);
+    }
+
+    #[test]
+    fn test_interval_is_learned_from_gaps() {
+        let start = Instant::now();
+        let mut monitor = LivenessMonitor::new(3, start);
+        heard(&mut monitor, 2, start);
+        assert_eq!(monitor.interval(2), None);
+        heard(&mut monitor, 2, start + 10 * MINUTE);
+        assert_eq!(monitor.interval(2), Some(10 * MINUTE));
+        // A quarter of the way towards the newest gap
+        heard(&mut monitor, 2, start + 12 * MINUTE);
+        assert_eq!(monitor.interval(2), Some(8 * MINUTE));
+        // Packets of the same report aren't a gap
+        heard(
+            &mut monitor,
+            2,
+            start + 12 * MINUTE + Duration::from_millis(200),
+        );
+        assert_eq!(monitor.interval(2), Some(8 * MINUTE));
+    }
+
+    #[test]
+    fn test_alerts_once_until_heard_again() {
+        let start = Instant::now();
+        let mut monitor = LivenessMonitor::new(3, start);
+        monitor.expect(2, MINUTE);
+        heard(&mut monitor, 2, start);
+        assert!(monitor.check(start + 2 * MINUTE).is_empty());
+
+        let alerts = monitor.check(start + 3 * MINUTE);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].node, 2);
+        assert_eq!(alerts[0].missed, 3);
+        assert_eq!(alerts[0].last.map(|last| last.at), Some(start));
+        assert!(monitor.check(start + 10 * MINUTE).is_empty());
+
+        heard(&mut monitor, 2, start + 11 * MINUTE);
+        // Configured, so not learned from the gap
+        assert_eq!(monitor.interval(2), Some(MINUTE));
+        assert_eq!(monitor.check(start + 14 * MINUTE).len(), 1);
+    }
+
+    #[test]
+    fn test_node_never_heard_is_alerted_on() {
+        let start = Instant::now();
+        let mut monitor = LivenessMonitor::new(2, start);
+        monitor.expect_from_str("2=60, 3=600").unwrap();
+        let alerts = monitor.check(start + 2 * MINUTE);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].node, 2);
+        assert_eq!(alerts[0].last, None);
+        assert!(alerts[0].to_string().ends_with("never heard"));
+    }
+
+    #[test]
+    fn test_bad_expectations_are_rejected() {
+        let mut monitor = LivenessMonitor::new(3, Instant::now());
+        assert!(monitor.expect_from_str("2").is_err());
+        assert!(monitor.expect_from_str("300=60").is_err());
+        assert!(monitor.expect_from_str("2=soon").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Rough positions of nodes, from how strong gateways heard them.
+//!
+//! Each gateway turns what its concentrator heard into `Reception`s. With the gateway's position
+//! they become `Observation`s, and `locate` combines the observations of one packet from several
+//! gateways. Only RSSI is used for now, the times are kept so TDOA can take over, as receptions
+//! by gateways disciplined to GPS (see `clock`) can be compared to the µs.
+//!
+//! Gateways don't exchange their receptions yet, so `locate` is for whatever gathers them from
+//! several gateways, e.g. the server their uplinks go to.
+use std::time::{Duration, SystemTime};
+
+use must_hop::{
+    node::PacketRef,
+    units::{Db, Dbm},
+};
+
+use crate::identity::GatewayId;
+
+/// Where something is, in metres east (`x`) and north (`y`) of a local origin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Position {
+    pub fn distance(&self, other: &Position) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+/// How a gateway heard one packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reception {
+    pub gateway: GatewayId,
+    pub packet: PacketRef,
+    pub rssi: Dbm,
+    pub snr: Db,
+    /// Concentrator counter when the packet was received, at 1 µs resolution. Only comparable
+    /// between gateways sharing a time base.
+    pub timestamp: Duration,
+    /// When the packet was received, from GPS if `disciplined` and from the system clock if not
+    pub time: SystemTime,
+    pub disciplined: bool,
+}
+
+/// A reception by a gateway at a known position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observation {
+    pub gateway: Position,
+    pub reception: Reception,
+}
+
+/// Log-distance path loss, turning an RSSI into a distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathLoss {
+    /// RSSI one metre from the node
+    pub rssi_at_1m: Dbm,
+    /// 2 in free space, up to 4 or more between buildings
+    pub exponent: f32,
+}
+
+impl Default for PathLoss {
+    /// A node at 14 dBm, in a suburban area
+    fn default() -> Self {
+        Self {
+            rssi_at_1m: Dbm(-30.0),
+            exponent: 2.7,
+        }
+    }
+}
+
+impl PathLoss {
+    /// Metres to a node heard at `rssi`
+    pub fn distance(&self, rssi: Dbm) -> f64 {
+        10f64.powf(((self.rssi_at_1m - rssi).0 / (10.0 * self.exponent)) as f64)
+    }
+}
+
+/// How a position was estimated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Least squares on the distances to three or more gateways
+    RssiTrilateration,
+    /// Between the gateways, closer to the ones hearing it strongest, for fewer than three
+    /// gateways or gateways in a line
+    RssiCentroid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub position: Position,
+    pub method: Method,
+    /// Gateways which heard the packet
+    pub gateways: usize,
+}
+
+/// Estimates where the sender of a packet is, from the observations of it. None without any.
+pub fn locate(observations: &[Observation], model: &PathLoss) -> Option<Estimate> {
+    let distances: Vec<(Position, f64)> = observations
+        .iter()
+        .map(|obs| (obs.gateway, model.distance(obs.reception.rssi)))
+        .collect();
+    let (position, method) = match trilaterate(&distances) {
+        Some(position) => (position, Method::RssiTrilateration),
+        None => (centroid(&distances)?, Method::RssiCentroid),
+    };
+    Some(Estimate {
+        position,
+        method,
+        gateways: observations.len(),
+    })
+}
+
+/// Subtracting the first circle from the others leaves linear equations in x and y, solved by
+/// least squares. None for fewer than three gateways, or when they are in a line.
+fn trilaterate(distances: &[(Position, f64)]) -> Option<Position> {
+    let ((p0, d0), rest) = distances.split_first()?;
+    if rest.len() < 2 {
+        return None;
+    }
+    // Normal equations, A^T A [x y]^T = A^T b
+    let (mut aa, mut ab, mut bb, mut ac, mut bc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (p, d) in rest {
+        let a = 2.0 * (p.x - p0.x);
+        let b = 2.0 * (p.y - p0.y);
+        let c = d0 * d0 - d * d + p.x * p.x - p0.x * p0.x + p.y * p.y - p0.y * p0.y;
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        ac += a * c;
+        bc += b * c;
+    }
+    let det = aa * bb - ab * ab;
+    if det.abs() < f64::EPSILON * aa.max(bb).max(1.0) {
+        return None;
+    }
+    Some(Position {
+        x: (ac * bb - bc * ab) / det,
+        y: (aa * bc - ab * ac) / det,
+    })
+}
+
+/// Gateway positions weighted by the inverse square of the distance to them
+fn centroid(distances: &[(Position, f64)]) -> Option<Position> {
+    let (mut x, mut y, mut total) = (0.0, 0.0, 0.0);
+    for (p, d) in distances {
+        let weight = 1.0 / d.max(1.0).powi(2);
+        x += p.x * weight;
+        y += p.y * weight;
+        total += weight;
+    }
+    if total == 0.0 {
+        return None;
+    }
+    Some(Position {
+        x: x / total,
+        y: y / total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gateways at three corners of a square kilometre
+    const GATEWAYS: [Position; 3] = [
+        Position { x: 0.0, y: 0.0 },
+        Position { x: 1000.0, y: 0.0 },
+        Position { x: 0.0, y: 1000.0 },
+    ];
+
+    /// How `gateway` hears a node at `node` under `model`
+    fn observation(gateway: Position, node: Position, model: &PathLoss) -> Observation {
+        let distance = gateway.distance(&node);
+        let loss = 10.0 * model.exponent * distance.log10() as f32;
+        Observation {
+            gateway,
+            reception: Reception {
+                gateway: GatewayId(1),
+                packet: PacketRef {
+                    source_id: 5,
+                    packet_id: 1,
+                },
+                rssi: Dbm(model.rssi_at_1m.0 - loss),
+                snr: Db(5.0),
+                timestamp: Duration::ZERO,
+                time: SystemTime::UNIX_EPOCH,
+                disciplined: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_path_loss_distance() {
+        let model = PathLoss::default();
+        assert!((model.distance(model.rssi_at_1m) - 1.0).abs() < 1e-6);
+        // 10 times as far is 10 * exponent dB weaker
+        let rssi = Dbm(model.rssi_at_1m.0 - 10.0 * model.exponent);
+        assert!((model.distance(rssi) - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_trilateration_finds_the_node() {
+        let model = PathLoss::default();
+        let node = Position { x: 300.0, y: 400.0 };
+        let observations: Vec<Observation> = GATEWAYS
+            .iter()
+            .map(|gateway| observation(*gateway, node, &model))
+            .collect();
+        let estimate = locate(&observations, &model).unwrap();
+        assert_eq!(estimate.method, Method::RssiTrilateration);
+        assert_eq!(estimate.gateways, 3);
+        assert!(estimate.position.distance(&node) < 1.0, "{:?}", estimate);
+    }
+
+    #[test]
+    fn test_two_gateways_take_the_centroid() {
+        let model = PathLoss::default();
+        // Halfway, so heard as strong by both
+        let node = Position { x: 500.0, y: 0.0 };
+        let observations = [
+            observation(GATEWAYS[0], node, &model),
+            observation(GATEWAYS[1], node, &model),
+        ];
+        let estimate = locate(&observations, &model).unwrap();
+        assert_eq!(estimate.method, Method::RssiCentroid);
+        assert!(estimate.position.distance(&node) < 1.0, "{:?}", estimate);
+
+        // Closer to the gateway hearing it stronger
+        let node = Position { x: 200.0, y: 0.0 };
+        let observations = [
+            observation(GATEWAYS[0], node, &model),
+            observation(GATEWAYS[1], node, &model),
+        ];
+        let estimate = locate(&observations, &model).unwrap();
+        assert!(estimate.position.x < 500.0, "{:?}", estimate);
+    }
+
+    #[test]
+    fn test_gateways_in_a_line_take_the_centroid() {
+        let model = PathLoss::default();
+        let node = Position { x: 500.0, y: 300.0 };
+        let observations: Vec<Observation> = [0.0, 500.0, 1000.0]
+            .iter()
+            .map(|x| observation(Position { x: *x, y: 0.0 }, node, &model))
+            .collect();
+        let estimate = locate(&observations, &model).unwrap();
+        assert_eq!(estimate.method, Method::RssiCentroid);
+        assert!((estimate.position.x - 500.0).abs() < 1.0, "{:?}", estimate);
+    }
+
+    #[test]
+    fn test_nothing_heard_is_nowhere() {
+        assert_eq!(locate(&[], &PathLoss::default()), None);
+    }
+}
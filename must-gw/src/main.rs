@@ -1,10 +1,172 @@
-use loragw::RxPacket;
-use must_gw::{create_concentrator, node};
-use must_hop::node::{
-    MHNode, mesh_router::MeshRouter, network_manager::NetworkManager, policy::GatewayPolicy,
+use loragw::{RX_FIFO_SIZE, RxPacket};
+use must_gw::{
+    adr::{AdrConfig, AdrEngine},
+    control, create_concentrator,
+    filter::{Action, Filters},
+    health::SystemHealth,
+    identity::GatewayId,
+    linktest::{self, LinkTestReport},
+    liveness::LivenessMonitor,
+    network::{self, NetworkConfig, Uplinked, Uplinks},
+    node,
+    quiet::QuietCalendar,
+    schema::{self, SchemaRegistry},
+    shutdown::{Shutdown, ShutdownListener},
+    store::Store,
+    tls::TlsConfig,
+    trace::{self, ControlCommand, TraceCommand, Tracer},
+    uplink::{Delivery, Uplink},
 };
+use must_hop::{
+    node::{
+        entropy::{EntropySource, Xorshift},
+        group::{self, FIRST_GROUP, GroupRegistry},
+    },
+    prelude::{
+        ConfirmedTypes, Control, Db, GatewayPolicy, MHNode, MeshRouter, NetworkManager, PacketType,
+        Region,
+    },
+    telemetry::SENSOR_REPORT,
+};
+use std::time::Duration;
+use tokio::{net::UnixStream, time::Interval};
+
+/// Saved mesh state older than this is stale after a restart, nodes will have given up on it
+const STATE_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+/// A moving gateway drops packets it heard this recently, relayed by the nodes it passed
+const MOBILE_DEDUP_WINDOW: Duration = Duration::from_secs(2);
+/// How long pending downlinks get to be ACKed on shutdown, unless MUST_GW_DRAIN_SECS says
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the gateway's health is reported, unless MUST_GW_HEALTH_SECS says
+const DEFAULT_HEALTH_INTERVAL: Duration = Duration::from_secs(60);
+/// How often nodes are checked for missed reports
+const LIVENESS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Reports a node may miss in a row before it is alerted on, unless MUST_GW_MISSED says
+const DEFAULT_MISSED_REPORTS: u32 = 3;
+
+/// Packets kept while the uplink server is down, unless MUST_GW_SPOOL_MAX says
+const DEFAULT_SPOOL_CAPACITY: usize = 10_000;
+/// How often spooled packets are sent again while no new ones arrive
+const SPOOL_REPLAY_INTERVAL: Duration = Duration::from_secs(10);
+/// How often the gateway checks whether a quiet period starts or ends
+const QUIET_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Probes per spreading factor in a link test, unless given on the command line
+const DEFAULT_LINK_TEST_PROBES: u16 = 10;
+/// How long a node gets to reply to a request, or to send each part of its logs
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the running gateway takes commands, unless MUST_GW_SOCKET says
+const DEFAULT_CONTROL_SOCKET: &str = "must-gw.sock";
+
+/// What the gateway was started to do
+enum Command {
+    /// Serve the mesh until shut down
+    Run,
+    /// `must-gw linktest <node> [probes]`: test the link to a node on every spreading factor
+    LinkTest { node: u8, probes: u16 },
+    /// `must-gw logs <node>`: print the logs a node kept
+    Logs { node: u8 },
+    /// `must-gw reboot|factory-reset|safe-mode <node>` or
+    /// `must-gw loglevel <node> <subsystem> <level>`: have a node carry out `request`
+    Manage { node: u8, request: Control },
+    /// `must-gw trace|untrace <node>`, `must-gw traces` or `must-gw maintenance [on|off]`: sent
+    /// to the running gateway
+    Control(ControlCommand),
+}
+
+impl Command {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        match args.next().as_deref() {
+            None => Ok(Command::Run),
+            Some("linktest") => {
+                let node = args
+                    .next()
+                    .ok_or("usage: must-gw linktest <node> [probes]")?
+                    .parse()
+                    .map_err(|e| format!("invalid node id: {}", e))?;
+                let probes = match args.next() {
+                    Some(probes) => probes
+                        .parse()
+                        .map_err(|e| format!("invalid probe count: {}", e))?,
+                    None => DEFAULT_LINK_TEST_PROBES,
+                };
+                Ok(Command::LinkTest { node, probes })
+            }
+            Some("logs") => Ok(Command::Logs {
+                node: node_arg(args.next(), "logs")?,
+            }),
+            Some("trace") => Ok(Command::Control(ControlCommand::Trace(
+                TraceCommand::Enable(node_arg(args.next(), "trace")?),
+            ))),
+            Some("untrace") => Ok(Command::Control(ControlCommand::Trace(
+                TraceCommand::Disable(node_arg(args.next(), "untrace")?),
+            ))),
+            Some("traces") => Ok(Command::Control(ControlCommand::Trace(TraceCommand::List))),
+            Some("maintenance") => {
+                let line = args.fold("maintenance".to_string(), |line, arg| line + " " + &arg);
+                Ok(Command::Control(ControlCommand::parse(&line)?))
+            }
+            Some("loglevel") => {
+                const USAGE: &str = "usage: must-gw loglevel <node> <nm|router|mac|radio> \
+                                     <off|error|warn|info|trace>";
+                let node = node_arg(args.next(), "loglevel")?;
+                let (Some(subsystem), Some(level)) = (args.next(), args.next()) else {
+                    return Err(USAGE.to_string());
+                };
+                let request = control::log_level_request(&subsystem, &level).ok_or(USAGE)?;
+                Ok(Command::Manage { node, request })
+            }
+            Some(other) => match control::request_for(other) {
+                Some(request) => Ok(Command::Manage {
+                    node: node_arg(args.next(), other)?,
+                    request,
+                }),
+                None => Err(format!("unknown command: {}", other)),
+            },
+        }
+    }
+}
+
+/// The node a `command` is for
+fn node_arg(arg: Option<String>, command: &str) -> Result<u8, String> {
+    arg.ok_or_else(|| format!("usage: must-gw {} <node>", command))?
+        .parse()
+        .map_err(|e| format!("invalid node id: {}", e))
+}
+
+fn control_socket_path() -> String {
+    std::env::var("MUST_GW_SOCKET").unwrap_or_else(|_| DEFAULT_CONTROL_SOCKET.to_string())
+}
+
+/// What the gateway's loop woke up for
+enum Wake<T> {
+    Bootup,
+    Health,
+    Liveness,
+    Replay,
+    Quiet,
+    Shutdown,
+    Control(std::io::Result<(UnixStream, tokio::net::unix::SocketAddr)>),
+    Uplinked(Uplinked),
+    Heard(T),
+}
+
+/// Waits for the next bootup of a mobile gateway, or forever on a fixed one
+async fn next_bootup(bootups: &mut Option<Interval>) {
+    match bootups {
+        Some(bootups) => {
+            bootups.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
 
-async fn run_concentrator_task() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+async fn run_concentrator_task(
+    command: Command,
+    mut shutdown: ShutdownListener,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("Now try and use loragw:");
 
     let conc = match create_concentrator() {
@@ -24,6 +186,41 @@ async fn run_concentrator_task() -> Result<(), Box<dyn std::error::Error + Send
 
     println!("now try receive!");
     let mut node = node::GWNode::new(conc);
+    // On a vehicle, nodes are told where we are every MUST_GW_MOBILE seconds
+    let mobile_interval = match std::env::var("MUST_GW_MOBILE") {
+        Ok(secs) => match secs.parse()? {
+            0 => return Err("MUST_GW_MOBILE must be at least 1".into()),
+            secs => Some(Duration::from_secs(secs)),
+        },
+        Err(_) => None,
+    };
+    if mobile_interval.is_some() {
+        node = node.set_dedup_window(MOBILE_DEDUP_WINDOW);
+    }
+    // The concentrator's EUI, unless two gateways should share an identity or it can't be read
+    if let Ok(gateway_id) = std::env::var("MUST_GW_ID") {
+        node = node.set_gateway_id(gateway_id.parse::<GatewayId>()?);
+    }
+    // Holds the channel plan, TX power and frame airtime to the rules of MUST_GW_REGION, e.g.
+    // `EU868`, with an antenna of MUST_GW_ANTENNA_GAIN dBi
+    if let Ok(region) = std::env::var("MUST_GW_REGION") {
+        let antenna_gain = match std::env::var("MUST_GW_ANTENNA_GAIN") {
+            Ok(gain) => Db(gain.parse()?),
+            Err(_) => Db(0),
+        };
+        node = node.set_region(region.parse::<Region>()?, antenna_gain)?;
+    }
+    // Bytes of airtime each destination gets per turn while frames wait for the concentrator
+    if let Ok(quantum) = std::env::var("MUST_GW_DOWNLINK_QUANTUM") {
+        node = node.set_downlink_quantum(quantum.parse()?);
+    }
+    // Started with MUST_GW_MAINTENANCE=1 the gateway only listens, until `must-gw maintenance off`
+    if std::env::var("MUST_GW_MAINTENANCE").is_ok_and(|on| on == "1") {
+        println!("In maintenance mode, not transmitting");
+        node.set_maintenance(true);
+    }
+    let gateway_id = node.gateway_id();
+    println!("Gateway id is {}", gateway_id);
 
     let mut rec_buf: Vec<RxPacket> = Vec::new(); // Make sure RxPacket is imported
     println!("listening again ...");
@@ -32,15 +229,543 @@ async fn run_concentrator_task() -> Result<(), Box<dyn std::error::Error + Send
     println!("got pkts: {:?} ", pkt);
 
     println!("Now making mes router ...");
-    let mut router = MeshRouter::new(node, NetworkManager::new(0, 10, 3), GatewayPolicy);
+    let mut manager = NetworkManager::new(0, 10, 3);
+    // Downlink ids and retries shouldn't repeat those of the last run
+    let boot = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+    let mut entropy = Xorshift::new(boot.subsec_nanos() ^ boot.as_secs() as u32);
+    manager.seed(&mut entropy);
+    // Nodes pick up our frequency and data rate from the bootup
+    manager.set_channel_plan(node.channel_plan());
+    manager.set_gateway_id(Some(gateway_id.0));
+    // Co-located deployments each use their own network id, or one gateway serves several,
+    // each with its own uplink
+    let networks = match std::env::var("MUST_GW_NETWORKS") {
+        Ok(spec) => network::parse_networks(&spec)?,
+        Err(_) => vec![NetworkConfig {
+            id: match std::env::var("MUST_GW_NETWORK") {
+                Ok(network_id) => network_id.parse()?,
+                Err(_) => 0,
+            },
+            uplink: std::env::var("MUST_GW_UPLINK").ok(),
+        }],
+    };
+    if let Some((own, others)) = networks.split_first() {
+        manager.set_network_id(own.id);
+        for network in others {
+            manager.serve_network(network.id)?;
+        }
+    }
+    // Payload types listed in MUST_GW_CONFIRMED, e.g. `1,3`, are only ACKed once their uplink
+    // took them, so nodes keep retrying while the backhaul is down
+    if let Ok(types) = std::env::var("MUST_GW_CONFIRMED") {
+        let types = types
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::parse)
+            .collect::<Result<ConfirmedTypes, _>>()?;
+        manager.set_confirmed_types(types);
+    }
+    let mut router = MeshRouter::new(node, manager, GatewayPolicy);
+
+    // Continue where a previous run left off, so a restart doesn't send duplicate downlinks
+    let db_path = std::env::var("MUST_GW_DB").unwrap_or_else(|_| "must-gw.db".to_string());
+    let mut store = Store::open(&db_path)?;
+    let mut state = store.load(STATE_MAX_AGE)?;
+    let (first_id, reserved_at) = store.start_packet_ids(entropy.next_u32() as u16)?;
+    state.next_packet_id = first_id;
+    match reserved_at {
+        Some(at) => println!(
+            "Packet ids continue at {} (reserved {}s ago)",
+            first_id,
+            at.elapsed().unwrap_or_default().as_secs()
+        ),
+        None => println!("Packet ids start at {}", first_id),
+    }
+    router.restore_manager_state(state)?;
+    let mut saved_state = router.manager_state();
+    println!(
+        "Restored {} pending packets from {}",
+        saved_state.pending.len(),
+        db_path
+    );
+
+    // Nodes learn their distance to us, and our channel plan, from the bootup
+    router.bootup().await?;
+
+    // Group names get addresses in the order MUST_GW_GROUPS lists them, e.g. `sensors,valves`,
+    // and nodes tell us which they are in when they join. Every node id fits.
+    let mut groups = GroupRegistry::<{ FIRST_GROUP as usize }>::new();
+    if let Ok(names) = std::env::var("MUST_GW_GROUPS") {
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let address = groups.register(name)?;
+            println!("Group {} has address {}", name, address);
+        }
+    }
+
+    // Sensor formats described in a file, so new ones don't need a rebuild
+    let schemas = match std::env::var("MUST_GW_SCHEMAS") {
+        Ok(path) => Some(SchemaRegistry::load(&path)?),
+        Err(_) => None,
+    };
+
+    // Reused between polls, so receiving doesn't allocate a new buffer every time
+    let mut rec_buf = Vec::with_capacity(RX_FIFO_SIZE);
+
+    if let Command::LinkTest {
+        node: target,
+        probes,
+    } = command
+    {
+        let mut report = LinkTestReport::new(target);
+        for sf in linktest::SPREADING_FACTORS {
+            println!("Testing SF{} to node {} ...", sf, target);
+            let interval = linktest::probe_interval(sf);
+            tokio::select! {
+                result = router.link_test(&mut rec_buf, target, sf, probes, interval) => {
+                    report.push(result?)
+                }
+                _ = shutdown.triggered() => {
+                    println!("Link test interrupted");
+                    break;
+                }
+            }
+        }
+        println!("{}", report);
+        let (node, _) = router.into_parts();
+        node.stop()?;
+        return Ok(());
+    }
+    if let Command::Logs { node: target } = command {
+        println!("Fetching the logs of node {} ...", target);
+        tokio::select! {
+            fetched = control::fetch_logs(&mut router, &mut rec_buf, target, REPLY_TIMEOUT) => {
+                let fetched = fetched?;
+                println!("{} bytes of logs from node {}:", fetched.len(), target);
+                println!("{}", String::from_utf8_lossy(&fetched));
+            }
+            _ = shutdown.triggered() => println!("Fetching logs interrupted"),
+        }
+        let (node, _) = router.into_parts();
+        node.stop()?;
+        return Ok(());
+    }
+    if let Command::Manage {
+        node: target,
+        request,
+    } = command
+    {
+        println!("Sending {:?} to node {} ...", request, target);
+        tokio::select! {
+            reply = control::send_request(&mut router, &mut rec_buf, target, request, REPLY_TIMEOUT) => {
+                match reply? {
+                    Some(true) => println!("Node {} accepted", target),
+                    Some(false) => println!("Node {} refused", target),
+                    None => println!("Node {} didn't reply", target),
+                }
+            }
+            _ = shutdown.triggered() => println!("Interrupted while waiting for node {}", target),
+        }
+        let (node, _) = router.into_parts();
+        node.stop()?;
+        return Ok(());
+    }
+    let mut bootups = mobile_interval
+        .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+    let health_interval = match std::env::var("MUST_GW_HEALTH_SECS") {
+        Ok(secs) => match secs.parse()? {
+            0 => return Err("MUST_GW_HEALTH_SECS must be at least 1".into()),
+            secs => Duration::from_secs(secs),
+        },
+        Err(_) => DEFAULT_HEALTH_INTERVAL,
+    };
+    let mut health_reports = tokio::time::interval(health_interval);
+    // Nodes' report intervals are learned, unless MUST_GW_EXPECT gives them, e.g. `2=300,3=600`
+    let missed_reports = match std::env::var("MUST_GW_MISSED") {
+        Ok(missed) => missed.parse()?,
+        Err(_) => DEFAULT_MISSED_REPORTS,
+    };
+    let mut liveness = LivenessMonitor::new(missed_reports, std::time::Instant::now());
+    if let Ok(expected) = std::env::var("MUST_GW_EXPECT") {
+        liveness.expect_from_str(&expected)?;
+    }
+    let mut liveness_checks = tokio::time::interval(LIVENESS_CHECK_INTERVAL);
+    // In the quiet periods of MUST_GW_QUIET, e.g. `02:00-04:00` in UTC, only ACKs go out, and
+    // nodes are told to hold back their payloads
+    let quiet = match std::env::var("MUST_GW_QUIET") {
+        Ok(periods) => QuietCalendar::parse(&periods)?,
+        Err(_) => QuietCalendar::default(),
+    };
+    let mut quiet_checks = tokio::time::interval(QUIET_CHECK_INTERVAL);
+    // Until when the nodes were told to be quiet
+    let mut quiet_told: Option<std::time::Instant> = None;
+    // Nodes heard directly are switched to a faster spreading factor or a lower power when their
+    // margin allows, within the bounds MUST_GW_ADR gives, e.g. `sf=7-10,power=2-14`
+    let mut adr = match std::env::var("MUST_GW_ADR") {
+        Ok(spec) => Some(AdrEngine::new(
+            AdrConfig::from_str_or_default(&spec)?,
+            router.node().channel_plan().spreading_factor,
+        )),
+        Err(_) => None,
+    };
+    // Received packets go to their network's uplink, and wait in the database while it is down
+    let capacity = match std::env::var("MUST_GW_SPOOL_MAX") {
+        Ok(max) => max.parse()?,
+        Err(_) => DEFAULT_SPOOL_CAPACITY,
+    };
+    // Over TLS once MUST_GW_TLS_CA names the CA the servers' certificates are signed by
+    let tls = match TlsConfig::from_env()? {
+        Some(tls) => Some(tls.client_config()?),
+        None => None,
+    };
+    let uplinks = Uplinks::open(&networks, &db_path, capacity, gateway_id, tls)?;
+    for (network_id, uplink) in uplinks.iter() {
+        println!(
+            "Sending packets of network {} to {}{}, {} spooled from before",
+            network_id,
+            uplink.sink().addr(),
+            if uplink.sink().is_tls() {
+                " over TLS"
+            } else {
+                ""
+            },
+            uplink.spool().len()?
+        );
+    }
+    // MUST_GW_FILTERS diverts or drops packets before they go upstream, e.g.
+    // `source=200-254 => route 2; rssi<-125 => drop`
+    let filters = match std::env::var("MUST_GW_FILTERS") {
+        Ok(spec) => Filters::parse(&spec)?,
+        Err(_) => Filters::default(),
+    };
+    if !filters.rules().is_empty() {
+        println!(
+            "Filtering packets upstream with {} rules",
+            filters.rules().len()
+        );
+    }
+    for network_id in filters.routes() {
+        if !uplinks.iter().any(|(id, _)| id == network_id) {
+            return Err(
+                format!("filters route to network {network_id}, which has no uplink").into(),
+            );
+        }
+    }
+    let mut replays = tokio::time::interval(SPOOL_REPLAY_INTERVAL);
+    // Sending upstream blocks while a server is slow or down, so it doesn't hold up listening
+    let mut uplinks = uplinks.spawn();
+    // Packets from and to the nodes the operator picks with `must-gw trace` are logged in full
+    let trace_rate = match std::env::var("MUST_GW_TRACE_RATE") {
+        Ok(rate) => rate.parse()?,
+        Err(_) => trace::DEFAULT_TRACE_RATE,
+    };
+    let mut tracer = Tracer::new(trace_rate);
+    let socket_path = control_socket_path();
+    let control_socket = trace::bind(&socket_path)?;
+    println!("Taking commands on {}", socket_path);
     loop {
-        let mut rec_buf = Vec::new();
-        let conn = router.listen(&mut rec_buf).await?;
-        let pkts = router.receive(conn, &rec_buf).await?;
+        let wake = tokio::select! {
+            _ = next_bootup(&mut bootups) => Wake::Bootup,
+            _ = health_reports.tick() => Wake::Health,
+            _ = liveness_checks.tick() => Wake::Liveness,
+            _ = replays.tick() => Wake::Replay,
+            _ = quiet_checks.tick(), if !quiet.is_empty() => Wake::Quiet,
+            _ = shutdown.triggered() => Wake::Shutdown,
+            client = control_socket.accept() => Wake::Control(client),
+            Some(uplinked) = uplinks.next() => Wake::Uplinked(uplinked),
+            heard = router.listen(&mut rec_buf) => Wake::Heard(heard),
+        };
+        let heard = match wake {
+            Wake::Bootup => {
+                if let Err(e) = router.bootup().await {
+                    eprintln!("Error while booting up: {}", e);
+                }
+                continue;
+            }
+            Wake::Health => {
+                let concentrator_temp = match router.node().concentrator_temperature() {
+                    Ok(temp) => Some(temp),
+                    Err(e) => {
+                        eprintln!("Can't read the concentrator temperature: {}", e);
+                        None
+                    }
+                };
+                let health = SystemHealth {
+                    clock: router.node().clock_stats(),
+                    ..SystemHealth::read(concentrator_temp)
+                };
+                println!("[{}] health: {}", gateway_id, health);
+                for load in router.node().channel_loads() {
+                    println!("[{}] {}", gateway_id, load);
+                }
+                continue;
+            }
+            Wake::Liveness => {
+                for alert in liveness.check(std::time::Instant::now()) {
+                    eprintln!("[{}] ALERT {}", gateway_id, alert);
+                }
+                continue;
+            }
+            Wake::Quiet => {
+                let now = std::time::Instant::now();
+                match quiet.remaining(std::time::SystemTime::now()) {
+                    Some(left) => {
+                        if !router.node().is_quiet() {
+                            println!("[{}] quiet period for {:?}", gateway_id, left);
+                            router.node_mut().set_quiet(true);
+                        }
+                        // Again when the next period follows on, so nodes don't end too early
+                        if quiet_told.is_none_or(|told| now + left > told + QUIET_CHECK_INTERVAL) {
+                            let secs = u32::try_from(left.as_secs()).unwrap_or(u32::MAX);
+                            match router.broadcast_control(Control::Quiet { secs }).await {
+                                Ok(()) => quiet_told = Some(now + left),
+                                Err(e) => eprintln!("Can't tell the nodes to be quiet: {}", e),
+                            }
+                        }
+                    }
+                    None if router.node().is_quiet() => {
+                        println!("[{}] quiet period over", gateway_id);
+                        router.node_mut().set_quiet(false);
+                        quiet_told = None;
+                        if let Err(e) = router.node_mut().flush().await {
+                            eprintln!("Error sending the frames held back: {}", e);
+                        }
+                    }
+                    None => {}
+                }
+                continue;
+            }
+            Wake::Replay => {
+                uplinks.replay();
+                continue;
+            }
+            Wake::Uplinked(Uplinked::Replayed(replayed)) => {
+                for (network_id, replayed) in replayed {
+                    match replayed {
+                        Ok(0) => {}
+                        Ok(sent) => println!(
+                            "[{}] sent {} spooled packets of network {}",
+                            gateway_id, sent, network_id
+                        ),
+                        Err(e) => eprintln!(
+                            "Error replaying spooled packets of network {}: {}",
+                            network_id, e
+                        ),
+                    }
+                }
+                continue;
+            }
+            Wake::Uplinked(Uplinked::Sent {
+                network,
+                uplink,
+                delivery,
+                spooled,
+                dropped,
+            }) => {
+                let pkt = &uplink.packet;
+                let taken = match delivery {
+                    None | Some(Ok(Delivery::Sent)) => true,
+                    Some(Ok(Delivery::Spooled)) => {
+                        eprintln!(
+                            "[{}] uplink of network {} down, {} packets spooled, {} dropped",
+                            gateway_id, network, spooled, dropped
+                        );
+                        false
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("Error spooling {}: {}", pkt.packet_ref(), e);
+                        false
+                    }
+                };
+                // A spooled packet of a confirmed type is retried by its node until the uplink
+                // is back
+                if taken
+                    && let Err(e) = router
+                        .confirm_delivery(pkt.network_id, pkt.packet_ref())
+                        .await
+                {
+                    eprintln!("Error confirming {}: {}", pkt.packet_ref(), e);
+                }
+                continue;
+            }
+            Wake::Control(client) => {
+                let served = match client {
+                    Ok((stream, _)) => {
+                        trace::serve(stream, |command| match command {
+                            ControlCommand::Trace(command) => tracer.apply(command),
+                            ControlCommand::Maintenance(on) => {
+                                if let Some(on) = on {
+                                    router.node_mut().set_maintenance(on);
+                                }
+                                if router.node().in_maintenance() {
+                                    "maintenance on, not transmitting".to_string()
+                                } else {
+                                    "maintenance off".to_string()
+                                }
+                            }
+                        })
+                        .await
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = served {
+                    eprintln!("Error on the control socket: {}", e);
+                }
+                continue;
+            }
+            Wake::Shutdown => break,
+            Wake::Heard(heard) => heard,
+        };
+        // A HAL glitch should not take the gateway down, so errors are logged and we keep polling
+        if let Err(e) = heard {
+            eprintln!("Error while listening: {}", e);
+            continue;
+        }
+        let handling = std::time::Instant::now();
+        let pkts = match router.receive((), &rec_buf).await {
+            Ok(pkts) => pkts,
+            Err(e) => {
+                eprintln!("Error while receiving: {}", e);
+                continue;
+            }
+        };
+        let now = std::time::Instant::now();
+        for line in tracer.trace(&rec_buf, &pkts, now - handling, now) {
+            println!("[{}] {}", gateway_id, line);
+        }
         if !pkts.is_empty() {
-            println!("got pkts! : {:?}", pkts);
+            println!("[{}] got pkts! : {:?}", gateway_id, pkts);
+        }
+        for reception in router.node().receptions() {
+            liveness.heard(
+                reception.packet.source_id,
+                reception.rssi,
+                reception.snr,
+                now,
+            );
+        }
+        if let Some(adr) = &mut adr {
+            let receptions = router.node().receptions();
+            for delivered in &pkts {
+                let pkt = &delivered.packet;
+                if let Some(reception) = receptions.iter().find(|r| r.packet == pkt.packet_ref()) {
+                    adr.heard(delivered, reception.snr);
+                }
+                if pkt.packet_type != PacketType::Control {
+                    continue;
+                }
+                if let Some((Control::Reply { accepted }, _)) = Control::decode(&pkt.payload) {
+                    adr.replied(pkt.source_id, accepted);
+                }
+            }
+            for (target, settings) in adr.recommendations(now) {
+                println!("[{}] ADR: node {} to {}", gateway_id, target, settings);
+                if let Err(e) = router.send_control(target, settings.into()).await {
+                    eprintln!("Error sending ADR to node {}: {}", target, e);
+                }
+            }
+        }
+        for pkt in pkts.iter().map(|delivered| &delivered.packet) {
+            let Some(subscribed) = group::subscription(pkt) else {
+                continue;
+            };
+            if let Err(e) = groups.subscribe(pkt.source_id, subscribed) {
+                eprintln!("Can't keep the groups of node {}: {}", pkt.source_id, e);
+                continue;
+            }
+            let names: Vec<String> = group::addresses(subscribed)
+                .map(|address| match groups.name(address) {
+                    Some(name) => name.to_string(),
+                    None => address.to_string(),
+                })
+                .collect();
+            println!(
+                "[{}] node {} is in groups: {}",
+                gateway_id,
+                pkt.source_id,
+                names.join(", ")
+            );
+        }
+        for delivered in &pkts {
+            let pkt = &delivered.packet;
+            let uplink = Uplink::new(delivered.clone());
+            let network_id = match filters.decide(&uplink) {
+                Action::Accept => Some(pkt.network_id),
+                Action::Route(network_id) => Some(network_id),
+                Action::Drop => None,
+            };
+            match network_id {
+                // Confirmed once the uplink task took it, see `Wake::Uplinked`
+                Some(network_id) => uplinks.send_to(network_id, uplink),
+                // Dropped on purpose, so there is nothing to wait for
+                None => {
+                    if let Err(e) = router
+                        .confirm_delivery(pkt.network_id, pkt.packet_ref())
+                        .await
+                    {
+                        eprintln!("Error confirming {}: {}", pkt.packet_ref(), e);
+                    }
+                }
+            }
+        }
+        for pkt in pkts.iter().map(|delivered| &delivered.packet) {
+            let decoded = match &schemas {
+                Some(schemas) => schemas.decode(&pkt.payload),
+                // Without a schema file, only the sensor reports must-hop defines
+                None if pkt.payload.first() == Some(&SENSOR_REPORT) => {
+                    schema::decode_report(&pkt.payload)
+                }
+                None => continue,
+            };
+            match decoded {
+                Ok(decoded) => {
+                    println!("[{}] {} decoded: {}", gateway_id, pkt.packet_ref(), decoded)
+                }
+                Err(e) => eprintln!("Can't decode {}: {}", pkt.packet_ref(), e),
+            }
+        }
+        let state = router.manager_state();
+        if state != saved_state {
+            if let Err(e) = store.save(&state) {
+                eprintln!("Error saving mesh state: {}", e);
+            }
+            saved_state = state;
         }
     }
+
+    // Give pending downlinks a last chance to be ACKed, and keep what is left for the next start
+    let drain_timeout = shutdown.drain_timeout();
+    println!("Shutting down, draining for up to {:?}", drain_timeout);
+    let drained = router
+        .quiesce(
+            &mut rec_buf,
+            embassy_time::Duration::from_millis(drain_timeout.as_millis() as u64),
+        )
+        .await;
+    if let Err(e) = store.save(&router.manager_state()) {
+        eprintln!("Error saving mesh state: {}", e);
+    }
+    // What was heard last still goes upstream, or into the spool
+    uplinks.finish().await;
+    drop(control_socket);
+    let _ = std::fs::remove_file(&socket_path);
+    let (node, _) = router.into_parts();
+    node.stop()?;
+    match drained {
+        Ok(summary) => {
+            if !summary.received.is_empty() {
+                println!("got pkts while draining: {:?}", summary.received);
+            }
+            println!(
+                "Shut down with {} packets pending, after {} retries and {} packets received while draining",
+                summary.pending,
+                summary.retransmitted,
+                summary.received.len()
+            );
+        }
+        Err(e) => println!("Shut down after draining failed: {}", e),
+    }
+    Ok(())
 }
 
 #[tokio::main]
@@ -48,12 +773,49 @@ async fn main() {
     // To get logging from loragw
     env_logger::init();
 
+    let command = match Command::from_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // Commands for the running gateway don't open the concentrator, which it holds
+    if let Command::Control(command) = command {
+        let socket_path = control_socket_path();
+        match trace::request(&socket_path, command).await {
+            Ok(reply) => println!("{}", reply),
+            Err(e) => {
+                eprintln!("Can't reach the gateway on {}: {}", socket_path, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let drain_timeout = match std::env::var("MUST_GW_DRAIN_SECS").map(|secs| secs.parse()) {
+        Ok(Ok(secs)) => Duration::from_secs(secs),
+        Ok(Err(e)) => {
+            eprintln!("Invalid MUST_GW_DRAIN_SECS, using the default: {}", e);
+            DEFAULT_DRAIN_TIMEOUT
+        }
+        Err(_) => DEFAULT_DRAIN_TIMEOUT,
+    };
+    let shutdown = Shutdown::new(drain_timeout);
+    let listener = shutdown.listener();
+    tokio::spawn(async move {
+        if let Err(e) = shutdown.on_signal().await {
+            eprintln!("Can't listen for shutdown signals: {}", e);
+        }
+    });
+
     println!("Spawning concentrator task...");
 
     // 3. Spawn the task using tokio::spawn
     let task_handle = tokio::spawn(async move {
         // Run the task and catch any errors it throws
-        if let Err(e) = run_concentrator_task().await {
+        if let Err(e) = run_concentrator_task(command, listener).await {
             eprintln!("Concentrator task shut down with error: {:?}", e);
         }
     });
@@ -0,0 +1,239 @@
+//! Serves several logical networks from one concentrator, e.g. a test and a production mesh next
+//! to each other. `MUST_GW_NETWORKS` lists them as `id[=host:port]`, comma separated, e.g.
+//! `1=collector:7000,2=test-collector:7000`. The first is the gateway's own network. Every
+//! network's packets go to its own uplink server, spooled in its own table, and the downlinks
+//! waiting for an ACK are shared out between the networks.
+//!
+//! Connecting to an uplink server and writing to it blocks, so the uplinks run on a thread of
+//! their own with `Uplinks::spawn`. The gateway's loop hands packets over without waiting and
+//! hears back from the `UplinkTask` what became of them, while it keeps listening.
+use std::{fmt, path::Path, sync::Arc};
+
+use rustls::ClientConfig;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use must_hop::node::network_manager::MAX_NETWORKS;
+
+use crate::{
+    identity::GatewayId,
+    store::StoreError,
+    uplink::{Delivery, Spool, Spooled, TcpSink, Uplink},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkConfig {
+    pub id: u8,
+    /// Where the network's packets go, e.g. `collector.local:7000`
+    pub uplink: Option<String>,
+}
+
+impl NetworkConfig {
+    /// Table of the network's spool. The gateway's own network keeps the table a gateway serving
+    /// one network uses, so its spool survives adding networks.
+    fn spool_table(&self, own: bool) -> String {
+        if own {
+            "spool".to_string()
+        } else {
+            format!("spool_{}", self.id)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    InvalidId(String),
+    Duplicate(u8),
+    /// More networks than one concentrator serves, the gateway's own and `MAX_NETWORKS` more
+    TooMany(usize),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::InvalidId(id) => write!(f, "invalid network id: {id}"),
+            NetworkError::Duplicate(id) => write!(f, "network {id} is listed twice"),
+            NetworkError::TooMany(n) => {
+                write!(f, "{n} networks, at most {} are served", MAX_NETWORKS + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+/// Reads `MUST_GW_NETWORKS`
+pub fn parse_networks(spec: &str) -> Result<Vec<NetworkConfig>, NetworkError> {
+    let mut networks: Vec<NetworkConfig> = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (id, uplink) = match entry.split_once('=') {
+            Some((id, uplink)) => (id.trim(), Some(uplink.trim().to_string())),
+            None => (entry, None),
+        };
+        let id: u8 = id
+            .parse()
+            .map_err(|_| NetworkError::InvalidId(id.to_string()))?;
+        if networks.iter().any(|n| n.id == id) {
+            return Err(NetworkError::Duplicate(id));
+        }
+        networks.push(NetworkConfig { id, uplink });
+    }
+    if networks.len() > MAX_NETWORKS + 1 {
+        return Err(NetworkError::TooMany(networks.len()));
+    }
+    Ok(networks)
+}
+
+/// The uplink of every network which has one
+pub struct Uplinks {
+    sinks: Vec<(u8, Spooled<TcpSink>)>,
+}
+
+impl Uplinks {
+    /// Opens the spool of every network with an uplink in the database at `path`, each keeping
+    /// at most `capacity` packets. `networks` start with the gateway's own. With `tls`, every
+    /// uplink is encrypted.
+    pub fn open(
+        networks: &[NetworkConfig],
+        path: impl AsRef<Path>,
+        capacity: usize,
+        gateway: GatewayId,
+        tls: Option<Arc<ClientConfig>>,
+    ) -> Result<Self, StoreError> {
+        let mut sinks = Vec::new();
+        for (i, network) in networks.iter().enumerate() {
+            let Some(addr) = &network.uplink else {
+                continue;
+            };
+            let spool = Spool::open_table(&path, &network.spool_table(i == 0), capacity)?;
+            let mut sink = TcpSink::new(addr.clone(), gateway);
+            if let Some(tls) = &tls {
+                sink = sink.set_tls(tls.clone());
+            }
+            sinks.push((network.id, Spooled::new(sink, spool)));
+        }
+        Ok(Self { sinks })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// The networks with an uplink, and their sinks
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &Spooled<TcpSink>)> {
+        self.sinks.iter().map(|(id, sink)| (*id, sink))
+    }
+
+    /// Sends `uplink` to the sink of its network, `None` if that network has none
+    pub fn send(&mut self, uplink: &Uplink) -> Option<Result<Delivery, StoreError>> {
+        self.send_to(uplink.packet.network_id, uplink)
+    }
+
+    /// Sends `uplink` to the sink of `network`, whichever network it is from
+    pub fn send_to(
+        &mut self,
+        network: u8,
+        uplink: &Uplink,
+    ) -> Option<Result<Delivery, StoreError>> {
+        self.sinks
+            .iter_mut()
+            .find(|(id, _)| *id == network)
+            .map(|(_, sink)| sink.send(uplink))
+    }
+
+    /// Sends what every network spooled, returning how many were sent by network
+    pub fn replay(&mut self) -> Vec<(u8, Result<usize, StoreError>)> {
+        self.sinks
+            .iter_mut()
+            .map(|(id, sink)| (*id, sink.replay()))
+            .collect()
+    }
+}
+
+/// What the uplink task did with what it was handed
+#[derive(Debug)]
+pub enum Uplinked {
+    /// `uplink` went to the sink of `network`, or into its spool. `None` if the network has no
+    /// sink.
+    Sent {
+        network: u8,
+        uplink: Box<Uplink>,
+        delivery: Option<Result<Delivery, StoreError>>,
+        /// Packets in the network's spool after this one, and dropped from it so far
+        spooled: usize,
+        dropped: u64,
+    },
+    /// How many spooled packets were sent again, by network
+    Replayed(Vec<(u8, Result<usize, StoreError>)>),
+}
+
+enum Job {
+    Send(u8, Uplink),
+    Replay,
+}
+
+/// The uplinks running on a thread of their own, see `Uplinks::spawn`
+pub struct UplinkTask {
+    jobs: mpsc::UnboundedSender<Job>,
+    done: mpsc::UnboundedReceiver<Uplinked>,
+    handle: JoinHandle<()>,
+}
+
+impl Uplinks {
+    /// Moves the uplinks onto a blocking thread, which sends what the returned task is handed in
+    /// order
+    pub fn spawn(mut self) -> UplinkTask {
+        let (jobs, mut todo) = mpsc::unbounded_channel();
+        let (report, done) = mpsc::unbounded_channel();
+        let handle = tokio::task::spawn_blocking(move || {
+            while let Some(job) = todo.blocking_recv() {
+                let uplinked = match job {
+                    Job::Send(network, uplink) => {
+                        let delivery = self.send_to(network, &uplink);
+                        let (spooled, dropped) = self
+                            .iter()
+                            .find(|(id, _)| *id == network)
+                            .map_or((0, 0), |(_, sink)| {
+                                (sink.spool().len().unwrap_or(0), sink.spool().dropped())
+                            });
+                        Uplinked::Sent {
+                            network,
+                            uplink: Box::new(uplink),
+                            delivery,
+                            spooled,
+                            dropped,
+                        }
+                    }
+                    Job::Replay => Uplinked::Replayed(self.replay()),
+                };
+                // Nobody listens once the gateway shuts down, what is left is still sent or
+                // spooled
+                let _ = report.send(uplinked);
+            }
+        });
+        UplinkTask { jobs, done, handle }
+    }
+}
+
+impl UplinkTask {
+    /// Hands `uplink` to the sink of `network`, whichever network it is from. `next` tells what
+    /// became of it.
+    pub fn send_to(&self, network: u8, uplink: Uplink) {
+        let _ = self.jobs.send(Job::Send(network, uplink));
+    }
+
+    /// Has every network send what it spooled
+    pub fn replay(&self) {
+        let _ = self.jobs.send(Job::Replay);
+    }
+
+    /// What became of the next packet or replay handed over, `None` if the thread is gone
+    pub async fn next(&mut self) -> Option<Uplinked> {
+        self.done.recv().await
+    }
+
+    /// Waits until everything handed over is sent or spooled
+    pub async fn finish(self) {
+        drop(self.jobs);
+        let _ = self.handle.await;
+    }
+}
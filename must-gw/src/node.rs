@@ -1,15 +1,42 @@
-use std::{collections::VecDeque, time::Duration};
+use std::{collections::HashMap, time::Duration};
 
-use loragw::{Concentrator, Error, Running, RxPacket, TxPacket, TxPacketLoRa, TxStatus};
-use must_hop::node::{MHNode, MHPacket};
-use postcard::to_slice;
-use tokio::time::{self, Instant};
+use loragw::{
+    Concentrator, Error, Running, RxPacket, TxPacket, TxPacketLoRa, TxReport, TxStatus, TxTracker,
+};
+use must_hop::{
+    node::{
+        LinkDirection, LinkQuality, MAX_FRAME_LEN, MHNode, MHPacket, MHPacketView,
+        airtime::Modulation,
+        config::ChannelPlan,
+        flags,
+        region::{Region, RegionError},
+        rx_windows::RxWindows,
+    },
+    units::{Db, Dbm},
+};
+use tokio::time;
+
+use crate::{
+    LEN, SIZE,
+    clock::{ClockDiscipline, ClockStats, PpsSync},
+    dedup::RecentPackets,
+    downlink::{self, DownlinkClass, DownlinkScheduler},
+    identity::GatewayId,
+    locate::Reception,
+    poll::PollInterval,
+    utilization::{ChannelLoad, ChannelUtilization},
+};
 
-const SIZE: usize = 128;
-const LEN: usize = 5; // Lets keep it the same as the nodes, make it simple
 const LORA_FREQ: usize = 868_100_000;
-// Max size that radio can send at all
-const TRANSMISSION_BUFFER: usize = 256;
+/// How often the TX status is checked while waiting for the concentrator
+const TX_POLL: Duration = Duration::from_millis(5);
+/// Longest a confirmed frame may take to leave the antenna: the airtime of a full SF12 frame,
+/// with room for one scheduled a few seconds ahead
+const TX_CONFIRM_TIMEOUT: Duration = Duration::from_secs(15);
+/// How far ahead of its time the concentrator needs a timestamped frame
+const TX_LEAD: Duration = Duration::from_millis(50);
+/// Over which channel utilization is estimated, unless `set_utilization_window` says
+pub const UTILIZATION_WINDOW: Duration = Duration::from_secs(60);
 
 #[derive(Clone)]
 pub struct PacketParams {
@@ -19,16 +46,16 @@ pub struct PacketParams {
     pub mode: loragw::TxMode,
     /// Which radio to transmit on.
     pub radio: loragw::FrontRadio,
-    /// TX power (in dBm).
-    pub power: i8,
+    /// TX power.
+    pub power: Dbm<i8>,
     /// Modulation bandwidth.
     pub bandwidth: loragw::Bandwidth,
     /// Spreading factor to use with this packet.
     pub spreading: loragw::Spreading,
     /// Error-correcting-code of the packet.
     pub coderate: loragw::Coderate,
-    /// Invert signal polarity for orthogonal downlinks.
-    pub invert_polarity: bool,
+    /// Direction of the link, which decides the signal polarity.
+    pub direction: LinkDirection,
     /// Preamble length.
     /// Use `None` for default.
     pub preamble: Option<u16>,
@@ -43,13 +70,12 @@ impl Default for PacketParams {
             freq: LORA_FREQ as u32,
             mode: loragw::TxMode::Immediate,
             radio: loragw::FrontRadio::R0,
-            power: 14,
+            power: Dbm(14),
             bandwidth: loragw::Bandwidth::BW125kHz,
             spreading: loragw::Spreading::SF7,
             coderate: loragw::Coderate::Cr4_8,
-            // Do not invert polarity (Gateways only invert if talking to standard
-            // LoRaWAN nodes. If talking Gateway-to-Gateway, this usually stays false).
-            invert_polarity: false,
+            // The gateway is part of the mesh, so nodes have to hear it without inverting
+            direction: LinkDirection::Mesh,
             // Standard LoRa preamble length
             // preamble: Some(8),
             preamble: None,
@@ -67,11 +93,11 @@ impl From<PacketParams> for TxPacketLoRa {
             freq: params.freq,
             mode: params.mode,
             radio: params.radio,
-            power: params.radio as i8,
+            power: params.power,
             bandwidth: params.bandwidth,
             spreading: params.spreading,
             coderate: params.coderate,
-            invert_polarity: params.invert_polarity,
+            invert_polarity: params.direction.iq_inverted(),
             preamble: params.preamble,
             omit_crc: params.omit_crc,
             implicit_header: params.implicit_header,
@@ -80,34 +106,356 @@ impl From<PacketParams> for TxPacketLoRa {
     }
 }
 
+/// The last uplink of a node listening in RX windows, which they are timed from
+struct WindowedUplink {
+    /// Concentrator counter at the end of the uplink
+    count_us: Duration,
+    /// When it was taken from the concentrator
+    heard: std::time::Instant,
+    freq: u32,
+    bandwidth: loragw::Bandwidth,
+    spreading: loragw::Spreading,
+    /// Windows a frame was scheduled in already
+    rx1_taken: bool,
+    rx2_taken: bool,
+}
+
 pub struct GWNode {
     radio: Concentrator<Running>,
-    /// Kind of a hack to do it like this, perhaps MHNODE will be altered?
-    fetched_packets: VecDeque<RxPacket>,
+    gateway_id: GatewayId,
     pkt_params: PacketParams,
+    dedup: Option<RecentPackets>,
+    /// Of the last LoRa packet received
+    last_quality: Option<LinkQuality>,
+    /// How every packet of the last `receive` was heard
+    receptions: Vec<Reception>,
+    /// Whether `transmit` waits until the frame is on the air, and when the last one was
+    confirm_tx: bool,
+    last_tx: Option<TxReport>,
+    /// Whose rules channel plans and frames are held to
+    region: Option<Region>,
+    rx_windows: RxWindows,
+    /// Nodes which only listen in RX windows, by id
+    windowed: HashMap<u8, WindowedUplink>,
+    /// The last frame for a windowed node which came after its windows, sent in the next ones
+    held: HashMap<u8, Vec<MHPacket<SIZE>>>,
+    /// GPS time of the counter, once PPS syncs come in
+    clock: ClockDiscipline,
+    utilization: ChannelUtilization,
+    /// Frames waiting for the concentrator to be free
+    downlinks: DownlinkScheduler<TxPacket>,
+    /// Nothing is sent while on, packets are still received
+    maintenance: bool,
+    /// Only the `DownlinkClass::Ack` frames are sent while on, the rest wait
+    quiet: bool,
 }
 
 impl GWNode {
+    /// Identifies the gateway by the concentrator's EUI, or 0 if it can't be read
     pub fn new(concentrator: Concentrator<Running>) -> Self {
+        let eui = concentrator.eui().unwrap_or_else(|e| {
+            eprintln!("Can't read the concentrator EUI: {}", e);
+            0
+        });
         Self {
             radio: concentrator,
-            fetched_packets: VecDeque::new(),
+            gateway_id: GatewayId(eui),
             pkt_params: PacketParams::default(),
+            dedup: None,
+            last_quality: None,
+            receptions: Vec::new(),
+            confirm_tx: false,
+            last_tx: None,
+            region: None,
+            rx_windows: RxWindows::default(),
+            windowed: HashMap::new(),
+            held: HashMap::new(),
+            clock: ClockDiscipline::new(),
+            utilization: ChannelUtilization::new(UTILIZATION_WINDOW),
+            downlinks: DownlinkScheduler::default(),
+            maintenance: false,
+            quiet: false,
+        }
+    }
+
+    /// Drop packets heard again within `window`, for a gateway on the move which hears the
+    /// same packet from many relays. Keep `window` below the nodes' ACK timeout.
+    pub fn set_dedup_window(mut self, window: Duration) -> Self {
+        self.dedup = Some(RecentPackets::new(window));
+        self
+    }
+
+    /// Use `gateway_id` instead of the concentrator's EUI
+    pub fn set_gateway_id(mut self, gateway_id: GatewayId) -> Self {
+        self.gateway_id = gateway_id;
+        self
+    }
+
+    pub fn gateway_id(&self) -> GatewayId {
+        self.gateway_id
+    }
+
+    /// Makes `transmit` return once the frame left the antenna instead of when the concentrator
+    /// took it, with a `TxReport` of it in `last_tx_report`
+    pub fn set_confirm_tx(mut self, confirm_tx: bool) -> Self {
+        self.confirm_tx = confirm_tx;
+        self
+    }
+
+    /// When the last frame was scheduled and emitted, if `set_confirm_tx` is on
+    pub fn last_tx_report(&self) -> Option<TxReport> {
+        self.last_tx
+    }
+
+    /// Temperature of the concentrator board, for the gateway's health reports
+    pub fn concentrator_temperature(&self) -> Result<f32, Error> {
+        self.radio.temperature()
+    }
+
+    /// Stops the concentrator, so the next start doesn't find it running
+    pub fn stop(self) -> Result<(), Error> {
+        self.radio.stop().map(|_| ())
+    }
+
+    /// Use `poll` instead of the default adaptive interval between FIFO polls
+    pub fn set_poll_interval(self, poll: PollInterval) -> Self {
+        self.radio.set_poll_interval(poll);
+        self
+    }
+
+    /// Keeps to `region` with an antenna of `antenna_gain`: fails if the channel plan is outside
+    /// it, lowers the TX power to what it allows, and from then on refuses channel plans it
+    /// doesn't allow and frames longer than its dwell time
+    pub fn set_region(mut self, region: Region, antenna_gain: Db<i8>) -> Result<Self, RegionError> {
+        let plan = self.channel_plan();
+        region.check_channel(plan.frequency_hz, plan.bandwidth_khz)?;
+        self.pkt_params.power = self.pkt_params.power.min(region.max_tx_power(antenna_gain));
+        self.region = Some(region);
+        Ok(self)
+    }
+
+    /// Times the replies to nodes listening in RX windows by `windows` instead of the defaults,
+    /// which have to be the ones the nodes use
+    pub fn set_rx_windows(mut self, windows: RxWindows) -> Self {
+        self.rx_windows = windows;
+        self
+    }
+
+    /// The plan nodes should use to hear us, advertised in the bootup
+    pub fn channel_plan(&self) -> ChannelPlan {
+        ChannelPlan {
+            frequency_hz: self.pkt_params.freq,
+            spreading_factor: self.pkt_params.spreading as u8,
+            bandwidth_khz: bandwidth_khz(self.pkt_params.bandwidth),
+        }
+    }
+
+    /// How each packet of the last `receive` was heard, e.g. to `locate` its sender together with
+    /// other gateways. Copies dropped by the dedup window are included, they were heard too.
+    pub fn receptions(&self) -> &[Reception] {
+        &self.receptions
+    }
+
+    /// Estimate how busy each channel is over `window` instead of `UTILIZATION_WINDOW`
+    pub fn set_utilization_window(mut self, window: Duration) -> Self {
+        self.utilization = ChannelUtilization::new(window);
+        self
+    }
+
+    /// How busy each IF chain was over the utilization window, from what it demodulated
+    pub fn channel_loads(&self) -> Vec<ChannelLoad> {
+        self.utilization.loads(std::time::Instant::now())
+    }
+
+    /// Give each destination `quantum` bytes per turn when frames wait for the concentrator,
+    /// instead of `downlink::DEFAULT_QUANTUM`
+    pub fn set_downlink_quantum(mut self, quantum: usize) -> Self {
+        self.downlinks = DownlinkScheduler::new(quantum);
+        self
+    }
+
+    /// Frames waiting for the concentrator to be free
+    pub fn downlinks_waiting(&self) -> usize {
+        self.downlinks.len()
+    }
+
+    /// Stops sending anything, ACKs, bootups and downlinks alike, while `on`, and keeps receiving,
+    /// e.g. during spectrum measurements or to tell whether the gateway's own TX interferes.
+    /// Frames waiting for the concentrator are dropped when it goes on.
+    pub fn set_maintenance(&mut self, on: bool) {
+        if on && !self.downlinks.is_empty() {
+            println!(
+                "Maintenance: dropping {} waiting frames",
+                self.downlinks.len()
+            );
+            while self.downlinks.pop().is_some() {}
+        }
+        self.maintenance = on;
+    }
+
+    pub fn in_maintenance(&self) -> bool {
+        self.maintenance
+    }
+
+    /// Sends only ACKs, bootups and link tests while `quiet`, see `quiet`. The other frames wait
+    /// in the downlink scheduler, or until the next uplink for nodes listening in RX windows, and
+    /// go out with the next `transmit` or `flush` after it.
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Takes the counter latched on a PPS edge and the GPS time of that second, with what the
+    /// system clock read on it. Receptions near it are timed from GPS rather than the host.
+    pub fn sync_clock(&mut self, pps: PpsSync, system_time: std::time::SystemTime) {
+        self.clock.sync(pps, system_time);
+    }
+
+    /// Offset and drift at the last PPS sync, None before the first
+    pub fn clock_stats(&self) -> Option<ClockStats> {
+        self.clock.stats()
+    }
+
+    /// The node all of `packets` are for, if it listens in RX windows
+    fn windowed_destination(&self, packets: &[MHPacket<SIZE>]) -> Option<u8> {
+        let node = packets.first()?.destination_id;
+        (packets.iter().all(|pkt| pkt.destination_id == node) && self.windowed.contains_key(&node))
+            .then_some(node)
+    }
+
+    /// How to send a frame of `len` bytes to `node` so it comes in its next RX window: in RX1 on
+    /// the uplink's channel and spreading factor, or in RX2 on the channel plan's frequency and
+    /// the fallback spreading factor. None once both are taken or too close, or the frame is too
+    /// long for them.
+    fn window_params(&mut self, node: u8, len: usize) -> Option<PacketParams> {
+        let windows = self.rx_windows;
+        let rx2_spreading =
+            loragw::Spreading::try_from(u32::from(windows.rx2_spreading_factor)).ok()?;
+        let uplink = self.windowed.get_mut(&node)?;
+        let elapsed = uplink.heard.elapsed() + TX_LEAD;
+        let rx1 = Modulation::new(uplink.spreading as u8, bandwidth_khz(uplink.bandwidth));
+        let (delay, freq, spreading, bandwidth) = if !uplink.rx1_taken
+            && elapsed < std_duration(windows.rx1_delay)
+            && windows.fits_rx1(rx1, len)
+        {
+            uplink.rx1_taken = true;
+            (
+                windows.rx1_delay,
+                uplink.freq,
+                uplink.spreading,
+                uplink.bandwidth,
+            )
+        } else if !uplink.rx2_taken
+            && elapsed < std_duration(windows.rx2_delay)
+            && windows.fits_rx2(bandwidth_khz(self.pkt_params.bandwidth), len)
+        {
+            uplink.rx2_taken = true;
+            (
+                windows.rx2_delay,
+                self.pkt_params.freq,
+                rx2_spreading,
+                self.pkt_params.bandwidth,
+            )
+        } else {
+            return None;
+        };
+        Some(PacketParams {
+            freq,
+            mode: loragw::TxMode::after(uplink.count_us, std_duration(delay)),
+            spreading,
+            bandwidth,
+            ..self.pkt_params.clone()
+        })
+    }
+
+    /// Sends the waiting frames in the scheduler's order, as long as the concentrator is free,
+    /// or all of them if `wait`. A frame is only taken from the scheduler once the concentrator
+    /// is free for it, so cancelling this doesn't lose one. During a quiet period only the
+    /// `DownlinkClass::Ack` ones are sent.
+    async fn send_downlinks(&mut self, wait: bool) -> Result<(), Error> {
+        while !self.downlinks.is_empty() {
+            if self.radio.transmit_status()? != TxStatus::Free {
+                if !wait {
+                    return Ok(());
+                }
+                time::sleep(TX_POLL).await;
+                continue;
+            }
+            let most = if self.quiet {
+                DownlinkClass::Ack
+            } else {
+                DownlinkClass::Bulk
+            };
+            let Some(tx_pkt) = self.downlinks.pop_up_to(most) else {
+                break;
+            };
+            self.emit(tx_pkt).await?;
         }
+        Ok(())
     }
+
+    /// Hands a frame to the free concentrator, and waits for it to leave the antenna if
+    /// `set_confirm_tx` is on. Gives up with `Error::Timeout` after `TX_CONFIRM_TIMEOUT`, e.g.
+    /// for a timestamped frame whose count never comes.
+    async fn emit(&mut self, tx_pkt: TxPacket) -> Result<(), Error> {
+        self.radio.transmit(tx_pkt)?;
+        if !self.confirm_tx {
+            return Ok(());
+        }
+        let mut tracker = TxTracker::new(std::time::Instant::now());
+        // Borrowed mutably, a shared borrow of the concentrator, which isn't Sync, would make
+        // the future not Send
+        let radio = &mut self.radio;
+        let emitted = async move {
+            loop {
+                let status = radio.transmit_status()?;
+                if let Some(report) = tracker.update(status, std::time::Instant::now())? {
+                    return Ok::<_, Error>(report);
+                }
+                time::sleep(TX_POLL).await;
+            }
+        };
+        let report = time::timeout(TX_CONFIRM_TIMEOUT, emitted)
+            .await
+            .map_err(|_| Error::Timeout)??;
+        self.last_tx = Some(report);
+        Ok(())
+    }
+
     fn to_tx_packet(&self, packets: &[MHPacket<SIZE>]) -> Result<TxPacket, Error> {
-        let mut buffer = [0u8; TRANSMISSION_BUFFER];
+        self.to_tx_packet_with(packets, self.pkt_params.clone())
+    }
+
+    fn to_tx_packet_with(
+        &self,
+        packets: &[MHPacket<SIZE>],
+        params: PacketParams,
+    ) -> Result<TxPacket, Error> {
         println!("BUFFER SIZE IS: {}", SIZE);
-        let used_slice = match to_slice(&packets, &mut buffer) {
-            Ok(slice) => slice,
-            Err(e) => {
-                eprintln!("Serialization failed: {:?}", e);
-                return Err(Error::Data);
+        let len = MHPacket::frame_len(packets);
+        if len > MAX_FRAME_LEN {
+            eprintln!("A frame of {} bytes doesn't fit on the air", len);
+            return Err(Error::Size);
+        }
+        // Encoded straight into the payload of the TX packet
+        let mut payload = vec![0u8; len];
+        if let Err(e) = MHPacket::encode_frame_into(packets, &mut payload) {
+            eprintln!("Serialization failed: {:?}", e);
+            return Err(Error::Data);
+        }
+        if let Some(region) = self.region {
+            let modulation = Modulation::from(self.channel_plan());
+            if let Err(e) = region.check_dwell(modulation, payload.len()) {
+                eprintln!("Not sending: {}", e);
+                return Err(Error::Size);
             }
-        };
+        }
         Ok(TxPacket::LoRa(TxPacketLoRa {
-            payload: used_slice.to_vec(),
-            ..self.pkt_params.clone().into()
+            payload,
+            ..params.into()
         }))
     }
 }
@@ -118,12 +466,60 @@ impl MHNode<SIZE, LEN> for GWNode {
     type ReceiveBuffer = Vec<RxPacket>;
     type Duration = u16;
 
+    /// Frames for a node listening in RX windows are timed to come in the next one, or held
+    /// until its next uplink if they are gone. Scheduling one holds up the next frame until it
+    /// was sent. Other frames wait in the `downlink` scheduler while the concentrator is busy,
+    /// and go out by urgency and in turns per destination once it is free.
     async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), Self::Error> {
+        if self.maintenance {
+            println!(
+                "Maintenance: not sending a frame of {} packets to {:?}",
+                packets.len(),
+                downlink::destination(packets)
+            );
+            return Ok(());
+        }
+        if let Some(node) = self.windowed_destination(packets) {
+            if self.quiet && DownlinkClass::of_frame(packets) != DownlinkClass::Ack {
+                println!(
+                    "Quiet period: holding a frame for {} until its next uplink",
+                    node
+                );
+                self.held.insert(node, packets.to_vec());
+                return Ok(());
+            }
+            let len = MHPacket::frame_len(packets);
+            let Some(params) = self.window_params(node, len) else {
+                println!("Holding a frame for {} until its next uplink", node);
+                self.held.insert(node, packets.to_vec());
+                return Ok(());
+            };
+            let tx_pkt = self.to_tx_packet_with(packets, params)?;
+            while self.radio.transmit_status()? != TxStatus::Free {
+                time::sleep(TX_POLL).await;
+            }
+            return self.emit(tx_pkt).await;
+        }
         let tx_pkt = self.to_tx_packet(packets)?;
+        let cost = match &tx_pkt {
+            TxPacket::LoRa(pkt) => pkt.payload.len(),
+            TxPacket::FSK(pkt) => pkt.payload.len(),
+        };
+        self.downlinks.push(
+            downlink::destination(packets),
+            DownlinkClass::of_frame(packets),
+            cost,
+            tx_pkt,
+        );
+        self.send_downlinks(false).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.send_downlinks(true).await?;
         while self.radio.transmit_status()? != TxStatus::Free {
-            time::sleep(Duration::from_millis(5)).await;
+            time::sleep(TX_POLL).await;
         }
-        self.radio.transmit(tx_pkt)
+        Ok(())
     }
 
     async fn receive(
@@ -137,18 +533,71 @@ impl MHNode<SIZE, LEN> for GWNode {
         //     _ => Vec::new(),
         // };
         let mut rec_packets: heapless::Vec<MHPacket<SIZE>, LEN> = heapless::Vec::new();
+        let now = std::time::Instant::now();
+        let mut held_frames = Vec::new();
+        self.receptions.clear();
         for pkt in rec_buf
         /*.iter().chain(pkts.iter())*/
         {
+            self.utilization.record(pkt, now);
             let pkt = match pkt {
                 RxPacket::LoRa(rx_packet) => rx_packet,
                 _ => continue,
             };
-            let raw_bytes = &pkt.payload;
-            match postcard::from_bytes::<heapless::Vec<MHPacket<SIZE>, LEN>>(raw_bytes) {
-                Ok(packets) => {
-                    println!("SUCCESS !!!! Received packet: {:?}", packets.len());
-                    for packet in packets {
+            self.last_quality = Some(LinkQuality {
+                rssi: pkt.rssi.to_i16(),
+                snr: pkt.snr.to_i8(),
+            });
+            let gps_time = self.clock.time_of(pkt.timestamp);
+            // Decode in place, so the payload is copied once, straight into the returned packet
+            match MHPacketView::decode_frame::<LEN>(&pkt.payload) {
+                Ok(views) => {
+                    println!("SUCCESS !!!! Received packet: {:?}", views.len());
+                    for view in views {
+                        // Heard straight from a node listening in RX windows, which open now
+                        if view.has_flag(flags::RX_WINDOWS) && view.hop_count == 0 {
+                            self.windowed.insert(
+                                view.source_id,
+                                WindowedUplink {
+                                    count_us: pkt.timestamp,
+                                    heard: now,
+                                    freq: pkt.freq,
+                                    bandwidth: pkt.bandwidth,
+                                    spreading: pkt.spreading,
+                                    rx1_taken: false,
+                                    rx2_taken: false,
+                                },
+                            );
+                            if let Some(held) = self.held.remove(&view.source_id) {
+                                held_frames.push(held);
+                            }
+                        }
+                        // A node built with a larger SIZE shouldn't cost us the rest of the frame
+                        let packet = match view.checked_packet() {
+                            Ok(packet) => packet,
+                            Err(e) => {
+                                eprintln!("Dropping packet: {:?}", e);
+                                continue;
+                            }
+                        };
+                        // A copy still tells how well the relay it came through is heard
+                        self.receptions.push(Reception {
+                            gateway: self.gateway_id,
+                            packet: packet.packet_ref(),
+                            rssi: pkt.rssi,
+                            snr: pkt.snr,
+                            timestamp: pkt.timestamp,
+                            time: gps_time.unwrap_or(pkt.received_at),
+                            disciplined: gps_time.is_some(),
+                        });
+                        let pid = (packet.network_id, packet.source_id, packet.packet_id);
+                        if self
+                            .dedup
+                            .as_mut()
+                            .is_some_and(|d| d.is_duplicate(pid, now))
+                        {
+                            continue;
+                        }
                         rec_packets.push(packet).map_err(|_| loragw::Error::Data)?
                     }
                 }
@@ -158,32 +607,73 @@ impl MHNode<SIZE, LEN> for GWNode {
                 }
             };
         }
+        // Before the replies to what was just heard, so they take RX2
+        for held in held_frames {
+            self.transmit(&held).await?;
+        }
         Ok(rec_packets)
     }
 
+    fn link_quality(&self) -> Option<LinkQuality> {
+        self.last_quality
+    }
+
+    /// Transmits on the plan's frequency and data rate. Receiving is left to the concentrator's
+    /// configuration, which usually demodulates every spreading factor.
+    fn apply_channel_plan(&mut self, plan: &ChannelPlan) -> Result<(), Self::Error> {
+        if let Some(region) = self.region {
+            region
+                .check_channel(plan.frequency_hz, plan.bandwidth_khz)
+                .map_err(|e| {
+                    eprintln!("Not switching to {:?}: {}", plan, e);
+                    Error::Data
+                })?;
+        }
+        self.pkt_params.spreading = (plan.spreading_factor as u32).try_into()?;
+        self.pkt_params.bandwidth = match plan.bandwidth_khz {
+            125 => loragw::Bandwidth::BW125kHz,
+            250 => loragw::Bandwidth::BW250kHz,
+            500 => loragw::Bandwidth::BW500kHz,
+            _ => return Err(Error::Data),
+        };
+        self.pkt_params.freq = plan.frequency_hz;
+        Ok(())
+    }
+
+    /// The concentrator's multi-SF demodulators already hear every spreading factor
+    fn listen_also_on(&mut self, _spreading_factor: Option<u8>) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+
     async fn listen(
         &mut self,
         rec_buf: &mut Self::ReceiveBuffer,
         with_timeout: bool,
     ) -> Result<Self::Connection, Self::Error> {
-        let start_time = Instant::now();
         let timeout = Duration::from_secs(5);
+        // Nothing waits for the concentrator while we listen
+        self.send_downlinks(true).await?;
         rec_buf.clear();
-
-        loop {
-            if !self.fetched_packets.is_empty() {
-                rec_buf.extend(self.fetched_packets.drain(..));
-                return Ok(());
-            }
-            if let Some(packets) = self.radio.receive()? {
-                self.fetched_packets.extend(packets);
-                continue;
-            }
-            if with_timeout && start_time.elapsed() > timeout {
-                // TODO: Need better error type here
-                return Err(loragw::Error::Busy);
-            }
-            time::sleep(Duration::from_millis(10)).await;
+        if !with_timeout {
+            self.radio.receive_async(rec_buf).await?;
+            return Ok(());
         }
+        match time::timeout(timeout, self.radio.receive_async(rec_buf)).await {
+            Ok(read) => read.map(|_| ()),
+            // TODO: Need better error type here
+            Err(_) => Err(loragw::Error::Busy),
+        }
+    }
+}
+
+fn bandwidth_khz(bandwidth: loragw::Bandwidth) -> u16 {
+    match bandwidth {
+        loragw::Bandwidth::BW250kHz => 250,
+        loragw::Bandwidth::BW500kHz => 500,
+        _ => 125,
     }
 }
+
+fn std_duration(duration: embassy_time::Duration) -> Duration {
+    Duration::from_micros(duration.as_micros())
+}
@@ -0,0 +1,3 @@
+//! How often the gateway polls the concentrator's FIFO. The interval lives in `loragw`, which
+//! polls with it in `Concentrator::receive_async`.
+pub use loragw::PollInterval;
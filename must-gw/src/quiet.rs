@@ -0,0 +1,80 @@
+//! Quiet periods: times of day, e.g. a nightly maintenance window or the hours a shared-spectrum
+//! agreement leaves to others, in which the gateway only sends what nodes time out on, ACKs,
+//! bootups and link tests. Commands and data wait in the downlink scheduler until the period is
+//! over. At its start the nodes are told with a broadcast `Control::Quiet` to hold back their own
+//! payloads for as long, and send what they stored once it is over.
+//!
+//! Periods are given in UTC as `HH:MM-HH:MM`, comma separated, and may run past midnight, e.g.
+//! `02:00-04:00,22:30-23:15`.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY_SECS: u32 = 24 * 60 * 60;
+
+/// A period of each day, in seconds into the UTC day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietPeriod {
+    start: u32,
+    end: u32,
+}
+
+impl QuietPeriod {
+    /// Seconds left of it at `second` of the day, None outside it
+    fn left_at(&self, second: u32) -> Option<u32> {
+        let since_start = (second + DAY_SECS - self.start) % DAY_SECS;
+        let len = (self.end + DAY_SECS - self.start) % DAY_SECS;
+        (since_start < len).then(|| len - since_start)
+    }
+}
+
+/// The quiet periods of every day
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuietCalendar {
+    periods: Vec<QuietPeriod>,
+}
+
+impl QuietCalendar {
+    /// Parses periods like `02:00-04:00,22:30-23:15`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut periods = Vec::new();
+        for period in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (start, end) = period
+                .split_once('-')
+                .ok_or_else(|| format!("expected HH:MM-HH:MM, got {}", period))?;
+            let (start, end) = (time_of_day(start)?, time_of_day(end)?);
+            if start == end {
+                return Err(format!("quiet period {} is empty", period));
+            }
+            periods.push(QuietPeriod { start, end });
+        }
+        Ok(Self { periods })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.periods.is_empty()
+    }
+
+    /// How much is left of the quiet period going on at `now`, the longest if they overlap, or
+    /// None outside them
+    pub fn remaining(&self, now: SystemTime) -> Option<Duration> {
+        let second = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| (since.as_secs() % u64::from(DAY_SECS)) as u32);
+        self.periods
+            .iter()
+            .filter_map(|period| period.left_at(second))
+            .max()
+            .map(|left| Duration::from_secs(u64::from(left)))
+    }
+}
+
+/// Seconds into the day of `HH:MM`
+fn time_of_day(s: &str) -> Result<u32, String> {
+    let invalid = || format!("invalid time of day {}, expected HH:MM", s.trim());
+    let (hours, minutes) = s.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok((hours * 60 + minutes) * 60)
+}
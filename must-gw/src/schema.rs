@@ -0,0 +1,386 @@
+//! Describes sensor payloads in a TOML or JSON file, so the gateway decodes new formats into named
+//! fields without being rebuilt.
+//!
+//! A payload starts with its payload type id and the version of its schema, then the fields as
+//! postcard encodes them, which is what a node gets from `postcard::to_slice` on a struct of them.
+//!
+//! ```toml
+//! [[schema]]
+//! id = 1
+//! version = 1
+//! name = "sensor"
+//! fields = [
+//!     { name = "device_id", type = "u8" },
+//!     { name = "temperature", type = "f32", unit = "C" },
+//!     { name = "voltage", type = "u16", scale = 0.001, unit = "V" },
+//! ]
+//! ```
+//!
+//! Sensor reports of `must_hop::telemetry` decode without a schema, unless one is given for
+//! their payload type and version.
+use std::{collections::HashMap, fmt, path::Path};
+
+use must_hop::telemetry::{self, Reading, ReportError, SensorReport};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+/// Most readings a sensor report is decoded with
+const MAX_READINGS: usize = 16;
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    /// Two schemas for the same payload type and version
+    Duplicate {
+        id: u8,
+        version: u8,
+    },
+    /// No schema for the payload type and version, or no header at all
+    Unknown {
+        id: u8,
+        version: u8,
+    },
+    /// The payload ended, or didn't decode, at `field`
+    Field {
+        field: String,
+    },
+    /// Bytes left after the last field, so the payload isn't what its schema says
+    Trailing(usize),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Io(e) => write!(f, "schema file error: {e}"),
+            SchemaError::Toml(e) => write!(f, "schema file error: {e}"),
+            SchemaError::Json(e) => write!(f, "schema file error: {e}"),
+            SchemaError::Duplicate { id, version } => {
+                write!(f, "payload type {id} version {version} is defined twice")
+            }
+            SchemaError::Unknown { id, version } => {
+                write!(f, "no schema for payload type {id} version {version}")
+            }
+            SchemaError::Field { field } => write!(f, "payload doesn't decode at {field}"),
+            SchemaError::Trailing(n) => write!(f, "{n} bytes left after the last field"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<std::io::Error> for SchemaError {
+    fn from(err: std::io::Error) -> Self {
+        SchemaError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for SchemaError {
+    fn from(err: toml::de::Error) -> Self {
+        SchemaError::Toml(err)
+    }
+}
+
+impl From<serde_json::Error> for SchemaError {
+    fn from(err: serde_json::Error) -> Self {
+        SchemaError::Json(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Bool,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: FieldType,
+    /// The value is `raw * scale + offset`, e.g. millivolts with a scale of 0.001 become volts
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub unit: Option<String>,
+}
+
+impl Field {
+    /// Takes the field off the front of `bytes`
+    fn decode<'a>(&self, bytes: &'a [u8]) -> Result<(Value, &'a [u8]), SchemaError> {
+        let (raw, rest) = match self.ty {
+            FieldType::Bool => take::<bool>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::U8 => take::<u8>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::I8 => take::<i8>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::U16 => take::<u16>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::I16 => take::<i16>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::U32 => take::<u32>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::I32 => take::<i32>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::U64 => take::<u64>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::I64 => take::<i64>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::F32 => take::<f32>(bytes).map(|(v, rest)| (json!(v), rest)),
+            FieldType::F64 => take::<f64>(bytes).map(|(v, rest)| (json!(v), rest)),
+        }
+        .ok_or_else(|| SchemaError::Field {
+            field: self.name.clone(),
+        })?;
+        if self.scale.is_none() && self.offset.is_none() {
+            return Ok((raw, rest));
+        }
+        // A bool isn't scaled
+        let Some(raw) = raw.as_f64() else {
+            return Ok((raw, rest));
+        };
+        let value = raw * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0);
+        Ok((json!(value), rest))
+    }
+}
+
+fn take<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Option<(T, &'a [u8])> {
+    postcard::take_from_bytes(bytes).ok()
+}
+
+/// Fields of one version of one payload type, in the order they are encoded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub id: u8,
+    pub version: u8,
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+/// What a schema file holds
+#[derive(Debug, Deserialize)]
+struct SchemaFile {
+    schema: Vec<Schema>,
+}
+
+/// Schemas by payload type id and version. Old versions can stay next to new ones, for nodes
+/// which haven't been updated.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<(u8, u8), Schema>,
+}
+
+impl SchemaRegistry {
+    /// Loads a `.json` file as JSON, and anything else as TOML
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SchemaError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            _ => Self::from_toml(&contents),
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, SchemaError> {
+        Self::from_schemas(toml::from_str::<SchemaFile>(contents)?.schema)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, SchemaError> {
+        Self::from_schemas(serde_json::from_str::<SchemaFile>(contents)?.schema)
+    }
+
+    pub fn from_schemas(schemas: impl IntoIterator<Item = Schema>) -> Result<Self, SchemaError> {
+        let mut registry = Self::default();
+        for schema in schemas {
+            registry.insert(schema)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn insert(&mut self, schema: Schema) -> Result<(), SchemaError> {
+        let key = (schema.id, schema.version);
+        if self.schemas.contains_key(&key) {
+            return Err(SchemaError::Duplicate {
+                id: schema.id,
+                version: schema.version,
+            });
+        }
+        self.schemas.insert(key, schema);
+        Ok(())
+    }
+
+    pub fn get(&self, id: u8, version: u8) -> Option<&Schema> {
+        self.schemas.get(&(id, version))
+    }
+
+    /// Decodes a payload into `{"schema", "id", "version", "fields": {name: value}}`, with the
+    /// units in `"units": {name: unit}` for the fields which have one
+    pub fn decode(&self, payload: &[u8]) -> Result<Value, SchemaError> {
+        let (id, version) = match payload {
+            [id, version, ..] => (*id, *version),
+            [id] => (*id, 0),
+            [] => (0, 0),
+        };
+        let Some(schema) = self.get(id, version).filter(|_| payload.len() >= 2) else {
+            return decode_report(payload);
+        };
+        let mut rest = &payload[2..];
+        let mut fields = Map::new();
+        let mut units = Map::new();
+        for field in &schema.fields {
+            let (value, left) = field.decode(rest)?;
+            rest = left;
+            fields.insert(field.name.clone(), value);
+            if let Some(unit) = &field.unit {
+                units.insert(field.name.clone(), json!(unit));
+            }
+        }
+        if !rest.is_empty() {
+            return Err(SchemaError::Trailing(rest.len()));
+        }
+        Ok(json!({
+            "schema": schema.name,
+            "id": id,
+            "version": version,
+            "fields": fields,
+            "units": units,
+        }))
+    }
+}
+
+/// Decodes a `must_hop::telemetry::SensorReport` like a payload with a schema, with a field per
+/// reading. Readings the gateway doesn't know are named after their tag, with their raw bytes.
+pub fn decode_report(payload: &[u8]) -> Result<Value, SchemaError> {
+    let report = SensorReport::<MAX_READINGS>::decode(payload).map_err(|err| match err {
+        ReportError::Unknown { id, version } => SchemaError::Unknown { id, version },
+        ReportError::Malformed | ReportError::Full => SchemaError::Field {
+            field: "readings".into(),
+        },
+    })?;
+    let mut fields = Map::new();
+    let mut units = Map::new();
+    fields.insert("device_id".into(), json!(report.device_id));
+    for reading in &report.readings {
+        let (name, value, unit) = match reading {
+            Reading::Battery(millivolts) => ("battery".into(), json!(millivolts), Some("mV")),
+            Reading::Temperature(celsius) => ("temperature".into(), json!(celsius), Some("C")),
+            Reading::Acceleration(a) => (
+                "acceleration".into(),
+                json!({ "x": a.x, "y": a.y, "z": a.z }),
+                Some("g"),
+            ),
+            Reading::Other { tag, value } => (format!("tag_{tag}"), json!(value), None),
+        };
+        if let Some(unit) = unit {
+            units.insert(name.clone(), json!(unit));
+        }
+        fields.insert(name, value);
+    }
+    Ok(json!({
+        "schema": "sensor_report",
+        "id": telemetry::SENSOR_REPORT,
+        "version": telemetry::VERSION,
+        "fields": fields,
+        "units": units,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+        [[schema]]
+        id = 1
+        version = 1
+        name = "sensor"
+        fields = [
+            { name = "device_id", type = "u8" },
+            { name = "temperature", type = "f32", unit = "C" },
+            { name = "voltage", type = "u16", scale = 0.001, unit = "V" },
+        ]
+    "#;
+
+    /// Device 7 at 21.5 C and 3300 mV, as a node encodes it
+    fn payload() -> Vec<u8> {
+        let mut payload = vec![1, 1, 7];
+        payload.extend_from_slice(&21.5f32.to_le_bytes());
+        // 3300 as a varint
+        payload.extend_from_slice(&[0xe4, 0x19]);
+        payload
+    }
+
+    #[test]
+    fn test_toml_schema_decodes_fields() {
+        let registry = SchemaRegistry::from_toml(TOML).unwrap();
+        let decoded = registry.decode(&payload()).unwrap();
+        assert_eq!(decoded["schema"], "sensor");
+        assert_eq!(decoded["fields"]["device_id"], 7);
+        assert_eq!(decoded["fields"]["temperature"], 21.5);
+        assert!((decoded["fields"]["voltage"].as_f64().unwrap() - 3.3).abs() < 1e-9);
+        assert_eq!(decoded["units"]["voltage"], "V");
+        assert!(decoded["units"].get("device_id").is_none());
+    }
+
+    #[test]
+    fn test_json_schema_is_the_same() {
+        let json = r#"{"schema": [{"id": 1, "version": 1, "name": "sensor", "fields": [
+            {"name": "device_id", "type": "u8"},
+            {"name": "temperature", "type": "f32", "unit": "C"},
+            {"name": "voltage", "type": "u16", "scale": 0.001, "unit": "V"}
+        ]}]}"#;
+        let from_json = SchemaRegistry::from_json(json).unwrap();
+        let from_toml = SchemaRegistry::from_toml(TOML).unwrap();
+        assert_eq!(from_json.get(1, 1), from_toml.get(1, 1));
+    }
+
+    #[test]
+    fn test_duplicate_schema_is_rejected() {
+        let twice = format!("{TOML}{TOML}");
+        assert!(matches!(
+            SchemaRegistry::from_toml(&twice),
+            Err(SchemaError::Duplicate { id: 1, version: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_payload_must_match_its_schema() {
+        let registry = SchemaRegistry::from_toml(TOML).unwrap();
+        let mut long = payload();
+        long.push(0);
+        assert!(matches!(
+            registry.decode(&long),
+            Err(SchemaError::Trailing(1))
+        ));
+        let short = &payload()[..5];
+        assert!(matches!(
+            registry.decode(short),
+            Err(SchemaError::Field { field }) if field == "temperature"
+        ));
+    }
+
+    #[test]
+    fn test_sensor_reports_decode_without_a_schema() {
+        let report = SensorReport::<4>::new(3)
+            .with(Reading::Battery(3000))
+            .unwrap()
+            .with(Reading::Other {
+                tag: 200,
+                value: heapless::Vec::from_slice(&[1, 2]).unwrap(),
+            })
+            .unwrap();
+        let payload = report.encode::<32>().unwrap();
+        let decoded = SchemaRegistry::default().decode(&payload).unwrap();
+        assert_eq!(decoded["schema"], "sensor_report");
+        assert_eq!(decoded["fields"]["device_id"], 3);
+        assert_eq!(decoded["fields"]["battery"], 3000);
+        assert_eq!(decoded["units"]["battery"], "mV");
+        assert_eq!(decoded["fields"]["tag_200"], json!([1, 2]));
+        assert!(matches!(
+            SchemaRegistry::default().decode(&[9, 9]),
+            Err(SchemaError::Unknown { id: 9, version: 9 })
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+//! Coordinates a graceful shutdown across the gateway's tasks.
+use std::time::Duration;
+
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::watch,
+};
+
+/// Tells every task holding a `ShutdownListener` to stop, and how long it may drain for.
+#[derive(Debug)]
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+    drain_timeout: Duration,
+}
+
+impl Shutdown {
+    pub fn new(drain_timeout: Duration) -> Self {
+        Self {
+            sender: watch::Sender::new(false),
+            drain_timeout,
+        }
+    }
+
+    pub fn listener(&self) -> ShutdownListener {
+        ShutdownListener {
+            receiver: self.sender.subscribe(),
+            drain_timeout: self.drain_timeout,
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Triggers the shutdown on SIGTERM or Ctrl-C.
+    pub async fn on_signal(&self) -> std::io::Result<()> {
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => {}
+            ctrl_c = tokio::signal::ctrl_c() => ctrl_c?,
+        }
+        self.trigger();
+        Ok(())
+    }
+}
+
+/// A task's end of a `Shutdown`.
+#[derive(Debug, Clone)]
+pub struct ShutdownListener {
+    receiver: watch::Receiver<bool>,
+    drain_timeout: Duration,
+}
+
+impl ShutdownListener {
+    /// Waits until the shutdown is triggered. Cancel safe, so it can be used in a `select!`.
+    pub async fn triggered(&mut self) {
+        if self.receiver.wait_for(|stop| *stop).await.is_err() {
+            // Without a sender, nobody can trigger it anymore
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// How long the task may take to finish its work once the shutdown is triggered.
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+}
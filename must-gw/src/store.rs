@@ -0,0 +1,296 @@
+//! SQLite store keeping the gateway's mesh state across restarts, so a crash doesn't lose
+//! pending downlinks or forget which packets were already handled.
+//!
+//! The gateway's packet ids are reserved a block at a time, stamped with the UTC second of the
+//! reservation. A restart continues after the reserved block rather than the last saved id, so
+//! ids sent between the last save and a crash aren't reused and dropped by nodes as duplicates.
+//!
+//! Pending packets are saved with the `WIRE_VERSION` they were encoded in. Those of another
+//! version, or which can't be decoded, are dropped on load instead of keeping the gateway from
+//! starting.
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use must_hop::node::{MAX_FRAME_LEN, MHPacket, WIRE_VERSION, network_manager::ManagerState};
+use rusqlite::{Connection, params};
+
+use crate::{LEN, SIZE};
+
+#[derive(Debug)]
+pub enum StoreError {
+    Sql(rusqlite::Error),
+    Serialization(postcard::Error),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Sql(e) => write!(f, "store error: {e}"),
+            StoreError::Serialization(e) => write!(f, "store serialization error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Sql(err)
+    }
+}
+
+impl From<postcard::Error> for StoreError {
+    fn from(err: postcard::Error) -> Self {
+        StoreError::Serialization(err)
+    }
+}
+
+pub(crate) fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// How many packet ids are reserved at once, another block is reserved once half is used
+const PACKET_ID_BLOCK: u16 = 1024;
+
+pub struct Store {
+    conn: Connection,
+    /// The first id past the reserved block
+    packet_id_limit: u16,
+}
+
+impl Store {
+    /// Opens or creates the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS pending (
+                packet BLOB NOT NULL,
+                retries INTEGER NOT NULL,
+                saved_at INTEGER NOT NULL,
+                wire_version INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS recent_seen (
+                source_id INTEGER NOT NULL,
+                packet_id INTEGER NOT NULL,
+                saved_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            );",
+        )?;
+        // Saved before the version was, those rows get 0 and are dropped on load
+        let versioned: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('pending') WHERE name = 'wire_version'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !versioned {
+            conn.execute(
+                "ALTER TABLE pending ADD COLUMN wire_version INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        Ok(Self {
+            conn,
+            packet_id_limit: 0,
+        })
+    }
+
+    /// Where the gateway's packet ids start: after the block a previous run reserved, returned
+    /// with the time it was reserved at, or at `first` in a new database, e.g. a random one
+    /// so a replaced database doesn't repeat the old ids. Reserves a block from there.
+    pub fn start_packet_ids(
+        &mut self,
+        first: u16,
+    ) -> Result<(u16, Option<SystemTime>), StoreError> {
+        let meta = |key: &str| -> Option<i64> {
+            self.conn
+                .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                    row.get(0)
+                })
+                .ok()
+        };
+        let (start, reserved_at) = match (meta("packet_id_limit"), meta("next_packet_id")) {
+            (Some(limit), _) => (
+                limit as u16,
+                meta("packet_id_limit_at")
+                    .map(|secs| UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)),
+            ),
+            // Saved before ids were reserved, ids may have been used past the saved one
+            (None, Some(next)) => ((next as u16).wrapping_add(PACKET_ID_BLOCK), None),
+            (None, None) => (first, None),
+        };
+        let tx = self.conn.transaction()?;
+        self.packet_id_limit = reserve_packet_ids(&tx, start)?;
+        tx.commit()?;
+        Ok((start, reserved_at))
+    }
+
+    /// Replaces the saved state with `state`. Entries saved earlier keep their age.
+    pub fn save(&mut self, state: &ManagerState<SIZE, LEN>) -> Result<(), StoreError> {
+        let now = now_secs();
+        let tx = self.conn.transaction()?;
+        // Keep the original save time of entries which are still there, so age filtering works
+        // across repeated saves
+        let mut old_pending: Vec<(Vec<u8>, i64)> = {
+            let mut stmt = tx.prepare("SELECT packet, saved_at FROM pending")?;
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?
+        };
+        let old_seen: Vec<(u8, u16, i64)> = {
+            let mut stmt = tx.prepare("SELECT source_id, packet_id, saved_at FROM recent_seen")?;
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+                .collect::<Result<_, _>>()?
+        };
+        tx.execute("DELETE FROM pending", [])?;
+        tx.execute("DELETE FROM recent_seen", [])?;
+
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        for (packet, retries) in &state.pending {
+            let bytes = packet.encode_into(&mut buf)?;
+            let saved_at = match old_pending.iter().position(|(b, _)| b == bytes) {
+                Some(i) => old_pending.swap_remove(i).1,
+                None => now,
+            };
+            tx.execute(
+                "INSERT INTO pending (packet, retries, saved_at, wire_version) \
+                VALUES (?1, ?2, ?3, ?4)",
+                params![bytes, retries, saved_at, WIRE_VERSION],
+            )?;
+        }
+        for (source_id, packet_id) in &state.recent_seen {
+            let saved_at = old_seen
+                .iter()
+                .find(|(s, p, _)| s == source_id && p == packet_id)
+                .map_or(now, |(_, _, t)| *t);
+            tx.execute(
+                "INSERT INTO recent_seen (source_id, packet_id, saved_at) VALUES (?1, ?2, ?3)",
+                params![source_id, packet_id, saved_at],
+            )?;
+        }
+        // Reserve the next block while ids of this one are left, or if more than a block was used
+        let left = self.packet_id_limit.wrapping_sub(state.next_packet_id);
+        if !(PACKET_ID_BLOCK / 2..=PACKET_ID_BLOCK).contains(&left) {
+            self.packet_id_limit = reserve_packet_ids(&tx, state.next_packet_id)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Loads the saved state, leaving out entries older than `max_age`. Packet ids start at 0,
+    /// see `start_packet_ids`. Pending packets of another wire version, or which can't be
+    /// decoded, are deleted.
+    pub fn load(&self, max_age: Duration) -> Result<ManagerState<SIZE, LEN>, StoreError> {
+        let oldest = now_secs() - max_age.as_secs() as i64;
+        let mut state = ManagerState {
+            next_packet_id: 0,
+            pending: heapless::Vec::new(),
+            recent_seen: heapless::Vec::new(),
+            // The gateway has one radio
+            bindings: heapless::Vec::new(),
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT rowid, packet, retries, wire_version FROM pending \
+            WHERE saved_at >= ?1 ORDER BY rowid",
+        )?;
+        let rows: Vec<(i64, Vec<u8>, u8, u8)> = stmt
+            .query_map([oldest], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<Result<_, _>>()?;
+        for (rowid, bytes, retries, version) in rows {
+            let packet = match MHPacket::<SIZE>::decode_from(&bytes) {
+                Ok(packet) if version == WIRE_VERSION => packet,
+                _ => {
+                    self.conn
+                        .execute("DELETE FROM pending WHERE rowid = ?1", params![rowid])?;
+                    continue;
+                }
+            };
+            // Only LEN fit, the newest are dropped
+            let _ = state.pending.push((packet, retries));
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT source_id, packet_id FROM recent_seen WHERE saved_at >= ?1 ORDER BY rowid",
+        )?;
+        let rows = stmt.query_map([oldest], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let _ = state.recent_seen.push(row?);
+        }
+        Ok(state)
+    }
+}
+
+/// Saves that the ids from `from` on are reserved up to a block past it, and returns that limit
+fn reserve_packet_ids(tx: &rusqlite::Transaction, from: u16) -> Result<u16, StoreError> {
+    let limit = from.wrapping_add(PACKET_ID_BLOCK);
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('packet_id_limit', ?1)",
+        params![limit],
+    )?;
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('packet_id_limit_at', ?1)",
+        params![now_secs()],
+    )?;
+    tx.execute("DELETE FROM meta WHERE key = 'next_packet_id'", [])?;
+    Ok(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use must_hop::node::PacketType;
+
+    fn packet(packet_id: u16) -> MHPacket<SIZE> {
+        MHPacket {
+            network_id: 0,
+            destination_id: 5,
+            packet_type: PacketType::Data,
+            flags: 0,
+            packet_id,
+            source_id: 1,
+            max_payload: SIZE as u8,
+            payload: heapless::Vec::from_slice(&[0xAA]).unwrap(),
+            hop_count: 0,
+            hop_to_gw: u8::MAX,
+            route: heapless::Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_undecodable_and_old_rows_are_dropped() {
+        let mut store = Store::open(":memory:").unwrap();
+        let mut state = store.load(Duration::from_secs(60)).unwrap();
+        state.pending.push((packet(1), 0)).unwrap();
+        state.pending.push((packet(2), 1)).unwrap();
+        store.save(&state).unwrap();
+        store
+            .conn
+            .execute(
+                "INSERT INTO pending (packet, retries, saved_at, wire_version) \
+                VALUES (x'ff', 0, ?1, ?2)",
+                params![now_secs(), WIRE_VERSION],
+            )
+            .unwrap();
+        // Saved before the version was
+        store
+            .conn
+            .execute("UPDATE pending SET wire_version = 0 WHERE rowid = 2", [])
+            .unwrap();
+
+        let loaded = store.load(Duration::from_secs(60)).unwrap();
+        assert_eq!(loaded.pending.as_slice(), &[(packet(1), 0)]);
+        let left: i64 = store
+            .conn
+            .query_row("SELECT COUNT(*) FROM pending", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(left, 1);
+    }
+}
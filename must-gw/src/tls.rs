@@ -0,0 +1,105 @@
+//! TLS for the uplinks, for gateways on backhaul which can't be trusted, e.g. cellular. The
+//! server has to present a certificate signed by the configured CA, and no other: the system's
+//! roots aren't loaded, so the CA is pinned. The gateway can authenticate itself with a client
+//! certificate.
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rustls::{
+    ClientConfig, RootCertStore,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM file of the CA the server's certificate is signed by
+    pub ca: PathBuf,
+    /// PEM files of the gateway's certificate chain and its private key
+    pub client_auth: Option<(PathBuf, PathBuf)>,
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    /// A PEM file which can't be read or holds nothing usable, and why
+    Pem(PathBuf, String),
+    /// Only one of the client certificate and key is given
+    HalfClientAuth,
+    Rustls(rustls::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Pem(path, e) => write!(f, "can't load {}: {e}", path.display()),
+            TlsError::HalfClientAuth => {
+                write!(
+                    f,
+                    "a client certificate needs both MUST_GW_TLS_CERT and MUST_GW_TLS_KEY"
+                )
+            }
+            TlsError::Rustls(e) => write!(f, "TLS error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<rustls::Error> for TlsError {
+    fn from(err: rustls::Error) -> Self {
+        TlsError::Rustls(err)
+    }
+}
+
+impl TlsConfig {
+    /// Reads `MUST_GW_TLS_CA`, `MUST_GW_TLS_CERT` and `MUST_GW_TLS_KEY`. None without a CA,
+    /// uplinks are plain TCP then.
+    pub fn from_env() -> Result<Option<Self>, TlsError> {
+        let path = |name| std::env::var_os(name).map(PathBuf::from);
+        let Some(ca) = path("MUST_GW_TLS_CA") else {
+            return Ok(None);
+        };
+        let client_auth = match (path("MUST_GW_TLS_CERT"), path("MUST_GW_TLS_KEY")) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => return Err(TlsError::HalfClientAuth),
+        };
+        Ok(Some(Self { ca, client_auth }))
+    }
+
+    /// Loads the files, for every uplink to share
+    pub fn client_config(&self) -> Result<Arc<ClientConfig>, TlsError> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca)? {
+            roots.add(cert)?;
+        }
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match &self.client_auth {
+            Some((cert, key)) => {
+                let key = PrivateKeyDer::from_pem_file(key)
+                    .map_err(|e| TlsError::Pem(key.clone(), e.to_string()))?;
+                builder.with_client_auth_cert(load_certs(cert)?, key)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Arc::new(config))
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let pem_error =
+        |e: rustls::pki_types::pem::Error| TlsError::Pem(path.to_path_buf(), e.to_string());
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(pem_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pem_error)?;
+    if certs.is_empty() {
+        return Err(TlsError::Pem(
+            path.to_path_buf(),
+            "no certificates".to_string(),
+        ));
+    }
+    Ok(certs)
+}
@@ -0,0 +1,320 @@
+//! Verbose tracing of the packets from and to chosen nodes, to debug one node on a busy network
+//! without flooding the log with everyone else's. The operator turns it on and off per node
+//! through the control socket, with `must-gw trace <node>` and `must-gw untrace <node>`. A traced
+//! packet is logged with its frame in hex, its header, what the gateway did with it and when it
+//! was heard. At most `max_per_sec` are logged a second, and the ones left out are counted.
+//!
+//! The control socket is a Unix socket taking one command a connection, a line such as
+//! `trace 4`, and answering with one line. Besides tracing, it switches maintenance mode with
+//! `maintenance on|off`.
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    io,
+    os::unix::fs::FileTypeExt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use loragw::RxPacket;
+use must_hop::node::{MHPacketView, delivered::Delivered};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{LEN, SIZE};
+
+/// Traces logged a second, unless MUST_GW_TRACE_RATE says
+pub const DEFAULT_TRACE_RATE: u32 = 20;
+/// Length of the window `max_per_sec` applies to
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+/// How long a client of the control socket gets to send its command, so a stuck one doesn't
+/// hold up the gateway
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A line sent to the control socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    Trace(TraceCommand),
+    /// `maintenance on|off`, or `maintenance` to ask whether it is on
+    Maintenance(Option<bool>),
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("maintenance") {
+            return TraceCommand::parse(line).map(ControlCommand::Trace);
+        }
+        match words.next() {
+            None => Ok(ControlCommand::Maintenance(None)),
+            Some("on") => Ok(ControlCommand::Maintenance(Some(true))),
+            Some("off") => Ok(ControlCommand::Maintenance(Some(false))),
+            Some(_) => Err("usage: maintenance [on|off]".to_string()),
+        }
+    }
+
+    /// The line to send for it
+    pub fn to_line(&self) -> String {
+        match self {
+            ControlCommand::Trace(command) => command.to_line(),
+            ControlCommand::Maintenance(None) => "maintenance".to_string(),
+            ControlCommand::Maintenance(Some(true)) => "maintenance on".to_string(),
+            ControlCommand::Maintenance(Some(false)) => "maintenance off".to_string(),
+        }
+    }
+}
+
+/// A command about which nodes are traced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceCommand {
+    /// `trace <node>`
+    Enable(u8),
+    /// `untrace <node>`
+    Disable(u8),
+    /// `traces`, which nodes are traced
+    List,
+}
+
+impl TraceCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let mut node = || -> Result<u8, String> {
+            words
+                .next()
+                .ok_or_else(|| format!("usage: {} <node>", command))?
+                .parse()
+                .map_err(|e| format!("invalid node id: {}", e))
+        };
+        match command {
+            "trace" => Ok(TraceCommand::Enable(node()?)),
+            "untrace" => Ok(TraceCommand::Disable(node()?)),
+            "traces" => Ok(TraceCommand::List),
+            other => Err(format!("unknown command: {}", other)),
+        }
+    }
+
+    /// The line to send for it
+    pub fn to_line(&self) -> String {
+        match self {
+            TraceCommand::Enable(node) => format!("trace {}", node),
+            TraceCommand::Disable(node) => format!("untrace {}", node),
+            TraceCommand::List => "traces".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    nodes: BTreeSet<u8>,
+    max_per_sec: u32,
+    window_start: Option<Instant>,
+    in_window: u32,
+    /// Left out since the last trace which was logged
+    suppressed: u64,
+}
+
+impl Tracer {
+    pub fn new(max_per_sec: u32) -> Self {
+        Self {
+            nodes: BTreeSet::new(),
+            max_per_sec,
+            window_start: None,
+            in_window: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// Carries out `command`, returning the reply for the control socket
+    pub fn apply(&mut self, command: TraceCommand) -> String {
+        match command {
+            TraceCommand::Enable(node) => {
+                self.nodes.insert(node);
+                format!("tracing node {}", node)
+            }
+            TraceCommand::Disable(node) => {
+                self.nodes.remove(&node);
+                format!("stopped tracing node {}", node)
+            }
+            TraceCommand::List => {
+                let nodes: Vec<String> = self.nodes.iter().map(u8::to_string).collect();
+                format!("tracing: {}", nodes.join(" "))
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn is_traced(&self, view: &MHPacketView<'_>) -> bool {
+        self.nodes.contains(&view.source_id) || self.nodes.contains(&view.destination_id)
+    }
+
+    /// Whether another trace fits in the rate, counting it as suppressed if not
+    fn admit(&mut self, now: Instant) -> bool {
+        if self
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= RATE_WINDOW)
+        {
+            self.window_start = Some(now);
+            self.in_window = 0;
+        }
+        if self.in_window >= self.max_per_sec {
+            self.suppressed += 1;
+            return false;
+        }
+        self.in_window += 1;
+        true
+    }
+
+    /// Trace lines of the traced packets in `rec_buf`, the frames of one `receive` which gave
+    /// `delivered` and took `handled_in`
+    pub fn trace(
+        &mut self,
+        rec_buf: &[RxPacket],
+        delivered: &[Delivered<SIZE>],
+        handled_in: Duration,
+        now: Instant,
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.is_empty() {
+            return lines;
+        }
+        for frame in rec_buf {
+            let RxPacket::LoRa(frame) = frame else {
+                continue;
+            };
+            let Ok(views) = MHPacketView::decode_frame::<LEN>(&frame.payload) else {
+                continue;
+            };
+            for view in &views {
+                if !self.is_traced(view) || !self.admit(now) {
+                    continue;
+                }
+                let decision = if delivered.iter().map(|d| &d.packet).any(|pkt| {
+                    pkt.network_id == view.network_id
+                        && pkt.source_id == view.source_id
+                        && pkt.packet_id == view.packet_id
+                }) {
+                    "delivered and ACKed"
+                } else {
+                    "dropped as a copy, or not for this gateway"
+                };
+                let mut line = format!(
+                    "TRACE {}:{} net {} {:?} flags {:#04x} {} -> {}, hops {} (to gw {}), route {:02x?}: {}. \
+                    Heard at {} Hz SF{} {:.0} SNR {:.1}, concentrator time {} us, \
+                    handled in {:?}. Frame:",
+                    view.source_id,
+                    view.packet_id,
+                    view.network_id,
+                    view.packet_type,
+                    view.flags,
+                    view.source_id,
+                    view.destination_id,
+                    view.hop_count,
+                    view.hop_to_gw,
+                    view.route,
+                    decision,
+                    frame.freq,
+                    frame.spreading as u8,
+                    frame.rssi,
+                    frame.snr,
+                    frame.timestamp.as_micros(),
+                    handled_in
+                );
+                for byte in &frame.payload {
+                    let _ = write!(line, " {:02x}", byte);
+                }
+                if self.suppressed > 0 {
+                    let _ = write!(line, " ({} traces left out before)", self.suppressed);
+                    self.suppressed = 0;
+                }
+                lines.push(line);
+            }
+        }
+        lines
+    }
+}
+
+/// Listens on the control socket at `path`, replacing the one a previous run left behind. Fails
+/// with `AddrInUse` if another gateway answers on it, or if something else than a socket is there.
+pub fn bind(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) => {
+            if !meta.file_type().is_socket()
+                || std::os::unix::net::UnixStream::connect(path).is_ok()
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is already in use", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    UnixListener::bind(path)
+}
+
+/// Reads the command of a client of the control socket, carries it out with `apply` and answers
+/// with what it returns
+pub async fn serve(
+    stream: UnixStream,
+    apply: impl FnOnce(ControlCommand) -> String,
+) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    tokio::time::timeout(COMMAND_TIMEOUT, stream.read_line(&mut line))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no command sent"))??;
+    let reply = match ControlCommand::parse(&line) {
+        Ok(command) => apply(command),
+        Err(e) => e,
+    };
+    let stream = stream.get_mut();
+    stream.write_all(reply.as_bytes()).await?;
+    stream.write_all(b"\n").await
+}
+
+/// Sends `command` to the gateway listening on the control socket at `path`, returning its answer
+pub async fn request(path: impl AsRef<Path>, command: ControlCommand) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path).await?;
+    stream.write_all(command.to_line().as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).await?;
+    Ok(reply.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind_replaces_only_a_stale_socket() {
+        let dir = std::env::temp_dir().join(format!("must-gw-bind-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("control.sock");
+
+        // Left behind by a run which is gone
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = bind(&path).unwrap();
+
+        // Another gateway still answers on it
+        let err = bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+
+        let file = dir.join("not-a-socket");
+        std::fs::write(&file, "keep").unwrap();
+        assert_eq!(bind(&file).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,495 @@
+//! Sends received packets upstream without losing them while the backhaul is down. Every
+//! `UplinkSink` is wrapped in a `Spooled`, which keeps what the sink couldn't take in a `Spool`
+//! on disk and sends it again, in the order it was received, once the sink takes packets again.
+//! A full spool drops its oldest packets.
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use must_hop::node::{LinkQuality, MAX_FRAME_LEN, MHPacket, delivered::Delivered};
+use rusqlite::{Connection, OptionalExtension, params};
+use rustls::{ClientConfig, ClientConnection, StreamOwned, pki_types::ServerName};
+use serde_json::json;
+
+use crate::{
+    SIZE,
+    identity::GatewayId,
+    store::{StoreError, now_secs},
+};
+
+/// How long a `TcpSink` waits for the server, to connect or to take a line
+const TCP_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a `TcpSink` waits after the server first can't be reached before trying again. It
+/// doubles with every failed attempt, up to `MAX_RECONNECT_INTERVAL`.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_RECONNECT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// A packet on its way upstream
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uplink {
+    pub packet: MHPacket<SIZE>,
+    /// Unix time the gateway received it, in seconds
+    pub received_at: i64,
+    /// The relay the gateway heard it from, see `Delivered::relayed_by`
+    pub relayed_by: Option<u8>,
+    /// Hops it took, if its route tells
+    pub hops: Option<u8>,
+    /// How strong the gateway heard it
+    pub link: Option<LinkQuality>,
+}
+
+impl Uplink {
+    /// A packet the router delivered just now
+    pub fn new(delivered: Delivered<SIZE>) -> Self {
+        Self {
+            packet: delivered.packet,
+            received_at: now_secs(),
+            relayed_by: delivered.relayed_by,
+            hops: delivered.hops,
+            link: delivered.link,
+        }
+    }
+}
+
+/// Where received packets go upstream, e.g. an MQTT broker, a UDP forwarder or a database
+pub trait UplinkSink {
+    type Error: fmt::Display;
+
+    /// Sends one packet, failing while the other end can't be reached
+    fn send(&mut self, uplink: &Uplink) -> Result<(), Self::Error>;
+}
+
+/// Packets a sink couldn't take yet, oldest first, in a SQLite database so they survive a
+/// restart
+pub struct Spool {
+    conn: Connection,
+    table: String,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl Spool {
+    /// Opens or creates the spool in the database at `path`, keeping at most `capacity` packets
+    pub fn open(path: impl AsRef<Path>, capacity: usize) -> Result<Self, StoreError> {
+        Self::open_table(path, "spool", capacity)
+    }
+
+    /// Like `open`, in its own `table`, for one of several sinks sharing the database
+    pub fn open_table(
+        path: impl AsRef<Path>,
+        table: &str,
+        capacity: usize,
+    ) -> Result<Self, StoreError> {
+        Self::with_connection(Connection::open(path)?, table, capacity)
+    }
+
+    fn with_connection(conn: Connection, table: &str, capacity: usize) -> Result<Self, StoreError> {
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {table} (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                packet BLOB NOT NULL,
+                received_at INTEGER NOT NULL
+            );"
+        ))?;
+        Ok(Self {
+            conn,
+            table: table.to_string(),
+            capacity: capacity.max(1),
+            dropped: 0,
+        })
+    }
+
+    /// Adds `uplink` after the others, dropping the oldest if the spool is full. Only the packet
+    /// and when it was received are kept, it is sent again without how it arrived.
+    pub fn push(&mut self, uplink: &Uplink) -> Result<(), StoreError> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let bytes = uplink.packet.encode_into(&mut buf)?;
+        let table = &self.table;
+        self.conn.execute(
+            &format!("INSERT INTO {table} (packet, received_at) VALUES (?1, ?2)"),
+            params![bytes, uplink.received_at],
+        )?;
+        let dropped = self.conn.execute(
+            &format!(
+                "DELETE FROM {table} WHERE id NOT IN \
+                (SELECT id FROM {table} ORDER BY id DESC LIMIT ?1)"
+            ),
+            params![self.capacity as i64],
+        )?;
+        self.dropped += dropped as u64;
+        Ok(())
+    }
+
+    /// Packets waiting to be sent
+    pub fn len(&self) -> Result<usize, StoreError> {
+        let len: i64 =
+            self.conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", self.table), [], |row| {
+                    row.get(0)
+                })?;
+        Ok(len as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, StoreError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Packets dropped since the spool was opened, because it was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Sends the spooled packets to `sink`, oldest first, until it fails. Returns how many were
+    /// sent.
+    pub fn replay<S: UplinkSink>(&mut self, sink: &mut S) -> Result<usize, StoreError> {
+        let mut sent = 0;
+        loop {
+            let oldest: Option<(i64, Vec<u8>, i64)> = self
+                .conn
+                .query_row(
+                    &format!(
+                        "SELECT id, packet, received_at FROM {} ORDER BY id LIMIT 1",
+                        self.table
+                    ),
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?;
+            let Some((id, bytes, received_at)) = oldest else {
+                return Ok(sent);
+            };
+            // A packet which can't be decoded, e.g. saved by an older version, is skipped
+            if let Ok(packet) = MHPacket::decode_from(&bytes) {
+                if sink
+                    .send(&Uplink {
+                        packet,
+                        received_at,
+                        relayed_by: None,
+                        hops: None,
+                        link: None,
+                    })
+                    .is_err()
+                {
+                    return Ok(sent);
+                }
+                sent += 1;
+            }
+            self.conn.execute(
+                &format!("DELETE FROM {} WHERE id = ?1", self.table),
+                params![id],
+            )?;
+        }
+    }
+}
+
+/// What happened to a packet given to `Spooled::send`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    /// Kept until the sink takes packets again
+    Spooled,
+}
+
+/// A sink which spools what it can't send, whatever kind of sink it is
+pub struct Spooled<S> {
+    sink: S,
+    spool: Spool,
+}
+
+impl<S: UplinkSink> Spooled<S> {
+    pub fn new(sink: S, spool: Spool) -> Self {
+        Self { sink, spool }
+    }
+
+    /// Sends `uplink` after what is spooled, or spools it if the sink can't take it
+    pub fn send(&mut self, uplink: &Uplink) -> Result<Delivery, StoreError> {
+        self.spool.replay(&mut self.sink)?;
+        if self.spool.is_empty()? && self.sink.send(uplink).is_ok() {
+            return Ok(Delivery::Sent);
+        }
+        self.spool.push(uplink)?;
+        Ok(Delivery::Spooled)
+    }
+
+    /// Sends what is spooled, e.g. every few seconds while no packets arrive. Returns how many
+    /// were sent.
+    pub fn replay(&mut self) -> Result<usize, StoreError> {
+        self.spool.replay(&mut self.sink)
+    }
+
+    pub fn spool(&self) -> &Spool {
+        &self.spool
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+}
+
+/// A connection to the server, encrypted if the sink has a TLS config
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Sends every packet as a line of JSON to a TCP server, over TLS if `set_tls` was called.
+/// While the server is down it backs off, trying again after `RECONNECT_INTERVAL` and twice as
+/// long after every failed attempt. Lines written just before the connection dropped may be
+/// lost, since TCP only reports the failure on a later write.
+pub struct TcpSink {
+    addr: String,
+    gateway: GatewayId,
+    tls: Option<Arc<ClientConfig>>,
+    stream: Option<Stream>,
+    last_attempt: Option<Instant>,
+    backoff: Duration,
+}
+
+impl TcpSink {
+    /// Sends to `addr`, e.g. `collector.local:7000`, tagging packets with `gateway`. Connects on
+    /// the first send.
+    pub fn new(addr: impl Into<String>, gateway: GatewayId) -> Self {
+        Self {
+            addr: addr.into(),
+            gateway,
+            tls: None,
+            stream: None,
+            last_attempt: None,
+            backoff: RECONNECT_INTERVAL,
+        }
+    }
+
+    /// Connects over TLS with `config`, checking the server's certificate against the host of
+    /// `addr`
+    pub fn set_tls(mut self, config: Arc<ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    fn connect(&mut self) -> io::Result<&mut Stream> {
+        if self.stream.is_none() {
+            if self
+                .last_attempt
+                .is_some_and(|at| at.elapsed() < self.backoff)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "waiting to reconnect",
+                ));
+            }
+            if self.last_attempt.is_some() {
+                // The attempt before this failed too
+                self.backoff = (self.backoff * 2).min(MAX_RECONNECT_INTERVAL);
+            }
+            self.last_attempt = Some(Instant::now());
+            let stream = self.open()?;
+            self.last_attempt = None;
+            self.backoff = RECONNECT_INTERVAL;
+            self.stream = Some(stream);
+        }
+        self.stream
+            .as_mut()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))
+    }
+
+    /// Connects, and with TLS completes the handshake, so a server which isn't trusted fails
+    /// here rather than on a later write
+    fn open(&self) -> io::Result<Stream> {
+        let addr = self.addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("can't resolve {}", self.addr),
+            )
+        })?;
+        let mut stream = TcpStream::connect_timeout(&addr, TCP_TIMEOUT)?;
+        stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+        let Some(config) = &self.tls else {
+            return Ok(Stream::Plain(stream));
+        };
+        stream.set_read_timeout(Some(TCP_TIMEOUT))?;
+        let host = self
+            .addr
+            .rsplit_once(':')
+            .map_or(self.addr.as_str(), |(host, _)| host)
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut conn = ClientConnection::new(config.clone(), name).map_err(io::Error::other)?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+        Ok(Stream::Tls(Box::new(StreamOwned::new(conn, stream))))
+    }
+}
+
+impl UplinkSink for TcpSink {
+    type Error = io::Error;
+
+    fn send(&mut self, uplink: &Uplink) -> Result<(), Self::Error> {
+        let packet = &uplink.packet;
+        let payload: String = packet
+            .payload
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let line = json!({
+            "gateway": self.gateway.to_string(),
+            "network_id": packet.network_id,
+            "source_id": packet.source_id,
+            "packet_id": packet.packet_id,
+            "received_at": uplink.received_at,
+            "relayed_by": uplink.relayed_by,
+            "hops": uplink.hops,
+            "rssi_dbm": uplink.link.map(|link| link.rssi.0),
+            "snr_db": uplink.link.map(|link| link.snr.0),
+            "payload": payload,
+        });
+        let result = writeln!(self.connect()?, "{}", line);
+        if result.is_err() {
+            self.stream = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use must_hop::node::PacketType;
+
+    fn uplink(packet_id: u16) -> Uplink {
+        Uplink {
+            packet: MHPacket {
+                network_id: 0,
+                destination_id: 1,
+                packet_type: PacketType::Data,
+                flags: 0,
+                packet_id,
+                source_id: 5,
+                max_payload: 40,
+                payload: heapless::Vec::from_slice(&[0xAA]).unwrap(),
+                hop_count: 0,
+                hop_to_gw: 1,
+                route: heapless::Vec::new(),
+            },
+            received_at: 1_700_000_000 + packet_id as i64,
+            relayed_by: None,
+            hops: None,
+            link: None,
+        }
+    }
+
+    /// Takes `accept` packets, then fails
+    struct Recorder {
+        accept: usize,
+        sent: Vec<u16>,
+    }
+
+    impl UplinkSink for Recorder {
+        type Error = &'static str;
+
+        fn send(&mut self, uplink: &Uplink) -> Result<(), Self::Error> {
+            if self.sent.len() == self.accept {
+                return Err("down");
+            }
+            self.sent.push(uplink.packet.packet_id);
+            Ok(())
+        }
+    }
+
+    fn spool(capacity: usize) -> Spool {
+        Spool::with_connection(Connection::open_in_memory().unwrap(), "spool", capacity).unwrap()
+    }
+
+    #[test]
+    fn test_replay_keeps_the_order() {
+        let mut spool = spool(10);
+        for id in [3, 1, 2] {
+            spool.push(&uplink(id)).unwrap();
+        }
+        let mut sink = Recorder {
+            accept: usize::MAX,
+            sent: Vec::new(),
+        };
+        assert_eq!(spool.replay(&mut sink).unwrap(), 3);
+        assert_eq!(sink.sent, [3, 1, 2]);
+        assert!(spool.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_full_spool_drops_the_oldest() {
+        let mut spool = spool(2);
+        for id in 1..=3 {
+            spool.push(&uplink(id)).unwrap();
+        }
+        assert_eq!(spool.len().unwrap(), 2);
+        assert_eq!(spool.dropped(), 1);
+        let mut sink = Recorder {
+            accept: usize::MAX,
+            sent: Vec::new(),
+        };
+        spool.replay(&mut sink).unwrap();
+        assert_eq!(sink.sent, [2, 3]);
+    }
+
+    #[test]
+    fn test_replay_stops_when_the_sink_fails() {
+        let mut spool = spool(10);
+        for id in 1..=3 {
+            spool.push(&uplink(id)).unwrap();
+        }
+        let mut sink = Recorder {
+            accept: 1,
+            sent: Vec::new(),
+        };
+        assert_eq!(spool.replay(&mut sink).unwrap(), 1);
+        assert_eq!(spool.len().unwrap(), 2);
+
+        sink.accept = usize::MAX;
+        assert_eq!(spool.replay(&mut sink).unwrap(), 2);
+        assert_eq!(sink.sent, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_spooled_sends_after_what_is_spooled() {
+        let sink = Recorder {
+            accept: 0,
+            sent: Vec::new(),
+        };
+        let mut spooled = Spooled::new(sink, spool(10));
+        assert_eq!(spooled.send(&uplink(1)).unwrap(), Delivery::Spooled);
+        spooled.sink.accept = usize::MAX;
+        assert_eq!(spooled.send(&uplink(2)).unwrap(), Delivery::Sent);
+        assert_eq!(spooled.sink().sent, [1, 2]);
+    }
+}
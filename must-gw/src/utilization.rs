@@ -0,0 +1,159 @@
+//! How busy each channel of the concentrator is, estimated from what it demodulated. The HAL
+//! doesn't report how long a demodulator was locked, so every packet an IF chain hands over,
+//! including those failing the CRC, counts as busy for its time on air. Traffic too weak to
+//! demodulate isn't counted, so this is a lower bound on what the channel carries.
+//!
+//! The busy share over the last `window` is what the capacity planner (`must-capacity`) gives as
+//! utilization, so its estimates can be checked against a deployment.
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use loragw::{CRCCheck, RxPacket};
+use must_hop::node::airtime::Modulation;
+
+/// Bytes an FSK frame carries on top of its payload: preamble, sync word, length and CRC
+const FSK_OVERHEAD: usize = 5 + 3 + 1 + 2;
+
+/// What one IF chain demodulated over the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLoad {
+    pub if_chain: u8,
+    /// Center frequency of the last packet on the chain
+    pub freq: u32,
+    pub packets: u32,
+    pub crc_errors: u32,
+    /// Share of the window the chain had a packet on the air, from 0 to 1
+    pub busy: f32,
+}
+
+impl fmt::Display for ChannelLoad {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "IF{} at {:.1} MHz: {:.1}% busy, {} packets, {} CRC errors",
+            self.if_chain,
+            self.freq as f64 / 1e6,
+            self.busy * 100.0,
+            self.packets,
+            self.crc_errors
+        )
+    }
+}
+
+/// A demodulated packet
+#[derive(Debug, Clone, Copy)]
+struct Demodulated {
+    at: Instant,
+    if_chain: u8,
+    freq: u32,
+    airtime: Duration,
+    crc_error: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelUtilization {
+    window: Duration,
+    /// Oldest first
+    packets: VecDeque<Demodulated>,
+}
+
+impl ChannelUtilization {
+    /// Estimates over the last `window`
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            packets: VecDeque::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Counts `pkt`, taken from the concentrator at `now`
+    pub fn record(&mut self, pkt: &RxPacket, now: Instant) {
+        self.forget(now);
+        self.packets.push_back(Demodulated {
+            at: now,
+            if_chain: pkt.if_chain(),
+            freq: pkt.freq(),
+            airtime: airtime(pkt),
+            crc_error: matches!(pkt.crc_check(), CRCCheck::Fail),
+        });
+    }
+
+    /// Load of every IF chain which demodulated something within the window, by chain
+    pub fn loads(&self, now: Instant) -> Vec<ChannelLoad> {
+        let mut loads: BTreeMap<u8, (ChannelLoad, Duration)> = BTreeMap::new();
+        for pkt in self.packets.iter().filter(|pkt| !self.expired(pkt, now)) {
+            let (load, airtime) = loads.entry(pkt.if_chain).or_insert((
+                ChannelLoad {
+                    if_chain: pkt.if_chain,
+                    freq: pkt.freq,
+                    packets: 0,
+                    crc_errors: 0,
+                    busy: 0.0,
+                },
+                Duration::ZERO,
+            ));
+            load.freq = pkt.freq;
+            load.packets += 1;
+            load.crc_errors += u32::from(pkt.crc_error);
+            *airtime += pkt.airtime;
+        }
+        loads
+            .into_values()
+            .map(|(load, airtime)| ChannelLoad {
+                busy: (airtime.as_secs_f32() / self.window.as_secs_f32()).min(1.0),
+                ..load
+            })
+            .collect()
+    }
+
+    fn forget(&mut self, now: Instant) {
+        while self
+            .packets
+            .front()
+            .is_some_and(|pkt| self.expired(pkt, now))
+        {
+            self.packets.pop_front();
+        }
+    }
+
+    fn expired(&self, pkt: &Demodulated, now: Instant) -> bool {
+        now.duration_since(pkt.at) > self.window
+    }
+}
+
+/// Time on air of `pkt`, with the usual preamble
+fn airtime(pkt: &RxPacket) -> Duration {
+    match pkt {
+        RxPacket::LoRa(pkt) => {
+            let modulation = Modulation {
+                coding_rate: match pkt.coderate {
+                    loragw::Coderate::Cr4_6 => 6,
+                    loragw::Coderate::Cr4_7 => 7,
+                    loragw::Coderate::Cr4_8 => 8,
+                    _ => 5,
+                },
+                crc: !matches!(pkt.crc_check, CRCCheck::NoCRC),
+                ..Modulation::new(
+                    pkt.spreading as u8,
+                    match pkt.bandwidth {
+                        loragw::Bandwidth::BW250kHz => 250,
+                        loragw::Bandwidth::BW500kHz => 500,
+                        _ => 125,
+                    },
+                )
+            };
+            Duration::from_micros(modulation.airtime(pkt.payload.len()).as_micros())
+        }
+        RxPacket::FSK(pkt) => {
+            let bits = 8 * (pkt.payload.len() + FSK_OVERHEAD) as u64;
+            Duration::from_micros(bits * 1_000_000 / u64::from(pkt.datarate.max(1)))
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! The gateway decodes the golden frames of `must_hop::node::vectors` like it decodes what the
+//! concentrator hears, with its own SIZE and LEN, and encodes what it sends the same way.
+use must_gw::{LEN, SIZE};
+use must_hop::node::{
+    MHPacket, MHPacketView, WIRE_VERSION,
+    network_manager::NetworkManagerError,
+    vectors::{OLD_VECTORS, VECTORS},
+};
+
+#[test]
+fn test_gateway_decodes_vectors() {
+    for vector in VECTORS {
+        assert_eq!(vector.version, WIRE_VERSION, "{}", vector.name);
+        let views = MHPacketView::decode_frame::<LEN>(vector.frame).unwrap();
+        assert_eq!(views.as_slice(), vector.packets, "{}", vector.name);
+        for view in &views {
+            let packet = view.checked_packet::<SIZE>();
+            if view.payload.len() > SIZE {
+                assert!(
+                    matches!(packet, Err(NetworkManagerError::PayloadTooLarge { .. })),
+                    "{}",
+                    vector.name
+                );
+            } else {
+                assert!(packet.is_ok(), "{}", vector.name);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_gateway_encodes_vectors() {
+    for vector in VECTORS {
+        let Ok(packets) = MHPacket::<SIZE>::decode_frame::<LEN>(vector.frame) else {
+            continue;
+        };
+        let mut buf = [0u8; 256];
+        let encoded = MHPacket::encode_frame_into(&packets, &mut buf).unwrap();
+        assert_eq!(encoded, vector.frame, "{}", vector.name);
+    }
+}
+
+#[test]
+fn test_gateway_rejects_old_vectors() {
+    for vector in OLD_VECTORS {
+        assert!(vector.version < WIRE_VERSION, "{}", vector.name);
+        assert!(
+            matches!(
+                MHPacketView::decode_frame::<LEN>(vector.frame),
+                Err(NetworkManagerError::WrongVersion(_))
+            ),
+            "{}",
+            vector.name
+        );
+    }
+}
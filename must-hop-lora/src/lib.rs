@@ -0,0 +1,373 @@
+//! LoRa nodes for must-hop on top of lora-phy: `LoraNode` implements `MHNode` for any
+//! lora-phy radio, and `tasks` runs a node or a gateway with it. An interface variant for the
+//! SX126x is shown in `/examples`.
+#![no_std]
+
+pub mod tasks;
+mod verbosity;
+
+use lora_phy::mod_params::{
+    Bandwidth, CodingRate, ModulationParams, PacketParams, SpreadingFactor,
+};
+use lora_phy::mod_params::{PacketStatus, RadioError};
+use lora_phy::mod_traits::RadioKind;
+use lora_phy::{DelayNs, LoRa, RxMode};
+use must_hop::node::{
+    LinkDirection, LinkQuality, MAX_FRAME_LEN, MHNode, MHPacket, PreambleStats,
+    airtime::Modulation,
+    config::ChannelPlan,
+    region::{Region, RegionError},
+};
+use must_hop::units::{Db, Dbm};
+
+use crate::verbosity::{error, trace};
+
+use embassy_time::Instant;
+use heapless::Vec;
+
+// Approximately 1 second?
+const RECEIVE_TIMEOUT: u16 = 100;
+/// Output power frames are usually sent with
+const TX_POWER: Dbm<i32> = Dbm(20);
+/// Highest output power of the SX126x, for urgent sends. `LoraNode::set_region` lowers it where
+/// the region allows less
+const MAX_TX_POWER: Dbm<i32> = Dbm(22);
+
+/// Parameters that define send and receive parameters
+#[derive(Clone, Copy)]
+pub struct TransmitParameters {
+    pub sf: SpreadingFactor,
+    pub bw: Bandwidth,
+    pub cr: CodingRate,
+    pub lora_hz: u32,
+    pub pre_amp: u16,
+    pub imp_hed: bool,
+    pub max_pack_len: usize,
+    pub crc: bool,
+    /// Decides IQ inversion, mesh traffic uses `LinkDirection::Mesh`
+    pub direction: LinkDirection,
+}
+
+impl TransmitParameters {
+    /// These parameters, on the frequency and data rate of `plan`
+    pub fn with_channel_plan(self, plan: &ChannelPlan) -> Result<Self, RadioError> {
+        let sf = match plan.spreading_factor {
+            5 => SpreadingFactor::_5,
+            6 => SpreadingFactor::_6,
+            7 => SpreadingFactor::_7,
+            8 => SpreadingFactor::_8,
+            9 => SpreadingFactor::_9,
+            10 => SpreadingFactor::_10,
+            11 => SpreadingFactor::_11,
+            12 => SpreadingFactor::_12,
+            _ => return Err(RadioError::UnavailableSpreadingFactor),
+        };
+        let bw = match plan.bandwidth_khz {
+            125 => Bandwidth::_125KHz,
+            250 => Bandwidth::_250KHz,
+            500 => Bandwidth::_500KHz,
+            _ => return Err(RadioError::UnavailableBandwidth),
+        };
+        Ok(Self {
+            sf,
+            bw,
+            lora_hz: plan.frequency_hz,
+            ..self
+        })
+    }
+
+    /// These parameters, if `region` allows their frequency and bandwidth, and a frame of
+    /// `max_pack_len` bytes within its dwell time
+    pub fn in_region(self, region: Region) -> Result<Self, RegionError> {
+        let modulation = self.modulation();
+        region.check_channel(self.lora_hz, modulation.bandwidth_khz)?;
+        region.check_dwell(modulation, self.max_pack_len)?;
+        Ok(self)
+    }
+
+    /// The settings deciding how long a frame is on the air
+    pub fn modulation(&self) -> Modulation {
+        Modulation {
+            spreading_factor: self.sf.factor() as u8,
+            bandwidth_khz: (self.bw.value_in_hz() / 1000) as u16,
+            coding_rate: match self.cr {
+                CodingRate::_4_5 => 5,
+                CodingRate::_4_6 => 6,
+                CodingRate::_4_7 => 7,
+                CodingRate::_4_8 => 8,
+            },
+            preamble_symbols: self.pre_amp,
+            crc: self.crc,
+        }
+    }
+}
+
+/// Unsure whether this will be used
+pub enum RadioState {
+    Rx,
+    Tx,
+}
+/// A node implementatino for lora, where a LoRa interface variant type has to be implemented to
+/// use. An IV for a SX126x is shown in `/examples`
+pub struct LoraNode<'a, RK, DLY, const SIZE: usize, const LEN: usize>
+where
+    RK: RadioKind,
+    DLY: DelayNs,
+{
+    lora: &'a mut LoRa<RK, DLY>,
+    _tp: TransmitParameters,
+    pkt_params: PacketParams,
+    mdltn_params: ModulationParams,
+    /// Of the last frame received
+    last_quality: Option<LinkQuality>,
+    /// Output power, `TX_POWER` until `set_tx_power`
+    tx_power: Dbm<i32>,
+    /// Whether to send at `MAX_TX_POWER`
+    max_power: bool,
+    /// Highest output power the region allows, `MAX_TX_POWER` until `set_region`
+    power_limit: Dbm<i32>,
+    preamble: PreambleStats,
+    /// The last channel activity detection found a preamble, and no frame was received since
+    awaiting_frame: bool,
+}
+
+impl<RK, DLY, const SIZE: usize, const LEN: usize> MHNode<SIZE, LEN>
+    for LoraNode<'_, RK, DLY, SIZE, LEN>
+where
+    RK: RadioKind,
+    DLY: DelayNs,
+{
+    type Error = RadioError;
+    type Connection = Result<(u8, PacketStatus), RadioError>;
+    /// Takes any frame, however large SIZE and LEN are
+    type ReceiveBuffer = [u8; MAX_FRAME_LEN];
+    type Duration = u16;
+
+    async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), RadioError> {
+        let now = Instant::now();
+
+        // TODO: Can this be made opt-in? Such that individual transmission is possible?
+        let mut buffer = [0u8; MAX_FRAME_LEN];
+        trace!(Radio, "BUFFER SIZE IS: {}", SIZE);
+        let used_slice = match MHPacket::encode_frame_into(packets, &mut buffer) {
+            Ok(slice) => slice,
+            Err(e) => {
+                error!(Radio, "Serialization failed: {:?}", e);
+                return Err(RadioError::OpError(1));
+            }
+        };
+        trace!(Radio, "used slice size is {}", used_slice.len());
+        // Simple listen to talk logic
+        // TODO: This crashes when in a loop
+        // loop {
+        // trace!("preparing for cad ...");
+        // self.lora.prepare_for_cad(&self.mdltn_params).await?;
+        // if self.lora.cad(&self.mdltn_params).await? {
+        //     warn!("cad successfull with activity detected");
+        //     // self.lora.sleep(false).await?;
+        //     Timer::after_millis(50).await;
+        //     // TODO: Get some random amount of time before continuing loop
+        // } else {
+        //     trace!("cad successfull with NO activity detected");
+        //     // break;
+        // }
+        // }
+        let before_tx = Instant::now();
+        let power = match self.max_power {
+            true => MAX_TX_POWER,
+            false => self.tx_power,
+        }
+        .min(self.power_limit);
+        self.lora
+            .prepare_for_tx(
+                &self.mdltn_params,
+                &mut self.pkt_params,
+                power.0,
+                used_slice,
+            )
+            .await?;
+
+        self.lora.tx().await?;
+        trace!(Radio, "Transmit successfull!");
+        let after = Instant::now();
+        let tx_dur = after - now;
+        let only_tx = after - before_tx;
+        trace!(
+            Radio,
+            "[TX DURATION] millis: {},\t ticks: {}",
+            tx_dur.as_millis(),
+            tx_dur
+        );
+        trace!(
+            Radio,
+            "[TX DURATION] millis: {},\t ticks: {}",
+            only_tx.as_millis(),
+            only_tx
+        );
+        // Takes around 1.9 seconds for full transmit function, and 1.6 for just transmitting
+
+        // NOTE: This might create a delay between transmitting something and being able to receive
+        // again
+        // lora.sleep(false).await?;
+        // info!("Sleep successful");
+        Ok(())
+    }
+
+    // Should transition to Rx if in Tx
+    async fn receive(
+        &mut self,
+        conn: Result<(u8, PacketStatus), RadioError>,
+        rec_buf: &[u8; MAX_FRAME_LEN],
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, RadioError> {
+        // First we check if we actually got something
+        let (len, rx_pkt_status) = match conn {
+            Ok((len, rx_pkt_status)) => (len, rx_pkt_status),
+            Err(err) => {
+                self.cad_unanswered();
+                match err {
+                    RadioError::ReceiveTimeout => return Err(err),
+                    _ => {
+                        error!(Radio, "Error in receiving_buffer: {:?}", err);
+                        self.preamble.rx_aborts = self.preamble.rx_aborts.saturating_add(1);
+                        return Err(err);
+                    }
+                }
+            }
+        };
+        self.awaiting_frame = false;
+        // trace!("rx successful, pkt status: {:?}", rx_pkt_status);
+        self.last_quality = Some(LinkQuality {
+            rssi: Dbm(rx_pkt_status.rssi),
+            snr: Db(rx_pkt_status.snr.clamp(i8::MIN as i16, i8::MAX as i16) as i8),
+        });
+
+        // Try to unpack the buffer into expected packet
+        let valid_data = &rec_buf[..len as usize];
+        let packets = match MHPacket::<SIZE>::decode_frame::<LEN>(valid_data) {
+            Ok(packet) => packet,
+            Err(e) => {
+                error!(Radio, "Deserialization failed: {:?}", e);
+                return Err(RadioError::PayloadSizeUnexpected(0));
+            }
+        };
+        trace!(Radio, "Got packet!");
+
+        // TODO: Check if this should be retransmitted
+        // if (packet.to != me)
+        // transmit(lora, packet, tp).await?;
+
+        Ok(packets)
+    }
+
+    async fn listen(
+        &mut self,
+        rec_buf: &mut [u8; MAX_FRAME_LEN],
+        with_timeout: bool,
+    ) -> Result<Self::Connection, RadioError> {
+        let rec_mode = match with_timeout {
+            true => RxMode::Single(RECEIVE_TIMEOUT),
+            false => RxMode::Continuous,
+        };
+        self.prepare_for_rx(rec_mode).await?;
+        Ok(self.lora.rx(&self.pkt_params, rec_buf).await)
+    }
+
+    fn link_quality(&self) -> Option<LinkQuality> {
+        self.last_quality
+    }
+
+    fn set_max_power(&mut self, max: bool) -> Result<(), RadioError> {
+        self.max_power = max;
+        Ok(())
+    }
+
+    fn set_tx_power(&mut self, power: Dbm<i8>) -> Result<(), RadioError> {
+        self.tx_power = Dbm::from(power).min(MAX_TX_POWER);
+        Ok(())
+    }
+
+    async fn channel_activity(&mut self) -> Result<Option<bool>, RadioError> {
+        self.cad_unanswered();
+        self.lora.prepare_for_cad(&self.mdltn_params).await?;
+        let active = self.lora.cad(&self.mdltn_params).await?;
+        if active {
+            self.preamble.cad_detections = self.preamble.cad_detections.saturating_add(1);
+            self.awaiting_frame = true;
+        }
+        Ok(Some(active))
+    }
+
+    fn preamble_stats(&self) -> Option<PreambleStats> {
+        Some(self.preamble)
+    }
+
+    fn apply_channel_plan(&mut self, plan: &ChannelPlan) -> Result<(), RadioError> {
+        let tp = self._tp.with_channel_plan(plan)?;
+        (self.mdltn_params, self.pkt_params) = Self::radio_params(self.lora, &tp)?;
+        self._tp = tp;
+        Ok(())
+    }
+}
+
+impl<'a, RK, DLY, const N: usize, const LEN: usize> LoraNode<'a, RK, DLY, N, LEN>
+where
+    RK: RadioKind,
+    DLY: DelayNs,
+{
+    pub fn new(lora: &'a mut LoRa<RK, DLY>, tp: TransmitParameters) -> Result<Self, RadioError> {
+        let (mdltn_params, pkt_params) = Self::radio_params(lora, &tp)?;
+        Ok(Self {
+            lora,
+            _tp: tp,
+            pkt_params,
+            mdltn_params,
+            last_quality: None,
+            tx_power: TX_POWER,
+            max_power: false,
+            power_limit: MAX_TX_POWER,
+            preamble: PreambleStats::default(),
+            awaiting_frame: false,
+        })
+    }
+
+    /// Keeps to `region` with an antenna of `antenna_gain`: fails if the transmit parameters are
+    /// outside it, and lowers the output power, urgent sends included, to what it allows
+    pub fn set_region(&mut self, region: Region, antenna_gain: Db<i8>) -> Result<(), RegionError> {
+        self._tp.in_region(region)?;
+        self.power_limit = Dbm::from(region.max_tx_power(antenna_gain)).min(MAX_TX_POWER);
+        Ok(())
+    }
+
+    /// Counts a preamble found by channel activity detection which no frame followed, once the
+    /// next detection or a failed reception shows it
+    fn cad_unanswered(&mut self) {
+        if core::mem::take(&mut self.awaiting_frame) {
+            self.preamble.cad_false_positives = self.preamble.cad_false_positives.saturating_add(1);
+        }
+    }
+
+    fn radio_params(
+        lora: &mut LoRa<RK, DLY>,
+        tp: &TransmitParameters,
+    ) -> Result<(ModulationParams, PacketParams), RadioError> {
+        let mdltn_params = lora.create_modulation_params(tp.sf, tp.bw, tp.cr, tp.lora_hz)?;
+
+        let pkt_params = lora.create_rx_packet_params(
+            tp.pre_amp,
+            tp.imp_hed,
+            tp.max_pack_len as u8,
+            tp.crc,
+            tp.direction.iq_inverted(),
+            &mdltn_params,
+        )?;
+        Ok((mdltn_params, pkt_params))
+    }
+
+    pub async fn prepare_for_rx(&mut self, rx_mode: RxMode) -> Result<(), RadioError> {
+        // TODO: Is it a proble using single here? Should it be continouos to not get timeout
+        // errors all the time? Can this listening be timed and synchronized for a TDMA?
+        self.lora
+            .prepare_for_rx(rx_mode, &self.mdltn_params, &self.pkt_params)
+            .await
+    }
+}
@@ -0,0 +1,146 @@
+//! Tasks running a LoRa node or a gateway on the mesh
+
+use crate::verbosity::{error, info, warn};
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::channel;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
+
+use must_hop::node::{
+    MAX_FRAME_LEN, MHPacket,
+    entropy::EntropySource,
+    mesh_router::MeshRouter,
+    network_manager::NetworkManager,
+    policy::{GatewayPolicy, NodePolicy},
+};
+
+use crate::{LoraNode, TransmitParameters};
+
+use lora_phy::mod_traits::RadioKind;
+use lora_phy::{DelayNs, LoRa};
+
+/// Runs a LoRa node, sending what arrives on `channel` to the gateway, e.g. payloads of
+/// `must_hop::telemetry::SensorReport::encode`. Its manager is seeded from `entropy`, see
+/// `NetworkManager::seed`.
+pub async fn lora_task<RK, DLY, T, M, const SIZE: usize, const LEN: usize>(
+    lora: &mut LoRa<RK, DLY>,
+    channel: channel::Receiver<'static, M, T, 3>,
+    tp: TransmitParameters,
+    source_id: u8,
+    timeout: u8,
+    max_retries: u8,
+    entropy: impl EntropySource,
+) where
+    RK: RadioKind,
+    DLY: DelayNs,
+    T: Into<Vec<u8, SIZE>>,
+    M: embassy_sync::blocking_mutex::raw::RawMutex,
+{
+    let node = match LoraNode::new(lora, tp) {
+        Ok(rx) => rx,
+        Err(e) => {
+            error!(Mac, "Error in preparing for RX: {:?}", e);
+            return;
+        }
+    };
+    let mut nm = NetworkManager::<SIZE, LEN>::new(source_id, timeout, max_retries);
+    nm.seed(entropy);
+    let mut router = MeshRouter::new(node, nm, NodePolicy);
+    loop {
+        info!(Mac, "In lora task loop");
+
+        let mut receiving_buffer = [0u8; MAX_FRAME_LEN];
+
+        info!(Mac, "Waiting for packet or sensor data to send");
+        // Either sensor data should be sent, or a packet is ready to be received
+        let either = select(channel.receive(), router.listen(&mut receiving_buffer)).await;
+        match either {
+            Either::First(data) => {
+                info!(Mac, "SENSOR DATA won");
+                // destination 0 is the gateway
+                if let Err(e) = router.send_payload(data.into(), 0).await {
+                    error!(Mac, "Error in transmitting sensor data: {:?}", e);
+                    continue;
+                }
+            }
+            Either::Second(conn) => {
+                info!(Mac, "RECEIVER won, reading ...");
+                let conn = match conn {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        error!(Mac, "Error in getting connection: {:?}", e);
+                        continue;
+                    }
+                };
+                let my_pkts = match router.receive(conn, &receiving_buffer).await {
+                    Ok(pkts) => pkts,
+                    Err(e) => {
+                        error!(Mac, "Error in receiving packet: {:?}", e);
+                        continue;
+                    }
+                };
+                info!(Mac, "I got these pkts: {}", my_pkts.len());
+            }
+        }
+    }
+}
+
+/// Runs a LoRa gateway with `manager`, e.g. one set up with a network id and channel plan to
+/// advertise. Sends a bootup every `bootup_interval`, ACKs what the mesh sends it, and passes the
+/// received packets to `uplinks`, for a task forwarding them over the backhaul. While `uplinks`
+/// is full, packets are dropped rather than holding up the radio.
+pub async fn gateway_task<RK, DLY, M, const SIZE: usize, const LEN: usize, const CAP: usize>(
+    lora: &mut LoRa<RK, DLY>,
+    uplinks: channel::Sender<'static, M, MHPacket<SIZE>, CAP>,
+    tp: TransmitParameters,
+    manager: NetworkManager<SIZE, LEN>,
+    bootup_interval: Duration,
+) where
+    RK: RadioKind,
+    DLY: DelayNs,
+    M: embassy_sync::blocking_mutex::raw::RawMutex,
+{
+    let node = match LoraNode::new(lora, tp) {
+        Ok(rx) => rx,
+        Err(e) => {
+            error!(Mac, "Error in preparing for RX: {:?}", e);
+            return;
+        }
+    };
+    let mut router = MeshRouter::new(node, manager, GatewayPolicy);
+    let mut next_bootup = Instant::now();
+    let mut receiving_buffer = [0u8; MAX_FRAME_LEN];
+    loop {
+        let either = select(Timer::at(next_bootup), router.listen(&mut receiving_buffer)).await;
+        match either {
+            Either::First(()) => {
+                next_bootup += bootup_interval;
+                if let Err(e) = router.bootup().await {
+                    error!(Mac, "Error in sending bootup: {:?}", e);
+                }
+            }
+            Either::Second(conn) => {
+                let conn = match conn {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        error!(Mac, "Error in getting connection: {:?}", e);
+                        continue;
+                    }
+                };
+                let pkts = match router.receive(conn, &receiving_buffer).await {
+                    Ok(pkts) => pkts,
+                    Err(e) => {
+                        error!(Mac, "Error in receiving packet: {:?}", e);
+                        continue;
+                    }
+                };
+                for delivered in pkts {
+                    if uplinks.try_send(delivered.packet).is_err() {
+                        warn!(Mac, "Uplink queue is full, dropping a packet");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+/// must-hop's logging macros, which are private to it, for the subsystems this crate logs as. They
+/// follow the levels set with `must_hop::verbosity::set` like must-hop's own logs do.
+#[cfg(feature = "defmt")]
+macro_rules! log_at {
+    ($level:ident, $log:ident, $subsystem:ident, $($arg:tt)+) => {
+        if must_hop::verbosity::enabled(
+            must_hop::verbosity::Subsystem::$subsystem,
+            must_hop::verbosity::Level::$level,
+        ) {
+            defmt::$log!($($arg)+);
+        }
+    };
+}
+
+// Without a logger the arguments are only type checked
+#[cfg(not(feature = "defmt"))]
+macro_rules! log_at {
+    ($level:ident, $log:ident, $subsystem:ident, $($arg:tt)+) => {
+        if false {
+            let _ = core::format_args!($($arg)+);
+        }
+    };
+}
+
+/// Like `defmt::error!`, for `Subsystem::$subsystem`
+macro_rules! log_error {
+    ($subsystem:ident, $($arg:tt)+) => { $crate::verbosity::log_at!(Error, error, $subsystem, $($arg)+) };
+}
+
+/// Like `defmt::warn!`, for `Subsystem::$subsystem`
+macro_rules! log_warn {
+    ($subsystem:ident, $($arg:tt)+) => { $crate::verbosity::log_at!(Warn, warn, $subsystem, $($arg)+) };
+}
+
+/// Like `defmt::info!`, for `Subsystem::$subsystem`
+macro_rules! log_info {
+    ($subsystem:ident, $($arg:tt)+) => { $crate::verbosity::log_at!(Info, info, $subsystem, $($arg)+) };
+}
+
+/// Like `defmt::trace!`, for `Subsystem::$subsystem`
+macro_rules! log_trace {
+    ($subsystem:ident, $($arg:tt)+) => { $crate::verbosity::log_at!(Trace, trace, $subsystem, $($arg)+) };
+}
+
+pub(crate) use {
+    log_at, log_error as error, log_info as info, log_trace as trace, log_warn as warn,
+};
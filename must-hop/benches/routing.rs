@@ -0,0 +1,153 @@
+//! Hot paths of a node: encoding and decoding frames, and routing a batch of received packets.
+//! Run with `just bench`, criterion keeps the previous run to compare against.
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use must_hop::node::{
+    MHPacket, MHPacketView, PacketType,
+    network_manager::{NetworkManager, RecentSeen},
+    seen_filter::{SeenFilter, SeenSet},
+};
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+fn packet(source_id: u8, destination_id: u8, packet_id: u16) -> MHPacket<SIZE> {
+    MHPacket {
+        network_id: 0,
+        destination_id,
+        packet_type: PacketType::Data,
+        flags: 0,
+        packet_id,
+        source_id,
+        max_payload: SIZE as u8,
+        payload: Vec::from_slice(&[0xA5; SIZE]).unwrap(),
+        hop_count: 1,
+        hop_to_gw: 2,
+        route: Vec::new(),
+    }
+}
+
+/// A full frame of packets from node 9, starting at `first_id`
+fn frame(destination_id: u8, first_id: u16) -> Vec<MHPacket<SIZE>, LEN> {
+    (0..LEN as u16)
+        .map(|i| packet(9, destination_id, first_id + i))
+        .collect()
+}
+
+fn codec(c: &mut Criterion) {
+    let frame = frame(1, 0);
+    let mut buf = [0u8; 512];
+    let bytes = MHPacket::encode_frame_into(&frame, &mut buf)
+        .unwrap()
+        .to_vec();
+
+    let mut group = c.benchmark_group("codec");
+    group.bench_function("encode_frame", |b| {
+        let mut buf = [0u8; 512];
+        b.iter(|| {
+            MHPacket::encode_frame_into(black_box(&frame), &mut buf)
+                .unwrap()
+                .len()
+        })
+    });
+    group.bench_function("decode_frame", |b| {
+        b.iter(|| MHPacket::<SIZE>::decode_frame::<LEN>(black_box(&bytes)).unwrap())
+    });
+    group.bench_function("decode_frame_view", |b| {
+        b.iter(|| MHPacketView::decode_frame::<LEN>(black_box(&bytes)).unwrap())
+    });
+    group.finish();
+}
+
+fn routing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("handle_packets");
+
+    // Node 1 has already seen the frame, so the dedup cache is full and every lookup hits
+    group.bench_function("duplicates", |b| {
+        let mut manager = NetworkManager::<SIZE, LEN>::new(1, 10, 3);
+        let seen = frame(1, 0);
+        manager.handle_packets(seen.clone()).unwrap();
+        b.iter_batched(
+            || seen.clone(),
+            |pkts| manager.handle_packets(pkts).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    // Packets node 1 is not in the path of, with fresh ids so every lookup misses and the cache
+    // keeps rotating
+    group.bench_function("not_on_path", |b| {
+        let mut manager = NetworkManager::<SIZE, LEN>::new(1, 10, 3);
+        let mut next_id = 0u16;
+        b.iter_batched(
+            || {
+                next_id = next_id.wrapping_add(LEN as u16);
+                frame(8, next_id)
+            },
+            |pkts| manager.handle_packets(pkts).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn recent_seen(c: &mut Criterion) {
+    let mut seen = RecentSeen::<LEN>::new();
+    for i in 0..LEN as u16 {
+        seen.push((9, i));
+    }
+
+    let mut group = c.benchmark_group("recent_seen");
+    // The oldest entry, so the whole buffer is searched
+    group.bench_function("hit", |b| b.iter(|| seen.contains(black_box((9, 0)))));
+    group.bench_function("miss", |b| b.iter(|| seen.contains(black_box((9, 1000)))));
+    group.finish();
+}
+
+/// Pairs a relay next to the gateway overhears within a retry timeout
+const OVERHEARD: u16 = 256;
+
+/// The ring sized to remember every overheard packet, against a filter of 4 buckets of 2048 bits
+/// taking as much memory
+fn seen_filter(c: &mut Criterion) {
+    let now = Instant::from_secs(0);
+    let mut ring = RecentSeen::<{ OVERHEARD as usize }>::new();
+    let mut filter = SeenFilter::<4, 64>::new(Duration::from_secs(30), 5);
+    for i in 0..OVERHEARD {
+        ring.push((9, i));
+        filter.insert((9, i), now);
+    }
+
+    println!(
+        "RecentSeen<{OVERHEARD}>: {} bytes, SeenFilter<4, 64>: {} bytes, {:.2}% false positives",
+        core::mem::size_of::<RecentSeen<{ OVERHEARD as usize }>>(),
+        SeenFilter::<4, 64>::MEMORY,
+        filter.false_positive_rate(now) * 100.0
+    );
+
+    let mut group = c.benchmark_group("seen_filter");
+    group.bench_function("ring_hit", |b| b.iter(|| ring.contains(black_box((9, 0)))));
+    group.bench_function("ring_miss", |b| {
+        b.iter(|| ring.contains(black_box((9, 1000))))
+    });
+    group.bench_function("filter_hit", |b| {
+        b.iter(|| filter.contains(black_box((9, 0)), now))
+    });
+    group.bench_function("filter_miss", |b| {
+        b.iter(|| filter.contains(black_box((9, 1000)), now))
+    });
+    group.bench_function("filter_insert", |b| {
+        let mut packet_id = 0u16;
+        b.iter(|| {
+            packet_id = packet_id.wrapping_add(1);
+            filter.insert(black_box((9, packet_id)), now)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, codec, routing, recent_seen, seen_filter);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! Prints the on-air size of MHPackets and full frames for a few SIZE/LEN configurations, to
+//! spot wire format changes which push a frame past what a LoRa radio can send.
+//! Run with `just bench-size`.
+use heapless::Vec;
+use must_hop::node::{MAX_SIZE, MHPacket, PacketType, route::MAX_ROUTE_LEN};
+
+/// Largest LoRa payload
+const LORA_MAX_PAYLOAD: usize = 255;
+
+/// The largest packet: ids, payload length and route at their longest encoding
+fn worst_case<const SIZE: usize>(payload_len: usize) -> MHPacket<SIZE> {
+    MHPacket {
+        network_id: u8::MAX,
+        // Only packets to the gateway carry hop_to_gw
+        destination_id: 1,
+        packet_type: PacketType::BootUp,
+        flags: u8::MAX,
+        packet_id: u16::MAX,
+        source_id: u8::MAX,
+        max_payload: SIZE as u8,
+        payload: (0..payload_len).map(|_| 0xFF).collect(),
+        hop_count: u8::MAX,
+        hop_to_gw: u8::MAX,
+        route: (0..MAX_ROUTE_LEN).map(|_| u8::MAX).collect(),
+    }
+}
+
+fn encoded_len<T: serde::Serialize>(value: &T) -> usize {
+    let mut buf = [0u8; 4096];
+    postcard::to_slice(value, &mut buf).unwrap().len()
+}
+
+fn report<const SIZE: usize, const LEN: usize>() {
+    let empty = encoded_len(&worst_case::<SIZE>(0));
+    let full = encoded_len(&worst_case::<SIZE>(SIZE));
+    let frame: Vec<MHPacket<SIZE>, LEN> = (0..LEN).map(|_| worst_case(SIZE)).collect();
+    let frame_len = encoded_len(&frame);
+    println!(
+        "{:>5} {:>4} {:>14} {:>13} {:>11} {:>8}",
+        SIZE,
+        LEN,
+        empty,
+        full,
+        frame_len,
+        if frame_len <= LORA_MAX_PAYLOAD {
+            "yes"
+        } else {
+            "NO"
+        }
+    );
+}
+
+fn main() {
+    println!(
+        "{:>5} {:>4} {:>14} {:>13} {:>11} {:>8}",
+        "SIZE", "LEN", "empty packet", "full packet", "full frame", "fits"
+    );
+    report::<16, 1>();
+    report::<16, 5>();
+    report::<40, 1>();
+    report::<40, 5>();
+    report::<128, 1>();
+    report::<128, 5>();
+    report::<MAX_SIZE, 1>();
+}
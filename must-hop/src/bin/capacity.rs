@@ -0,0 +1,224 @@
+//! Plans how many nodes a channel takes before deploying them. From the node count, how often
+//! they report, their spreading factors and payload size, prints how busy each spreading factor
+//! keeps the channel, how likely a frame collides, and how large the dedup ring (`LEN`) and the
+//! queue of packets waiting for an ACK (`PENDING`) should be. Airtime comes from `must_hop::node::airtime`, like in the collision simulator.
+//!
+//! `must-capacity <nodes> <interval secs> <payload bytes> [sf:share ...] [--bw kHz] [--hops n]
+//! [--timeout secs] [--retries n] [--measured percent]`, e.g.
+//! `must-capacity 200 600 20 7:50 9:30 12:20 --hops 2`. With `--measured`, the busy share the
+//! gateway logs for the channel is compared with the plan. Run with `just capacity ...`.
+use std::process::ExitCode;
+
+use must_hop::node::{MAX_SIZE, MHPacket, PacketType, airtime::Modulation, route::MAX_ROUTE_LEN};
+
+/// Bandwidth unless `--bw` says
+const DEFAULT_BANDWIDTH_KHZ: u16 = 125;
+/// Transmissions per uplink unless `--hops` says, one per hop to the gateway
+const DEFAULT_HOPS: u32 = 1;
+/// Like the examples give `NetworkManager::new`, unless `--timeout` and `--retries` say
+const DEFAULT_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_RETRIES: u32 = 3;
+/// Retransmission timeouts stop doubling after this many retries, like in the NetworkManager
+const MAX_BACKOFF_SHIFT: u32 = 6;
+/// Largest LoRa payload, which a frame of `LEN` packets must fit in
+const LORA_MAX_PAYLOAD: usize = 255;
+
+struct Plan {
+    nodes: u32,
+    interval_secs: f64,
+    payload_len: usize,
+    /// Spreading factor and the share of nodes using it, shares summing to 1
+    spreading_factors: Vec<(u8, f64)>,
+    bandwidth_khz: u16,
+    hops: u32,
+    timeout_secs: u64,
+    retries: u32,
+    /// Utilization of the channel as the gateway measured it, from 0 to 1
+    measured: Option<f64>,
+}
+
+impl Plan {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut weights = Vec::new();
+        let mut plan = Plan {
+            nodes: 0,
+            interval_secs: 0.0,
+            payload_len: 0,
+            spreading_factors: Vec::new(),
+            bandwidth_khz: DEFAULT_BANDWIDTH_KHZ,
+            hops: DEFAULT_HOPS,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            retries: DEFAULT_RETRIES,
+            measured: None,
+        };
+        while let Some(arg) = args.next() {
+            let mut value =
+                |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+            match arg.as_str() {
+                "--bw" => plan.bandwidth_khz = parse(&value("--bw")?, "bandwidth")?,
+                "--hops" => plan.hops = parse::<u32>(&value("--hops")?, "hops")?.max(1),
+                "--timeout" => plan.timeout_secs = parse(&value("--timeout")?, "timeout")?,
+                "--retries" => plan.retries = parse(&value("--retries")?, "retries")?,
+                "--measured" => {
+                    let percent: f64 = parse(&value("--measured")?, "percentage")?;
+                    plan.measured = Some(percent / 100.0);
+                }
+                _ => match arg.split_once(':') {
+                    Some((sf, share)) => {
+                        let sf: u8 = parse(sf, "spreading factor")?;
+                        if !(5..=12).contains(&sf) {
+                            return Err(format!("spreading factor {} is not 5 to 12", sf));
+                        }
+                        weights.push((sf, parse::<f64>(share, "share")?));
+                    }
+                    None => positional.push(arg),
+                },
+            }
+        }
+        let [nodes, interval, payload] = positional.as_slice() else {
+            return Err("expected <nodes> <interval secs> <payload bytes>".into());
+        };
+        plan.nodes = parse(nodes, "nodes")?;
+        plan.interval_secs = parse(interval, "interval")?;
+        plan.payload_len = parse(payload, "payload")?;
+        if plan.interval_secs <= 0.0 {
+            return Err("interval must be above 0".into());
+        }
+        if plan.payload_len > MAX_SIZE {
+            return Err(format!("payload is larger than {} bytes", MAX_SIZE));
+        }
+        if weights.is_empty() {
+            weights.push((7, 1.0));
+        }
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        if total <= 0.0 {
+            return Err("shares must sum to more than 0".into());
+        }
+        plan.spreading_factors = weights.into_iter().map(|(sf, w)| (sf, w / total)).collect();
+        Ok(plan)
+    }
+
+    /// How long a node waits for an ACK over all its retries, so how long copies of a packet
+    /// may still arrive
+    fn retry_window_secs(&self) -> f64 {
+        (0..=self.retries)
+            .map(|retry| (self.timeout_secs << retry.min(MAX_BACKOFF_SHIFT)) as f64)
+            .sum()
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str, what: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} is not a valid {}", value, what))
+}
+
+/// Bytes on the air for a frame with one uplink of `payload_len` bytes, after it passed `relays`
+/// relays which it records in its route
+fn frame_len(payload_len: usize, relays: u32) -> usize {
+    let packet = MHPacket::<MAX_SIZE> {
+        network_id: 0,
+        destination_id: 1,
+        packet_type: PacketType::Data,
+        flags: 0,
+        packet_id: u16::MAX,
+        source_id: u8::MAX,
+        max_payload: MAX_SIZE as u8,
+        payload: (0..payload_len).map(|_| 0xFF).collect(),
+        hop_count: 0,
+        hop_to_gw: u8::MAX,
+        route: (0..(relays as usize).min(MAX_ROUTE_LEN))
+            .map(|_| u8::MAX)
+            .collect(),
+    };
+    MHPacket::frame_len(&[packet])
+}
+
+fn main() -> ExitCode {
+    let plan = match Plan::from_args(std::env::args().skip(1)) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Sized as on the last hop, where the route is longest
+    let frame = frame_len(plan.payload_len, plan.hops - 1);
+    // A packet goes over every hop, and the gateway ACKs it back over them
+    let ack_frame = frame_len(0, 0);
+    println!(
+        "{} nodes reporting {} bytes every {} s, {} byte frames over {} hop(s)\n",
+        plan.nodes, plan.payload_len, plan.interval_secs, frame, plan.hops
+    );
+    println!(
+        "{:>3} {:>6} {:>10} {:>12} {:>10} {:>10}",
+        "SF", "nodes", "airtime", "utilization", "collision", "duty cycle"
+    );
+
+    // Frames on the air per second, and the share of time they take, over all spreading factors
+    let mut total_rate = 0.0;
+    let mut total_load = 0.0;
+    for (sf, share) in &plan.spreading_factors {
+        let modulation = Modulation::new(*sf, plan.bandwidth_khz);
+        let airtime = modulation.airtime(frame).as_micros() as f64 / 1e6;
+        let ack_airtime = modulation.airtime(ack_frame).as_micros() as f64 / 1e6;
+        let nodes = plan.nodes as f64 * share;
+        let rate = nodes * plan.hops as f64 / plan.interval_secs;
+        total_rate += 2.0 * rate;
+        // Spreading factors barely interfere, so each is its own channel. Nodes send as soon as
+        // they can, so a frame collides like in pure ALOHA.
+        let load = rate * (airtime + ack_airtime);
+        total_load += load;
+        let collision = 1.0 - (-2.0 * load).exp();
+        println!(
+            "{:>3} {:>6.0} {:>8.1}ms {:>11.1}% {:>9.1}% {:>9.2}%",
+            sf,
+            nodes,
+            airtime * 1e3,
+            load * 100.0,
+            collision * 100.0,
+            airtime / plan.interval_secs * 100.0
+        );
+    }
+
+    // The gateway counts every spreading factor of a channel together
+    println!(
+        "\nAll spreading factors keep the channel {:.1}% busy.",
+        total_load * 100.0
+    );
+    if let Some(measured) = plan.measured {
+        println!(
+            "The gateway measured {:.1}%, {:.2} times the plan.",
+            measured * 100.0,
+            measured / total_load.max(f64::MIN_POSITIVE)
+        );
+        if measured > total_load {
+            println!("Something else shares the channel, e.g. other networks, or more retries.");
+        }
+    }
+
+    // A relay hearing every frame must remember each for as long as copies may arrive
+    let window = plan.retry_window_secs();
+    let dedup = (total_rate * window).ceil().max(1.0) as usize;
+    // A node keeps its own packets until they are ACKed, at worst over all retries
+    let queue = (window / plan.interval_secs).ceil().max(1.0) as usize;
+    let fits = (LORA_MAX_PAYLOAD - 1) / (frame - 1);
+    println!(
+        "Retry window {:.0} s: dedup ring of at least {} packets, queue of at least {} \
+        (own packets, more on relays).",
+        window, dedup, queue
+    );
+    println!(
+        "LEN sizes the ring and PENDING the queue, and a frame of LEN packets fits in a LoRa \
+        payload for LEN up to {}.",
+        fits
+    );
+    if dedup > fits {
+        println!(
+            "The ring doesn't fit a frame's LEN, so fewer nodes per channel or longer intervals, \
+            or a seen_filter::SeenFilter next to it."
+        );
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,181 @@
+/// LZSS compression in the heatshrink format, for blobs such as configuration or firmware which are
+/// sent to nodes. Decompressing needs no allocation and no window buffer besides the output, so
+/// nodes can unpack a blob straight into where it goes.
+///
+/// The bit stream is heatshrink's with a window of `2^WINDOW_BITS` bytes and matches of up to
+/// `2^LOOKAHEAD_BITS` bytes: a 1 bit followed by a literal byte, or a 0 bit followed by the
+/// offset - 1 and the length - 1 of a match earlier in the output, as `heatshrink -w 8 -l 4`
+/// writes it.
+use core::fmt;
+
+use heapless::Vec;
+
+use crate::node::{MHPacket, flags};
+
+/// Bits of a match offset, matches are at most 256 bytes back
+pub const WINDOW_BITS: u8 = 8;
+/// Bits of a match length, matches are at most 16 bytes long
+pub const LOOKAHEAD_BITS: u8 = 4;
+
+const WINDOW: usize = 1 << WINDOW_BITS;
+const LOOKAHEAD: usize = 1 << LOOKAHEAD_BITS;
+/// A match costs 1 + 8 + 4 bits, and a literal 9 bits, so a match pays off from 2 bytes
+const MIN_MATCH: usize = 2;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CompressError {
+    /// The output buffer is too small
+    OutputFull,
+    /// A match points before the start of the output, so the input isn't a valid blob
+    BadMatch,
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::OutputFull => write!(f, "output buffer is full"),
+            CompressError::BadMatch => write!(f, "match points before the start of the output"),
+        }
+    }
+}
+
+impl core::error::Error for CompressError {}
+
+struct BitWriter<'a> {
+    out: &'a mut [u8],
+    /// Bits written so far
+    bits: usize,
+}
+
+impl BitWriter<'_> {
+    fn write(&mut self, value: u16, count: u8) -> Result<(), CompressError> {
+        for i in (0..count).rev() {
+            let byte = self.bits / 8;
+            let slot = self.out.get_mut(byte).ok_or(CompressError::OutputFull)?;
+            if self.bits.is_multiple_of(8) {
+                *slot = 0;
+            }
+            if (value >> i) & 1 == 1 {
+                *slot |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+        Ok(())
+    }
+
+    /// Bytes used, the last one padded with zeros
+    fn len(&self) -> usize {
+        self.bits.div_ceil(8)
+    }
+}
+
+struct BitReader<'a> {
+    input: &'a [u8],
+    bits: usize,
+}
+
+impl BitReader<'_> {
+    /// The next `count` bits, or None if the input ends before them
+    fn read(&mut self, count: u8) -> Option<u16> {
+        if self.bits + count as usize > self.input.len() * 8 {
+            return None;
+        }
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = (self.input[self.bits / 8] >> (7 - self.bits % 8)) & 1;
+            value = (value << 1) | bit as u16;
+            self.bits += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Compresses `input` into `out`, returning the bytes used. Text-like blobs typically shrink by
+/// a quarter to a half, random data grows by an eighth.
+pub fn compress(input: &[u8], out: &mut [u8]) -> Result<usize, CompressError> {
+    let mut writer = BitWriter { out, bits: 0 };
+    let mut pos = 0;
+    while pos < input.len() {
+        let (offset, len) = longest_match(input, pos);
+        if len >= MIN_MATCH {
+            writer.write(0, 1)?;
+            writer.write((offset - 1) as u16, WINDOW_BITS)?;
+            writer.write((len - 1) as u16, LOOKAHEAD_BITS)?;
+            pos += len;
+        } else {
+            writer.write(1, 1)?;
+            writer.write(input[pos] as u16, 8)?;
+            pos += 1;
+        }
+    }
+    Ok(writer.len())
+}
+
+/// Offset back and length of the longest match for `input[pos..]` in the window before it
+fn longest_match(input: &[u8], pos: usize) -> (usize, usize) {
+    let max_len = LOOKAHEAD.min(input.len() - pos);
+    let mut best = (0, 0);
+    for start in pos.saturating_sub(WINDOW)..pos {
+        // A match may run into the bytes it produces, like any LZ77 match
+        let len = (0..max_len)
+            .take_while(|&i| input[start + i] == input[pos + i])
+            .count();
+        if len > best.1 {
+            best = (pos - start, len);
+            if len == max_len {
+                break;
+            }
+        }
+    }
+    best
+}
+
+/// Decompresses `input` into `out`, returning the bytes written
+pub fn decompress(input: &[u8], out: &mut [u8]) -> Result<usize, CompressError> {
+    let mut reader = BitReader { input, bits: 0 };
+    let mut len = 0;
+    // The input ends with fewer bits left than a whole literal or match, which are padding
+    while let Some(tag) = reader.read(1) {
+        if tag == 1 {
+            let Some(byte) = reader.read(8) else {
+                break;
+            };
+            *out.get_mut(len).ok_or(CompressError::OutputFull)? = byte as u8;
+            len += 1;
+            continue;
+        }
+        let (Some(offset), Some(count)) = (reader.read(WINDOW_BITS), reader.read(LOOKAHEAD_BITS))
+        else {
+            break;
+        };
+        let offset = offset as usize + 1;
+        let count = count as usize + 1;
+        if offset > len {
+            return Err(CompressError::BadMatch);
+        }
+        if len + count > out.len() {
+            return Err(CompressError::OutputFull);
+        }
+        // Byte by byte, since the match may overlap what it writes
+        for _ in 0..count {
+            out[len] = out[len - offset];
+            len += 1;
+        }
+    }
+    Ok(len)
+}
+
+/// Decompresses the payload of a packet sent with `flags::COMPRESSED` in place, and clears the
+/// flag. A blob which doesn't fit in a packet once decompressed is left as it came, flag and all,
+/// for the application to decompress into a buffer of its own.
+pub fn inflate<const SIZE: usize>(pkt: &mut MHPacket<SIZE>) -> Result<(), CompressError> {
+    if !pkt.has_flag(flags::COMPRESSED) {
+        return Ok(());
+    }
+    let mut out = [0u8; SIZE];
+    let len = decompress(&pkt.payload, &mut out)?;
+    pkt.payload = Vec::from_slice(&out[..len]).map_err(|_| CompressError::OutputFull)?;
+    pkt.set_flag(flags::COMPRESSED, false);
+    Ok(())
+}
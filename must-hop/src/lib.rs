@@ -1,6 +1,20 @@
 #![no_std]
 // #![no_main]
 
-pub mod lora;
+#[cfg(feature = "sim")]
+extern crate alloc;
+#[cfg(feature = "in_std")]
+extern crate std;
+
+#[cfg(feature = "compress")]
+pub mod compress;
 pub mod node;
-pub mod tasks;
+pub mod prelude;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod telemetry;
+/// Signal levels and ratios, from `must-units` which `loragw` shares
+pub use must_units as units;
+pub mod verbosity;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -5,16 +5,46 @@ use core::future::Future;
 use heapless::Vec;
 use serde::{Deserialize, Serialize};
 
+use config::ChannelPlan;
+use network_manager::NetworkManagerError;
+use route::Route;
+
+use crate::units::{Db, Dbm};
+
+pub mod airtime;
+pub mod clock;
+pub mod config;
+pub mod control;
+pub mod decisions;
+pub mod delivered;
+pub mod entropy;
+pub mod flags;
+pub mod group;
+pub mod handle;
+pub mod linktest;
 pub mod mesh_router;
 pub mod network_manager;
+pub mod payload;
 pub mod policy;
+pub mod provision;
+pub mod region;
+pub mod route;
+pub mod rx_windows;
+pub mod scan;
+pub mod seen_filter;
+pub mod status;
+pub mod store;
+pub mod telemetry;
+pub mod vectors;
+mod wire;
 
 /// Either this packet
 /// Is Data, and should get an ACK return
 /// A Data stream, meaning it wants to send multiple packets(u8 amount). In this case, Node B will
 /// continue to listen, until it has receieved (u8) amount of packages
 /// ACK should only be sent by a GW, because they will not retransmit
-#[derive(Serialize, Deserialize, Debug, PartialEq, defmt::Format, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PacketType {
     /// To send just a single packet
     Data,
@@ -22,25 +52,278 @@ pub enum PacketType {
     Ack,
     /// When GW boots up, it sends this out
     BootUp,
+    /// Between two neighbours testing their link, never forwarded. See `linktest`
+    LinkTest,
+    /// Management of a node, or its reply. Routed like Data, but handled by the router. See
+    /// `control`
+    Control,
+}
+
+/// Direction of a link, deciding whether the LoRa IQ signal is inverted.
+///
+/// Gateways invert IQ on downlinks, so nodes don't hear each others uplinks and gateways don't hear
+/// each others downlinks. Mesh traffic is never inverted, since every node has to hear every other
+/// node, gateway included. Both ends of a link have to agree, so derive the flag from here instead
+/// of setting it by hand.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkDirection {
+    /// Node to gateway, like a LoRaWAN uplink
+    Uplink,
+    /// Gateway to node, like a LoRaWAN downlink
+    Downlink,
+    /// Node to node, or gateway to node inside the mesh
+    Mesh,
+}
+
+impl LinkDirection {
+    /// Whether IQ is inverted on this link, for both the transmitter and the receiver
+    pub const fn iq_inverted(self) -> bool {
+        matches!(self, LinkDirection::Downlink)
+    }
+}
+
+/// How strong the last frame was heard
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkQuality {
+    pub rssi: Dbm<i16>,
+    pub snr: Db<i8>,
+}
+
+/// Receptions the radio started but which didn't end in a frame, to tell noisy sites apart and
+/// tune the channel activity detection thresholds there
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PreambleStats {
+    /// Times channel activity detection found a preamble
+    pub cad_detections: u32,
+    /// Detections which no frame followed, e.g. triggered by noise
+    pub cad_false_positives: u32,
+    /// Preambles received without a valid frame after them, on a header or CRC error
+    pub rx_aborts: u32,
 }
 
+/// Largest SIZE a network can use. A LoRa frame is at most 255 bytes, and the version, the packet
+/// header, a full route and the frame length take up to 25 of them.
+pub const MAX_SIZE: usize = 230;
+
+/// Longest frame on the air, what a LoRa radio sends at most. Enough for the buffer a frame is
+/// encoded into with `MHPacket::encode_frame_into`.
+pub const MAX_FRAME_LEN: usize = 255;
+
+/// Destination of a packet meant for every node. Every node delivers it and floods it on, once.
+pub const BROADCAST_ID: u8 = u8::MAX;
+
+/// Version of the on-air packet format, see `wire`. Every frame starts with it, and a frame of
+/// another version is rejected with `NetworkManagerError::WrongVersion`, so every node and
+/// gateway of a network has to run the same version. It isn't negotiated on the air.
+///
+/// - 1: destination, type, packet id, source, payload, hop count and `hop_to_gw`
+/// - 2: `network_id` in front
+/// - 3: `max_payload` after `source_id`
+/// - 4: `route` at the end
+/// - 5: `hop_to_gw` is only sent on packets to the gateway
+/// - 6: `flags` after `packet_type`
+/// - 7: the version in front of every frame
+///
+/// Frames before 7 start with their packet count instead, which is never 7 or more on the air,
+/// so they are rejected too.
+pub const WIRE_VERSION: u8 = 7;
+
 /// MHPacket defines the package sent around the network
-#[derive(Serialize, Deserialize, Debug, PartialEq, defmt::Format, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct MHPacket<const SIZE: usize> {
+    /// Network the packet belongs to, so co-located meshes don't route each other's packets
+    pub network_id: u8,
     /// Destination identifier
     // TODO: Perhaps bigger than u8?
     pub destination_id: u8,
     pub packet_type: PacketType,
+    /// Optional features the packet uses, see `flags` for which bit is which
+    pub flags: u8,
     pub packet_id: u16,
     pub source_id: u8,
+    /// SIZE of the sender, so a receiver built with a smaller SIZE can tell why a payload
+    /// doesn't fit
+    pub max_payload: u8,
     /// Your specificed data wanting to send
     // (DE)serialize is only available up to 32 bytes
     pub payload: Vec<u8, SIZE>,
     /// The amount of hops this package has been on
     // TODO: Implement logic for this
     pub hop_count: u8,
-    /// Amount of hops the current node has to GW
+    /// Amount of hops the current node has to GW. Only sent on packets to the gateway, others
+    /// are received with `u8::MAX`
     pub hop_to_gw: u8,
+    /// Relays the packet should take, with `hop_count` of them passed. Empty when it is flooded.
+    /// Packets to the gateway instead record the relays they passed, when relays record routes.
+    pub route: Route,
+}
+
+/// Identifies a packet in logs and errors, shown as `source_id:packet_id`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PacketRef {
+    pub source_id: u8,
+    pub packet_id: u16,
+}
+
+impl core::fmt::Display for PacketRef {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}:{}", self.source_id, self.packet_id)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for PacketRef {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=u8}:{=u16}", self.source_id, self.packet_id)
+    }
+}
+
+impl<const SIZE: usize> MHPacket<SIZE> {
+    /// Whether `flag`, one of the bits in `flags`, is set
+    pub fn has_flag(&self, flag: u8) -> bool {
+        flags::has(self.flags, flag)
+    }
+
+    /// Sets or clears `flag`, leaving the other bits, also unknown ones, as they are
+    pub fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    /// Flag bits this version doesn't know, which are relayed on untouched
+    pub fn unknown_flags(&self) -> u8 {
+        flags::unknown(self.flags)
+    }
+
+    pub fn packet_ref(&self) -> PacketRef {
+        PacketRef {
+            source_id: self.source_id,
+            packet_id: self.packet_id,
+        }
+    }
+
+    /// Encodes the packet alone into the front of `buf`, returning the part used, e.g. to store
+    /// it. Read it back with `decode_from`.
+    pub fn encode_into<'b>(&self, buf: &'b mut [u8]) -> Result<&'b [u8], postcard::Error> {
+        postcard::to_slice(self, buf).map(|used| &*used)
+    }
+
+    /// Decodes a packet made with `encode_into`
+    pub fn decode_from(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+
+    /// Encodes `packets` as one frame into the front of `buf`, e.g. the radio's TX buffer,
+    /// returning the part used. The frame starts with `WIRE_VERSION`. A buffer of
+    /// `MAX_FRAME_LEN` takes any frame which fits on the air. Read it back with `decode_frame`.
+    pub fn encode_frame_into<'b>(
+        packets: &[Self],
+        buf: &'b mut [u8],
+    ) -> Result<&'b [u8], postcard::Error> {
+        let (version, rest) = buf
+            .split_first_mut()
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+        *version = WIRE_VERSION;
+        let len = postcard::to_slice(packets, rest)?.len();
+        Ok(&buf[..1 + len])
+    }
+
+    /// Bytes `packets` take as one frame, counted without encoding them anywhere
+    pub fn frame_len(packets: &[Self]) -> usize {
+        // Counting can't fail
+        postcard::serialize_with_flavor(packets, postcard::ser_flavors::Size::default())
+            .map_or(usize::MAX, |len| 1 + len)
+    }
+
+    /// Decodes a frame as sent by `MHNode::transmit`. A payload larger than SIZE is a
+    /// `PayloadTooLarge` error, instead of failing somewhere in the middle of deserializing.
+    pub fn decode_frame<const LEN: usize>(
+        bytes: &[u8],
+    ) -> Result<Vec<Self, LEN>, NetworkManagerError> {
+        MHPacketView::decode_frame::<LEN>(bytes)?
+            .iter()
+            .map(MHPacketView::checked_packet)
+            .collect()
+    }
+}
+
+/// Borrowed view of an MHPacket, where the payload points into the received frame instead of
+/// being copied. Decoding a frame of LEN packets into views takes LEN * (9 + 4 pointers) bytes,
+/// independent of SIZE, which matters for gateways handling many frames.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MHPacketView<'a> {
+    pub network_id: u8,
+    pub destination_id: u8,
+    pub packet_type: PacketType,
+    pub flags: u8,
+    pub packet_id: u16,
+    pub source_id: u8,
+    pub max_payload: u8,
+    /// Payload, serialized the same way as the heapless Vec of MHPacket
+    pub payload: &'a [u8],
+    pub hop_count: u8,
+    pub hop_to_gw: u8,
+    /// Route, serialized the same way as the heapless Vec of MHPacket
+    pub route: &'a [u8],
+}
+
+impl<'a> MHPacketView<'a> {
+    /// Whether `flag`, one of the bits in `flags`, is set
+    pub fn has_flag(&self, flag: u8) -> bool {
+        flags::has(self.flags, flag)
+    }
+
+    /// Decodes a frame as sent by `MHNode::transmit`, without copying payloads. A frame of
+    /// another `WIRE_VERSION` is a `WrongVersion` error, instead of packets read from the wrong
+    /// fields.
+    pub fn decode_frame<const LEN: usize>(
+        bytes: &'a [u8],
+    ) -> Result<Vec<MHPacketView<'a>, LEN>, NetworkManagerError> {
+        let (&version, packets) = bytes
+            .split_first()
+            .ok_or(postcard::Error::DeserializeUnexpectedEnd)?;
+        if version != WIRE_VERSION {
+            return Err(NetworkManagerError::WrongVersion(version));
+        }
+        Ok(postcard::from_bytes(packets)?)
+    }
+
+    /// Like `to_packet`, but tells which packet was too large
+    pub fn checked_packet<const SIZE: usize>(&self) -> Result<MHPacket<SIZE>, NetworkManagerError> {
+        self.to_packet()
+            .ok_or(NetworkManagerError::PayloadTooLarge {
+                source_id: self.source_id,
+                packet_id: self.packet_id,
+                len: self.payload.len(),
+                max_payload: self.max_payload,
+            })
+    }
+
+    /// Copies the view into an owned packet, failing if the payload is larger than SIZE, or the
+    /// route longer than `MAX_ROUTE_LEN`
+    pub fn to_packet<const SIZE: usize>(&self) -> Option<MHPacket<SIZE>> {
+        Some(MHPacket {
+            network_id: self.network_id,
+            destination_id: self.destination_id,
+            packet_type: self.packet_type,
+            flags: self.flags,
+            packet_id: self.packet_id,
+            source_id: self.source_id,
+            max_payload: self.max_payload,
+            payload: Vec::from_slice(self.payload).ok()?,
+            hop_count: self.hop_count,
+            hop_to_gw: self.hop_to_gw,
+            route: Vec::from_slice(self.route).ok()?,
+        })
+    }
 }
 
 /// Any radio wanting to be a node, has to be able to transmit and receive
@@ -71,4 +354,58 @@ pub trait MHNode<const SIZE: usize, const LEN: usize> {
         rec_buf: &mut Self::ReceiveBuffer,
         with_timeout: bool,
     ) -> impl Future<Output = Result<Self::Connection, Self::Error>>;
+
+    /// Waits until every frame given to `transmit` is on the air. Radios which are done when
+    /// `transmit` returns can leave this out.
+    fn flush(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        async { Ok(()) }
+    }
+
+    /// How strong the frame last given to `receive` was heard. Radios which can't tell can leave
+    /// this out.
+    fn link_quality(&self) -> Option<LinkQuality> {
+        None
+    }
+
+    /// Switches to the frequency and data rate of `plan`, received from the gateway. Radios
+    /// without a configurable channel can leave this out.
+    fn apply_channel_plan(&mut self, _plan: &ChannelPlan) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Also receives on `spreading_factor` next to the channel plan's, for a backbone relay, or
+    /// only on the plan's again with None. Radios which can hear both at once, e.g. a
+    /// concentrator, keep it across `apply_channel_plan` and return true. The default returns
+    /// false, and the router takes turns listening on each.
+    fn listen_also_on(&mut self, _spreading_factor: Option<u8>) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Sends at the highest power the radio and region allow, for `MeshRouter::urgent_send`, or
+    /// at the usual power again with false. Radios without a configurable power can leave this
+    /// out.
+    fn set_max_power(&mut self, _max: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Sends at `power` from now on, e.g. as the gateway's adaptive data rate recommends, within
+    /// what the radio allows. `set_max_power` still overrides it. Radios without a configurable
+    /// power can leave this out.
+    fn set_tx_power(&mut self, _power: Dbm<i8>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Whether a preamble is on the air on the current channel, found with channel activity
+    /// detection in a few symbols rather than a whole listen. Some(false) has a scanning router
+    /// move on to the next channel at once. Radios without it can leave this out, and return
+    /// None, so every channel is listened on for its whole dwell.
+    fn channel_activity(&mut self) -> impl Future<Output = Result<Option<bool>, Self::Error>> {
+        async { Ok(None) }
+    }
+
+    /// How often a preamble led to nothing since the radio started. Radios which can't tell can
+    /// leave this out.
+    fn preamble_stats(&self) -> Option<PreambleStats> {
+        None
+    }
 }
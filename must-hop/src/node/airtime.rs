@@ -0,0 +1,67 @@
+/// How long a LoRa frame is on the air, from the formula in Semtech's SX127x datasheet, with an
+/// explicit header. The simulator uses it to find overlapping transmissions, and planning tools to
+/// find the load on a channel.
+use embassy_time::Duration;
+
+use super::config::ChannelPlan;
+
+/// The radio sends 4.25 symbols after the programmed preamble, here in quarter symbols
+const SYNC_QUARTER_SYMBOLS: u64 = 17;
+/// Symbol time from which the low data rate optimization is on, like the radio drivers set it
+const LDRO_SYMBOL_US: u64 = 16_000;
+
+/// The LoRa settings a frame is sent with, as far as they decide its airtime
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Modulation {
+    /// From 5 to 12
+    pub spreading_factor: u8,
+    /// 125, 250 or 500
+    pub bandwidth_khz: u16,
+    /// Denominator of the coding rate, from 5 to 8 for 4/5 to 4/8
+    pub coding_rate: u8,
+    pub preamble_symbols: u16,
+    pub crc: bool,
+}
+
+impl Modulation {
+    /// Coding rate 4/5 with an 8 symbol preamble and CRC, the usual LoRa defaults
+    pub const fn new(spreading_factor: u8, bandwidth_khz: u16) -> Self {
+        Self {
+            spreading_factor,
+            bandwidth_khz,
+            coding_rate: 5,
+            preamble_symbols: 8,
+            crc: true,
+        }
+    }
+
+    pub fn symbol_time(&self) -> Duration {
+        Duration::from_micros(self.symbols_us(1))
+    }
+
+    /// Time on air of a frame with `payload_len` bytes, e.g. a serialized MHPacket
+    pub fn airtime(&self, payload_len: usize) -> Duration {
+        let sf = i64::from(self.spreading_factor);
+        let low_data_rate = i64::from(self.symbols_us(1) >= LDRO_SYMBOL_US);
+        let bits = 8 * payload_len as i64 - 4 * sf + 28 + 16 * i64::from(self.crc);
+        let bits_per_block = (4 * (sf - 2 * low_data_rate)).max(1) as u64;
+        let blocks = (bits.max(0) as u64).div_ceil(bits_per_block);
+        let payload_symbols = 8 + blocks * u64::from(self.coding_rate);
+        let quarter_symbols =
+            4 * u64::from(self.preamble_symbols) + SYNC_QUARTER_SYMBOLS + 4 * payload_symbols;
+        Duration::from_micros(self.symbols_us(quarter_symbols) / 4)
+    }
+
+    /// Microseconds of `symbols` symbols
+    fn symbols_us(&self, symbols: u64) -> u64 {
+        symbols * (1u64 << self.spreading_factor.min(12)) * 1000
+            / u64::from(self.bandwidth_khz.max(1))
+    }
+}
+
+impl From<ChannelPlan> for Modulation {
+    fn from(plan: ChannelPlan) -> Self {
+        Self::new(plan.spreading_factor, plan.bandwidth_khz)
+    }
+}
@@ -0,0 +1,56 @@
+/// Where the NetworkManager gets the time from, so tests can run retransmission timeouts without
+/// waiting for them
+use core::cell::Cell;
+use embassy_time::{Duration, Instant};
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The embassy-time clock, used by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EmbassyClock;
+
+impl Clock for EmbassyClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which only moves when advanced. The manager gets a reference, and the test keeps the
+/// clock to advance it.
+#[derive(Debug)]
+pub struct TestClock {
+    now: Cell<Instant>,
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestClock {
+    /// A clock starting at zero
+    pub const fn new() -> Self {
+        Self {
+            now: Cell::new(Instant::from_ticks(0)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+impl<C: Clock> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
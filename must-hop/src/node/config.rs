@@ -0,0 +1,98 @@
+/// Radio configuration which the gateway hands out, and nodes keep across reboots
+use core::future::Future;
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Longest encoding of a ChannelPlan, with every varint at its maximum length
+const MAX_ENCODED_LEN: usize = 9;
+/// Longest encoding of a gateway id
+const MAX_ID_LEN: usize = 10;
+/// Encoding of the backbone spreading factor
+const BACKBONE_LEN: usize = 1;
+
+/// Frequency and default data rate of the mesh. The gateway advertises it in its bootup, so nodes
+/// don't need it compiled in, only something they can hear the bootup on.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelPlan {
+    /// Center frequency in Hz
+    pub frequency_hz: u32,
+    /// LoRa spreading factor, from 5 to 12
+    pub spreading_factor: u8,
+    /// LoRa bandwidth in kHz, 125, 250 or 500
+    pub bandwidth_khz: u16,
+}
+
+/// What a gateway advertises in its bootup. Each field is only there if the ones before it are.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub(crate) struct Advertised {
+    pub plan: Option<ChannelPlan>,
+    pub gateway_id: Option<u64>,
+    /// Spreading factor backbone relays carry uplinks on
+    pub backbone_sf: Option<u8>,
+}
+
+impl ChannelPlan {
+    /// The plan as a bootup payload, followed by the gateway's id if it has one, and then the
+    /// backbone spreading factor if there is one. Nodes which don't know about the later fields
+    /// stop reading before them.
+    pub(crate) fn encode<const SIZE: usize>(
+        &self,
+        gateway_id: Option<u64>,
+        backbone_sf: Option<u8>,
+    ) -> Result<Vec<u8, SIZE>, postcard::Error> {
+        let mut buf = [0u8; MAX_ENCODED_LEN + MAX_ID_LEN + BACKBONE_LEN];
+        let mut used = postcard::to_slice(self, &mut buf)?.len();
+        if let Some(id) = gateway_id {
+            used += postcard::to_slice(&id, &mut buf[used..])?.len();
+            if let Some(sf) = backbone_sf {
+                used += postcard::to_slice(&sf, &mut buf[used..])?.len();
+            }
+        }
+        Vec::from_slice(&buf[..used]).map_err(|_| postcard::Error::SerializeBufferFull)
+    }
+
+    /// What a bootup payload advertises. Gateways without a plan send nothing.
+    pub(crate) fn decode(payload: &[u8]) -> Advertised {
+        let Ok((plan, rest)) = postcard::take_from_bytes::<Self>(payload) else {
+            return Advertised::default();
+        };
+        let Ok((gateway_id, rest)) = postcard::take_from_bytes(rest) else {
+            return Advertised {
+                plan: Some(plan),
+                ..Advertised::default()
+            };
+        };
+        Advertised {
+            plan: Some(plan),
+            gateway_id: Some(gateway_id),
+            backbone_sf: postcard::from_bytes(rest).ok(),
+        }
+    }
+}
+
+/// What a node does in the mesh. Backbone relays are powered nodes which carry uplinks to the
+/// gateway on the faster spreading factor the gateway advertises, while listening on the channel
+/// plan's too for the edge nodes around them. Edge nodes only use the channel plan.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RelayRole {
+    #[default]
+    Edge,
+    Backbone,
+}
+
+/// Somewhere to keep the configuration across reboots, e.g. a flash page
+pub trait ConfigStore {
+    type Error;
+
+    /// The last saved channel plan, if any was saved
+    fn load_channel_plan(
+        &mut self,
+    ) -> impl Future<Output = Result<Option<ChannelPlan>, Self::Error>>;
+
+    fn save_channel_plan(
+        &mut self,
+        plan: &ChannelPlan,
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+}
@@ -0,0 +1,183 @@
+/// Management of nodes over the mesh. A `Control` packet is routed like Data, and the router of
+/// the node it is for handles it through its `ControlHooks` instead of handing it to the
+/// application. Replies go back the same way and are handed to the application which asked.
+use core::fmt;
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    units::Dbm,
+    verbosity::{Level, Subsystem},
+};
+
+/// Longest encoding of a Control message, with every varint at its maximum length
+const MAX_ENCODED_LEN: usize = 11;
+/// Spreading factor of a node in safe mode, the slowest but the one which reaches furthest
+pub const SAFE_MODE_SPREADING_FACTOR: u8 = 12;
+
+/// Payload of a `PacketType::Control` packet. Some messages are followed by data in the rest of
+/// the payload.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Control {
+    /// Asks for the node's logs from `offset` on, oldest first
+    DumpLogs { offset: u32 },
+    /// Logs from `offset` on follow, of the `total` bytes the node has. Ask again from where
+    /// these end for the rest.
+    Logs { offset: u32, total: u32 },
+    /// Restarts the node
+    Reboot,
+    /// Clears what the node persisted, e.g. its channel plan or stored packets
+    FactoryReset,
+    /// Switches the node to `SAFE_MODE_SPREADING_FACTOR` and conservative settings, until the
+    /// next channel plan it applies
+    EnterSafeMode,
+    /// Whether the node carries out the Reboot, FactoryReset, EnterSafeMode, LinkAdr or
+    /// SetLogLevel it got
+    Reply { accepted: bool },
+    /// The groups a node is in, a bit each, see `group::bit`. Sent to the gateway when the node
+    /// joins, and whenever its subscriptions change.
+    Subscribe { groups: u32 },
+    /// Switches the node to `spreading_factor` on the channel plan and sends at `tx_power`, as
+    /// the gateway's adaptive data rate recommends, until the next channel plan it applies
+    LinkAdr {
+        spreading_factor: u8,
+        tx_power: Dbm<i8>,
+    },
+    /// Has `subsystem` of the node log up to `level` from now on, see `verbosity`. Confirmed and
+    /// replied to like a Reboot.
+    SetLogLevel { subsystem: Subsystem, level: Level },
+    /// Holds back the node's own payloads for `secs` seconds, e.g. during a gateway's nightly
+    /// maintenance or the hours a shared-spectrum agreement gives to others, 0 ends it early.
+    /// Usually broadcast by the gateway, and carried out without a reply if the hooks confirm it,
+    /// for a day at most.
+    Quiet { secs: u32 },
+}
+
+impl Control {
+    /// The message followed by as much of `data` as fits, and how much of `data` that was
+    pub(crate) fn encode<const SIZE: usize>(
+        &self,
+        data: &[u8],
+    ) -> Result<(Vec<u8, SIZE>, usize), postcard::Error> {
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        let used = postcard::to_slice(self, &mut buf)?;
+        let mut payload: Vec<u8, SIZE> =
+            Vec::from_slice(used).map_err(|_| postcard::Error::SerializeBufferFull)?;
+        let fits = data.len().min(SIZE - payload.len());
+        // Can't fail, it was just checked
+        let _ = payload.extend_from_slice(&data[..fits]);
+        Ok((payload, fits))
+    }
+
+    /// The message and the data after it
+    pub fn decode(payload: &[u8]) -> Option<(Self, &[u8])> {
+        postcard::take_from_bytes(payload).ok()
+    }
+}
+
+/// How the router carries out Control messages for this node. Without hooks, a node has no logs
+/// to send and refuses the rest.
+pub trait ControlHooks {
+    /// Copies logs from `offset` on into `out`, oldest first. Returns how many bytes were copied
+    /// and how many there are in total.
+    fn read_logs(&mut self, _offset: u32, _out: &mut [u8]) -> (usize, u32) {
+        (0, 0)
+    }
+
+    /// Whether to carry out a Reboot, FactoryReset, EnterSafeMode, LinkAdr, SetLogLevel or Quiet
+    /// from `source`, e.g. only from the gateway. None are by default. Logs are always sent.
+    fn confirm(&mut self, _source: u8, _request: Control) -> bool {
+        false
+    }
+
+    /// Restarts the device, called once the reply went out. Only returns if it can't.
+    fn reboot(&mut self) {}
+
+    /// Clears what the application persisted, called once the reply went out. Usually followed
+    /// by a reboot.
+    fn factory_reset(&mut self) {}
+
+    /// Lowers the TX power, or anything else which keeps the node reachable, after the router
+    /// switched to `SAFE_MODE_SPREADING_FACTOR`
+    fn enter_safe_mode(&mut self) {}
+}
+
+/// Ignores every Control message, used by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoControl;
+
+impl ControlHooks for NoControl {}
+
+/// The last `N` bytes logged, for the gateway to fetch with `DumpLogs` after something went wrong
+/// in the field. Takes plain text through `fmt::Write`, or encoded frames, e.g. defmt's, through
+/// `write`. The oldest bytes are overwritten when it is full.
+#[derive(Debug, Clone)]
+pub struct LogBuffer<const N: usize> {
+    buf: [u8; N],
+    /// Where the next byte goes
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Default for LogBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LogBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.buf[self.head] = byte;
+            self.head = (self.head + 1) % N;
+            self.len = (self.len + 1).min(N);
+        }
+    }
+
+    /// Bytes kept, at most N
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Copies the kept bytes from `offset` on into `out`, oldest first, returning how many
+    pub fn read(&self, offset: usize, out: &mut [u8]) -> usize {
+        let count = self.len.saturating_sub(offset).min(out.len());
+        let oldest = (self.head + N - self.len) % N;
+        for (i, byte) in out[..count].iter_mut().enumerate() {
+            *byte = self.buf[(oldest + offset + i) % N];
+        }
+        count
+    }
+}
+
+impl<const N: usize> fmt::Write for LogBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<const N: usize> ControlHooks for LogBuffer<N> {
+    fn read_logs(&mut self, offset: u32, out: &mut [u8]) -> (usize, u32) {
+        (self.read(offset as usize, out), self.len as u32)
+    }
+}
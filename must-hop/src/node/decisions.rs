@@ -0,0 +1,120 @@
+/// Record of what the NetworkManager did with each packet it received, so misrouting in the field
+/// can be reconstructed afterwards. Only filled with the `trace-decisions` feature.
+use super::{MHPacket, PacketType};
+use embassy_time::Instant;
+use heapless::Vec;
+use postcard::{take_from_bytes, to_slice};
+use serde::{Deserialize, Serialize};
+
+/// Amount of decisions kept, the oldest are overwritten first
+pub const DECISION_LOG_LEN: usize = 32;
+
+/// The branch taken for a received packet
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Decision {
+    /// Seen before, and ACK'ed again
+    Duplicate,
+    /// Sent on towards its destination
+    Forward,
+    /// Confirmed one of our pending packets
+    Ack,
+    /// Not for us and not on our path, or a duplicate ACK
+    Drop,
+    /// Handed to the application
+    Deliver,
+    /// Bootup from a shorter path to the gateway, which is sent on
+    Bootup,
+}
+
+/// Header of a received packet, and what was done with it
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DecisionRecord {
+    /// Milliseconds since boot, wrapping
+    pub at_ms: u32,
+    pub source_id: u8,
+    pub destination_id: u8,
+    pub packet_id: u16,
+    pub packet_type: PacketType,
+    pub hop_count: u8,
+    pub hop_to_gw: u8,
+    pub decision: Decision,
+}
+
+impl DecisionRecord {
+    pub fn new<const SIZE: usize>(pkt: &MHPacket<SIZE>, decision: Decision, at: Instant) -> Self {
+        Self {
+            at_ms: at.as_millis() as u32,
+            source_id: pkt.source_id,
+            destination_id: pkt.destination_id,
+            packet_id: pkt.packet_id,
+            packet_type: pkt.packet_type,
+            hop_count: pkt.hop_count,
+            hop_to_gw: pkt.hop_to_gw,
+            decision,
+        }
+    }
+
+    /// Decodes the records of a payload made by `DecisionLog::to_payload`, newest first
+    pub fn decode_all(mut bytes: &[u8]) -> impl Iterator<Item = DecisionRecord> + '_ {
+        core::iter::from_fn(move || {
+            let (record, rest) = take_from_bytes::<DecisionRecord>(bytes).ok()?;
+            bytes = rest;
+            Some(record)
+        })
+    }
+}
+
+/// Ring buffer of the latest decisions
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DecisionLog<const N: usize> {
+    buffer: [Option<DecisionRecord>; N],
+    cursor: usize,
+}
+
+impl<const N: usize> DecisionLog<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [None; N],
+            cursor: 0,
+        }
+    }
+
+    pub fn push(&mut self, record: DecisionRecord) {
+        self.buffer[self.cursor] = Some(record);
+        self.cursor = (self.cursor + 1) % N;
+    }
+
+    /// Records from oldest to newest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &DecisionRecord> {
+        let (newer, older) = self.buffer.split_at(self.cursor);
+        older.iter().chain(newer.iter()).flatten()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Encodes as many of the newest records as fit into a payload, so the log can be sent over
+    /// the mesh
+    pub fn to_payload<const SIZE: usize>(&self) -> Vec<u8, SIZE> {
+        let mut buf = [0u8; SIZE];
+        let mut used = 0;
+        for record in self.iter().rev() {
+            match to_slice(record, &mut buf[used..]) {
+                Ok(written) => used += written.len(),
+                Err(_) => break,
+            }
+        }
+        // Can't fail, used is at most SIZE
+        Vec::from_slice(&buf[..used]).unwrap_or_default()
+    }
+}
+
+impl<const N: usize> Default for DecisionLog<N> {
+    fn default() -> Self {
+        DecisionLog::new()
+    }
+}
@@ -0,0 +1,74 @@
+/// What `MeshRouter::receive` hands to the application: the packet, with what the router knows
+/// about how it got here, so applications and gateway backends don't derive it again.
+use embassy_time::Instant;
+
+use super::{LinkQuality, MHPacket, network_manager::is_gw_bound};
+
+/// A packet for this node, or one the gateway takes in, with how it arrived
+#[derive(Debug, PartialEq, Clone)]
+pub struct Delivered<const SIZE: usize> {
+    pub packet: MHPacket<SIZE>,
+    /// The relay we heard the packet from, None when it came straight from its source or the
+    /// packet doesn't tell, see `hops`
+    pub relayed_by: Option<u8>,
+    /// Hops the packet took, 1 when it came straight from its source. Only known from its route,
+    /// so for source routed packets and, when relays record routes, packets to the gateway.
+    pub hops: Option<u8>,
+    /// How strong the frame was heard, on radios which can tell
+    pub link: Option<LinkQuality>,
+    /// When the frame was handled
+    pub received_at: Instant,
+}
+
+impl<const SIZE: usize> Delivered<SIZE> {
+    /// `packet` from a frame heard with `link` at `received_at`. `routes_recorded` tells whether
+    /// relays add themselves to the route of packets to the gateway, so an empty one came straight
+    /// from its source.
+    pub(crate) fn new(
+        packet: MHPacket<SIZE>,
+        link: Option<LinkQuality>,
+        received_at: Instant,
+        routes_recorded: bool,
+    ) -> Self {
+        let route = &packet.route;
+        let (relayed_by, hops) = if is_gw_bound(&packet) {
+            match route.last() {
+                // A full route lost the relays after it
+                Some(_) if route.is_full() => (None, None),
+                Some(&relay) => (Some(relay), Some(route.len() as u8 + 1)),
+                None if routes_recorded => (None, Some(1)),
+                None => (None, None),
+            }
+        } else if !route.is_empty() {
+            // `hop_count` relays of the route passed it on
+            let passed = packet.hop_count as usize;
+            let relay = passed.checked_sub(1).and_then(|i| route.get(i)).copied();
+            (relay, Some(packet.hop_count.saturating_add(1)))
+        } else {
+            (None, None)
+        };
+        Self {
+            packet,
+            relayed_by,
+            hops,
+            link,
+            received_at,
+        }
+    }
+}
+
+// Instant is only defmt::Format with embassy-time's defmt feature, so it is shown in ticks
+#[cfg(feature = "defmt")]
+impl<const SIZE: usize> defmt::Format for Delivered<SIZE> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Delivered {{ packet: {}, relayed_by: {}, hops: {}, link: {}, received_at: {=u64} }}",
+            self.packet,
+            self.relayed_by,
+            self.hops,
+            self.link,
+            self.received_at.as_ticks()
+        )
+    }
+}
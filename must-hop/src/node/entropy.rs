@@ -0,0 +1,71 @@
+/// Where randomness comes from, for the packet id a node starts at and the jitter on its retries,
+/// so nodes which booted or collided together don't keep doing everything at the same time. Each
+/// platform has its own RNG: wrap it with `from_fn`, e.g. `from_fn(|| rng.next_u32())` for the
+/// STM32 RNG of embassy-stm32 or `from_fn(|| rng.random())` for the one of esp-hal. Without an
+/// RNG, or in tasks which can't be generic, seed an `Xorshift` from whatever varies between boots.
+use embassy_time::Duration;
+
+pub trait EntropySource {
+    fn next_u32(&mut self) -> u32;
+
+    /// A random duration from zero to `max`
+    fn up_to(&mut self, max: Duration) -> Duration {
+        Duration::from_micros(u64::from(self.next_u32()) % (max.as_micros() + 1))
+    }
+}
+
+impl<E: EntropySource + ?Sized> EntropySource for &mut E {
+    fn next_u32(&mut self) -> u32 {
+        (**self).next_u32()
+    }
+}
+
+/// Xorshift, enough to spread out delays, and reproducible from its seed
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Xorshift(u32);
+
+impl Xorshift {
+    pub fn new(seed: u32) -> Self {
+        Self(seed.max(1))
+    }
+}
+
+impl EntropySource for Xorshift {
+    fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+/// Entropy from a closure, see `from_fn`
+#[derive(Debug, Clone)]
+pub struct FnEntropy<F>(F);
+
+/// Takes randomness from `f`, e.g. reading a hardware RNG
+pub fn from_fn<F: FnMut() -> u32>(f: F) -> FnEntropy<F> {
+    FnEntropy(f)
+}
+
+impl<F: FnMut() -> u32> EntropySource for FnEntropy<F> {
+    fn next_u32(&mut self) -> u32 {
+        (self.0)()
+    }
+}
+
+/// The randomness std seeds its hash maps with, different every run
+#[cfg(feature = "in_std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdEntropy;
+
+#[cfg(feature = "in_std")]
+impl EntropySource for StdEntropy {
+    fn next_u32(&mut self) -> u32 {
+        use core::hash::{BuildHasher, Hasher};
+        // Every RandomState is keyed anew
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u8(0);
+        hasher.finish() as u32
+    }
+}
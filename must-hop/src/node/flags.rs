@@ -0,0 +1,35 @@
+/// Bits of `MHPacket::flags`, one per optional feature a packet can use. Every bit is allocated
+/// here, so features added later don't pick the same one:
+///
+/// | bit | flag           | meaning                                                       |
+/// |-----|----------------|---------------------------------------------------------------|
+/// | 0   | `PRIORITY`     | sent and relayed before other queued packets                  |
+/// | 1   | `ACK_REQUIRED` | the destination has to ACK it, whatever the `AckMode`         |
+/// | 2   | `COMPRESSED`   | the payload is compressed, see `compress`                     |
+/// | 3   | `ENCRYPTED`    | the payload is encrypted with the network key                 |
+/// | 4   | `PIGGYBACK`    | the payload carries an ACK or status after the application's  |
+/// | 5   | `RX_WINDOWS`   | the sender only listens in the RX windows after it            |
+/// | 6-7 | reserved       | for a later wire version, sent as 0                           |
+///
+/// A node which doesn't know a bit leaves it alone: it delivers and relays the packet as usual,
+/// and relays it with the bit still set, so newer nodes behind it still see it. Only the flags
+/// set by the sender mean something, nothing in the mesh sets or clears them on the way.
+pub const PRIORITY: u8 = 1 << 0;
+pub const ACK_REQUIRED: u8 = 1 << 1;
+pub const COMPRESSED: u8 = 1 << 2;
+pub const ENCRYPTED: u8 = 1 << 3;
+pub const PIGGYBACK: u8 = 1 << 4;
+pub const RX_WINDOWS: u8 = 1 << 5;
+
+/// Every allocated bit, the others are unknown to this version
+pub const KNOWN: u8 = PRIORITY | ACK_REQUIRED | COMPRESSED | ENCRYPTED | PIGGYBACK | RX_WINDOWS;
+
+/// Whether every bit of `flag` is set in `flags`
+pub const fn has(flags: u8, flag: u8) -> bool {
+    flags & flag == flag
+}
+
+/// The bits of `flags` this version doesn't know, set by a newer sender
+pub const fn unknown(flags: u8) -> u8 {
+    flags & !KNOWN
+}
@@ -0,0 +1,133 @@
+/// Groups of nodes one packet reaches, e.g. every temperature sensor, so a single downlink
+/// commands a part of the fleet. Groups have addresses at the top of the id space, from
+/// `FIRST_GROUP` up to below `BROADCAST_ID`, which nodes don't take as their own id. A packet to a
+/// group is flooded like a broadcast, once and never ACKed, but only the nodes subscribed to the
+/// group deliver it. Nodes subscribe with `MeshRouter::subscribe`, and tell the gateway with a
+/// `Control::Subscribe` once they joined, so its `GroupRegistry` knows who is in which group.
+use core::fmt;
+use heapless::{String, Vec};
+
+use super::{BROADCAST_ID, MHPacket, PacketType, control::Control};
+
+/// Address of the first group
+pub const FIRST_GROUP: u8 = 0xE0;
+/// Groups a network can have, one bit each in a `Control::Subscribe`
+pub const GROUP_COUNT: usize = (BROADCAST_ID - FIRST_GROUP) as usize;
+/// Longest name a group is registered under
+pub const MAX_GROUP_NAME: usize = 24;
+
+/// Whether `id` is the address of a group rather than of a node
+pub const fn is_group(id: u8) -> bool {
+    id >= FIRST_GROUP && id != BROADCAST_ID
+}
+
+/// The bit of the group at `address` in a set of groups, as subscriptions are kept and sent
+pub const fn bit(address: u8) -> u32 {
+    1 << (address - FIRST_GROUP)
+}
+
+/// The addresses of the groups in `groups`
+pub fn addresses(groups: u32) -> impl Iterator<Item = u8> {
+    (FIRST_GROUP..BROADCAST_ID).filter(move |&address| groups & bit(address) != 0)
+}
+
+/// The groups a node subscribed to, if `pkt` is its `Control::Subscribe`
+pub fn subscription<const SIZE: usize>(pkt: &MHPacket<SIZE>) -> Option<u32> {
+    if pkt.packet_type != PacketType::Control {
+        return None;
+    }
+    match Control::decode(&pkt.payload) {
+        Some((Control::Subscribe { groups }, _)) => Some(groups),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GroupError {
+    /// Every group address is taken, or the registry tracks as many nodes as it can
+    Full,
+    NameTooLong,
+}
+
+impl fmt::Display for GroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupError::Full => write!(f, "no room for another group or node"),
+            GroupError::NameTooLong => {
+                write!(f, "group names are at most {} bytes", MAX_GROUP_NAME)
+            }
+        }
+    }
+}
+
+impl core::error::Error for GroupError {}
+
+/// On the gateway: the names of the groups, and the subscriptions of up to N nodes. Names get
+/// addresses in the order they are registered, the first `FIRST_GROUP`, so nodes built with the
+/// same list subscribe to the right ones.
+#[derive(Debug, Default, Clone)]
+pub struct GroupRegistry<const N: usize> {
+    names: Vec<String<MAX_GROUP_NAME>, GROUP_COUNT>,
+    /// Nodes and the groups they subscribed to
+    subscriptions: Vec<(u8, u32), N>,
+}
+
+impl<const N: usize> GroupRegistry<N> {
+    pub const fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            subscriptions: Vec::new(),
+        }
+    }
+
+    /// The address of the group `name`, registering it first if it is new
+    pub fn register(&mut self, name: &str) -> Result<u8, GroupError> {
+        if let Some(address) = self.address(name) {
+            return Ok(address);
+        }
+        let name = String::try_from(name).map_err(|_| GroupError::NameTooLong)?;
+        self.names.push(name).map_err(|_| GroupError::Full)?;
+        Ok(FIRST_GROUP + (self.names.len() - 1) as u8)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u8> {
+        self.names
+            .iter()
+            .position(|registered| registered == name)
+            .map(|index| FIRST_GROUP + index as u8)
+    }
+
+    pub fn name(&self, address: u8) -> Option<&str> {
+        let index = address.checked_sub(FIRST_GROUP)?;
+        self.names.get(index as usize).map(String::as_str)
+    }
+
+    /// Takes `groups` as every group `node` is in now, e.g. from its `Control::Subscribe`. No
+    /// groups forgets the node.
+    pub fn subscribe(&mut self, node: u8, groups: u32) -> Result<(), GroupError> {
+        self.subscriptions.retain(|(known, _)| *known != node);
+        if groups == 0 {
+            return Ok(());
+        }
+        self.subscriptions
+            .push((node, groups))
+            .map_err(|_| GroupError::Full)
+    }
+
+    /// The groups `node` subscribed to
+    pub fn groups_of(&self, node: u8) -> u32 {
+        self.subscriptions
+            .iter()
+            .find(|(known, _)| *known == node)
+            .map_or(0, |(_, groups)| *groups)
+    }
+
+    /// The nodes subscribed to the group at `address`
+    pub fn members(&self, address: u8) -> impl Iterator<Item = u8> + '_ {
+        self.subscriptions
+            .iter()
+            .filter(move |(_, groups)| is_group(address) && groups & bit(address) != 0)
+            .map(|(node, _)| *node)
+    }
+}
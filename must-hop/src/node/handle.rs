@@ -0,0 +1,124 @@
+/// Lets several tasks use one MeshRouter. The router lives in the radio task, running
+/// `MeshRouter::run`, and every other task gets a `MeshHandle` to send through it and to hear what
+/// it received.
+use embassy_sync::{
+    blocking_mutex::raw::RawMutex,
+    channel::{Channel, Sender},
+    pubsub::{self, PubSubChannel, Subscriber},
+};
+use heapless::Vec;
+
+use super::{delivered::Delivered, status::MeshStatus};
+
+/// What a `MeshHandle` asks the router to do
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MeshCommand<const SIZE: usize> {
+    Send {
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+    },
+    Broadcast {
+        payload: Vec<u8, SIZE>,
+    },
+    /// Publish a `MeshEvent::Status`
+    Status,
+}
+
+/// What the router tells subscribers about
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MeshEvent<const SIZE: usize> {
+    /// A packet for this node, or a broadcast, with how it arrived
+    Received(Delivered<SIZE>),
+    /// A payload couldn't be sent, either because the radio failed or too many packets are
+    /// waiting for an ACK
+    SendFailed { destination: u8 },
+    /// The radio failed while receiving, and the packets are lost
+    ReceiveFailed,
+    /// Answers `MeshHandle::request_status`
+    Status(MeshStatus),
+}
+
+/// The channels between a router and its handles. Put it in a `static`, so handles can be given
+/// to tasks. Holds CAP commands and CAP events, for at most SUBS subscribers.
+pub struct MeshChannels<M: RawMutex, const SIZE: usize, const CAP: usize, const SUBS: usize> {
+    pub(crate) commands: Channel<M, MeshCommand<SIZE>, CAP>,
+    pub(crate) events: PubSubChannel<M, MeshEvent<SIZE>, CAP, SUBS, 1>,
+}
+
+impl<M: RawMutex, const SIZE: usize, const CAP: usize, const SUBS: usize> Default
+    for MeshChannels<M, SIZE, CAP, SUBS>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: RawMutex, const SIZE: usize, const CAP: usize, const SUBS: usize>
+    MeshChannels<M, SIZE, CAP, SUBS>
+{
+    pub const fn new() -> Self {
+        Self {
+            commands: Channel::new(),
+            events: PubSubChannel::new(),
+        }
+    }
+
+    pub fn handle(&self) -> MeshHandle<'_, M, SIZE, CAP, SUBS> {
+        MeshHandle {
+            commands: self.commands.sender(),
+            events: &self.events,
+        }
+    }
+}
+
+/// Cloneable access to a router running in another task
+pub struct MeshHandle<'a, M: RawMutex, const SIZE: usize, const CAP: usize, const SUBS: usize> {
+    commands: Sender<'a, M, MeshCommand<SIZE>, CAP>,
+    events: &'a PubSubChannel<M, MeshEvent<SIZE>, CAP, SUBS, 1>,
+}
+
+impl<M: RawMutex, const SIZE: usize, const CAP: usize, const SUBS: usize> Clone
+    for MeshHandle<'_, M, SIZE, CAP, SUBS>
+{
+    fn clone(&self) -> Self {
+        Self {
+            commands: self.commands,
+            events: self.events,
+        }
+    }
+}
+
+impl<'a, M: RawMutex, const SIZE: usize, const CAP: usize, const SUBS: usize>
+    MeshHandle<'a, M, SIZE, CAP, SUBS>
+{
+    /// Queues the payload for `destination`, waiting if the router has CAP commands queued
+    /// already. A failed send is reported as a `MeshEvent::SendFailed`.
+    pub async fn send(&self, payload: Vec<u8, SIZE>, destination: u8) {
+        self.commands
+            .send(MeshCommand::Send {
+                payload,
+                destination,
+            })
+            .await
+    }
+
+    /// Queues the payload for every node in the mesh
+    pub async fn broadcast(&self, payload: Vec<u8, SIZE>) {
+        self.commands.send(MeshCommand::Broadcast { payload }).await
+    }
+
+    /// Asks the router for its `MeshStatus`, which subscribers get as a `MeshEvent::Status`
+    pub async fn request_status(&self) {
+        self.commands.send(MeshCommand::Status).await
+    }
+
+    /// Subscribes to the router's events. A subscriber which falls more than CAP events behind
+    /// loses the oldest ones. Errors if there already are SUBS subscribers.
+    pub fn subscribe_events(
+        &self,
+    ) -> Result<Subscriber<'a, M, MeshEvent<SIZE>, CAP, SUBS, 1>, pubsub::Error> {
+        self.events.subscriber()
+    }
+}
@@ -0,0 +1,106 @@
+/// Link test between two neighbours, e.g. a gateway and a node, to see how well each spreading
+/// factor gets through before placing the node or picking ADR thresholds. The initiator sends a
+/// `Start`, then probes, and the responder echoes how it heard every probe.
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use super::LinkQuality;
+use crate::units::{Db, Dbm};
+
+/// Longest encoding of a LinkTest message, with every varint at its maximum length
+const MAX_ENCODED_LEN: usize = 10;
+
+/// Payload of a `PacketType::LinkTest` packet
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkTest {
+    /// Switch to `spreading_factor` for `count` probes, sent `interval_ms` apart
+    Start {
+        spreading_factor: u8,
+        count: u16,
+        interval_ms: u32,
+    },
+    Probe {
+        seq: u16,
+    },
+    /// How the responder heard probe `seq`, if its radio can tell
+    Echo {
+        seq: u16,
+        quality: Option<LinkQuality>,
+    },
+}
+
+impl LinkTest {
+    pub(crate) fn encode<const SIZE: usize>(&self) -> Result<Vec<u8, SIZE>, postcard::Error> {
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        let used = postcard::to_slice(self, &mut buf)?;
+        Vec::from_slice(used).map_err(|_| postcard::Error::SerializeBufferFull)
+    }
+
+    pub(crate) fn decode(payload: &[u8]) -> Option<Self> {
+        postcard::from_bytes(payload).ok()
+    }
+}
+
+/// Running mean of the link quality of the packets heard
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStats {
+    pub heard: u16,
+    rssi_sum: i32,
+    snr_sum: i32,
+}
+
+impl LinkStats {
+    pub fn add(&mut self, quality: LinkQuality) {
+        self.heard += 1;
+        self.rssi_sum += i32::from(quality.rssi.0);
+        self.snr_sum += i32::from(quality.snr.0);
+    }
+
+    /// Mean RSSI and SNR, or None if no quality was added
+    pub fn mean(&self) -> Option<LinkQuality> {
+        if self.heard == 0 {
+            return None;
+        }
+        Some(LinkQuality {
+            rssi: Dbm((self.rssi_sum / self.heard as i32) as i16),
+            snr: Db((self.snr_sum / self.heard as i32) as i8),
+        })
+    }
+}
+
+/// Result of testing one spreading factor
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SfResult {
+    pub spreading_factor: u8,
+    /// Probes sent
+    pub sent: u16,
+    /// Probes which came back as an echo
+    pub echoed: u16,
+    /// Probes as heard by the responder
+    pub downlink: LinkStats,
+    /// Echoes as heard by us
+    pub uplink: LinkStats,
+}
+
+impl SfResult {
+    pub(crate) fn new(spreading_factor: u8) -> Self {
+        Self {
+            spreading_factor,
+            sent: 0,
+            echoed: 0,
+            downlink: LinkStats::default(),
+            uplink: LinkStats::default(),
+        }
+    }
+
+    /// Share of the probes which made it there and back, from 0 to 1
+    pub fn delivery_ratio(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        self.echoed as f32 / self.sent as f32
+    }
+}
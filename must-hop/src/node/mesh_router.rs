@@ -1,22 +1,72 @@
+use crate::verbosity::{self, trace};
 use core::fmt;
-use core::marker::PhantomData;
-#[cfg(not(feature = "in_std"))]
-use defmt::trace;
-#[cfg(feature = "in_std")]
-use log::trace;
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Instant, Timer, with_deadline, with_timeout};
 
-use crate::node::policy::{GatewayPolicy, NodePolicy, RoutingPolicy};
+use crate::node::policy::{GatewayPolicy, NodePolicy, RoutingPolicy, RuntimePolicy};
 
 use super::{
-    MHNode, MHPacket,
-    network_manager::{NetworkManager, NetworkManagerError},
+    BROADCAST_ID, LinkQuality, MHNode, MHPacket, PacketRef, PacketType,
+    airtime::Modulation,
+    clock::{Clock, EmbassyClock},
+    config::{ChannelPlan, ConfigStore, RelayRole},
+    control::{Control, ControlHooks, NoControl, SAFE_MODE_SPREADING_FACTOR},
+    delivered::Delivered,
+    flags,
+    handle::{MeshChannels, MeshCommand, MeshEvent},
+    linktest::{LinkTest, SfResult},
+    network_manager::{MAX_NETWORKS, ManagerState, NetworkManager, NetworkManagerError},
+    payload::{self, Typed},
+    provision::Provisioning,
+    rx_windows::RxWindows,
+    scan::ChannelScan,
+    status::MeshStatus,
+    store::{PacketStore, StoredPayload},
+    telemetry::{DropReason, NoTelemetry, Telemetry},
 };
 use heapless::Vec;
+use serde::de::DeserializeOwned;
 
-#[derive(Debug, defmt::Format)]
+/// Errors of the router, by the layer they came from. Displayed, or formatted with defmt, as one
+/// line with the error code, the layer and the packet, e.g.
+/// `E0x201 node: transmitting 3:17 failed: ...`
+#[derive(Debug)]
 pub enum MeshRouterError<E> {
     Manager(NetworkManagerError),
     Node(E),
+    /// The node failed to transmit the frame starting with `packet`
+    Transmit {
+        packet: PacketRef,
+        error: E,
+    },
+    /// Loading from or saving to a `ConfigStore` failed, or a provisioning was for another node
+    Config,
+    /// A `PacketStore` failed, e.g. it was full
+    Store,
+}
+
+impl<E> MeshRouterError<E> {
+    /// The layer in the high byte, 1 for the manager, 2 for the node, 3 for the configuration and
+    /// 4 for the packet store, and the error within the layer in the low byte
+    pub const fn code(&self) -> u16 {
+        match self {
+            MeshRouterError::Manager(e) => 0x100 | e.code() as u16,
+            MeshRouterError::Node(_) => 0x200,
+            MeshRouterError::Transmit { .. } => 0x201,
+            MeshRouterError::Config => 0x300,
+            MeshRouterError::Store => 0x400,
+        }
+    }
+
+    /// The packet the error is about, if it is about one
+    pub fn packet(&self) -> Option<PacketRef> {
+        match self {
+            MeshRouterError::Manager(e) => e.packet(),
+            MeshRouterError::Transmit { packet, .. } => Some(*packet),
+            MeshRouterError::Node(_) | MeshRouterError::Config | MeshRouterError::Store => None,
+        }
+    }
 }
 
 impl<E> From<NetworkManagerError> for MeshRouterError<E> {
@@ -24,17 +74,133 @@ impl<E> From<NetworkManagerError> for MeshRouterError<E> {
         MeshRouterError::Manager(err)
     }
 }
+
 impl<E: fmt::Debug> fmt::Display for MeshRouterError<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // A simple implementation just delegates to the Debug output,
-        // but you can customize this to be more human-readable.
-        write!(f, "Mesh Router Error: {:?}", self)
+        write!(f, "E{:#x} ", self.code())?;
+        match self {
+            MeshRouterError::Manager(e) => write!(f, "manager: {}", e),
+            MeshRouterError::Node(e) => write!(f, "node: {:?}", e),
+            MeshRouterError::Transmit { packet, error } => {
+                write!(f, "node: transmitting {} failed: {:?}", packet, error)
+            }
+            MeshRouterError::Config => write!(f, "config: store failed"),
+            MeshRouterError::Store => write!(f, "store: packet store failed"),
+        }
     }
 }
 
-// 3. Implement the Error trait.
-// We bound E to also implement Error so the inner error is valid too.
-impl<E: fmt::Debug + core::error::Error> core::error::Error for MeshRouterError<E> {}
+#[cfg(feature = "defmt")]
+impl<E: defmt::Format> defmt::Format for MeshRouterError<E> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "E{=u16:#x} ", self.code());
+        match self {
+            MeshRouterError::Manager(e) => defmt::write!(f, "manager: {}", e),
+            MeshRouterError::Node(e) => defmt::write!(f, "node: {}", e),
+            MeshRouterError::Transmit { packet, error } => {
+                defmt::write!(f, "node: transmitting {} failed: {}", packet, error)
+            }
+            MeshRouterError::Config => defmt::write!(f, "config: store failed"),
+            MeshRouterError::Store => defmt::write!(f, "store: packet store failed"),
+        }
+    }
+}
+
+// The node's error is the source, so error reports show every layer
+impl<E: fmt::Debug + core::error::Error + 'static> core::error::Error for MeshRouterError<E> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            MeshRouterError::Manager(e) => Some(e),
+            MeshRouterError::Node(e) | MeshRouterError::Transmit { error: e, .. } => Some(e),
+            MeshRouterError::Config | MeshRouterError::Store => None,
+        }
+    }
+}
+
+/// What was left when `MeshRouter::quiesce` returned, to decide whether to sleep
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Quiesced<const SIZE: usize, const LEN: usize> {
+    /// Packets still waiting for an ACK
+    pub pending: usize,
+    /// Retries sent while waiting
+    pub retransmitted: usize,
+    /// Packets for this node of the frame which arrived while waiting. Once they are handled,
+    /// `quiesce` may be called again to keep waiting.
+    pub received: Vec<Delivered<SIZE>, LEN>,
+}
+
+impl<const SIZE: usize, const LEN: usize> Quiesced<SIZE, LEN> {
+    /// Nothing is waiting for an ACK, and nothing arrived which needs handling
+    pub fn is_idle(&self) -> bool {
+        self.pending == 0 && self.received.is_empty()
+    }
+}
+
+/// How `MeshRouter::urgent_send` sends a packet, e.g. a last gasp before the battery gives out or
+/// a tamper alarm
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UrgentSend {
+    /// Times the packet is sent
+    pub repeats: u8,
+    /// Least time between two sends
+    pub gap: Duration,
+    /// Sent on this instead of the channel plan's, the most robust by default
+    pub spreading_factor: u8,
+    /// Share of the time the node may be on the air, in percent, which stretches the gaps where
+    /// needed. 1% in most EU868 sub-bands.
+    pub duty_cycle_percent: u8,
+}
+
+impl Default for UrgentSend {
+    fn default() -> Self {
+        Self {
+            repeats: 3,
+            gap: Duration::from_millis(100),
+            spreading_factor: SAFE_MODE_SPREADING_FACTOR,
+            duty_cycle_percent: 1,
+        }
+    }
+}
+
+impl UrgentSend {
+    /// How long to wait after each send of a frame with `frame_len` bytes, on `plan` if known:
+    /// the gap, or the time off the air the duty cycle asks for if that is longer
+    pub fn gap_after_frame(&self, plan: Option<ChannelPlan>, frame_len: usize) -> Duration {
+        let Some(plan) = plan else {
+            return self.gap;
+        };
+        let on_air = Modulation::new(self.spreading_factor, plan.bandwidth_khz).airtime(frame_len);
+        let percent = u32::from(self.duty_cycle_percent.clamp(1, 100));
+        self.gap.max(on_air * (100 - percent) / percent)
+    }
+}
+
+/// How long a backbone relay listens on one spreading factor before switching to the other, on
+/// radios which can't hear both at once
+const BACKBONE_DWELL: Duration = Duration::from_millis(500);
+
+/// Longest a `Control::Quiet` holds back the node's payloads, the day a gateway's quiet calendar
+/// spans, so a corrupt or mistaken request can't silence it for months
+const MAX_QUIET: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How a backbone relay listens, once it knows the backbone spreading factor
+#[derive(Debug, Clone, Copy)]
+struct BackboneListen {
+    spreading_factor: u8,
+    /// Whether the radio hears both spreading factors at once
+    both: bool,
+    /// Which one it listens on now, when taking turns
+    on_backbone: bool,
+}
+
+/// A link test this node is answering
+struct LinkTestRound {
+    initiator: u8,
+    count: u16,
+    /// Back to the channel plan by then, even if the last probe is lost
+    deadline: Instant,
+}
 
 // impl<E> From<E> for MeshRouterError<E> {
 //     fn from(err: E) -> Self {
@@ -45,41 +211,322 @@ impl<E: fmt::Debug + core::error::Error> core::error::Error for MeshRouterError<
 /// Mesh Stack(MS) handles the user defined radio which implements MHNode, and a Network Manager,
 /// managing the logic necessary to send and receive packets, but the user does not have to think
 /// about how packets are received and sent on, if they are not for them.
-/// Handles the flow of packets
-pub struct MeshRouter<Node, const SIZE: usize, const LEN: usize, Policy = NodePolicy>
-where
+/// Handles the flow of packets. LEN, PENDING and TX_Q are the manager's queue depths, see
+/// `NetworkManager`.
+pub struct MeshRouter<
+    Node,
+    const SIZE: usize,
+    const LEN: usize,
+    Policy = NodePolicy,
+    T = NoTelemetry,
+    C = NoControl,
+    const PENDING: usize = LEN,
+    const TX_Q: usize = LEN,
+    K = EmbassyClock,
+> where
     Node: MHNode<SIZE, LEN>,
     Policy: RoutingPolicy<SIZE, LEN>,
+    T: Telemetry,
+    C: ControlHooks,
+    K: Clock,
 {
     node: Node,
-    manager: NetworkManager<SIZE, LEN>,
-    policy: PhantomData<Policy>,
+    manager: NetworkManager<SIZE, LEN, K, T, PENDING, TX_Q>,
+    policy: Policy,
+    link_test: Option<LinkTestRound>,
+    control: C,
+    /// How long the last listen took, reported once `receive` knows whether it heard a frame
+    listened: Option<Duration>,
+    role: RelayRole,
+    backbone: Option<BackboneListen>,
+    scan: Option<ChannelScan>,
+    /// Own payloads are held back until then, see `Control::Quiet`
+    quiet_until: Option<Instant>,
 }
 
-impl<Node, Policy, const SIZE: usize, const LEN: usize> MeshRouter<Node, SIZE, LEN, Policy>
+impl<
+    Node,
+    Policy,
+    T,
+    K,
+    const SIZE: usize,
+    const LEN: usize,
+    const PENDING: usize,
+    const TX_Q: usize,
+> MeshRouter<Node, SIZE, LEN, Policy, T, NoControl, PENDING, TX_Q, K>
 where
     Node: MHNode<SIZE, LEN>,
     Policy: RoutingPolicy<SIZE, LEN>,
+    T: Telemetry,
+    K: Clock,
 {
-    /// Takes ownership of a node and network manager, because this handles those
-    pub fn new(node: Node, manager: NetworkManager<SIZE, LEN>, _policy: Policy) -> Self {
+    /// Takes ownership of a node and network manager, because this handles those. The manager's
+    /// clock may be any, e.g. a `TestClock` of a manager made with `with_clock`.
+    pub fn new(
+        node: Node,
+        manager: NetworkManager<SIZE, LEN, K, T, PENDING, TX_Q>,
+        policy: Policy,
+    ) -> Self {
         Self {
             node,
             manager,
-            policy: PhantomData,
+            policy,
+            link_test: None,
+            control: NoControl,
+            listened: None,
+            role: RelayRole::Edge,
+            backbone: None,
+            scan: None,
+            quiet_until: None,
+        }
+    }
+
+    /// Carries out Control messages for this node with `control`, e.g. a `LogBuffer` to answer
+    /// `DumpLogs`
+    pub fn set_control<C: ControlHooks>(
+        self,
+        control: C,
+    ) -> MeshRouter<Node, SIZE, LEN, Policy, T, C, PENDING, TX_Q, K> {
+        MeshRouter {
+            node: self.node,
+            manager: self.manager,
+            policy: self.policy,
+            link_test: self.link_test,
+            control,
+            listened: self.listened,
+            role: self.role,
+            backbone: self.backbone,
+            scan: self.scan,
+            quiet_until: self.quiet_until,
         }
     }
+}
+
+impl<
+    Node,
+    Policy,
+    T,
+    C,
+    K,
+    const SIZE: usize,
+    const LEN: usize,
+    const PENDING: usize,
+    const TX_Q: usize,
+> MeshRouter<Node, SIZE, LEN, Policy, T, C, PENDING, TX_Q, K>
+where
+    Node: MHNode<SIZE, LEN>,
+    Policy: RoutingPolicy<SIZE, LEN>,
+    T: Telemetry,
+    C: ControlHooks,
+    K: Clock,
+{
+    /// The hooks Control messages are carried out with
+    pub fn control(&self) -> &C {
+        &self.control
+    }
+
+    /// E.g. to write to a `LogBuffer` given to `set_control`
+    pub fn control_mut(&mut self) -> &mut C {
+        &mut self.control
+    }
+
+    /// Gives back the node and the manager, e.g. to shut the radio down
+    pub fn into_parts(self) -> (Node, NetworkManager<SIZE, LEN, K, T, PENDING, TX_Q>) {
+        (self.node, self.manager)
+    }
+
+    /// RAM the router takes in bytes, like `NetworkManager::memory_footprint`, with the batches
+    /// it holds on the stack: a received frame of LEN packets, those sent on and delivered from
+    /// it, and TX_Q packets to send. Buffers the radio keeps outside of `Node` aren't counted.
+    pub const fn memory_footprint() -> usize {
+        let packet = size_of::<MHPacket<SIZE>>();
+        size_of::<Self>() + LEN * (3 * packet + size_of::<Delivered<SIZE>>()) + TX_Q * packet
+    }
 
     /// Use to await another node's communication, and can be used in a select or join
     pub async fn listen(
         &mut self,
         rec_buf: &mut Node::ReceiveBuffer,
     ) -> Result<Node::Connection, MeshRouterError<Node::Error>> {
-        trace!("listening ...");
-        self.node
-            .listen(rec_buf, false)
-            .await
-            .map_err(MeshRouterError::Node)
+        trace!(Router, "listening ...");
+        let started = Instant::now();
+        let mut conn = None;
+        if let Some(deadline) = self.link_test.as_ref().map(|round| round.deadline) {
+            // Back to the channel plan if the initiator falls silent
+            match with_deadline(deadline, self.node.listen(rec_buf, false)).await {
+                Ok(heard) => conn = Some(heard),
+                Err(_) => self.end_link_test()?,
+            }
+        }
+        let conn = match conn {
+            Some(conn) => conn,
+            None => self.listen_on_profiles(rec_buf).await?,
+        };
+        self.listen_ended(started, conn.is_ok());
+        conn.map_err(MeshRouterError::Node)
+    }
+
+    /// Listens on the channel plan, and on a backbone relay on the backbone spreading factor too,
+    /// taking turns of `BACKBONE_DWELL` if the radio can't hear both at once. A scanning node
+    /// takes turns on its channels instead.
+    async fn listen_on_profiles(
+        &mut self,
+        rec_buf: &mut Node::ReceiveBuffer,
+    ) -> Result<Result<Node::Connection, Node::Error>, MeshRouterError<Node::Error>> {
+        if self.scan.is_some() {
+            return self.listen_scanning(rec_buf).await;
+        }
+        let Some(backbone) = self.update_backbone()? else {
+            return Ok(self.node.listen(rec_buf, false).await);
+        };
+        if backbone.both {
+            return Ok(self.node.listen(rec_buf, false).await);
+        }
+        loop {
+            if let Ok(heard) = with_timeout(BACKBONE_DWELL, self.node.listen(rec_buf, false)).await
+            {
+                return Ok(heard);
+            }
+            if let Some(backbone) = &mut self.backbone {
+                backbone.on_backbone = !backbone.on_backbone;
+            }
+            self.apply_listen_profile()?;
+        }
+    }
+
+    /// Listens on each channel of the scan for its dwell, or only until channel activity
+    /// detection finds it quiet. A channel with a preamble on it is listened on for up to a whole
+    /// cycle, so the frame is in before moving on, while a false detection costs one cycle.
+    async fn listen_scanning(
+        &mut self,
+        rec_buf: &mut Node::ReceiveBuffer,
+    ) -> Result<Result<Node::Connection, Node::Error>, MeshRouterError<Node::Error>> {
+        loop {
+            let Some(scan) = &self.scan else {
+                return Ok(self.node.listen(rec_buf, false).await);
+            };
+            let (dwell, cycle) = (scan.dwell(), scan.cycle());
+            let listen_for = match self
+                .node
+                .channel_activity()
+                .await
+                .map_err(MeshRouterError::Node)?
+            {
+                Some(false) => None,
+                Some(true) => Some(cycle),
+                None => Some(dwell),
+            };
+            let heard = match listen_for {
+                Some(listen_for) => with_timeout(listen_for, self.node.listen(rec_buf, false))
+                    .await
+                    .ok(),
+                None => None,
+            };
+            if let Some(heard) = heard {
+                return Ok(heard);
+            }
+            if let Some(scan) = &mut self.scan {
+                scan.advance();
+            }
+            self.apply_plan()?;
+        }
+    }
+
+    /// Has this node take turns listening on the channels of `scan`, e.g. those of a gateway
+    /// listening on several, or only on the channel plan again with None. It needs a channel plan
+    /// for the data rate, and sends on the channel it listened on last, so replies go out where
+    /// the frame was heard.
+    pub fn set_channel_scan(
+        &mut self,
+        scan: Option<ChannelScan>,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        self.scan = scan;
+        self.apply_listen_profile()
+    }
+
+    pub fn channel_scan(&self) -> Option<&ChannelScan> {
+        self.scan.as_ref()
+    }
+
+    /// The channel plan, on the channel scanned now if scanning
+    fn listening_plan(&self) -> Option<ChannelPlan> {
+        let plan = self.manager.channel_plan()?;
+        Some(match &self.scan {
+            Some(scan) => scan.on_current(&plan),
+            None => plan,
+        })
+    }
+
+    /// Tells the radio about a backbone spreading factor which is new, or gone, and returns how
+    /// to listen
+    fn update_backbone(&mut self) -> Result<Option<BackboneListen>, MeshRouterError<Node::Error>> {
+        let wanted = self.backbone_spreading_factor();
+        if wanted != self.backbone.map(|b| b.spreading_factor) {
+            let both = self
+                .node
+                .listen_also_on(wanted)
+                .map_err(MeshRouterError::Node)?;
+            self.backbone = wanted.map(|spreading_factor| BackboneListen {
+                spreading_factor,
+                both,
+                on_backbone: false,
+            });
+            self.apply_listen_profile()?;
+        }
+        Ok(self.backbone)
+    }
+
+    /// Switches the radio to the spreading factor it should listen on now
+    fn apply_listen_profile(&mut self) -> Result<(), MeshRouterError<Node::Error>> {
+        match self.backbone {
+            Some(backbone) if backbone.on_backbone && !backbone.both => {
+                self.apply_spreading_factor(backbone.spreading_factor)
+            }
+            _ => self.apply_plan(),
+        }
+    }
+
+    /// Makes this node a backbone relay or an edge node. Backbone relays should be powered, and
+    /// form a chain of neighbours to the gateway, since only other backbone relays and the
+    /// gateway hear them on the backbone spreading factor.
+    pub fn set_relay_role(&mut self, role: RelayRole) {
+        self.role = role;
+    }
+
+    pub fn relay_role(&self) -> RelayRole {
+        self.role
+    }
+
+    /// The spreading factor this node sends uplinks on, if it is a backbone relay and heard a
+    /// gateway advertise one
+    pub fn backbone_spreading_factor(&self) -> Option<u8> {
+        match self.role {
+            RelayRole::Backbone => self.manager.backbone_spreading_factor(),
+            RelayRole::Edge => None,
+        }
+    }
+
+    /// Like `listen`, but lets the node give up after its own timeout, so the caller gets
+    /// control back regularly
+    pub async fn listen_with_timeout(
+        &mut self,
+        rec_buf: &mut Node::ReceiveBuffer,
+    ) -> Result<Node::Connection, MeshRouterError<Node::Error>> {
+        trace!(Router, "listening with timeout ...");
+        self.end_expired_link_test()?;
+        let started = Instant::now();
+        let conn = self.node.listen(rec_buf, true).await;
+        self.listen_ended(started, conn.is_ok());
+        conn.map_err(MeshRouterError::Node)
+    }
+
+    /// A listen which failed heard nothing, otherwise `receive` tells whether it heard a frame
+    fn listen_ended(&mut self, started: Instant, ok: bool) {
+        let waited = started.elapsed();
+        match ok {
+            true => self.listened = Some(waited),
+            false => self.manager.telemetry_mut().listened(waited, false),
+        }
     }
 
     // TODO: If an error like buffer overflow occurs, then this should be handled by the NM. I
@@ -91,45 +538,901 @@ where
         destination: u8,
     ) -> Result<(), MeshRouterError<Node::Error>> {
         let timeouted_pkts = self.manager.payload_to_send(payload, destination)?;
-        trace!("Sending {} packets!", timeouted_pkts.len());
+        trace!(Router, "Sending {} packets!", timeouted_pkts.len());
         self.send_packets(&timeouted_pkts).await
     }
 
+    /// Sends a blob compressed, see `NetworkManager::compressed_payload_to_send`. The destination's
+    /// `receive` decompresses it.
+    #[cfg(feature = "compress")]
+    pub async fn send_compressed(
+        &mut self,
+        blob: &[u8],
+        destination: u8,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let pkts = self.manager.compressed_payload_to_send(blob, destination)?;
+        self.send_packets(&pkts).await
+    }
+
+    /// Like `send_payload`, on one of the networks a gateway serves, see
+    /// `NetworkManager::payload_to_send_on`
+    pub async fn send_payload_on(
+        &mut self,
+        network_id: u8,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let timeouted_pkts = self
+            .manager
+            .payload_to_send_on(network_id, payload, destination)?;
+        self.send_packets(&timeouted_pkts).await
+    }
+
+    /// A bootup on every network the manager serves, one frame each
+    async fn send_bootups(&mut self) -> Result<(), MeshRouterError<Node::Error>> {
+        let networks: Vec<u8, { MAX_NETWORKS + 1 }> = self.manager.networks().collect();
+        for network_id in networks {
+            let bootup_pkt = MHPacket {
+                network_id,
+                ..self.manager.handle_bootup()?
+            };
+            self.send_packets(&[bootup_pkt]).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the payload through the relays in `route` only, e.g. one a gateway learned with a
+    /// `RouteTable`. Falls back to flooding if the route doesn't deliver it.
+    pub async fn send_routed(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+        route: &[u8],
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let pkts = self
+            .manager
+            .routed_payload_to_send(payload, destination, route)?;
+        self.send_packets(&pkts).await
+    }
+
+    /// Asks `destination` for its logs from `offset` on. The `Control::Logs` reply is among the
+    /// packets `receive` returns, with as many bytes as fit in a packet, so ask again from where
+    /// it ends until `total` is reached.
+    pub async fn request_logs(
+        &mut self,
+        destination: u8,
+        offset: u32,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        self.send_control(destination, Control::DumpLogs { offset })
+            .await
+    }
+
+    /// Sends `destination` a Control message, e.g. a `Reboot`. The node's `Control::Reply` is
+    /// among the packets `receive` returns.
+    pub async fn send_control(
+        &mut self,
+        destination: u8,
+        msg: Control,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let (payload, _) = msg.encode(&[]).map_err(NetworkManagerError::from)?;
+        let pkts = self.manager.control_to_send(payload, destination)?;
+        self.send_packets(&pkts).await
+    }
+
+    /// Replies whether the hooks confirm `request`, and if so carries it out once the reply is
+    /// on its way
+    async fn carry_out(
+        &mut self,
+        source: u8,
+        request: Control,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let accepted = self.control.confirm(source, request);
+        trace!(
+            Router,
+            "Control request from {} accepted: {}", source, accepted
+        );
+        self.send_control(source, Control::Reply { accepted })
+            .await?;
+        if !accepted {
+            return Ok(());
+        }
+        // The node may be gone, or on another spreading factor, after this
+        self.node.flush().await.map_err(MeshRouterError::Node)?;
+        match request {
+            Control::Reboot => self.control.reboot(),
+            Control::FactoryReset => self.control.factory_reset(),
+            Control::EnterSafeMode => {
+                self.apply_spreading_factor(SAFE_MODE_SPREADING_FACTOR)?;
+                self.control.enter_safe_mode();
+            }
+            Control::LinkAdr {
+                spreading_factor,
+                tx_power,
+            } => {
+                self.apply_spreading_factor(spreading_factor)?;
+                self.node
+                    .set_tx_power(tx_power)
+                    .map_err(MeshRouterError::Node)?;
+            }
+            Control::SetLogLevel { subsystem, level } => verbosity::set(subsystem, level),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Sends the payload to every node. Broadcasts are flooded once and never ACKed
+    pub async fn broadcast(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let pkt = self.manager.broadcast_packet(payload)?;
+        self.send_packets(&[pkt]).await
+    }
+
+    /// Sends every node a Control message, e.g. a `Quiet`. Like a broadcast it is flooded once and
+    /// never ACKed, and the nodes carry it out without a reply.
+    pub async fn broadcast_control(
+        &mut self,
+        msg: Control,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let (payload, _) = msg.encode(&[]).map_err(NetworkManagerError::from)?;
+        let pkt = MHPacket {
+            packet_type: PacketType::Control,
+            ..self.manager.broadcast_packet(payload)?
+        };
+        self.send_packets(&[pkt]).await
+    }
+
+    /// Holds back this node's own payloads for `duration`, as a `Control::Quiet` does. Only
+    /// `send_or_store` and `drain_stored` keep to it: relaying, ACKs and `urgent_send` go on, and
+    /// `send_payload` is up to the application, which can check `is_quiet`.
+    pub fn quiet_for(&mut self, duration: Duration) {
+        self.quiet_until = (duration > Duration::from_ticks(0)).then(|| Instant::now() + duration);
+    }
+
+    /// Whether a quiet period is going on
+    pub fn is_quiet(&self) -> bool {
+        self.quiet_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Sends the payload at once, bypassing the packets waiting for an ACK or a retry: on the
+    /// robust spreading factor of `urgency`, at the radio's highest power, and `repeats` times
+    /// with gaps the duty cycle allows, instead of waiting for an ACK. The packet is marked as
+    /// priority, so relays send it on before anything else. For alarms and a last gasp, where
+    /// there may be no time to wait for retries.
+    pub async fn urgent_send(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+        urgency: &UrgentSend,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let pkt = self.manager.urgent_packet(payload, destination)?;
+        let frame_len = MHPacket::frame_len(core::slice::from_ref(&pkt));
+        let gap = urgency.gap_after_frame(self.manager.channel_plan(), frame_len);
+        self.node
+            .set_max_power(true)
+            .map_err(MeshRouterError::Node)?;
+        self.apply_spreading_factor(urgency.spreading_factor)?;
+        let mut sent = Ok(());
+        for repeat in 0..urgency.repeats {
+            if repeat > 0 {
+                Timer::after(gap).await;
+            }
+            sent = self.transmit(core::slice::from_ref(&pkt)).await;
+            if sent.is_err() {
+                break;
+            }
+            if let Err(e) = self.node.flush().await {
+                sent = Err(MeshRouterError::Node(e));
+                break;
+            }
+        }
+        // Back to the usual power and channel, also after a failed send
+        self.node
+            .set_max_power(false)
+            .map_err(MeshRouterError::Node)?;
+        self.apply_listen_profile()?;
+        sent
+    }
+
+    /// Sends the payload like `send_payload`, then only listens in the RX windows of `windows`:
+    /// RX1 on the channel and spreading factor the frame went out on, and RX2 on the fallback
+    /// spreading factor if nothing came in RX1, see `rx_windows`. The frame is marked with
+    /// `flags::RX_WINDOWS`, so the gateway sends what it has for this node in them. Returns the
+    /// packets for this node heard in the windows, after which the radio can sleep until the
+    /// next uplink. For nodes which don't relay, relays have to keep listening.
+    pub async fn send_with_rx_windows(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+        windows: &RxWindows,
+        rec_buf: &mut Node::ReceiveBuffer,
+    ) -> Result<Vec<Delivered<SIZE>, LEN>, MeshRouterError<Node::Error>> {
+        let mut pkts = self.manager.payload_to_send(payload, destination)?;
+        // Only our own, relayed packets are answered in the windows of their source
+        for pkt in pkts.iter_mut().filter(|pkt| pkt.hop_count == 0) {
+            pkt.set_flag(flags::RX_WINDOWS, true);
+        }
+        self.send_packets(&pkts).await?;
+        self.node.flush().await.map_err(MeshRouterError::Node)?;
+        let sent = Instant::now();
+        let uplink = self.listening_plan().map_or(
+            Modulation::new(windows.rx2_spreading_factor, 125),
+            Modulation::from,
+        );
+        let (opens, open_for) = windows.rx1(uplink);
+        if let Some(conn) = self
+            .listen_in_window(sent + opens, open_for, rec_buf)
+            .await?
+        {
+            return self.receive(conn, rec_buf).await;
+        }
+        self.apply_spreading_factor(windows.rx2_spreading_factor)?;
+        let (opens, open_for) = windows.rx2(uplink.bandwidth_khz);
+        let heard = self.listen_in_window(sent + opens, open_for, rec_buf).await;
+        // Back on the channel plan, also after a failed listen
+        self.apply_listen_profile()?;
+        match heard? {
+            Some(conn) => self.receive(conn, rec_buf).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Listens from `at` for `open_for`, None if nothing was heard by then
+    async fn listen_in_window(
+        &mut self,
+        at: Instant,
+        open_for: Duration,
+        rec_buf: &mut Node::ReceiveBuffer,
+    ) -> Result<Option<Node::Connection>, MeshRouterError<Node::Error>> {
+        Timer::at(at).await;
+        let started = Instant::now();
+        match with_timeout(open_for, self.node.listen(rec_buf, false)).await {
+            Ok(conn) => {
+                self.listen_ended(started, conn.is_ok());
+                conn.map(Some).map_err(MeshRouterError::Node)
+            }
+            Err(_) => {
+                self.listen_ended(started, false);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Sends the payload to every node subscribed to `group`, see `group`. Flooded once and never
+    /// ACKed, like a broadcast
+    pub async fn send_to_group(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        group: u8,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let pkt = self.manager.group_packet(payload, group)?;
+        self.send_packets(&[pkt]).await
+    }
+
+    /// Delivers the packets sent to `group` from now on. The gateway is told once we joined it,
+    /// or at once if we already have.
+    pub async fn subscribe(&mut self, group: u8) -> Result<(), MeshRouterError<Node::Error>> {
+        self.manager.subscribe(group)?;
+        self.announce_subscriptions().await
+    }
+
+    pub async fn unsubscribe(&mut self, group: u8) -> Result<(), MeshRouterError<Node::Error>> {
+        self.manager.unsubscribe(group);
+        self.announce_subscriptions().await
+    }
+
+    /// Tells the gateway which groups we are in, if we know one
+    async fn announce_subscriptions(&mut self) -> Result<(), MeshRouterError<Node::Error>> {
+        if self.manager.gw_hops() == u8::MAX {
+            return Ok(());
+        }
+        let groups = self.manager.subscriptions();
+        // The gateway is always node 1
+        self.send_control(1, Control::Subscribe { groups }).await
+    }
+
+    /// Sends a frame of packets. A backbone relay sends those for the gateway on the backbone
+    /// spreading factor, and the rest on the channel plan's.
     async fn send_packets(
         &mut self,
         // pkts: Vec<MHPacket<SIZE>, LEN>,
         pkts: &[MHPacket<SIZE>],
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let backbone = match self.backbone_spreading_factor() {
+            // A link test keeps the spreading factor it tests
+            Some(sf) if self.link_test.is_none() => sf,
+            _ => return self.transmit(pkts).await,
+        };
+        let (uplinks, rest): (Vec<_, LEN>, Vec<_, LEN>) = pkts.iter().cloned().partition(|pkt| {
+            pkt.destination_id == 1
+                && matches!(pkt.packet_type, PacketType::Data | PacketType::Control)
+        });
+        if !uplinks.is_empty() {
+            self.apply_spreading_factor(backbone)?;
+            self.transmit(&uplinks).await?;
+            self.node.flush().await.map_err(MeshRouterError::Node)?;
+        }
+        if !rest.is_empty() {
+            self.apply_plan()?;
+            self.transmit(&rest).await?;
+            self.node.flush().await.map_err(MeshRouterError::Node)?;
+        }
+        self.apply_listen_profile()
+    }
+
+    async fn transmit(
+        &mut self,
+        pkts: &[MHPacket<SIZE>],
     ) -> Result<(), MeshRouterError<Node::Error>> {
         self.node
             .transmit(pkts)
             .await
-            .map_err(MeshRouterError::Node)?;
+            .map_err(|error| match pkts.first() {
+                Some(pkt) => MeshRouterError::Transmit {
+                    packet: pkt.packet_ref(),
+                    error,
+                },
+                None => MeshRouterError::Node(error),
+            })?;
+        let telemetry = self.manager.telemetry_mut();
+        for pkt in pkts {
+            telemetry.packet_sent(pkt.packet_ref());
+        }
         Ok(())
     }
 
     /// Handles that when receiving, the packet type can be stream, therefore this keeps on
     /// listening. Then adds packets to be sent on via the NetworkManager. Lastly, those packets
-    /// are sent again if not meant for this node
+    /// are sent again if not meant for this node. The packets for this node are returned as
+    /// `Delivered`, with how they arrived.
     pub async fn receive(
         &mut self,
         conn: Node::Connection,
         receiving_buffer: &Node::ReceiveBuffer,
-    ) -> Result<Vec<MHPacket<SIZE>, LEN>, MeshRouterError<Node::Error>> {
+    ) -> Result<Vec<Delivered<SIZE>, LEN>, MeshRouterError<Node::Error>> {
         // TODO: should be able to receieve multiple packets
-        let pkts = self
-            .node
-            .receive(conn, receiving_buffer)
-            .await
-            .map_err(MeshRouterError::Node)?;
-        trace!("Done receiving, handling {} pkts", pkts.len());
+        let pkts = self.node.receive(conn, receiving_buffer).await;
+        let received_at = Instant::now();
+        if let Some(waited) = self.listened.take() {
+            let heard = pkts.as_ref().is_ok_and(|pkts| !pkts.is_empty());
+            self.manager.telemetry_mut().listened(waited, heard);
+        }
+        let pkts = pkts.map_err(MeshRouterError::Node)?;
+        trace!(Router, "Done receiving, handling {} pkts", pkts.len());
+        self.end_expired_link_test()?;
+
+        // Link tests are answered here, the policy never sees them
+        let quality = self.node.link_quality();
+        let (link_tests, pkts): (Vec<_, LEN>, Vec<_, LEN>) = pkts
+            .into_iter()
+            .partition(|pkt| pkt.packet_type == PacketType::LinkTest);
+        for pkt in link_tests {
+            self.answer_link_test(pkt, quality).await?;
+        }
 
-        let (to_send, my_pkt) = Policy::process_packets(&mut self.manager, pkts)?;
-        trace!("GOT {} packets for me!", my_pkt.len());
-        trace!("GOT {} packets which should be sent on!", to_send.len());
+        let joined = self.manager.gw_hops() != u8::MAX;
+        let (to_send, my_pkt) = self.policy.process_packets(&mut self.manager, pkts)?;
+        trace!(Router, "GOT {} packets for me!", my_pkt.len());
+        trace!(
+            Router,
+            "GOT {} packets which should be sent on!",
+            to_send.len()
+        );
         if !to_send.is_empty() {
             self.send_packets(&to_send).await?;
         }
-        Ok(my_pkt)
+        // Joining tells the gateway which groups we are in
+        if !joined && self.manager.gw_hops() != u8::MAX && self.manager.subscriptions() != 0 {
+            self.announce_subscriptions().await?;
+        }
+        // Requests to this node are carried out here, replies go to whoever asked
+        let mut delivered = Vec::new();
+        #[cfg_attr(not(feature = "compress"), allow(unused_mut))]
+        for mut pkt in my_pkt {
+            #[cfg(feature = "compress")]
+            if let Err(crate::compress::CompressError::BadMatch) =
+                crate::compress::inflate(&mut pkt)
+            {
+                trace!(
+                    Router,
+                    "Compressed payload of {} is corrupt",
+                    pkt.packet_ref()
+                );
+                self.manager
+                    .telemetry_mut()
+                    .packet_dropped(pkt.packet_ref(), DropReason::Undecodable);
+                continue;
+            }
+            let request = match pkt.packet_type {
+                PacketType::Control => Control::decode(&pkt.payload).map(|(msg, _)| msg),
+                _ => None,
+            };
+            match request {
+                Some(Control::DumpLogs { offset }) => self.send_logs(pkt.source_id, offset).await?,
+                Some(
+                    request @ (Control::Reboot
+                    | Control::FactoryReset
+                    | Control::EnterSafeMode
+                    | Control::LinkAdr { .. }
+                    | Control::SetLogLevel { .. }),
+                ) => self.carry_out(pkt.source_id, request).await?,
+                Some(request @ Control::Quiet { secs }) => {
+                    let accepted = self.control.confirm(pkt.source_id, request);
+                    trace!(
+                        Router,
+                        "Quiet for {}s, asked by {}, accepted: {}", secs, pkt.source_id, accepted
+                    );
+                    if accepted {
+                        self.quiet_for(Duration::from_secs(u64::from(secs)).min(MAX_QUIET));
+                    }
+                }
+                // Can't overflow, there are no more than were returned
+                _ => {
+                    let routes_recorded = self.manager.record_route();
+                    let _ =
+                        delivered.push(Delivered::new(pkt, quality, received_at, routes_recorded));
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// On a gateway, sends the ACK held back for `packet` on `network_id` by the manager's
+    /// `ConfirmedTypes`, once the application has it safe. False if no ACK was held for it.
+    pub async fn confirm_delivery(
+        &mut self,
+        network_id: u8,
+        packet: PacketRef,
+    ) -> Result<bool, MeshRouterError<Node::Error>> {
+        let Some(ack) = self.manager.confirm_delivery(network_id, packet) else {
+            return Ok(false);
+        };
+        self.send_packets(&[ack]).await?;
+        Ok(true)
+    }
+
+    /// Like `receive`, with the payloads decoded as T, e.g. the application's command type. A
+    /// packet whose payload isn't a T comes back as `Undecodable`, and is reported to the
+    /// telemetry as dropped.
+    pub async fn receive_as<V: DeserializeOwned>(
+        &mut self,
+        conn: Node::Connection,
+        receiving_buffer: &Node::ReceiveBuffer,
+    ) -> Result<Vec<Typed<V>, LEN>, MeshRouterError<Node::Error>> {
+        let pkts = self.receive(conn, receiving_buffer).await?;
+        let telemetry = self.manager.telemetry_mut();
+        Ok(pkts
+            .iter()
+            .map(|delivered| {
+                let typed = payload::decode(&delivered.packet);
+                if let Err(undecodable) = &typed {
+                    trace!(Router, "Payload of {} undecodable", undecodable.packet);
+                    telemetry.packet_dropped(undecodable.packet, DropReason::Undecodable);
+                }
+                typed
+            })
+            .collect())
+    }
+
+    /// Answers a `DumpLogs` with as much of the logs from `offset` as fits in a packet
+    async fn send_logs(
+        &mut self,
+        destination: u8,
+        offset: u32,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let mut logs = [0u8; SIZE];
+        let (len, total) = self.control.read_logs(offset, &mut logs);
+        let (payload, _) = Control::Logs { offset, total }
+            .encode(&logs[..len])
+            .map_err(NetworkManagerError::from)?;
+        let pkts = self.manager.control_to_send(payload, destination)?;
+        self.send_packets(&pkts).await
+    }
+
+    /// Gets ready for sleep: flushes the radio, then keeps receiving and retrying until every
+    /// pending packet is ACKed or `max_wait` passed. Returns early with the packets for this node
+    /// when a frame brought some, so none are lost to a full summary.
+    pub async fn quiesce(
+        &mut self,
+        rec_buf: &mut Node::ReceiveBuffer,
+        max_wait: Duration,
+    ) -> Result<Quiesced<SIZE, LEN>, MeshRouterError<Node::Error>> {
+        let deadline = Instant::now() + max_wait;
+        let mut summary = Quiesced {
+            pending: 0,
+            retransmitted: 0,
+            received: Vec::new(),
+        };
+        loop {
+            // One radio, every transport goes out on it
+            let due: Vec<MHPacket<SIZE>, TX_Q> = self
+                .manager
+                .due_retransmissions()
+                .into_iter()
+                .map(|(pkt, _)| pkt)
+                .collect();
+            if !due.is_empty() {
+                self.send_packets(&due).await?;
+                summary.retransmitted += due.len();
+            }
+            self.node.flush().await.map_err(MeshRouterError::Node)?;
+            let now = Instant::now();
+            if self.manager.get_pending_count() == 0
+                || !summary.received.is_empty()
+                || now >= deadline
+            {
+                break;
+            }
+            // Wake up for the next retry, if it comes before the deadline
+            let wake = self
+                .manager
+                .next_retry()
+                .map_or(deadline, |at| at.min(deadline));
+            let Ok(conn) =
+                with_timeout(wake.saturating_duration_since(now), self.listen(rec_buf)).await
+            else {
+                continue;
+            };
+            // A frame has at most LEN packets, and the loop ends before listening for the next
+            summary.received = self.receive(conn?, rec_buf).await?;
+        }
+        summary.pending = self.manager.get_pending_count();
+        Ok(summary)
+    }
+
+    /// Tests the link to the neighbour `destination` on `spreading_factor`: tells it to switch,
+    /// sends `count` probes `interval` apart and waits for each to be echoed. Nothing else is
+    /// handled meanwhile, packets heard are dropped. Without a channel plan, both ends stay on
+    /// the spreading factor they are on.
+    pub async fn link_test(
+        &mut self,
+        rec_buf: &mut Node::ReceiveBuffer,
+        destination: u8,
+        spreading_factor: u8,
+        count: u16,
+        interval: Duration,
+    ) -> Result<SfResult, MeshRouterError<Node::Error>> {
+        let start = LinkTest::Start {
+            spreading_factor,
+            count,
+            interval_ms: interval.as_millis() as u32,
+        };
+        let start = self.manager.link_test_packet(
+            start.encode().map_err(NetworkManagerError::from)?,
+            destination,
+        )?;
+        self.send_packets(&[start]).await?;
+        self.node.flush().await.map_err(MeshRouterError::Node)?;
+        self.apply_spreading_factor(spreading_factor)?;
+        let tested = self
+            .probe(rec_buf, destination, spreading_factor, count, interval)
+            .await;
+        // Back on the channel plan, whether or not the test went through
+        self.end_link_test()?;
+        tested
+    }
+
+    async fn probe(
+        &mut self,
+        rec_buf: &mut Node::ReceiveBuffer,
+        destination: u8,
+        spreading_factor: u8,
+        count: u16,
+        interval: Duration,
+    ) -> Result<SfResult, MeshRouterError<Node::Error>> {
+        let me = self.manager.source_id();
+        let mut result = SfResult::new(spreading_factor);
+        // Gives the responder time to switch
+        Timer::after(interval).await;
+        for seq in 0..count {
+            let probe = LinkTest::Probe { seq }
+                .encode()
+                .map_err(NetworkManagerError::from)?;
+            let probe = self.manager.link_test_packet(probe, destination)?;
+            self.send_packets(&[probe]).await?;
+            result.sent += 1;
+            let deadline = Instant::now() + interval;
+            while let Ok(conn) = with_deadline(deadline, self.node.listen(rec_buf, false)).await {
+                let conn = conn.map_err(MeshRouterError::Node)?;
+                // A frame which can't be received is as good as lost
+                let Ok(pkts) = self.node.receive(conn, rec_buf).await else {
+                    continue;
+                };
+                let uplink = self.node.link_quality();
+                let echo = pkts
+                    .iter()
+                    .filter(|pkt| {
+                        pkt.packet_type == PacketType::LinkTest
+                            && pkt.source_id == destination
+                            && pkt.destination_id == me
+                    })
+                    .find_map(|pkt| match LinkTest::decode(&pkt.payload) {
+                        Some(LinkTest::Echo {
+                            seq: echoed,
+                            quality,
+                        }) if echoed == seq => Some(quality),
+                        _ => None,
+                    });
+                if let Some(downlink) = echo {
+                    result.echoed += 1;
+                    if let Some(quality) = downlink {
+                        result.downlink.add(quality);
+                    }
+                    if let Some(quality) = uplink {
+                        result.uplink.add(quality);
+                    }
+                    break;
+                }
+            }
+            // Probes go out `interval` apart, however soon the echo came
+            Timer::at(deadline).await;
+        }
+        Ok(result)
+    }
+
+    /// Switches to a Start's spreading factor, or echoes a probe
+    async fn answer_link_test(
+        &mut self,
+        pkt: MHPacket<SIZE>,
+        quality: Option<LinkQuality>,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        if pkt.destination_id != self.manager.source_id() {
+            return Ok(());
+        }
+        match LinkTest::decode(&pkt.payload) {
+            Some(LinkTest::Start {
+                spreading_factor,
+                count,
+                interval_ms,
+            }) => {
+                trace!(
+                    Router,
+                    "Link test on SF{} from {}", spreading_factor, pkt.source_id
+                );
+                self.apply_spreading_factor(spreading_factor)?;
+                // An interval to switch, one for every probe and one to spare
+                let round = Duration::from_millis(interval_ms as u64) * (count as u32 + 2);
+                self.link_test = Some(LinkTestRound {
+                    initiator: pkt.source_id,
+                    count,
+                    deadline: Instant::now() + round,
+                });
+            }
+            Some(LinkTest::Probe { seq }) => {
+                let Some(round) = &self.link_test else {
+                    return Ok(());
+                };
+                if round.initiator != pkt.source_id {
+                    return Ok(());
+                }
+                let last = seq.saturating_add(1) >= round.count;
+                let echo = LinkTest::Echo { seq, quality }
+                    .encode()
+                    .map_err(NetworkManagerError::from)?;
+                let echo = self.manager.link_test_packet(echo, pkt.source_id)?;
+                self.send_packets(&[echo]).await?;
+                if last {
+                    self.node.flush().await.map_err(MeshRouterError::Node)?;
+                    self.end_link_test()?;
+                }
+            }
+            // Echoes are read by `link_test`, one heard here came too late
+            Some(LinkTest::Echo { .. }) | None => {}
+        }
+        Ok(())
+    }
+
+    /// Switches the radio to `spreading_factor` on the channel plan, if there is one
+    fn apply_spreading_factor(
+        &mut self,
+        spreading_factor: u8,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let Some(plan) = self.listening_plan() else {
+            return Ok(());
+        };
+        self.node
+            .apply_channel_plan(&ChannelPlan {
+                spreading_factor,
+                ..plan
+            })
+            .map_err(MeshRouterError::Node)
+    }
+
+    /// Goes back to the channel plan after a link test
+    fn end_link_test(&mut self) -> Result<(), MeshRouterError<Node::Error>> {
+        self.link_test = None;
+        self.apply_listen_profile()
+    }
+
+    /// Switches the radio back to the channel plan, if there is one, on the channel scanned now
+    fn apply_plan(&mut self) -> Result<(), MeshRouterError<Node::Error>> {
+        match self.listening_plan() {
+            Some(plan) => self
+                .node
+                .apply_channel_plan(&plan)
+                .map_err(MeshRouterError::Node),
+            None => Ok(()),
+        }
+    }
+
+    fn end_expired_link_test(&mut self) -> Result<(), MeshRouterError<Node::Error>> {
+        if self
+            .link_test
+            .as_ref()
+            .is_some_and(|round| Instant::now() >= round.deadline)
+        {
+            return self.end_link_test();
+        }
+        Ok(())
+    }
+
+    /// Runs the router for the tasks holding a handle from `channels`: their payloads are sent,
+    /// and the packets for this node are published as events. Errors are published too, so this
+    /// never returns.
+    pub async fn run<M: RawMutex, const CAP: usize, const SUBS: usize>(
+        &mut self,
+        channels: &MeshChannels<M, SIZE, CAP, SUBS>,
+        rec_buf: &mut Node::ReceiveBuffer,
+    ) {
+        let events = channels.events.immediate_publisher();
+        loop {
+            match select(channels.commands.receive(), self.listen(rec_buf)).await {
+                Either::First(MeshCommand::Send {
+                    payload,
+                    destination,
+                }) => {
+                    if self.send_payload(payload, destination).await.is_err() {
+                        events.publish_immediate(MeshEvent::SendFailed { destination });
+                    }
+                }
+                Either::First(MeshCommand::Broadcast { payload }) => {
+                    if self.broadcast(payload).await.is_err() {
+                        events.publish_immediate(MeshEvent::SendFailed {
+                            destination: BROADCAST_ID,
+                        });
+                    }
+                }
+                Either::First(MeshCommand::Status) => {
+                    events.publish_immediate(MeshEvent::Status(MeshStatus {
+                        queue_depth: channels.commands.len().min(u8::MAX as usize) as u8,
+                        ..self.status()
+                    }));
+                }
+                Either::Second(Ok(conn)) => match self.receive(conn, rec_buf).await {
+                    Ok(pkts) => {
+                        trace!(Router, "Publishing {} pkts", pkts.len());
+                        for delivered in pkts {
+                            events.publish_immediate(MeshEvent::Received(delivered));
+                        }
+                    }
+                    Err(_) => events.publish_immediate(MeshEvent::ReceiveFailed),
+                },
+                Either::Second(Err(_)) => events.publish_immediate(MeshEvent::ReceiveFailed),
+            }
+        }
+    }
+
+    /// Sends the payload, or keeps it in `store` while no gateway is known or during a quiet
+    /// period. Call `drain_stored` once a bootup was received or the quiet period is over to
+    /// send them.
+    pub async fn send_or_store<S: PacketStore<SIZE>>(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+        store: &mut S,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        if self.manager.gateway_known() && !self.is_quiet() {
+            return self.send_payload(payload, destination).await;
+        }
+        trace!(Router, "No gateway known or quiet, storing payload");
+        store
+            .push(StoredPayload {
+                destination,
+                payload,
+            })
+            .await
+            .map_err(|_| MeshRouterError::Store)
+    }
+
+    /// Sends the oldest payload in `store`, if a gateway is known, no quiet period is going on and
+    /// there is room for one more packet waiting for an ACK. Returns whether one was sent, so
+    /// calling it between receives paces the drain by the ACKs coming back.
+    pub async fn drain_stored<S: PacketStore<SIZE>>(
+        &mut self,
+        store: &mut S,
+    ) -> Result<bool, MeshRouterError<Node::Error>> {
+        if !self.manager.gateway_known() || self.is_quiet() || !self.manager.has_room() {
+            return Ok(false);
+        }
+        let Some(stored) = store.peek().await.map_err(|_| MeshRouterError::Store)? else {
+            return Ok(false);
+        };
+        let sent = self.send_payload(stored.payload, stored.destination).await;
+        // Once the manager took it, a failed transmit is retried like any other packet
+        if !matches!(sent, Err(MeshRouterError::Manager(_))) {
+            store.pop().await.map_err(|_| MeshRouterError::Store)?;
+        }
+        sent.map(|_| true)
+    }
+
+    /// Applies the channel plan saved in `store`, if any, so a rebooted node starts on the plan it
+    /// last got from the gateway
+    pub async fn load_channel_plan<S: ConfigStore>(
+        &mut self,
+        store: &mut S,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        let plan = store
+            .load_channel_plan()
+            .await
+            .map_err(|_| MeshRouterError::Config)?;
+        if let Some(plan) = plan {
+            self.node
+                .apply_channel_plan(&plan)
+                .map_err(MeshRouterError::Node)?;
+            self.manager.set_channel_plan(plan);
+        }
+        Ok(())
+    }
+
+    /// Joins the network and applies the channel plan of `provisioning`, loaded from a
+    /// `ProvisionStore` at boot. The manager must have been made for its node id, which can't
+    /// change afterwards.
+    pub fn provision(
+        &mut self,
+        provisioning: &Provisioning,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        if provisioning.node_id != self.manager.source_id() {
+            return Err(MeshRouterError::Config);
+        }
+        self.node
+            .apply_channel_plan(&provisioning.plan)
+            .map_err(MeshRouterError::Node)?;
+        self.manager.set_network_id(provisioning.network_id);
+        self.manager.set_channel_plan(provisioning.plan);
+        Ok(())
+    }
+
+    /// Switches to, and saves, a channel plan which a bootup changed. Call it after `receive`,
+    /// so the bootup is passed on with the old plan first. Returns whether the plan changed.
+    pub async fn update_channel_plan<S: ConfigStore>(
+        &mut self,
+        store: &mut S,
+    ) -> Result<bool, MeshRouterError<Node::Error>> {
+        let Some(plan) = self.manager.take_channel_plan_change() else {
+            return Ok(false);
+        };
+        trace!(Router, "Switching channel plan");
+        self.node
+            .apply_channel_plan(&plan)
+            .map_err(MeshRouterError::Node)?;
+        store
+            .save_channel_plan(&plan)
+            .await
+            .map_err(|_| MeshRouterError::Config)?;
+        Ok(true)
+    }
+
+    pub fn channel_plan(&self) -> Option<ChannelPlan> {
+        self.manager.channel_plan()
+    }
+
+    /// The id of the gateway the last bootup came from, see `NetworkManager::gateway_id`
+    pub fn gateway_id(&self) -> Option<u64> {
+        self.manager.gateway_id()
+    }
+
+    /// The manager's state, to persist it across restarts
+    pub fn manager_state(&self) -> ManagerState<SIZE, LEN, PENDING> {
+        self.manager.state()
+    }
+
+    /// Continues from a state saved with `manager_state`
+    pub fn restore_manager_state(
+        &mut self,
+        state: ManagerState<SIZE, LEN, PENDING>,
+    ) -> Result<(), MeshRouterError<Node::Error>> {
+        Ok(self.manager.restore(state)?)
     }
 
     // only for tests
@@ -137,16 +1440,76 @@ where
     pub fn get_pending_count(&self) -> usize {
         self.manager.get_pending_count()
     }
+
+    /// The telemetry given to the manager, e.g. to read its counters
+    pub fn telemetry(&self) -> &T {
+        self.manager.telemetry()
+    }
+
+    /// Hops to the gateway, or `u8::MAX` if none is known or its last bootup is too old
+    pub fn gw_hops(&self) -> u8 {
+        self.manager.gw_hops()
+    }
+
+    /// The node's state at a glance. Only `run` knows of payloads queued through handles, so
+    /// `queue_depth` is 0 here.
+    pub fn status(&self) -> MeshStatus {
+        MeshStatus {
+            node_id: self.manager.source_id(),
+            gw_hops: self.manager.gw_hops(),
+            pending: self.manager.get_pending_count().min(u8::MAX as usize) as u8,
+            last_rssi: self.node.link_quality().map(|quality| quality.rssi),
+            queue_depth: 0,
+            preamble: self.node.preamble_stats(),
+        }
+    }
+
+    /// The node the router sends through, e.g. to read the state of its radio
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// The node the router sends through, e.g. to change its settings while it runs
+    pub fn node_mut(&mut self) -> &mut Node {
+        &mut self.node
+    }
+
+    /// The policy received packets are handled with
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
 }
 
-impl<Node, const SIZE: usize, const LEN: usize> MeshRouter<Node, SIZE, LEN, GatewayPolicy>
+impl<Node, T, C, K, const SIZE: usize, const LEN: usize, const PENDING: usize, const TX_Q: usize>
+    MeshRouter<Node, SIZE, LEN, GatewayPolicy, T, C, PENDING, TX_Q, K>
 where
     Node: MHNode<SIZE, LEN>,
+    T: Telemetry,
+    C: ControlHooks,
+    K: Clock,
 {
     /// When gateway starts up, it should annonce itself, such that the nodes know their distance
-    /// to GW and retransmits messages if they are closer.
+    /// to GW and retransmits messages if they are closer. Sent on every network the manager serves.
     pub async fn bootup(&mut self) -> Result<(), MeshRouterError<Node::Error>> {
-        let bootup_pkt = self.manager.handle_bootup()?;
-        self.send_packets(&[bootup_pkt]).await
+        self.send_bootups().await
+    }
+}
+
+impl<Node, T, C, K, const SIZE: usize, const LEN: usize, const PENDING: usize, const TX_Q: usize>
+    MeshRouter<Node, SIZE, LEN, RuntimePolicy, T, C, PENDING, TX_Q, K>
+where
+    Node: MHNode<SIZE, LEN>,
+    T: Telemetry,
+    C: ControlHooks,
+    K: Clock,
+{
+    /// Announces the gateway, like the bootup of a router with `GatewayPolicy`, when running as
+    /// one. Returns whether a bootup was sent, a node never sends one.
+    pub async fn bootup(&mut self) -> Result<bool, MeshRouterError<Node::Error>> {
+        if self.policy != RuntimePolicy::Gateway {
+            return Ok(false);
+        }
+        self.send_bootups().await?;
+        Ok(true)
     }
 }
@@ -1,19 +1,35 @@
-use super::{MHPacket, PacketType};
+use super::{
+    BROADCAST_ID, MAX_SIZE, MHPacket, PacketRef, PacketType, WIRE_VERSION,
+    clock::{Clock, EmbassyClock},
+    config::{Advertised, ChannelPlan},
+    decisions::Decision,
+    entropy::{EntropySource, Xorshift},
+    flags, group, payload,
+    seen_filter::SeenSet,
+    telemetry::{DropReason, NoTelemetry, Telemetry},
+};
 use core::cmp::{max, min};
+use core::fmt;
 
-#[cfg(not(feature = "in_std"))]
-use defmt::{error, trace};
-#[cfg(feature = "in_std")]
-use log::{error, trace};
+use crate::verbosity::{error, trace};
 
 use embassy_time::{Duration, Instant};
 use heapless::Vec;
-use lora_phy::mod_params::RadioError;
 use postcard::Error as PostError;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "trace-decisions")]
+use super::decisions::{DECISION_LOG_LEN, DecisionLog, DecisionRecord};
 
 // pub const LEN: usize = 5;
+/// Retransmission timeouts stop doubling after this many retries
+const MAX_BACKOFF_SHIFT: u8 = 6;
+/// Networks a gateway serves besides its own `network_id`
+pub const MAX_NETWORKS: usize = 4;
+
 /// Does not need to be serialized, because only MHPacket will be sent
-#[derive(Debug, PartialEq, defmt::Format)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PendingPacket<const SIZE: usize> {
     /// We keep the whole packet so it can be retransmitted
     packet: MHPacket<SIZE>,
@@ -21,22 +37,96 @@ pub struct PendingPacket<const SIZE: usize> {
     timeout: Instant,
     /// And don't retry too many times
     retries: u8,
+    /// Which radio the packet is sent on, and retried on
+    transport: Transport,
 }
 
-#[derive(Debug, defmt::Format)]
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum NetworkManagerError {
-    Hardware(RadioError),
     Serialization(PostError),
     Timeout,
     InvalidPacket(u16),
     BufferFull,
+    /// A packet carried more than SIZE bytes, most likely because its sender was built with a
+    /// larger SIZE, given in `max_payload`
+    PayloadTooLarge {
+        source_id: u8,
+        packet_id: u16,
+        len: usize,
+        max_payload: u8,
+    },
+    /// A packet for a network the gateway doesn't serve
+    NotServed(u8),
+    /// Subscribing to, or sending to, an address which isn't a group's
+    NotAGroup(u8),
+    /// A frame of another `WIRE_VERSION`, given, from a node running another version
+    WrongVersion(u8),
 }
 
-impl From<RadioError> for NetworkManagerError {
-    fn from(err: RadioError) -> Self {
-        NetworkManagerError::Hardware(err)
+impl NetworkManagerError {
+    /// Number of the error, the low byte of `MeshRouterError::code`
+    pub const fn code(&self) -> u8 {
+        match self {
+            NetworkManagerError::Serialization(_) => 2,
+            NetworkManagerError::Timeout => 3,
+            NetworkManagerError::InvalidPacket(_) => 4,
+            NetworkManagerError::BufferFull => 5,
+            NetworkManagerError::PayloadTooLarge { .. } => 6,
+            NetworkManagerError::NotServed(_) => 7,
+            NetworkManagerError::NotAGroup(_) => 8,
+            NetworkManagerError::WrongVersion(_) => 9,
+        }
+    }
+
+    /// The packet the error is about, if it is about one
+    pub fn packet(&self) -> Option<PacketRef> {
+        match self {
+            NetworkManagerError::PayloadTooLarge {
+                source_id,
+                packet_id,
+                ..
+            } => Some(PacketRef {
+                source_id: *source_id,
+                packet_id: *packet_id,
+            }),
+            _ => None,
+        }
     }
 }
+
+impl fmt::Display for NetworkManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkManagerError::Serialization(e) => write!(f, "serialization failed: {}", e),
+            NetworkManagerError::Timeout => write!(f, "timed out"),
+            NetworkManagerError::InvalidPacket(id) => write!(f, "invalid packet {}", id),
+            NetworkManagerError::BufferFull => write!(f, "buffer full"),
+            NetworkManagerError::PayloadTooLarge {
+                source_id,
+                packet_id,
+                len,
+                max_payload,
+            } => write!(
+                f,
+                "packet {}:{} carries {} bytes, more than our SIZE, its sender has SIZE {}",
+                source_id, packet_id, len, max_payload
+            ),
+            NetworkManagerError::NotServed(id) => write!(f, "network {} is not served", id),
+            NetworkManagerError::NotAGroup(id) => write!(f, "{} is not a group address", id),
+            NetworkManagerError::WrongVersion(version) => {
+                write!(
+                    f,
+                    "frame of wire version {}, we run {}",
+                    version, WIRE_VERSION
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for NetworkManagerError {}
+
 impl From<PostError> for NetworkManagerError {
     fn from(err: PostError) -> Self {
         NetworkManagerError::Serialization(err)
@@ -66,6 +156,12 @@ impl<const N: usize> RecentSeen<N> {
     pub fn contains(&self, pid: (u8, u16)) -> bool {
         self.buffer.contains(&Some(pid))
     }
+
+    /// Entries from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = (u8, u16)> + '_ {
+        let (newer, older) = self.buffer.split_at(self.cursor);
+        older.iter().chain(newer.iter()).flatten().copied()
+    }
 }
 
 impl<const N: usize> Default for RecentSeen<N> {
@@ -74,42 +170,536 @@ impl<const N: usize> Default for RecentSeen<N> {
     }
 }
 
+/// The state of a NetworkManager worth keeping across restarts, e.g. on a gateway which should
+/// not send duplicate downlinks after a crash. Timeouts are not kept, since the clock restarts.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ManagerState<const SIZE: usize, const LEN: usize, const PENDING: usize = LEN> {
+    pub next_packet_id: u16,
+    /// Packets waiting for an ACK, with their retries
+    pub pending: Vec<(MHPacket<SIZE>, u8), PENDING>,
+    /// (source_id, packet_id) of packets seen recently, oldest first
+    pub recent_seen: Vec<(u8, u16), LEN>,
+    /// Destinations reached through another transport than the default, see `rebind`. Pending
+    /// packets to them are restored on that transport.
+    #[serde(default)]
+    pub bindings: Vec<(u8, Transport), LEN>,
+}
+
+/// Who confirms that a packet arrived
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AckMode {
+    /// Hearing the next hop rebroadcast a packet confirms it. The destination doesn't rebroadcast,
+    /// so it sends an explicit ACK to the last hop instead, which is also accepted.
+    #[default]
+    Passive,
+    /// Every relay, and the destination, explicitly ACKs the packet it heard. Rebroadcasts don't
+    /// count as confirmation.
+    HopByHop,
+    /// Only the destination ACKs, and the ACK is routed back to the source. Relays don't keep
+    /// pending packets, only the source retransmits.
+    EndToEnd,
+}
+
+/// Payload types, by the first byte of a data packet's payload, which a gateway only ACKs once
+/// the application has the packet safe, e.g. accepted by the backend, rather than as soon as it
+/// is heard. See `NetworkManager::set_confirmed_types`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConfirmedTypes([u32; 8]);
+
+impl ConfirmedTypes {
+    pub fn insert(&mut self, payload_type: u8) {
+        self.0[usize::from(payload_type / 32)] |= 1 << (payload_type % 32);
+    }
+
+    pub fn contains(&self, payload_type: u8) -> bool {
+        self.0[usize::from(payload_type / 32)] & (1 << (payload_type % 32)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|bits| *bits == 0)
+    }
+}
+
+impl FromIterator<u8> for ConfirmedTypes {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let mut types = Self::default();
+        for payload_type in iter {
+            types.insert(payload_type);
+        }
+        types
+    }
+}
+
+/// Which of its radios a node bridging several, e.g. BLE and LoRa, reaches a destination through.
+/// Nodes with one radio only use the default.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Transport {
+    #[default]
+    Lora,
+    Ble,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum PayloadType {
     Data,
     Command,
     ACK,
+    /// Delivered to us, and flooded on
+    Broadcast,
+    /// For a group we aren't in, only flooded on
+    Flood,
     Bootup,
 }
 
 /// Maintains record of packages sent, to ensure that they are received.
 /// Also handles that packets from other nodes should be sent on
-pub struct NetworkManager<const SIZE: usize, const LEN: usize> {
-    pending_acks: Vec<PendingPacket<SIZE>, LEN>,
-    // TODO: This should be more random, so each node doesn't start at 0
+///
+/// Three queue depths size its buffers, all LEN unless given:
+/// - `LEN`: packets handled from one received frame and sent on from it, and the rings of
+///   recently seen and ACKed packets
+/// - `PENDING`: own and relayed packets waiting for an ACK
+/// - `TX_Q`: packets handed out to send at once by `payload_to_send` and `due_retransmissions`.
+///   They go out in one frame, so TX_Q must not be more than the LEN of the nodes hearing it.
+///
+/// `memory_footprint` tells how much RAM a choice of them takes.
+pub struct NetworkManager<
+    const SIZE: usize,
+    const LEN: usize,
+    C = EmbassyClock,
+    T = NoTelemetry,
+    const PENDING: usize = LEN,
+    const TX_Q: usize = LEN,
+> {
+    pending_acks: Vec<PendingPacket<SIZE>, PENDING>,
     next_packet_id: u16,
+    /// Spreads retries out once seeded, see `seed`
+    jitter: Option<Xorshift>,
     /// Uses the passed in LEN for a ring buffer
     recent_seen: RecentSeen<LEN>,
+    /// For a busy relay: remembers more packets than `recent_seen`, see `set_seen_filter`
+    seen_filter: Option<&'static mut (dyn SeenSet + Send)>,
     /// Hops to gateway, handled by manager
     gw_hops: u8,
+    /// For a moving gateway: how long a bootup is trusted, when the last one was heard, and its
+    /// packet id
+    gateway_ttl: Option<Duration>,
+    gw_heard: Instant,
+    last_bootup: Option<u16>,
+    /// For gradient repair: the neighbour whose bootup gave us `gw_hops`, how long it may stay
+    /// quiet, and when it was last heard
+    parent: Option<u8>,
+    parent_timeout: Option<Duration>,
+    parent_heard: Instant,
+    /// On a gateway: packets ACKed within `ack_window`, and when, oldest first
+    ack_window: Duration,
+    recent_acks: Vec<((u8, u8, u16), Instant), LEN>,
+    /// On a gateway: packets whose ACK waits for `confirm_delivery`, oldest first
+    confirmed_types: ConfirmedTypes,
+    held_acks: Vec<(u8, u8, u16), LEN>,
+    /// Destinations reached through another transport than the default
+    bindings: Vec<(u8, Transport), LEN>,
     /// Configurations for the manager
     source_id: u8,
     timeout: u8,
     _max_retries: u8,
+    ack_mode: AckMode,
+    /// Whether we add ourselves to the route of packets we relay to the gateway
+    record_route: bool,
+    /// Only packets with this network id are handled, unless promiscuous
+    network_id: u8,
+    /// On a gateway: further networks served next to `network_id`, sharing the radio
+    also_served: Vec<u8, MAX_NETWORKS>,
+    promiscuous: bool,
+    /// Groups we deliver packets of, a bit each
+    subscriptions: u32,
+    /// Channel plan from the gateway, and whether it changed since the router last applied it
+    channel_plan: Option<ChannelPlan>,
+    plan_changed: bool,
+    /// Advertised in our bootups on a gateway, learned from them on a node
+    gateway_id: Option<u64>,
+    backbone_sf: Option<u8>,
+    #[cfg(feature = "trace-decisions")]
+    decisions: DecisionLog<DECISION_LOG_LEN>,
+    clock: C,
+    telemetry: T,
 }
 
-impl<const SIZE: usize, const LEN: usize> NetworkManager<SIZE, LEN> {
+impl<const SIZE: usize, const LEN: usize, const PENDING: usize, const TX_Q: usize>
+    NetworkManager<SIZE, LEN, EmbassyClock, NoTelemetry, PENDING, TX_Q>
+{
     pub fn new(source_id: u8, timeout: u8, max_retries: u8) -> Self {
+        Self::with_clock(source_id, timeout, max_retries, EmbassyClock)
+    }
+}
+
+impl<const SIZE: usize, const LEN: usize, T: Telemetry, const PENDING: usize, const TX_Q: usize>
+    NetworkManager<SIZE, LEN, EmbassyClock, T, PENDING, TX_Q>
+{
+    /// Like `new`, but reports what happens to packets to `telemetry`
+    pub fn with_telemetry(source_id: u8, timeout: u8, max_retries: u8, telemetry: T) -> Self {
+        Self::with_clock_and_telemetry(source_id, timeout, max_retries, EmbassyClock, telemetry)
+    }
+}
+
+impl<const SIZE: usize, const LEN: usize, C: Clock, const PENDING: usize, const TX_Q: usize>
+    NetworkManager<SIZE, LEN, C, NoTelemetry, PENDING, TX_Q>
+{
+    /// Like `new`, but timeouts follow `clock`, e.g. a `TestClock`
+    pub fn with_clock(source_id: u8, timeout: u8, max_retries: u8, clock: C) -> Self {
+        Self::with_clock_and_telemetry(source_id, timeout, max_retries, clock, NoTelemetry)
+    }
+}
+
+impl<
+    const SIZE: usize,
+    const LEN: usize,
+    C: Clock,
+    T: Telemetry,
+    const PENDING: usize,
+    const TX_Q: usize,
+> NetworkManager<SIZE, LEN, C, T, PENDING, TX_Q>
+{
+    pub fn with_clock_and_telemetry(
+        source_id: u8,
+        timeout: u8,
+        max_retries: u8,
+        clock: C,
+        telemetry: T,
+    ) -> Self {
+        const {
+            assert!(
+                SIZE > 0 && SIZE <= MAX_SIZE,
+                "SIZE must be between 1 and MAX_SIZE, a LoRa frame is at most 255 bytes"
+            );
+            assert!(LEN > 0, "LEN must fit at least one packet");
+            assert!(PENDING > 0, "PENDING must fit at least one packet");
+            assert!(TX_Q > 0, "TX_Q must fit at least one packet");
+        }
         Self {
             pending_acks: Vec::new(),
             next_packet_id: 0,
+            jitter: None,
             recent_seen: RecentSeen::default(),
+            seen_filter: None,
             // Default to max, only have a reasonable count if GW present
             gw_hops: 255,
+            gateway_ttl: None,
+            gw_heard: clock.now(),
+            last_bootup: None,
+            parent: None,
+            parent_timeout: None,
+            parent_heard: clock.now(),
+            // A node retries after its timeout at the soonest, so half of ours keeps answering
+            // retries from nodes with the same one
+            ack_window: backoff(timeout, 0) / 2,
+            recent_acks: Vec::new(),
+            confirmed_types: ConfirmedTypes::default(),
+            held_acks: Vec::new(),
+            bindings: Vec::new(),
             source_id,
             timeout,
             _max_retries: max_retries,
+            ack_mode: AckMode::default(),
+            record_route: false,
+            network_id: 0,
+            also_served: Vec::new(),
+            promiscuous: false,
+            subscriptions: 0,
+            channel_plan: None,
+            plan_changed: false,
+            gateway_id: None,
+            backbone_sf: None,
+            #[cfg(feature = "trace-decisions")]
+            decisions: DecisionLog::new(),
+            clock,
+            telemetry,
+        }
+    }
+
+    /// RAM the manager takes, its queues included, in bytes. It is const, so firmware can assert
+    /// that its queue depths fit the MCU when it is built. The batches of packets it hands out
+    /// are on the caller's stack, which `MeshRouter::memory_footprint` adds.
+    pub const fn memory_footprint() -> usize {
+        size_of::<Self>()
+    }
+
+    pub fn source_id(&self) -> u8 {
+        self.source_id
+    }
+
+    /// Starts packet ids at a random one, and delays every retry by up to a quarter of its
+    /// backoff more, with randomness from `entropy`. Unseeded, every node starts at id 1 and
+    /// retries right on its backoff, which keeps simulations reproducible.
+    pub fn seed(&mut self, mut entropy: impl EntropySource) {
+        self.next_packet_id = entropy.next_u32() as u16;
+        self.jitter = Some(Xorshift::new(entropy.next_u32()));
+    }
+
+    pub fn telemetry(&self) -> &T {
+        &self.telemetry
+    }
+
+    pub fn telemetry_mut(&mut self) -> &mut T {
+        &mut self.telemetry
+    }
+
+    /// Also checks `filter` for duplicates, e.g. a `SeenFilter` on a relay next to the gateway
+    /// which overhears more packets than the LEN `recent_seen` remembers until their retries come
+    pub fn set_seen_filter(&mut self, filter: &'static mut (dyn SeenSet + Send)) {
+        self.seen_filter = Some(filter);
+    }
+
+    /// Use `ack_mode` instead of passive ACKs. Every node in the mesh must use the same mode.
+    pub fn set_ack_mode(&mut self, ack_mode: AckMode) {
+        self.ack_mode = ack_mode;
+    }
+
+    pub fn ack_mode(&self) -> AckMode {
+        self.ack_mode
+    }
+
+    /// On a gateway, how long copies of a packet are not ACKed again, e.g. the same uplink relayed
+    /// by several nodes. Keep it below the nodes' ACK timeout so their retries are answered.
+    /// Defaults to half of our timeout, a zero window ACKs every copy.
+    pub fn set_ack_window(&mut self, window: Duration) {
+        self.ack_window = window;
+    }
+
+    /// On a gateway, whether `pkt` is heard for the first time within the ACK window, so it is
+    /// ACKed and handed to the application. Copies are counted as duplicates and dropped.
+    pub fn first_heard(&mut self, pkt: &MHPacket<SIZE>) -> bool {
+        let now = self.clock.now();
+        let window = self.ack_window;
+        self.recent_acks.retain(|(_, at)| now < *at + window);
+        // Nodes of different networks may share ids
+        let pid = (pkt.network_id, pkt.source_id, pkt.packet_id);
+        if self.recent_acks.iter().any(|(seen, _)| *seen == pid) {
+            self.record(pkt, Decision::Drop);
+            self.telemetry
+                .packet_dropped(pkt.packet_ref(), DropReason::Duplicate);
+            return false;
+        }
+        if self.recent_acks.is_full() {
+            self.recent_acks.remove(0);
+        }
+        // Can't fail, there was room made
+        let _ = self.recent_acks.push((pid, now));
+        true
+    }
+
+    /// On a gateway, hold back the ACK of data packets of `types` until `confirm_delivery`, so a
+    /// node keeps retrying until the application has the packet safe. Retries are handed to the
+    /// application again, so it may see a packet more than once. Defaults to none.
+    pub fn set_confirmed_types(&mut self, types: ConfirmedTypes) {
+        self.confirmed_types = types;
+        if types.is_empty() {
+            self.held_acks.clear();
+        }
+    }
+
+    /// On a gateway, whether the ACK of `pkt` is held back for `confirm_delivery`. Only the
+    /// latest LEN are held, the ACKs of older ones won't be sent.
+    pub fn hold_ack(&mut self, pkt: &MHPacket<SIZE>) -> bool {
+        let held = pkt.packet_type == PacketType::Data
+            && pkt
+                .payload
+                .first()
+                .is_some_and(|payload_type| self.confirmed_types.contains(*payload_type));
+        if !held {
+            return false;
+        }
+        let pid = (pkt.network_id, pkt.source_id, pkt.packet_id);
+        if self.held_acks.contains(&pid) {
+            return true;
+        }
+        if self.held_acks.is_full() {
+            self.held_acks.remove(0);
+        }
+        // Can't fail, there was room made
+        let _ = self.held_acks.push(pid);
+        true
+    }
+
+    /// On a gateway, the ACK held back for `packet` on `network_id`, now that the application
+    /// has it safe. None if its ACK wasn't held, or was given up.
+    pub fn confirm_delivery(
+        &mut self,
+        network_id: u8,
+        packet: PacketRef,
+    ) -> Option<MHPacket<SIZE>> {
+        let pid = (network_id, packet.source_id, packet.packet_id);
+        let at = self.held_acks.iter().position(|held| *held == pid)?;
+        self.held_acks.remove(at);
+        Some(MHPacket {
+            network_id,
+            destination_id: packet.source_id,
+            packet_type: PacketType::Ack,
+            flags: 0,
+            packet_id: packet.packet_id,
+            source_id: self.source_id,
+            max_payload: SIZE as u8,
+            payload: Vec::new(),
+            hop_count: 0,
+            hop_to_gw: 0,
+            route: Vec::new(),
+        })
+    }
+
+    /// Adds our id to the route of packets we relay to the gateway, so it learns the way back
+    /// with a `RouteTable`. Enable it on every node of a stable mesh to use source routing.
+    pub fn set_record_route(&mut self, record_route: bool) {
+        self.record_route = record_route;
+    }
+
+    pub fn record_route(&self) -> bool {
+        self.record_route
+    }
+
+    /// Joins the network `network_id`, ignoring packets from other networks. Defaults to 0
+    pub fn set_network_id(&mut self, network_id: u8) {
+        self.network_id = network_id;
+    }
+
+    pub fn network_id(&self) -> u8 {
+        self.network_id
+    }
+
+    /// Also serves `network_id` on a gateway, e.g. a test mesh next to the production one on the
+    /// same radio. Its packets are ACKed and received like those of our own network, and
+    /// `bootup` announces us on it too.
+    pub fn serve_network(&mut self, network_id: u8) -> Result<(), NetworkManagerError> {
+        if self.serves(network_id) {
+            return Ok(());
+        }
+        self.also_served
+            .push(network_id)
+            .map_err(|_| NetworkManagerError::BufferFull)
+    }
+
+    /// Every network served, our own `network_id` first
+    pub fn networks(&self) -> impl Iterator<Item = u8> + '_ {
+        core::iter::once(self.network_id).chain(self.also_served.iter().copied())
+    }
+
+    pub fn serves(&self, network_id: u8) -> bool {
+        network_id == self.network_id || self.also_served.contains(&network_id)
+    }
+
+    /// Handle packets from every network, e.g. for a sniffer. Our own packets still carry our
+    /// network id
+    pub fn set_promiscuous(&mut self, promiscuous: bool) {
+        self.promiscuous = promiscuous;
+    }
+
+    /// Delivers the packets sent to the group at `group`, instead of only flooding them on
+    pub fn subscribe(&mut self, group: u8) -> Result<(), NetworkManagerError> {
+        if !group::is_group(group) {
+            return Err(NetworkManagerError::NotAGroup(group));
+        }
+        self.subscriptions |= group::bit(group);
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, group: u8) {
+        if group::is_group(group) {
+            self.subscriptions &= !group::bit(group);
+        }
+    }
+
+    /// The groups we are in, a bit each, see `group::bit`
+    pub fn subscriptions(&self) -> u32 {
+        self.subscriptions
+    }
+
+    pub fn is_subscribed(&self, group: u8) -> bool {
+        group::is_group(group) && self.subscriptions & group::bit(group) != 0
+    }
+
+    /// Uses `plan` without waiting for a bootup. A gateway advertises it, and a node can set the
+    /// one it saved before rebooting
+    pub fn set_channel_plan(&mut self, plan: ChannelPlan) {
+        self.channel_plan = Some(plan);
+    }
+
+    pub fn channel_plan(&self) -> Option<ChannelPlan> {
+        self.channel_plan
+    }
+
+    /// The channel plan, if a bootup changed it since this was last called
+    pub fn take_channel_plan_change(&mut self) -> Option<ChannelPlan> {
+        if !core::mem::take(&mut self.plan_changed) {
+            return None;
         }
+        self.channel_plan
+    }
+
+    /// On a gateway: the id to advertise in bootups after the channel plan, e.g. the
+    /// concentrator's EUI, so nodes can tell gateways apart
+    pub fn set_gateway_id(&mut self, gateway_id: Option<u64>) {
+        self.gateway_id = gateway_id;
+    }
+
+    /// Our id on a gateway, or on a node the id of the gateway of the last bootup with one
+    pub fn gateway_id(&self) -> Option<u64> {
+        self.gateway_id
+    }
+
+    /// On a gateway, advertises `spreading_factor` in our bootups for backbone relays to carry
+    /// uplinks on. It is sent after the gateway id, so only with one.
+    pub fn set_backbone_spreading_factor(&mut self, spreading_factor: Option<u8>) {
+        self.backbone_sf = spreading_factor;
+    }
+
+    /// Our backbone spreading factor on a gateway, or on a node the one of the last bootup with
+    /// one
+    pub fn backbone_spreading_factor(&self) -> Option<u8> {
+        self.backbone_sf
+    }
+
+    /// Whether `pkt` is from our network, or we take all of them
+    pub fn accepts(&self, pkt: &MHPacket<SIZE>) -> bool {
+        self.promiscuous || self.serves(pkt.network_id)
+    }
+
+    /// The latest decisions taken on received packets
+    #[cfg(feature = "trace-decisions")]
+    pub fn decisions(&self) -> &DecisionLog<DECISION_LOG_LEN> {
+        &self.decisions
+    }
+
+    /// Notes what was done with a received packet, when tracing decisions
+    #[inline]
+    fn record(&mut self, pkt: &MHPacket<SIZE>, decision: Decision) {
+        #[cfg(feature = "trace-decisions")]
+        self.decisions
+            .push(DecisionRecord::new(pkt, decision, self.clock.now()));
+        #[cfg(not(feature = "trace-decisions"))]
+        let _ = (pkt, decision);
+    }
+
+    /// Whether (source_id, packet_id) was seen recently, by `recent_seen` or the seen filter
+    fn is_seen(&self, pid: (u8, u16)) -> bool {
+        self.recent_seen.contains(pid)
+            || self
+                .seen_filter
+                .as_ref()
+                .is_some_and(|filter| filter.contains(pid, self.clock.now()))
+    }
+
+    fn mark_seen(&mut self, pid: (u8, u16)) {
+        self.recent_seen.push(pid);
+        if let Some(filter) = self.seen_filter.as_mut() {
+            filter.insert(pid, self.clock.now());
+        }
+    }
+
+    /// `value` serialized with postcard as a payload, e.g. for `send_payload`. The receiver
+    /// decodes it with `MeshRouter::receive_as`.
+    pub fn from_t<V: Serialize>(value: &V) -> Result<Vec<u8, SIZE>, NetworkManagerError> {
+        payload::encode(value)
     }
 
     pub fn new_packet(
@@ -118,23 +708,166 @@ impl<const SIZE: usize, const LEN: usize> NetworkManager<SIZE, LEN> {
         destination: u8,
     ) -> Result<MHPacket<SIZE>, PostError> {
         // let payload_bytes = Vec::from_slice(payload).map_err(|_| PostError::SerializeBufferFull)?;
-        self.next_packet_id += 1;
+        self.next_packet_id = self.next_packet_id.wrapping_add(1);
         Ok(MHPacket {
+            network_id: self.network_id,
             destination_id: destination,
             packet_type: PacketType::Data,
+            flags: 0,
             packet_id: self.next_packet_id,
             source_id: self.source_id,
+            max_payload: SIZE as u8,
             payload,
             hop_count: 0,
-            hop_to_gw: self.gw_hops,
+            hop_to_gw: self.gw_hops(),
+            route: Vec::new(),
         })
     }
 
+    /// For a gateway which moves, e.g. on a vehicle: the gateway is forgotten when no bootup was
+    /// heard for `ttl`, and every new bootup replaces the path to it, not only a shorter one.
+    /// The gateway should then boot up more often than `ttl`.
+    pub fn set_gateway_ttl(&mut self, ttl: Option<Duration>) {
+        self.gateway_ttl = ttl;
+    }
+
+    /// Repairs the hops to the gateway when a relay dies: when the neighbour we reach the gateway
+    /// through, our parent, wasn't heard for `timeout`, the gateway is forgotten, so nodes behind
+    /// us stop relaying towards a dead end, and the next bootup is taken however long its path.
+    /// Anything the parent sends keeps it alive, and its relays of new bootups are always taken,
+    /// so the gateway should boot up more often than `timeout`. When the parent's own packets to
+    /// the gateway tell it lost hops, we follow, which carries the change on to the nodes behind
+    /// us.
+    pub fn set_parent_timeout(&mut self, timeout: Option<Duration>) {
+        self.parent_timeout = timeout;
+    }
+
+    /// The neighbour we reach the gateway through, None when no bootup was heard or, with a
+    /// parent timeout, it went quiet
+    pub fn parent(&self) -> Option<u8> {
+        self.parent.filter(|_| !self.parent_lost())
+    }
+
+    fn parent_lost(&self) -> bool {
+        self.parent_timeout
+            .is_some_and(|timeout| self.clock.now() > self.parent_heard + timeout)
+    }
+
+    /// Hops to the gateway, or `u8::MAX` if none is known, its last bootup is too old or the
+    /// parent towards it went quiet
+    pub fn gw_hops(&self) -> u8 {
+        match self.gateway_ttl {
+            Some(ttl) if self.clock.now() > self.gw_heard + ttl => u8::MAX,
+            _ if self.parent.is_some() && self.parent_lost() => u8::MAX,
+            _ => self.gw_hops,
+        }
+    }
+
+    /// Whether a bootup told us the way to a gateway
+    pub fn gateway_known(&self) -> bool {
+        self.gw_hops() != u8::MAX
+    }
+
+    /// When the next pending packet times out, if any is pending
+    pub fn next_retry(&self) -> Option<Instant> {
+        self.pending_acks.iter().map(|p| p.timeout).min()
+    }
+
+    /// Whether another packet can wait for an ACK
+    pub fn has_room(&self) -> bool {
+        !self.pending_acks.is_full()
+    }
+
+    /// The transport packets to `destination` are sent on
+    pub fn transport_for(&self, destination: u8) -> Transport {
+        self.bindings
+            .iter()
+            .find(|(id, _)| *id == destination)
+            .map_or(Transport::default(), |(_, transport)| *transport)
+    }
+
+    /// The transport a pending packet is sent on, `None` if it isn't pending
+    pub fn pending_transport(&self, pkt: PacketRef) -> Option<Transport> {
+        self.pending_acks
+            .iter()
+            .find(|p| p.packet.packet_ref() == pkt)
+            .map(|p| p.transport)
+    }
+
+    /// Sends packets to `destination` on `transport` from now on, e.g. when a bridge finds it
+    /// moved out of BLE range into LoRa range. Packets to it waiting for an ACK move along
+    /// instead of timing out on the old transport: up to TX_Q are returned to be sent on the new
+    /// one straight away, and get a full timeout there without counting as a retry.
+    pub fn rebind(
+        &mut self,
+        destination: u8,
+        transport: Transport,
+    ) -> Result<Vec<MHPacket<SIZE>, TX_Q>, NetworkManagerError> {
+        self.bindings.retain(|(id, _)| *id != destination);
+        if transport != Transport::default() {
+            self.bindings
+                .push((destination, transport))
+                .map_err(|_| NetworkManagerError::BufferFull)?;
+        }
+        let now = self.clock.now();
+        let timeout = self.timeout;
+        let mut moved = Vec::new();
+        for p in self
+            .pending_acks
+            .iter_mut()
+            .filter(|p| p.packet.destination_id == destination && p.transport != transport)
+        {
+            p.transport = transport;
+            // More than TX_Q go on the new transport when their timeout comes
+            if moved.push(p.packet.clone()).is_ok() {
+                p.timeout = now + backoff(timeout, p.retries);
+            }
+        }
+        Ok(moved)
+    }
+
     #[doc(hidden)]
     pub fn get_pending_count(&self) -> usize {
         self.pending_acks.len()
     }
 
+    /// Copies out the state needed to continue after a restart, see `restore`
+    pub fn state(&self) -> ManagerState<SIZE, LEN, PENDING> {
+        ManagerState {
+            next_packet_id: self.next_packet_id,
+            // Can't overflow, both are as long as what they are collected into
+            pending: self
+                .pending_acks
+                .iter()
+                .map(|p| (p.packet.clone(), p.retries))
+                .collect(),
+            recent_seen: self.recent_seen.iter().collect(),
+            bindings: self.bindings.clone(),
+        }
+    }
+
+    /// Continues from a saved state. Restored packets get a full timeout before they are
+    /// retransmitted, and packet ids continue after the saved one, even when it is lower than a
+    /// seeded start, as ids wrap around.
+    pub fn restore(
+        &mut self,
+        state: ManagerState<SIZE, LEN, PENDING>,
+    ) -> Result<(), NetworkManagerError> {
+        self.next_packet_id = state.next_packet_id;
+        // Before the pending packets, which take the transport of their destination
+        self.bindings = state.bindings;
+        for pid in state.recent_seen {
+            self.recent_seen.push(pid);
+        }
+        for (packet, retries) in state.pending {
+            self.add_packet(packet)?;
+            if let Some(pending) = self.pending_acks.last_mut() {
+                pending.retries = retries;
+            }
+        }
+        Ok(())
+    }
+
     /// This removes retried packets, and checks the pending acks list. Given the data payload in bytes, it is made into a MHPacket
     /// and added to internal acks list. It returns a list of packets to send, which includes the packet with the payload provided.
     /// But it also returns all packets which haven't been ACK'ed before it's timeout.
@@ -142,43 +875,215 @@ impl<const SIZE: usize, const LEN: usize> NetworkManager<SIZE, LEN> {
         &mut self,
         payload: Vec<u8, SIZE>,
         destination: u8,
-    ) -> Result<Vec<MHPacket<SIZE>, LEN>, NetworkManagerError> {
+    ) -> Result<Vec<MHPacket<SIZE>, TX_Q>, NetworkManagerError> {
+        self.routed_payload_to_send(payload, destination, &[])
+    }
+
+    /// Like `payload_to_send`, on one of the networks served. The packets waiting for an ACK are
+    /// shared out evenly between the networks, so a busy one can't hold up the others: a
+    /// network holding its share already gets `BufferFull`.
+    pub fn payload_to_send_on(
+        &mut self,
+        network_id: u8,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+    ) -> Result<Vec<MHPacket<SIZE>, TX_Q>, NetworkManagerError> {
+        if !self.serves(network_id) {
+            return Err(NetworkManagerError::NotServed(network_id));
+        }
+        let share = (PENDING / self.networks().count()).max(1);
+        let pending = self
+            .pending_acks
+            .iter()
+            .filter(|p| p.packet.network_id == network_id)
+            .count();
+        if pending >= share {
+            return Err(NetworkManagerError::BufferFull);
+        }
+        let new_pkt = MHPacket {
+            network_id,
+            ..self.new_packet(payload, destination)?
+        };
+        self.queue_new(new_pkt)
+    }
+
+    /// Like `payload_to_send`, but only the relays in `route` send the packet on, in order,
+    /// instead of every node on the way flooding it. Retries are flooded, in case the route broke.
+    pub fn routed_payload_to_send(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+        route: &[u8],
+    ) -> Result<Vec<MHPacket<SIZE>, TX_Q>, NetworkManagerError> {
+        let route = Vec::from_slice(route).map_err(|_| NetworkManagerError::BufferFull)?;
+        let new_pkt = MHPacket {
+            route,
+            ..self.new_packet(payload, destination)?
+        };
+        self.queue_new(new_pkt)
+    }
+
+    /// Like `payload_to_send`, for a blob such as a configuration compressed with
+    /// `compress::compress` and marked `flags::COMPRESSED`, so it may be larger than a packet
+    /// before compressing. Sent as it is if compressing doesn't make it smaller. Only for
+    /// destinations built with the `compress` feature, others get the compressed bytes.
+    #[cfg(feature = "compress")]
+    pub fn compressed_payload_to_send(
+        &mut self,
+        blob: &[u8],
+        destination: u8,
+    ) -> Result<Vec<MHPacket<SIZE>, TX_Q>, NetworkManagerError> {
+        let mut packed = [0u8; SIZE];
+        let (payload, compressed) = match crate::compress::compress(blob, &mut packed) {
+            Ok(len) if len < blob.len() => (&packed[..len], true),
+            _ => (blob, false),
+        };
+        let payload = Vec::from_slice(payload).map_err(|_| NetworkManagerError::BufferFull)?;
+        let mut new_pkt = self.new_packet(payload, destination)?;
+        // Set before it is queued, so retries are marked too
+        new_pkt.set_flag(flags::COMPRESSED, compressed);
+        self.queue_new(new_pkt)
+    }
+
+    /// Like `payload_to_send`, for a `Control` message or its reply
+    pub fn control_to_send(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+    ) -> Result<Vec<MHPacket<SIZE>, TX_Q>, NetworkManagerError> {
+        let new_pkt = MHPacket {
+            packet_type: PacketType::Control,
+            ..self.new_packet(payload, destination)?
+        };
+        self.queue_new(new_pkt)
+    }
+
+    /// The due retransmissions followed by `new_pkt`, which then waits for its ACK too
+    fn queue_new(
+        &mut self,
+        new_pkt: MHPacket<SIZE>,
+    ) -> Result<Vec<MHPacket<SIZE>, TX_Q>, NetworkManagerError> {
+        // A bridge finds the transport of each with `pending_transport`
+        let mut to_send: Vec<MHPacket<SIZE>, TX_Q> = self
+            .due_retransmissions()
+            .into_iter()
+            .map(|(pkt, _)| pkt)
+            .collect();
+        if to_send.push(new_pkt.clone()).is_err() {
+            error!(Manager, "Buffer was too full");
+            self.telemetry
+                .packet_dropped(new_pkt.packet_ref(), DropReason::BufferFull);
+        } else {
+            // NOTE: Only do this if buffer was not full, otherwise this just errors out
+            // Now we add the new_pkt to pending_acks
+            self.add_packet(new_pkt)?;
+        }
+        Ok(to_send)
+    }
+
+    /// Pending packets whose timeout has passed, to be sent again on the transport given with
+    /// each. Each retry doubles the time until the next one, and a packet is dropped once its last
+    /// retry times out too.
+    pub fn due_retransmissions(&mut self) -> Vec<(MHPacket<SIZE>, Transport), TX_Q> {
         // Clean up packets with too many retries
         // TODO: Shuold switch SF if this happens
-        let curr_time = Instant::now();
-        self.pending_acks
-            .retain(|p| p.retries < self._max_retries || p.timeout < curr_time);
+        let curr_time = self.clock.now();
+        let max_retries = self._max_retries;
+        let telemetry = &mut self.telemetry;
+        self.pending_acks.retain(|p| {
+            let keep = p.retries < max_retries || p.timeout >= curr_time;
+            if !keep {
+                telemetry.packet_dropped(p.packet.packet_ref(), DropReason::RetriesExhausted);
+            }
+            keep
+        });
 
         // Look into packages with expired timeouts,
         let pendings_len = self.pending_acks.len() as u8;
-        trace!("pendings len: {}", pendings_len);
-        let mut to_send: Vec<MHPacket<SIZE>, LEN> = self
-            .pending_acks
+        trace!(Manager, "pendings len: {}", pendings_len);
+        let timeout = self.timeout;
+        let jitter = &mut self.jitter;
+        self.pending_acks
             .iter_mut()
             .filter(|p| p.timeout < curr_time)
+            // The rest stay due for the next call
+            .take(TX_Q)
             .map(|p| {
                 p.retries += 1;
-                p.packet.clone()
+                let wait = backoff(timeout, p.retries);
+                let spread = jitter
+                    .as_mut()
+                    .map_or(Duration::from_ticks(0), |jitter| jitter.up_to(wait / 4));
+                p.timeout = curr_time + wait + spread;
+                // No ACK came over the route, so it may be broken, and the retry is flooded
+                if !is_gw_bound(&p.packet) {
+                    p.packet.route.clear();
+                }
+                telemetry.retry(p.packet.packet_ref(), p.retries);
+                (p.packet.clone(), p.transport)
             })
-            .collect();
+            .collect()
+    }
 
-        let new_pkt: MHPacket<SIZE> = self.new_packet(payload, destination)?;
-        if to_send.push(new_pkt.clone()).is_err() {
-            error!("Buffer was too full");
-        } else {
-            // NOTE: Only do this if buffer was not full, otherwise this just errors out
-            // Now we add the new_pkt to pending_acks
-            self.add_packet(new_pkt)?;
+    /// Makes a packet for every node. Nobody ACKs a broadcast, so it is never retransmitted, and
+    /// it is marked as seen so we don't deliver it to ourselves when it is flooded back.
+    pub fn broadcast_packet(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+    ) -> Result<MHPacket<SIZE>, NetworkManagerError> {
+        let pkt = self.new_packet(payload, BROADCAST_ID)?;
+        self.mark_seen((pkt.source_id, pkt.packet_id));
+        Ok(pkt)
+    }
+
+    /// Makes a packet for every node in `group`. Like a broadcast it is flooded once and never
+    /// ACKed, and marked as seen so it isn't delivered back to us.
+    pub fn group_packet(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        group: u8,
+    ) -> Result<MHPacket<SIZE>, NetworkManagerError> {
+        if !group::is_group(group) {
+            return Err(NetworkManagerError::NotAGroup(group));
         }
-        Ok(to_send)
+        let pkt = self.new_packet(payload, group)?;
+        self.mark_seen((pkt.source_id, pkt.packet_id));
+        Ok(pkt)
+    }
+
+    /// Makes an urgent packet, e.g. a last gasp, marked as priority so relays send it before
+    /// anything else. It doesn't wait for an ACK, the router repeats it instead, so it is marked
+    /// as seen to drop the copies relays send back.
+    pub fn urgent_packet(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+    ) -> Result<MHPacket<SIZE>, NetworkManagerError> {
+        let mut pkt = self.new_packet(payload, destination)?;
+        pkt.set_flag(flags::PRIORITY, true);
+        self.mark_seen((pkt.source_id, pkt.packet_id));
+        Ok(pkt)
+    }
+
+    /// Makes a link test packet for the neighbour `destination`. It is neither ACKed nor
+    /// forwarded, so it is never retransmitted either.
+    pub fn link_test_packet(
+        &mut self,
+        payload: Vec<u8, SIZE>,
+        destination: u8,
+    ) -> Result<MHPacket<SIZE>, NetworkManagerError> {
+        let mut pkt = self.new_packet(payload, destination)?;
+        pkt.packet_type = PacketType::LinkTest;
+        Ok(pkt)
     }
 
     /// Adds the packet to the internal list
     pub fn add_packet(&mut self, packet: MHPacket<SIZE>) -> Result<(), NetworkManagerError> {
-        let curr_time = Instant::now(); // + Instant::from_secs(self.timeout as u64);
-        let pkt_timout = curr_time + Duration::from_secs(self.timeout as u64);
+        let curr_time = self.clock.now();
+        let pkt_timout = curr_time + backoff(self.timeout, 0);
         // First add this package to our vec
         let pend_pkt = PendingPacket {
+            transport: self.transport_for(packet.destination_id),
             packet,
             timeout: pkt_timout,
             retries: 0,
@@ -189,55 +1094,162 @@ impl<const SIZE: usize, const LEN: usize> NetworkManager<SIZE, LEN> {
         Ok(())
     }
 
+    /// Whether `pkt` confirms one of our packets waiting for an ACK, by the `AckMode`, which then
+    /// stops waiting
+    pub fn confirm(&mut self, pkt: &MHPacket<SIZE>) -> bool {
+        let ack_mode = self.ack_mode;
+        let Some(our_packet_index) = self.pending_acks.iter().position(|p| {
+            // An explicit ACK is addressed to the source of the packet
+            let explicit_ack =
+                pkt.packet_type == PacketType::Ack && pkt.destination_id == p.packet.source_id;
+            // shortcircuit here
+            p.packet.packet_id == pkt.packet_id
+                && p.packet.network_id == pkt.network_id
+                && match ack_mode {
+                    AckMode::Passive => p.packet.source_id == pkt.source_id || explicit_ack,
+                    AckMode::HopByHop | AckMode::EndToEnd => explicit_ack,
+                }
+        }) else {
+            return false;
+        };
+        // Then remove it from our vec
+        trace!(Manager, "RECEIVED KNOWN PACKAGE, REMOVING FROM LIST");
+        self.pending_acks.remove(our_packet_index);
+        self.record(pkt, Decision::Ack);
+        self.telemetry.ack_received(pkt.packet_ref());
+        true
+    }
+
     /// Manages actions which the pakcet might require from a network pov, and returns the packet
     /// if none are required, otherwise returns none
     pub fn receive_packet(
         &mut self,
         pkt: MHPacket<SIZE>,
     ) -> Result<Option<(MHPacket<SIZE>, PayloadType)>, NetworkManagerError> {
+        if !self.accepts(&pkt) {
+            self.record(&pkt, Decision::Drop);
+            self.telemetry
+                .packet_dropped(pkt.packet_ref(), DropReason::OtherNetwork);
+            return Ok(None);
+        }
+        if self.parent_timeout.is_some() && self.parent == Some(pkt.source_id) {
+            self.parent_heard = self.clock.now();
+            // Relays only lower the hops in packets to the gateway, so more than ours are the
+            // parent's own
+            if is_gw_bound(&pkt) && pkt.hop_to_gw >= self.gw_hops {
+                self.gw_hops = pkt.hop_to_gw.saturating_add(1);
+            }
+        }
+        if pkt.packet_type == PacketType::LinkTest {
+            // Single hop, the router answers them before they get here
+            self.record(&pkt, Decision::Drop);
+            self.telemetry
+                .packet_dropped(pkt.packet_ref(), DropReason::Ignored);
+            return Ok(None);
+        }
         if pkt.packet_type == PacketType::BootUp {
-            if pkt.hop_count >= self.gw_hops {
+            // A moving gateway's new bootup replaces the old path, however long it is, and so does
+            // a new one through our parent when it is watched
+            let refresh = self.parent_timeout.is_some() && self.parent == Some(pkt.source_id);
+            let new_bootup =
+                (self.gateway_ttl.is_some() || refresh) && self.last_bootup != Some(pkt.packet_id);
+            if pkt.hop_count >= self.gw_hops() && !new_bootup {
                 // If incoming route has the same length, then discard this
+                self.record(&pkt, Decision::Drop);
+                self.telemetry
+                    .packet_dropped(pkt.packet_ref(), DropReason::StaleBootup);
                 return Ok(None);
             }
             // GW sends 0, first node has 1 hop, therefore:
             self.gw_hops = pkt.hop_count + 1;
+            self.gw_heard = self.clock.now();
+            self.last_bootup = Some(pkt.packet_id);
+            // Relays put themselves as the source of the bootups they pass on
+            self.parent = Some(pkt.source_id);
+            self.parent_heard = self.clock.now();
+            let Advertised {
+                plan,
+                gateway_id,
+                backbone_sf,
+            } = ChannelPlan::decode(&pkt.payload);
+            if gateway_id.is_some() {
+                self.gateway_id = gateway_id;
+            }
+            if backbone_sf.is_some() {
+                self.backbone_sf = backbone_sf;
+            }
+            if plan.is_some() && plan != self.channel_plan {
+                self.channel_plan = plan;
+                self.plan_changed = true;
+            }
+            self.record(&pkt, Decision::Bootup);
             // Fire and forget
             return Ok(Some((pkt, PayloadType::Bootup)));
         }
         // Check if it is one of our packets
-        if let Some(our_packet_index) = self.pending_acks.iter().position(|p| {
-            // shortcircuit here
-            p.packet.packet_id == pkt.packet_id
-                && (p.packet.source_id == pkt.source_id
-                    || (pkt.packet_type == PacketType::Ack
-            // TODO: Shouldn't this be flipped?
-                        && pkt.destination_id == p.packet.source_id))
-        }) {
-            // Then remove it from our vec, and return
-            trace!("RECEIVED KNOWN PACKAGE, REMOVING FROM LIST");
-            self.pending_acks.remove(our_packet_index);
-            // self.recent_seen.push((pkt.source_id, pkt.packet_id));
+        if self.confirm(&pkt) {
             return Ok(None);
         }
+        let ack_mode = self.ack_mode;
+        if ack_mode != AckMode::Passive {
+            // Our own packet rebroadcast, or an ACK for another hop, needs nothing from us
+            let hop_ack = ack_mode == AckMode::HopByHop && pkt.packet_type == PacketType::Ack;
+            if pkt.source_id == self.source_id || hop_ack {
+                self.record(&pkt, Decision::Drop);
+                self.telemetry
+                    .packet_dropped(pkt.packet_ref(), DropReason::Ignored);
+                return Ok(None);
+            }
+        }
         // So we aren't waiting for pkt, perhaps we've seen it before?
-        if self.recent_seen.contains((pkt.source_id, pkt.packet_id)) {
-            // We do not ACK an ACK
-            if pkt.packet_type == PacketType::Ack {
+        if self.is_seen((pkt.source_id, pkt.packet_id)) {
+            // We do not ACK an ACK, and with end-to-end ACKs only the destination does
+            // Broadcasts and group packets are never ACKed, they are flooded once and that's it,
+            // and neither are our own packets relayed back to us
+            let relay = pkt.destination_id != self.source_id;
+            if pkt.packet_type == PacketType::Ack
+                || pkt.source_id == self.source_id
+                || pkt.destination_id == BROADCAST_ID
+                || group::is_group(pkt.destination_id)
+                || (ack_mode == AckMode::EndToEnd && relay)
+            {
+                self.record(&pkt, Decision::Drop);
+                self.telemetry
+                    .packet_dropped(pkt.packet_ref(), DropReason::Duplicate);
                 return Ok(None);
             }
             // A duplicate which we should ACK, but not care about
+            self.record(&pkt, Decision::Duplicate);
+            self.telemetry
+                .packet_dropped(pkt.packet_ref(), DropReason::Duplicate);
             return Ok(Some((pkt, PayloadType::ACK)));
         }
-        self.recent_seen.push((pkt.source_id, pkt.packet_id));
+        self.mark_seen((pkt.source_id, pkt.packet_id));
 
+        if pkt.destination_id == BROADCAST_ID {
+            self.record(&pkt, Decision::Deliver);
+            self.telemetry.packet_forwarded(pkt.packet_ref());
+            return Ok(Some((pkt, PayloadType::Broadcast)));
+        }
+        if group::is_group(pkt.destination_id) {
+            self.telemetry.packet_forwarded(pkt.packet_ref());
+            if self.is_subscribed(pkt.destination_id) {
+                self.record(&pkt, Decision::Deliver);
+                return Ok(Some((pkt, PayloadType::Broadcast)));
+            }
+            self.record(&pkt, Decision::Forward);
+            return Ok(Some((pkt, PayloadType::Flood)));
+        }
         // Perhaps it should be sent on?
         let to_us = pkt.destination_id == self.source_id;
         if !to_us {
-            let is_gw_bound = pkt.destination_id == 1;
-            let should_forward = if is_gw_bound {
+            let to_gw = is_gw_bound(&pkt);
+            let should_forward = if to_gw {
                 // Are we closer to GW?
-                self.gw_hops < pkt.hop_to_gw
+                self.gw_hops() < pkt.hop_to_gw
+            } else if !pkt.route.is_empty() {
+                // Source routed, so only the next relay sends it on
+                pkt.route.get(pkt.hop_count as usize) == Some(&self.source_id)
             } else {
                 // Are we in between source and destination?
                 (min(pkt.source_id, pkt.destination_id) <= self.source_id)
@@ -246,19 +1258,36 @@ impl<const SIZE: usize, const LEN: usize> NetworkManager<SIZE, LEN> {
 
             if !should_forward {
                 // If NOT, then we are not in the path of the packet, and do not rebroadcast
+                self.record(&pkt, Decision::Drop);
+                self.telemetry
+                    .packet_dropped(pkt.packet_ref(), DropReason::NotOnPath);
                 return Ok(None);
             }
             let increased_gw_hops = {
                 let mut temp = pkt.clone();
-                temp.hop_to_gw = self.gw_hops;
+                temp.hop_to_gw = self.gw_hops();
+                if to_gw {
+                    if self.record_route {
+                        // A full route is left as it is, the gateway just won't learn this one
+                        let _ = temp.route.push(self.source_id);
+                    }
+                } else if !temp.route.is_empty() {
+                    temp.hop_count += 1;
+                }
                 temp
             };
-            self.add_packet(increased_gw_hops.clone())?;
-            trace!("PACKAGE SHOULD BE SENT ON");
+            // With end-to-end ACKs, only the source retransmits
+            if ack_mode != AckMode::EndToEnd {
+                self.add_packet(increased_gw_hops.clone())?;
+            }
+            self.record(&pkt, Decision::Forward);
+            self.telemetry.packet_forwarded(pkt.packet_ref());
+            trace!(Manager, "PACKAGE SHOULD BE SENT ON");
             Ok(Some((increased_gw_hops, PayloadType::Data)))
         } else {
             // If this is actually for us, then it is probably a command that the underlying app
             // wants, so this gives it back
+            self.record(&pkt, Decision::Deliver);
             Ok(Some((pkt, PayloadType::Command)))
         }
     }
@@ -272,67 +1301,118 @@ impl<const SIZE: usize, const LEN: usize> NetworkManager<SIZE, LEN> {
         let mut to_send: Vec<MHPacket<SIZE>, LEN> = Vec::new();
         let mut commands: Vec<MHPacket<SIZE>, LEN> = Vec::new();
         for pkt in pkts {
+            let pkt_ref = pkt.packet_ref();
             let (packet, ptype) = match self.receive_packet(pkt) {
                 Ok(Some(p)) => p,
                 Ok(None) => continue,
                 Err(e) => {
-                    error!("Error in managing packet: {:?}", e);
+                    error!(Manager, "Error in managing packet {}: {:?}", pkt_ref, e);
                     continue;
                 }
             };
             let err_closure = |e| {
-                error!("Error pushing to commands: {:?}", e);
+                error!(Manager, "Error pushing to commands: {:?}", e);
                 NetworkManagerError::BufferFull
             };
             match ptype {
-                PayloadType::Data => to_send.push(packet).map_err(err_closure)?,
-                PayloadType::Command => commands.push(packet).map_err(err_closure)?,
-                PayloadType::ACK => to_send
-                    .push(MHPacket {
-                        destination_id: packet.source_id,
-                        packet_type: PacketType::Ack,
-                        packet_id: packet.packet_id,
-                        source_id: self.source_id,
-                        payload: Vec::from_slice(&[0u8])
-                            .map_err(|_| NetworkManagerError::BufferFull)?,
-                        hop_count: 0,
-                        hop_to_gw: self.gw_hops,
-                    })
-                    .map_err(err_closure)?,
+                PayloadType::Data => {
+                    // The ACK goes first, so the previous hop stops retransmitting sooner
+                    if self.ack_mode == AckMode::HopByHop
+                        && matches!(packet.packet_type, PacketType::Data | PacketType::Control)
+                    {
+                        to_send.push(self.ack_for(&packet)?).map_err(err_closure)?;
+                    }
+                    to_send.push(packet).map_err(err_closure)?
+                }
+                PayloadType::Command => {
+                    // Nothing is rebroadcast for a packet we deliver, so it is always ACKed
+                    to_send.push(self.ack_for(&packet)?).map_err(err_closure)?;
+                    commands.push(packet).map_err(err_closure)?
+                }
+                PayloadType::ACK => to_send.push(self.ack_for(&packet)?).map_err(err_closure)?,
+                PayloadType::Broadcast => {
+                    commands.push(packet.clone()).map_err(err_closure)?;
+                    to_send.push(packet).map_err(err_closure)?
+                }
+                PayloadType::Flood => to_send.push(packet).map_err(err_closure)?,
                 PayloadType::Bootup => to_send
                     .push(MHPacket {
+                        network_id: packet.network_id,
                         destination_id: packet.destination_id,
                         packet_type: PacketType::BootUp,
+                        flags: packet.flags,
                         packet_id: packet.packet_id,
                         source_id: self.source_id,
-                        payload: Vec::from_slice(&[0u8])
-                            .map_err(|_| NetworkManagerError::BufferFull)?,
+                        max_payload: SIZE as u8,
+                        // The channel plan goes on with it
+                        payload: packet.payload,
                         hop_count: packet.hop_count + 1,
-                        hop_to_gw: self.gw_hops,
+                        hop_to_gw: self.gw_hops(),
+                        route: Vec::new(),
                     })
                     .map_err(err_closure)?,
             };
         }
-        Ok((to_send, commands))
+        // Priority packets go out first, in the order they came
+        let (mut ordered, rest): (Vec<_, LEN>, Vec<_, LEN>) = to_send
+            .into_iter()
+            .partition(|pkt| pkt.has_flag(flags::PRIORITY));
+        ordered.extend(rest);
+        Ok((ordered, commands))
+    }
+
+    /// An explicit ACK for `packet`, addressed to its source
+    fn ack_for(&self, packet: &MHPacket<SIZE>) -> Result<MHPacket<SIZE>, NetworkManagerError> {
+        Ok(MHPacket {
+            network_id: packet.network_id,
+            destination_id: packet.source_id,
+            packet_type: PacketType::Ack,
+            flags: 0,
+            packet_id: packet.packet_id,
+            source_id: self.source_id,
+            max_payload: SIZE as u8,
+            payload: Vec::from_slice(&[0u8]).map_err(|_| NetworkManagerError::BufferFull)?,
+            hop_count: 0,
+            hop_to_gw: self.gw_hops(),
+            route: Vec::new(),
+        })
     }
 
     pub fn handle_bootup(&mut self) -> Result<MHPacket<SIZE>, NetworkManagerError> {
-        self.next_packet_id += 1;
+        self.next_packet_id = self.next_packet_id.wrapping_add(1);
         Ok(MHPacket {
+            network_id: self.network_id,
             destination_id: 0, // broadcast id
             packet_type: PacketType::BootUp,
+            flags: 0,
             packet_id: self.next_packet_id,
             source_id: self.source_id,
-            payload: Vec::from_slice(&[]).map_err(|_| NetworkManagerError::BufferFull)?,
+            max_payload: SIZE as u8,
+            payload: match self.channel_plan {
+                Some(plan) => plan.encode(self.gateway_id, self.backbone_sf)?,
+                None => Vec::new(),
+            },
             hop_count: 0,
             hop_to_gw: 0,
+            route: Vec::new(),
         })
     }
 }
 
+/// Whether the packet is on its way to the gateway
+pub(crate) fn is_gw_bound<const SIZE: usize>(pkt: &MHPacket<SIZE>) -> bool {
+    pkt.destination_id == 1
+}
+
+/// Time to wait for an ACK after the `retries`th retry
+fn backoff(timeout: u8, retries: u8) -> Duration {
+    Duration::from_secs((timeout as u64) << retries.min(MAX_BACKOFF_SHIFT))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::node::{clock::TestClock, route::RouteTable};
 
     // A helper to make a dummy manager for testing
     fn setup_manager() -> NetworkManager<40, 5> {
@@ -395,4 +1475,528 @@ mod tests {
         // let res = manager.send_packet(pkt);
         // assert!(matches!(res, Err(NetworkManagerError::BufferFull)));
     }
+
+    #[test]
+    fn test_state_survives_restart() {
+        let mut manager = setup_manager();
+        let pkt = Vec::from_slice(&[1]).unwrap();
+        manager.payload_to_send(pkt, 2).unwrap();
+        let mut other = NetworkManager::<40, 5>::new(3, 10, 3);
+        let seen = other.new_packet(Vec::from_slice(&[2]).unwrap(), 1).unwrap();
+        manager.receive_packet(seen.clone()).unwrap();
+
+        let mut restarted = setup_manager();
+        restarted.restore(manager.state()).unwrap();
+        assert_eq!(restarted.get_pending_count(), 1);
+        // Ids continue where we left off, so the old ones aren't reused
+        let next = restarted.new_packet(Vec::new(), 2).unwrap();
+        assert_eq!(next.packet_id, 2);
+        // And a retransmission of a packet we already delivered is a duplicate
+        let (_, ptype) = restarted.receive_packet(seen).unwrap().unwrap();
+        assert_eq!(ptype, PayloadType::ACK);
+    }
+
+    #[test]
+    fn test_seen_filter_catches_what_the_ring_forgot() {
+        extern crate std;
+        use crate::node::seen_filter::SeenFilter;
+        use std::boxed::Box;
+
+        let mut relay = setup_manager();
+        let filter = SeenFilter::<4, 32>::new(Duration::from_secs(30), 4);
+        relay.set_seen_filter(Box::leak(Box::new(filter)));
+        let mut other = NetworkManager::<40, 5>::new(3, 10, 3);
+        let first = other.new_packet(Vec::from_slice(&[1]).unwrap(), 2).unwrap();
+        relay.receive_packet(first.clone()).unwrap();
+        // Far more than the ring of 5 holds
+        for _ in 0..20 {
+            let pkt = other.new_packet(Vec::from_slice(&[2]).unwrap(), 2).unwrap();
+            relay.receive_packet(pkt).unwrap();
+        }
+        assert!(!relay.recent_seen.contains((3, first.packet_id)));
+        let (_, ptype) = relay.receive_packet(first).unwrap().unwrap();
+        assert_eq!(ptype, PayloadType::ACK);
+    }
+
+    #[test]
+    fn test_destination_acks_delivery() {
+        let mut manager = setup_manager();
+        let mut other = NetworkManager::<40, 5>::new(3, 10, 3);
+        other
+            .payload_to_send(Vec::from_slice(&[1]).unwrap(), 1)
+            .unwrap();
+        let pkt = other.pending_acks[0].packet.clone();
+
+        let (to_send, commands) = manager
+            .handle_packets(Vec::from_slice(&[pkt]).unwrap())
+            .unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(to_send.len(), 1);
+        assert_eq!(to_send[0].packet_type, PacketType::Ack);
+        assert_eq!(to_send[0].destination_id, 3);
+
+        // The sender takes the explicit ACK as confirmation, even in passive mode
+        assert_eq!(other.receive_packet(to_send[0].clone()).unwrap(), None);
+        assert_eq!(other.get_pending_count(), 0);
+    }
+
+    #[test]
+    fn test_retry_scheduling() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        manager
+            .payload_to_send(Vec::from_slice(&[1]).unwrap(), 2)
+            .unwrap();
+
+        // Not due until the timeout has passed
+        clock.advance(Duration::from_secs(10));
+        assert!(manager.due_retransmissions().is_empty());
+        clock.advance(Duration::from_secs(1));
+        let due = manager.due_retransmissions();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0.payload, [1]);
+        // And then it waits for its next timeout, instead of going out on every call
+        assert!(manager.due_retransmissions().is_empty());
+
+        // A new payload brings along the retries which are due
+        clock.advance(Duration::from_secs(21));
+        let to_send = manager
+            .payload_to_send(Vec::from_slice(&[2]).unwrap(), 2)
+            .unwrap();
+        assert_eq!(to_send.len(), 2);
+        assert_eq!(to_send[0].payload, [1]);
+        assert_eq!(to_send[1].payload, [2]);
+    }
+
+    #[test]
+    fn test_retry_backoff_grows() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        manager.payload_to_send(Vec::new(), 2).unwrap();
+
+        // Seconds since the previous send until the retry goes out
+        let mut gaps: Vec<u64, 3> = Vec::new();
+        let mut since_send = 0;
+        while !gaps.is_full() {
+            clock.advance(Duration::from_secs(1));
+            since_send += 1;
+            if !manager.due_retransmissions().is_empty() {
+                gaps.push(since_send).unwrap();
+                since_send = 0;
+            }
+        }
+        assert_eq!(gaps, [11, 21, 41]);
+    }
+
+    #[test]
+    fn test_seeded_ids_and_retries_spread_out() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        manager.seed(Xorshift::new(7));
+        let pkts = manager.payload_to_send(Vec::new(), 2).unwrap();
+        let first = Xorshift::new(7).next_u32() as u16;
+        assert_eq!(pkts[0].packet_id, first.wrapping_add(1));
+
+        let mut gaps: Vec<u64, 3> = Vec::new();
+        let mut since_send = 0;
+        while !gaps.is_full() {
+            clock.advance(Duration::from_secs(1));
+            since_send += 1;
+            if !manager.due_retransmissions().is_empty() {
+                gaps.push(since_send).unwrap();
+                since_send = 0;
+            }
+        }
+        // Retries wait up to a quarter of their backoff longer
+        assert_eq!(gaps[0], 11);
+        assert!((21..=26).contains(&gaps[1]));
+        assert!((41..=51).contains(&gaps[2]));
+    }
+
+    #[test]
+    fn test_retries_expire() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        manager.payload_to_send(Vec::new(), 2).unwrap();
+
+        let mut retries = 0;
+        for _ in 0..200 {
+            clock.advance(Duration::from_secs(1));
+            retries += manager.due_retransmissions().len();
+        }
+        // Every retry was sent, and the packet is given up on after the last one timed out
+        assert_eq!(retries, 3);
+        assert_eq!(manager.get_pending_count(), 0);
+    }
+
+    #[test]
+    fn test_queue_depths_are_independent() {
+        let clock = TestClock::new();
+        // Frames of 2 packets, but room for 8 waiting for an ACK
+        let mut manager =
+            NetworkManager::<40, 2, _, NoTelemetry, 8, 2>::with_clock(1, 10, 3, &clock);
+        for i in 0..8 {
+            manager
+                .payload_to_send(Vec::from_slice(&[i]).unwrap(), 2)
+                .unwrap();
+        }
+        assert_eq!(manager.get_pending_count(), 8);
+        assert!(!manager.has_room());
+
+        // All are due at once, and go out 2 at a time
+        clock.advance(Duration::from_secs(11));
+        for _ in 0..4 {
+            assert_eq!(manager.due_retransmissions().len(), 2);
+        }
+        assert!(manager.due_retransmissions().is_empty());
+
+        assert!(
+            NetworkManager::<40, 2, EmbassyClock, NoTelemetry, 8, 2>::memory_footprint()
+                > NetworkManager::<40, 2>::memory_footprint()
+        );
+    }
+
+    #[test]
+    fn test_gradient_repairs_when_parent_vanishes() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(3, 10, 3, &clock);
+        manager.set_parent_timeout(Some(Duration::from_secs(10)));
+        let mut other = NetworkManager::<40, 5>::new(2, 10, 3);
+        let template = other.handle_bootup().unwrap();
+        let bootup_from = |source_id: u8, packet_id: u16, hop_count: u8| MHPacket {
+            source_id,
+            packet_id,
+            hop_count,
+            ..template.clone()
+        };
+
+        let bootup = bootup_from(2, 1, 1);
+        assert!(manager.receive_packet(bootup).unwrap().is_some());
+        assert_eq!((manager.gw_hops(), manager.parent()), (2, Some(2)));
+
+        // The parent's uplinks keep it alive
+        clock.advance(Duration::from_secs(6));
+        let mut uplink = other.new_packet(Vec::new(), 1).unwrap();
+        uplink.hop_to_gw = 1;
+        manager.receive_packet(uplink.clone()).unwrap();
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(manager.gw_hops(), 2);
+
+        // It lost its own path and took a longer one, so do we
+        uplink.packet_id += 1;
+        uplink.hop_to_gw = 4;
+        manager.receive_packet(uplink).unwrap();
+        assert_eq!(manager.gw_hops(), 5);
+
+        // It went quiet, so the gateway is forgotten, and a longer path than before is taken
+        clock.advance(Duration::from_secs(11));
+        assert_eq!((manager.gw_hops(), manager.parent()), (u8::MAX, None));
+        let bootup = bootup_from(4, 2, 5);
+        assert!(manager.receive_packet(bootup).unwrap().is_some());
+        assert_eq!((manager.gw_hops(), manager.parent()), (6, Some(4)));
+
+        // A new bootup through the parent refreshes the path, one through others doesn't
+        let bootup = bootup_from(5, 3, 2);
+        assert!(manager.receive_packet(bootup).unwrap().is_some());
+        assert_eq!((manager.gw_hops(), manager.parent()), (3, Some(5)));
+        let bootup = bootup_from(6, 4, 3);
+        assert!(manager.receive_packet(bootup).unwrap().is_none());
+        let bootup = bootup_from(5, 4, 3);
+        assert!(manager.receive_packet(bootup).unwrap().is_some());
+        assert_eq!((manager.gw_hops(), manager.parent()), (4, Some(5)));
+    }
+
+    #[test]
+    fn test_gateway_acks_copies_once() {
+        let clock = TestClock::new();
+        let mut gateway = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        let mut node: NetworkManager<40, 5> = NetworkManager::new(2, 10, 3);
+        let pkt = &node.payload_to_send(Vec::new(), 1).unwrap()[0];
+
+        assert!(gateway.first_heard(pkt));
+        // The same uplink through another relay
+        clock.advance(Duration::from_secs(1));
+        assert!(!gateway.first_heard(pkt));
+        // The node's retry after its timeout is answered again
+        clock.advance(Duration::from_secs(10));
+        assert!(gateway.first_heard(pkt));
+    }
+
+    #[test]
+    fn test_gateway_holds_confirmed_acks() {
+        let mut gateway = setup_manager();
+        gateway.set_confirmed_types([7].into_iter().collect());
+        let mut node: NetworkManager<40, 5> = NetworkManager::new(2, 10, 3);
+        let confirmed = &node
+            .payload_to_send(Vec::from_slice(&[7, 1]).unwrap(), 1)
+            .unwrap()[0];
+        let other = node
+            .payload_to_send(Vec::from_slice(&[8, 1]).unwrap(), 1)
+            .unwrap()
+            .last()
+            .cloned()
+            .unwrap();
+
+        assert!(gateway.hold_ack(confirmed));
+        assert!(!gateway.hold_ack(&other));
+        // A retry is still held, and only the first confirmation gives the ACK
+        assert!(gateway.hold_ack(confirmed));
+        let ack = gateway
+            .confirm_delivery(confirmed.network_id, confirmed.packet_ref())
+            .unwrap();
+        assert_eq!(ack.packet_type, PacketType::Ack);
+        assert_eq!(
+            ack.packet_ref(),
+            PacketRef {
+                source_id: 1,
+                packet_id: confirmed.packet_id
+            }
+        );
+        assert_eq!(ack.destination_id, 2);
+        assert!(
+            gateway
+                .confirm_delivery(confirmed.network_id, confirmed.packet_ref())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_pending_packets_follow_rebind() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        let pkt = manager.payload_to_send(Vec::new(), 2).unwrap()[0].packet_ref();
+        let other = manager.payload_to_send(Vec::new(), 3).unwrap()[0].packet_ref();
+        assert_eq!(manager.pending_transport(pkt), Some(Transport::Lora));
+
+        // Node 2 came into BLE range just before its packet would have timed out
+        clock.advance(Duration::from_secs(9));
+        let moved = manager.rebind(2, Transport::Ble).unwrap();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].packet_ref(), pkt);
+        assert_eq!(manager.pending_transport(pkt), Some(Transport::Ble));
+        assert_eq!(manager.pending_transport(other), Some(Transport::Lora));
+        assert_eq!(manager.transport_for(2), Transport::Ble);
+        // It gets a full timeout on BLE, while the other one is retried on time
+        clock.advance(Duration::from_secs(2));
+        let due = manager.due_retransmissions();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0.packet_ref(), other);
+        assert_eq!(due[0].1, Transport::Lora);
+        // New packets go out on BLE too, and nothing moves when the binding stays
+        let next = manager.payload_to_send(Vec::new(), 2).unwrap();
+        assert_eq!(
+            manager.pending_transport(next.last().unwrap().packet_ref()),
+            Some(Transport::Ble)
+        );
+        assert!(manager.rebind(2, Transport::Ble).unwrap().is_empty());
+        // Its retry goes out on BLE
+        clock.advance(Duration::from_secs(9));
+        let due = manager.due_retransmissions();
+        assert!(due.contains(&(moved[0].clone(), Transport::Ble)));
+    }
+
+    #[test]
+    fn test_bindings_are_restored() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        manager.rebind(2, Transport::Ble).unwrap();
+        let pkt = manager.payload_to_send(Vec::new(), 2).unwrap()[0].packet_ref();
+
+        let mut restarted = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        restarted.restore(manager.state()).unwrap();
+        assert_eq!(restarted.transport_for(2), Transport::Ble);
+        assert_eq!(restarted.pending_transport(pkt), Some(Transport::Ble));
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(restarted.due_retransmissions()[0].1, Transport::Ble);
+    }
+
+    #[test]
+    fn test_other_network_is_ignored() {
+        let mut manager = setup_manager();
+        manager.set_network_id(1);
+        let mut neighbour = NetworkManager::<40, 5>::new(3, 10, 3);
+        neighbour.set_network_id(2);
+        let pkt = neighbour
+            .new_packet(Vec::from_slice(&[1]).unwrap(), 1)
+            .unwrap();
+        assert_eq!(pkt.network_id, 2);
+
+        assert_eq!(manager.receive_packet(pkt.clone()).unwrap(), None);
+        // Unless we listen to every network
+        manager.set_promiscuous(true);
+        let (_, ptype) = manager.receive_packet(pkt).unwrap().unwrap();
+        assert_eq!(ptype, PayloadType::Command);
+        // And what we send is still on our own network
+        let ours = manager.new_packet(Vec::new(), 3).unwrap();
+        assert_eq!(ours.network_id, 1);
+    }
+
+    #[test]
+    fn test_gateway_serves_several_networks() {
+        let mut gateway = setup_manager();
+        gateway.set_network_id(1);
+        gateway.serve_network(2).unwrap();
+        assert_eq!(gateway.networks().collect::<Vec<u8, 2>>(), [1, 2]);
+        // The same node id and packet id on both networks are two packets
+        let mut test_node = NetworkManager::<40, 5>::new(3, 10, 3);
+        test_node.set_network_id(2);
+        let mut prod_node = NetworkManager::<40, 5>::new(3, 10, 3);
+        prod_node.set_network_id(1);
+        let test_pkt = test_node.new_packet(Vec::new(), 1).unwrap();
+        let prod_pkt = prod_node.new_packet(Vec::new(), 1).unwrap();
+        assert!(gateway.accepts(&test_pkt));
+        assert!(gateway.first_heard(&test_pkt));
+        assert!(gateway.first_heard(&prod_pkt));
+        assert!(!gateway.first_heard(&test_pkt));
+        let mut other = prod_pkt.clone();
+        other.network_id = 3;
+        assert!(!gateway.accepts(&other));
+
+        // Downlinks share the queue out, LEN 5 gives each network 2 packets
+        for _ in 0..2 {
+            let sent = gateway.payload_to_send_on(2, Vec::new(), 3).unwrap();
+            assert_eq!(sent.last().unwrap().network_id, 2);
+        }
+        assert!(matches!(
+            gateway.payload_to_send_on(2, Vec::new(), 3),
+            Err(NetworkManagerError::BufferFull)
+        ));
+        assert!(gateway.payload_to_send_on(1, Vec::new(), 3).is_ok());
+        assert!(matches!(
+            gateway.payload_to_send_on(3, Vec::new(), 3),
+            Err(NetworkManagerError::NotServed(3))
+        ));
+    }
+
+    #[derive(Default)]
+    struct Counts {
+        forwarded: usize,
+        acks: usize,
+        retries: Vec<u8, 4>,
+        drops: Vec<DropReason, 4>,
+    }
+
+    impl Telemetry for Counts {
+        fn packet_forwarded(&mut self, _packet: PacketRef) {
+            self.forwarded += 1;
+        }
+
+        fn ack_received(&mut self, _packet: PacketRef) {
+            self.acks += 1;
+        }
+
+        fn retry(&mut self, _packet: PacketRef, retries: u8) {
+            self.retries.push(retries).unwrap();
+        }
+
+        fn packet_dropped(&mut self, _packet: PacketRef, reason: DropReason) {
+            self.drops.push(reason).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_telemetry_hooks() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _, _>::with_clock_and_telemetry(
+            1,
+            10,
+            3,
+            &clock,
+            Counts::default(),
+        );
+        let ours = manager.payload_to_send(Vec::new(), 2).unwrap()[0].clone();
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(manager.due_retransmissions().len(), 1);
+        // The next hop rebroadcasting it confirms it
+        assert_eq!(manager.receive_packet(ours).unwrap(), None);
+
+        let mut neighbour = NetworkManager::<40, 5>::new(3, 10, 3);
+        neighbour.set_network_id(2);
+        let other_network = neighbour.new_packet(Vec::new(), 1).unwrap();
+        manager.receive_packet(other_network).unwrap();
+        let not_on_path = NetworkManager::<40, 5>::new(3, 10, 3)
+            .new_packet(Vec::new(), 4)
+            .unwrap();
+        manager.receive_packet(not_on_path).unwrap();
+        let through_us = NetworkManager::<40, 5>::new(0, 10, 3)
+            .new_packet(Vec::new(), 2)
+            .unwrap();
+        manager.receive_packet(through_us).unwrap();
+
+        let counts = manager.telemetry();
+        assert_eq!(counts.forwarded, 1);
+        assert_eq!(counts.acks, 1);
+        assert_eq!(counts.retries, [1]);
+        assert_eq!(
+            counts.drops,
+            [DropReason::OtherNetwork, DropReason::NotOnPath]
+        );
+    }
+
+    #[test]
+    fn test_source_route_is_followed() {
+        let mut gw = setup_manager();
+        let pkt = gw.routed_payload_to_send(Vec::new(), 5, &[3, 4]).unwrap()[0].clone();
+
+        // Node 2 is between the gateway and 5, so it would flood it, but isn't on the route
+        let mut off_route = NetworkManager::<40, 5>::new(2, 10, 3);
+        assert_eq!(off_route.receive_packet(pkt.clone()).unwrap(), None);
+        let mut relay = NetworkManager::<40, 5>::new(3, 10, 3);
+        let (forwarded, ptype) = relay.receive_packet(pkt).unwrap().unwrap();
+        assert_eq!(ptype, PayloadType::Data);
+        assert_eq!(forwarded.hop_count, 1);
+        let mut next = NetworkManager::<40, 5>::new(4, 10, 3);
+        assert!(next.receive_packet(forwarded).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_broken_route_is_flooded() {
+        let clock = TestClock::new();
+        let mut manager = NetworkManager::<40, 5, _>::with_clock(1, 10, 3, &clock);
+        manager.routed_payload_to_send(Vec::new(), 5, &[3]).unwrap();
+        clock.advance(Duration::from_secs(11));
+        let retries = manager.due_retransmissions();
+        assert!(retries[0].0.route.is_empty());
+    }
+
+    #[test]
+    fn test_route_is_recorded_and_learned() {
+        let mut relay = NetworkManager::<40, 5>::new(3, 10, 3);
+        relay.set_record_route(true);
+        let bootup = setup_manager().handle_bootup().unwrap();
+        relay.receive_packet(bootup).unwrap();
+        let uplink = NetworkManager::<40, 5>::new(5, 10, 3)
+            .new_packet(Vec::new(), 1)
+            .unwrap();
+
+        let (forwarded, _) = relay.receive_packet(uplink).unwrap().unwrap();
+        assert_eq!(forwarded.route, [3]);
+        let mut routes = RouteTable::<4>::new();
+        routes.learn(&forwarded);
+        assert_eq!(routes.route_to(5).unwrap(), &[3]);
+        assert_eq!(routes.route_to(4), None);
+    }
+
+    #[cfg(feature = "trace-decisions")]
+    #[test]
+    fn test_decision_log() {
+        use crate::node::decisions::{Decision, DecisionRecord};
+
+        let mut manager = setup_manager();
+        let mut other = NetworkManager::<40, 5>::new(3, 10, 3);
+        let pkt = other.new_packet(Vec::from_slice(&[1]).unwrap(), 1).unwrap();
+
+        // Delivered the first time, a duplicate the second
+        manager.receive_packet(pkt.clone()).unwrap();
+        manager.receive_packet(pkt.clone()).unwrap();
+        let decisions: Vec<Decision, 5> = manager.decisions().iter().map(|r| r.decision).collect();
+        assert_eq!(decisions, [Decision::Deliver, Decision::Duplicate]);
+
+        // The payload holds the newest decision first
+        let payload = manager.decisions().to_payload::<40>();
+        let decoded: Vec<DecisionRecord, 5> = DecisionRecord::decode_all(&payload).collect();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].decision, Decision::Duplicate);
+        assert_eq!(decoded[0].source_id, 3);
+    }
 }
@@ -0,0 +1,60 @@
+/// Payloads as application types, so firmware doesn't encode and decode every packet by hand.
+/// `NetworkManager::from_t` serializes a value with postcard for `send_payload`, and
+/// `MeshRouter::receive_as` hands back what arrives decoded, or which packets weren't the type.
+use heapless::Vec;
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::{MHPacket, PacketRef, network_manager::NetworkManagerError};
+
+/// A payload decoded as the application's type, with who sent it
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Decoded<T> {
+    pub packet: PacketRef,
+    pub network_id: u8,
+    /// Our id, the broadcast id or a group address
+    pub destination_id: u8,
+    pub value: T,
+}
+
+/// A packet for us whose payload isn't the type asked for, e.g. from a node with older firmware.
+/// It was ACKed like any other, so it isn't sent again.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Undecodable {
+    pub packet: PacketRef,
+    pub error: postcard::Error,
+}
+
+/// One packet handed to the application by `MeshRouter::receive_as`
+pub type Typed<T> = Result<Decoded<T>, Undecodable>;
+
+/// `value` serialized as a payload
+pub(crate) fn encode<T: Serialize, const SIZE: usize>(
+    value: &T,
+) -> Result<Vec<u8, SIZE>, NetworkManagerError> {
+    let mut buf = [0u8; SIZE];
+    let used = postcard::to_slice(value, &mut buf)?.len();
+    Vec::from_slice(&buf[..used]).map_err(|_| NetworkManagerError::BufferFull)
+}
+
+/// The payload of `pkt` decoded as T, which has to take all of it
+pub fn decode<T: DeserializeOwned, const SIZE: usize>(pkt: &MHPacket<SIZE>) -> Typed<T> {
+    let packet = pkt.packet_ref();
+    let value = match postcard::take_from_bytes::<T>(&pkt.payload) {
+        Ok((value, [])) => value,
+        Ok(_) => {
+            return Err(Undecodable {
+                packet,
+                error: postcard::Error::DeserializeBadEncoding,
+            });
+        }
+        Err(error) => return Err(Undecodable { packet, error }),
+    };
+    Ok(Decoded {
+        packet,
+        network_id: pkt.network_id,
+        destination_id: pkt.destination_id,
+        value,
+    })
+}
@@ -2,22 +2,28 @@ use crate::node::PacketType;
 
 use super::{
     MHPacket,
+    clock::Clock,
     network_manager::{NetworkManager, NetworkManagerError},
+    telemetry::Telemetry,
 };
+use core::{fmt, str::FromStr};
 use heapless::Vec;
+use serde::{Deserialize, Serialize};
 
 pub trait RoutingPolicy<const SIZE: usize, const LEN: usize> {
     /// Takes received packets and decides what to send on (TX) and what to keep (RX)
-    fn process_packets(
-        manager: &mut NetworkManager<SIZE, LEN>,
+    fn process_packets<C: Clock, T: Telemetry, const PENDING: usize, const TX_Q: usize>(
+        &mut self,
+        manager: &mut NetworkManager<SIZE, LEN, C, T, PENDING, TX_Q>,
         pkts: Vec<MHPacket<SIZE>, LEN>,
     ) -> Result<(Vec<MHPacket<SIZE>, LEN>, Vec<MHPacket<SIZE>, LEN>), NetworkManagerError>;
 }
 
 pub struct NodePolicy;
 impl<const SIZE: usize, const LEN: usize> RoutingPolicy<SIZE, LEN> for NodePolicy {
-    fn process_packets(
-        manager: &mut NetworkManager<SIZE, LEN>,
+    fn process_packets<C: Clock, T: Telemetry, const PENDING: usize, const TX_Q: usize>(
+        &mut self,
+        manager: &mut NetworkManager<SIZE, LEN, C, T, PENDING, TX_Q>,
         pkts: Vec<MHPacket<SIZE>, LEN>,
     ) -> Result<(Vec<MHPacket<SIZE>, LEN>, Vec<MHPacket<SIZE>, LEN>), NetworkManagerError> {
         // If 1 package or multiple packets should be sent on:
@@ -27,28 +33,48 @@ impl<const SIZE: usize, const LEN: usize> RoutingPolicy<SIZE, LEN> for NodePolic
 }
 
 /// A gateway responds with an ACK to all packages, but the node application should also receive
-/// the packet as well
+/// the packet as well. Copies of a packet heard within the manager's ACK window, e.g. through
+/// several relays, are neither ACKed nor received again. ACKs of the gateway's own downlinks,
+/// and the downlinks relayed back, confirm them like on a node. Packets of the manager's
+/// `ConfirmedTypes` are received, but their ACK waits for `confirm_delivery`.
 pub struct GatewayPolicy;
 impl<const SIZE: usize, const LEN: usize> RoutingPolicy<SIZE, LEN> for GatewayPolicy {
-    fn process_packets(
-        _manager: &mut NetworkManager<SIZE, LEN>,
-        pkts: Vec<MHPacket<SIZE>, LEN>,
+    fn process_packets<C: Clock, T: Telemetry, const PENDING: usize, const TX_Q: usize>(
+        &mut self,
+        manager: &mut NetworkManager<SIZE, LEN, C, T, PENDING, TX_Q>,
+        mut pkts: Vec<MHPacket<SIZE>, LEN>,
     ) -> Result<(Vec<MHPacket<SIZE>, LEN>, Vec<MHPacket<SIZE>, LEN>), NetworkManagerError> {
+        // Packets from a neighbouring network are neither ACKed nor given to the application
+        pkts.retain(|pkt| manager.accepts(pkt));
+        pkts.retain(|pkt| !manager.confirm(pkt));
+        // Only the first copy of an uplink is ACKed and received, ACKs themselves are never ACKed.
+        // A bootup passed on by a relay carries our packet id under the relay's source id, so it
+        // mustn't take the place of the relay's own uplink with that id.
+        pkts.retain(|pkt| {
+            matches!(pkt.packet_type, PacketType::Ack | PacketType::BootUp)
+                || pkt.source_id == 0
+                || manager.first_heard(pkt)
+        });
         let to_send = pkts
             .iter()
             // Filter out GW's own ACKS
             .filter(|pkt| pkt.packet_type != PacketType::Ack && pkt.source_id != 0)
+            .filter(|pkt| !manager.hold_ack(pkt))
             .map(|pkt| {
                 // The rest of the fields don't really matter, because the pid is the first thing that
                 // NM checks
                 MHPacket {
+                    network_id: pkt.network_id,
                     destination_id: pkt.source_id,
                     source_id: pkt.destination_id,
                     packet_type: PacketType::Ack,
+                    flags: 0,
+                    max_payload: SIZE as u8,
                     payload: Vec::new(),
                     packet_id: pkt.packet_id,
                     hop_count: 0,
                     hop_to_gw: 0,
+                    route: Vec::new(),
                 }
             })
             .collect();
@@ -56,3 +82,65 @@ impl<const SIZE: usize, const LEN: usize> RoutingPolicy<SIZE, LEN> for GatewayPo
         Ok((to_send, pkts))
     }
 }
+
+/// A policy picked at runtime, e.g. from a configuration file, so one binary can run as a node
+/// or as a gateway. Firmware which is always one or the other should use `NodePolicy` or
+/// `GatewayPolicy`, which leave out the code of the other.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RuntimePolicy {
+    #[default]
+    Node,
+    Gateway,
+}
+
+impl<const SIZE: usize, const LEN: usize> RoutingPolicy<SIZE, LEN> for RuntimePolicy {
+    fn process_packets<C: Clock, T: Telemetry, const PENDING: usize, const TX_Q: usize>(
+        &mut self,
+        manager: &mut NetworkManager<SIZE, LEN, C, T, PENDING, TX_Q>,
+        pkts: Vec<MHPacket<SIZE>, LEN>,
+    ) -> Result<(Vec<MHPacket<SIZE>, LEN>, Vec<MHPacket<SIZE>, LEN>), NetworkManagerError> {
+        match self {
+            RuntimePolicy::Node => NodePolicy.process_packets(manager, pkts),
+            RuntimePolicy::Gateway => GatewayPolicy.process_packets(manager, pkts),
+        }
+    }
+}
+
+/// A name which isn't `node` or `gateway`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UnknownPolicy;
+
+impl fmt::Display for UnknownPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown routing policy, expected node or gateway")
+    }
+}
+
+impl core::error::Error for UnknownPolicy {}
+
+impl FromStr for RuntimePolicy {
+    type Err = UnknownPolicy;
+
+    /// `node` or `gateway`, in any case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("node") {
+            Ok(RuntimePolicy::Node)
+        } else if s.eq_ignore_ascii_case("gateway") {
+            Ok(RuntimePolicy::Gateway)
+        } else {
+            Err(UnknownPolicy)
+        }
+    }
+}
+
+impl fmt::Display for RuntimePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimePolicy::Node => write!(f, "node"),
+            RuntimePolicy::Gateway => write!(f, "gateway"),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+/// What a node is configured with at install time instead of having it compiled in, e.g. written
+/// from a phone to the provisioning characteristic of the MustHop GATT service. The node saves it
+/// in a `ProvisionStore` and reboots, and from then on builds its NetworkManager from the node id
+/// and applies the rest with `MeshRouter::provision`.
+use core::future::Future;
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use super::{BROADCAST_ID, config::ChannelPlan, group};
+
+/// Length of the network key
+pub const KEY_LEN: usize = 16;
+/// Longest encoding of a Provisioning: the ids, the key as is, and the longest ChannelPlan
+pub const MAX_ENCODED_LEN: usize = 2 + KEY_LEN + 9;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Provisioning {
+    pub node_id: u8,
+    pub network_id: u8,
+    /// Shared by the nodes of a network, kept for encrypting its traffic. Nothing encrypts with it
+    /// yet.
+    pub key: [u8; KEY_LEN],
+    pub plan: ChannelPlan,
+}
+
+/// Why a write to the provisioning characteristic was refused
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProvisionError {
+    /// Not a Provisioning, or with bytes left over
+    Malformed,
+    /// 0, the broadcast id, or a group address
+    InvalidNodeId,
+    /// A spreading factor or bandwidth the radio can't use
+    InvalidPlan,
+}
+
+impl Provisioning {
+    /// As the app writes it to the provisioning characteristic
+    pub fn encode(&self) -> Result<Vec<u8, MAX_ENCODED_LEN>, postcard::Error> {
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        let used = postcard::to_slice(self, &mut buf)?.len();
+        Vec::from_slice(&buf[..used]).map_err(|_| postcard::Error::SerializeBufferFull)
+    }
+
+    /// Reads a write to the provisioning characteristic, refusing one the node couldn't join a
+    /// mesh with, so it doesn't save it and reboot into a node nobody reaches
+    pub fn decode(value: &[u8]) -> Result<Self, ProvisionError> {
+        let (provisioning, rest) =
+            postcard::take_from_bytes::<Self>(value).map_err(|_| ProvisionError::Malformed)?;
+        if !rest.is_empty() {
+            return Err(ProvisionError::Malformed);
+        }
+        let node_id = provisioning.node_id;
+        if node_id == 0 || node_id == BROADCAST_ID || group::is_group(node_id) {
+            return Err(ProvisionError::InvalidNodeId);
+        }
+        let plan = &provisioning.plan;
+        if !(5..=12).contains(&plan.spreading_factor)
+            || ![125, 250, 500].contains(&plan.bandwidth_khz)
+        {
+            return Err(ProvisionError::InvalidPlan);
+        }
+        Ok(provisioning)
+    }
+}
+
+/// Somewhere to keep the provisioning across reboots, e.g. a flash page next to the one of a
+/// `ConfigStore`
+pub trait ProvisionStore {
+    type Error;
+
+    /// The saved provisioning, `None` until the node was provisioned
+    fn load_provisioning(
+        &mut self,
+    ) -> impl Future<Output = Result<Option<Provisioning>, Self::Error>>;
+
+    fn save_provisioning(
+        &mut self,
+        provisioning: &Provisioning,
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+}
@@ -0,0 +1,305 @@
+/// Sub-GHz band rules per region, from the LoRaWAN regional parameters: the frequencies a channel
+/// must stay within, the duty cycle of each sub-band, the highest EIRP and how long a frame may
+/// stay on the air. Nodes check their `TransmitParameters` against a region and cap their power
+/// to it, and `must-gw` does the same with its channel plan, so both ends follow one definition.
+/// The tables are the common case of each region, check the rules of the country a network is
+/// deployed in.
+use core::{fmt, str::FromStr};
+use embassy_time::Duration;
+
+use super::{airtime::Modulation, config::ChannelPlan};
+use crate::units::{Db, Dbm};
+
+/// A range of frequencies sharing one duty cycle
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SubBand {
+    pub min_hz: u32,
+    pub max_hz: u32,
+    /// Share of the time a sender may be on the air, in tenths of a percent. None where the
+    /// region limits dwell time or asks for listen before talk instead.
+    pub duty_cycle_permille: Option<u16>,
+}
+
+impl SubBand {
+    const fn new(min_hz: u32, max_hz: u32, duty_cycle_permille: Option<u16>) -> Self {
+        Self {
+            min_hz,
+            max_hz,
+            duty_cycle_permille,
+        }
+    }
+
+    /// Whether a channel on `frequency_hz`, `bandwidth_khz` wide, is within the sub-band
+    pub fn contains(&self, frequency_hz: u32, bandwidth_khz: u16) -> bool {
+        let half = u32::from(bandwidth_khz) * 500;
+        frequency_hz.saturating_sub(half) >= self.min_hz
+            && frequency_hz.saturating_add(half) <= self.max_hz
+    }
+}
+
+/// The rules of a region
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RegionParams {
+    pub sub_bands: &'static [SubBand],
+    /// Highest radiated power, the TX power plus the antenna gain
+    pub max_eirp: Dbm<i8>,
+    /// Longest a frame may be on the air, where the region limits it
+    pub max_dwell: Option<Duration>,
+    pub bandwidths_khz: &'static [u16],
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Region {
+    Eu868,
+    Us915,
+    Au915,
+    As923,
+    In865,
+    Kr920,
+}
+
+const EU868: RegionParams = RegionParams {
+    sub_bands: &[
+        SubBand::new(863_000_000, 865_000_000, Some(1)),
+        SubBand::new(865_000_000, 868_000_000, Some(10)),
+        SubBand::new(868_000_000, 868_600_000, Some(10)),
+        SubBand::new(868_700_000, 869_200_000, Some(1)),
+        SubBand::new(869_400_000, 869_650_000, Some(100)),
+        SubBand::new(869_700_000, 870_000_000, Some(10)),
+    ],
+    max_eirp: Dbm(16),
+    max_dwell: None,
+    bandwidths_khz: &[125, 250],
+};
+
+const US915: RegionParams = RegionParams {
+    sub_bands: &[SubBand::new(902_000_000, 928_000_000, None)],
+    max_eirp: Dbm(30),
+    max_dwell: Some(Duration::from_millis(400)),
+    bandwidths_khz: &[125, 500],
+};
+
+const AU915: RegionParams = RegionParams {
+    sub_bands: &[SubBand::new(915_000_000, 928_000_000, None)],
+    max_eirp: Dbm(30),
+    max_dwell: None,
+    bandwidths_khz: &[125, 500],
+};
+
+const AS923: RegionParams = RegionParams {
+    sub_bands: &[SubBand::new(915_000_000, 928_000_000, Some(10))],
+    max_eirp: Dbm(16),
+    max_dwell: Some(Duration::from_millis(400)),
+    bandwidths_khz: &[125, 250],
+};
+
+const IN865: RegionParams = RegionParams {
+    sub_bands: &[SubBand::new(865_000_000, 867_000_000, None)],
+    max_eirp: Dbm(30),
+    max_dwell: None,
+    bandwidths_khz: &[125],
+};
+
+const KR920: RegionParams = RegionParams {
+    sub_bands: &[SubBand::new(920_900_000, 923_300_000, None)],
+    max_eirp: Dbm(14),
+    max_dwell: None,
+    bandwidths_khz: &[125],
+};
+
+/// Settings a region doesn't allow
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RegionError {
+    /// No sub-band of the region holds the whole channel
+    Frequency {
+        frequency_hz: u32,
+        bandwidth_khz: u16,
+    },
+    Bandwidth(u16),
+    /// The TX power and antenna gain radiate more than the region allows
+    Eirp {
+        eirp: Dbm<i8>,
+        max: Dbm<i8>,
+    },
+    /// A frame of `len` bytes would stay on the air longer than the region allows
+    DwellTime {
+        len: usize,
+        airtime: Duration,
+    },
+    /// A name `Region::from_str` doesn't know
+    Unknown,
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionError::Frequency {
+                frequency_hz,
+                bandwidth_khz,
+            } => write!(
+                f,
+                "a {} kHz channel on {} Hz is outside the region's bands",
+                bandwidth_khz, frequency_hz
+            ),
+            RegionError::Bandwidth(khz) => write!(f, "{} kHz isn't allowed in the region", khz),
+            RegionError::Eirp { eirp, max } => {
+                write!(f, "{} EIRP is more than the region's {}", eirp, max)
+            }
+            RegionError::DwellTime { len, airtime } => write!(
+                f,
+                "a {} byte frame stays on the air for {} ms, longer than the region allows",
+                len,
+                airtime.as_millis()
+            ),
+            RegionError::Unknown => write!(f, "unknown region"),
+        }
+    }
+}
+
+impl core::error::Error for RegionError {}
+
+impl FromStr for Region {
+    type Err = RegionError;
+
+    /// Parses names like `EU868` or `eu868`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const NAMES: [(&str, Region); 6] = [
+            ("EU868", Region::Eu868),
+            ("US915", Region::Us915),
+            ("AU915", Region::Au915),
+            ("AS923", Region::As923),
+            ("IN865", Region::In865),
+            ("KR920", Region::Kr920),
+        ];
+        NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|(_, region)| *region)
+            .ok_or(RegionError::Unknown)
+    }
+}
+
+impl Region {
+    pub const fn params(self) -> &'static RegionParams {
+        match self {
+            Region::Eu868 => &EU868,
+            Region::Us915 => &US915,
+            Region::Au915 => &AU915,
+            Region::As923 => &AS923,
+            Region::In865 => &IN865,
+            Region::Kr920 => &KR920,
+        }
+    }
+
+    /// The sub-band holding a channel on `frequency_hz`, `bandwidth_khz` wide
+    pub fn check_channel(
+        self,
+        frequency_hz: u32,
+        bandwidth_khz: u16,
+    ) -> Result<&'static SubBand, RegionError> {
+        let params = self.params();
+        if !params.bandwidths_khz.contains(&bandwidth_khz) {
+            return Err(RegionError::Bandwidth(bandwidth_khz));
+        }
+        params
+            .sub_bands
+            .iter()
+            .find(|band| band.contains(frequency_hz, bandwidth_khz))
+            .ok_or(RegionError::Frequency {
+                frequency_hz,
+                bandwidth_khz,
+            })
+    }
+
+    /// The highest TX power allowed with an antenna of `antenna_gain`
+    pub fn max_tx_power(self, antenna_gain: Db<i8>) -> Dbm<i8> {
+        Dbm(self.params().max_eirp.0.saturating_sub(antenna_gain.0))
+    }
+
+    pub fn check_tx_power(
+        self,
+        tx_power: Dbm<i8>,
+        antenna_gain: Db<i8>,
+    ) -> Result<(), RegionError> {
+        let eirp = Dbm(tx_power.0.saturating_add(antenna_gain.0));
+        let max = self.params().max_eirp;
+        match eirp > max {
+            true => Err(RegionError::Eirp { eirp, max }),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether a frame of `len` bytes sent with `modulation` is short enough
+    pub fn check_dwell(self, modulation: Modulation, len: usize) -> Result<(), RegionError> {
+        let airtime = modulation.airtime(len);
+        match self.params().max_dwell {
+            Some(max) if airtime > max => Err(RegionError::DwellTime { len, airtime }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `plan` is allowed, with frames of up to `max_len` bytes
+    pub fn check_plan(self, plan: &ChannelPlan, max_len: usize) -> Result<(), RegionError> {
+        self.check_channel(plan.frequency_hz, plan.bandwidth_khz)?;
+        self.check_dwell(Modulation::from(*plan), max_len)
+    }
+
+    /// How long a sender has to stay off the air on `frequency_hz` after a frame of `airtime`,
+    /// zero where the sub-band has no duty cycle or the frequency is outside the region
+    pub fn off_time(self, frequency_hz: u32, airtime: Duration) -> Duration {
+        let permille = self
+            .params()
+            .sub_bands
+            .iter()
+            .find(|band| band.contains(frequency_hz, 0))
+            .and_then(|band| band.duty_cycle_permille)
+            .map(|permille| u32::from(permille.clamp(1, 1000)));
+        match permille {
+            Some(permille) => airtime * (1000 - permille) / permille,
+            None => Duration::from_ticks(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eu868_rules() {
+        let region: Region = "eu868".parse().unwrap();
+        assert_eq!(region, Region::Eu868);
+        let band = region.check_channel(868_100_000, 125).unwrap();
+        assert_eq!(band.duty_cycle_permille, Some(10));
+        // Reaches past the end of the 868.0 to 868.6 MHz sub-band
+        assert!(region.check_channel(868_550_000, 125).is_err());
+        assert_eq!(
+            region.check_channel(868_100_000, 500),
+            Err(RegionError::Bandwidth(500))
+        );
+        assert_eq!(region.max_tx_power(Db(2)), Dbm(14));
+        assert!(region.check_tx_power(Dbm(14), Db(2)).is_ok());
+        assert!(region.check_tx_power(Dbm(20), Db(0)).is_err());
+        // 1%: 99 times the airtime off the air
+        assert_eq!(
+            region.off_time(868_100_000, Duration::from_millis(50)),
+            Duration::from_millis(4950)
+        );
+    }
+
+    #[test]
+    fn test_us915_dwell_time() {
+        let region = Region::Us915;
+        assert!(region.check_dwell(Modulation::new(7, 125), 100).is_ok());
+        assert!(matches!(
+            region.check_dwell(Modulation::new(10, 125), 100),
+            Err(RegionError::DwellTime { len: 100, .. })
+        ));
+        assert_eq!(
+            region.off_time(915_000_000, Duration::from_millis(50)),
+            Duration::from_ticks(0)
+        );
+    }
+}
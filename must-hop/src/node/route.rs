@@ -0,0 +1,48 @@
+/// Source routing: instead of flooding, a packet can name the relays it should take. Packets to
+/// the gateway record the relays they passed, so the gateway learns the way back to every node.
+use heapless::Vec;
+
+use super::MHPacket;
+
+/// Most relays a route can name
+pub const MAX_ROUTE_LEN: usize = 8;
+
+/// Relays in the order the packet passes them
+pub type Route = Vec<u8, MAX_ROUTE_LEN>;
+
+/// Routes to the nodes a gateway heard from, learned from the relays recorded in their packets.
+/// Keeps N routes, forgetting the oldest first.
+#[derive(Debug, Default)]
+pub struct RouteTable<const N: usize> {
+    routes: Vec<(u8, Route), N>,
+}
+
+impl<const N: usize> RouteTable<N> {
+    pub const fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// Learns the way back to the source of `packet`, which came to us through the relays in
+    /// its route
+    pub fn learn<const SIZE: usize>(&mut self, packet: &MHPacket<SIZE>) {
+        self.forget(packet.source_id);
+        if self.routes.is_full() {
+            self.routes.remove(0);
+        }
+        let back = packet.route.iter().rev().copied().collect();
+        // Can't fail, there is room after removing one
+        let _ = self.routes.push((packet.source_id, back));
+    }
+
+    /// Relays to go through to reach `destination`, empty if it is a neighbour
+    pub fn route_to(&self, destination: u8) -> Option<&Route> {
+        self.routes
+            .iter()
+            .find(|(node, _)| *node == destination)
+            .map(|(_, route)| route)
+    }
+
+    pub fn forget(&mut self, destination: u8) {
+        self.routes.retain(|(node, _)| *node != destination);
+    }
+}
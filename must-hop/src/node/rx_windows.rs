@@ -0,0 +1,128 @@
+/// Class-A style receive windows, so a node which doesn't relay, e.g. on a battery, only listens
+/// for a short while after each uplink instead of all the time. RX1 opens `rx1_delay` after the
+/// end of the uplink, on the channel and spreading factor it went out on, and RX2 `rx2_delay`
+/// after it on the fallback spreading factor, on the channel plan's frequency. After that the
+/// radio can sleep until the next uplink. Frames sent this way carry `flags::RX_WINDOWS`, and a
+/// gateway hearing one straight from its source sends what it has for that node in the windows,
+/// timed from the end of the uplink. Both ends use the same `RxWindows`, the defaults unless
+/// both are told otherwise.
+use embassy_time::Duration;
+
+use super::{airtime::Modulation, control::SAFE_MODE_SPREADING_FACTOR};
+
+/// RX1 opens a second after the uplink, like LoRaWAN's RECEIVE_DELAY1
+pub const RX1_DELAY: Duration = Duration::from_secs(1);
+pub const RX2_DELAY: Duration = Duration::from_secs(2);
+
+/// When a node listens after an uplink
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RxWindows {
+    /// From the end of the uplink to when a downlink in RX1 starts
+    pub rx1_delay: Duration,
+    /// From the end of the uplink to when a downlink in RX2 starts
+    pub rx2_delay: Duration,
+    /// Of RX2, on the channel plan's frequency
+    pub rx2_spreading_factor: u8,
+    /// How early a window opens and how late a downlink may start in it, for clock drift
+    pub margin: Duration,
+    /// Longest downlink frame a window stays open for
+    pub max_frame_len: usize,
+}
+
+impl Default for RxWindows {
+    fn default() -> Self {
+        Self {
+            rx1_delay: RX1_DELAY,
+            rx2_delay: RX2_DELAY,
+            rx2_spreading_factor: SAFE_MODE_SPREADING_FACTOR,
+            margin: Duration::from_millis(20),
+            max_frame_len: 64,
+        }
+    }
+}
+
+impl RxWindows {
+    /// When RX1 opens after the end of the uplink, and how long it stays open with the uplink's
+    /// `modulation`. It closes once RX2 opens at the latest.
+    pub fn rx1(&self, modulation: Modulation) -> (Duration, Duration) {
+        let (opens, open_for) = self.window(self.rx1_delay, modulation);
+        let rx2_opens = self.early(self.rx2_delay);
+        let open_for = match rx2_opens.checked_sub(opens) {
+            Some(until_rx2) => open_for.min(until_rx2),
+            None => Duration::from_ticks(0),
+        };
+        (opens, open_for)
+    }
+
+    /// When RX2 opens after the end of the uplink, and how long it stays open
+    pub fn rx2(&self, bandwidth_khz: u16) -> (Duration, Duration) {
+        self.window(
+            self.rx2_delay,
+            Modulation::new(self.rx2_spreading_factor, bandwidth_khz),
+        )
+    }
+
+    /// Whether a downlink of `len` bytes, sent in RX1 with the uplink's `modulation`, is over
+    /// before RX1 closes, even if it starts `margin` late
+    pub fn fits_rx1(&self, modulation: Modulation, len: usize) -> bool {
+        self.fits(self.rx1_delay, self.rx1(modulation), modulation, len)
+    }
+
+    pub fn fits_rx2(&self, bandwidth_khz: u16, len: usize) -> bool {
+        let modulation = Modulation::new(self.rx2_spreading_factor, bandwidth_khz);
+        self.fits(self.rx2_delay, self.rx2(bandwidth_khz), modulation, len)
+    }
+
+    /// A window for downlinks starting `delay` after the uplink: from `margin` early until the
+    /// longest frame starting `margin` late is over
+    fn window(&self, delay: Duration, modulation: Modulation) -> (Duration, Duration) {
+        let opens = self.early(delay);
+        let closes = delay + self.margin + modulation.airtime(self.max_frame_len);
+        (opens, closes - opens)
+    }
+
+    fn fits(
+        &self,
+        delay: Duration,
+        (opens, open_for): (Duration, Duration),
+        modulation: Modulation,
+        len: usize,
+    ) -> bool {
+        delay + self.margin + modulation.airtime(len) <= opens + open_for
+    }
+
+    fn early(&self, delay: Duration) -> Duration {
+        delay
+            .checked_sub(self.margin)
+            .unwrap_or(Duration::from_ticks(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_fit_short_downlinks() {
+        let windows = RxWindows::default();
+        let fast = Modulation::new(7, 125);
+        let (opens, open_for) = windows.rx1(fast);
+        assert_eq!(opens, Duration::from_millis(980));
+        assert!(opens + open_for <= windows.rx2(125).0);
+        assert!(windows.fits_rx1(fast, 64));
+        assert!(!windows.fits_rx1(fast, 200));
+        assert!(windows.fits_rx2(125, 64));
+        assert!(!windows.fits_rx2(125, 100));
+    }
+
+    #[test]
+    fn test_slow_rx1_closes_when_rx2_opens() {
+        let windows = RxWindows::default();
+        let slow = Modulation::new(12, 125);
+        let (opens, open_for) = windows.rx1(slow);
+        assert_eq!(opens + open_for, windows.rx2(125).0);
+        // A second on the air at SF12, it would still be on when RX2 opens
+        assert!(!windows.fits_rx1(slow, 10));
+        assert!(windows.fits_rx2(125, 10));
+    }
+}
@@ -0,0 +1,90 @@
+/// Channel scanning, so a node with a single radio can take part in a gateway's multi-channel plan
+/// without each node being given a channel of its own. The node takes turns listening on every
+/// channel of a `ChannelScan`, dwelling on each for its share of the cycle, by how much traffic
+/// is expected there. On radios with channel activity detection (`MHNode::channel_activity`) a
+/// quiet channel is left after the detection instead of the whole dwell, and one with a preamble
+/// on it is listened on until the frame is in. Frames are only heard while the node is on their
+/// channel, so senders should use a preamble about as long as the time a scan takes.
+use embassy_time::Duration;
+use heapless::Vec;
+
+use super::config::ChannelPlan;
+
+/// Most channels a node scans, as many as a concentrator listens on
+pub const MAX_SCAN_CHANNELS: usize = 8;
+
+/// A channel of the gateway's plan
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanChannel {
+    /// Center frequency in Hz
+    pub frequency_hz: u32,
+    /// Traffic expected on the channel relative to the others, 0 leaves it out of the scan
+    pub weight: u8,
+}
+
+/// The channels a node scans, and where it is in the scan
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChannelScan {
+    channels: Vec<ScanChannel, MAX_SCAN_CHANNELS>,
+    /// How long one pass over every channel takes without channel activity detection
+    cycle: Duration,
+    /// Of the channel listened on now
+    current: usize,
+}
+
+impl ChannelScan {
+    /// Scans `channels` once every `cycle`. None if there are more than `MAX_SCAN_CHANNELS`, or
+    /// none of them has a weight.
+    pub fn new(channels: &[ScanChannel], cycle: Duration) -> Option<Self> {
+        let channels = Vec::from_slice(channels).ok()?;
+        let current = channels.iter().position(|channel| channel.weight > 0)?;
+        Some(Self {
+            channels,
+            cycle,
+            current,
+        })
+    }
+
+    pub fn channels(&self) -> &[ScanChannel] {
+        &self.channels
+    }
+
+    pub fn cycle(&self) -> Duration {
+        self.cycle
+    }
+
+    /// The channel listened on now
+    pub fn current(&self) -> ScanChannel {
+        self.channels[self.current]
+    }
+
+    /// How long to listen on the current channel, its share of the cycle
+    pub fn dwell(&self) -> Duration {
+        let total: u64 = self
+            .channels
+            .iter()
+            .map(|channel| u64::from(channel.weight))
+            .sum();
+        let weight = u64::from(self.current().weight);
+        Duration::from_ticks(self.cycle.as_ticks() * weight / total)
+    }
+
+    /// Moves on to the next channel with a weight, back to the first after the last
+    pub fn advance(&mut self) {
+        for _ in 0..self.channels.len() {
+            self.current = (self.current + 1) % self.channels.len();
+            if self.current().weight > 0 {
+                return;
+            }
+        }
+    }
+
+    /// `plan` on the current channel, keeping its data rate
+    pub fn on_current(&self, plan: &ChannelPlan) -> ChannelPlan {
+        ChannelPlan {
+            frequency_hz: self.current().frequency_hz,
+            ..*plan
+        }
+    }
+}
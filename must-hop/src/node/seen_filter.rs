@@ -0,0 +1,194 @@
+/// Remembers which packets a busy relay has seen, e.g. one next to the gateway overhearing
+/// hundreds of nodes, in constant memory and time. `RecentSeen` scans its ring on every lookup
+/// and forgets a packet after LEN others, which near the gateway is sooner than retries arrive.
+///
+/// A `SeenFilter` is a time wheel of BUCKETS Bloom filters of WORDS * 32 bits each. Packets go
+/// into the current bucket, and every `span` the oldest bucket is cleared and becomes the current
+/// one, so a packet is remembered for between (BUCKETS - 1) and BUCKETS spans. A lookup may
+/// wrongly find a packet it never saw, dropping it as a duplicate, but never misses one it did:
+/// `false_positive_rate` tells how likely that is with what the filter holds now. With `n`
+/// packets a span, a bucket of `m` bits and `k` hashes, each bucket adds about
+/// `(1 - e^(-k * n / m))^k`, e.g. 0.7% for 100 packets in 1024 bits with 7 hashes.
+use embassy_time::{Duration, Instant};
+
+use super::network_manager::RecentSeen;
+
+/// Something remembering (source_id, packet_id) pairs, which the manager checks for duplicates
+/// besides its own `RecentSeen`
+pub trait SeenSet {
+    fn contains(&self, pid: (u8, u16), now: Instant) -> bool;
+    fn insert(&mut self, pid: (u8, u16), now: Instant);
+}
+
+impl<const N: usize> SeenSet for RecentSeen<N> {
+    fn contains(&self, pid: (u8, u16), _now: Instant) -> bool {
+        RecentSeen::contains(self, pid)
+    }
+
+    fn insert(&mut self, pid: (u8, u16), _now: Instant) {
+        self.push(pid);
+    }
+}
+
+pub struct SeenFilter<const BUCKETS: usize, const WORDS: usize> {
+    bits: [[u32; WORDS]; BUCKETS],
+    /// When each bucket became the current one
+    started: [Option<Instant>; BUCKETS],
+    current: usize,
+    span: Duration,
+    hashes: u8,
+}
+
+impl<const BUCKETS: usize, const WORDS: usize> SeenFilter<BUCKETS, WORDS> {
+    /// Bytes the filter takes, which BUCKETS and WORDS decide
+    pub const MEMORY: usize = BUCKETS * WORDS * 4;
+
+    /// Each bucket takes packets for `span`, and a packet sets `hashes` bits in it. More hashes
+    /// cut false positives while a bucket is sparse, fewer once it fills up: `m / n * ln 2` is
+    /// best for `n` packets a span in `m` bits.
+    pub const fn new(span: Duration, hashes: u8) -> Self {
+        const {
+            assert!(
+                BUCKETS > 1,
+                "BUCKETS must be at least 2, one to fill and one to remember"
+            );
+            assert!(WORDS > 0, "WORDS must be at least 1");
+        }
+        Self {
+            bits: [[0; WORDS]; BUCKETS],
+            started: [None; BUCKETS],
+            current: 0,
+            span,
+            hashes: if hashes == 0 { 1 } else { hashes },
+        }
+    }
+
+    /// The chance that a packet never seen is taken for a duplicate, from how full the buckets
+    /// still remembered at `now` are
+    pub fn false_positive_rate(&self, now: Instant) -> f32 {
+        let bits = (WORDS * 32) as f32;
+        let mut all_clear = 1.0;
+        for bucket in self.live(now) {
+            let fill = self.bits[bucket]
+                .iter()
+                .map(|word| word.count_ones())
+                .sum::<u32>() as f32
+                / bits;
+            let mut false_positive = 1.0;
+            for _ in 0..self.hashes {
+                false_positive *= fill;
+            }
+            all_clear *= 1.0 - false_positive;
+        }
+        1.0 - all_clear
+    }
+
+    /// Buckets which aren't older than the wheel, with what they hold still remembered
+    fn live(&self, now: Instant) -> impl Iterator<Item = usize> + '_ {
+        let horizon = self.span * BUCKETS as u32;
+        (0..BUCKETS).filter(move |bucket| {
+            self.started[*bucket]
+                .is_some_and(|started| now.saturating_duration_since(started) < horizon)
+        })
+    }
+
+    /// Moves on to a fresh bucket for every span passed since the current one started
+    fn rotate(&mut self, now: Instant) {
+        let Some(mut started) = self.started[self.current] else {
+            self.started[self.current] = Some(now);
+            return;
+        };
+        // After a long quiet spell everything is forgotten at once
+        if now.saturating_duration_since(started) >= self.span * BUCKETS as u32 {
+            self.bits = [[0; WORDS]; BUCKETS];
+            self.started = [None; BUCKETS];
+            self.started[self.current] = Some(now);
+            return;
+        }
+        while now.saturating_duration_since(started) >= self.span {
+            started += self.span;
+            self.current = (self.current + 1) % BUCKETS;
+            self.bits[self.current] = [0; WORDS];
+            self.started[self.current] = Some(started);
+        }
+    }
+
+    /// The bits `pid` sets, by double hashing
+    fn indices(&self, pid: (u8, u16)) -> impl Iterator<Item = usize> + use<BUCKETS, WORDS> {
+        let key = (u32::from(pid.0) << 16) | u32::from(pid.1);
+        let first = mix(key);
+        let step = mix(key ^ 0x9E37_79B9) | 1;
+        let bits = (WORDS * 32) as u32;
+        (0..u32::from(self.hashes))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(step)) % bits) as usize)
+    }
+}
+
+impl<const BUCKETS: usize, const WORDS: usize> SeenSet for SeenFilter<BUCKETS, WORDS> {
+    fn contains(&self, pid: (u8, u16), now: Instant) -> bool {
+        self.live(now).any(|bucket| {
+            self.indices(pid)
+                .all(|bit| self.bits[bucket][bit / 32] & (1 << (bit % 32)) != 0)
+        })
+    }
+
+    fn insert(&mut self, pid: (u8, u16), now: Instant) {
+        self.rotate(now);
+        for bit in self.indices(pid) {
+            self.bits[self.current][bit / 32] |= 1 << (bit % 32);
+        }
+    }
+}
+
+/// MurmurHash3's finalizer, spreading the few bits a packet id differs in over the whole word
+fn mix(mut key: u32) -> u32 {
+    key ^= key >> 16;
+    key = key.wrapping_mul(0x85EB_CA6B);
+    key ^= key >> 13;
+    key = key.wrapping_mul(0xC2B2_AE35);
+    key ^ (key >> 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_remembers_more_than_a_ring() {
+        let start = Instant::from_secs(0);
+        let mut filter = SeenFilter::<4, 64>::new(Duration::from_secs(10), 5);
+        let mut ring = RecentSeen::<5>::new();
+        for packet_id in 0..200 {
+            filter.insert((9, packet_id), start);
+            SeenSet::insert(&mut ring, (9, packet_id), start);
+        }
+        // Never a false negative, and the ring forgot all but the last LEN
+        assert!((0..200).all(|packet_id| filter.contains((9, packet_id), start)));
+        assert!(!SeenSet::contains(&ring, (9, 0), start));
+        // Packets never seen are rarely taken for duplicates
+        let false_positives = (1000..2000)
+            .filter(|packet_id| filter.contains((9, *packet_id), start))
+            .count();
+        assert!(false_positives < 100, "{false_positives} false positives");
+        assert!(filter.false_positive_rate(start) < 0.1);
+    }
+
+    #[test]
+    fn test_filter_forgets_after_the_wheel_turned() {
+        let start = Instant::from_secs(0);
+        let mut filter = SeenFilter::<3, 8>::new(Duration::from_secs(10), 3);
+        filter.insert((2, 1), start);
+        // Still remembered while its bucket is one of the last three
+        let later = start + Duration::from_secs(25);
+        filter.insert((2, 2), later);
+        assert!(filter.contains((2, 1), later));
+        let much_later = start + Duration::from_secs(31);
+        filter.insert((2, 3), much_later);
+        assert!(!filter.contains((2, 1), much_later));
+        assert!(filter.contains((2, 2), much_later));
+        // A long quiet spell forgets everything
+        let quiet = much_later + Duration::from_secs(60);
+        assert!(!filter.contains((2, 3), quiet));
+        assert_eq!(filter.false_positive_rate(quiet), 0.0);
+    }
+}
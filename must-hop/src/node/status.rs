@@ -0,0 +1,56 @@
+/// A node's state in the mesh at a glance, e.g. for a phone app inspecting a node in the field.
+/// The MustHop GATT service on BLE nodes exposes it, with one characteristic per field and one
+/// to inject a payload, under the UUIDs here so firmware and apps agree on them.
+use serde::{Deserialize, Serialize};
+
+use super::PreambleStats;
+use crate::units::Dbm;
+
+/// The MustHop GATT service
+pub const SERVICE_UUID: &str = "6d750001-7374-4f68-6f70-6d6573680000";
+/// `u8`, the node's id
+pub const NODE_ID_UUID: &str = "6d750002-7374-4f68-6f70-6d6573680000";
+/// `u8`, hops to the gateway, 255 while none is known
+pub const GW_HOPS_UUID: &str = "6d750003-7374-4f68-6f70-6d6573680000";
+/// `u8`, packets waiting for an ACK
+pub const PENDING_UUID: &str = "6d750004-7374-4f68-6f70-6d6573680000";
+/// `i16` little endian, RSSI of the last frame in dBm, `i16::MIN` while none was heard
+pub const LAST_RSSI_UUID: &str = "6d750005-7374-4f68-6f70-6d6573680000";
+/// `u8`, payloads handed to the router but not sent yet
+pub const QUEUE_DEPTH_UUID: &str = "6d750006-7374-4f68-6f70-6d6573680000";
+/// Written by the app: the destination id, then the payload to send to it
+pub const INJECT_UUID: &str = "6d750007-7374-4f68-6f70-6d6573680000";
+/// Written by the app at install time: an encoded `Provisioning`, after which the node saves it
+/// and reboots
+pub const PROVISION_UUID: &str = "6d750008-7374-4f68-6f70-6d6573680000";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MeshStatus {
+    pub node_id: u8,
+    /// `u8::MAX` while no gateway is known
+    pub gw_hops: u8,
+    /// Packets waiting for an ACK
+    pub pending: u8,
+    /// RSSI of the last frame heard
+    pub last_rssi: Option<Dbm<i16>>,
+    /// Payloads handed to the router, e.g. through a `MeshHandle`, not sent yet
+    pub queue_depth: u8,
+    /// Preambles which led to nothing, on radios which count them, to find noisy sites
+    pub preamble: Option<PreambleStats>,
+}
+
+impl MeshStatus {
+    /// `last_rssi` as the characteristic holds it
+    pub fn last_rssi_or_min(&self) -> i16 {
+        self.last_rssi.map_or(i16::MIN, |rssi| rssi.0)
+    }
+}
+
+/// Splits a write to the inject characteristic into the destination and the payload. An empty
+/// write is `None`, a destination without a payload is sent as is.
+pub fn parse_inject(value: &[u8]) -> Option<(u8, &[u8])> {
+    value
+        .split_first()
+        .map(|(destination, payload)| (*destination, payload))
+}
@@ -0,0 +1,71 @@
+/// Store-and-forward: payloads sent while no gateway is known are kept until a bootup shows a
+/// path to one, instead of being retried into the void and dropped
+use core::future::Future;
+use heapless::{Deque, Vec};
+use serde::{Deserialize, Serialize};
+
+/// A payload waiting for a gateway
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StoredPayload<const SIZE: usize> {
+    pub destination: u8,
+    pub payload: Vec<u8, SIZE>,
+}
+
+/// Where stored payloads are kept, oldest first. On a node which may lose power before it meets a
+/// gateway this should be flash, otherwise a `MemoryPacketStore` does.
+pub trait PacketStore<const SIZE: usize> {
+    type Error;
+
+    /// Keeps `stored` after the others, or errors if the store is full
+    fn push(
+        &mut self,
+        stored: StoredPayload<SIZE>,
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// The oldest payload, without removing it
+    fn peek(&mut self) -> impl Future<Output = Result<Option<StoredPayload<SIZE>>, Self::Error>>;
+
+    /// Removes the oldest payload, once it was sent
+    fn pop(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// Keeps up to N payloads in RAM
+#[derive(Debug, Default)]
+pub struct MemoryPacketStore<const SIZE: usize, const N: usize> {
+    payloads: Deque<StoredPayload<SIZE>, N>,
+}
+
+impl<const SIZE: usize, const N: usize> MemoryPacketStore<SIZE, N> {
+    pub const fn new() -> Self {
+        Self {
+            payloads: Deque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+}
+
+impl<const SIZE: usize, const N: usize> PacketStore<SIZE> for MemoryPacketStore<SIZE, N> {
+    /// The store was full
+    type Error = ();
+
+    async fn push(&mut self, stored: StoredPayload<SIZE>) -> Result<(), ()> {
+        self.payloads.push_back(stored).map_err(|_| ())
+    }
+
+    async fn peek(&mut self) -> Result<Option<StoredPayload<SIZE>>, ()> {
+        Ok(self.payloads.front().cloned())
+    }
+
+    async fn pop(&mut self) -> Result<(), ()> {
+        self.payloads.pop_front();
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+/// Hooks called as packets move through the mesh, so firmware can keep its own counters, toggle a
+/// GPIO for a logic analyzer or log with defmt. Every hook does nothing by default.
+use embassy_time::Duration;
+use serde::{Deserialize, Serialize};
+
+use super::PacketRef;
+
+/// Buckets of a `ListenHistogram`
+pub const LISTEN_BUCKETS: usize = 12;
+/// Upper bound of the first bucket, each next one doubles it
+const FIRST_BUCKET_MS: u64 = 10;
+
+/// Why a packet was dropped
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DropReason {
+    /// From another network, and we aren't promiscuous
+    OtherNetwork,
+    /// A bootup over a path no shorter than the one we know
+    StaleBootup,
+    /// Seen before. It may still be ACKed again
+    Duplicate,
+    /// Not for us, and we aren't between its source and destination
+    NotOnPath,
+    /// Our own packet heard again, or an ACK meant for another hop
+    Ignored,
+    /// No ACK came after the last retry
+    RetriesExhausted,
+    /// No room to send it
+    BufferFull,
+    /// Delivered and ACKed, but its payload isn't the type the application asked for
+    Undecodable,
+}
+
+pub trait Telemetry {
+    /// A packet was handed to the radio, whether ours, forwarded, an ACK or a retry
+    fn packet_sent(&mut self, _packet: PacketRef) {}
+
+    /// A packet from another node will be sent on
+    fn packet_forwarded(&mut self, _packet: PacketRef) {}
+
+    /// One of our pending packets was confirmed
+    fn ack_received(&mut self, _packet: PacketRef) {}
+
+    /// A pending packet timed out, and is sent for the `retries`th time
+    fn retry(&mut self, _packet: PacketRef, _retries: u8) {}
+
+    /// A packet was dropped, and why
+    fn packet_dropped(&mut self, _packet: PacketRef, _reason: DropReason) {}
+
+    /// The radio listened for `waited`, and `heard` a frame or gave up, e.g. to tune receive
+    /// windows with a `ListenHistogram`
+    fn listened(&mut self, _waited: Duration, _heard: bool) {}
+}
+
+/// Ignores everything, used by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoTelemetry;
+
+impl Telemetry for NoTelemetry {}
+
+/// How long listens lasted, split by whether a frame arrived or the radio gave up. Bucket `i`
+/// holds listens up to `bucket_limit(i)`, the last one everything longer. Serializable, so a
+/// node can send it to the gateway with its reports.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ListenHistogram {
+    pub heard: [u16; LISTEN_BUCKETS],
+    pub timed_out: [u16; LISTEN_BUCKETS],
+}
+
+impl ListenHistogram {
+    /// Upper bound of bucket `i`: 10 ms, 20 ms, 40 ms and so on up to about 10 s
+    pub const fn bucket_limit(i: usize) -> Duration {
+        Duration::from_millis(FIRST_BUCKET_MS << i)
+    }
+
+    pub fn record(&mut self, waited: Duration, heard: bool) {
+        let bucket = (0..LISTEN_BUCKETS - 1)
+            .find(|&i| waited <= Self::bucket_limit(i))
+            .unwrap_or(LISTEN_BUCKETS - 1);
+        let counts = match heard {
+            true => &mut self.heard,
+            false => &mut self.timed_out,
+        };
+        counts[bucket] = counts[bucket].saturating_add(1);
+    }
+
+    /// Listens recorded, with a frame and without
+    pub fn total(&self) -> (u32, u32) {
+        let sum = |counts: &[u16]| -> u32 { counts.iter().map(|&n| n as u32).sum() };
+        (sum(&self.heard), sum(&self.timed_out))
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Telemetry for ListenHistogram {
+    fn listened(&mut self, waited: Duration, heard: bool) {
+        self.record(waited, heard);
+    }
+}
@@ -0,0 +1,264 @@
+/// Golden frames of the on-air format, which both the firmware's and the gateway's tests decode.
+/// Nodes and gateways are built with different SIZEs and LENs, e.g. 40 on a node and 128 on
+/// `must-gw`, and each side's tests alone would only check that it reads what it wrote itself.
+/// Every vector is a frame as `MHNode::transmit` sends it, with the packets it holds. A packet
+/// whose payload is larger than the decoder's SIZE must fail with `PayloadTooLarge`, the others
+/// must decode to exactly these packets and encode back to exactly these bytes.
+///
+/// Each vector names its version as a number rather than `WIRE_VERSION`, so changing the version
+/// fails the tests until the vectors are changed too. When it changes, the current vectors move to
+/// `OLD_VECTORS`, which must be rejected with `WrongVersion`, and new ones are added for what the
+/// new version changed.
+use super::{BROADCAST_ID, MHPacketView, PacketType, flags};
+
+/// A frame and the packets in it
+#[derive(Debug, Clone, Copy)]
+pub struct Vector {
+    pub name: &'static str,
+    /// Wire version the frame is in
+    pub version: u8,
+    pub frame: &'static [u8],
+    pub packets: &'static [MHPacketView<'static>],
+}
+
+/// One vector per packet type, and for the framing around the payload, route and header
+pub const VECTORS: &[Vector] = &[
+    DATA_TO_GATEWAY,
+    ACK,
+    BOOTUP,
+    LINK_TEST,
+    ROUTED_CONTROL,
+    SEVERAL_PACKETS,
+    LARGEST_NODE_PAYLOAD,
+    LARGE_GATEWAY_PAYLOAD,
+];
+
+/// Frames of earlier versions, which must be rejected
+pub const OLD_VECTORS: &[Vector] = &[DATA_TO_GATEWAY_V6];
+
+/// A node's data to the gateway through two relays, which recorded the route
+pub const DATA_TO_GATEWAY: Vector = Vector {
+    name: "data to gateway",
+    version: 7,
+    frame: &[
+        0x07, 0x01, 0x00, 0x01, 0x00, 0x00, 0xac, 0x02, 0x05, 0x28, 0x04, 0xde, 0xad, 0xbe, 0xef,
+        0x02, 0x02, 0x02, 0x03, 0x04,
+    ],
+    packets: &[MHPacketView {
+        network_id: 0,
+        destination_id: 1,
+        packet_type: PacketType::Data,
+        flags: 0,
+        packet_id: 300,
+        source_id: 5,
+        max_payload: 40,
+        payload: &[0xde, 0xad, 0xbe, 0xef],
+        hop_count: 2,
+        hop_to_gw: 2,
+        route: &[3, 4],
+    }],
+};
+
+/// `DATA_TO_GATEWAY` as sent before frames started with the version, which decodes as version 1
+pub const DATA_TO_GATEWAY_V6: Vector = Vector {
+    name: "data to gateway, version 6",
+    version: 6,
+    frame: &[
+        0x01, 0x00, 0x01, 0x00, 0x00, 0xac, 0x02, 0x05, 0x28, 0x04, 0xde, 0xad, 0xbe, 0xef, 0x02,
+        0x02, 0x02, 0x03, 0x04,
+    ],
+    packets: DATA_TO_GATEWAY.packets,
+};
+
+/// The gateway's ACK, which doesn't carry `hop_to_gw`
+pub const ACK: Vector = Vector {
+    name: "ack",
+    version: 7,
+    frame: &[
+        0x07, 0x01, 0x00, 0x05, 0x01, 0x00, 0x01, 0x01, 0x80, 0x01, 0x01, 0x00, 0x00,
+    ],
+    packets: &[MHPacketView {
+        network_id: 0,
+        destination_id: 5,
+        packet_type: PacketType::Ack,
+        flags: 0,
+        packet_id: 1,
+        source_id: 1,
+        max_payload: 128,
+        payload: &[0x01],
+        hop_count: 0,
+        hop_to_gw: u8::MAX,
+        route: &[],
+    }],
+};
+
+/// A bootup, broadcast
+pub const BOOTUP: Vector = Vector {
+    name: "bootup",
+    version: 7,
+    frame: &[
+        0x07, 0x01, 0x00, 0xff, 0x02, 0x00, 0x07, 0x01, 0x80, 0x03, 0x01, 0x02, 0x03, 0x00, 0x00,
+    ],
+    packets: &[MHPacketView {
+        network_id: 0,
+        destination_id: BROADCAST_ID,
+        packet_type: PacketType::BootUp,
+        flags: 0,
+        packet_id: 7,
+        source_id: 1,
+        max_payload: 128,
+        payload: &[0x01, 0x02, 0x03],
+        hop_count: 0,
+        hop_to_gw: u8::MAX,
+        route: &[],
+    }],
+};
+
+/// A link test between neighbours
+pub const LINK_TEST: Vector = Vector {
+    name: "link test",
+    version: 7,
+    frame: &[
+        0x07, 0x01, 0x00, 0x03, 0x03, 0x00, 0x09, 0x02, 0x28, 0x02, 0x00, 0x07, 0x00, 0x00,
+    ],
+    packets: &[MHPacketView {
+        network_id: 0,
+        destination_id: 3,
+        packet_type: PacketType::LinkTest,
+        flags: 0,
+        packet_id: 9,
+        source_id: 2,
+        max_payload: 40,
+        payload: &[0x00, 0x07],
+        hop_count: 0,
+        hop_to_gw: u8::MAX,
+        route: &[],
+    }],
+};
+
+/// A priority control packet the gateway source routes through two relays
+pub const ROUTED_CONTROL: Vector = Vector {
+    name: "routed control",
+    version: 7,
+    frame: &[
+        0x07, 0x01, 0x00, 0x04, 0x04, 0x01, 0xc8, 0x01, 0x01, 0x80, 0x01, 0x00, 0x00, 0x02, 0x02,
+        0x03,
+    ],
+    packets: &[MHPacketView {
+        network_id: 0,
+        destination_id: 4,
+        packet_type: PacketType::Control,
+        flags: flags::PRIORITY,
+        packet_id: 200,
+        source_id: 1,
+        max_payload: 128,
+        payload: &[0x00],
+        hop_count: 0,
+        hop_to_gw: u8::MAX,
+        route: &[2, 3],
+    }],
+};
+
+/// Two packets of another network in one frame, the first with a flag bit no version knows yet
+pub const SEVERAL_PACKETS: Vector = Vector {
+    name: "several packets",
+    version: 7,
+    frame: &[
+        0x07, 0x02, 0x03, 0x01, 0x00, 0x81, 0x01, 0x02, 0x28, 0x01, 0x11, 0x00, 0x01, 0x00, 0x03,
+        0x09, 0x00, 0x00, 0x02, 0x02, 0x28, 0x00, 0x00, 0x00,
+    ],
+    packets: &[
+        MHPacketView {
+            network_id: 3,
+            destination_id: 1,
+            packet_type: PacketType::Data,
+            flags: flags::PRIORITY | 1 << 7,
+            packet_id: 1,
+            source_id: 2,
+            max_payload: 40,
+            payload: &[0x11],
+            hop_count: 0,
+            hop_to_gw: 1,
+            route: &[],
+        },
+        MHPacketView {
+            network_id: 3,
+            destination_id: 9,
+            packet_type: PacketType::Data,
+            flags: 0,
+            packet_id: 2,
+            source_id: 2,
+            max_payload: 40,
+            payload: &[],
+            hop_count: 0,
+            hop_to_gw: u8::MAX,
+            route: &[],
+        },
+    ],
+};
+
+/// 0, 1, 2 up to 39
+const LARGEST_PAYLOAD: [u8; 40] = {
+    let mut payload = [0; 40];
+    let mut i = 0;
+    while i < payload.len() {
+        payload[i] = i as u8;
+        i += 1;
+    }
+    payload
+};
+
+/// The largest payload of a node built with SIZE 40, with a packet id taking two bytes
+pub const LARGEST_NODE_PAYLOAD: Vector = Vector {
+    name: "largest node payload",
+    version: 7,
+    frame: &[
+        0x07, 0x01, 0x00, 0x01, 0x00, 0x00, 0xb4, 0x24, 0x06, 0x28, 0x28, 0x00, 0x01, 0x02, 0x03,
+        0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12,
+        0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21,
+        0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x01, 0x01, 0x01, 0x07,
+    ],
+    packets: &[MHPacketView {
+        network_id: 0,
+        destination_id: 1,
+        packet_type: PacketType::Data,
+        flags: 0,
+        packet_id: 0x1234,
+        source_id: 6,
+        max_payload: 40,
+        payload: &LARGEST_PAYLOAD,
+        hop_count: 1,
+        hop_to_gw: 1,
+        route: &[7],
+    }],
+};
+
+/// A payload of a gateway built with SIZE 128, too large for a node built with SIZE 40, with a
+/// packet id taking three bytes
+pub const LARGE_GATEWAY_PAYLOAD: Vector = Vector {
+    name: "large gateway payload",
+    version: 7,
+    frame: &[
+        0x07, 0x01, 0x00, 0x06, 0x00, 0x00, 0x80, 0x80, 0x01, 0x01, 0x80, 0x64, 0x5a, 0x5a, 0x5a,
+        0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a,
+        0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a,
+        0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a,
+        0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a,
+        0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a,
+        0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a,
+        0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x00, 0x00,
+    ],
+    packets: &[MHPacketView {
+        network_id: 0,
+        destination_id: 6,
+        packet_type: PacketType::Data,
+        flags: 0,
+        packet_id: 1 << 14,
+        source_id: 1,
+        max_payload: 128,
+        payload: &[0x5a; 100],
+        hop_count: 0,
+        hop_to_gw: u8::MAX,
+        route: &[],
+    }],
+};
@@ -0,0 +1,173 @@
+/// How MHPackets are laid out on the air. Fields are sent in order like the derived postcard
+/// encoding, except `hop_to_gw`, which is only sent on packets to the gateway. Every other packet
+/// is a byte shorter, and decodes with `hop_to_gw` as `u8::MAX`, like an unknown gateway.
+/// `flags` is decoded with all its bits, also the ones this version doesn't know.
+use core::{fmt, marker::PhantomData};
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeStruct,
+};
+
+use super::{MHPacket, MHPacketView, PacketType};
+
+const FIELDS: &[&str] = &[
+    "network_id",
+    "destination_id",
+    "packet_type",
+    "flags",
+    "packet_id",
+    "source_id",
+    "max_payload",
+    "payload",
+    "hop_count",
+    "hop_to_gw",
+    "route",
+];
+
+/// Only relays of packets to the gateway compare their distance to it with the sender's
+fn carries_hop_to_gw(destination_id: u8) -> bool {
+    destination_id == 1
+}
+
+impl<const SIZE: usize> Serialize for MHPacket<SIZE> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let with_hops = carries_hop_to_gw(self.destination_id);
+        let len = if with_hops {
+            FIELDS.len()
+        } else {
+            FIELDS.len() - 1
+        };
+        let mut s = serializer.serialize_struct("MHPacket", len)?;
+        s.serialize_field("network_id", &self.network_id)?;
+        s.serialize_field("destination_id", &self.destination_id)?;
+        s.serialize_field("packet_type", &self.packet_type)?;
+        s.serialize_field("flags", &self.flags)?;
+        s.serialize_field("packet_id", &self.packet_id)?;
+        s.serialize_field("source_id", &self.source_id)?;
+        s.serialize_field("max_payload", &self.max_payload)?;
+        s.serialize_field("payload", &self.payload)?;
+        s.serialize_field("hop_count", &self.hop_count)?;
+        if with_hops {
+            s.serialize_field("hop_to_gw", &self.hop_to_gw)?;
+        } else {
+            s.skip_field("hop_to_gw")?;
+        }
+        s.serialize_field("route", &self.route)?;
+        s.end()
+    }
+}
+
+/// A packet as decoded, with the payload and route either owned or borrowed from the frame
+struct Fields<P, R> {
+    network_id: u8,
+    destination_id: u8,
+    packet_type: PacketType,
+    flags: u8,
+    packet_id: u16,
+    source_id: u8,
+    max_payload: u8,
+    payload: P,
+    hop_count: u8,
+    hop_to_gw: u8,
+    route: R,
+}
+
+impl<'de, P: Deserialize<'de>, R: Deserialize<'de>> Fields<P, R> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("MHPacket", FIELDS, FieldsVisitor(PhantomData))
+    }
+}
+
+struct FieldsVisitor<P, R>(PhantomData<(P, R)>);
+
+/// The next field of the packet, the `index`th
+fn next<'de, T: Deserialize<'de>, A: SeqAccess<'de>>(
+    seq: &mut A,
+    index: &mut usize,
+) -> Result<T, A::Error> {
+    let value = seq
+        .next_element()?
+        .ok_or_else(|| de::Error::invalid_length(*index, &"an MHPacket"))?;
+    *index += 1;
+    Ok(value)
+}
+
+impl<'de, P: Deserialize<'de>, R: Deserialize<'de>> Visitor<'de> for FieldsVisitor<P, R> {
+    type Value = Fields<P, R>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an MHPacket")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut i = 0;
+        let network_id = next(&mut seq, &mut i)?;
+        let destination_id = next(&mut seq, &mut i)?;
+        let packet_type = next(&mut seq, &mut i)?;
+        let flags = next(&mut seq, &mut i)?;
+        let packet_id = next(&mut seq, &mut i)?;
+        let source_id = next(&mut seq, &mut i)?;
+        let max_payload = next(&mut seq, &mut i)?;
+        let payload = next(&mut seq, &mut i)?;
+        let hop_count = next(&mut seq, &mut i)?;
+        let hop_to_gw = if carries_hop_to_gw(destination_id) {
+            next(&mut seq, &mut i)?
+        } else {
+            u8::MAX
+        };
+        let route = next(&mut seq, &mut i)?;
+        Ok(Fields {
+            network_id,
+            destination_id,
+            packet_type,
+            flags,
+            packet_id,
+            source_id,
+            max_payload,
+            payload,
+            hop_count,
+            hop_to_gw,
+            route,
+        })
+    }
+}
+
+impl<'de, const SIZE: usize> Deserialize<'de> for MHPacket<SIZE> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let f = Fields::deserialize(deserializer)?;
+        Ok(MHPacket {
+            network_id: f.network_id,
+            destination_id: f.destination_id,
+            packet_type: f.packet_type,
+            flags: f.flags,
+            packet_id: f.packet_id,
+            source_id: f.source_id,
+            max_payload: f.max_payload,
+            payload: f.payload,
+            hop_count: f.hop_count,
+            hop_to_gw: f.hop_to_gw,
+            route: f.route,
+        })
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for MHPacketView<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let f = Fields::deserialize(deserializer)?;
+        Ok(MHPacketView {
+            network_id: f.network_id,
+            destination_id: f.destination_id,
+            packet_type: f.packet_type,
+            flags: f.flags,
+            packet_id: f.packet_id,
+            source_id: f.source_id,
+            max_payload: f.max_payload,
+            payload: f.payload,
+            hop_count: f.hop_count,
+            hop_to_gw: f.hop_to_gw,
+            route: f.route,
+        })
+    }
+}
@@ -0,0 +1,18 @@
+/// The types most applications need, so `use must_hop::prelude::*;` is enough to set up a node or
+/// a gateway. Less common types stay in their modules under `node`.
+pub use crate::node::{
+    BROADCAST_ID, LinkDirection, LinkQuality, MAX_SIZE, MHNode, MHPacket, MHPacketView, PacketRef,
+    PacketType,
+    config::{ChannelPlan, ConfigStore},
+    control::{Control, ControlHooks, LogBuffer, NoControl},
+    delivered::Delivered,
+    handle::{MeshChannels, MeshCommand, MeshEvent, MeshHandle},
+    mesh_router::{MeshRouter, MeshRouterError, Quiesced},
+    network_manager::{AckMode, ConfirmedTypes, ManagerState, NetworkManager, NetworkManagerError},
+    policy::{GatewayPolicy, NodePolicy, RoutingPolicy, RuntimePolicy},
+    region::{Region, RegionError},
+    route::RouteTable,
+    store::{MemoryPacketStore, PacketStore, StoredPayload},
+    telemetry::{DropReason, NoTelemetry, Telemetry},
+};
+pub use crate::units::{Db, Dbm};
@@ -0,0 +1,343 @@
+/// An in-memory radio environment, where links between nodes are defined by hand. Used to run the
+/// NetworkManager and MeshRouter without hardware, e.g. in the browser through `wasm`. Nodes may
+/// be put on channels, and only hear neighbours sharing one, so a gateway made with `gateway`
+/// can listen on several at once like a concentrator.
+use alloc::{collections::BTreeMap, rc::Rc, vec::Vec as AllocVec};
+use core::cell::RefCell;
+use embassy_futures::yield_now;
+use embassy_time::Timer;
+
+use heapless::Vec;
+
+use crate::node::{
+    MHNode, MHPacket,
+    clock::EmbassyClock,
+    config::ChannelPlan,
+    mesh_router::MeshRouter,
+    network_manager::{NetworkManager, NetworkManagerError},
+    policy::{GatewayPolicy, NodePolicy},
+    telemetry::Telemetry,
+};
+
+pub mod collision;
+pub mod faults;
+
+use faults::{Fault, Faults, RadioCall};
+
+/// A packet which was put on the air
+#[derive(Debug, Clone)]
+pub struct Transmission<const SIZE: usize> {
+    pub sender: u8,
+    pub packet: MHPacket<SIZE>,
+}
+
+pub struct SimulationEnv<const SIZE: usize> {
+    /// Maps a Node ID to a list of Node IDs that can hear its transmissions.
+    /// E.g., Node 1 -> [2, 3] means if 1 transmits, 2 and 3 receive it.
+    pub topology: BTreeMap<u8, AllocVec<u8>>,
+    /// Each node's personal receiving buffer (their "inbox")
+    pub inboxes: BTreeMap<u8, AllocVec<MHPacket<SIZE>>>,
+    /// Every transmission since the log was last taken, in order
+    pub log: AllocVec<Transmission<SIZE>>,
+    /// The frequencies a node hears, one for a node's radio and several for a gateway's
+    /// concentrator. A node without any hears and is heard on every channel.
+    pub channels: BTreeMap<u8, AllocVec<u32>>,
+    /// Faults still to happen to a node's radio, see `inject`
+    pub faults: BTreeMap<u8, Faults>,
+}
+
+impl<const SIZE: usize> Default for SimulationEnv<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> SimulationEnv<SIZE> {
+    pub const fn new() -> Self {
+        Self {
+            topology: BTreeMap::new(),
+            inboxes: BTreeMap::new(),
+            log: AllocVec::new(),
+            channels: BTreeMap::new(),
+            faults: BTreeMap::new(),
+        }
+    }
+
+    /// Define that `receiver` is within radio range of `sender`
+    pub fn add_link(&mut self, sender: u8, receiver: u8) {
+        self.topology.entry(sender).or_default().push(receiver);
+        // Make sure the receiver has an inbox ready
+        self.inboxes.entry(receiver).or_default();
+    }
+
+    pub fn add_bidi_link(&mut self, node_a: u8, node_b: u8) {
+        self.add_link(node_a, node_b);
+        self.add_link(node_b, node_a);
+    }
+
+    /// Removes the link from `sender` to `receiver`, if any
+    pub fn remove_link(&mut self, sender: u8, receiver: u8) {
+        if let Some(neighbors) = self.topology.get_mut(&sender) {
+            neighbors.retain(|id| *id != receiver);
+        }
+    }
+
+    /// Cuts `node` off: nobody hears it and it hears nobody. What it hadn't received is lost.
+    pub fn isolate(&mut self, node: u8) {
+        self.topology.remove(&node);
+        for neighbors in self.topology.values_mut() {
+            neighbors.retain(|id| *id != node);
+        }
+        if let Some(inbox) = self.inboxes.get_mut(&node) {
+            inbox.clear();
+        }
+    }
+
+    pub fn apply(&mut self, change: TopologyChange) {
+        match change {
+            TopologyChange::Link(a, b) => self.add_bidi_link(a, b),
+            TopologyChange::Unlink(a, b) => {
+                self.remove_link(a, b);
+                self.remove_link(b, a);
+            }
+            TopologyChange::Leave(node) => self.isolate(node),
+        }
+    }
+
+    /// Puts `node` on `channels`, given in Hz, or on every channel again with none
+    pub fn set_channels(&mut self, node: u8, channels: &[u32]) {
+        match channels {
+            [] => self.channels.remove(&node),
+            _ => self.channels.insert(node, channels.into()),
+        };
+    }
+
+    /// Whether `sender` and `receiver` are on a common channel, so a frame of one reaches the
+    /// other if they are linked. A gateway on several channels answers each node on its own.
+    pub fn shares_channel(&self, sender: u8, receiver: u8) -> bool {
+        match (self.channels.get(&sender), self.channels.get(&receiver)) {
+            (Some(sent_on), Some(heard_on)) => sent_on.iter().any(|hz| heard_on.contains(hz)),
+            _ => true,
+        }
+    }
+
+    /// Makes `fault` happen to the radio of `node`, after the faults injected before
+    pub fn inject(&mut self, node: u8, fault: Fault) {
+        self.faults.entry(node).or_default().push(fault);
+    }
+
+    /// Takes back the faults still to happen to `node`
+    pub fn clear_faults(&mut self, node: u8) {
+        self.faults.remove(&node);
+    }
+
+    /// Counts a call of `call` by `node`, failing it if an injected fault says so
+    fn on_call(&mut self, node: u8, call: RadioCall) -> Result<(), NetworkManagerError> {
+        match self.faults.get_mut(&node) {
+            Some(faults) => faults.on_call(call),
+            None => Ok(()),
+        }
+    }
+
+    /// Amount of packets waiting to be received, across all nodes
+    pub fn in_flight(&self) -> usize {
+        self.inboxes.values().map(AllocVec::len).sum()
+    }
+}
+
+/// A change to the topology, e.g. a node moving, joining or leaving
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyChange {
+    /// The two nodes hear each other from now on, also for a node joining
+    Link(u8, u8),
+    /// Neither hears the other any more
+    Unlink(u8, u8),
+    /// The node is switched off or moves out of range of everyone
+    Leave(u8),
+}
+
+/// Topology changes at given steps of a simulation, where a step is whatever the test does in
+/// one round, e.g. every node receiving once
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    changes: AllocVec<(u32, TopologyChange)>,
+}
+
+impl Schedule {
+    pub const fn new() -> Self {
+        Self {
+            changes: AllocVec::new(),
+        }
+    }
+
+    /// Makes `change` at `step`, after the changes given for it before
+    pub fn at(mut self, step: u32, change: TopologyChange) -> Self {
+        self.changes.push((step, change));
+        self
+    }
+
+    /// Makes the changes due at `step` to `env`, returning how many there were
+    pub fn apply<const SIZE: usize>(&self, step: u32, env: &mut SimulationEnv<SIZE>) -> usize {
+        let mut applied = 0;
+        for (_, change) in self.changes.iter().filter(|(at, _)| *at == step) {
+            env.apply(*change);
+            applied += 1;
+        }
+        applied
+    }
+
+    /// Steps at which the topology changes, in order
+    pub fn steps(&self) -> impl Iterator<Item = u32> + '_ {
+        let mut steps: AllocVec<u32> = self.changes.iter().map(|(at, _)| *at).collect();
+        steps.sort_unstable();
+        steps.dedup();
+        steps.into_iter()
+    }
+}
+
+/// Radio of a node in a `SimulationEnv`
+pub struct SimRadio<const SIZE: usize> {
+    pub node_id: u8,
+    pub env: Rc<RefCell<SimulationEnv<SIZE>>>,
+}
+
+impl<const SIZE: usize, const LEN: usize> MHNode<SIZE, LEN> for SimRadio<SIZE> {
+    type Error = NetworkManagerError;
+    type Connection = ();
+    type ReceiveBuffer = ();
+    type Duration = u16;
+
+    async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), Self::Error> {
+        let delay = {
+            let mut env = self.env.borrow_mut();
+            env.on_call(self.node_id, RadioCall::Transmit)?;
+            env.faults.get_mut(&self.node_id).and_then(Faults::tx_delay)
+        };
+        if let Some(delay) = delay {
+            Timer::after(delay).await;
+        }
+        let mut env = self.env.borrow_mut();
+        for pkt in packets {
+            env.log.push(Transmission {
+                sender: self.node_id,
+                packet: pkt.clone(),
+            });
+        }
+        if env
+            .faults
+            .get_mut(&self.node_id)
+            .is_some_and(Faults::drops_tx)
+        {
+            return Ok(());
+        }
+        // Every node in range of this node, on its channel, gets a copy of the packets
+        let neighbors = env.topology.get(&self.node_id).cloned().unwrap_or_default();
+        for neighbor_id in neighbors {
+            if !env.shares_channel(self.node_id, neighbor_id) {
+                continue;
+            }
+            if let Some(inbox) = env.inboxes.get_mut(&neighbor_id) {
+                inbox.extend(packets.iter().cloned());
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive(
+        &mut self,
+        _conn: Self::Connection,
+        _receiving_buffer: &(),
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, Self::Error> {
+        let mut env = self.env.borrow_mut();
+        env.on_call(self.node_id, RadioCall::Receive)?;
+        let env = &mut *env;
+        let mut rec_vec: Vec<MHPacket<SIZE>, LEN> = Vec::new();
+        if let Some(inbox) = env.inboxes.get_mut(&self.node_id) {
+            let n = inbox.len().min(LEN);
+            for mut pkt in inbox.drain(..n) {
+                if let Some(faults) = env.faults.get_mut(&self.node_id) {
+                    faults.corrupt_rx(&mut pkt);
+                }
+                // Can't fail, at most LEN packets are drained
+                let _ = rec_vec.push(pkt);
+            }
+        }
+        Ok(rec_vec)
+    }
+
+    /// Waits until a packet is in the inbox, like a radio waiting for a preamble. With a timeout,
+    /// it returns straight away.
+    async fn listen(
+        &mut self,
+        _receiving_buffer: &mut (),
+        with_timeout: bool,
+    ) -> Result<Self::Connection, Self::Error> {
+        self.env
+            .borrow_mut()
+            .on_call(self.node_id, RadioCall::Listen)?;
+        while !with_timeout
+            && self
+                .env
+                .borrow()
+                .inboxes
+                .get(&self.node_id)
+                .is_none_or(AllocVec::is_empty)
+        {
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    /// Moves a node's radio to the plan's frequency. A gateway on several channels keeps them,
+    /// as a concentrator listens on its channels whatever the plan says.
+    fn apply_channel_plan(&mut self, plan: &ChannelPlan) -> Result<(), Self::Error> {
+        let mut env = self.env.borrow_mut();
+        if env
+            .channels
+            .get(&self.node_id)
+            .is_none_or(|on| on.len() <= 1)
+        {
+            env.set_channels(self.node_id, &[plan.frequency_hz]);
+        }
+        Ok(())
+    }
+}
+
+/// A gateway in `env`, with the manager's id: a router with the `GatewayPolicy`, on a radio
+/// hearing every one of `channels` at once, like a concentrator. With no channels it hears every
+/// node, as a node's radio does before it is put on a channel.
+pub fn gateway<const SIZE: usize, const LEN: usize, T: Telemetry>(
+    env: &Rc<RefCell<SimulationEnv<SIZE>>>,
+    manager: NetworkManager<SIZE, LEN, EmbassyClock, T>,
+    channels: &[u32],
+) -> MeshRouter<SimRadio<SIZE>, SIZE, LEN, GatewayPolicy, T> {
+    let radio = radio(env, manager.source_id(), channels);
+    MeshRouter::new(radio, manager, GatewayPolicy)
+}
+
+/// A node in `env`, with the manager's id: a router with the `NodePolicy`, on a radio on
+/// `channel`, or hearing every node without one.
+pub fn node<const SIZE: usize, const LEN: usize, T: Telemetry>(
+    env: &Rc<RefCell<SimulationEnv<SIZE>>>,
+    manager: NetworkManager<SIZE, LEN, EmbassyClock, T>,
+    channel: Option<u32>,
+) -> MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy, T> {
+    let radio = radio(env, manager.source_id(), channel.as_slice());
+    MeshRouter::new(radio, manager, NodePolicy)
+}
+
+/// The radio of `node_id` in `env`, on `channels` or hearing every node without any, for a router
+/// with another policy or clock than `node` and `gateway` give, or a radio wrapping it
+pub fn radio<const SIZE: usize>(
+    env: &Rc<RefCell<SimulationEnv<SIZE>>>,
+    node_id: u8,
+    channels: &[u32],
+) -> SimRadio<SIZE> {
+    let mut sim = env.borrow_mut();
+    sim.inboxes.entry(node_id).or_default();
+    sim.set_channels(node_id, channels);
+    SimRadio {
+        node_id,
+        env: env.clone(),
+    }
+}
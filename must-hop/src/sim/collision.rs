@@ -0,0 +1,354 @@
+/// Channel access with collisions, to compare ways of deciding when to send before trying them in
+/// the field. Nodes offer packets at given times, and a `MacPolicy` decides when each is sent. A
+/// frame is on the air for its airtime, and a neighbour loses it when another frame it hears
+/// overlaps it, unless it is `CAPTURE_THRESHOLD` stronger than each of them, or when the
+/// neighbour is sending itself. Only single hops are evaluated: a packet is delivered when its
+/// destination receives it.
+use alloc::{
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+    vec::Vec,
+};
+use core::cmp::Reverse;
+use embassy_time::Duration;
+
+use crate::{
+    node::{
+        airtime::Modulation,
+        entropy::{EntropySource, Xorshift},
+    },
+    units::{Db, Dbm},
+};
+
+/// How much stronger a frame must be than the ones overlapping it to still be received
+pub const CAPTURE_THRESHOLD: Db<i16> = Db(6);
+
+/// A packet a node wants to send
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offer {
+    pub node: u8,
+    pub destination: u8,
+    /// Since the start of the run
+    pub ready_at: Duration,
+    /// Bytes on the air, e.g. the length of the serialized MHPacket
+    pub len: usize,
+}
+
+impl Offer {
+    /// `count` packets from `node`, one every `interval` from `first`
+    pub fn periodic(
+        node: u8,
+        destination: u8,
+        first: Duration,
+        interval: Duration,
+        count: u32,
+        len: usize,
+    ) -> impl Iterator<Item = Offer> {
+        (0..count).map(move |i| Offer {
+            node,
+            destination,
+            ready_at: first + interval * i,
+            len,
+        })
+    }
+}
+
+/// A frame on the air
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub sender: u8,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl Frame {
+    fn overlaps(&self, other: &Frame) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// The frames decided on so far, i.e. those of packets which were ready earlier
+pub struct Channel<'a> {
+    links: &'a BTreeMap<(u8, u8), Dbm<i16>>,
+    frames: &'a [Frame],
+}
+
+impl Channel<'_> {
+    /// When the channel is free again as `node` hears it, if it is busy `at`, e.g. to sense the
+    /// carrier
+    pub fn busy_until(&self, node: u8, at: Duration) -> Option<Duration> {
+        self.frames
+            .iter()
+            .filter(|f| f.start <= at && at < f.end)
+            .filter(|f| f.sender == node || self.links.contains_key(&(f.sender, node)))
+            .map(|f| f.end)
+            .max()
+    }
+}
+
+/// Decides when a node sends a packet it has ready
+pub trait MacPolicy {
+    /// When `node` starts sending a packet it has ready at `ready_at`. Earlier is taken as
+    /// `ready_at`.
+    fn start(&mut self, node: u8, ready_at: Duration, channel: &Channel<'_>) -> Duration;
+
+    /// Asked again right before `node` starts sending `at`, with the frames which started
+    /// until then, e.g. to sense the carrier once more. A later time defers the frame, and the
+    /// policy is asked again then. Policies which don't listen leave this out.
+    fn sense(&mut self, _node: u8, _at: Duration, _channel: &Channel<'_>) -> Option<Duration> {
+        None
+    }
+}
+
+/// Sends straight away, like pure ALOHA
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Aloha;
+
+impl MacPolicy for Aloha {
+    fn start(&mut self, _node: u8, ready_at: Duration, _channel: &Channel<'_>) -> Duration {
+        ready_at
+    }
+}
+
+/// Waits a random time up to `max_delay` before sending
+#[derive(Debug, Clone)]
+pub struct RandomDelay<E = Xorshift> {
+    pub max_delay: Duration,
+    rng: E,
+}
+
+impl RandomDelay {
+    /// Reproducible from `seed`
+    pub fn new(max_delay: Duration, seed: u32) -> Self {
+        Self::with_entropy(max_delay, Xorshift::new(seed))
+    }
+}
+
+impl<E: EntropySource> RandomDelay<E> {
+    pub fn with_entropy(max_delay: Duration, rng: E) -> Self {
+        Self { max_delay, rng }
+    }
+}
+
+impl<E: EntropySource> MacPolicy for RandomDelay<E> {
+    fn start(&mut self, _node: u8, ready_at: Duration, _channel: &Channel<'_>) -> Duration {
+        ready_at + self.rng.up_to(self.max_delay)
+    }
+}
+
+/// Listens before talking: while the channel is busy, waits until it is free and then a random
+/// backoff up to `max_backoff`
+#[derive(Debug, Clone)]
+pub struct Csma<E = Xorshift> {
+    pub max_backoff: Duration,
+    rng: E,
+}
+
+impl Csma {
+    /// Reproducible from `seed`
+    pub fn new(max_backoff: Duration, seed: u32) -> Self {
+        Self::with_entropy(max_backoff, Xorshift::new(seed))
+    }
+}
+
+impl<E: EntropySource> Csma<E> {
+    pub fn with_entropy(max_backoff: Duration, rng: E) -> Self {
+        Self { max_backoff, rng }
+    }
+}
+
+impl<E: EntropySource> MacPolicy for Csma<E> {
+    fn start(&mut self, node: u8, ready_at: Duration, channel: &Channel<'_>) -> Duration {
+        let mut at = ready_at;
+        while let Some(free) = channel.busy_until(node, at) {
+            at = free + self.rng.up_to(self.max_backoff);
+        }
+        at
+    }
+
+    /// Another node may have started during the backoff
+    fn sense(&mut self, node: u8, at: Duration, channel: &Channel<'_>) -> Option<Duration> {
+        channel
+            .busy_until(node, at)
+            .map(|_| self.start(node, at, channel))
+    }
+}
+
+/// Gives every node its own `slot` in a cycle of `slots`, by its id. A frame has to fit in a
+/// slot.
+#[derive(Debug, Clone, Copy)]
+pub struct Tdma {
+    pub slot: Duration,
+    pub slots: u8,
+}
+
+impl MacPolicy for Tdma {
+    fn start(&mut self, node: u8, ready_at: Duration, _channel: &Channel<'_>) -> Duration {
+        let cycle = (self.slot.as_micros() * u64::from(self.slots.max(1))).max(1);
+        let offset = self.slot.as_micros() * u64::from(node % self.slots.max(1));
+        let mut at = ready_at.as_micros() / cycle * cycle + offset;
+        if at < ready_at.as_micros() {
+            at += cycle;
+        }
+        Duration::from_micros(at)
+    }
+}
+
+/// How a policy did on a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Report {
+    pub offered: usize,
+    pub delivered: usize,
+    /// From ready to received, summed over the delivered packets
+    latency_us: u64,
+}
+
+impl Report {
+    pub fn delivery_ratio(&self) -> f32 {
+        if self.offered == 0 {
+            return 0.0;
+        }
+        self.delivered as f32 / self.offered as f32
+    }
+
+    /// From the packet being ready to it being received, `None` if none were delivered
+    pub fn mean_latency(&self) -> Option<Duration> {
+        (self.delivered > 0).then(|| Duration::from_micros(self.latency_us / self.delivered as u64))
+    }
+}
+
+/// What happens to a packet in `CollisionEnv::run`, in the order they are handled at the same
+/// time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    Start,
+    Ready,
+}
+
+/// Who hears whom and how strongly, with every frame sent with the same modulation
+#[derive(Debug, Clone)]
+pub struct CollisionEnv {
+    /// RSSI at the receiver in dBm, by sender and receiver
+    links: BTreeMap<(u8, u8), Dbm<i16>>,
+    pub modulation: Modulation,
+}
+
+impl CollisionEnv {
+    pub fn new(modulation: Modulation) -> Self {
+        Self {
+            links: BTreeMap::new(),
+            modulation,
+        }
+    }
+
+    /// Define that `receiver` hears `sender` at `rssi`
+    pub fn add_link(&mut self, sender: u8, receiver: u8, rssi: Dbm<i16>) {
+        self.links.insert((sender, receiver), rssi);
+    }
+
+    pub fn add_bidi_link(&mut self, node_a: u8, node_b: u8, rssi: Dbm<i16>) {
+        self.add_link(node_a, node_b, rssi);
+        self.add_link(node_b, node_a, rssi);
+    }
+
+    /// Sends `offers` when `policy` decides. Time runs forward: a packet is decided on once it is
+    /// ready, and sensed again when its frame is about to start, seeing every frame which started
+    /// before. A node sends one frame at a time, so a packet ready while its node is still
+    /// sending, or still waiting to, is ready once it is done.
+    pub fn run<P: MacPolicy + ?Sized>(&self, policy: &mut P, offers: &[Offer]) -> Report {
+        let mut offers = offers.to_vec();
+        offers.sort_by_key(|offer| offer.ready_at);
+        // Packets of every node, in the order they are ready
+        let mut queued: BTreeMap<u8, VecDeque<usize>> = BTreeMap::new();
+        for (i, offer) in offers.iter().enumerate() {
+            queued.entry(offer.node).or_default().push_back(i);
+        }
+        // Starts come before packets getting ready at the same time, so those sense them. The
+        // sequence number keeps ties in the order they were queued.
+        let mut events = BinaryHeap::new();
+        let mut seq = 0usize;
+        let mut push = |events: &mut BinaryHeap<_>, at: Duration, kind: Event, i: usize| {
+            events.push(Reverse((at, kind, seq, i)));
+            seq += 1;
+        };
+        for first in queued.values_mut().filter_map(|q| q.pop_front()) {
+            push(&mut events, offers[first].ready_at, Event::Ready, first);
+        }
+        let mut frames: Vec<Frame> = Vec::with_capacity(offers.len());
+        let mut sent: Vec<usize> = Vec::with_capacity(offers.len());
+        while let Some(Reverse((at, kind, _, i))) = events.pop() {
+            let node = offers[i].node;
+            let channel = Channel {
+                links: &self.links,
+                frames: &frames,
+            };
+            match kind {
+                Event::Ready => {
+                    let start = policy.start(node, at, &channel).max(at);
+                    push(&mut events, start, Event::Start, i);
+                }
+                Event::Start => {
+                    if let Some(later) = policy.sense(node, at, &channel).filter(|t| *t > at) {
+                        push(&mut events, later, Event::Start, i);
+                        continue;
+                    }
+                    let end = at + self.modulation.airtime(offers[i].len);
+                    frames.push(Frame {
+                        sender: node,
+                        start: at,
+                        end,
+                    });
+                    sent.push(i);
+                    if let Some(next) = queued.get_mut(&node).and_then(|q| q.pop_front()) {
+                        push(
+                            &mut events,
+                            offers[next].ready_at.max(end),
+                            Event::Ready,
+                            next,
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut report = Report::default();
+        for (index, (i, frame)) in sent.iter().zip(&frames).enumerate() {
+            report.offered += 1;
+            if self.received(index, offers[*i].destination, &frames) {
+                report.delivered += 1;
+                report.latency_us += (frame.end - offers[*i].ready_at).as_micros();
+            }
+        }
+        report
+    }
+
+    /// Runs `offers` with every policy, in the order given
+    pub fn compare<'p>(
+        &self,
+        policies: &mut [(&'p str, &mut dyn MacPolicy)],
+        offers: &[Offer],
+    ) -> Vec<(&'p str, Report)> {
+        policies
+            .iter_mut()
+            .map(|(name, policy)| (*name, self.run(&mut **policy, offers)))
+            .collect()
+    }
+
+    /// Whether `receiver` gets the `index`th of `frames`
+    fn received(&self, index: usize, receiver: u8, frames: &[Frame]) -> bool {
+        let frame = &frames[index];
+        let Some(rssi) = self.links.get(&(frame.sender, receiver)) else {
+            return false;
+        };
+        frames
+            .iter()
+            .enumerate()
+            .filter(|(i, other)| *i != index && other.overlaps(frame))
+            .all(|(_, other)| {
+                other.sender != receiver
+                    && self
+                        .links
+                        .get(&(other.sender, receiver))
+                        .is_none_or(|other_rssi| *rssi - *other_rssi >= CAPTURE_THRESHOLD)
+            })
+    }
+}
@@ -0,0 +1,122 @@
+/// Faults a `SimRadio` runs into on purpose, so retries, recovery and watchdogs can be tested
+/// without hardware. Injected per node with `SimulationEnv::inject`, and used up once they
+/// happened.
+use alloc::vec::Vec as AllocVec;
+use embassy_time::Duration;
+
+use crate::node::{MHPacket, network_manager::NetworkManagerError};
+
+/// A call of the `MHNode` interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadioCall {
+    Transmit,
+    Receive,
+    Listen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The next `n` frames the node transmits reach nobody, though they are in the log
+    DropTx(u32),
+    /// The next `packets` packets the node receives have payload byte `index` XORed with `mask`,
+    /// if they are that long
+    CorruptRx {
+        packets: u32,
+        index: usize,
+        mask: u8,
+    },
+    /// The `nth` call of `call` from now fails with `NetworkManagerError::Timeout`, like a radio
+    /// which stopped answering. 1 is the next call.
+    Fail { call: RadioCall, nth: u32 },
+    /// The next `frames` frames the node transmits reach the others only after `delay`, and
+    /// `transmit` doesn't return before
+    DelayTx { frames: u32, delay: Duration },
+}
+
+/// The faults still to happen to one node, in the order they were injected
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    pending: AllocVec<Fault>,
+}
+
+impl Faults {
+    pub fn push(&mut self, fault: Fault) {
+        self.pending.push(fault);
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Counts a call of `call`, failing it if a `Fault::Fail` is due
+    pub(crate) fn on_call(&mut self, call: RadioCall) -> Result<(), NetworkManagerError> {
+        let mut failed = false;
+        self.pending.retain_mut(|fault| match fault {
+            Fault::Fail { call: of, nth } if *of == call => {
+                if *nth <= 1 {
+                    failed = true;
+                    return false;
+                }
+                *nth -= 1;
+                true
+            }
+            _ => true,
+        });
+        match failed {
+            true => Err(NetworkManagerError::Timeout),
+            false => Ok(()),
+        }
+    }
+
+    /// How long the frame being transmitted is held up
+    pub(crate) fn tx_delay(&mut self) -> Option<Duration> {
+        let mut delay = None;
+        self.pending.retain_mut(|fault| match fault {
+            Fault::DelayTx { frames, delay: by } if delay.is_none() => {
+                delay = Some(*by);
+                *frames = frames.saturating_sub(1);
+                *frames > 0
+            }
+            _ => true,
+        });
+        delay
+    }
+
+    /// Whether the frame being transmitted is lost
+    pub(crate) fn drops_tx(&mut self) -> bool {
+        let mut dropped = false;
+        self.pending.retain_mut(|fault| match fault {
+            Fault::DropTx(n) if !dropped => {
+                dropped = true;
+                *n = n.saturating_sub(1);
+                *n > 0
+            }
+            _ => true,
+        });
+        dropped
+    }
+
+    /// Corrupts `pkt`, just received, if a `Fault::CorruptRx` is due
+    pub(crate) fn corrupt_rx<const SIZE: usize>(&mut self, pkt: &mut MHPacket<SIZE>) {
+        let mut corrupted = false;
+        self.pending.retain_mut(|fault| match fault {
+            Fault::CorruptRx {
+                packets,
+                index,
+                mask,
+            } if !corrupted => {
+                if let Some(byte) = pkt.payload.get_mut(*index) {
+                    *byte ^= *mask;
+                }
+                corrupted = true;
+                *packets = packets.saturating_sub(1);
+                *packets > 0
+            }
+            _ => true,
+        });
+    }
+}
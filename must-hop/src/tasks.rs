@@ -1,3 +0,0 @@
-// implemented tasks to use multi hop
-
-pub mod lora;
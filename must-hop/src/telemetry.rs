@@ -0,0 +1,288 @@
+/// What nodes report about their sensors, shared by the firmware and the gateway so neither has
+/// a copy of its own. Not to be confused with `node::telemetry`, which hears what happens to
+/// packets.
+///
+/// A report starts with `SENSOR_REPORT` and `VERSION`, like the payloads must-gw's schemas
+/// describe, then the device id and the readings. Every reading is a tag, the length of its value
+/// and the value as postcard encodes it. A reading with a tag the decoder doesn't know, e.g. from
+/// newer firmware, is kept as `Reading::Other` instead of failing the whole report, so new kinds
+/// of readings don't need a new version.
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Payload type of a sensor report, its first byte. Clear of the low ids applications number
+/// their own payloads with.
+pub const SENSOR_REPORT: u8 = 16;
+/// Version of the report layout, its second byte
+pub const VERSION: u8 = 1;
+/// Longest value of a reading kept as `Reading::Other`
+pub const MAX_VALUE_LEN: usize = 16;
+
+/// Tags of the readings this version knows
+pub mod tag {
+    pub const BATTERY: u8 = 1;
+    pub const TEMPERATURE: u8 = 2;
+    pub const ACCELERATION: u8 = 3;
+}
+
+/// In g, along the axes of the accelerometer
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Acceleration {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reading {
+    /// Battery voltage in millivolts
+    Battery(u16),
+    /// In degrees Celsius
+    Temperature(f32),
+    Acceleration(Acceleration),
+    /// A tag this version doesn't know, with its value as it was sent
+    Other {
+        tag: u8,
+        value: Vec<u8, MAX_VALUE_LEN>,
+    },
+}
+
+/// Why a payload isn't a `SensorReport`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReportError {
+    /// Another payload type, or a version this doesn't read
+    Unknown { id: u8, version: u8 },
+    /// Ends in the middle of a reading, or a known reading doesn't decode
+    Malformed,
+    /// More than N readings, a value longer than `MAX_VALUE_LEN`, or more than fits the payload
+    Full,
+}
+
+/// A node's readings of one moment, up to N of them
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SensorReport<const N: usize> {
+    pub device_id: u8,
+    pub readings: Vec<Reading, N>,
+}
+
+impl Reading {
+    fn tag(&self) -> u8 {
+        match self {
+            Reading::Battery(_) => tag::BATTERY,
+            Reading::Temperature(_) => tag::TEMPERATURE,
+            Reading::Acceleration(_) => tag::ACCELERATION,
+            Reading::Other { tag, .. } => *tag,
+        }
+    }
+
+    /// Writes the value to the front of `buf`, returning its length
+    fn encode_value(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let used = match self {
+            Reading::Battery(millivolts) => postcard::to_slice(millivolts, buf)?.len(),
+            Reading::Temperature(celsius) => postcard::to_slice(celsius, buf)?.len(),
+            Reading::Acceleration(acceleration) => postcard::to_slice(acceleration, buf)?.len(),
+            Reading::Other { value, .. } => {
+                let dest = buf
+                    .get_mut(..value.len())
+                    .ok_or(postcard::Error::SerializeBufferFull)?;
+                dest.copy_from_slice(value);
+                value.len()
+            }
+        };
+        Ok(used)
+    }
+
+    fn decode(tag: u8, value: &[u8]) -> Result<Self, ReportError> {
+        fn whole<'a, T: Deserialize<'a>>(value: &'a [u8]) -> Result<T, ReportError> {
+            match postcard::take_from_bytes(value) {
+                Ok((decoded, [])) => Ok(decoded),
+                _ => Err(ReportError::Malformed),
+            }
+        }
+        Ok(match tag {
+            tag::BATTERY => Reading::Battery(whole(value)?),
+            tag::TEMPERATURE => Reading::Temperature(whole(value)?),
+            tag::ACCELERATION => Reading::Acceleration(whole(value)?),
+            tag => Reading::Other {
+                tag,
+                value: Vec::from_slice(value).map_err(|_| ReportError::Full)?,
+            },
+        })
+    }
+}
+
+impl<const N: usize> SensorReport<N> {
+    pub fn new(device_id: u8) -> Self {
+        Self {
+            device_id,
+            readings: Vec::new(),
+        }
+    }
+
+    /// Adds `reading`, e.g. `SensorReport::new(42).with(Reading::Battery(3300))?`. Gives the
+    /// reading back if N are there already.
+    pub fn with(mut self, reading: Reading) -> Result<Self, Reading> {
+        self.readings.push(reading)?;
+        Ok(self)
+    }
+
+    /// Battery voltage in millivolts, if reported
+    pub fn battery(&self) -> Option<u16> {
+        self.readings.iter().find_map(|reading| match reading {
+            Reading::Battery(millivolts) => Some(*millivolts),
+            _ => None,
+        })
+    }
+
+    /// In degrees Celsius, if reported
+    pub fn temperature(&self) -> Option<f32> {
+        self.readings.iter().find_map(|reading| match reading {
+            Reading::Temperature(celsius) => Some(*celsius),
+            _ => None,
+        })
+    }
+
+    pub fn acceleration(&self) -> Option<Acceleration> {
+        self.readings.iter().find_map(|reading| match reading {
+            Reading::Acceleration(acceleration) => Some(*acceleration),
+            _ => None,
+        })
+    }
+
+    /// The report as a payload, e.g. for `MeshRouter::send_payload`
+    pub fn encode<const SIZE: usize>(&self) -> Result<Vec<u8, SIZE>, ReportError> {
+        let mut payload: Vec<u8, SIZE> = Vec::new();
+        payload
+            .extend_from_slice(&[SENSOR_REPORT, VERSION, self.device_id])
+            .map_err(|_| ReportError::Full)?;
+        for reading in &self.readings {
+            let mut value = [0u8; MAX_VALUE_LEN];
+            let len = reading
+                .encode_value(&mut value)
+                .map_err(|_| ReportError::Full)?;
+            payload
+                .extend_from_slice(&[reading.tag(), len as u8])
+                .map_err(|_| ReportError::Full)?;
+            payload
+                .extend_from_slice(&value[..len])
+                .map_err(|_| ReportError::Full)?;
+        }
+        Ok(payload)
+    }
+
+    /// Reads a payload made with `encode`, by this or a newer version of must-hop
+    pub fn decode(payload: &[u8]) -> Result<Self, ReportError> {
+        let (device_id, mut rest) = match payload {
+            [SENSOR_REPORT, VERSION, device_id, rest @ ..] => (*device_id, rest),
+            [id, version, ..] => {
+                return Err(ReportError::Unknown {
+                    id: *id,
+                    version: *version,
+                });
+            }
+            [id] => {
+                return Err(ReportError::Unknown {
+                    id: *id,
+                    version: 0,
+                });
+            }
+            [] => return Err(ReportError::Unknown { id: 0, version: 0 }),
+        };
+        let mut report = Self::new(device_id);
+        while let [tag, len, after @ ..] = rest {
+            let value = after
+                .get(..usize::from(*len))
+                .ok_or(ReportError::Malformed)?;
+            report
+                .readings
+                .push(Reading::decode(*tag, value)?)
+                .map_err(|_| ReportError::Full)?;
+            rest = &after[value.len()..];
+        }
+        if !rest.is_empty() {
+            return Err(ReportError::Malformed);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_round_trip() {
+        let report = SensorReport::<4>::new(42)
+            .with(Reading::Battery(3300))
+            .unwrap()
+            .with(Reading::Temperature(23.5))
+            .unwrap()
+            .with(Reading::Acceleration(Acceleration {
+                x: 1.2,
+                y: 0.0,
+                z: -1.0,
+            }))
+            .unwrap();
+        let payload = report.encode::<40>().unwrap();
+        assert_eq!(payload[..2], [SENSOR_REPORT, VERSION]);
+        let decoded = SensorReport::<4>::decode(&payload).unwrap();
+        assert_eq!(decoded, report);
+        assert_eq!(decoded.battery(), Some(3300));
+        assert_eq!(decoded.temperature(), Some(23.5));
+        // Too large for the payload
+        assert_eq!(report.encode::<16>(), Err(ReportError::Full));
+    }
+
+    #[test]
+    fn test_unknown_readings_are_kept() {
+        // A reading with tag 9 from newer firmware, between two known ones
+        let payload = [
+            SENSOR_REPORT,
+            VERSION,
+            7,
+            1,
+            2,
+            0xE4,
+            0x19,
+            9,
+            3,
+            1,
+            2,
+            3,
+            2,
+            4,
+            0,
+            0,
+            0xBC,
+            0x41,
+        ];
+        let report = SensorReport::<4>::decode(&payload).unwrap();
+        assert_eq!(report.battery(), Some(3300));
+        assert_eq!(report.temperature(), Some(23.5));
+        assert_eq!(
+            report.readings[1],
+            Reading::Other {
+                tag: 9,
+                value: Vec::from_slice(&[1, 2, 3]).unwrap()
+            }
+        );
+        // And it goes on as it came
+        assert_eq!(report.encode::<40>().unwrap(), payload);
+
+        assert_eq!(
+            SensorReport::<4>::decode(&[SENSOR_REPORT, 2, 7]),
+            Err(ReportError::Unknown {
+                id: SENSOR_REPORT,
+                version: 2
+            })
+        );
+        assert_eq!(
+            SensorReport::<4>::decode(&payload[..payload.len() - 1]),
+            Err(ReportError::Malformed)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+/// How much each part of must-hop logs, adjustable while the node runs. defmt's filter, chosen
+/// when building, sets the most that can be logged, and this turns a subsystem down from there,
+/// e.g. everything but the router to warnings while debugging routing in the field. A gateway
+/// changes it with `Control::SetLogLevel`, if the node's `ControlHooks` confirm it, and the
+/// firmware can itself, e.g. from a BLE characteristic, with `set`.
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// Most verbose last, so a level lets through those before it
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Trace,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Subsystem {
+    /// The `NetworkManager`, keeping track of packets
+    Manager,
+    /// The `MeshRouter`
+    Router,
+    /// The tasks deciding when the radio sends and listens
+    Mac,
+    /// The radio driver, e.g. `LoraNode` of must-hop-lora
+    Radio,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Manager,
+        Subsystem::Router,
+        Subsystem::Mac,
+        Subsystem::Radio,
+    ];
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Off,
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Trace,
+    ];
+}
+
+/// Of each subsystem, everything until it is turned down
+static LEVELS: [AtomicU8; Subsystem::ALL.len()] =
+    [const { AtomicU8::new(Level::Trace as u8) }; Subsystem::ALL.len()];
+
+/// Logs of `subsystem` up to `level` from now on
+pub fn set(subsystem: Subsystem, level: Level) {
+    LEVELS[subsystem as usize].store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level(subsystem: Subsystem) -> Level {
+    Level::ALL[usize::from(LEVELS[subsystem as usize].load(Ordering::Relaxed))]
+}
+
+/// Whether a log of `subsystem` at `level` goes out
+pub fn enabled(subsystem: Subsystem, level: Level) -> bool {
+    level != Level::Off && level <= self::level(subsystem)
+}
+
+/// Back to everything for every subsystem
+pub fn reset() {
+    for subsystem in Subsystem::ALL {
+        set(subsystem, Level::Trace);
+    }
+}
+
+#[cfg(all(feature = "defmt", not(feature = "log")))]
+macro_rules! log_at {
+    ($level:ident, $log:ident, $subsystem:ident, $($arg:tt)+) => {
+        if $crate::verbosity::enabled(
+            $crate::verbosity::Subsystem::$subsystem,
+            $crate::verbosity::Level::$level,
+        ) {
+            defmt::$log!($($arg)+);
+        }
+    };
+}
+
+// Without a logger the arguments are only type checked
+#[cfg(not(any(feature = "defmt", feature = "log")))]
+macro_rules! log_at {
+    ($level:ident, $log:ident, $subsystem:ident, $($arg:tt)+) => {
+        if false {
+            let _ = core::format_args!($($arg)+);
+        }
+    };
+}
+
+// With `log`, e.g. in_std, to the `log` crate instead
+#[cfg(feature = "log")]
+macro_rules! log_at {
+    ($level:ident, $log:ident, $subsystem:ident, $($arg:tt)+) => {
+        if $crate::verbosity::enabled(
+            $crate::verbosity::Subsystem::$subsystem,
+            $crate::verbosity::Level::$level,
+        ) {
+            log::$log!($($arg)+);
+        }
+    };
+}
+
+/// Like `defmt::error!`, for `Subsystem::$subsystem`
+macro_rules! log_error {
+    ($subsystem:ident, $($arg:tt)+) => { $crate::verbosity::log_at!(Error, error, $subsystem, $($arg)+) };
+}
+
+/// Like `defmt::trace!`, for `Subsystem::$subsystem`
+macro_rules! log_trace {
+    ($subsystem:ident, $($arg:tt)+) => { $crate::verbosity::log_at!(Trace, trace, $subsystem, $($arg)+) };
+}
+
+pub(crate) use {log_at, log_error as error, log_trace as trace};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert!(enabled(Subsystem::Router, Level::Trace));
+        set(Subsystem::Router, Level::Warn);
+        assert!(enabled(Subsystem::Router, Level::Error));
+        assert!(!enabled(Subsystem::Router, Level::Info));
+        assert_eq!(level(Subsystem::Router), Level::Warn);
+        // The others are as they were
+        assert!(enabled(Subsystem::Radio, Level::Trace));
+        set(Subsystem::Router, Level::Off);
+        assert!(!enabled(Subsystem::Router, Level::Error));
+        reset();
+        assert_eq!(level(Subsystem::Router), Level::Trace);
+    }
+}
@@ -0,0 +1,247 @@
+/// JS API over the simulator, so routing can be explored in a browser visualizer. Build it with
+/// `just build-wasm`.
+use alloc::{collections::BTreeMap, format, rc::Rc, string::String, vec::Vec as AllocVec};
+use core::cell::RefCell;
+
+use embassy_futures::block_on;
+use heapless::Vec;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    node::{
+        MHPacket,
+        mesh_router::{MeshRouter, MeshRouterError},
+        network_manager::{NetworkManager, NetworkManagerError},
+        policy::{GatewayPolicy, NodePolicy},
+    },
+    sim::{SimRadio, SimulationEnv},
+};
+
+const SIZE: usize = 64;
+const LEN: usize = 5;
+const TIMEOUT: u8 = 5;
+const MAX_RETRIES: u8 = 3;
+
+enum Router {
+    Node(MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>),
+    Gateway(MeshRouter<SimRadio<SIZE>, SIZE, LEN, GatewayPolicy>),
+}
+
+fn js_error(err: MeshRouterError<NetworkManagerError>) -> JsError {
+    JsError::new(&format!("{}", err))
+}
+
+/// A packet delivered to the application of a node
+#[wasm_bindgen]
+pub struct Delivery {
+    node: u8,
+    packet: MHPacket<SIZE>,
+}
+
+#[wasm_bindgen]
+impl Delivery {
+    #[wasm_bindgen(getter)]
+    pub fn node(&self) -> u8 {
+        self.node
+    }
+    #[wasm_bindgen(getter)]
+    pub fn source(&self) -> u8 {
+        self.packet.source_id
+    }
+    #[wasm_bindgen(getter, js_name = packetId)]
+    pub fn packet_id(&self) -> u16 {
+        self.packet.packet_id
+    }
+    #[wasm_bindgen(getter)]
+    pub fn payload(&self) -> AllocVec<u8> {
+        self.packet.payload.to_vec()
+    }
+}
+
+/// A packet put on the air by a node
+#[wasm_bindgen]
+pub struct AirPacket {
+    sender: u8,
+    packet: MHPacket<SIZE>,
+}
+
+#[wasm_bindgen]
+impl AirPacket {
+    #[wasm_bindgen(getter)]
+    pub fn sender(&self) -> u8 {
+        self.sender
+    }
+    #[wasm_bindgen(getter)]
+    pub fn destination(&self) -> u8 {
+        self.packet.destination_id
+    }
+    #[wasm_bindgen(getter)]
+    pub fn source(&self) -> u8 {
+        self.packet.source_id
+    }
+    #[wasm_bindgen(getter, js_name = packetId)]
+    pub fn packet_id(&self) -> u16 {
+        self.packet.packet_id
+    }
+    /// One of "Data", "Ack" or "BootUp"
+    #[wasm_bindgen(getter, js_name = packetType)]
+    pub fn packet_type(&self) -> String {
+        format!("{:?}", self.packet.packet_type)
+    }
+    #[wasm_bindgen(getter, js_name = hopCount)]
+    pub fn hop_count(&self) -> u8 {
+        self.packet.hop_count
+    }
+}
+
+/// A mesh of simulated nodes and gateways, stepped by hand from JS
+#[wasm_bindgen]
+pub struct MeshSimulation {
+    env: Rc<RefCell<SimulationEnv<SIZE>>>,
+    routers: BTreeMap<u8, Router>,
+    deliveries: AllocVec<Delivery>,
+}
+
+impl Default for MeshSimulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl MeshSimulation {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            env: Rc::new(RefCell::new(SimulationEnv::new())),
+            routers: BTreeMap::new(),
+            deliveries: AllocVec::new(),
+        }
+    }
+
+    fn radio(&self, id: u8) -> Result<SimRadio<SIZE>, JsError> {
+        if self.routers.contains_key(&id) {
+            return Err(JsError::new(&format!("node {} already exists", id)));
+        }
+        self.env.borrow_mut().inboxes.entry(id).or_default();
+        Ok(SimRadio {
+            node_id: id,
+            env: self.env.clone(),
+        })
+    }
+
+    /// Adds a node, which routes packets on towards the gateway
+    #[wasm_bindgen(js_name = addNode)]
+    pub fn add_node(&mut self, id: u8) -> Result<(), JsError> {
+        let manager = NetworkManager::new(id, TIMEOUT, MAX_RETRIES);
+        let router = MeshRouter::new(self.radio(id)?, manager, NodePolicy);
+        self.routers.insert(id, Router::Node(router));
+        Ok(())
+    }
+
+    /// Adds a gateway, which ACKs every packet it hears
+    #[wasm_bindgen(js_name = addGateway)]
+    pub fn add_gateway(&mut self, id: u8) -> Result<(), JsError> {
+        let manager = NetworkManager::new(id, TIMEOUT, MAX_RETRIES);
+        let router = MeshRouter::new(self.radio(id)?, manager, GatewayPolicy);
+        self.routers.insert(id, Router::Gateway(router));
+        Ok(())
+    }
+
+    /// `receiver` can hear `sender`
+    #[wasm_bindgen(js_name = addLink)]
+    pub fn add_link(&mut self, sender: u8, receiver: u8) {
+        self.env.borrow_mut().add_link(sender, receiver);
+    }
+
+    #[wasm_bindgen(js_name = addBidiLink)]
+    pub fn add_bidi_link(&mut self, node_a: u8, node_b: u8) {
+        self.env.borrow_mut().add_bidi_link(node_a, node_b);
+    }
+
+    #[wasm_bindgen(js_name = removeLink)]
+    pub fn remove_link(&mut self, sender: u8, receiver: u8) {
+        self.env.borrow_mut().remove_link(sender, receiver);
+    }
+
+    /// Sends `payload` from node `from` towards `destination`
+    pub fn send(&mut self, from: u8, destination: u8, payload: &[u8]) -> Result<(), JsError> {
+        let payload = Vec::from_slice(payload)
+            .map_err(|_| JsError::new(&format!("payload is larger than {} bytes", SIZE)))?;
+        match self.routers.get_mut(&from) {
+            Some(Router::Node(router)) => {
+                block_on(router.send_payload(payload, destination)).map_err(js_error)
+            }
+            Some(Router::Gateway(router)) => {
+                block_on(router.send_payload(payload, destination)).map_err(js_error)
+            }
+            None => Err(JsError::new(&format!("no node {}", from))),
+        }
+    }
+
+    /// Makes gateway `id` announce itself, so nodes learn their hop count
+    pub fn bootup(&mut self, id: u8) -> Result<(), JsError> {
+        match self.routers.get_mut(&id) {
+            Some(Router::Gateway(router)) => block_on(router.bootup()).map_err(js_error),
+            _ => Err(JsError::new(&format!("no gateway {}", id))),
+        }
+    }
+
+    /// Lets every node, in order of id, handle what it has heard since the last step. Returns the
+    /// amount of packets still on the air.
+    pub fn step(&mut self) -> Result<usize, JsError> {
+        for (id, router) in self.routers.iter_mut() {
+            let pkts = match router {
+                Router::Node(router) => block_on(router.receive((), &())),
+                Router::Gateway(router) => block_on(router.receive((), &())),
+            }
+            .map_err(js_error)?;
+            self.deliveries
+                .extend(pkts.into_iter().map(|delivered| Delivery {
+                    node: *id,
+                    packet: delivered.packet,
+                }));
+        }
+        Ok(self.env.borrow().in_flight())
+    }
+
+    /// Packets waiting for an ACK at node `id`
+    pub fn pending(&self, id: u8) -> usize {
+        match self.routers.get(&id) {
+            Some(Router::Node(router)) => router.get_pending_count(),
+            Some(Router::Gateway(router)) => router.get_pending_count(),
+            None => 0,
+        }
+    }
+
+    /// Packets delivered to applications since the last call
+    #[wasm_bindgen(js_name = takeDeliveries)]
+    pub fn take_deliveries(&mut self) -> AllocVec<Delivery> {
+        core::mem::take(&mut self.deliveries)
+    }
+
+    /// Packets put on the air since the last call
+    #[wasm_bindgen(js_name = takeTransmissions)]
+    pub fn take_transmissions(&mut self) -> AllocVec<AirPacket> {
+        let log = core::mem::take(&mut self.env.borrow_mut().log);
+        log.into_iter()
+            .map(|t| AirPacket {
+                sender: t.sender,
+                packet: t.packet,
+            })
+            .collect()
+    }
+
+    /// Describes the links as "sender->receiver" pairs, for drawing the topology
+    pub fn links(&self) -> AllocVec<String> {
+        let env = self.env.borrow();
+        env.topology
+            .iter()
+            .flat_map(|(sender, receivers)| {
+                receivers
+                    .iter()
+                    .map(move |receiver| format!("{}->{}", sender, receiver))
+            })
+            .collect()
+    }
+}
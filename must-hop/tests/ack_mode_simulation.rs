@@ -0,0 +1,105 @@
+//! A chain of three nodes, (A) <-> (B) <-> (C), where A sends to C, run with each ACK mode.
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        mesh_router::MeshRouter,
+        network_manager::{AckMode, NetworkManager},
+        policy::NodePolicy,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+const NODE_C: u8 = 4;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Router = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+
+fn router(env: &Env, node_id: u8, ack_mode: AckMode) -> Router {
+    let mut manager = NetworkManager::<SIZE, LEN>::new(node_id, 5, 3);
+    manager.set_ack_mode(ack_mode);
+    sim::node(env, manager, None)
+}
+
+fn chain(ack_mode: AckMode) -> (Env, Router, Router, Router) {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    {
+        let mut e = env.borrow_mut();
+        e.add_bidi_link(NODE_A, NODE_B);
+        e.add_bidi_link(NODE_B, NODE_C);
+    }
+    let a = router(&env, NODE_A, ack_mode);
+    let b = router(&env, NODE_B, ack_mode);
+    let c = router(&env, NODE_C, ack_mode);
+    (env, a, b, c)
+}
+
+#[tokio::test]
+async fn test_passive_destination_confirms_last_hop() {
+    let (_env, mut a, mut b, mut c) = chain(AckMode::Passive);
+
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_C)
+        .await
+        .unwrap();
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 0);
+    // Hearing B rebroadcast confirms A's packet
+    assert_eq!(a.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(a.get_pending_count(), 0);
+
+    assert_eq!(c.receive((), &()).await.unwrap().len(), 1);
+    // C doesn't rebroadcast, but its explicit ACK confirms B's packet
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(b.get_pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_hop_by_hop_confirms_every_hop() {
+    let (env, mut a, mut b, mut c) = chain(AckMode::HopByHop);
+
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_C)
+        .await
+        .unwrap();
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(b.get_pending_count(), 1);
+    // B's ACK confirms A's packet, and the rebroadcast itself is ignored
+    assert_eq!(a.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(a.get_pending_count(), 0);
+
+    // C ignores B's ACK to A, and ACKs the packet it got
+    assert_eq!(c.receive((), &()).await.unwrap().len(), 1);
+    assert_eq!(c.get_pending_count(), 0);
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(b.get_pending_count(), 0);
+    assert_eq!(env.borrow().in_flight(), 0);
+}
+
+#[tokio::test]
+async fn test_end_to_end_only_destination_confirms() {
+    let (env, mut a, mut b, mut c) = chain(AckMode::EndToEnd);
+
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_C)
+        .await
+        .unwrap();
+    // Relays don't wait for confirmations
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(b.get_pending_count(), 0);
+    // And a rebroadcast doesn't confirm anything
+    assert_eq!(a.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(a.get_pending_count(), 1);
+
+    // C ACKs, and B relays the ACK back to A
+    assert_eq!(c.receive((), &()).await.unwrap().len(), 1);
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(b.get_pending_count(), 0);
+    assert_eq!(a.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(a.get_pending_count(), 0);
+    // C hears its own ACK relayed, and drops it
+    assert_eq!(c.receive((), &()).await.unwrap().len(), 0);
+    assert_eq!(env.borrow().in_flight(), 0);
+}
@@ -0,0 +1,114 @@
+//! A powered backbone relay carrying uplinks on a faster spreading factor, on
+//! (GW) <-> (B) <-> (A) with B on the backbone and A an edge node
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        MHNode, MHPacket, PacketType,
+        config::{ChannelPlan, RelayRole},
+        mesh_router::MeshRouter,
+        network_manager::{NetworkManager, NetworkManagerError},
+        policy::NodePolicy,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_B: u8 = 2;
+const NODE_A: u8 = 3;
+
+const PLAN: ChannelPlan = ChannelPlan {
+    frequency_hz: 868_100_000,
+    spreading_factor: 12,
+    bandwidth_khz: 125,
+};
+const BACKBONE_SF: u8 = 7;
+
+/// A SimRadio which remembers the spreading factor every packet went out on
+struct SfRadio {
+    inner: SimRadio<SIZE>,
+    spreading_factor: u8,
+    sent: Rc<RefCell<std::vec::Vec<(PacketType, u8)>>>,
+}
+
+impl MHNode<SIZE, LEN> for SfRadio {
+    type Error = NetworkManagerError;
+    type Connection = ();
+    type ReceiveBuffer = ();
+    type Duration = u16;
+
+    async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), Self::Error> {
+        let sf = self.spreading_factor;
+        self.sent
+            .borrow_mut()
+            .extend(packets.iter().map(|pkt| (pkt.packet_type, sf)));
+        MHNode::<SIZE, LEN>::transmit(&mut self.inner, packets).await
+    }
+
+    async fn receive(
+        &mut self,
+        conn: (),
+        rec_buf: &(),
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, Self::Error> {
+        MHNode::<SIZE, LEN>::receive(&mut self.inner, conn, rec_buf).await
+    }
+
+    async fn listen(&mut self, rec_buf: &mut (), with_timeout: bool) -> Result<(), Self::Error> {
+        MHNode::<SIZE, LEN>::listen(&mut self.inner, rec_buf, with_timeout).await
+    }
+
+    fn apply_channel_plan(&mut self, plan: &ChannelPlan) -> Result<(), Self::Error> {
+        self.spreading_factor = plan.spreading_factor;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_backbone_relay_sends_uplinks_on_backbone_sf() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_B);
+    env.borrow_mut().add_bidi_link(NODE_B, NODE_A);
+    let mut gw_manager = NetworkManager::<SIZE, LEN>::new(GW, 5, 3);
+    gw_manager.set_channel_plan(PLAN);
+    gw_manager.set_gateway_id(Some(0xAB));
+    gw_manager.set_backbone_spreading_factor(Some(BACKBONE_SF));
+    let mut gw = sim::gateway(&env, gw_manager, &[]);
+    let sent = Rc::new(RefCell::new(std::vec::Vec::new()));
+    let mut b = MeshRouter::new(
+        SfRadio {
+            inner: sim::radio(&env, NODE_B, &[]),
+            spreading_factor: PLAN.spreading_factor,
+            sent: sent.clone(),
+        },
+        NetworkManager::<SIZE, LEN>::new(NODE_B, 5, 3),
+        NodePolicy,
+    );
+    b.set_relay_role(RelayRole::Backbone);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+
+    // B learns the backbone from the bootup, A is an edge node and ignores it
+    gw.bootup().await.unwrap();
+    b.receive((), &()).await.unwrap();
+    assert_eq!(b.backbone_spreading_factor(), Some(BACKBONE_SF));
+    a.receive((), &()).await.unwrap();
+    assert_eq!(a.backbone_spreading_factor(), None);
+
+    a.send_payload(Vec::from_slice(&[1]).unwrap(), GW)
+        .await
+        .unwrap();
+    b.receive((), &()).await.unwrap();
+    // The bootup was flooded on to the edge on the plan, the uplink went on the backbone
+    assert_eq!(
+        *sent.borrow(),
+        [
+            (PacketType::BootUp, PLAN.spreading_factor),
+            (PacketType::Data, BACKBONE_SF)
+        ]
+    );
+    let delivered = gw.receive((), &()).await.unwrap();
+    assert!(delivered.iter().any(|d| d.packet.payload == [1]));
+}
@@ -0,0 +1,182 @@
+//! The gateway advertises its channel plan in the bootup, and nodes along the chain
+//! (GW) <-> (A) <-> (B) pick it up and save it.
+use core::cell::{Cell, RefCell};
+use heapless::Vec;
+use must_hop::{
+    node::{
+        MHNode, MHPacket,
+        config::{ChannelPlan, ConfigStore},
+        mesh_router::MeshRouter,
+        network_manager::{NetworkManager, NetworkManagerError},
+        policy::NodePolicy,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+const PLAN: ChannelPlan = ChannelPlan {
+    frequency_hz: 869_525_000,
+    spreading_factor: 9,
+    bandwidth_khz: 250,
+};
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+
+#[derive(Default)]
+struct MemoryStore {
+    plan: Option<ChannelPlan>,
+    saves: usize,
+}
+
+impl ConfigStore for MemoryStore {
+    type Error = ();
+
+    async fn load_channel_plan(&mut self) -> Result<Option<ChannelPlan>, ()> {
+        Ok(self.plan)
+    }
+
+    async fn save_channel_plan(&mut self, plan: &ChannelPlan) -> Result<(), ()> {
+        self.plan = Some(*plan);
+        self.saves += 1;
+        Ok(())
+    }
+}
+
+/// A simulated radio which remembers the plan it was switched to
+struct TunableRadio {
+    sim: SimRadio<SIZE>,
+    applied: Rc<Cell<Option<ChannelPlan>>>,
+}
+
+impl MHNode<SIZE, LEN> for TunableRadio {
+    type Error = NetworkManagerError;
+    type Connection = ();
+    type ReceiveBuffer = ();
+    type Duration = u16;
+
+    async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), Self::Error> {
+        MHNode::<SIZE, LEN>::transmit(&mut self.sim, packets).await
+    }
+
+    async fn receive(
+        &mut self,
+        conn: (),
+        rec_buf: &(),
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, Self::Error> {
+        MHNode::<SIZE, LEN>::receive(&mut self.sim, conn, rec_buf).await
+    }
+
+    async fn listen(&mut self, rec_buf: &mut (), with_timeout: bool) -> Result<(), Self::Error> {
+        MHNode::<SIZE, LEN>::listen(&mut self.sim, rec_buf, with_timeout).await
+    }
+
+    fn apply_channel_plan(&mut self, plan: &ChannelPlan) -> Result<(), Self::Error> {
+        self.applied.set(Some(*plan));
+        Ok(())
+    }
+}
+
+fn node(
+    env: &Env,
+    node_id: u8,
+    applied: &Rc<Cell<Option<ChannelPlan>>>,
+) -> MeshRouter<TunableRadio, SIZE, LEN, NodePolicy> {
+    MeshRouter::new(
+        TunableRadio {
+            sim: sim::radio(env, node_id, &[]),
+            applied: applied.clone(),
+        },
+        NetworkManager::<SIZE, LEN>::new(node_id, 5, 3),
+        NodePolicy,
+    )
+}
+
+#[tokio::test]
+async fn test_bootup_hands_out_channel_plan() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    {
+        let mut e = env.borrow_mut();
+        e.add_bidi_link(GW, NODE_A);
+        e.add_bidi_link(NODE_A, NODE_B);
+    }
+    let mut manager = NetworkManager::<SIZE, LEN>::new(GW, 5, 3);
+    manager.set_channel_plan(PLAN);
+    let mut gw = sim::gateway(&env, manager, &[]);
+    let applied_a = Rc::new(Cell::new(None));
+    let applied_b = Rc::new(Cell::new(None));
+    let mut a = node(&env, NODE_A, &applied_a);
+    let mut b = node(&env, NODE_B, &applied_b);
+    let mut store_a = MemoryStore::default();
+    let mut store_b = MemoryStore::default();
+
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    assert!(a.update_channel_plan(&mut store_a).await.unwrap());
+    assert_eq!(store_a.plan, Some(PLAN));
+    assert_eq!(a.channel_plan(), Some(PLAN));
+    assert_eq!(applied_a.get(), Some(PLAN));
+
+    // A passed the plan on with the bootup
+    b.receive((), &()).await.unwrap();
+    assert!(b.update_channel_plan(&mut store_b).await.unwrap());
+    assert_eq!(store_b.plan, Some(PLAN));
+    assert_eq!(applied_b.get(), Some(PLAN));
+
+    // The same plan again changes nothing
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    assert!(!a.update_channel_plan(&mut store_a).await.unwrap());
+    assert_eq!(store_a.saves, 1);
+}
+
+#[tokio::test]
+async fn test_saved_plan_is_applied_at_boot() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    let mut store = MemoryStore {
+        plan: Some(PLAN),
+        saves: 0,
+    };
+    let applied = Rc::new(Cell::new(None));
+    let mut a = node(&env, NODE_A, &applied);
+    assert_eq!(a.channel_plan(), None);
+
+    a.load_channel_plan(&mut store).await.unwrap();
+    assert_eq!(a.channel_plan(), Some(PLAN));
+    assert_eq!(applied.get(), Some(PLAN));
+    // Nothing new to save
+    assert!(!a.update_channel_plan(&mut store).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_bootup_carries_gateway_id() {
+    const EUI: u64 = 0x0016_C001_FF10_A235;
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    {
+        let mut e = env.borrow_mut();
+        e.add_bidi_link(GW, NODE_A);
+        e.add_bidi_link(NODE_A, NODE_B);
+    }
+    let mut manager = NetworkManager::<SIZE, LEN>::new(GW, 5, 3);
+    manager.set_channel_plan(PLAN);
+    manager.set_gateway_id(Some(EUI));
+    let mut gw = sim::gateway(&env, manager, &[]);
+    let applied = Rc::new(Cell::new(None));
+    let mut a = node(&env, NODE_A, &applied);
+    let mut b = node(&env, NODE_B, &applied);
+    assert_eq!(a.gateway_id(), None);
+
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    assert_eq!(a.gateway_id(), Some(EUI));
+    assert_eq!(b.gateway_id(), Some(EUI));
+    // The plan is read as before
+    assert_eq!(b.channel_plan(), Some(PLAN));
+}
@@ -0,0 +1,173 @@
+//! A single-radio node scanning the channels of a multi-channel gateway, (A) <-> (B), with A
+//! sending on one of them
+use core::cell::RefCell;
+use embassy_futures::yield_now;
+use embassy_time::Duration;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        MHNode, MHPacket,
+        config::ChannelPlan,
+        mesh_router::MeshRouter,
+        network_manager::{NetworkManager, NetworkManagerError},
+        policy::NodePolicy,
+        scan::{ChannelScan, ScanChannel},
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+const PLAN: ChannelPlan = ChannelPlan {
+    frequency_hz: 868_100_000,
+    spreading_factor: 9,
+    bandwidth_khz: 125,
+};
+/// The channel A sends on
+const BUSY_HZ: u32 = 868_500_000;
+const CHANNELS: [ScanChannel; 3] = [
+    ScanChannel {
+        frequency_hz: 868_100_000,
+        weight: 1,
+    },
+    ScanChannel {
+        frequency_hz: 868_300_000,
+        weight: 0,
+    },
+    ScanChannel {
+        frequency_hz: BUSY_HZ,
+        weight: 3,
+    },
+];
+
+/// A SimRadio which only hears frames on `BUSY_HZ`, and remembers every frequency it was tuned to
+struct ScanningRadio {
+    inner: SimRadio<SIZE>,
+    /// Whether it has channel activity detection
+    cad: bool,
+    tuned: Rc<RefCell<std::vec::Vec<u32>>>,
+}
+
+impl ScanningRadio {
+    fn on_busy_channel(&self) -> bool {
+        self.tuned.borrow().last() == Some(&BUSY_HZ)
+    }
+
+    fn frame_waiting(&self) -> bool {
+        let env = self.inner.env.borrow();
+        env.inboxes
+            .get(&self.inner.node_id)
+            .is_some_and(|inbox| !inbox.is_empty())
+    }
+}
+
+impl MHNode<SIZE, LEN> for ScanningRadio {
+    type Error = NetworkManagerError;
+    type Connection = ();
+    type ReceiveBuffer = ();
+    type Duration = u16;
+
+    async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), Self::Error> {
+        MHNode::<SIZE, LEN>::transmit(&mut self.inner, packets).await
+    }
+
+    async fn receive(
+        &mut self,
+        conn: (),
+        rec_buf: &(),
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, Self::Error> {
+        MHNode::<SIZE, LEN>::receive(&mut self.inner, conn, rec_buf).await
+    }
+
+    async fn listen(&mut self, _rec_buf: &mut (), _with_timeout: bool) -> Result<(), Self::Error> {
+        while !(self.on_busy_channel() && self.frame_waiting()) {
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    fn apply_channel_plan(&mut self, plan: &ChannelPlan) -> Result<(), Self::Error> {
+        self.tuned.borrow_mut().push(plan.frequency_hz);
+        Ok(())
+    }
+
+    async fn channel_activity(&mut self) -> Result<Option<bool>, Self::Error> {
+        Ok(self
+            .cad
+            .then(|| self.on_busy_channel() && self.frame_waiting()))
+    }
+}
+
+/// The frequencies B was tuned to until it heard A, and how long it took
+async fn scan_until_heard(cad: bool) -> (std::vec::Vec<u32>, std::time::Duration) {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    let tuned = Rc::new(RefCell::new(std::vec::Vec::new()));
+    let mut b_manager = NetworkManager::<SIZE, LEN>::new(NODE_B, 5, 3);
+    b_manager.set_channel_plan(PLAN);
+    let mut b = MeshRouter::new(
+        ScanningRadio {
+            inner: sim::radio(&env, NODE_B, &[]),
+            cad,
+            tuned: tuned.clone(),
+        },
+        b_manager,
+        NodePolicy,
+    );
+    let scan = ChannelScan::new(&CHANNELS, Duration::from_millis(40)).unwrap();
+    b.set_channel_scan(Some(scan)).unwrap();
+
+    a.send_payload(Vec::from_slice(&[0x07]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    let started = std::time::Instant::now();
+    b.listen(&mut ()).await.unwrap();
+    let took = started.elapsed();
+    let delivered = b.receive((), &()).await.unwrap();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].packet.payload, [0x07]);
+    (tuned.borrow().clone(), took)
+}
+
+#[tokio::test]
+async fn test_scan_finds_the_busy_channel() {
+    // Without detection the quiet first channel is listened on for its whole dwell, a quarter of
+    // the cycle, and the channel without a weight is never tuned to
+    let (tuned, took) = scan_until_heard(false).await;
+    assert_eq!(tuned, [868_100_000, BUSY_HZ]);
+    assert!(took >= std::time::Duration::from_millis(10));
+    // Detection finds the first channel quiet and moves on
+    let (tuned, _) = scan_until_heard(true).await;
+    assert_eq!(tuned, [868_100_000, BUSY_HZ]);
+}
+
+#[test]
+fn test_dwell_follows_the_expected_traffic() {
+    let mut scan = ChannelScan::new(&CHANNELS, Duration::from_millis(400)).unwrap();
+    assert_eq!(scan.current().frequency_hz, 868_100_000);
+    assert_eq!(scan.dwell(), Duration::from_millis(100));
+    scan.advance();
+    assert_eq!(scan.current().frequency_hz, BUSY_HZ);
+    assert_eq!(scan.dwell(), Duration::from_millis(300));
+    scan.advance();
+    assert_eq!(scan.current().frequency_hz, 868_100_000);
+    assert_eq!(
+        scan.on_current(&PLAN),
+        ChannelPlan {
+            frequency_hz: 868_100_000,
+            ..PLAN
+        }
+    );
+
+    let quiet = [ScanChannel {
+        frequency_hz: 868_100_000,
+        weight: 0,
+    }];
+    assert_eq!(ChannelScan::new(&quiet, Duration::from_millis(400)), None);
+}
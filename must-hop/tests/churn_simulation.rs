@@ -0,0 +1,103 @@
+//! A line of nodes which changes while the gateway keeps booting up: a relay leaves, the
+//! gateway moves to the other end, and a new node joins. Every time, the nodes must find their
+//! new distance to the gateway within one bootup interval.
+use core::cell::RefCell;
+use embassy_time::Duration;
+use must_hop::{
+    node::{mesh_router::MeshRouter, network_manager::NetworkManager, policy::NodePolicy},
+    sim::{self, Schedule, SimRadio, SimulationEnv, TopologyChange},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+const NODE_C: u8 = 4;
+const NODE_D: u8 = 5;
+
+/// Steps between two bootups of the gateway
+const BOOTUP_EVERY: u32 = 4;
+const STEPS: u32 = 30;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Router = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+
+fn node(env: &Env, node_id: u8) -> (u8, Router) {
+    let mut manager = NetworkManager::new(node_id, 5, 3);
+    // Long enough to never expire here, but every new bootup replaces the path
+    manager.set_gateway_ttl(Some(Duration::from_secs(60)));
+    (node_id, sim::node(env, manager, None))
+}
+
+#[tokio::test]
+async fn test_reconverges_after_churn() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    {
+        let mut e = env.borrow_mut();
+        e.add_bidi_link(GW, NODE_A);
+        e.add_bidi_link(NODE_A, NODE_B);
+        e.add_bidi_link(NODE_B, NODE_C);
+    }
+    // B is switched off and the gateway drives from A to C, which moves next to A. Later D
+    // turns up next to A.
+    let schedule = Schedule::new()
+        .at(10, TopologyChange::Leave(NODE_B))
+        .at(10, TopologyChange::Unlink(GW, NODE_A))
+        .at(10, TopologyChange::Link(GW, NODE_C))
+        .at(10, TopologyChange::Link(NODE_A, NODE_C))
+        .at(21, TopologyChange::Link(NODE_A, NODE_D));
+    // The hops to the gateway every node should end up with, from the step the topology changed
+    let phases: [(u32, &[(u8, u8)]); 3] = [
+        (0, &[(NODE_A, 1), (NODE_B, 2), (NODE_C, 3)]),
+        (10, &[(NODE_A, 2), (NODE_C, 1)]),
+        (21, &[(NODE_A, 2), (NODE_C, 1), (NODE_D, 3)]),
+    ];
+    assert!(
+        schedule
+            .steps()
+            .all(|step| phases.iter().any(|(at, _)| *at == step))
+    );
+
+    let mut gw = sim::gateway(&env, NetworkManager::<SIZE, LEN>::new(GW, 5, 3), &[]);
+    let mut nodes = vec![node(&env, NODE_A), node(&env, NODE_B), node(&env, NODE_C)];
+    let mut converged_after = [None; 3];
+
+    for step in 0..STEPS {
+        schedule.apply(step, &mut env.borrow_mut());
+        if step == 21 {
+            nodes.push(node(&env, NODE_D));
+        }
+        if step % BOOTUP_EVERY == 0 {
+            gw.bootup().await.unwrap();
+        }
+        gw.receive((), &()).await.unwrap();
+        for (_, router) in nodes.iter_mut() {
+            router.receive((), &()).await.unwrap();
+        }
+
+        let phase = phases.iter().rposition(|(at, _)| step >= *at).unwrap();
+        let (started, expected) = phases[phase];
+        let converged = expected.iter().all(|(id, hops)| {
+            nodes
+                .iter()
+                .find(|(node_id, _)| node_id == id)
+                .is_some_and(|(_, router)| router.gw_hops() == *hops)
+        });
+        if converged && converged_after[phase].is_none() {
+            converged_after[phase] = Some(step - started);
+        }
+    }
+
+    for (phase, steps) in converged_after.into_iter().enumerate() {
+        let steps = steps.unwrap_or_else(|| panic!("phase {} never converged", phase));
+        assert!(
+            steps <= BOOTUP_EVERY,
+            "phase {} took {} steps to converge",
+            phase,
+            steps
+        );
+    }
+}
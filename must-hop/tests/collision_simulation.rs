@@ -0,0 +1,115 @@
+//! Compares channel access policies on the same traffic, with frames lost when they overlap.
+use embassy_time::Duration;
+use must_hop::{
+    node::airtime::Modulation,
+    sim::collision::{Aloha, CollisionEnv, Csma, MacPolicy, Offer, RandomDelay, Tdma},
+    units::Dbm,
+};
+
+const GW: u8 = 1;
+const NODES: core::ops::RangeInclusive<u8> = 2..=9;
+const HIDDEN: u8 = 9;
+const LEN: usize = 20;
+
+#[test]
+fn test_airtime_matches_semtech_calculator() {
+    assert_eq!(
+        Modulation::new(7, 125).airtime(10),
+        Duration::from_micros(41_216)
+    );
+    // Low data rate optimization is on from SF11 at 125 kHz
+    assert_eq!(
+        Modulation::new(12, 125).airtime(10),
+        Duration::from_micros(991_232)
+    );
+    assert_eq!(
+        Modulation::new(7, 125).symbol_time(),
+        Duration::from_micros(1024)
+    );
+}
+
+#[test]
+fn test_stronger_frame_is_captured() {
+    let mut env = CollisionEnv::new(Modulation::new(7, 125));
+    env.add_link(2, GW, Dbm(-80));
+    env.add_link(3, GW, Dbm(-90));
+    let at_once = |node| Offer {
+        node,
+        destination: GW,
+        ready_at: Duration::from_millis(0),
+        len: LEN,
+    };
+    assert_eq!(env.run(&mut Aloha, &[at_once(2)]).delivered, 1);
+    // 10 dB apart, so only the stronger one survives
+    let report = env.run(&mut Aloha, &[at_once(2), at_once(3)]);
+    assert_eq!((report.offered, report.delivered), (2, 1));
+    // But not when they are as strong
+    env.add_link(3, GW, Dbm(-82));
+    assert_eq!(env.run(&mut Aloha, &[at_once(2), at_once(3)]).delivered, 0);
+}
+
+#[test]
+fn test_compare_policies() {
+    let modulation = Modulation::new(7, 125);
+    let mut env = CollisionEnv::new(modulation);
+    for node in NODES {
+        env.add_link(node, GW, Dbm(-100));
+    }
+    // Everyone hears each other, except HIDDEN which hears nobody but the gateway
+    for a in NODES.filter(|a| *a != HIDDEN) {
+        for b in NODES.filter(|b| *b > a && *b != HIDDEN) {
+            env.add_bidi_link(a, b, Dbm(-90));
+        }
+    }
+    // Every node reporting at the same moment, like after a power cut
+    let offers: Vec<Offer> = NODES
+        .flat_map(|node| {
+            Offer::periodic(
+                node,
+                GW,
+                Duration::from_millis(0),
+                Duration::from_secs(2),
+                10,
+                LEN,
+            )
+        })
+        .collect();
+
+    let slot = modulation.airtime(LEN) + Duration::from_millis(5);
+    let mut aloha = Aloha;
+    let mut random = RandomDelay::new(Duration::from_secs(1), 7);
+    let mut csma = Csma::new(Duration::from_millis(100), 7);
+    let mut tdma = Tdma { slot, slots: 16 };
+    let mut policies: [(&str, &mut dyn MacPolicy); 4] = [
+        ("aloha", &mut aloha),
+        ("random", &mut random),
+        ("csma", &mut csma),
+        ("tdma", &mut tdma),
+    ];
+    let reports = env.compare(&mut policies, &offers);
+    for (name, report) in &reports {
+        println!(
+            "{}: {:.2} delivered, {:?} mean latency",
+            name,
+            report.delivery_ratio(),
+            report.mean_latency()
+        );
+        assert_eq!(report.offered, offers.len());
+    }
+    let ratio = |name| {
+        reports
+            .iter()
+            .find(|(n, _)| *n == name)
+            .unwrap()
+            .1
+            .delivery_ratio()
+    };
+    assert_eq!(ratio("aloha"), 0.0);
+    assert!(ratio("random") > ratio("aloha"));
+    // Carrier sense spreads out all but the hidden node, which hits the first frame every time
+    assert_eq!(ratio("csma"), 0.75);
+    assert_eq!(ratio("tdma"), 1.0);
+    // Waiting for a slot costs latency, but never more than a cycle and a frame
+    let tdma_latency = reports[3].1.mean_latency().unwrap();
+    assert!(tdma_latency <= slot * 17);
+}
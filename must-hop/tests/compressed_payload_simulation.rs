@@ -0,0 +1,48 @@
+//! A node sends another blobs compressed, (A) <-> (B), which B receives decompressed, except one
+//! too large for a packet once decompressed, which B receives as it was sent.
+use core::cell::RefCell;
+use must_hop::{
+    compress,
+    node::{flags, network_manager::NetworkManager},
+    sim::{self, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 100;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+const CONFIG: &[u8] =
+    br#"{"sensors": ["temperature", "humidity"], "temperature": 30, "humidity": 80}"#;
+
+#[tokio::test]
+async fn test_compressed_blobs_are_received_decompressed() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    let mut b = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_B, 5, 3), None);
+    a.send_compressed(CONFIG, NODE_B).await.unwrap();
+    let received = b.receive((), &()).await.unwrap();
+    assert_eq!(received.len(), 1);
+    let packet = &received[0].packet;
+    assert_eq!(packet.payload, CONFIG);
+    assert!(!packet.has_flag(flags::COMPRESSED));
+
+    // Too large for a packet once decompressed, so B decompresses it itself
+    let large = [b'a'; 300];
+    a.send_compressed(&large, NODE_B).await.unwrap();
+    let received = b.receive((), &()).await.unwrap();
+    let packet = &received[0].packet;
+    assert!(packet.has_flag(flags::COMPRESSED));
+    let mut out = [0u8; 300];
+    assert_eq!(compress::decompress(&packet.payload, &mut out), Ok(300));
+    assert_eq!(out, large);
+
+    // Incompressible, sent as it is
+    a.send_compressed(&[1, 2, 3], NODE_B).await.unwrap();
+    let received = b.receive((), &()).await.unwrap();
+    assert_eq!(received[0].packet.payload, [1, 2, 3]);
+    assert!(!received[0].packet.has_flag(flags::COMPRESSED));
+}
@@ -0,0 +1,58 @@
+//! Blobs compressed for nodes come back out the same
+use must_hop::compress::{CompressError, compress, decompress};
+
+const CONFIG: &[u8] = br#"{"interval_s": 60, "sensors": ["temperature", "humidity", "voltage"],
+"thresholds": {"temperature": 30, "humidity": 80, "voltage": 3300}, "report": "on_change"}"#;
+
+fn roundtrip(blob: &[u8]) -> usize {
+    let mut packed = [0u8; 512];
+    let packed_len = compress(blob, &mut packed).unwrap();
+    let mut unpacked = [0u8; 512];
+    let unpacked_len = decompress(&packed[..packed_len], &mut unpacked).unwrap();
+    assert_eq!(&unpacked[..unpacked_len], blob);
+    packed_len
+}
+
+#[test]
+fn test_text_shrinks() {
+    let packed_len = roundtrip(CONFIG);
+    assert!(packed_len * 10 < CONFIG.len() * 8, "{packed_len} bytes");
+}
+
+#[test]
+fn test_roundtrips() {
+    assert_eq!(roundtrip(&[]), 0);
+    roundtrip(&[0x42]);
+    // Long runs are matches overlapping what they write
+    roundtrip(&[0xAA; 300]);
+    let noise: Vec<u8> = (0..200u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    roundtrip(&noise);
+}
+
+#[test]
+fn test_small_buffers() {
+    let mut packed = [0u8; 8];
+    assert_eq!(
+        compress(CONFIG, &mut packed),
+        Err(CompressError::OutputFull)
+    );
+
+    let mut packed = [0u8; 512];
+    let packed_len = compress(CONFIG, &mut packed).unwrap();
+    let mut unpacked = [0u8; 16];
+    assert_eq!(
+        decompress(&packed[..packed_len], &mut unpacked),
+        Err(CompressError::OutputFull)
+    );
+}
+
+#[test]
+fn test_match_before_start() {
+    // A match 1 byte back, with nothing written yet
+    assert_eq!(
+        decompress(&[0x00, 0x00], &mut [0u8; 16]),
+        Err(CompressError::BadMatch)
+    );
+}
@@ -0,0 +1,83 @@
+//! What the gateway learns about how uplinks arrived, on (GW) <-> (A) <-> (B) with every node
+//! recording routes
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        PacketType,
+        delivered::Delivered,
+        mesh_router::MeshRouter,
+        network_manager::NetworkManager,
+        policy::{GatewayPolicy, NodePolicy, RoutingPolicy},
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+
+fn router<P: RoutingPolicy<SIZE, LEN>>(
+    env: &Env,
+    node_id: u8,
+    policy: P,
+) -> MeshRouter<SimRadio<SIZE>, SIZE, LEN, P> {
+    let mut manager = NetworkManager::<SIZE, LEN>::new(node_id, 5, 3);
+    manager.set_record_route(true);
+    MeshRouter::new(sim::radio(env, node_id, &[]), manager, policy)
+}
+
+/// The one data packet in `delivered`
+fn uplink(delivered: Vec<Delivered<SIZE>, LEN>) -> Delivered<SIZE> {
+    let mut data = delivered
+        .into_iter()
+        .filter(|delivered| delivered.packet.packet_type == PacketType::Data);
+    let uplink = data.next().expect("no uplink");
+    assert!(data.next().is_none());
+    uplink
+}
+
+#[tokio::test]
+async fn test_uplinks_tell_how_they_arrived() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut gw = router(&env, GW, GatewayPolicy);
+    let mut a = router(&env, NODE_A, NodePolicy);
+    let mut b = router(&env, NODE_B, NodePolicy);
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    a.receive((), &()).await.unwrap();
+    gw.receive((), &()).await.unwrap();
+    a.receive((), &()).await.unwrap();
+    gw.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    assert_eq!(env.borrow().in_flight(), 0);
+
+    // Through A, which added itself to the route
+    b.send_payload(Vec::from_slice(&[0x0B]).unwrap(), GW)
+        .await
+        .unwrap();
+    a.receive((), &()).await.unwrap();
+    let relayed = uplink(gw.receive((), &()).await.unwrap());
+    assert_eq!(relayed.packet.source_id, NODE_B);
+    assert_eq!((relayed.relayed_by, relayed.hops), (Some(NODE_A), Some(2)));
+    // The simulator can't tell how strong a frame was
+    assert_eq!(relayed.link, None);
+
+    // Straight from A, with nobody on the route
+    a.send_payload(Vec::from_slice(&[0x0A]).unwrap(), GW)
+        .await
+        .unwrap();
+    let direct = uplink(gw.receive((), &()).await.unwrap());
+    assert_eq!(direct.packet.source_id, NODE_A);
+    assert_eq!((direct.relayed_by, direct.hops), (None, Some(1)));
+    assert!(direct.received_at >= relayed.received_at);
+}
@@ -0,0 +1,107 @@
+//! Errors carry the layer, an error code and the packet which failed
+use core::error::Error;
+use core::fmt;
+use heapless::Vec;
+use must_hop::node::{
+    MHNode, MHPacket, PacketRef,
+    mesh_router::{MeshRouter, MeshRouterError},
+    network_manager::{NetworkManager, NetworkManagerError},
+    policy::NodePolicy,
+};
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+const NODE: u8 = 2;
+
+#[derive(Debug, PartialEq)]
+struct RadioDown;
+
+impl fmt::Display for RadioDown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "radio down")
+    }
+}
+
+impl Error for RadioDown {}
+
+/// A radio which fails everything
+struct FailingRadio;
+
+impl MHNode<SIZE, LEN> for FailingRadio {
+    type Error = RadioDown;
+    type Connection = ();
+    type ReceiveBuffer = ();
+    type Duration = u16;
+
+    async fn transmit(&mut self, _packets: &[MHPacket<SIZE>]) -> Result<(), RadioDown> {
+        Err(RadioDown)
+    }
+
+    async fn receive(
+        &mut self,
+        _conn: (),
+        _rec_buf: &(),
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, RadioDown> {
+        Err(RadioDown)
+    }
+
+    async fn listen(&mut self, _rec_buf: &mut (), _with_timeout: bool) -> Result<(), RadioDown> {
+        Err(RadioDown)
+    }
+}
+
+#[tokio::test]
+async fn test_transmit_error_names_packet() {
+    let mut router = MeshRouter::new(
+        FailingRadio,
+        NetworkManager::<SIZE, LEN>::new(NODE, 5, 3),
+        NodePolicy,
+    );
+    let err = router
+        .send_payload(Vec::from_slice(&[1, 2, 3]).unwrap(), 1)
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), 0x201);
+    let packet = err.packet().unwrap();
+    assert_eq!(packet.source_id, NODE);
+    let line = err.to_string();
+    assert!(line.starts_with("E0x201 node:"), "{line}");
+    assert!(line.contains(&packet.to_string()), "{line}");
+    let source = err.source().unwrap();
+    assert_eq!(source.downcast_ref::<RadioDown>(), Some(&RadioDown));
+}
+
+#[tokio::test]
+async fn test_listen_error_has_no_packet() {
+    let mut router = MeshRouter::new(
+        FailingRadio,
+        NetworkManager::<SIZE, LEN>::new(NODE, 5, 3),
+        NodePolicy,
+    );
+    let err = router.listen(&mut ()).await.unwrap_err();
+    assert!(matches!(err, MeshRouterError::Node(RadioDown)));
+    assert_eq!(err.code(), 0x200);
+    assert_eq!(err.packet(), None);
+}
+
+#[test]
+fn test_manager_error_code_and_packet() {
+    let err: MeshRouterError<RadioDown> = NetworkManagerError::PayloadTooLarge {
+        source_id: 3,
+        packet_id: 17,
+        len: 60,
+        max_payload: 64,
+    }
+    .into();
+    assert_eq!(err.code(), 0x106);
+    assert_eq!(
+        err.packet(),
+        Some(PacketRef {
+            source_id: 3,
+            packet_id: 17
+        })
+    );
+    assert!(err.to_string().starts_with("E0x106 manager: packet 3:17"));
+    assert!(err.source().unwrap().is::<NetworkManagerError>());
+}
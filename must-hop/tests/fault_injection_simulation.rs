@@ -0,0 +1,136 @@
+//! Faults injected into the simulated radios, on (A) <-> (B): lost, corrupted and late frames,
+//! and a radio which stops answering
+use core::cell::RefCell;
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use must_hop::{
+    node::{
+        mesh_router::{MeshRouter, MeshRouterError},
+        network_manager::{NetworkManager, NetworkManagerError},
+        policy::NodePolicy,
+    },
+    sim::{
+        self, SimRadio, SimulationEnv,
+        faults::{Fault, RadioCall},
+    },
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Router = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+
+fn pair() -> (Env, Router, Router) {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let router = |node_id| sim::node(&env, NetworkManager::<SIZE, LEN>::new(node_id, 1, 3), None);
+    let a = router(NODE_A);
+    let b = router(NODE_B);
+    (env, a, b)
+}
+
+#[tokio::test]
+async fn test_dropped_frame_is_retransmitted() {
+    let (env, mut a, mut b) = pair();
+    env.borrow_mut().inject(NODE_A, Fault::DropTx(1));
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    // Sent, but lost on the way
+    assert_eq!(env.borrow().log.len(), 1);
+    assert_eq!(env.borrow().in_flight(), 0);
+    assert!(env.borrow().faults[&NODE_A].is_empty());
+
+    // B doesn't answer, so A retries once the timeout passed
+    let summary = a
+        .quiesce(&mut (), Duration::from_millis(1500))
+        .await
+        .unwrap();
+    assert_eq!(summary.retransmitted, 1);
+    let received = b.receive((), &()).await.unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].packet.payload, [0x01]);
+}
+
+#[tokio::test]
+async fn test_corrupted_payload_is_received() {
+    let (env, mut a, mut b) = pair();
+    env.borrow_mut().inject(
+        NODE_B,
+        Fault::CorruptRx {
+            packets: 1,
+            index: 1,
+            mask: 0xFF,
+        },
+    );
+    a.send_payload(Vec::from_slice(&[0x01, 0x02]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    let received = b.receive((), &()).await.unwrap();
+    assert_eq!(received[0].packet.payload, [0x01, 0xFD]);
+}
+
+#[tokio::test]
+async fn test_nth_call_fails() {
+    let (env, mut a, mut b) = pair();
+    env.borrow_mut().inject(
+        NODE_A,
+        Fault::Fail {
+            call: RadioCall::Transmit,
+            nth: 2,
+        },
+    );
+    let mut results = [true; 3];
+    for (i, ok) in results.iter_mut().enumerate() {
+        *ok = a
+            .send_payload(Vec::from_slice(&[i as u8]).unwrap(), NODE_B)
+            .await
+            .is_ok();
+    }
+    assert_eq!(results, [true, false, true]);
+    assert_eq!(env.borrow().log.len(), 2);
+
+    env.borrow_mut().inject(
+        NODE_B,
+        Fault::Fail {
+            call: RadioCall::Receive,
+            nth: 1,
+        },
+    );
+    assert!(matches!(
+        b.receive((), &()).await,
+        Err(MeshRouterError::Node(NetworkManagerError::Timeout))
+    ));
+    // Nothing was taken from its inbox meanwhile
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_delayed_transmit() {
+    let (env, mut a, mut b) = pair();
+    env.borrow_mut().inject(
+        NODE_A,
+        Fault::DelayTx {
+            frames: 1,
+            delay: Duration::from_millis(50),
+        },
+    );
+    let start = Instant::now();
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 1);
+
+    // Only that frame
+    let start = Instant::now();
+    a.send_payload(Vec::from_slice(&[0x02]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_millis(50));
+}
@@ -0,0 +1,133 @@
+//! Uplinks, their ACKs, a downlink command and its ACK end to end through a gateway listening on
+//! two channels: (GW) <-> (A) <-> (B) on one channel, and (GW) <-> (C) on the other. A and C are
+//! in range of each other, but don't hear each other across channels.
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        MHPacket, PacketType,
+        delivered::Delivered,
+        mesh_router::MeshRouter,
+        network_manager::NetworkManager,
+        policy::{GatewayPolicy, NodePolicy},
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+const NODE_C: u8 = 4;
+
+const CHANNEL_1: u32 = 868_100_000;
+const CHANNEL_2: u32 = 868_300_000;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Node = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+type Gateway = MeshRouter<SimRadio<SIZE>, SIZE, LEN, GatewayPolicy>;
+
+fn node(env: &Env, node_id: u8, channel: u32) -> Node {
+    sim::node(env, NetworkManager::new(node_id, 5, 3), Some(channel))
+}
+
+/// The data packets in `pkts`, as the application sees them
+fn data(pkts: Vec<Delivered<SIZE>, LEN>) -> std::vec::Vec<MHPacket<SIZE>> {
+    pkts.into_iter()
+        .map(|delivered| delivered.packet)
+        .filter(|pkt| pkt.packet_type == PacketType::Data)
+        .collect()
+}
+
+async fn joined_mesh(env: &Env) -> (Gateway, Node, Node, Node) {
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    env.borrow_mut().add_bidi_link(GW, NODE_C);
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_C);
+    let mut gw = sim::gateway(
+        env,
+        NetworkManager::<SIZE, LEN>::new(GW, 5, 3),
+        &[CHANNEL_1, CHANNEL_2],
+    );
+    let mut a = node(env, NODE_A, CHANNEL_1);
+    let mut b = node(env, NODE_B, CHANNEL_1);
+    let mut c = node(env, NODE_C, CHANNEL_2);
+
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    // A's bootup went out on the first channel only, so C only heard the gateway's
+    assert_eq!(env.borrow().inboxes[&NODE_C].len(), 1);
+    c.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    assert_eq!((a.gw_hops(), b.gw_hops(), c.gw_hops()), (1, 2, 1));
+    // Let what the nodes flooded back, and the gateway's answers to it, settle
+    a.receive((), &()).await.unwrap();
+    gw.receive((), &()).await.unwrap();
+    a.receive((), &()).await.unwrap();
+    c.receive((), &()).await.unwrap();
+    gw.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    assert_eq!(env.borrow().in_flight(), 0);
+    (gw, a, b, c)
+}
+
+#[tokio::test]
+async fn test_uplink_and_downlink_across_hops() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    let (mut gw, mut a, mut b, _c) = joined_mesh(&env).await;
+
+    // Uplink from B through A. The gateway's ACK confirms it to A, and A relaying it to B
+    b.send_payload(Vec::from_slice(&[0x10]).unwrap(), GW)
+        .await
+        .unwrap();
+    a.receive((), &()).await.unwrap();
+    let uplinks = data(gw.receive((), &()).await.unwrap());
+    assert_eq!(uplinks.len(), 1);
+    assert_eq!(
+        (uplinks[0].source_id, &uplinks[0].payload[..]),
+        (NODE_B, &[0x10][..])
+    );
+    a.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    assert_eq!(b.get_pending_count(), 0);
+
+    // A downlink command to B. A relaying it confirms it to the gateway, and B's ACK to A
+    gw.send_payload(Vec::from_slice(&[0x20]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    assert_eq!(gw.get_pending_count(), 1);
+    a.receive((), &()).await.unwrap();
+    let commands = data(b.receive((), &()).await.unwrap());
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].payload, [0x20]);
+    a.receive((), &()).await.unwrap();
+    assert_eq!(a.get_pending_count(), 0);
+    gw.receive((), &()).await.unwrap();
+    assert_eq!(gw.get_pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_gateway_hears_both_channels() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    let (mut gw, mut a, _b, mut c) = joined_mesh(&env).await;
+
+    a.send_payload(Vec::from_slice(&[0x0A]).unwrap(), GW)
+        .await
+        .unwrap();
+    c.send_payload(Vec::from_slice(&[0x0C]).unwrap(), GW)
+        .await
+        .unwrap();
+    // Neither heard the other, though they are in range
+    assert!(env.borrow().inboxes[&NODE_A].is_empty());
+    assert!(env.borrow().inboxes[&NODE_C].is_empty());
+    let uplinks = data(gw.receive((), &()).await.unwrap());
+    let mut sources: std::vec::Vec<u8> = uplinks.iter().map(|pkt| pkt.source_id).collect();
+    sources.sort_unstable();
+    assert_eq!(sources, [NODE_A, NODE_C]);
+    // C, alone on the second channel, gets its ACK there
+    c.receive((), &()).await.unwrap();
+    assert_eq!(c.get_pending_count(), 0);
+}
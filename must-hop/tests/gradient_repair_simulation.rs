@@ -0,0 +1,98 @@
+//! A relay dying under the nodes behind it: (GW) <-> (A) <-> (B) <-> (E), with a longer way
+//! round (GW) <-> (C) <-> (D) <-> (B). Every node watches the parent it reaches the gateway
+//! through.
+use core::cell::RefCell;
+use embassy_time::Duration;
+use heapless::Vec;
+use must_hop::{
+    node::{mesh_router::MeshRouter, network_manager::NetworkManager, policy::NodePolicy},
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 8;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+const NODE_C: u8 = 4;
+const NODE_D: u8 = 5;
+const NODE_E: u8 = 6;
+
+const PARENT_TIMEOUT_MS: u64 = 200;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Router = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+
+fn manager(node_id: u8) -> NetworkManager<SIZE, LEN> {
+    let mut manager = NetworkManager::new(node_id, 5, 3);
+    manager.set_parent_timeout(Some(Duration::from_millis(PARENT_TIMEOUT_MS)));
+    manager
+}
+
+/// The hops to the gateway which `router` puts in the packets it sends now
+async fn hops_to_gw(env: &Env, router: &mut Router) -> u8 {
+    router.send_payload(Vec::new(), GW).await.unwrap();
+    let mut e = env.borrow_mut();
+    // Only the header matters, so the packet is taken off the air again
+    e.inboxes.values_mut().for_each(std::vec::Vec::clear);
+    e.log.last().unwrap().packet.hop_to_gw
+}
+
+#[tokio::test]
+async fn test_nodes_route_around_dead_relay() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    {
+        let mut e = env.borrow_mut();
+        e.add_bidi_link(GW, NODE_A);
+        e.add_bidi_link(NODE_A, NODE_B);
+        e.add_bidi_link(NODE_B, NODE_E);
+        e.add_bidi_link(GW, NODE_C);
+        e.add_bidi_link(NODE_C, NODE_D);
+        e.add_bidi_link(NODE_D, NODE_B);
+    }
+    let mut gw = sim::gateway(&env, NetworkManager::<SIZE, LEN>::new(GW, 5, 3), &[]);
+    let mut a = sim::node(&env, manager(NODE_A), None);
+    let mut b = sim::node(&env, manager(NODE_B), None);
+    let mut c = sim::node(&env, manager(NODE_C), None);
+    let mut d = sim::node(&env, manager(NODE_D), None);
+    let mut e = sim::node(&env, manager(NODE_E), None);
+
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    c.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    d.receive((), &()).await.unwrap();
+    e.receive((), &()).await.unwrap();
+    assert_eq!(hops_to_gw(&env, &mut a).await, 1);
+    assert_eq!(hops_to_gw(&env, &mut b).await, 2);
+    assert_eq!(hops_to_gw(&env, &mut e).await, 3);
+    assert_eq!(hops_to_gw(&env, &mut d).await, 2);
+
+    // A dies. B's uplinks keep E hearing its parent, until B gives up on A and E follows
+    env.borrow_mut().isolate(NODE_A);
+    for _ in 0..4 {
+        std::thread::sleep(std::time::Duration::from_millis(PARENT_TIMEOUT_MS / 2));
+        b.send_payload(Vec::from_slice(&[0x0B]).unwrap(), GW)
+            .await
+            .unwrap();
+        e.receive((), &()).await.unwrap();
+        // Nobody relays towards the dead end
+        assert!(env.borrow().inboxes[&NODE_B].is_empty());
+        env.borrow_mut()
+            .inboxes
+            .values_mut()
+            .for_each(std::vec::Vec::clear);
+    }
+    assert_eq!(hops_to_gw(&env, &mut e).await, u8::MAX);
+
+    // The gateway's next bootup comes round the long way
+    gw.bootup().await.unwrap();
+    c.receive((), &()).await.unwrap();
+    d.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    e.receive((), &()).await.unwrap();
+    assert_eq!(hops_to_gw(&env, &mut b).await, 3);
+    assert_eq!(hops_to_gw(&env, &mut e).await, 4);
+}
@@ -0,0 +1,131 @@
+//! A downlink to a group, on (GW) <-> (A) <-> (B) where only B is in the group: B tells the
+//! gateway when it joins, A floods the packet on without delivering it, and B delivers it.
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        delivered::Delivered,
+        group::{self, FIRST_GROUP, GroupError, GroupRegistry},
+        mesh_router::{MeshRouter, MeshRouterError},
+        network_manager::{NetworkManager, NetworkManagerError},
+        policy::NodePolicy,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+
+fn node(env: &Env, node_id: u8) -> MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy> {
+    sim::node(env, NetworkManager::new(node_id, 5, 3), None)
+}
+
+#[tokio::test]
+async fn test_group_downlink_reaches_only_subscribers() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut gw = sim::gateway(&env, NetworkManager::<SIZE, LEN>::new(GW, 5, 3), &[]);
+    let mut a = node(&env, NODE_A);
+    let mut b = node(&env, NODE_B);
+    let mut groups = GroupRegistry::<8>::new();
+    let sensors = groups.register("temperature-sensors").unwrap();
+    assert_eq!(sensors, FIRST_GROUP);
+
+    // Nothing is sent before B knows a gateway
+    b.subscribe(sensors).await.unwrap();
+    assert_eq!(env.borrow().in_flight(), 0);
+
+    // B joins through A, and its subscription is relayed to the gateway
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    a.receive((), &()).await.unwrap();
+    let delivered = gw.receive((), &()).await.unwrap();
+    let (source, subscribed) = delivered
+        .iter()
+        .map(|delivered| &delivered.packet)
+        .find_map(|pkt| group::subscription(pkt).map(|groups| (pkt.source_id, groups)))
+        .expect("B's subscription reached the gateway");
+    groups.subscribe(source, subscribed).unwrap();
+    assert_eq!(
+        groups.members(sensors).collect::<std::vec::Vec<_>>(),
+        [NODE_B]
+    );
+
+    let payload: Vec<u8, SIZE> = Vec::from_slice(&[0x42]).unwrap();
+    gw.send_to_group(payload, sensors).await.unwrap();
+    // A isn't in the group, so it only floods the packet on
+    let to_group = |pkts: Vec<Delivered<SIZE>, LEN>| -> std::vec::Vec<_> {
+        pkts.into_iter()
+            .map(|delivered| delivered.packet)
+            .filter(|pkt| pkt.destination_id == sensors)
+            .collect()
+    };
+    assert!(to_group(a.receive((), &()).await.unwrap()).is_empty());
+    let delivered = to_group(b.receive((), &()).await.unwrap());
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, [0x42]);
+    // Group packets aren't ACKed, so nothing waits for one
+    assert_eq!(gw.get_pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_only_group_addresses_are_subscribed_to() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    let mut a = node(&env, NODE_A);
+    assert!(matches!(
+        a.subscribe(NODE_B).await,
+        Err(MeshRouterError::Manager(NetworkManagerError::NotAGroup(
+            NODE_B
+        )))
+    ));
+    assert!(matches!(
+        a.send_to_group(Vec::new(), u8::MAX).await,
+        Err(MeshRouterError::Manager(NetworkManagerError::NotAGroup(
+            u8::MAX
+        )))
+    ));
+}
+
+#[test]
+fn test_registry_keeps_the_latest_subscription() {
+    let mut groups = GroupRegistry::<2>::new();
+    let sensors = groups.register("sensors").unwrap();
+    let valves = groups.register("valves").unwrap();
+    assert_eq!(groups.register("sensors"), Ok(sensors));
+    assert_eq!(groups.name(valves), Some("valves"));
+    assert_eq!(
+        groups.register("a-name-longer-than-the-limit"),
+        Err(GroupError::NameTooLong)
+    );
+
+    groups
+        .subscribe(NODE_A, group::bit(sensors) | group::bit(valves))
+        .unwrap();
+    groups.subscribe(NODE_B, group::bit(valves)).unwrap();
+    assert_eq!(
+        groups.subscribe(4, group::bit(valves)),
+        Err(GroupError::Full)
+    );
+    // A node's subscription replaces what it was in before
+    groups.subscribe(NODE_A, group::bit(sensors)).unwrap();
+    assert_eq!(
+        groups.members(valves).collect::<std::vec::Vec<_>>(),
+        [NODE_B]
+    );
+    assert_eq!(
+        group::addresses(groups.groups_of(NODE_A)).collect::<std::vec::Vec<_>>(),
+        [sensors]
+    );
+    // And no groups forgets it
+    groups.subscribe(NODE_A, 0).unwrap();
+    assert_eq!(groups.members(sensors).count(), 0);
+}
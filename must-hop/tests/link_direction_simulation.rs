@@ -0,0 +1,130 @@
+//! Loopback tests of IQ inversion, where a radio only hears packets sent with the same polarity
+//! as it is listening with.
+use heapless::Vec;
+use must_hop::node::{
+    LinkDirection, MHNode, MHPacket,
+    mesh_router::MeshRouter,
+    network_manager::{NetworkManager, NetworkManagerError},
+    policy::NodePolicy,
+};
+use std::sync::{Arc, Mutex};
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+/// Packets on the air, with whether they were sent with inverted IQ
+type Air = Arc<Mutex<std::vec::Vec<(bool, MHPacket<SIZE>)>>>;
+
+struct IqRadio {
+    air: Air,
+    direction: LinkDirection,
+}
+
+impl MHNode<SIZE, LEN> for IqRadio {
+    type Error = NetworkManagerError;
+    type Connection = ();
+    type ReceiveBuffer = ();
+    type Duration = u16;
+
+    async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), Self::Error> {
+        let mut air = self.air.lock().unwrap();
+        for pkt in packets {
+            air.push((self.direction.iq_inverted(), pkt.clone()));
+        }
+        Ok(())
+    }
+
+    async fn receive(
+        &mut self,
+        _conn: Self::Connection,
+        _receiving_buffer: &(),
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, Self::Error> {
+        let mut air = self.air.lock().unwrap();
+        let mut rec_vec: Vec<MHPacket<SIZE>, LEN> = Vec::new();
+        // Packets with the other polarity can't be demodulated, and are lost to this radio
+        let iq = self.direction.iq_inverted();
+        air.retain(|(inverted, pkt)| {
+            if *inverted == iq && !rec_vec.is_full() {
+                rec_vec.push(pkt.clone()).unwrap();
+                false
+            } else {
+                true
+            }
+        });
+        Ok(rec_vec)
+    }
+
+    async fn listen(
+        &mut self,
+        _receiving_buffer: &mut (),
+        _with_timeout: bool,
+    ) -> Result<Self::Connection, Self::Error> {
+        Ok(())
+    }
+}
+
+fn router(
+    air: &Air,
+    source_id: u8,
+    direction: LinkDirection,
+) -> MeshRouter<IqRadio, SIZE, LEN, NodePolicy> {
+    MeshRouter::new(
+        IqRadio {
+            air: air.clone(),
+            direction,
+        },
+        NetworkManager::<SIZE, LEN>::new(source_id, 5, 3),
+        NodePolicy,
+    )
+}
+
+#[test]
+fn only_downlinks_are_inverted() {
+    assert!(!LinkDirection::Uplink.iq_inverted());
+    assert!(LinkDirection::Downlink.iq_inverted());
+    assert!(!LinkDirection::Mesh.iq_inverted());
+}
+
+#[tokio::test]
+async fn mesh_nodes_hear_each_other() {
+    let air = Air::default();
+    let mut router_a = router(&air, 1, LinkDirection::Mesh);
+    let mut router_b = router(&air, 2, LinkDirection::Mesh);
+
+    router_a
+        .send_payload(Vec::from_slice(&[0x01]).unwrap(), 2)
+        .await
+        .unwrap();
+    let res = router_b.receive((), &()).await.unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].packet.payload[0], 0x01);
+}
+
+#[tokio::test]
+async fn mesh_node_does_not_hear_downlink() {
+    let air = Air::default();
+    let mut gateway = router(&air, 1, LinkDirection::Downlink);
+    let mut node = router(&air, 2, LinkDirection::Mesh);
+
+    gateway
+        .send_payload(Vec::from_slice(&[0x01]).unwrap(), 2)
+        .await
+        .unwrap();
+    let res = node.receive((), &()).await.unwrap();
+    assert!(res.is_empty());
+    assert_eq!(node.get_pending_count(), 0);
+}
+
+#[tokio::test]
+async fn downlink_is_heard_by_inverted_receiver() {
+    let air = Air::default();
+    let mut gateway = router(&air, 1, LinkDirection::Downlink);
+    let mut node = router(&air, 2, LinkDirection::Downlink);
+
+    gateway
+        .send_payload(Vec::from_slice(&[0x01]).unwrap(), 2)
+        .await
+        .unwrap();
+    let res = node.receive((), &()).await.unwrap();
+    assert_eq!(res.len(), 1);
+}
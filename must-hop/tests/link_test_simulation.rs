@@ -0,0 +1,80 @@
+//! A gateway tests its link to a node, (GW) <-> (A)
+use core::cell::RefCell;
+use embassy_futures::join::join;
+use embassy_time::Duration;
+use must_hop::{
+    node::{
+        mesh_router::MeshRouter,
+        network_manager::NetworkManager,
+        policy::{GatewayPolicy, NodePolicy},
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+
+const PROBES: u16 = 4;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+
+fn setup() -> (
+    Env,
+    MeshRouter<SimRadio<SIZE>, SIZE, LEN, GatewayPolicy>,
+    MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>,
+) {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    let gw = sim::gateway(&env, NetworkManager::new(GW, 5, 3), &[]);
+    let a = sim::node(&env, NetworkManager::new(NODE_A, 5, 3), None);
+    (env, gw, a)
+}
+
+/// Answers the Start and every probe, none of which reach the application
+async fn respond(a: &mut MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>) {
+    for _ in 0..=PROBES {
+        a.listen(&mut ()).await.unwrap();
+        assert!(a.receive((), &()).await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_link_test_echoes_every_probe() {
+    let (env, mut gw, mut a) = setup();
+    let (result, _) = join(
+        gw.link_test(&mut (), NODE_A, 9, PROBES, Duration::from_millis(10)),
+        respond(&mut a),
+    )
+    .await;
+    let result = result.unwrap();
+    assert_eq!(result.spreading_factor, 9);
+    assert_eq!(result.sent, PROBES);
+    assert_eq!(result.echoed, PROBES);
+    assert_eq!(result.delivery_ratio(), 1.0);
+    // The simulated radio can't tell how strong a frame was
+    assert_eq!(result.downlink.mean(), None);
+    // Nothing is ACKed or forwarded
+    assert_eq!(env.borrow().log.len(), 1 + 2 * PROBES as usize);
+    assert_eq!(gw.get_pending_count(), 0);
+    assert_eq!(a.get_pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_link_test_counts_lost_echoes() {
+    let (env, mut gw, mut a) = setup();
+    // Probes get there, but the echoes don't make it back
+    env.borrow_mut().remove_link(NODE_A, GW);
+    let (result, _) = join(
+        gw.link_test(&mut (), NODE_A, 12, PROBES, Duration::from_millis(10)),
+        respond(&mut a),
+    )
+    .await;
+    let result = result.unwrap();
+    assert_eq!(result.sent, PROBES);
+    assert_eq!(result.echoed, 0);
+    assert_eq!(result.delivery_ratio(), 0.0);
+}
@@ -0,0 +1,113 @@
+//! A node keeps a histogram of how long it listened, (A) <-> (B), and reports the preambles its
+//! radio heard in vain
+use core::cell::RefCell;
+use embassy_time::Duration;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        MHNode, MHPacket, PreambleStats,
+        mesh_router::MeshRouter,
+        network_manager::{NetworkManager, NetworkManagerError},
+        policy::NodePolicy,
+        telemetry::{LISTEN_BUCKETS, ListenHistogram},
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+#[test]
+fn test_buckets_double() {
+    let mut histogram = ListenHistogram::default();
+    histogram.record(Duration::from_millis(5), true);
+    histogram.record(Duration::from_millis(10), true);
+    histogram.record(Duration::from_millis(15), false);
+    histogram.record(Duration::from_secs(3600), false);
+    assert_eq!(histogram.heard[0], 2);
+    assert_eq!(histogram.timed_out[1], 1);
+    assert_eq!(histogram.timed_out[LISTEN_BUCKETS - 1], 1);
+    assert_eq!(histogram.total(), (2, 2));
+    assert_eq!(
+        ListenHistogram::bucket_limit(LISTEN_BUCKETS - 2),
+        Duration::from_millis(10_240)
+    );
+}
+
+#[tokio::test]
+async fn test_router_records_listens() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    let mut b = sim::node(
+        &env,
+        NetworkManager::<SIZE, LEN, _, _>::with_telemetry(NODE_B, 5, 3, ListenHistogram::default()),
+        None,
+    );
+
+    // Nothing in the air, the simulated radio gives up straight away
+    b.listen_with_timeout(&mut ()).await.unwrap();
+    assert!(b.receive((), &()).await.unwrap().is_empty());
+    assert_eq!(b.telemetry().total(), (0, 1));
+
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    b.listen(&mut ()).await.unwrap();
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 1);
+    assert_eq!(b.telemetry().total(), (1, 1));
+    assert_eq!(b.telemetry().heard[0], 1);
+}
+
+const NOISE: PreambleStats = PreambleStats {
+    cad_detections: 12,
+    cad_false_positives: 9,
+    rx_aborts: 4,
+};
+
+/// A SimRadio at a noisy site
+struct NoisyRadio(SimRadio<SIZE>);
+
+impl MHNode<SIZE, LEN> for NoisyRadio {
+    type Error = NetworkManagerError;
+    type Connection = ();
+    type ReceiveBuffer = ();
+    type Duration = u16;
+
+    async fn transmit(&mut self, packets: &[MHPacket<SIZE>]) -> Result<(), Self::Error> {
+        MHNode::<SIZE, LEN>::transmit(&mut self.0, packets).await
+    }
+
+    async fn receive(
+        &mut self,
+        conn: (),
+        rec_buf: &(),
+    ) -> Result<Vec<MHPacket<SIZE>, LEN>, Self::Error> {
+        MHNode::<SIZE, LEN>::receive(&mut self.0, conn, rec_buf).await
+    }
+
+    async fn listen(&mut self, rec_buf: &mut (), with_timeout: bool) -> Result<(), Self::Error> {
+        MHNode::<SIZE, LEN>::listen(&mut self.0, rec_buf, with_timeout).await
+    }
+
+    fn preamble_stats(&self) -> Option<PreambleStats> {
+        Some(NOISE)
+    }
+}
+
+#[test]
+fn test_status_reports_preamble_stats() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    let quiet = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    assert_eq!(quiet.status().preamble, None);
+    let noisy = MeshRouter::new(
+        NoisyRadio(sim::radio(&env, NODE_B, &[])),
+        NetworkManager::<SIZE, LEN>::new(NODE_B, 5, 3),
+        NodePolicy,
+    );
+    assert_eq!(noisy.status().preamble, Some(NOISE));
+}
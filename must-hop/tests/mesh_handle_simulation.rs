@@ -0,0 +1,130 @@
+//! Node A's router runs on its own, and the test talks to it through a MeshHandle, like a sensor
+//! task would. Node B is driven by hand.
+use core::cell::RefCell;
+use embassy_futures::{
+    select::{Either, select},
+    yield_now,
+};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        BROADCAST_ID,
+        delivered::Delivered,
+        handle::{MeshChannels, MeshEvent},
+        mesh_router::MeshRouter,
+        network_manager::NetworkManager,
+        policy::NodePolicy,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+
+fn router(env: &Env, node_id: u8) -> MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy> {
+    sim::node(env, NetworkManager::<SIZE, LEN>::new(node_id, 5, 3), None)
+}
+
+/// Lets the running router work until B has something to receive
+async fn wait_for_b(env: &Env) {
+    while env.borrow().inboxes[&NODE_B].is_empty() {
+        yield_now().await;
+    }
+}
+
+#[tokio::test]
+async fn test_handle_sends_and_subscribes() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut a = router(&env, NODE_A);
+    let mut b = router(&env, NODE_B);
+
+    let channels = MeshChannels::<NoopRawMutex, SIZE, 4, 2>::new();
+    let handle = channels.handle();
+    let mut events = handle.subscribe_events().unwrap();
+
+    let test = async {
+        // A clone works the same, like it would in another task
+        handle
+            .clone()
+            .send(Vec::from_slice(&[7]).unwrap(), NODE_B)
+            .await;
+        wait_for_b(&env).await;
+        let got = b.receive((), &()).await.unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].packet.payload, [7]);
+
+        // What B sends to A comes out as an event
+        b.send_payload(Vec::from_slice(&[9]).unwrap(), NODE_A)
+            .await
+            .unwrap();
+        let MeshEvent::Received(Delivered { packet: pkt, .. }) = events.next_message_pure().await
+        else {
+            panic!("Expected a received packet");
+        };
+        assert_eq!(pkt.source_id, NODE_B);
+        assert_eq!(pkt.payload, [9]);
+        // And A has ACKed it before publishing
+        assert_eq!(b.receive((), &()).await.unwrap().len(), 0);
+        assert_eq!(b.get_pending_count(), 0);
+
+        handle.broadcast(Vec::from_slice(&[1]).unwrap()).await;
+        wait_for_b(&env).await;
+        let got = b.receive((), &()).await.unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].packet.destination_id, BROADCAST_ID);
+        // B floods it on, and A knows it as its own
+        assert_eq!(env.borrow().inboxes[&NODE_A].len(), 1);
+        while !env.borrow().inboxes[&NODE_A].is_empty() {
+            yield_now().await;
+        }
+        assert!(events.try_next_message_pure().is_none());
+    };
+
+    let mut rec_buf = ();
+    match select(a.run(&channels, &mut rec_buf), test).await {
+        Either::First(()) => panic!("The router stopped"),
+        Either::Second(()) => {}
+    }
+    assert_eq!(a.get_pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_handle_requests_status() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut a = router(&env, NODE_A);
+
+    let channels = MeshChannels::<NoopRawMutex, SIZE, 4, 2>::new();
+    let handle = channels.handle();
+    let mut events = handle.subscribe_events().unwrap();
+
+    let test = async {
+        // B never answers, so the packet keeps waiting for its ACK
+        handle.send(Vec::from_slice(&[7]).unwrap(), NODE_B).await;
+        wait_for_b(&env).await;
+        handle.request_status().await;
+        let MeshEvent::Status(status) = events.next_message_pure().await else {
+            panic!("Expected the status");
+        };
+        assert_eq!(status.node_id, NODE_A);
+        assert_eq!(status.gw_hops, u8::MAX);
+        assert_eq!(status.pending, 1);
+        // The simulated radio can't tell how strong a frame was
+        assert_eq!(status.last_rssi, None);
+        assert_eq!(status.queue_depth, 0);
+    };
+
+    let mut rec_buf = ();
+    match select(a.run(&channels, &mut rec_buf), test).await {
+        Either::First(()) => panic!("The router stopped"),
+        Either::Second(()) => {}
+    }
+}
@@ -0,0 +1,74 @@
+//! A gateway driving past (A) <-> (B): first it is next to A, then next to B, then it is gone.
+use core::cell::RefCell;
+use embassy_time::Duration;
+use heapless::Vec;
+use must_hop::{
+    node::{mesh_router::MeshRouter, network_manager::NetworkManager, policy::NodePolicy},
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+const GATEWAY_TTL_MS: u64 = 200;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Router = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+
+fn manager(node_id: u8) -> NetworkManager<SIZE, LEN> {
+    let mut manager = NetworkManager::new(node_id, 5, 3);
+    manager.set_gateway_ttl(Some(Duration::from_millis(GATEWAY_TTL_MS)));
+    manager
+}
+
+/// The hops to the gateway which `router` puts in the packets it sends now
+async fn hops_to_gw(env: &Env, router: &mut Router) -> u8 {
+    router.send_payload(Vec::new(), GW).await.unwrap();
+    let mut e = env.borrow_mut();
+    // Only the header matters, so the packet is taken off the air again
+    e.inboxes.values_mut().for_each(std::vec::Vec::clear);
+    e.log.last().unwrap().packet.hop_to_gw
+}
+
+#[tokio::test]
+async fn test_nodes_follow_moving_gateway() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    {
+        let mut e = env.borrow_mut();
+        e.add_bidi_link(GW, NODE_A);
+        e.add_bidi_link(NODE_A, NODE_B);
+    }
+    let mut gw = sim::gateway(&env, NetworkManager::<SIZE, LEN>::new(GW, 5, 3), &[]);
+    let mut a = sim::node(&env, manager(NODE_A), None);
+    let mut b = sim::node(&env, manager(NODE_B), None);
+
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    assert_eq!(hops_to_gw(&env, &mut a).await, 1);
+    assert_eq!(hops_to_gw(&env, &mut b).await, 2);
+
+    // The gateway drives over to B
+    {
+        let mut e = env.borrow_mut();
+        e.remove_link(GW, NODE_A);
+        e.remove_link(NODE_A, GW);
+        e.add_bidi_link(GW, NODE_B);
+    }
+    gw.bootup().await.unwrap();
+    b.receive((), &()).await.unwrap();
+    // A's old path was shorter, but the new bootup replaces it
+    a.receive((), &()).await.unwrap();
+    assert_eq!(hops_to_gw(&env, &mut b).await, 1);
+    assert_eq!(hops_to_gw(&env, &mut a).await, 2);
+
+    // And drives off, so the nodes forget it
+    std::thread::sleep(std::time::Duration::from_millis(2 * GATEWAY_TTL_MS));
+    assert_eq!(hops_to_gw(&env, &mut a).await, u8::MAX);
+    assert_eq!(hops_to_gw(&env, &mut b).await, u8::MAX);
+}
@@ -0,0 +1,168 @@
+//! The gateway manages a node remotely, (GW) <-> (A) <-> (B)
+use core::cell::RefCell;
+use must_hop::{
+    node::{
+        PacketType,
+        control::{Control, ControlHooks},
+        delivered::Delivered,
+        mesh_router::MeshRouter,
+        network_manager::NetworkManager,
+        policy::{GatewayPolicy, NodePolicy, RoutingPolicy},
+        telemetry::NoTelemetry,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+    units::Dbm,
+    verbosity::{self, Level, Subsystem},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Managed = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy, NoTelemetry, Fleet>;
+
+/// Takes requests from the gateway only, and notes what it was asked to do
+#[derive(Default)]
+struct Fleet {
+    rebooted: bool,
+    reset: bool,
+    safe_mode: bool,
+}
+
+impl ControlHooks for Fleet {
+    fn confirm(&mut self, source: u8, _request: Control) -> bool {
+        source == GW
+    }
+
+    fn reboot(&mut self) {
+        self.rebooted = true;
+    }
+
+    fn factory_reset(&mut self) {
+        self.reset = true;
+    }
+
+    fn enter_safe_mode(&mut self) {
+        self.safe_mode = true;
+    }
+}
+
+fn router<P: RoutingPolicy<SIZE, LEN>>(
+    env: &Env,
+    node_id: u8,
+    policy: P,
+) -> MeshRouter<SimRadio<SIZE>, SIZE, LEN, P> {
+    MeshRouter::new(
+        sim::radio(env, node_id, &[]),
+        NetworkManager::new(node_id, 5, 3),
+        policy,
+    )
+}
+
+fn setup() -> (
+    MeshRouter<SimRadio<SIZE>, SIZE, LEN, GatewayPolicy>,
+    MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>,
+    MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>,
+) {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let gw = router(&env, GW, GatewayPolicy);
+    let a = router(&env, NODE_A, NodePolicy);
+    let b = router(&env, NODE_B, NodePolicy);
+    (gw, a, b)
+}
+
+/// The Control reply among `pkts`
+fn reply(pkts: &[Delivered<SIZE>]) -> Control {
+    let pkt = pkts
+        .iter()
+        .map(|delivered| &delivered.packet)
+        .find(|pkt| pkt.packet_type == PacketType::Control)
+        .expect("no reply");
+    Control::decode(&pkt.payload).unwrap().0
+}
+
+#[tokio::test]
+async fn test_requests_are_refused_without_hooks() {
+    let (mut gw, mut a, _) = setup();
+    gw.send_control(NODE_A, Control::Reboot).await.unwrap();
+    // The router answers, the application never sees the request
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    let pkts = gw.receive((), &()).await.unwrap();
+    assert_eq!(reply(&pkts), Control::Reply { accepted: false });
+}
+
+#[tokio::test]
+async fn test_confirmed_requests_are_carried_out() {
+    let (mut gw, a, _) = setup();
+    let mut a: Managed = a.set_control(Fleet::default());
+    for request in [
+        Control::Reboot,
+        Control::FactoryReset,
+        Control::EnterSafeMode,
+        Control::LinkAdr {
+            spreading_factor: 9,
+            tx_power: Dbm(14),
+        },
+    ] {
+        gw.send_control(NODE_A, request).await.unwrap();
+        assert!(a.receive((), &()).await.unwrap().is_empty());
+        let pkts = gw.receive((), &()).await.unwrap();
+        assert_eq!(reply(&pkts), Control::Reply { accepted: true });
+    }
+    assert!(a.control().rebooted);
+    assert!(a.control().reset);
+    // Without a channel plan the radio stays on its spreading factor, the hook still runs
+    assert!(a.control().safe_mode);
+}
+
+#[tokio::test]
+async fn test_hooks_refuse_other_nodes() {
+    let (_gw, a, mut b) = setup();
+    let mut a: Managed = a.set_control(Fleet::default());
+    b.send_control(NODE_A, Control::FactoryReset).await.unwrap();
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    let pkts = b.receive((), &()).await.unwrap();
+    assert_eq!(reply(&pkts), Control::Reply { accepted: false });
+    assert!(!a.control().reset);
+}
+
+#[tokio::test]
+async fn test_log_level_is_set_remotely() {
+    let request = Control::SetLogLevel {
+        subsystem: Subsystem::Router,
+        level: Level::Warn,
+    };
+    // Refused without hooks, like the rest
+    let (mut gw, mut a, _) = setup();
+    gw.send_control(NODE_A, request).await.unwrap();
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    assert_eq!(verbosity::level(Subsystem::Router), Level::Trace);
+    let pkts = gw.receive((), &()).await.unwrap();
+    assert_eq!(reply(&pkts), Control::Reply { accepted: false });
+
+    // And from other nodes than the gateway
+    let (_gw, a, mut b) = setup();
+    let mut a: Managed = a.set_control(Fleet::default());
+    b.send_control(NODE_A, request).await.unwrap();
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    assert_eq!(verbosity::level(Subsystem::Router), Level::Trace);
+    let pkts = b.receive((), &()).await.unwrap();
+    assert_eq!(reply(&pkts), Control::Reply { accepted: false });
+
+    let (mut gw, a, _) = setup();
+    let mut a: Managed = a.set_control(Fleet::default());
+    gw.send_control(NODE_A, request).await.unwrap();
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    assert_eq!(verbosity::level(Subsystem::Router), Level::Warn);
+    assert_eq!(verbosity::level(Subsystem::Radio), Level::Trace);
+    let pkts = gw.receive((), &()).await.unwrap();
+    assert_eq!(reply(&pkts), Control::Reply { accepted: true });
+    verbosity::reset();
+}
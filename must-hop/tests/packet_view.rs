@@ -0,0 +1,175 @@
+use heapless::Vec;
+use must_hop::node::{
+    MAX_FRAME_LEN, MHPacket, MHPacketView, PacketType, WIRE_VERSION, flags,
+    network_manager::NetworkManagerError,
+};
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+fn packet(packet_id: u16, payload: &[u8]) -> MHPacket<SIZE> {
+    MHPacket {
+        network_id: 7,
+        destination_id: 0,
+        packet_type: PacketType::Data,
+        flags: 0,
+        packet_id,
+        source_id: 2,
+        max_payload: SIZE as u8,
+        payload: Vec::from_slice(payload).unwrap(),
+        hop_count: 1,
+        // Not sent to the gateway, so not on the air
+        hop_to_gw: u8::MAX,
+        route: Vec::from_slice(&[3, 4]).unwrap(),
+    }
+}
+
+#[test]
+fn view_decodes_owned_frame() {
+    let mut frame: Vec<MHPacket<SIZE>, LEN> = Vec::new();
+    frame.push(packet(1, &[0xAA, 0xBB])).unwrap();
+    frame.push(packet(2, &[0x01; SIZE])).unwrap();
+    let mut buf = [0u8; 256];
+    let bytes = MHPacket::encode_frame_into(&frame, &mut buf).unwrap();
+
+    let views = MHPacketView::decode_frame::<LEN>(bytes).unwrap();
+    assert_eq!(views.len(), 2);
+    assert_eq!(views[0].payload, &[0xAA, 0xBB]);
+    // The payload borrows from the frame instead of being copied
+    assert!(bytes.as_ptr_range().contains(&views[1].payload.as_ptr()));
+
+    for (view, pkt) in views.iter().zip(frame.iter()) {
+        assert_eq!(view.to_packet::<SIZE>().as_ref(), Some(pkt));
+    }
+}
+
+#[test]
+fn hop_to_gw_is_only_sent_to_the_gateway() {
+    let mut buf = [0u8; 256];
+    let mut to_peer = packet(1, &[0xAA]);
+    to_peer.hop_to_gw = 2;
+    let peer_len = postcard::to_slice(&to_peer, &mut buf).unwrap().len();
+    let decoded: MHPacket<SIZE> = postcard::from_bytes(&buf[..peer_len]).unwrap();
+    assert_eq!(decoded.hop_to_gw, u8::MAX);
+
+    let mut to_gw = to_peer.clone();
+    to_gw.destination_id = 1;
+    let gw_len = postcard::to_slice(&to_gw, &mut buf).unwrap().len();
+    assert_eq!(gw_len, peer_len + 1);
+    let decoded: MHPacket<SIZE> = postcard::from_bytes(&buf[..gw_len]).unwrap();
+    assert_eq!(decoded, to_gw);
+    let view: MHPacketView = postcard::from_bytes(&buf[..gw_len]).unwrap();
+    assert_eq!(view.hop_to_gw, 2);
+}
+
+#[test]
+fn oversized_view_payload_is_rejected() {
+    let mut frame: Vec<MHPacket<SIZE>, LEN> = Vec::new();
+    frame.push(packet(1, &[0x01; SIZE])).unwrap();
+    let mut buf = [0u8; 256];
+    let bytes = MHPacket::encode_frame_into(&frame, &mut buf).unwrap();
+
+    let views = MHPacketView::decode_frame::<LEN>(bytes).unwrap();
+    assert!(views[0].to_packet::<8>().is_none());
+}
+
+#[test]
+fn flags_are_kept_with_unknown_bits() {
+    let mut pkt = packet(1, &[0xAA]);
+    pkt.set_flag(flags::PRIORITY | flags::COMPRESSED, true);
+    // A bit a newer sender allocated
+    pkt.flags |= 1 << 7;
+    pkt.set_flag(flags::COMPRESSED, false);
+    assert!(pkt.has_flag(flags::PRIORITY));
+    assert!(!pkt.has_flag(flags::COMPRESSED));
+    assert_eq!(pkt.unknown_flags(), 1 << 7);
+
+    let mut buf = [0u8; 256];
+    let bytes = postcard::to_slice(&pkt, &mut buf).unwrap();
+    let view: MHPacketView = postcard::from_bytes(bytes).unwrap();
+    assert!(view.has_flag(flags::PRIORITY));
+    assert_eq!(view.flags, flags::PRIORITY | 1 << 7);
+    let decoded: MHPacket<SIZE> = postcard::from_bytes(bytes).unwrap();
+    assert_eq!(decoded, pkt);
+}
+
+#[test]
+fn larger_sender_is_payload_too_large() {
+    // A gateway with a larger SIZE sends more than we can take
+    let big = MHPacket::<128> {
+        network_id: 0,
+        destination_id: 2,
+        packet_type: PacketType::Ack,
+        flags: 0,
+        packet_id: 7,
+        source_id: 1,
+        max_payload: 128,
+        payload: Vec::from_slice(&[0x01; 100]).unwrap(),
+        hop_count: 0,
+        hop_to_gw: 0,
+        route: Vec::new(),
+    };
+    let mut frame: Vec<MHPacket<128>, LEN> = Vec::new();
+    frame.push(big).unwrap();
+    let mut buf = [0u8; 256];
+    let bytes = MHPacket::encode_frame_into(&frame, &mut buf).unwrap();
+
+    let err = MHPacket::<SIZE>::decode_frame::<LEN>(bytes).unwrap_err();
+    assert!(matches!(
+        err,
+        NetworkManagerError::PayloadTooLarge {
+            source_id: 1,
+            packet_id: 7,
+            len: 100,
+            max_payload: 128,
+        }
+    ));
+}
+
+#[test]
+fn frame_is_encoded_into_the_given_buffer() {
+    let frame = [packet(1, &[0xAA, 0xBB]), packet(2, &[0x01; SIZE])];
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let bytes = MHPacket::encode_frame_into(&frame, &mut buf).unwrap();
+    // The version, then the same as a heapless Vec of the packets
+    let mut owned = [0u8; MAX_FRAME_LEN];
+    let vec: Vec<MHPacket<SIZE>, LEN> = Vec::from_slice(&frame).unwrap();
+    assert_eq!(bytes[0], WIRE_VERSION);
+    assert_eq!(&bytes[1..], postcard::to_slice(&vec, &mut owned).unwrap());
+    assert_eq!(MHPacket::frame_len(&frame), bytes.len());
+    assert_eq!(
+        MHPacket::<SIZE>::decode_frame::<LEN>(bytes).unwrap(),
+        frame.as_slice()
+    );
+
+    let mut short = [0u8; 16];
+    assert!(MHPacket::encode_frame_into(&frame, &mut short).is_err());
+
+    let bytes = frame[0].encode_into(&mut buf).unwrap();
+    assert_eq!(MHPacket::decode_from(bytes), Ok(frame[0].clone()));
+}
+
+#[test]
+fn frame_of_another_version_is_rejected() {
+    let frame = [packet(1, &[0xAA])];
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let len = MHPacket::encode_frame_into(&frame, &mut buf).unwrap().len();
+    buf[0] = WIRE_VERSION + 1;
+    assert!(matches!(
+        MHPacketView::decode_frame::<LEN>(&buf[..len]),
+        Err(NetworkManagerError::WrongVersion(v)) if v == WIRE_VERSION + 1
+    ));
+
+    // A frame from before the version byte starts with its packet count
+    let mut owned = [0u8; MAX_FRAME_LEN];
+    let vec: Vec<MHPacket<SIZE>, LEN> = Vec::from_slice(&frame).unwrap();
+    let unversioned = postcard::to_slice(&vec, &mut owned).unwrap();
+    assert!(matches!(
+        MHPacket::<SIZE>::decode_frame::<LEN>(unversioned),
+        Err(NetworkManagerError::WrongVersion(1))
+    ));
+    assert!(matches!(
+        MHPacketView::decode_frame::<LEN>(&[]),
+        Err(NetworkManagerError::Serialization(_))
+    ));
+}
@@ -0,0 +1,136 @@
+//! A phone writes the provisioning of a fresh node, which saves it and after a reboot joins the
+//! network it was provisioned for: (GW) <-> (A)
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        PacketType,
+        config::ChannelPlan,
+        mesh_router::MeshRouterError,
+        network_manager::NetworkManager,
+        provision::{ProvisionError, ProvisionStore, Provisioning},
+    },
+    sim::{self, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NETWORK: u8 = 7;
+
+const PROVISIONING: Provisioning = Provisioning {
+    node_id: NODE_A,
+    network_id: NETWORK,
+    key: [0x5A; 16],
+    plan: ChannelPlan {
+        frequency_hz: 869_525_000,
+        spreading_factor: 9,
+        bandwidth_khz: 250,
+    },
+};
+
+#[derive(Default)]
+struct MemoryStore {
+    provisioning: Option<Provisioning>,
+}
+
+impl ProvisionStore for MemoryStore {
+    type Error = ();
+
+    async fn load_provisioning(&mut self) -> Result<Option<Provisioning>, ()> {
+        Ok(self.provisioning)
+    }
+
+    async fn save_provisioning(&mut self, provisioning: &Provisioning) -> Result<(), ()> {
+        self.provisioning = Some(*provisioning);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_provisioned_node_joins_its_network() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    let mut manager = NetworkManager::<SIZE, LEN>::new(GW, 5, 3);
+    manager.set_network_id(NETWORK);
+    let mut gw = sim::gateway(&env, manager, &[]);
+
+    // What the app writes to the characteristic, which the node saves before rebooting
+    let mut store = MemoryStore::default();
+    let written = PROVISIONING.encode().unwrap();
+    let provisioning = Provisioning::decode(&written).unwrap();
+    store.save_provisioning(&provisioning).await.unwrap();
+
+    // After the reboot
+    let provisioning = store.load_provisioning().await.unwrap().unwrap();
+    let mut a = sim::node(
+        &env,
+        NetworkManager::<SIZE, LEN>::new(provisioning.node_id, 5, 3),
+        None,
+    );
+    a.provision(&provisioning).unwrap();
+    assert_eq!(a.channel_plan(), Some(PROVISIONING.plan));
+
+    // On the gateway's network now, so it hears the bootup
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    assert_eq!(a.gw_hops(), 1);
+
+    let payload: Vec<u8, SIZE> = Vec::from_slice(&[1, 2, 3]).unwrap();
+    a.send_payload(payload, GW).await.unwrap();
+    // Next to A's bootup passed back on
+    let received = gw.receive((), &()).await.unwrap();
+    let mut data = received
+        .iter()
+        .filter(|delivered| delivered.packet.packet_type == PacketType::Data);
+    assert_eq!(data.next().unwrap().packet.network_id, NETWORK);
+    assert!(data.next().is_none());
+}
+
+#[tokio::test]
+async fn test_provisioning_for_another_node_is_refused() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    let mut b = sim::node(&env, NetworkManager::<SIZE, LEN>::new(3, 5, 3), None);
+    assert!(matches!(
+        b.provision(&PROVISIONING),
+        Err(MeshRouterError::Config)
+    ));
+    assert_eq!(b.channel_plan(), None);
+}
+
+#[test]
+fn test_invalid_provisioning_is_refused() {
+    let written = PROVISIONING.encode().unwrap();
+    assert_eq!(
+        Provisioning::decode(&written[..written.len() - 1]),
+        Err(ProvisionError::Malformed)
+    );
+    let mut longer = written.clone();
+    longer.push(0).unwrap();
+    assert_eq!(
+        Provisioning::decode(&longer),
+        Err(ProvisionError::Malformed)
+    );
+
+    let broadcast = Provisioning {
+        node_id: u8::MAX,
+        ..PROVISIONING
+    };
+    assert_eq!(
+        Provisioning::decode(&broadcast.encode().unwrap()),
+        Err(ProvisionError::InvalidNodeId)
+    );
+    let mut plan = PROVISIONING.plan;
+    plan.spreading_factor = 13;
+    let bad_plan = Provisioning {
+        plan,
+        ..PROVISIONING
+    };
+    assert_eq!(
+        Provisioning::decode(&bad_plan.encode().unwrap()),
+        Err(ProvisionError::InvalidPlan)
+    );
+}
@@ -0,0 +1,38 @@
+//! The parts of the API applications build on, used the way they use them, so a change breaking
+//! them fails here, with or without defmt. `just api-check` compares the whole public API against
+//! its snapshot.
+use heapless::Vec;
+use must_hop::prelude::*;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+#[test]
+fn manager_sends_and_survives_a_restart() {
+    let mut manager = NetworkManager::<SIZE, LEN>::new(1, 10, 3);
+    let pkts = manager
+        .payload_to_send(Vec::from_slice(&[1, 2]).unwrap(), 0)
+        .unwrap();
+    assert_eq!(&pkts[0].payload[..], &[1, 2]);
+
+    let mut buf = [0u8; 256];
+    let bytes = pkts[0].encode_into(&mut buf).unwrap();
+    assert_eq!(MHPacket::<SIZE>::decode_from(bytes).unwrap(), pkts[0]);
+
+    let state: ManagerState<SIZE, LEN> = manager.state();
+    let mut restarted = NetworkManager::<SIZE, LEN>::new(1, 10, 3);
+    restarted.restore(state).unwrap();
+    assert_eq!(restarted.state(), manager.state());
+}
+
+#[test]
+fn units_are_plain_numbers() {
+    assert_eq!(Dbm(14i8).0, 14);
+    assert!(Db(-7.5) < Db(0.0));
+}
+
+/// A router only needs a node, a manager and a policy
+#[allow(dead_code)]
+fn router<N: MHNode<SIZE, LEN>>(node: N) {
+    let _node = MeshRouter::new(node, NetworkManager::<SIZE, LEN>::new(1, 10, 3), NodePolicy);
+}
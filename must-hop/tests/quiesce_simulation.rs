@@ -0,0 +1,87 @@
+//! A node getting ready to sleep waits for its packets to be ACKed, on (A) <-> (B)
+use core::cell::RefCell;
+use embassy_time::Duration;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        clock::TestClock, mesh_router::MeshRouter, network_manager::NetworkManager,
+        policy::NodePolicy,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+type Env = Rc<RefCell<SimulationEnv<SIZE>>>;
+type Router = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+
+fn pair() -> (Env, Router, Router) {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let router = |node_id| sim::node(&env, NetworkManager::<SIZE, LEN>::new(node_id, 5, 3), None);
+    let a = router(NODE_A);
+    let b = router(NODE_B);
+    (env, a, b)
+}
+
+#[tokio::test]
+async fn test_quiesce_waits_for_ack() {
+    let (_env, mut a, mut b) = pair();
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+    // B delivers it and ACKs, and B's payload for A arrives with the ACK
+    assert_eq!(b.receive((), &()).await.unwrap().len(), 1);
+    b.send_payload(Vec::from_slice(&[0x02]).unwrap(), NODE_A)
+        .await
+        .unwrap();
+
+    let summary = a.quiesce(&mut (), Duration::from_secs(1)).await.unwrap();
+    assert_eq!(summary.pending, 0);
+    assert_eq!(summary.retransmitted, 0);
+    assert_eq!(summary.received.len(), 1);
+    assert_eq!(summary.received[0].packet.payload, [0x02]);
+    // It has to be handled before sleeping
+    assert!(!summary.is_idle());
+}
+
+#[tokio::test]
+async fn test_quiesce_gives_up_after_max_wait() {
+    let (_env, mut a, _b) = pair();
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+
+    // B never answers
+    let summary = a.quiesce(&mut (), Duration::from_millis(50)).await.unwrap();
+    assert_eq!(summary.pending, 1);
+    assert!(summary.received.is_empty());
+    assert!(!summary.is_idle());
+}
+
+#[tokio::test]
+async fn test_quiesce_retries_on_the_managers_clock() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    let clock = TestClock::new();
+    let mut a = MeshRouter::new(
+        sim::radio(&env, NODE_A, &[]),
+        NetworkManager::<SIZE, LEN, _>::with_clock(NODE_A, 5, 3, &clock),
+        NodePolicy,
+    );
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), NODE_B)
+        .await
+        .unwrap();
+
+    // Nothing is due until the test clock passes the timeout
+    let summary = a.quiesce(&mut (), Duration::from_millis(10)).await.unwrap();
+    assert_eq!(summary.retransmitted, 0);
+    clock.advance(Duration::from_secs(6));
+    let summary = a.quiesce(&mut (), Duration::from_millis(10)).await.unwrap();
+    assert_eq!(summary.retransmitted, 1);
+    assert_eq!(summary.pending, 1);
+}
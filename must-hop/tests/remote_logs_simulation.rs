@@ -0,0 +1,99 @@
+//! A gateway fetches the logs of a node, (GW) <-> (A)
+use core::cell::RefCell;
+use must_hop::{
+    node::{
+        PacketType,
+        control::{Control, LogBuffer},
+        mesh_router::MeshRouter,
+        network_manager::NetworkManager,
+        policy::{GatewayPolicy, NodePolicy},
+        telemetry::NoTelemetry,
+    },
+    sim::{self, SimRadio, SimulationEnv},
+};
+use std::fmt::Write;
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+const LOG_SIZE: usize = 64;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+
+type Gateway = MeshRouter<SimRadio<SIZE>, SIZE, LEN, GatewayPolicy>;
+type Node = MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy>;
+type LoggingNode =
+    MeshRouter<SimRadio<SIZE>, SIZE, LEN, NodePolicy, NoTelemetry, LogBuffer<LOG_SIZE>>;
+
+fn setup() -> (Gateway, Node) {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    let gw = sim::gateway(&env, NetworkManager::new(GW, 5, 3), &[]);
+    let a = sim::node(&env, NetworkManager::new(NODE_A, 5, 3), None);
+    (gw, a)
+}
+
+/// The Control reply among the packets the gateway received, and the data after it
+async fn reply(gw: &mut Gateway) -> (Control, Vec<u8>) {
+    let pkts = gw.receive((), &()).await.unwrap();
+    let pkt = pkts
+        .iter()
+        .map(|delivered| &delivered.packet)
+        .find(|pkt| pkt.packet_type == PacketType::Control)
+        .expect("no reply");
+    let (msg, data) = Control::decode(&pkt.payload).unwrap();
+    (msg, data.to_vec())
+}
+
+#[tokio::test]
+async fn test_gateway_pages_through_node_logs() {
+    let (mut gw, a) = setup();
+    let mut a: LoggingNode = a.set_control(LogBuffer::new());
+    let mut logged = String::new();
+    for i in 0..10 {
+        writeln!(a.control_mut(), "boot {}", i).unwrap();
+        writeln!(logged, "boot {}", i).unwrap();
+    }
+    // Only the latest LOG_SIZE bytes are kept
+    let kept = &logged.as_bytes()[logged.len() - LOG_SIZE..];
+
+    let mut fetched = Vec::new();
+    while fetched.len() < LOG_SIZE {
+        let offset = fetched.len() as u32;
+        gw.request_logs(NODE_A, offset).await.unwrap();
+        // The router answers, the application never sees the request
+        assert!(a.receive((), &()).await.unwrap().is_empty());
+        let (msg, data) = reply(&mut gw).await;
+        assert_eq!(
+            msg,
+            Control::Logs {
+                offset,
+                total: LOG_SIZE as u32
+            }
+        );
+        assert!(!data.is_empty());
+        fetched.extend_from_slice(&data);
+    }
+    assert_eq!(fetched, kept);
+
+    // The gateway's ACK of the last reply
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    assert_eq!(a.get_pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_node_without_logs_replies_empty() {
+    let (mut gw, mut a) = setup();
+    gw.request_logs(NODE_A, 0).await.unwrap();
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    let (msg, data) = reply(&mut gw).await;
+    assert_eq!(
+        msg,
+        Control::Logs {
+            offset: 0,
+            total: 0
+        }
+    );
+    assert!(data.is_empty());
+}
@@ -0,0 +1,54 @@
+//! One binary running as a gateway or a node, picked from configuration, on (GW) <-> (A)
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        PacketType, mesh_router::MeshRouter, network_manager::NetworkManager, policy::RuntimePolicy,
+    },
+    sim::{self, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+
+#[tokio::test]
+async fn test_policy_from_config() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    let router = |node_id: u8, policy: &str| {
+        MeshRouter::new(
+            sim::radio(&env, node_id, &[]),
+            NetworkManager::<SIZE, LEN>::new(node_id, 5, 3),
+            policy.parse::<RuntimePolicy>().unwrap(),
+        )
+    };
+    let mut gw = router(GW, "gateway");
+    let mut a = router(NODE_A, "Node");
+    assert_eq!(*gw.policy(), RuntimePolicy::Gateway);
+    assert!("relay".parse::<RuntimePolicy>().is_err());
+
+    // Only the gateway boots up
+    assert!(!a.bootup().await.unwrap());
+    assert!(env.borrow().log.is_empty());
+    assert!(gw.bootup().await.unwrap());
+    a.receive((), &()).await.unwrap();
+
+    a.send_payload(Vec::from_slice(&[7]).unwrap(), GW)
+        .await
+        .unwrap();
+    assert_eq!(a.get_pending_count(), 1);
+    // The gateway hands the packet to the application and ACKs it, like GatewayPolicy
+    let delivered = gw.receive((), &()).await.unwrap();
+    assert!(
+        delivered
+            .iter()
+            .map(|delivered| &delivered.packet)
+            .any(|pkt| pkt.packet_type == PacketType::Data && pkt.payload == [7])
+    );
+    a.receive((), &()).await.unwrap();
+    assert_eq!(a.get_pending_count(), 0);
+}
@@ -0,0 +1,151 @@
+//! A node which hasn't heard from a gateway keeps its payloads, and sends them once the gateway
+//! boots up, on (GW) <-> (A) <-> (B). So does one the gateway told to be quiet, until it may send
+//! again.
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        PacketType,
+        control::{Control, ControlHooks},
+        mesh_router::MeshRouterError,
+        network_manager::NetworkManager,
+        store::MemoryPacketStore,
+    },
+    sim::{self, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+/// Goes quiet when the gateway asks
+struct FromGateway;
+
+impl ControlHooks for FromGateway {
+    fn confirm(&mut self, source: u8, _request: Control) -> bool {
+        source == GW
+    }
+}
+
+#[tokio::test]
+async fn test_payloads_wait_for_gateway() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    let mut gw = sim::gateway(&env, NetworkManager::<SIZE, LEN>::new(GW, 5, 3), &[]);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    let mut store = MemoryPacketStore::<SIZE, 4>::new();
+
+    for i in 0..2 {
+        a.send_or_store(Vec::from_slice(&[i]).unwrap(), GW, &mut store)
+            .await
+            .unwrap();
+    }
+    assert_eq!(store.len(), 2);
+    assert!(env.borrow().log.is_empty());
+    // Nothing is sent until there is a gateway
+    assert!(!a.drain_stored(&mut store).await.unwrap());
+
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+    assert!(a.drain_stored(&mut store).await.unwrap());
+    assert!(a.drain_stored(&mut store).await.unwrap());
+    assert!(!a.drain_stored(&mut store).await.unwrap());
+    assert!(store.is_empty());
+
+    let delivered: std::vec::Vec<_> = gw
+        .receive((), &())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|delivered| delivered.packet)
+        .filter(|pkt| pkt.packet_type == PacketType::Data)
+        .map(|pkt| pkt.payload)
+        .collect();
+    assert_eq!(delivered, [[0], [1]]);
+
+    // With a gateway known, payloads go straight out
+    a.send_or_store(Vec::from_slice(&[2]).unwrap(), GW, &mut store)
+        .await
+        .unwrap();
+    assert!(store.is_empty());
+}
+
+#[tokio::test]
+async fn test_full_store_is_a_store_error() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    let mut store = MemoryPacketStore::<SIZE, 1>::new();
+
+    a.send_or_store(Vec::from_slice(&[0]).unwrap(), GW, &mut store)
+        .await
+        .unwrap();
+    let err = a
+        .send_or_store(Vec::from_slice(&[1]).unwrap(), GW, &mut store)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, MeshRouterError::Store));
+    assert_eq!(err.code(), 0x400);
+    assert_eq!(store.len(), 1);
+}
+
+#[tokio::test]
+async fn test_payloads_wait_out_quiet_period() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_A);
+    let mut gw = sim::gateway(&env, NetworkManager::<SIZE, LEN>::new(GW, 5, 3), &[]);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None)
+        .set_control(FromGateway);
+    let mut store = MemoryPacketStore::<SIZE, 4>::new();
+    gw.bootup().await.unwrap();
+    a.receive((), &()).await.unwrap();
+
+    gw.broadcast_control(Control::Quiet { secs: 3600 })
+        .await
+        .unwrap();
+    // Carried out by the router, the application doesn't see it
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    assert!(a.is_quiet());
+    a.send_or_store(Vec::from_slice(&[0]).unwrap(), GW, &mut store)
+        .await
+        .unwrap();
+    assert_eq!(store.len(), 1);
+    assert!(!a.drain_stored(&mut store).await.unwrap());
+
+    // Ended early, the payload goes out
+    gw.broadcast_control(Control::Quiet { secs: 0 })
+        .await
+        .unwrap();
+    a.receive((), &()).await.unwrap();
+    assert!(!a.is_quiet());
+    assert!(a.drain_stored(&mut store).await.unwrap());
+    assert!(store.is_empty());
+    let delivered: std::vec::Vec<_> = gw
+        .receive((), &())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|delivered| delivered.packet)
+        .filter(|pkt| pkt.packet_type == PacketType::Data)
+        .map(|pkt| pkt.payload)
+        .collect();
+    assert_eq!(delivered, [[0]]);
+}
+
+#[tokio::test]
+async fn test_quiet_from_other_nodes_is_ignored() {
+    let env = Rc::new(RefCell::new(SimulationEnv::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None)
+        .set_control(FromGateway);
+    let mut b = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_B, 5, 3), None);
+
+    b.broadcast_control(Control::Quiet { secs: 3600 })
+        .await
+        .unwrap();
+    assert!(a.receive((), &()).await.unwrap().is_empty());
+    assert!(!a.is_quiet());
+}
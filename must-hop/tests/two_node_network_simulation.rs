@@ -115,12 +115,15 @@ async fn test_multiple_packets_fifo_order() {
     let res1 = router_b.receive((), &()).await.unwrap();
     assert_eq!(router_b.get_pending_count(), 0);
     assert_eq!(res1.len(), 3);
-    assert_eq!(res1[0].payload[0], 0x01, "Should receive msg1 first");
+    assert_eq!(res1[0].packet.payload[0], 0x01, "Should receive msg1 first");
 
-    assert_eq!(res1[1].payload[0], 0x02, "Should receive msg2 second");
+    assert_eq!(
+        res1[1].packet.payload[0], 0x02,
+        "Should receive msg2 second"
+    );
 
     // Third receive
-    assert_eq!(res1[2].payload[0], 0x03, "Should receive msg3 third");
+    assert_eq!(res1[2].packet.payload[0], 0x03, "Should receive msg3 third");
 }
 
 #[tokio::test]
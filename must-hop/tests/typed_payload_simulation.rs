@@ -0,0 +1,74 @@
+//! A node sends another its command type, (A) <-> (B), which B receives decoded, and a payload of
+//! another type which B is told it can't decode.
+use core::cell::RefCell;
+use heapless::Vec;
+use must_hop::{
+    node::{
+        PacketRef,
+        network_manager::NetworkManager,
+        payload::Undecodable,
+        telemetry::{DropReason, Telemetry},
+    },
+    sim::{self, SimulationEnv},
+};
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const NODE_A: u8 = 2;
+const NODE_B: u8 = 3;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Command {
+    SetInterval { secs: u32 },
+}
+
+#[derive(Default)]
+struct Undecoded(std::vec::Vec<PacketRef>);
+
+impl Telemetry for Undecoded {
+    fn packet_dropped(&mut self, packet: PacketRef, reason: DropReason) {
+        if reason == DropReason::Undecodable {
+            self.0.push(packet);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_payloads_are_received_as_the_application_type() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    env.borrow_mut().add_bidi_link(NODE_A, NODE_B);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    let mut b = sim::node(
+        &env,
+        NetworkManager::<SIZE, LEN, _, _>::with_telemetry(NODE_B, 5, 3, Undecoded::default()),
+        None,
+    );
+
+    let command = Command::SetInterval { secs: 600 };
+    let payload = NetworkManager::<SIZE, LEN>::from_t(&command).unwrap();
+    a.send_payload(payload, NODE_B).await.unwrap();
+    let received = b.receive_as::<Command>((), &()).await.unwrap();
+    assert_eq!(received.len(), 1);
+    let decoded = received[0].as_ref().unwrap();
+    assert_eq!(decoded.packet.source_id, NODE_A);
+    assert_eq!(decoded.destination_id, NODE_B);
+    assert_eq!(decoded.value, command);
+    assert!(b.telemetry().0.is_empty());
+
+    // A varint which never ends isn't a Command
+    let garbage: Vec<u8, SIZE> = Vec::from_slice(&[0xFF, 0xFF]).unwrap();
+    a.send_payload(garbage, NODE_B).await.unwrap();
+    let received = b.receive_as::<Command>((), &()).await.unwrap();
+    assert_eq!(received.len(), 1);
+    let Err(Undecodable { packet, .. }) = received[0] else {
+        panic!("decoded a payload which isn't a Command");
+    };
+    assert_eq!(packet.source_id, NODE_A);
+    assert_eq!(b.telemetry().0, [packet]);
+    // It was still ACKed, so A doesn't send it again
+    a.receive((), &()).await.unwrap();
+    assert_eq!(a.get_pending_count(), 0);
+}
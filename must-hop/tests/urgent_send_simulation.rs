@@ -0,0 +1,90 @@
+//! An alarm sent urgently past a queued uplink, on (GW) <-> (B) <-> (A)
+use core::cell::RefCell;
+use embassy_time::Duration;
+use heapless::Vec;
+use must_hop::{
+    node::{config::ChannelPlan, flags, mesh_router::UrgentSend, network_manager::NetworkManager},
+    sim::{self, SimulationEnv},
+};
+use std::rc::Rc;
+
+const SIZE: usize = 40;
+const LEN: usize = 5;
+
+const GW: u8 = 1;
+const NODE_B: u8 = 2;
+const NODE_A: u8 = 3;
+
+const ALARM: u8 = 0xA1;
+
+#[tokio::test]
+async fn test_urgent_send_is_repeated_and_relayed_first() {
+    let env = Rc::new(RefCell::new(SimulationEnv::<SIZE>::new()));
+    env.borrow_mut().add_bidi_link(GW, NODE_B);
+    env.borrow_mut().add_bidi_link(NODE_B, NODE_A);
+    let mut gw = sim::gateway(&env, NetworkManager::<SIZE, LEN>::new(GW, 5, 3), &[]);
+    let mut b = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_B, 5, 3), None);
+    let mut a = sim::node(&env, NetworkManager::<SIZE, LEN>::new(NODE_A, 5, 3), None);
+    gw.bootup().await.unwrap();
+    b.receive((), &()).await.unwrap();
+    a.receive((), &()).await.unwrap();
+    b.receive((), &()).await.unwrap();
+    gw.receive((), &()).await.unwrap();
+    env.borrow_mut().log.clear();
+
+    a.send_payload(Vec::from_slice(&[0x01]).unwrap(), GW)
+        .await
+        .unwrap();
+    let urgency = UrgentSend {
+        gap: Duration::from_millis(1),
+        ..UrgentSend::default()
+    };
+    a.urgent_send(Vec::from_slice(&[ALARM]).unwrap(), GW, &urgency)
+        .await
+        .unwrap();
+    let alarms = env
+        .borrow()
+        .log
+        .iter()
+        .filter(|sent| sent.sender == NODE_A && sent.packet.payload == [ALARM])
+        .inspect(|sent| assert!(sent.packet.has_flag(flags::PRIORITY)))
+        .count();
+    assert_eq!(alarms, urgency.repeats as usize);
+    // Only the uplink waits for an ACK
+    assert_eq!(a.get_pending_count(), 1);
+
+    // B heard the uplink first, but relays the alarm before it
+    b.receive((), &()).await.unwrap();
+    let relayed = env
+        .borrow()
+        .log
+        .iter()
+        .find(|sent| sent.sender == NODE_B)
+        .map(|sent| sent.packet.payload.clone());
+    assert_eq!(relayed.as_deref(), Some(&[ALARM][..]));
+
+    // The gateway gets the alarm once, however often it was repeated
+    let delivered = gw.receive((), &()).await.unwrap();
+    let alarms = delivered.iter().filter(|d| d.packet.payload == [ALARM]);
+    assert_eq!(alarms.count(), 1);
+}
+
+#[test]
+fn test_duty_cycle_stretches_the_gap() {
+    let plan = ChannelPlan {
+        frequency_hz: 868_100_000,
+        spreading_factor: 7,
+        bandwidth_khz: 125,
+    };
+    let urgency = UrgentSend::default();
+    // About 1.3 s on the air at SF12 for 20 bytes, so 99 times that off it at 1%
+    let gap = urgency.gap_after_frame(Some(plan), 20);
+    assert!(gap > Duration::from_secs(100));
+    // Without a plan the airtime isn't known, and the gap is kept
+    assert_eq!(urgency.gap_after_frame(None, 20), urgency.gap);
+    let unlimited = UrgentSend {
+        duty_cycle_percent: 100,
+        ..urgency
+    };
+    assert_eq!(unlimited.gap_after_frame(Some(plan), 20), urgency.gap);
+}
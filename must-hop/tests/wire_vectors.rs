@@ -0,0 +1,65 @@
+//! The golden frames decode the same on nodes of any SIZE, see `node::vectors`. must-gw's tests
+//! decode them too, with its own SIZE.
+use must_hop::node::{
+    MHPacket, MHPacketView, WIRE_VERSION,
+    network_manager::NetworkManagerError,
+    vectors::{OLD_VECTORS, VECTORS},
+};
+
+const LEN: usize = 5;
+
+fn decode_vectors<const SIZE: usize>() {
+    for vector in VECTORS {
+        assert_eq!(vector.version, WIRE_VERSION, "{}", vector.name);
+        let views = MHPacketView::decode_frame::<LEN>(vector.frame).unwrap();
+        assert_eq!(views.as_slice(), vector.packets, "{}", vector.name);
+
+        let decoded = MHPacket::<SIZE>::decode_frame::<LEN>(vector.frame);
+        if vector.packets.iter().any(|p| p.payload.len() > SIZE) {
+            assert!(
+                matches!(decoded, Err(NetworkManagerError::PayloadTooLarge { .. })),
+                "{}",
+                vector.name
+            );
+            continue;
+        }
+        let decoded = decoded.unwrap();
+        for (pkt, view) in decoded.iter().zip(vector.packets) {
+            assert_eq!(
+                Some(pkt),
+                view.to_packet::<SIZE>().as_ref(),
+                "{}",
+                vector.name
+            );
+        }
+        let mut buf = [0u8; 256];
+        let encoded = MHPacket::encode_frame_into(&decoded, &mut buf).unwrap();
+        assert_eq!(encoded, vector.frame, "{}", vector.name);
+    }
+}
+
+fn reject_old_vectors<const SIZE: usize>() {
+    for vector in OLD_VECTORS {
+        assert!(vector.version < WIRE_VERSION, "{}", vector.name);
+        assert!(
+            matches!(
+                MHPacket::<SIZE>::decode_frame::<LEN>(vector.frame),
+                Err(NetworkManagerError::WrongVersion(_))
+            ),
+            "{}",
+            vector.name
+        );
+    }
+}
+
+#[test]
+fn test_vectors_on_small_nodes() {
+    decode_vectors::<40>();
+    reject_old_vectors::<40>();
+}
+
+#[test]
+fn test_vectors_on_the_largest_size() {
+    decode_vectors::<{ must_hop::node::MAX_SIZE }>();
+    reject_old_vectors::<{ must_hop::node::MAX_SIZE }>();
+}
@@ -0,0 +1,181 @@
+//! Decibels with their unit in the type, so an RSSI, a TX power and an SNR can't be mixed up with
+//! each other or with a plain number. `Dbm` is a power level, e.g. an RSSI or a TX power, and
+//! `Db` a ratio, e.g. an SNR, a gain or an offset. A level plus a ratio is a level, and the
+//! difference of two levels is a ratio. The number inside is whatever the radio uses: `Dbm<i8>`
+//! for a TX power, `Dbm<i16>` for the RSSI of an SX126x, `Dbm<f32>` for a concentrator's.
+#![no_std]
+
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A power level in dBm. Serializes as the bare number.
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default,
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct Dbm<T = f32>(pub T);
+
+/// A ratio in dB. Serializes as the bare number.
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default,
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct Db<T = f32>(pub T);
+
+impl<T: Add<Output = T>> Add<Db<T>> for Dbm<T> {
+    type Output = Dbm<T>;
+
+    fn add(self, gain: Db<T>) -> Dbm<T> {
+        Dbm(self.0 + gain.0)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub<Db<T>> for Dbm<T> {
+    type Output = Dbm<T>;
+
+    fn sub(self, loss: Db<T>) -> Dbm<T> {
+        Dbm(self.0 - loss.0)
+    }
+}
+
+/// How much stronger `self` is than `other`
+impl<T: Sub<Output = T>> Sub for Dbm<T> {
+    type Output = Db<T>;
+
+    fn sub(self, other: Dbm<T>) -> Db<T> {
+        Db(self.0 - other.0)
+    }
+}
+
+impl<T: AddAssign> AddAssign<Db<T>> for Dbm<T> {
+    fn add_assign(&mut self, gain: Db<T>) {
+        self.0 += gain.0;
+    }
+}
+
+impl<T: SubAssign> SubAssign<Db<T>> for Dbm<T> {
+    fn sub_assign(&mut self, loss: Db<T>) {
+        self.0 -= loss.0;
+    }
+}
+
+impl<T: Add<Output = T>> Add for Db<T> {
+    type Output = Db<T>;
+
+    fn add(self, other: Db<T>) -> Db<T> {
+        Db(self.0 + other.0)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for Db<T> {
+    type Output = Db<T>;
+
+    fn sub(self, other: Db<T>) -> Db<T> {
+        Db(self.0 - other.0)
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for Db<T> {
+    type Output = Db<T>;
+
+    fn neg(self) -> Db<T> {
+        Db(-self.0)
+    }
+}
+
+impl<T: AddAssign> AddAssign for Db<T> {
+    fn add_assign(&mut self, other: Db<T>) {
+        self.0 += other.0;
+    }
+}
+
+impl<T: SubAssign> SubAssign for Db<T> {
+    fn sub_assign(&mut self, other: Db<T>) {
+        self.0 -= other.0;
+    }
+}
+
+/// Shown with the unit, keeping the precision asked for, e.g. `{:.1}` gives `-97.5 dBm`
+impl<T: fmt::Display> fmt::Display for Dbm<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)?;
+        f.write_str(" dBm")
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Db<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)?;
+        f.write_str(" dB")
+    }
+}
+
+/// Conversions which can't lose anything
+macro_rules! widen {
+    ($($from:ty => $to:ty),*) => {
+        $(
+            impl From<Dbm<$from>> for Dbm<$to> {
+                fn from(level: Dbm<$from>) -> Self {
+                    Dbm(<$to>::from(level.0))
+                }
+            }
+
+            impl From<Db<$from>> for Db<$to> {
+                fn from(ratio: Db<$from>) -> Self {
+                    Db(<$to>::from(ratio.0))
+                }
+            }
+        )*
+    };
+}
+
+widen!(i8 => i16, i8 => i32, i8 => f32, i16 => i32, i16 => f32);
+
+/// Rounds half away from zero, `f32::round` needs std
+fn round(value: f32) -> f32 {
+    if value < 0.0 {
+        value - 0.5
+    } else {
+        value + 0.5
+    }
+}
+
+impl Dbm<f32> {
+    /// To whole dBm, saturating at the ends of an i16
+    pub fn to_i16(self) -> Dbm<i16> {
+        Dbm(round(self.0) as i16)
+    }
+}
+
+impl Db<f32> {
+    /// To whole dB, saturating at the ends of an i8
+    pub fn to_i8(self) -> Db<i8> {
+        Db(round(self.0) as i8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_and_ratios() {
+        let rssi = Dbm(-97i16);
+        let noise = Dbm(-117i16);
+        assert_eq!(rssi - noise, Db(20));
+        assert_eq!(Dbm(14i8) + Db(3), Dbm(17));
+        assert_eq!(Dbm(14i8) - Db(3), Dbm(11));
+        assert_eq!(Dbm::<i32>::from(Dbm(22i8)), Dbm(22));
+        assert_eq!(Dbm(-97.5f32).to_i16(), Dbm(-98));
+        assert_eq!(Db(7.4f32).to_i8(), Db(7));
+        assert_eq!(Db(1000f32).to_i8(), Db(i8::MAX));
+    }
+}